@@ -12,6 +12,7 @@ let
     faster-whisper
     numpy
     pyaudio
+    webrtcvad
   ]);
   
   # Select the appropriate whisper-cpp variant based on accelerationType
@@ -241,10 +242,11 @@ let
     postInstall = ''
       echo "Built whisp-away with acceleration type: ${accelerationType}"
       
-      # Install both Python scripts as RAW Python (not wrapped)
+      # Install the Python scripts (and the VAD module they share) as RAW Python (not wrapped)
       # We'll call these with Python directly and inject environment from Rust
       install -Dm755 ${./src/faster_whisper/scripts/transcribe_faster.py} $out/share/whisp-away/transcribe_faster.py
       install -Dm755 ${./src/faster_whisper/scripts/whisper_daemon.py} $out/share/whisp-away/whisper_daemon.py
+      install -Dm644 ${./src/faster_whisper/scripts/vad.py} $out/share/whisp-away/vad.py
       
       # First, patch the binary with the required RPATH for libraries
       patchelf --set-rpath "${lib.makeLibraryPath ([
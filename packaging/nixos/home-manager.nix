@@ -71,7 +71,8 @@ in {
       default = false;
       description = ''
         Output transcribed text to clipboard instead of typing at cursor.
-        When true, uses wl-copy (Wayland) or xclip (X11).
+        The clipboard is served in-process (Wayland data-control or X11),
+        falling back to wl-copy or xclip.
         Can be toggled at runtime via the tray menu.
       '';
     };
@@ -86,6 +87,19 @@ in {
       '';
     };
     
+    socketActivation = mkOption {
+      type = types.bool;
+      default = false;
+      description = ''
+        Start the whisper daemon on demand, when the first dictation connects to
+        its socket (systemd socket activation), instead of keeping it running.
+        It exits after `daemon_idle_exit_mins` (default 30) without a
+        transcription, so the model isn't kept in memory on days dictation
+        isn't used. Combined with autoStartDaemon, the daemon is also started
+        on login.
+      '';
+    };
+    
     autoStartTray = mkOption {
       type = types.bool;
       default = false;
@@ -113,17 +127,18 @@ in {
       };
     }
     
-    # Create cache directories using systemd tmpfiles
+    # Create model directories using systemd tmpfiles
     {
       systemd.user.tmpfiles.rules = [
-        "d %h/.cache/faster-whisper 0755 - - -"
-        "d %h/.cache/whisper-cpp 0755 - - -"
-        "d %h/.cache/whisper-cpp/models 0755 - - -"
+        "d %h/.local/share/whisp-away 0755 - - -"
+        "d %h/.local/share/whisp-away/models 0755 - - -"
+        "d %h/.local/share/whisp-away/models/whisper-cpp 0755 - - -"
+        "d %h/.local/share/whisp-away/models/faster-whisper 0755 - - -"
       ];
     }
     
-    # Optional: Auto-start daemon service
-    (mkIf cfg.autoStartDaemon {
+    # Optional: Daemon service, started on login and/or on demand by its socket
+    (mkIf (cfg.autoStartDaemon || cfg.socketActivation) {
       systemd.user.services.whisp-away-daemon = {
         Unit = {
           Description = "WhispAway speech recognition daemon";
@@ -145,12 +160,29 @@ in {
             "CUDA_VISIBLE_DEVICES=0"
           ];
         };
+      } // optionalAttrs cfg.autoStartDaemon {
         Install = {
           WantedBy = [ "graphical-session.target" ];
         };
       };
     })
     
+    # Optional: Socket that starts the daemon on the first connection
+    (mkIf cfg.socketActivation {
+      systemd.user.sockets.whisp-away-daemon = {
+        Unit = {
+          Description = "WhispAway speech recognition daemon socket";
+        };
+        Socket = {
+          ListenStream = "/tmp/whisp-away-daemon.sock";
+          SocketMode = "0666";
+        };
+        Install = {
+          WantedBy = [ "sockets.target" ];
+        };
+      };
+    })
+    
     # Optional: Auto-start tray service
     (mkIf cfg.autoStartTray {
       systemd.user.services.whisp-away-tray = {
@@ -71,7 +71,8 @@ in {
       default = false;
       description = ''
         Output transcribed text to clipboard instead of typing at cursor.
-        When true, uses wl-copy (Wayland) or xclip (X11).
+        The clipboard is served in-process (Wayland data-control or X11),
+        falling back to wl-copy or xclip.
         Can be toggled at runtime via the tray menu.
       '';
     };
@@ -86,6 +87,19 @@ in {
       '';
     };
     
+    socketActivation = mkOption {
+      type = types.bool;
+      default = false;
+      description = ''
+        Start the whisper daemon on demand, when the first dictation connects to
+        its socket (systemd socket activation), instead of keeping it running.
+        It exits after `daemon_idle_exit_mins` (default 30) without a
+        transcription, so the model isn't kept in memory on days dictation
+        isn't used. Combined with autoStartDaemon, the daemon is also started
+        on login.
+      '';
+    };
+    
     autoStartTray = mkOption {
       type = types.bool;
       default = false;
@@ -112,21 +126,22 @@ in {
         LD_LIBRARY_PATH = "${pkgs.cudaPackages.cudatoolkit}/lib:${pkgs.cudaPackages.cudnn}/lib:\${LD_LIBRARY_PATH}";
       };
       
-      # Create cache directories using systemd tmpfiles
+      # Create model directories using systemd tmpfiles
       systemd.user.tmpfiles.rules = [
-        "d %h/.cache/faster-whisper 0755 %u %u -"
-        "d %h/.cache/whisper-cpp 0755 %u %u -"
-        "d %h/.cache/whisper-cpp/models 0755 %u %u -"
+        "d %h/.local/share/whisp-away 0755 %u %u -"
+        "d %h/.local/share/whisp-away/models 0755 %u %u -"
+        "d %h/.local/share/whisp-away/models/whisper-cpp 0755 %u %u -"
+        "d %h/.local/share/whisp-away/models/faster-whisper 0755 %u %u -"
       ];
     }
     
-    # Optional: Auto-start daemon service
-    (mkIf cfg.autoStartDaemon {
+    # Optional: Daemon service, started on login and/or on demand by its socket
+    (mkIf (cfg.autoStartDaemon || cfg.socketActivation) {
       systemd.user.services.whisp-away-daemon = {
         description = "WhispAway speech recognition daemon";
         after = [ "graphical-session.target" ];
         partOf = [ "graphical-session.target" ];
-        wantedBy = [ "graphical-session.target" ];
+        wantedBy = optionals cfg.autoStartDaemon [ "graphical-session.target" ];
         serviceConfig = {
           Type = "simple";
           ExecStart = "${whisp-away}/bin/whisp-away daemon";
@@ -145,6 +160,16 @@ in {
       };
     })
     
+    # Optional: Socket that starts the daemon on the first connection
+    (mkIf cfg.socketActivation {
+      systemd.user.sockets.whisp-away-daemon = {
+        description = "WhispAway speech recognition daemon socket";
+        wantedBy = [ "sockets.target" ];
+        listenStreams = [ "/tmp/whisp-away-daemon.sock" ];
+        socketConfig.SocketMode = "0666";
+      };
+    })
+    
     # Optional: Auto-start tray service
     (mkIf cfg.autoStartTray {
       systemd.user.services.whisp-away-tray = {
@@ -8,7 +8,7 @@
   download-whisper-model = writeShellScriptBin "download-whisper-model" ''
     #!${pkgs.bash}/bin/bash
     
-    MODEL_DIR="$HOME/.cache/whisper-cpp/models"
+    MODEL_DIR="''${XDG_DATA_HOME:-$HOME/.local/share}/whisp-away/models/whisper-cpp"
     mkdir -p "$MODEL_DIR"
     
     MODEL="''${1:-medium.en}"
@@ -62,14 +62,17 @@
     echo ""
     echo "Downloaded models:"
     
-    MODEL_DIR="$HOME/.cache/whisper-cpp/models"
-    if [ -d "$MODEL_DIR" ]; then
+    # Models not yet migrated from the old location still count
+    FOUND=0
+    for MODEL_DIR in "''${XDG_DATA_HOME:-$HOME/.local/share}/whisp-away/models/whisper-cpp" "$HOME/.cache/whisper-cpp/models"; do
       for model in "$MODEL_DIR"/ggml-*.bin; do
         if [ -f "$model" ]; then
           basename "$model" | sed 's/ggml-//;s/.bin//'
+          FOUND=1
         fi
       done
-    else
+    done
+    if [ "$FOUND" -eq 0 ]; then
       echo "  None"
     fi
     
@@ -0,0 +1,207 @@
+use std::fs;
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::unix::net::UnixListener;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+#[cfg(feature = "whisper-cpp")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "whisper-cpp")]
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use crate::instance;
+use crate::settings::{self, Layers, Resolved};
+
+/// First file descriptor systemd passes to an activated service (SD_LISTEN_FDS_START)
+const LISTEN_FDS_START: RawFd = 3;
+
+/// systemd socket unit that starts the daemon (or a named instance's) on the first connection
+fn socket_unit() -> String {
+    format!("whisp-away-daemon{}.socket", instance::suffix())
+}
+
+/// Passes the inherited listening socket on to the faster-whisper Python daemon, whose
+/// PID systemd's LISTEN_PID doesn't name
+#[cfg(feature = "faster-whisper")]
+pub const LISTEN_FD_VAR: &str = "WA_LISTEN_FD";
+
+/// Resolves how long a daemon started by systemd socket activation waits without a
+/// transcription before exiting (systemd starts it again on the next one) with priority:
+/// 1. WA_DAEMON_IDLE_EXIT_MINS env var (0 to keep running)
+/// 2. User config file `daemon_idle_exit_mins`
+/// 3. Default to 30
+pub fn idle_exit_mins_setting() -> Resolved<u64> {
+    Layers::new("daemon idle exit (min)")
+        .env("WA_DAEMON_IDLE_EXIT_MINS", settings::parsed)
+        .config(|c| c.daemon_idle_exit_mins)
+        .or_default(30)
+}
+
+/// The listening socket systemd passed when it started the daemon for a connecting client,
+/// per sd_listen_fds(3)
+/// LISTEN_PID and LISTEN_FDS are removed, so processes the daemon starts don't take the
+/// socket for theirs
+pub fn systemd_listener() -> Option<UnixListener> {
+    let pid = std::env::var("LISTEN_PID").ok();
+    let fds = std::env::var("LISTEN_FDS").ok();
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    listen_fds(pid?, fds?, std::process::id())?;
+    info!("Started by systemd socket activation");
+    // SAFETY: systemd passed this descriptor for us to own, and nothing else has taken it
+    Some(unsafe { UnixListener::from_raw_fd(LISTEN_FDS_START) })
+}
+
+/// How many sockets LISTEN_PID and LISTEN_FDS pass to process `own_pid`; None when they
+/// aren't meant for it or pass none
+fn listen_fds(pid: String, fds: String, own_pid: u32) -> Option<u32> {
+    if pid.parse::<u32>().ok() != Some(own_pid) {
+        debug!("LISTEN_PID {} is another process's", pid);
+        return None;
+    }
+    match fds.parse::<u32>() {
+        Ok(0) | Err(_) => None,
+        Ok(1) => Some(1),
+        Ok(count) => {
+            warn!("systemd passed {} sockets; using the first", count);
+            Some(count)
+        }
+    }
+}
+
+/// Where a running daemon leaves its PID, so the tray and `status` can tell whether it is
+/// up without connecting (which would start it when systemd listens on the socket)
+fn pid_path() -> String {
+    instance::runtime_path("whisp-away-daemon", "pid")
+}
+
+/// Record this process as the running daemon
+#[cfg(any(feature = "whisper-cpp", feature = "faster-whisper"))]
+pub fn write_pid() {
+    if let Err(e) = fs::write(pid_path(), std::process::id().to_string()) {
+        warn!("Failed to write {}: {}", pid_path(), e);
+    }
+}
+
+#[cfg(any(feature = "whisper-cpp", feature = "faster-whisper"))]
+pub fn remove_pid() {
+    let _ = fs::remove_file(pid_path());
+}
+
+/// Whether the daemon whose PID was recorded is still running
+pub fn daemon_running() -> bool {
+    fs::read_to_string(pid_path()).ok()
+        .and_then(|pid| pid.trim().parse::<libc::pid_t>().ok())
+        // SAFETY: signal 0 only checks that the process exists
+        .is_some_and(|pid| unsafe { libc::kill(pid, 0) } == 0)
+}
+
+/// Whether systemd listens on the daemon socket to start it on demand; a stopped daemon
+/// is then only waiting for the next dictation (checked once per process)
+pub fn on_demand() -> bool {
+    static ON_DEMAND: OnceLock<bool> = OnceLock::new();
+    *ON_DEMAND.get_or_init(|| {
+        let unit = socket_unit();
+        let active = Command::new("systemctl")
+            .args(["--user", "is-active", "--quiet", &unit])
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        debug!("{} active: {}", unit, active);
+        active
+    })
+}
+
+/// Whether connecting to the daemon is pointless or would start it: it isn't running and
+/// systemd would start it just to answer
+pub fn asleep() -> bool {
+    on_demand() && !daemon_running()
+}
+
+/// Counts running transcriptions and exits the process once none has run for the idle time
+#[cfg(feature = "whisper-cpp")]
+pub struct IdleExit {
+    /// Transcriptions running, and when the last one finished
+    state: Mutex<(usize, Instant)>,
+}
+
+#[cfg(feature = "whisper-cpp")]
+impl IdleExit {
+    /// Watch for the idle time to pass; None when the daemon should keep running
+    pub fn start(limit: Duration) -> Option<Arc<Self>> {
+        if limit.is_zero() {
+            return None;
+        }
+        let idle = Arc::new(Self { state: Mutex::new((0, Instant::now())) });
+        let watched = Arc::clone(&idle);
+        std::thread::spawn(move || loop {
+            std::thread::sleep((limit / 10).clamp(Duration::from_secs(1), Duration::from_secs(60)));
+            if watched.expired(limit) {
+                info!("No transcription for {} min, exiting until systemd starts the daemon again", limit.as_secs() / 60);
+                remove_pid();
+                std::process::exit(0);
+            }
+        });
+        info!("Exiting after {} min without a transcription", limit.as_secs() / 60);
+        Some(idle)
+    }
+
+    /// Whether nothing is transcribing and nothing has for `limit`
+    fn expired(&self, limit: Duration) -> bool {
+        let (running, last) = *self.state.lock().unwrap_or_else(|e| e.into_inner());
+        running == 0 && last.elapsed() >= limit
+    }
+
+    /// Hold off exiting until the returned guard is dropped
+    pub fn transcribing(&self) -> Transcribing<'_> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).0 += 1;
+        Transcribing(self)
+    }
+}
+
+/// A transcription in progress; the idle time counts from when it is dropped
+#[cfg(feature = "whisper-cpp")]
+pub struct Transcribing<'a>(&'a IdleExit);
+
+#[cfg(feature = "whisper-cpp")]
+impl Drop for Transcribing<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap_or_else(|e| e.into_inner());
+        *state = (state.0.saturating_sub(1), Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_only_sockets_passed_to_this_process() {
+        let own = |pid: &str, fds: &str| listen_fds(pid.to_string(), fds.to_string(), 4242);
+        assert_eq!(own("4242", "1"), Some(1));
+        assert_eq!(own("4242", "2"), Some(2));
+        // Meant for the process that started us, or nothing passed at all
+        assert_eq!(own("4241", "1"), None);
+        assert_eq!(own("not a pid", "1"), None);
+        assert_eq!(own("4242", "0"), None);
+        assert_eq!(own("4242", ""), None);
+    }
+
+    #[cfg(feature = "whisper-cpp")]
+    #[test]
+    fn idle_exit_waits_for_running_transcriptions() {
+        let idle = IdleExit { state: Mutex::new((0, Instant::now())) };
+        assert!(!idle.expired(Duration::from_secs(60)));
+        assert!(idle.expired(Duration::ZERO));
+
+        let first = idle.transcribing();
+        let second = idle.transcribing();
+        assert!(!idle.expired(Duration::ZERO));
+        drop(first);
+        assert!(!idle.expired(Duration::ZERO));
+        drop(second);
+        assert!(idle.expired(Duration::ZERO));
+        assert!(!idle.expired(Duration::from_secs(60)));
+    }
+}
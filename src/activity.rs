@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "whisper-cpp")]
+use tracing::{debug, trace};
+use crate::helpers::now_secs;
+use crate::instance;
+
+/// How long a failed transcription keeps the tray in the error state
+const ERROR_DISPLAY_SECS: u64 = 10;
+
+/// A "transcribing" record older than this is left over from a crashed daemon
+const STALE_TRANSCRIBING_SECS: u64 = 600;
+
+/// Daemon pipeline stage - written by the daemons, read by the tray
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Activity {
+    Idle,
+    Transcribing,
+    Error,
+    /// Models dropped with `whisp-away daemon unload`
+    Unloaded,
+    /// A model is being loaded; only reported to `watch` subscribers
+    Loading,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ActivityRecord {
+    activity: Activity,
+    /// Unix time the activity was recorded
+    updated: u64,
+}
+
+/// Path of the activity file (also handed to the Python daemon via WA_ACTIVITY_FILE)
+pub fn activity_path() -> String {
+    instance::runtime_path("whisp-away-activity", "json")
+}
+
+/// Record the daemon's current activity; failures only cost the tray some accuracy
+#[cfg(feature = "whisper-cpp")]
+pub fn set(activity: Activity) {
+    let record = ActivityRecord { activity, updated: now_secs() };
+    let result = serde_json::to_string(&record)
+        .map_err(std::io::Error::other)
+        .and_then(|json| std::fs::write(activity_path(), json));
+    match result {
+        Ok(()) => trace!("Activity: {:?}", activity),
+        Err(e) => debug!("Failed to write activity file: {}", e),
+    }
+}
+
+/// Current daemon activity, with expired errors and stale transcriptions reported as idle
+pub fn current() -> Activity {
+    let Some(record) = std::fs::read_to_string(activity_path())
+        .ok()
+        .and_then(|content| serde_json::from_str::<ActivityRecord>(&content).ok())
+    else {
+        return Activity::Idle;
+    };
+
+    let age = now_secs().saturating_sub(record.updated);
+    match record.activity {
+        Activity::Error if age > ERROR_DISPLAY_SECS => Activity::Idle,
+        Activity::Transcribing if age > STALE_TRANSCRIBING_SECS => Activity::Idle,
+        activity => activity,
+    }
+}
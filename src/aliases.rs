@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+use tracing::debug;
+use crate::config::ModelAlias;
+use crate::settings::Layers;
+
+/// A name and what each backend loads for it; None leaves the name as it is for that backend
+struct Alias {
+    name: &'static str,
+    whisper_cpp: Option<&'static str>,
+    faster_whisper: Option<&'static str>,
+    openai: Option<&'static str>,
+}
+
+const fn alias(name: &'static str, whisper_cpp: Option<&'static str>, faster_whisper: Option<&'static str>, openai: Option<&'static str>) -> Alias {
+    Alias { name, whisper_cpp, faster_whisper, openai }
+}
+
+/// Names that mean the same thing on every backend, and upstream names a backend spells
+/// differently
+const BUILT_IN: [Alias; 5] = [
+    alias("fast", Some("base"), Some("base"), Some("gpt-4o-mini-transcribe")),
+    alias("balanced", Some("small"), Some("small"), Some("whisper-1")),
+    alias("accurate", Some("large-v3-turbo"), Some("large-v3-turbo"), Some("gpt-4o-transcribe")),
+    // The ggml files carry the full checkpoint name
+    alias("turbo", Some("large-v3-turbo"), None, None),
+    alias("large", Some("large-v3"), None, None),
+];
+
+/// The model `backend` ("whisper-cpp", "faster-whisper" or "openai") loads for `model`:
+/// an alias in the config file's `[model_aliases]`, then a built-in one; any other name,
+/// or a path, is returned as it is
+pub fn resolve(model: &str, backend: &str) -> String {
+    let resolved = lookup(model, backend, &user_aliases());
+    if resolved != model {
+        debug!("Model {} is {} for {}", model, resolved, backend);
+    }
+    resolved
+}
+
+fn user_aliases() -> BTreeMap<String, ModelAlias> {
+    Layers::new("model aliases")
+        .config(|c| c.model_aliases)
+        .or_default(BTreeMap::new())
+        .value
+}
+
+/// A user alias may name a built-in one ("dictation = \"accurate\""), which is looked up in turn
+fn lookup(model: &str, backend: &str, user: &BTreeMap<String, ModelAlias>) -> String {
+    let model = match user.get(model) {
+        Some(ModelAlias::Model(target)) => target.as_str(),
+        Some(ModelAlias::PerBackend(targets)) => targets.get(backend).map(String::as_str).unwrap_or(model),
+        None => model,
+    };
+    built_in(model, backend).unwrap_or(model).to_string()
+}
+
+fn built_in(model: &str, backend: &str) -> Option<&'static str> {
+    let alias = BUILT_IN.iter().find(|alias| alias.name == model)?;
+    match backend {
+        "whisper-cpp" => alias.whisper_cpp,
+        "faster-whisper" => alias.faster_whisper,
+        "openai" => alias.openai,
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_built_in_aliases_per_backend() {
+        let none = BTreeMap::new();
+        assert_eq!(lookup("accurate", "whisper-cpp", &none), "large-v3-turbo");
+        assert_eq!(lookup("fast", "openai", &none), "gpt-4o-mini-transcribe");
+        assert_eq!(lookup("turbo", "whisper-cpp", &none), "large-v3-turbo");
+        assert_eq!(lookup("turbo", "faster-whisper", &none), "turbo");
+        assert_eq!(lookup("base.en", "whisper-cpp", &none), "base.en");
+    }
+
+    #[test]
+    fn user_aliases_come_first() {
+        let user = BTreeMap::from([
+            ("accurate".to_string(), ModelAlias::Model("large-v3".to_string())),
+            ("dictation".to_string(), ModelAlias::Model("fast".to_string())),
+            ("notes".to_string(), ModelAlias::PerBackend(BTreeMap::from([
+                ("faster-whisper".to_string(), "distil-large-v3".to_string()),
+            ]))),
+        ]);
+        assert_eq!(lookup("accurate", "whisper-cpp", &user), "large-v3");
+        assert_eq!(lookup("dictation", "whisper-cpp", &user), "base");
+        assert_eq!(lookup("notes", "faster-whisper", &user), "distil-large-v3");
+        assert_eq!(lookup("notes", "whisper-cpp", &user), "notes");
+    }
+}
@@ -0,0 +1,643 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::privacy;
+
+/// WAVE_FORMAT_PCM and WAVE_FORMAT_EXTENSIBLE (whose subformat is PCM for every recorder used)
+const FORMAT_PCM: u16 = 1;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Length of the header written by `header`: RIFF, fmt and data chunk headers
+pub const CANONICAL_HEADER_LEN: usize = 44;
+
+/// Samples are read and written in blocks of this size, so an hour-long recording (~115 MB)
+/// is never held in memory whole
+const BLOCK_BYTES: usize = 64 * 1024;
+
+/// What a WAV file holds and where its samples are
+/// Recorders don't all write the canonical 44-byte header: ffmpeg adds a LIST chunk, others
+/// a fact chunk or an extensible fmt chunk, so the samples start wherever the data chunk does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavInfo {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+    /// Offset of the first sample in the file
+    pub data_offset: u64,
+    /// Bytes of samples
+    pub data_len: u64,
+}
+
+impl WavInfo {
+    pub fn duration_secs(&self) -> f64 {
+        let bytes_per_second = self.sample_rate as u64 * self.block_align();
+        if bytes_per_second == 0 {
+            return 0.0;
+        }
+        self.data_len as f64 / bytes_per_second as f64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data_len == 0
+    }
+
+    /// Samples of every channel together
+    #[cfg(any(feature = "whisper-cpp", test))]
+    pub fn frames(&self) -> u64 {
+        match self.block_align() {
+            0 => 0,
+            align => self.data_len / align,
+        }
+    }
+
+    /// 16kHz mono 16-bit, what whisper is fed
+    #[cfg(any(feature = "whisper-cpp", test))]
+    pub fn is_whisper_ready(&self) -> bool {
+        self.sample_rate == 16000 && self.channels == 1 && self.bits_per_sample == 16
+    }
+
+    /// The samples in a whole file read into memory
+    #[cfg(test)]
+    pub fn data<'a>(&self, wav: &'a [u8]) -> &'a [u8] {
+        let start = (self.data_offset as usize).min(wav.len());
+        let end = (start + self.data_len as usize).min(wav.len());
+        &wav[start..end]
+    }
+
+    /// Bytes of one frame: a sample of every channel
+    pub fn block_align(&self) -> u64 {
+        self.channels as u64 * (self.bits_per_sample as u64).div_ceil(8)
+    }
+}
+
+/// Read the format and the position of the samples from a WAV file's chunks
+pub fn probe(path: impl AsRef<Path>) -> Result<WavInfo> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let len = file.metadata().with_context(|| format!("Failed to read {}", path.display()))?.len();
+    read_chunks(&mut BufReader::new(file), len).with_context(|| format!("Invalid WAV file {}", path.display()))
+}
+
+/// `probe` for a WAV file already read into memory
+#[cfg(test)]
+pub fn probe_bytes(wav: &[u8]) -> Result<WavInfo> {
+    read_chunks(&mut io::Cursor::new(wav), wav.len() as u64).context("Invalid WAV file")
+}
+
+/// The samples of a WAV file, read a block at a time
+pub struct Samples {
+    reader: io::Take<BufReader<File>>,
+    buffer: Vec<u8>,
+    pub info: WavInfo,
+}
+
+impl Samples {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let info = probe(path)?;
+        let mut file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        file.seek(SeekFrom::Start(info.data_offset))?;
+        Ok(Self { reader: BufReader::new(file).take(info.data_len), buffer: vec![0; BLOCK_BYTES], info })
+    }
+
+    /// The next BLOCK_BYTES of samples (fewer only at the end, so samples and frames are
+    /// never split), empty once all are read
+    pub fn next_block(&mut self) -> Result<&[u8]> {
+        let mut filled = 0;
+        while filled < self.buffer.len() {
+            match self.reader.read(&mut self.buffer[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e).context("Failed to read samples"),
+            }
+        }
+        Ok(&self.buffer[..filled])
+    }
+
+    /// Hand every block to `f` in order
+    pub fn for_each_block(mut self, mut f: impl FnMut(&[u8]) -> Result<()>) -> Result<()> {
+        loop {
+            let block = self.next_block()?;
+            if block.is_empty() {
+                return Ok(());
+            }
+            f(block)?;
+        }
+    }
+}
+
+/// A 16-bit WAV file written block by block next to `path`, replacing it once finished, so
+/// a recording can be rewritten from itself
+/// Dropped unfinished, the partial file is removed and `path` is left as it was
+pub struct WavWriter {
+    file: BufWriter<File>,
+    path: String,
+    partial: String,
+    channels: u16,
+    sample_rate: u32,
+    data_len: u64,
+    finished: bool,
+}
+
+impl WavWriter {
+    pub fn create(path: &str, channels: u16, sample_rate: u32) -> Result<Self> {
+        let partial = format!("{}.partial", path);
+        let mut file = BufWriter::new(File::create(&partial).with_context(|| format!("Failed to create {}", partial))?);
+        file.write_all(&header(channels, sample_rate, 0))?;
+        Ok(Self { file, path: path.to_string(), partial, channels, sample_rate, data_len: 0, finished: false })
+    }
+
+    pub fn write(&mut self, samples: &[u8]) -> Result<()> {
+        self.file.write_all(samples).with_context(|| format!("Failed to write {}", self.partial))?;
+        self.data_len += samples.len() as u64;
+        Ok(())
+    }
+
+    /// Fill in the size and put the file in place of `path`
+    pub fn finish(mut self) -> Result<()> {
+        let data_len = u32::try_from(self.data_len)
+            .ok()
+            .filter(|len| *len <= u32::MAX - CANONICAL_HEADER_LEN as u32)
+            .ok_or_else(|| anyhow!("{} bytes of samples don't fit in a WAV file", self.data_len))?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header(self.channels, self.sample_rate, data_len))?;
+        self.file.flush().with_context(|| format!("Failed to write {}", self.partial))?;
+        fs::rename(&self.partial, &self.path).with_context(|| format!("Failed to replace {}", self.path))?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = privacy::remove_file(&self.partial);
+        }
+    }
+}
+
+/// Whether a recording has nothing to transcribe: a WAV file without samples, or one that
+/// ends before its samples start
+/// Files in other formats (and missing ones) aren't judged here
+pub fn is_empty(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+    match probe(path) {
+        Ok(wav) => wav.is_empty(),
+        Err(_) => {
+            let mut magic = [0u8; 4];
+            let read = File::open(path).and_then(|mut file| file.read(&mut magic));
+            matches!(read, Ok(0)) || (read.is_ok() && &magic == b"RIFF")
+        }
+    }
+}
+
+/// 16-bit samples at or beyond this magnitude are at full scale, i.e. clipped
+const FULL_SCALE: i32 = i16::MAX as i32;
+
+/// Windows quieter than this are silence when measuring leading and trailing silence
+const SILENCE_DBFS: f32 = -45.0;
+
+/// Level of silence, and the floor of every level measured
+const FLOOR_DBFS: f32 = -96.0;
+
+/// Levels of a recording, for telling a microphone set too hot or too quiet
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Levels {
+    pub peak_dbfs: f32,
+    pub rms_dbfs: f32,
+    /// Samples at full scale
+    pub clipped_samples: u64,
+    /// Share of the samples that are clipped (0.0-1.0)
+    pub clipped_fraction: f64,
+    /// Mean sample as a share of full scale; far from 0 means a faulty input
+    pub dc_offset: f64,
+    /// Silence before the first and after the last sound, in seconds
+    pub leading_silence_secs: f64,
+    pub trailing_silence_secs: f64,
+    /// Sound louder than silence, in seconds
+    #[serde(default)]
+    pub loud_secs: f64,
+}
+
+/// Measure the levels of a 16-bit recording; every channel counts
+pub fn levels(path: impl AsRef<Path>) -> Result<Levels> {
+    let samples = Samples::open(path)?;
+    if samples.info.bits_per_sample != 16 {
+        return Err(anyhow!("{}-bit samples aren't measured", samples.info.bits_per_sample));
+    }
+    let mut meter = Meter::new(samples.info);
+    samples.for_each_block(|block| {
+        meter.add(block);
+        Ok(())
+    })?;
+    Ok(meter.finish())
+}
+
+/// Levels measured as the samples go by, in 10ms windows of every channel together
+struct Meter {
+    info: WavInfo,
+    window: usize,
+    count: u64,
+    sum: i64,
+    sum_squares: f64,
+    peak: i32,
+    clipped_samples: u64,
+    /// The window being filled
+    window_len: usize,
+    window_squares: f64,
+    /// Quiet windows before the first loud one, and since the last one
+    leading: u64,
+    quiet_run: u64,
+    loud_windows: u64,
+}
+
+impl Meter {
+    fn new(info: WavInfo) -> Self {
+        Self {
+            info,
+            window: ((info.sample_rate / 100) as usize * info.channels.max(1) as usize).max(1),
+            count: 0,
+            sum: 0,
+            sum_squares: 0.0,
+            peak: 0,
+            clipped_samples: 0,
+            window_len: 0,
+            window_squares: 0.0,
+            leading: 0,
+            quiet_run: 0,
+            loud_windows: 0,
+        }
+    }
+
+    fn add(&mut self, data: &[u8]) {
+        for sample in data.chunks_exact(2) {
+            let sample = i16::from_le_bytes([sample[0], sample[1]]) as i32;
+            let square = (sample * sample) as f64;
+            self.count += 1;
+            self.sum += sample as i64;
+            self.sum_squares += square;
+            self.peak = self.peak.max(sample.abs());
+            if sample.abs() >= FULL_SCALE {
+                self.clipped_samples += 1;
+            }
+            self.window_len += 1;
+            self.window_squares += square;
+            if self.window_len == self.window {
+                self.close_window();
+            }
+        }
+    }
+
+    fn close_window(&mut self) {
+        let rms = (self.window_squares / self.window_len as f64).sqrt();
+        if to_dbfs(rms) > SILENCE_DBFS {
+            self.loud_windows += 1;
+            self.quiet_run = 0;
+        } else if self.loud_windows == 0 {
+            self.leading += 1;
+        } else {
+            self.quiet_run += 1;
+        }
+        self.window_len = 0;
+        self.window_squares = 0.0;
+    }
+
+    fn finish(mut self) -> Levels {
+        if self.window_len > 0 {
+            self.close_window();
+        }
+        let count = self.count.max(1) as f64;
+        let window_secs = self.window as f64 / (self.info.sample_rate.max(1) as f64 * self.info.channels.max(1) as f64);
+        let secs = |windows: u64| (windows as f64 * window_secs).min(self.info.duration_secs());
+        Levels {
+            peak_dbfs: to_dbfs(self.peak as f64),
+            rms_dbfs: to_dbfs((self.sum_squares / count).sqrt()),
+            clipped_samples: self.clipped_samples,
+            clipped_fraction: self.clipped_samples as f64 / count,
+            dc_offset: self.sum as f64 / count / 32768.0,
+            leading_silence_secs: secs(self.leading),
+            trailing_silence_secs: secs(self.quiet_run),
+            loud_secs: secs(self.loud_windows),
+        }
+    }
+}
+
+/// RMS level in dBFS of the last `secs` of a 16-bit recording, also one still being written,
+/// every channel together
+#[cfg(feature = "tui")]
+pub fn recent_level(path: impl AsRef<Path>, secs: f64) -> Result<f32> {
+    let path = path.as_ref();
+    let info = probe(path)?;
+    if info.bits_per_sample != 16 {
+        return Err(anyhow!("{}-bit samples aren't measured", info.bits_per_sample));
+    }
+    let frame = info.block_align().max(1);
+    let wanted = (secs * info.sample_rate as f64) as u64 * frame;
+    let len = wanted.min(info.data_len / frame * frame);
+    let mut file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    file.seek(SeekFrom::Start(info.data_offset + info.data_len - len))?;
+    let mut data = vec![0u8; len as usize];
+    file.read_exact(&mut data).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(rms_dbfs(&data))
+}
+
+#[cfg(feature = "tui")]
+fn rms_dbfs(data: &[u8]) -> f32 {
+    let samples = data.chunks_exact(2).map(|s| i16::from_le_bytes([s[0], s[1]]) as f64);
+    let sum_squares: f64 = samples.map(|s| s * s).sum();
+    to_dbfs((sum_squares / (data.len() / 2).max(1) as f64).sqrt())
+}
+
+/// Level of a 16-bit amplitude relative to full scale
+fn to_dbfs(amplitude: f64) -> f32 {
+    if amplitude <= 0.0 {
+        return FLOOR_DBFS;
+    }
+    ((20.0 * (amplitude / 32768.0).log10()) as f32).max(FLOOR_DBFS)
+}
+
+/// A canonical header for `data_len` bytes of 16-bit PCM samples
+pub fn header(channels: u16, sample_rate: u32, data_len: u32) -> Vec<u8> {
+    let block_align = channels * 2;
+    let mut header = Vec::with_capacity(CANONICAL_HEADER_LEN);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(data_len + CANONICAL_HEADER_LEN as u32 - 8).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&FORMAT_PCM.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    header
+}
+
+/// Walk the chunks up to the data chunk, taking the format from the fmt chunk and skipping
+/// everything else (LIST, fact, bext, ...)
+fn read_chunks<R: Read + Seek>(reader: &mut R, len: u64) -> Result<WavInfo> {
+    let mut riff = [0u8; 12];
+    reader.read_exact(&mut riff).map_err(|_| anyhow!("too short for a RIFF header ({} bytes)", len))?;
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err(anyhow!("not a RIFF/WAVE file"));
+    }
+
+    let mut format: Option<(u16, u32, u16)> = None;
+    let mut position = 12u64;
+    loop {
+        let mut chunk = [0u8; 8];
+        reader.read_exact(&mut chunk).map_err(|_| anyhow!("no data chunk in its {} bytes", len))?;
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        position += 8;
+
+        match &chunk[0..4] {
+            b"fmt " => {
+                if size < 16 {
+                    return Err(anyhow!("fmt chunk of {} bytes is too short", size));
+                }
+                let mut fmt = [0u8; 16];
+                reader.read_exact(&mut fmt).map_err(|_| anyhow!("ends partway through the fmt chunk"))?;
+                let tag = u16::from_le_bytes([fmt[0], fmt[1]]);
+                if tag != FORMAT_PCM && tag != FORMAT_EXTENSIBLE {
+                    return Err(anyhow!("format {:#06x} isn't PCM", tag));
+                }
+                let channels = u16::from_le_bytes([fmt[2], fmt[3]]);
+                let sample_rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+                let bits_per_sample = u16::from_le_bytes([fmt[14], fmt[15]]);
+                format = Some((channels, sample_rate, bits_per_sample));
+                reader.seek(SeekFrom::Current(size as i64 - 16 + (size & 1) as i64))?;
+            }
+            b"data" => {
+                let (channels, sample_rate, bits_per_sample) =
+                    format.ok_or_else(|| anyhow!("data chunk before the fmt chunk"))?;
+                let available = len.saturating_sub(position);
+                // A recorder that is still running (or was killed) hasn't filled in the size
+                // yet, and one that was cut short claims more than there is
+                let data_len = match size {
+                    0 | u32::MAX => available,
+                    size => (size as u64).min(available),
+                };
+                return Ok(WavInfo { channels, sample_rate, bits_per_sample, data_offset: position, data_len });
+            }
+            _ => {
+                // Chunks are padded to an even length
+                reader.seek(SeekFrom::Current(size as i64 + (size & 1) as i64))?;
+            }
+        }
+        position += size as u64 + (size & 1) as u64;
+        if position > len {
+            return Err(anyhow!("a chunk runs past the end of the file ({} bytes)", len));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn fmt_body(channels: u16, sample_rate: u32) -> Vec<u8> {
+        header(channels, sample_rate, 0)[20..36].to_vec()
+    }
+
+    fn wav(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let mut wav = b"RIFF".to_vec();
+        wav.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+        wav.extend_from_slice(b"WAVE");
+        wav.extend_from_slice(&body);
+        wav
+    }
+
+    #[test]
+    fn canonical_header() {
+        let mut file = header(1, 16000, 32000);
+        file.extend_from_slice(&[0; 32000]);
+        let info = probe_bytes(&file).unwrap();
+        assert_eq!(info.data_offset, CANONICAL_HEADER_LEN as u64);
+        assert_eq!(info.data_len, 32000);
+        assert_eq!(info.duration_secs(), 1.0);
+        assert!(info.is_whisper_ready());
+    }
+
+    #[test]
+    fn header_only_is_empty() {
+        let info = probe_bytes(&header(1, 16000, 0)).unwrap();
+        assert!(info.is_empty());
+        assert_eq!(info.duration_secs(), 0.0);
+    }
+
+    #[test]
+    fn list_chunk_before_data() {
+        // What ffmpeg writes: a LIST/INFO chunk naming the encoder
+        let list = chunk(b"LIST", b"INFOISFT\x0e\0\0\0Lavf60.16.100\0");
+        let file = wav(&[chunk(b"fmt ", &fmt_body(1, 16000)), list.clone(), chunk(b"data", &[1, 0, 2, 0, 3, 0])]);
+        let info = probe_bytes(&file).unwrap();
+        assert_eq!(info.data_offset, (12 + 24 + list.len() + 8) as u64);
+        assert_eq!(info.data_len, 6);
+        assert_eq!(info.data(&file), &[1, 0, 2, 0, 3, 0]);
+        assert_eq!(info.frames(), 3);
+    }
+
+    #[test]
+    fn fact_chunk_and_extensible_format() {
+        let mut fmt = fmt_body(2, 48000);
+        fmt[0..2].copy_from_slice(&FORMAT_EXTENSIBLE.to_le_bytes());
+        fmt.extend_from_slice(&[22, 0]);
+        fmt.extend_from_slice(&[0; 22]);
+        let file = wav(&[chunk(b"fmt ", &fmt), chunk(b"fact", &[4, 0, 0, 0]), chunk(b"data", &[0; 16])]);
+        let info = probe_bytes(&file).unwrap();
+        assert_eq!((info.channels, info.sample_rate, info.bits_per_sample), (2, 48000, 16));
+        assert_eq!(info.data_offset, (12 + 8 + 40 + 12 + 8) as u64);
+        assert_eq!(info.data_len, 16);
+        assert_eq!(info.frames(), 4);
+        assert!(!info.is_whisper_ready());
+    }
+
+    #[test]
+    fn odd_sized_chunk_is_padded() {
+        let file = wav(&[chunk(b"fmt ", &fmt_body(1, 16000)), chunk(b"LIST", b"odd"), chunk(b"data", &[7, 0])]);
+        let info = probe_bytes(&file).unwrap();
+        assert_eq!(info.data(&file), &[7, 0]);
+    }
+
+    #[test]
+    fn unfinished_data_size_uses_the_rest_of_the_file() {
+        for size in [0u32, u32::MAX] {
+            let mut file = header(1, 16000, 0);
+            file[40..44].copy_from_slice(&size.to_le_bytes());
+            file.extend_from_slice(&[0; 10]);
+            assert_eq!(probe_bytes(&file).unwrap().data_len, 10);
+        }
+    }
+
+    #[test]
+    fn truncated_data_is_clamped() {
+        let mut file = header(1, 16000, 1000);
+        file.extend_from_slice(&[0; 100]);
+        assert_eq!(probe_bytes(&file).unwrap().data_len, 100);
+    }
+
+    #[test]
+    fn rejects_what_isnt_a_wav() {
+        assert!(probe_bytes(b"ID3\x04\0\0\0\0\0\0").is_err());
+        assert!(probe_bytes(&header(1, 16000, 0)[..30]).is_err());
+        let no_fmt = wav(&[chunk(b"data", &[0; 4])]);
+        assert!(probe_bytes(&no_fmt).is_err());
+    }
+
+    fn measured(samples: &[i16]) -> Levels {
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut file = header(1, 16000, data.len() as u32);
+        file.extend_from_slice(&data);
+        let info = probe_bytes(&file).unwrap();
+        let mut meter = Meter::new(info);
+        // In uneven pieces, as blocks end wherever the buffer does
+        for piece in info.data(&file).chunks(998) {
+            meter.add(piece);
+        }
+        meter.finish()
+    }
+
+    #[test]
+    fn counts_clipped_samples() {
+        let mut samples = vec![1000i16; 900];
+        samples.extend([i16::MAX, i16::MIN].repeat(50));
+        let levels = measured(&samples);
+        assert_eq!(levels.clipped_samples, 100);
+        assert!((levels.clipped_fraction - 0.1).abs() < 1e-9);
+        assert!(levels.peak_dbfs > -0.01);
+    }
+
+    #[test]
+    fn measures_dc_offset() {
+        let levels = measured(&[3277; 1600]);
+        assert!((levels.dc_offset - 0.1).abs() < 0.001);
+        assert_eq!(levels.clipped_samples, 0);
+        assert!((levels.rms_dbfs + 20.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn measures_leading_and_trailing_silence() {
+        // 0.5s silent, 0.2s loud, 0.3s silent at 16kHz
+        let samples: Vec<i16> = [vec![0; 8000], vec![8000; 3200], vec![0; 4800]].concat();
+        let levels = measured(&samples);
+        assert!((levels.leading_silence_secs - 0.5).abs() < 1e-9);
+        assert!((levels.trailing_silence_secs - 0.3).abs() < 1e-9);
+        assert!((levels.loud_secs - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn all_silence_is_leading() {
+        let levels = measured(&[0; 16000]);
+        assert!((levels.leading_silence_secs - 1.0).abs() < 1e-9);
+        assert_eq!(levels.trailing_silence_secs, 0.0);
+        assert_eq!(levels.loud_secs, 0.0);
+        assert_eq!(levels.peak_dbfs, FLOOR_DBFS);
+    }
+
+    #[test]
+    fn rewrites_a_recording_block_by_block() {
+        let path = std::env::temp_dir().join(format!("whisp-away-blocks-{}.wav", std::process::id()));
+        let path = path.to_str().unwrap();
+        // Two and a half blocks, after a LIST chunk
+        let data: Vec<u8> = (0..BLOCK_BYTES * 5 / 2).map(|i| (i % 251) as u8).collect();
+        let mut file = wav(&[chunk(b"fmt ", &fmt_body(1, 16000)), chunk(b"LIST", b"INFO"), chunk(b"data", &data)]);
+        std::fs::write(path, &file).unwrap();
+
+        let mut samples = Samples::open(path).unwrap();
+        let mut sizes = Vec::new();
+        let mut copy = WavWriter::create(path, 1, 16000).unwrap();
+        loop {
+            let block = samples.next_block().unwrap();
+            if block.is_empty() {
+                break;
+            }
+            sizes.push(block.len());
+            copy.write(block).unwrap();
+        }
+        copy.finish().unwrap();
+        assert_eq!(sizes, [BLOCK_BYTES, BLOCK_BYTES, BLOCK_BYTES / 2]);
+        file = std::fs::read(path).unwrap();
+        assert_eq!(&file[..CANONICAL_HEADER_LEN], &header(1, 16000, data.len() as u32)[..]);
+        assert_eq!(&file[CANONICAL_HEADER_LEN..], &data[..]);
+
+        // Left unfinished, the recording stays as it was
+        let mut abandoned = WavWriter::create(path, 1, 16000).unwrap();
+        abandoned.write(&[1, 2]).unwrap();
+        drop(abandoned);
+        assert_eq!(std::fs::read(path).unwrap(), file);
+        assert!(!Path::new(&format!("{}.partial", path)).exists());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn recent_level_measures_the_end_of_a_growing_file() {
+        let path = std::env::temp_dir().join(format!("whisp-away-level-{}.wav", std::process::id()));
+        // 1s of silence then 0.1s at -20 dBFS, with the size a running recorder leaves
+        let mut file = header(1, 16000, 0);
+        file.extend([0i16; 16000].iter().flat_map(|s| s.to_le_bytes()));
+        file.extend([3277i16; 1600].iter().flat_map(|s| s.to_le_bytes()));
+        std::fs::write(&path, &file).unwrap();
+        let loud = recent_level(&path, 0.1).unwrap();
+        let mostly_silent = recent_level(&path, 1.0).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!((loud + 20.0).abs() < 0.1);
+        assert!((mostly_silent + 30.0).abs() < 0.1);
+    }
+}
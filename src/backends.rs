@@ -0,0 +1,79 @@
+use anyhow::{anyhow, Result};
+use std::os::unix::net::UnixListener;
+use crate::typing::Output;
+
+/// Backends this binary was built with, by the name `backend` selects them
+pub const COMPILED: &[&str] = &[
+    #[cfg(feature = "whisper-cpp")]
+    "whisper-cpp",
+    #[cfg(feature = "faster-whisper")]
+    "faster-whisper",
+    #[cfg(feature = "http-backends")]
+    "openai",
+];
+
+/// The cargo feature a backend is built with
+fn feature(backend: &str) -> &'static str {
+    match backend {
+        "openai" => "http-backends",
+        "faster-whisper" => "faster-whisper",
+        _ => "whisper-cpp",
+    }
+}
+
+/// Why a backend can't be used: unknown, or left out of this build
+pub fn unavailable(backend: &str) -> anyhow::Error {
+    if !crate::BACKENDS.contains(&backend) {
+        return anyhow!("Unknown backend: {}", backend);
+    }
+    anyhow!(
+        "whisp-away was built without the {} backend (cargo feature \"{}\"); this build has: {}",
+        backend, feature(backend), if COMPILED.is_empty() { "none".to_string() } else { COMPILED.join(", ") }
+    )
+}
+
+/// Run the backend's daemon
+pub fn run_daemon(backend: &str, model: &str, socket_path: &str, compute_type: Option<&str>, listener: Option<UnixListener>) -> Result<()> {
+    match backend {
+        #[cfg(feature = "whisper-cpp")]
+        "whisper-cpp" => crate::whisper_cpp::run_daemon(model, listener),
+        #[cfg(feature = "faster-whisper")]
+        "faster-whisper" => crate::faster_whisper::run_daemon(model, socket_path, compute_type, listener),
+        "openai" if COMPILED.contains(&"openai") => Err(anyhow!("The openai backend transcribes through the API and needs no daemon")),
+        _ => {
+            let _ = (model, socket_path, compute_type, listener);
+            Err(unavailable(backend))
+        }
+    }
+}
+
+/// Stop the recording and transcribe it the backend's own way: its daemon, then directly
+/// (the API backend has no daemon)
+pub fn stop_and_transcribe(
+    backend: &str,
+    socket_path: &str,
+    output: &Output,
+    compute_type: Option<&str>,
+    batch_size: Option<u32>,
+    final_model: Option<&str>,
+    max_retries: u32,
+) -> Result<()> {
+    match backend {
+        #[cfg(feature = "whisper-cpp")]
+        "whisper-cpp" => {
+            // The bindings can't take arbitrary flags, so extra args mean the fallback runs the binary
+            let bindings = crate::whisper_cpp::direct::extra_args_setting().value.is_empty();
+            crate::whisper_cpp::stop_and_transcribe_daemon(socket_path, None, None, bindings, None, output, final_model)
+        }
+        #[cfg(feature = "faster-whisper")]
+        "faster-whisper" => {
+            crate::faster_whisper::stop_and_transcribe_daemon(socket_path, output, compute_type, batch_size, final_model)
+        }
+        #[cfg(feature = "http-backends")]
+        "openai" => crate::openai::stop_and_transcribe(output, max_retries),
+        _ => {
+            let _ = (socket_path, output, compute_type, batch_size, final_model, max_retries);
+            Err(unavailable(backend))
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Row, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use crate::helpers::now_secs;
+use crate::paths;
+use crate::socket::{Job, JobState};
+
+/// How long a change waits for another process (`watch`, `jobs retry`) to finish its own
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A file queued by `whisp-away watch`, kept across restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchJob {
+    #[serde(flatten)]
+    pub job: Job,
+    /// Absolute path of the audio file
+    pub path: String,
+    /// Why it failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The queue as kept by versions before the database
+#[derive(Debug, Default, Deserialize)]
+struct LegacyStore {
+    jobs: Vec<BatchJob>,
+}
+
+/// The queue, in the state directory so a crash or reboot doesn't lose it
+pub fn store_path() -> PathBuf {
+    paths::state_dir().join("batch-jobs.db")
+}
+
+fn legacy_store_path() -> PathBuf {
+    paths::state_dir().join("batch-jobs.json")
+}
+
+fn state_name(state: JobState) -> &'static str {
+    match state {
+        JobState::Pending => "pending",
+        JobState::Running => "running",
+        JobState::Done => "done",
+        JobState::Failed => "failed",
+        JobState::Cancelled => "cancelled",
+    }
+}
+
+fn parse_state(name: &str) -> JobState {
+    match name {
+        "pending" => JobState::Pending,
+        "running" => JobState::Running,
+        "done" => JobState::Done,
+        "cancelled" => JobState::Cancelled,
+        _ => JobState::Failed,
+    }
+}
+
+/// Columns a job is read back from, in `from_row`'s order
+const COLUMNS: &str = "id, label, model, state, submitted, finished, path, error";
+
+fn from_row(row: &Row) -> rusqlite::Result<BatchJob> {
+    Ok(BatchJob {
+        job: Job {
+            id: row.get(0)?,
+            label: row.get(1)?,
+            model: row.get(2)?,
+            state: parse_state(&row.get::<_, String>(3)?),
+            submitted: row.get(4)?,
+            finished: row.get(5)?,
+            progress: None,
+            request_id: None,
+        },
+        path: row.get(6)?,
+        error: row.get(7)?,
+    })
+}
+
+/// The queue's database; every change is a transaction, so `watch` and `jobs retry` don't
+/// overwrite each other
+struct Queue {
+    db: Connection,
+}
+
+impl Queue {
+    fn open() -> Result<Self> {
+        fs::create_dir_all(paths::state_dir())
+            .with_context(|| format!("Failed to create {}", paths::state_dir().display()))?;
+        let queue = Self::open_at(&store_path())?;
+        queue.import(&legacy_store_path());
+        Ok(queue)
+    }
+
+    fn open_at(path: &Path) -> Result<Self> {
+        let db = Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        db.busy_timeout(BUSY_TIMEOUT)?;
+        db.execute_batch(
+            "CREATE TABLE IF NOT EXISTS jobs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                label TEXT NOT NULL,
+                model TEXT,
+                state TEXT NOT NULL,
+                submitted INTEGER NOT NULL,
+                finished INTEGER,
+                path TEXT NOT NULL,
+                error TEXT
+            );",
+        ).with_context(|| format!("Failed to set up {}", path.display()))?;
+        Ok(Self { db })
+    }
+
+    /// Take over the jobs of the JSON file older versions kept the queue in
+    fn import(&self, legacy: &Path) {
+        let Ok(content) = fs::read_to_string(legacy) else {
+            return;
+        };
+        let store: LegacyStore = match serde_json::from_str(&content) {
+            Ok(store) => store,
+            Err(e) => {
+                warn!("Failed to parse {}, leaving it: {}", legacy.display(), e);
+                return;
+            }
+        };
+        for queued in &store.jobs {
+            let result = self.db.execute(
+                "INSERT INTO jobs (label, model, state, submitted, finished, path, error) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![queued.job.label, queued.job.model, state_name(queued.job.state), queued.job.submitted, queued.job.finished, queued.path, queued.error],
+            );
+            if let Err(e) = result {
+                warn!("Failed to import the batch queue from {}: {}", legacy.display(), e);
+                return;
+            }
+        }
+        debug!("Imported {} batch job(s) from {}", store.jobs.len(), legacy.display());
+        let _ = fs::remove_file(legacy);
+    }
+
+    fn enqueue(&self, path: &Path, model: Option<String>) -> Result<()> {
+        let key = path.to_string_lossy().into_owned();
+        let label = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let queued = self.db.execute(
+            "INSERT INTO jobs (label, model, state, submitted, path)
+             SELECT ?1, ?2, 'pending', ?3, ?4
+             WHERE NOT EXISTS (SELECT 1 FROM jobs WHERE path = ?4 AND state IN ('pending', 'running'))",
+            params![label, model, now_secs(), key],
+        )?;
+        if queued > 0 {
+            debug!("Queued batch job {} for {}", self.db.last_insert_rowid(), key);
+        }
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<usize> {
+        Ok(self.db.execute("UPDATE jobs SET state = 'pending' WHERE state = 'running'", [])?)
+    }
+
+    fn next(&mut self, dir: &Path) -> Result<Option<BatchJob>> {
+        let transaction = self.db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let pending = {
+            let mut statement = transaction.prepare(&format!("SELECT {} FROM jobs WHERE state = 'pending' ORDER BY id", COLUMNS))?;
+            let jobs = statement.query_map([], from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+            jobs.into_iter().find(|queued| Path::new(&queued.path).parent() == Some(dir))
+        };
+        let Some(mut queued) = pending else {
+            return Ok(None);
+        };
+        transaction.execute("UPDATE jobs SET state = 'running', error = NULL WHERE id = ?1", [queued.job.id])?;
+        transaction.commit()?;
+        queued.job.state = JobState::Running;
+        queued.error = None;
+        Ok(Some(queued))
+    }
+
+    fn finish(&self, id: u64, error: Option<String>) -> Result<()> {
+        let state = if error.is_some() { JobState::Failed } else { JobState::Done };
+        self.db.execute(
+            "UPDATE jobs SET state = ?1, finished = ?2, error = ?3 WHERE id = ?4",
+            params![state_name(state), now_secs(), error, id],
+        )?;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<BatchJob>> {
+        let mut statement = self.db.prepare(&format!("SELECT {} FROM jobs ORDER BY id", COLUMNS))?;
+        let jobs = statement.query_map([], from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(jobs)
+    }
+
+    fn retry(&mut self, id: Option<u64>) -> Result<usize> {
+        let transaction = self.db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let queued_again = match id {
+            Some(id) => {
+                let state: String = transaction
+                    .query_row("SELECT state FROM jobs WHERE id = ?1", [id], |row| row.get(0))
+                    .optional()?
+                    .ok_or_else(|| anyhow!("No batch job {}", id))?;
+                if parse_state(&state).is_active() {
+                    return Err(anyhow!("Batch job {} hasn't finished", id));
+                }
+                transaction.execute("UPDATE jobs SET state = 'pending', finished = NULL, error = NULL WHERE id = ?1", [id])?
+            }
+            None => transaction.execute("UPDATE jobs SET state = 'pending', finished = NULL, error = NULL WHERE state = 'failed'", [])?,
+        };
+        transaction.commit()?;
+        Ok(queued_again)
+    }
+
+    fn clear(&self) -> Result<usize> {
+        Ok(self.db.execute("DELETE FROM jobs WHERE state NOT IN ('pending', 'running')", [])?)
+    }
+}
+
+/// Queue a file unless it already waits or runs
+pub fn enqueue(path: &Path, model: Option<String>) -> Result<()> {
+    Queue::open()?.enqueue(path, model)
+}
+
+/// Jobs that were running when `watch` last stopped go back to the queue; returns how many
+pub fn resume() -> Result<usize> {
+    let resumed = Queue::open()?.resume()?;
+    if resumed > 0 {
+        info!("Resuming {} interrupted batch job(s)", resumed);
+    }
+    Ok(resumed)
+}
+
+/// Take the oldest pending job for a file in `dir`, marking it running
+pub fn next(dir: &Path) -> Result<Option<BatchJob>> {
+    Queue::open()?.next(dir)
+}
+
+/// Record how a job ended
+pub fn finish(id: u64, error: Option<String>) -> Result<()> {
+    Queue::open()?.finish(id, error)
+}
+
+/// All batch jobs, oldest first
+pub fn list() -> Result<Vec<BatchJob>> {
+    Queue::open()?.list()
+}
+
+/// Queue failed jobs again, or just job `id`; a running `watch` picks them up right away
+/// Returns how many were queued
+pub fn retry(id: Option<u64>) -> Result<usize> {
+    Queue::open()?.retry(id)
+}
+
+/// Forget finished jobs; `watch` still remembers which files it transcribed, so they aren't
+/// queued again. Returns how many were removed
+pub fn clear() -> Result<usize> {
+    Queue::open()?.clear()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A queue in a state directory of its own
+    fn queue(name: &str) -> (Queue, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("whisp-away-batch-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        (Queue::open_at(&dir.join("batch-jobs.db")).unwrap(), dir)
+    }
+
+    fn states(queue: &Queue) -> Vec<JobState> {
+        queue.list().unwrap().iter().map(|queued| queued.job.state).collect()
+    }
+
+    #[test]
+    fn next_takes_the_oldest_pending_job_of_the_directory() {
+        let (mut queue, dir) = queue("next");
+        queue.enqueue(Path::new("/memos/a.m4a"), None).unwrap();
+        queue.enqueue(Path::new("/other/b.m4a"), None).unwrap();
+        queue.enqueue(Path::new("/memos/c.m4a"), Some("small".to_string())).unwrap();
+        // Already waiting
+        queue.enqueue(Path::new("/memos/a.m4a"), None).unwrap();
+
+        let first = queue.next(Path::new("/memos")).unwrap().unwrap();
+        assert_eq!((first.path.as_str(), first.job.label.as_str(), first.job.state), ("/memos/a.m4a", "a.m4a", JobState::Running));
+        let second = queue.next(Path::new("/memos")).unwrap().unwrap();
+        assert_eq!((second.path.as_str(), second.job.model.as_deref()), ("/memos/c.m4a", Some("small")));
+        assert!(queue.next(Path::new("/memos")).unwrap().is_none());
+        assert_eq!(states(&queue), [JobState::Running, JobState::Pending, JobState::Running]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resume_queues_interrupted_jobs_again() {
+        let (mut queue, dir) = queue("resume");
+        queue.enqueue(Path::new("/memos/a.m4a"), None).unwrap();
+        queue.enqueue(Path::new("/memos/b.m4a"), None).unwrap();
+        let running = queue.next(Path::new("/memos")).unwrap().unwrap();
+        let done = queue.next(Path::new("/memos")).unwrap().unwrap();
+        queue.finish(done.job.id, None).unwrap();
+
+        assert_eq!(queue.resume().unwrap(), 1);
+        assert_eq!(queue.next(Path::new("/memos")).unwrap().unwrap().job.id, running.job.id);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn retry_queues_failed_jobs_again() {
+        let (mut queue, dir) = queue("retry");
+        for name in ["a", "b", "c"] {
+            queue.enqueue(Path::new(&format!("/memos/{}.m4a", name)), None).unwrap();
+        }
+        let a = queue.next(Path::new("/memos")).unwrap().unwrap();
+        let b = queue.next(Path::new("/memos")).unwrap().unwrap();
+        queue.finish(a.job.id, Some("No speech".to_string())).unwrap();
+        queue.finish(b.job.id, None).unwrap();
+        let failed = &queue.list().unwrap()[0];
+        assert_eq!((failed.job.state, failed.error.as_deref()), (JobState::Failed, Some("No speech")));
+        assert!(failed.job.finished.is_some());
+
+        assert!(queue.retry(Some(99)).is_err());
+        // Still waiting
+        assert!(queue.retry(Some(3)).is_err());
+        assert_eq!(queue.retry(None).unwrap(), 1);
+        let retried = &queue.list().unwrap()[0];
+        assert_eq!((retried.job.state, retried.error.as_deref(), retried.job.finished), (JobState::Pending, None, None));
+        // A job that went well can be done again by its number
+        assert_eq!(queue.retry(Some(b.job.id)).unwrap(), 1);
+        assert_eq!(states(&queue), [JobState::Pending; 3]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn clear_forgets_finished_jobs_only() {
+        let (mut queue, dir) = queue("clear");
+        for name in ["a", "b", "c"] {
+            queue.enqueue(Path::new(&format!("/memos/{}.m4a", name)), None).unwrap();
+        }
+        let a = queue.next(Path::new("/memos")).unwrap().unwrap();
+        let b = queue.next(Path::new("/memos")).unwrap().unwrap();
+        queue.finish(a.job.id, None).unwrap();
+        queue.finish(b.job.id, Some("Unsupported format".to_string())).unwrap();
+
+        assert_eq!(queue.clear().unwrap(), 2);
+        let left = queue.list().unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!((left[0].path.as_str(), left[0].job.state), ("/memos/c.m4a", JobState::Pending));
+        // Ids aren't reused after clearing
+        queue.enqueue(Path::new("/memos/d.m4a"), None).unwrap();
+        assert_eq!(queue.list().unwrap()[1].job.id, 4);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn imports_the_json_queue() {
+        let (queue, dir) = queue("import");
+        let legacy = dir.join("batch-jobs.json");
+        fs::write(&legacy, r#"{"next_id": 2, "jobs": [
+            {"id": 1, "label": "a.m4a", "state": "done", "submitted": 10, "finished": 20, "path": "/memos/a.m4a"},
+            {"id": 2, "label": "b.m4a", "state": "running", "submitted": 11, "path": "/memos/b.m4a"}
+        ]}"#).unwrap();
+        queue.import(&legacy);
+
+        assert!(!legacy.exists());
+        let jobs = queue.list().unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!((jobs[0].job.finished, jobs[1].job.state), (Some(20), JobState::Running));
+        assert_eq!(queue.resume().unwrap(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::{anyhow, Result};
+use tracing::{debug, info, warn};
+use crate::clipboard;
+use crate::hallucination;
+use crate::postprocess;
+use crate::settings::{self, Layers, Resolved};
+use crate::socket::{self, TranscriptionRequest, TranscriptionResponse};
+use crate::typing::{self, Output};
+
+/// Resolves whether each dictation is also translated to English with priority:
+/// 1. WA_BILINGUAL env var
+/// 2. User config file `bilingual.enabled`
+/// 3. Default to false
+pub fn enabled_setting() -> Resolved<bool> {
+    Layers::new("bilingual")
+        .env("WA_BILINGUAL", settings::flag)
+        .config(|c| c.bilingual?.enabled)
+        .or_default(false)
+}
+
+/// Resolves what is output (typed, pasted, copied or sent, as usual) in bilingual mode with priority:
+/// 1. WA_BILINGUAL_OUTPUT env var
+/// 2. User config file `bilingual.output`
+/// 3. Default to "{original}"
+pub fn output_template_setting() -> Resolved<String> {
+    Layers::new("bilingual output")
+        .env("WA_BILINGUAL_OUTPUT", Some)
+        .config(|c| c.bilingual?.output)
+        .or_default("{original}".to_string())
+}
+
+/// Resolves what is copied to the clipboard after the output in bilingual mode with priority:
+/// 1. WA_BILINGUAL_COPY env var ("" to copy nothing)
+/// 2. User config file `bilingual.copy`
+/// 3. Default to "{translation}"
+pub fn copy_template_setting() -> Resolved<String> {
+    Layers::new("bilingual copy")
+        .env("WA_BILINGUAL_COPY", Some)
+        .config(|c| c.bilingual?.copy)
+        .or_default("{translation}".to_string())
+}
+
+pub fn enabled() -> bool {
+    enabled_setting().value
+}
+
+/// Fill in a template's {original}, {translation} and {language} in one pass, so a
+/// transcript that says "{translation}" is output as it was said; other braces stay as they are
+pub fn render(template: &str, original: &str, translation: &str, language: &str) -> String {
+    let vars = [("{original}", original), ("{translation}", translation), ("{language}", language)];
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match vars.iter().find(|(name, _)| rest.starts_with(name)) {
+            Some((name, value)) => {
+                rendered.push_str(value);
+                rest = &rest[name.len()..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Transcribe the dictation's audio again as an English translation, filling in
+/// `response.translation`
+/// English dictations are their own translation; a failed translation is only logged, so
+/// the transcript is still output
+pub fn add_translation(socket_path: &str, request: &TranscriptionRequest, response: &mut TranscriptionResponse) {
+    if is_english(response.language.as_deref()) {
+        debug!("Dictation is in English, using it as its own translation");
+        response.translation = response.text.clone();
+        return;
+    }
+    info!("Translating the {} dictation to English", response.language.as_deref().unwrap_or("unknown"));
+    let translate = TranscriptionRequest {
+        task: Some("translate".to_string()),
+        label: Some("translation".to_string()),
+        stream: false,
+        words: false,
+        ..request.clone()
+    };
+    match socket::transcribe(socket_path, &translate).and_then(translated_text) {
+        Ok(translation) => response.translation = Some(translation),
+        Err(e) => warn!("Failed to translate the dictation: {:#}", e),
+    }
+}
+
+/// Whether a detected language is English; OpenAI's API names languages in full
+pub fn is_english(language: Option<&str>) -> bool {
+    language.is_some_and(|language| language.eq_ignore_ascii_case("en") || language.eq_ignore_ascii_case("english"))
+}
+
+/// The filtered and post-processed text of a translation pass
+pub fn translated_text(mut translation: TranscriptionResponse) -> Result<String> {
+    if translation.cancelled || !translation.success {
+        return Err(anyhow!("{}", translation.error.as_deref().unwrap_or("translation failed")));
+    }
+    hallucination::filter(&mut translation);
+    let text = translation.text.ok_or_else(|| anyhow!("no text in the translation"))?;
+    Ok(postprocess::apply(text.trim(), Some("en")))
+}
+
+/// Output a transcript: as is without a translation, otherwise through the output template,
+/// then copy the copy template (unless the output already went to the clipboard)
+pub fn output(response: &TranscriptionResponse, output: &Output, source: &str) -> Result<()> {
+    let original = response.text.as_deref().unwrap_or_default();
+    let Some(translation) = response.translation.as_deref() else {
+        return typing::output_text(original, output, source);
+    };
+    let language = response.language.as_deref().unwrap_or_default();
+    let text = render(&output_template_setting().value, original, translation, language);
+    typing::output_text(&text, output, source)?;
+
+    let copied = render(&copy_template_setting().value, original, translation, language);
+    if copied.trim().is_empty() {
+        return Ok(());
+    }
+    if *output == Output::Clipboard {
+        debug!("Output went to the clipboard, not copying the bilingual copy template over it");
+        return Ok(());
+    }
+    debug!("Copying the bilingual copy template ({} chars)", copied.len());
+    clipboard::copy(copied.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_templates() {
+        let render = |template| render(template, "Hola a todos", "Hello everyone", "es");
+        assert_eq!(render("{original}"), "Hola a todos");
+        assert_eq!(render("{original} ({language}: {translation})"), "Hola a todos (es: Hello everyone)");
+        assert_eq!(render("{translation}{translation}"), "Hello everyoneHello everyone");
+        assert_eq!(render(""), "");
+        assert_eq!(render("{unknown} {original"), "{unknown} {original");
+    }
+
+    #[test]
+    fn placeholders_in_the_transcript_stay_text() {
+        let original = "Fill in {translation} and {language} here";
+        let translation = "Now {original}";
+        let rendered = render("{original} / {translation} / {language}", original, translation, "{original}");
+        assert_eq!(rendered, "Fill in {translation} and {language} here / Now {original} / {original}");
+    }
+}
@@ -0,0 +1,95 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::{ErrorKind, Write};
+use std::process::{Child, Command, Stdio};
+use tracing::debug;
+use crate::settings::{Layers, Resolved};
+use crate::sinks;
+use crate::typing::Output;
+
+/// Menus tried in turn for the "auto" chooser, with their dmenu-mode flags
+const MENUS: [(&str, &[&str]); 4] = [
+    ("rofi", &["-dmenu", "-i", "-p", "Output"]),
+    ("wofi", &["--dmenu", "--prompt", "Output"]),
+    ("fuzzel", &["--dmenu", "--prompt", "Output: "]),
+    ("dmenu", &["-i", "-p", "Output"]),
+];
+
+/// Resolves the menu for --choose-output, one of those names or a shell command that reads
+/// the entries on stdin and prints the selected one (e.g. "tofi --prompt-text 'Output: '"),
+/// with priority:
+/// 1. WA_CHOOSER env var
+/// 2. User config file `chooser`
+/// 3. Default to "auto": the first of rofi, wofi, fuzzel and dmenu that is installed
+pub fn chooser_setting() -> Resolved<String> {
+    Layers::new("chooser")
+        .env("WA_CHOOSER", Some)
+        .config(|c| c.chooser)
+        .or_default("auto".to_string())
+}
+
+/// Menu entry for an output
+fn label(output: &Output) -> String {
+    match output {
+        Output::Type => "Type here".to_string(),
+        Output::Clipboard => "Copy to clipboard".to_string(),
+        Output::Fifo => format!("Write to {}", sinks::fifo_path_setting().value),
+        Output::Plugin(name) => format!("Send to {}", name),
+        Output::File(name) => format!("Add to {}", name),
+    }
+}
+
+/// Every output the menu offers: the built-in ones, then each sink plugin and file
+fn entries() -> Vec<Output> {
+    let mut outputs = vec![Output::Type, Output::Clipboard, Output::Fifo];
+    outputs.extend(sinks::list_sinks().into_iter().map(Output::Plugin));
+    outputs.extend(sinks::list_files().into_iter().map(Output::File));
+    outputs
+}
+
+/// Start the menu for a chooser setting: a known menu with its flags, or a shell command
+fn spawn_menu(chooser: &str) -> Result<Child> {
+    let spawn = |command: &mut Command| command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn();
+    if chooser == "auto" {
+        for (program, args) in MENUS {
+            match spawn(Command::new(program).args(args)) {
+                Err(e) if e.kind() == ErrorKind::NotFound => debug!("{} not installed", program),
+                result => return result.with_context(|| format!("Failed to run {}", program)),
+            }
+        }
+        return Err(anyhow!("No menu found (tried rofi, wofi, fuzzel and dmenu), set WA_CHOOSER"));
+    }
+    match MENUS.iter().find(|(menu, _)| *menu == chooser) {
+        Some((program, args)) => spawn(Command::new(program).args(*args)),
+        None => spawn(Command::new("sh").args(["-c", chooser])),
+    }
+    .with_context(|| format!("Failed to run chooser '{}'", chooser))
+}
+
+/// Let the user pick where the transcript goes, with `default` first so Enter keeps it
+/// None when the menu is dismissed
+pub fn choose_output(default: &Output) -> Result<Option<Output>> {
+    let mut outputs = entries();
+    outputs.retain(|output| output != default);
+    outputs.insert(0, default.clone());
+    let menu: Vec<String> = outputs.iter().map(label).collect();
+
+    let chooser = chooser_setting().value;
+    debug!("Choosing output with '{}' from {:?}", chooser, menu);
+    let mut child = spawn_menu(&chooser)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A menu that closes right away shows up in its exit status
+        let _ = stdin.write_all(menu.join("\n").as_bytes());
+    }
+    let result = child.wait_with_output().context("Failed to wait for the chooser")?;
+
+    // Menus exit non-zero when dismissed with Escape
+    let selected = String::from_utf8_lossy(&result.stdout).trim().to_string();
+    if !result.status.success() || selected.is_empty() {
+        return Ok(None);
+    }
+    match menu.iter().position(|entry| *entry == selected) {
+        Some(index) => Ok(Some(outputs.swap_remove(index))),
+        // Menus that allow free input (rofi, dmenu) may return text that isn't an entry
+        None => selected.parse().map(Some).map_err(|e: String| anyhow!("Unknown output '{}': {}", selected, e)),
+    }
+}
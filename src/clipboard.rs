@@ -0,0 +1,262 @@
+use anyhow::{anyhow, Context, Result};
+#[cfg(target_os = "linux")]
+use arboard::SetExtLinux;
+#[cfg(target_os = "macos")]
+use arboard::SetExtApple;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use std::time::Instant;
+use tracing::{debug, info, warn};
+use crate::settings::{self, Layers, Resolved};
+
+/// Resolves how long copied text stays on the clipboard with priority:
+/// 1. WA_CLIPBOARD_HOLD_SECS env var
+/// 2. User config file `clipboard_hold_secs`
+/// 3. Default to 0: until something else is copied
+pub fn hold_secs_setting() -> Resolved<u64> {
+    Layers::new("clipboard hold (s)")
+        .env("WA_CLIPBOARD_HOLD_SECS", settings::parsed)
+        .config(|c| c.clipboard_hold_secs)
+        .or_default(0)
+}
+
+/// Resolves whether copied transcripts are hidden from clipboard managers with priority:
+/// 1. WA_CLIPBOARD_SENSITIVE env var
+/// 2. User config file `[clipboard] sensitive`
+/// 3. Default to false
+pub fn sensitive_setting() -> Resolved<bool> {
+    Layers::new("clipboard sensitive")
+        .env("WA_CLIPBOARD_SENSITIVE", settings::flag)
+        .config(|c| c.clipboard.and_then(|clipboard| clipboard.sensitive))
+        .or_default(false)
+}
+
+/// Resolves the text that toggles recording when copied, for `whisp-away clipboard-trigger`,
+/// with priority:
+/// 1. WA_CLIPBOARD_TRIGGER env var
+/// 2. User config file `[clipboard] trigger`
+/// 3. Default to "whisp-away:toggle"
+pub fn trigger_setting() -> Resolved<String> {
+    Layers::new("clipboard trigger")
+        .env("WA_CLIPBOARD_TRIGGER", Some)
+        .config(|c| c.clipboard.and_then(|clipboard| clipboard.trigger))
+        .or_default("whisp-away:toggle".to_string())
+}
+
+/// How often `clipboard-trigger` reads the clipboard
+const TRIGGER_POLL: Duration = Duration::from_millis(250);
+
+/// Run `whisp-away toggle` (with `toggle_args`) whenever the trigger text is copied
+/// The clipboard then gets back what it held before, so the trigger can be copied again
+/// and nothing pastes it by mistake; a trigger already copied when this starts is ignored
+pub fn watch_trigger(toggle_args: &[String]) -> Result<()> {
+    let trigger = trigger_setting().value;
+    let trigger = trigger.trim();
+    if trigger.is_empty() {
+        return Err(anyhow!("The clipboard trigger is empty"));
+    }
+    let exe = std::env::current_exe().context("Failed to locate whisp-away executable")?;
+    let mut previous = paste()?;
+    info!("Toggling recording whenever '{}' is copied", trigger);
+
+    let mut unreadable = false;
+    loop {
+        std::thread::sleep(TRIGGER_POLL);
+        let current = match paste() {
+            Ok(current) => current,
+            Err(e) => {
+                // Logged once per outage rather than every poll
+                if !std::mem::replace(&mut unreadable, true) {
+                    warn!("{:#}", e);
+                }
+                continue;
+            }
+        };
+        unreadable = false;
+        if current == previous {
+            continue;
+        }
+        if current.trim() != trigger {
+            previous = current;
+            continue;
+        }
+
+        debug!("Clipboard trigger copied, toggling recording");
+        if let Err(e) = copy(&previous) {
+            warn!("Failed to put back what the clipboard held: {:#}", e);
+        }
+        // Whatever the clipboard holds now, so a trigger that couldn't be replaced isn't
+        // taken for a new one on the next read
+        previous = paste().unwrap_or(current);
+        match Command::new(&exe).arg("toggle").args(toggle_args).status() {
+            Ok(status) if !status.success() => warn!("whisp-away toggle exited with {}", status),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to run whisp-away toggle: {}", e),
+        }
+    }
+}
+
+/// Copy text to the clipboard
+/// On Wayland and X11 the contents vanish with the process that owns them, so they are
+/// served by a detached `whisp-away serve-clipboard`; wl-copy/xclip are the fallback when
+/// no clipboard can be opened in-process
+pub fn copy(text: &str) -> Result<()> {
+    match spawn_holder(text) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            warn!("Native clipboard unavailable ({:#}), trying wl-copy/xclip", e);
+            if sensitive_setting().value {
+                warn!("wl-copy and xclip can't hide the text from clipboard managers");
+            }
+            copy_with_command(text)
+        }
+    }
+}
+
+fn spawn_holder(text: &str) -> Result<()> {
+    let hold_secs = hold_secs_setting().value;
+    let exe = std::env::current_exe().context("Failed to locate whisp-away executable")?;
+    let mut command = Command::new(exe);
+    command.args(["serve-clipboard", "--hold-secs", &hold_secs.to_string()]);
+    if sensitive_setting().value {
+        command.arg("--sensitive");
+    }
+    let mut child = command
+        // Logs go to stdout, which carries the reply
+        .env_remove("RUST_LOG")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .context("Failed to start clipboard holder")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+
+    // The holder answers once it has a clipboard, then keeps serving after we exit
+    let stdout = child.stdout.take().context("Clipboard holder has no stdout")?;
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        if line == "ok" {
+            debug!("Clipboard served by PID {} ({}s, 0 = until replaced)", child.id(), hold_secs);
+            return Ok(());
+        }
+        if let Some(error) = line.strip_prefix("error: ") {
+            return Err(anyhow!(error.to_string()));
+        }
+    }
+    Err(anyhow!("Clipboard holder exited"))
+}
+
+/// Own the clipboard with the text read from stdin (the detached holder process)
+/// Replies "ok" or the error on stdout, then serves until something else is copied,
+/// or for at most `hold_secs` when it isn't 0
+/// `sensitive` offers the x-kde-passwordManagerHint type alongside the text, which Klipper
+/// and wl-clipboard based managers such as cliphist take as "don't keep this"
+pub fn serve(hold_secs: u64, sensitive: bool) -> Result<()> {
+    let mut text = String::new();
+    std::io::stdin().read_to_string(&mut text)?;
+
+    let clipboard = match arboard::Clipboard::new() {
+        Ok(clipboard) => clipboard,
+        Err(e) => {
+            println!("error: {}", e);
+            return Err(e.into());
+        }
+    };
+    println!("ok");
+
+    own(clipboard, text, hold_secs, sensitive)
+}
+
+/// Wayland and X11 serve the clipboard from this process, so it stays alive while the text is held
+#[cfg(target_os = "linux")]
+fn own(mut clipboard: arboard::Clipboard, text: String, hold_secs: u64, sensitive: bool) -> Result<()> {
+    let mut set = clipboard.set();
+    if sensitive {
+        set = set.exclude_from_history();
+    }
+    if hold_secs == 0 {
+        return set.wait().text(text).context("Failed to set clipboard");
+    }
+    let deadline = Instant::now() + Duration::from_secs(hold_secs);
+    let result = set.wait_until(deadline).text(text);
+    // Wayland serves from a background thread and returns right away, so stay alive until the deadline
+    std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+    result.context("Failed to set clipboard")
+}
+
+/// The macOS pasteboard keeps the text after we exit; it's cleared after `hold_secs`
+/// unless something else was copied by then
+#[cfg(target_os = "macos")]
+fn own(mut clipboard: arboard::Clipboard, text: String, hold_secs: u64, sensitive: bool) -> Result<()> {
+    let mut set = clipboard.set();
+    if sensitive {
+        set = set.exclude_from_history();
+    }
+    set.text(text.clone()).context("Failed to set clipboard")?;
+    if hold_secs > 0 {
+        std::thread::sleep(Duration::from_secs(hold_secs));
+        if clipboard.get_text().is_ok_and(|current| current == text) {
+            clipboard.clear()?;
+        }
+    }
+    Ok(())
+}
+
+/// Read text from the clipboard, falling back to wl-paste (Wayland) and xclip (X11)
+pub fn paste() -> Result<String> {
+    match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) {
+        Ok(text) => return Ok(text),
+        Err(e) => debug!("Native clipboard unavailable ({}), trying wl-paste/xclip", e),
+    }
+    [("wl-paste", &["--no-newline"][..]), ("xclip", &["-selection", "clipboard", "-o"][..])]
+        .iter()
+        .find_map(|(program, args)| {
+            let output = Command::new(program).args(*args).output().ok()?;
+            output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+        })
+        .context("Failed to read the clipboard (tried wl-paste and xclip)")
+}
+
+/// Copy text with wl-copy (Wayland) or xclip (X11)
+fn copy_with_command(text: &str) -> Result<()> {
+    // Try wl-copy first (Wayland)
+    let wl_copy_result = Command::new("wl-copy")
+        .stdin(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(text.as_bytes())?;
+                drop(stdin);
+            }
+            child.wait()
+        });
+
+    if let Ok(status) = wl_copy_result {
+        if status.success() {
+            return Ok(());
+        }
+    }
+
+    // Fallback to xclip (X11)
+    let mut child = Command::new("xclip")
+        .args(["-selection", "clipboard"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to run clipboard command (tried wl-copy and xclip)")?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+        drop(stdin);
+    }
+
+    child.wait()
+        .context("Clipboard command failed")?;
+
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use crate::focus;
+use crate::helpers::{self, now_secs};
+use crate::instance;
+use crate::paths;
+use crate::platform::{self, Platform};
+use crate::settings::{self, Layers, Resolved};
+
+/// A dictation typed longer ago than this isn't scratched; by then the cursor has
+/// probably moved and backspacing would delete something else
+const SCRATCH_WINDOW_SECS: u64 = 300;
+
+/// Typed dictations remembered for scratching, newest last
+const MAX_REMEMBERED: usize = 20;
+
+/// Phrases that scratch the previous dictation when a transcript starts with them, and
+/// whether they also count when more words follow without punctuation in between
+/// ("undo the last step" is dictation, "undo. the last step" a command)
+const SCRATCH_PHRASES: &[(&str, bool)] = &[
+    ("scratch that", true),
+    ("undo that", true),
+    ("undo", false),
+];
+
+/// Resolves whether spoken commands at the start of a dictation are acted on with priority:
+/// 1. WA_VOICE_COMMANDS env var
+/// 2. User config file `voice_commands`
+/// 3. Default to false
+pub fn enabled_setting() -> Resolved<bool> {
+    Layers::new("voice commands")
+        .env("WA_VOICE_COMMANDS", settings::flag)
+        .config(|c| c.voice_commands)
+        .or_default(false)
+}
+
+/// A dictation typed at the cursor
+#[derive(Debug, Serialize, Deserialize)]
+struct Typed {
+    /// Characters typed, which is how many backspaces erase it
+    chars: usize,
+    /// Unix time it was typed
+    at: u64,
+    /// Window it was typed into, when focus can be detected
+    app_id: Option<String>,
+}
+
+fn typed_path() -> String {
+    instance::runtime_path("whisp-away-typed", "json")
+}
+
+fn read_typed() -> Vec<Typed> {
+    std::fs::read_to_string(typed_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_typed(typed: &[Typed]) {
+    let result = serde_json::to_vec(typed)
+        .map_err(std::io::Error::other)
+        .and_then(|json| paths::write_atomic(std::path::Path::new(&typed_path()), &json));
+    if let Err(e) = result {
+        debug!("Failed to write typed dictations: {}", e);
+    }
+}
+
+/// Remember the length of a dictation typed at the cursor, so "scratch that" can erase it
+pub fn remember_typed(text: &str) {
+    if !enabled_setting().value || text.is_empty() {
+        return;
+    }
+    let mut typed = read_typed();
+    typed.push(Typed {
+        chars: text.chars().count(),
+        at: now_secs(),
+        app_id: focus::focused_window().map(|window| window.app_id).filter(|app_id| !app_id.is_empty()),
+    });
+    let excess = typed.len().saturating_sub(MAX_REMEMBERED);
+    typed.drain(..excess);
+    write_typed(&typed);
+}
+
+/// Forget the newest typed dictation after it was erased some other way (a refinement
+/// backspacing over its draft)
+pub fn forget_last_typed() {
+    let mut typed = read_typed();
+    if typed.pop().is_some() {
+        write_typed(&typed);
+    }
+}
+
+/// The text after one leading scratch phrase, or None when the text doesn't start with one
+fn strip_scratch(text: &str) -> Option<&str> {
+    SCRATCH_PHRASES.iter().find_map(|&(phrase, followed_by_words)| {
+        let head = text.get(..phrase.len())?;
+        if !head.eq_ignore_ascii_case(phrase) {
+            return None;
+        }
+        let rest = &text[phrase.len()..];
+        let separator = |c: char| matches!(c, '.' | ',' | '!' | ';' | ':');
+        match rest.chars().next() {
+            None => Some(rest),
+            Some(c) if c.is_alphanumeric() => None,
+            Some(c) if !separator(c) && !followed_by_words => None,
+            Some(_) => Some(rest.trim_start_matches(|c: char| separator(c) || c.is_whitespace())),
+        }
+    })
+}
+
+/// Split the scratch phrases off the start of a transcript: how many there were and the
+/// text after them
+pub fn leading_scratches(text: &str) -> (usize, &str) {
+    let mut count = 0;
+    let mut rest = text.trim_start();
+    while let Some(after) = strip_scratch(rest) {
+        count += 1;
+        rest = after;
+    }
+    (count, rest)
+}
+
+/// Erase the newest typed dictation with backspaces
+/// False when there is none recent enough, or it went to another window than the focused one
+fn scratch_previous() -> Result<bool> {
+    let mut typed = read_typed();
+    let Some(last) = typed.pop() else {
+        return Ok(false);
+    };
+    if now_secs().saturating_sub(last.at) > SCRATCH_WINDOW_SECS {
+        debug!("Previous dictation is too old to scratch");
+        write_typed(&[]);
+        return Ok(false);
+    }
+    if let (Some(typed_into), Some(focused)) = (&last.app_id, focus::focused_window()) {
+        if *typed_into != focused.app_id {
+            debug!("Previous dictation went to {}, not the focused {}", typed_into, focused.app_id);
+            return Ok(false);
+        }
+    }
+    debug!("Scratching the previous dictation ({} characters)", last.chars);
+    platform::Current::erase(last.chars)?;
+    write_typed(&typed);
+    Ok(true)
+}
+
+/// Act on the scratch phrases a transcript typed at the cursor starts with, returning the
+/// text left to type
+/// Each phrase backspaces over one earlier dictation; when nothing is left to type, the
+/// outcome is notified here
+pub fn interpret(text: &str) -> Result<String> {
+    if !enabled_setting().value {
+        return Ok(text.to_string());
+    }
+    let (count, rest) = leading_scratches(text);
+    if count == 0 {
+        return Ok(text.to_string());
+    }
+    let mut scratched = 0;
+    for _ in 0..count {
+        if !scratch_previous()? {
+            break;
+        }
+        scratched += 1;
+    }
+    if scratched < count {
+        warn!("Scratched {} of {} dictations, nothing else to scratch", scratched, count);
+    }
+    if rest.is_empty() {
+        let message = match scratched {
+            0 => "⚠️ Nothing to scratch".to_string(),
+            1 => "↩️ Scratched the previous dictation".to_string(),
+            n => format!("↩️ Scratched the previous {} dictations", n),
+        };
+        helpers::send_notification("Voice Input", &message, 1500);
+    }
+    Ok(rest.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_leading_scratches() {
+        assert_eq!(leading_scratches("Scratch that."), (1, ""));
+        assert_eq!(leading_scratches("  undo that"), (1, ""));
+        assert_eq!(leading_scratches("Undo!"), (1, ""));
+        assert_eq!(leading_scratches("Scratch that. Scratch that, see you at five."), (2, "see you at five."));
+        assert_eq!(leading_scratches("Undo that. Undo. Hello"), (2, "Hello"));
+        assert_eq!(leading_scratches("scratch that meeting is moved"), (1, "meeting is moved"));
+    }
+
+    #[test]
+    fn leaves_dictation_that_only_looks_like_a_command() {
+        // "undo" is only a command when punctuation ends it
+        assert_eq!(leading_scratches("Undo the last step"), (0, "Undo the last step"));
+        assert_eq!(leading_scratches("Undo. The last step"), (1, "The last step"));
+        assert_eq!(leading_scratches("Undone, the work piles up"), (0, "Undone, the work piles up"));
+        assert_eq!(leading_scratches("Scratch thatch"), (0, "Scratch thatch"));
+        assert_eq!(leading_scratches("Please scratch that"), (0, "Please scratch that"));
+        assert_eq!(leading_scratches("Übung macht den Meister"), (0, "Übung macht den Meister"));
+        assert_eq!(leading_scratches(""), (0, ""));
+    }
+}
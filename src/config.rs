@@ -0,0 +1,707 @@
+use anyhow::{Context, Result};
+#[cfg(any(feature = "tray", feature = "whisper-cpp", feature = "faster-whisper"))]
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(any(feature = "tray", feature = "whisper-cpp", feature = "faster-whisper"))]
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{debug, trace, warn};
+
+/// User configuration file - edited by the user, read by every command
+/// All fields are optional; unset fields fall through to the daemon config and defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    pub backend: Option<String>,
+    pub model: Option<String>,
+    pub language: Option<String>,
+    /// Pick the language from the active keyboard layout unless one is given on the command line (default false)
+    pub layout_language: Option<bool>,
+    /// Languages for keyboard layouts the built-in table doesn't know, e.g. { "us(intl)" = "pt" }
+    pub layout_languages: Option<BTreeMap<String, String>>,
+    /// "transcribe" or "translate" (to English)
+    pub task: Option<String>,
+    /// Text whisper is primed with, e.g. names and jargon it should spell your way
+    pub initial_prompt: Option<String>,
+    pub use_clipboard: Option<bool>,
+    /// "type", "clipboard", "fifo" or "plugin:<name>" - takes precedence over use_clipboard
+    pub output: Option<String>,
+    /// Named pipe for `output = "fifo"`
+    pub fifo_path: Option<String>,
+    /// Menu for `--choose-output`: "auto", "rofi", "wofi", "fuzzel", "dmenu" or a command
+    pub chooser: Option<String>,
+    pub compute_type: Option<String>,
+    pub batch_size: Option<u32>,
+    /// Send audio over the socket instead of a file path
+    pub inline_audio: Option<bool>,
+    /// Pass the audio's file descriptor over the socket instead of a file path (Linux)
+    pub fd_audio: Option<bool>,
+    /// Remote daemon to use instead of the socket: "host:port", "tcp://host:port" or "tls://host:port"
+    pub daemon_address: Option<String>,
+    /// Where the daemon also takes requests from other machines, in the same form
+    pub daemon_listen: Option<String>,
+    /// Pre-shared token remote clients send, and the daemon requires of them
+    pub daemon_token: Option<String>,
+    /// The daemon's certificate chain and key (PEM) for a "tls://" daemon_listen
+    pub daemon_tls_cert: Option<String>,
+    pub daemon_tls_key: Option<String>,
+    /// Certificates (PEM) a client trusts for a "tls://" daemon_address, instead of the system's
+    pub daemon_tls_ca: Option<String>,
+    /// Larger model for the second pass of two-stage transcription
+    pub final_model: Option<String>,
+    /// Names for models, e.g. { notes = "distil-large-v3" } or per backend
+    /// { notes = { whisper-cpp = "medium", faster-whisper = "distil-large-v3" } }
+    pub model_aliases: Option<BTreeMap<String, ModelAlias>>,
+    /// Directories searched for models besides the download directories, e.g. ["~/models"]
+    pub model_dirs: Option<Vec<String>>,
+    /// Memory the daemon may use for resident models before unloading the least recently used
+    pub model_memory_mb: Option<u64>,
+    /// Minutes a daemon started by systemd socket activation waits without a transcription before exiting (0 keeps it running)
+    pub daemon_idle_exit_mins: Option<u64>,
+    /// Recordings shorter than this many seconds skip temperature fallback and beam search (default 5, 0 turns it off)
+    pub fast_path_secs: Option<f64>,
+    /// `notifications = "auto"`, or a `[notifications]` table with the mode and options
+    pub notifications: Option<Notifications>,
+    /// On-screen display command for setups without a notification server
+    pub osd_command: Option<String>,
+    /// Keep transcripts in the history file (default true)
+    pub history: Option<bool>,
+    /// Keep transcripts out of logs, history and notifications, overwrite recordings before
+    /// removing them and zero transcripts in memory once output (default false)
+    pub privacy: Option<bool>,
+    /// Keep at most this many transcripts
+    pub history_max_entries: Option<usize>,
+    /// Delete transcripts older than this, e.g. "1d" or "30d"
+    pub history_max_age: Option<String>,
+    /// Keep the history file under this many MB, dropping the oldest transcripts
+    pub history_max_mb: Option<u64>,
+    /// Keep the focused app and window title with each transcript, for `history --search`
+    pub history_window: Option<bool>,
+    /// Add speaking pace and pauses to the result notification
+    pub speech_stats: Option<bool>,
+    /// Add where the time went (recording, daemon, typing) to the result notification and JSON output
+    pub latency_breakdown: Option<bool>,
+    /// "mic", "monitor" (system audio) or "both" - what recordings capture
+    pub source: Option<String>,
+    /// "mono", "stereo" (downmixed, leaving out a silent side), "left" or "right" - microphone channels
+    pub channels: Option<String>,
+    /// Volume multiplier for the microphone while recording (default 1.5); lower it when recordings clip
+    pub mic_gain: Option<f64>,
+    /// Seconds of sound a recording needs to be transcribed; quieter ones are rejected as silence (default 0.2, 0 for off)
+    pub min_sound_secs: Option<f64>,
+    /// Minutes after which a dictation goes on in a new segment file, the closed one transcribed meanwhile (default off)
+    pub segment_mins: Option<u64>,
+    /// Keep a copy of the last recording in the runtime directory, for replaying it in `tui`
+    pub keep_last_recording: Option<bool>,
+    /// Unmute a muted microphone while recording instead of warning about it
+    pub auto_unmute: Option<bool>,
+    /// Have the daemon record instead of the client, for clients in a sandbox
+    pub daemon_recording: Option<bool>,
+    /// Extra flags for the whisper.cpp binary, e.g. ["--entropy-thold", "2.8"]
+    pub whisper_args: Option<Vec<String>>,
+    /// Seconds to wait for the --wait-for-focus window before copying to the clipboard instead
+    pub focus_timeout_secs: Option<u64>,
+    /// Type daemon transcripts segment by segment as they are decoded (default true)
+    pub stream_segments: Option<bool>,
+    /// Characters typed at the cursor in one go before copying to the clipboard instead (default 5000, 0 for no limit)
+    pub max_typed_chars: Option<usize>,
+    /// How a dictation ends: "keep" the model's punctuation, "space" after it, or "strip" it
+    pub ending: Option<String>,
+    /// Conventions for quotes, numbers and punctuation: "auto" (by the transcript's language), "off", "en", "de" or "fr"
+    pub locale: Option<String>,
+    /// Keystroke tool on Linux: "auto" (by the focused window), "wtype" or "xdotool"
+    pub typing_tool: Option<String>,
+    /// Milliseconds between typed keys in native Wayland windows
+    pub type_delay_ms: Option<u64>,
+    /// Milliseconds between typed keys in XWayland and X11 windows (default 12)
+    pub xwayland_type_delay_ms: Option<u64>,
+    /// Type nothing while the screen is locked (default true)
+    pub respect_screen_lock: Option<bool>,
+    /// Into terminals: "paste" (bracketed paste with Ctrl+Shift+V), "type" (without newlines) or "off"
+    pub terminal_output: Option<String>,
+    /// App ids / window classes treated as terminals; replaces the built-in list
+    pub terminal_apps: Option<Vec<String>>,
+    /// How long copied text stays on the clipboard, 0 until something else is copied
+    pub clipboard_hold_secs: Option<u64>,
+    /// Free space (MB) the runtime directory needs before recordings are written to disk instead
+    pub spill_threshold_mb: Option<u64>,
+    /// OpenAI-compatible endpoint for the "openai" backend (default https://api.openai.com/v1)
+    pub api_url: Option<String>,
+    /// Model name sent to the API (default whisper-1)
+    pub api_model: Option<String>,
+    /// Retries after a transient upload failure
+    pub max_retries: Option<u32>,
+    /// Ways of transcribing tried in order until one works, e.g. ["daemon", "whisper-cpp", "openai"]
+    pub failover: Option<Vec<String>>,
+    /// Model of the portable build's built-in engine, downloaded on first use (default "base")
+    pub embedded_model: Option<String>,
+    /// Grammar correction before output: "off", "languagetool" or "command:<command>"
+    pub grammar: Option<String>,
+    /// Local LanguageTool server for `grammar = "languagetool"`
+    pub languagetool_url: Option<String>,
+    /// Turn spoken addresses ("john dot doe at example dot com") into written ones (default true)
+    pub spoken_forms: Option<bool>,
+    /// Act on "scratch that" / "undo" at the start of a dictation (default false)
+    pub voice_commands: Option<bool>,
+    /// Drop text whisper makes up on silence or noise (default true)
+    pub hallucination_filter: Option<bool>,
+    /// No-speech probability above which a segment is dropped (default 0.8)
+    pub no_speech_threshold: Option<f64>,
+    /// Phrases dropped when they make up a whole segment or sentence; replaces the built-in list
+    pub hallucination_phrases: Option<Vec<String>>,
+    pub clipboard: Option<ClipboardConfig>,
+    pub tray: Option<TrayConfig>,
+    pub hooks: Option<HooksConfig>,
+    pub retranscribe: Option<RetranscribeConfig>,
+    pub bilingual: Option<BilingualConfig>,
+    pub uncertain: Option<UncertainConfig>,
+    pub line_breaks: Option<LineBreaksConfig>,
+    pub vad: Option<VadConfig>,
+    /// Named sets of overrides selected with --profile
+    pub profiles: Option<BTreeMap<String, Profile>>,
+    /// Note files transcripts are appended to with --output file:<name>
+    pub files: Option<BTreeMap<String, FileSink>>,
+    /// `[instances.<name>]` - settings laid over the rest of this file for `--instance <name>`,
+    /// e.g. the model and language of a second daemon
+    pub instances: Option<BTreeMap<String, toml::Table>>,
+}
+
+/// `[profiles.<name>]` - settings applied by `toggle/stop --profile <name>`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub model: Option<String>,
+    pub language: Option<String>,
+    pub task: Option<String>,
+    pub beam_size: Option<u32>,
+    pub initial_prompt: Option<String>,
+    pub output: Option<String>,
+    pub compute_type: Option<String>,
+    pub batch_size: Option<u32>,
+    pub final_model: Option<String>,
+    pub grammar: Option<String>,
+    /// Treat the dictation as a spelled code (NATO alphabet and digits)
+    pub spell: Option<bool>,
+    /// Regex for the app id or title of the window to wait for before typing
+    pub wait_for_focus: Option<String>,
+    /// Daemon instance to transcribe with, unless --instance or WA_INSTANCE names one
+    pub instance: Option<String>,
+}
+
+/// `[files.<name>]` - a file `--output file:<name>` appends each transcript to, e.g. a note
+/// in an Obsidian vault; the path, header and entry are templates (see `sinks::append_file`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileSink {
+    /// Path of the file, `~/` for the home directory; `{{ date }}` in it starts a new file each day
+    pub path: Option<String>,
+    /// Written before the first entry when the file is created, e.g. YAML frontmatter
+    pub header: Option<String>,
+    /// Appended for each transcript (default "{{ text }}" and a newline)
+    pub template: Option<String>,
+    /// Tags available to the templates as `{{ tags }}`, a YAML list
+    pub tags: Option<Vec<String>>,
+}
+
+/// Look up a file sink by name in the user config file
+pub fn read_file_sink(name: &str) -> Result<FileSink> {
+    read_config()
+        .and_then(|c| c.files)
+        .and_then(|mut files| files.remove(name))
+        .with_context(|| format!("Unknown file output '{}' (define [files.{}] in {})", name, name, config_path().display()))
+}
+
+/// The `notifications` key: the delivery mode alone, or a table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Notifications {
+    Mode(String),
+    Table(NotificationsConfig),
+}
+
+/// A `[model_aliases]` entry: one model for every backend, or one per backend
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ModelAlias {
+    Model(String),
+    PerBackend(BTreeMap<String, String>),
+}
+
+/// `[notifications]` section
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NotificationsConfig {
+    /// "auto", "desktop", "tray" or "none" - how notifications are delivered
+    pub mode: Option<String>,
+    /// Show the start of the transcript in the result notification
+    pub preview: Option<bool>,
+    /// Also speak each notification through the screen reader's speech service
+    pub announce: Option<bool>,
+}
+
+impl Notifications {
+    pub fn mode(self) -> Option<String> {
+        match self {
+            Notifications::Mode(mode) => Some(mode),
+            Notifications::Table(table) => table.mode,
+        }
+    }
+
+    pub fn preview(self) -> Option<bool> {
+        match self {
+            Notifications::Mode(_) => None,
+            Notifications::Table(table) => table.preview,
+        }
+    }
+
+    pub fn announce(self) -> Option<bool> {
+        match self {
+            Notifications::Mode(_) => None,
+            Notifications::Table(table) => table.announce,
+        }
+    }
+}
+
+/// `[clipboard]` section
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClipboardConfig {
+    /// Ask clipboard managers not to keep copied transcripts in their history
+    pub sensitive: Option<bool>,
+    /// Text that toggles recording when copied, for `whisp-away clipboard-trigger`
+    pub trigger: Option<String>,
+}
+
+/// `[retranscribe]` - a second pass over dictations the first pass wasn't sure of
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RetranscribeConfig {
+    /// Confidence (0.0-1.0) below which the audio is transcribed again (default 0.6)
+    pub threshold: Option<f64>,
+    /// Model for the second pass, e.g. a larger one kept resident by the daemon
+    pub model: Option<String>,
+    /// Beam search width for the second pass
+    pub beam_size: Option<u32>,
+    /// Seconds from the start of the first pass after which its text is used as is (default 10)
+    pub budget_secs: Option<f64>,
+}
+
+/// `[bilingual]` - the English translation of each dictation next to its transcript
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BilingualConfig {
+    pub enabled: Option<bool>,
+    /// What is output, with {original}, {translation} and {language} filled in (default "{original}")
+    pub output: Option<String>,
+    /// What is copied to the clipboard afterwards, "" for nothing (default "{translation}")
+    pub copy: Option<String>,
+}
+
+/// `[uncertain]` - marks around the words the model wasn't sure of, for proofreading
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UncertainConfig {
+    pub enabled: Option<bool>,
+    /// Word probability (0.0-1.0) below which a word is marked (default 0.5)
+    pub threshold: Option<f64>,
+    /// How a marked word is written, with {word} filled in (default "⟦{word}⟧")
+    pub marker: Option<String>,
+}
+
+/// `[line_breaks]` - new lines at pauses and speaker changes, for interviews and dialog
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LineBreaksConfig {
+    /// Seconds of silence between segments that start a new line
+    pub pause_secs: Option<f64>,
+    /// Start a new line where the daemon reports another speaker (default false)
+    pub speaker_change: Option<bool>,
+}
+
+/// `[vad]` - voice activity detection before faster-whisper transcribes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VadConfig {
+    /// "silero" (default), "webrtc", "energy" or "off"
+    pub engine: Option<String>,
+    pub silero: Option<SileroVadConfig>,
+    pub webrtc: Option<WebrtcVadConfig>,
+    pub energy: Option<EnergyVadConfig>,
+}
+
+/// `[vad.silero]`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SileroVadConfig {
+    /// Speech probability (0.0-1.0) above which a frame counts as speech (default 0.3)
+    pub threshold: Option<f64>,
+    /// Silence that ends a speech region (default 300)
+    pub min_silence_ms: Option<u32>,
+    /// Audio kept either side of each speech region (default 200)
+    pub speech_pad_ms: Option<u32>,
+}
+
+/// `[vad.webrtc]`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WebrtcVadConfig {
+    /// 0 (keeps the most audio) to 3 (drops the most non-speech) (default 2)
+    pub aggressiveness: Option<u8>,
+    /// Frame length: 10, 20 or 30 (default 30)
+    pub frame_ms: Option<u32>,
+    pub min_silence_ms: Option<u32>,
+    pub speech_pad_ms: Option<u32>,
+}
+
+/// `[vad.energy]`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnergyVadConfig {
+    /// Loudness in dBFS above which a frame counts as speech (default -45.0)
+    pub threshold_db: Option<f64>,
+    pub min_silence_ms: Option<u32>,
+    pub speech_pad_ms: Option<u32>,
+}
+
+/// `[hooks]` - shell commands run at points of the dictation lifecycle
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HooksConfig {
+    pub on_record_start: Option<String>,
+    pub on_record_stop: Option<String>,
+    /// Run with the text in WHISP_AWAY_TEXT once a dictation is transcribed
+    pub on_transcript: Option<String>,
+    /// Run with the message in WHISP_AWAY_ERROR when recording or transcribing fails
+    pub on_error: Option<String>,
+    /// Seconds a hook may run before it is killed (default 10)
+    pub timeout_secs: Option<u64>,
+}
+
+/// `[tray]` section
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrayConfig {
+    pub icons: Option<TrayIcons>,
+    /// Keys the tray binds to toggling dictation through the GlobalShortcuts portal, e.g.
+    /// "CTRL+ALT+D" (default none)
+    pub shortcut: Option<String>,
+}
+
+/// `[tray.icons]` - per-state icon theme names or paths to image files
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrayIcons {
+    pub idle: Option<String>,
+    pub recording: Option<String>,
+    pub transcribing: Option<String>,
+    pub error: Option<String>,
+    pub disabled: Option<String>,
+    pub no_daemon: Option<String>,
+    /// The daemon's models are unloaded (`whisp-away daemon unload`)
+    pub unloaded: Option<String>,
+    /// The daemon is loading a model
+    pub loading: Option<String>,
+}
+
+/// Get the path to the user config file
+/// WA_CONFIG overrides the default of $XDG_CONFIG_HOME/whisp-away/config.toml
+pub fn config_path() -> PathBuf {
+    if let Ok(path) = std::env::var("WA_CONFIG") {
+        return PathBuf::from(path);
+    }
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from(".config"))
+        .join("whisp-away")
+        .join("config.toml")
+}
+
+/// A setting the config file gets wrong, e.g. "failover[1]: unknown failover step 'demon'"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// Dotted path of the setting
+    pub key: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+/// Set once problems with the config file have been logged; settings read it many times
+/// per command. The config watcher clears it for each new version of the file, so a
+/// long-running daemon or tray reports problems in later edits too
+static CONFIG_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Read the user config file (called by resolution helpers)
+/// The selected instance's `[instances.<name>]` table and then settings given to
+/// `whisp-away with` are laid over it. Values that don't fit are left out and the rest
+/// of the file still applies
+pub fn read_config() -> Option<Config> {
+    let overlay = read_overlay_table();
+    let path = config_path();
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) => {
+            trace!("No config file found at: {}", path.display());
+            return overlay.and_then(|overlay| toml::Value::Table(overlay).try_into().ok());
+        }
+    };
+    
+    let mut table = match content.parse::<toml::Table>() {
+        Ok(table) => table,
+        Err(e) => {
+            if !CONFIG_WARNED.swap(true, Ordering::Relaxed) {
+                warn!("Failed to parse config file {}, using defaults (see `whisp-away config validate`): {}", path.display(), e);
+            }
+            return None;
+        }
+    };
+    if let Some(instance) = instance_table(&table) {
+        merge(&mut table, instance);
+    }
+    if let Some(overlay) = overlay {
+        merge(&mut table, overlay);
+    }
+    let (config, dropped) = from_table(table.clone());
+    if !CONFIG_WARNED.load(Ordering::Relaxed) {
+        let problems = check(&table, &config, dropped, "");
+        if !problems.is_empty() {
+            CONFIG_WARNED.store(true, Ordering::Relaxed);
+            warn!("Problems in config file {} (see `whisp-away config validate`):", path.display());
+            for problem in &problems {
+                warn!("  {}", problem);
+            }
+        }
+    }
+    trace!("Read config from: {}", path.display());
+    Some(config)
+}
+
+/// Every problem with a config file's contents: values of the wrong type, unknown keys and
+/// values settings don't accept, including those of `[instances.<name>]` tables
+/// Err when the file isn't valid TOML at all
+pub fn problems(content: &str) -> Result<Vec<Problem>> {
+    let table: toml::Table = content.parse()?;
+    let (config, dropped) = from_table(table.clone());
+    let mut problems = check(&table, &config, dropped, "");
+    for (name, instance) in config.instances.iter().flatten() {
+        let (config, dropped) = from_table(instance.clone());
+        problems.extend(check(instance, &config, dropped, &format!("instances.{}.", name)));
+    }
+    Ok(problems)
+}
+
+/// The values `from_table` dropped, keys nothing reads and values settings don't accept;
+/// `prefix` goes before the keys
+fn check(table: &toml::Table, config: &Config, dropped: Vec<Problem>, prefix: &str) -> Vec<Problem> {
+    let known = toml::Table::try_from(config).unwrap_or_default();
+    // A dropped value is missing from `config` too, but was already reported
+    let was_dropped = |key: &str| dropped.iter().any(|problem| {
+        problem.key == key || problem.key.strip_prefix(key).is_some_and(|rest| rest.starts_with('.'))
+    });
+    let unknown: Vec<Problem> = unknown_keys(table, &known).into_iter()
+        .filter(|key| !was_dropped(key))
+        .map(|key| Problem { key, message: "unknown setting".to_string() })
+        .collect();
+    dropped.into_iter()
+        .chain(unknown)
+        .chain(crate::validate::check(config))
+        .map(|problem| Problem { key: format!("{}{}", prefix, problem.key), ..problem })
+        .collect()
+}
+
+/// Deserialize a config table, leaving out the values that don't, with why
+pub fn from_table(table: toml::Table) -> (Config, Vec<Problem>) {
+    if let Ok(config) = toml::Value::Table(table.clone()).try_into() {
+        return (config, Vec::new());
+    }
+    let mut kept = toml::Table::new();
+    let mut problems = Vec::new();
+    salvage(&mut Vec::new(), table, &mut kept, &mut problems);
+    (toml::Value::Table(kept).try_into().unwrap_or_default(), problems)
+}
+
+/// Keep each value of `table` (found at `path`) that deserializes on its own, going into
+/// the tables that don't so one bad option doesn't take its whole section with it
+fn salvage(path: &mut Vec<String>, table: toml::Table, kept: &mut toml::Table, problems: &mut Vec<Problem>) {
+    for (key, value) in table {
+        path.push(key);
+        let alone = nest(path, value.clone());
+        match toml::Value::Table(alone.clone()).try_into::<Config>() {
+            Ok(_) => merge(kept, alone),
+            Err(e) => match value {
+                toml::Value::Table(table) if !table.is_empty() => salvage(path, table, kept, problems),
+                // The error ends with where it happened, which the key already says
+                _ => problems.push(Problem {
+                    key: path.join("."),
+                    message: e.to_string().split("\nin `").next().unwrap_or_default().trim().to_string(),
+                }),
+            },
+        }
+        path.pop();
+    }
+}
+
+/// `value` at `path` in an otherwise empty table
+fn nest(path: &[String], value: toml::Value) -> toml::Table {
+    let (first, rest) = path.split_first().expect("a value has a key");
+    let value = rest.iter().rev().fold(value, |value, key| toml::Value::Table(toml::Table::from_iter([(key.clone(), value)])));
+    toml::Table::from_iter([(first.clone(), value)])
+}
+
+/// The `[instances.<name>]` table of the selected instance
+fn instance_table(table: &toml::Table) -> Option<toml::Table> {
+    let name = crate::instance::name()?;
+    let instance = table.get("instances")?.get(&name)?.as_table()?.clone();
+    trace!("Laying [instances.{}] over the config file", name);
+    Some(instance)
+}
+
+/// Internal: the settings of `whisp-away with`, as TOML
+/// Kept in the environment so the workers and helpers the command starts see them too
+const OVERLAY_VAR: &str = "WA_WITH_CONFIG";
+
+fn read_overlay_table() -> Option<toml::Table> {
+    std::env::var(OVERLAY_VAR).ok()?.parse().ok()
+}
+
+/// Settings given to `whisp-away with` for this invocation, if any
+pub fn read_overlay() -> Option<Config> {
+    toml::Value::Table(read_overlay_table()?).try_into().ok()
+}
+
+/// Lay one-off settings (`whisp-away with -p '<toml>'`) over the config file and environment
+/// for the rest of this invocation; later patches win over earlier ones and an enclosing `with`
+pub fn set_overlay(patches: &[String]) -> Result<()> {
+    let mut overlay = read_overlay_table().unwrap_or_default();
+    for patch in patches {
+        let table: toml::Table = patch.parse()
+            .map_err(|e| anyhow::anyhow!("Invalid TOML in -p '{}': {}", patch, e))?;
+        let config: Config = toml::Value::Table(table.clone()).try_into()
+            .map_err(|e| anyhow::anyhow!("Invalid settings in -p '{}': {}", patch, e))?;
+        // Unknown keys would otherwise be dropped without a word
+        let known = toml::Table::try_from(&config).context("Failed to check settings")?;
+        if let Some(key) = unknown_keys(&table, &known).first() {
+            return Err(anyhow::anyhow!("Unknown setting '{}' in -p '{}'", key, patch));
+        }
+        if let Some(problem) = crate::validate::check(&config).first() {
+            return Err(anyhow::anyhow!("Invalid setting in -p '{}': {}", patch, problem));
+        }
+        merge(&mut overlay, table);
+    }
+    debug!("Settings for this invocation: {}", overlay);
+    std::env::set_var(OVERLAY_VAR, overlay.to_string());
+    Ok(())
+}
+
+/// Merge `overlay` into `base`, replacing values but merging tables key by key
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// The keys of `table` that didn't survive deserializing, dotted for nested tables
+fn unknown_keys(table: &toml::Table, known: &toml::Table) -> Vec<String> {
+    table.iter().flat_map(|(key, value)| match (value, known.get(key)) {
+        (_, None) => vec![key.clone()],
+        (toml::Value::Table(table), Some(toml::Value::Table(known))) => {
+            unknown_keys(table, known).into_iter().map(|nested| format!("{}.{}", key, nested)).collect()
+        }
+        _ => Vec::new(),
+    }).collect()
+}
+
+/// Look up a profile by name in the user config file
+pub fn read_profile(name: &str) -> Result<Profile> {
+    read_config()
+        .and_then(|c| c.profiles)
+        .and_then(|mut profiles| profiles.remove(name))
+        .with_context(|| format!("Unknown profile '{}' (define [profiles.{}] in {})", name, name, config_path().display()))
+}
+
+/// Top-level keys whose values differ between two configs
+#[cfg(any(feature = "tray", feature = "whisper-cpp", feature = "faster-whisper", test))]
+fn changed_keys(old: &Config, new: &Config) -> Vec<String> {
+    let table = |config: &Config| toml::Table::try_from(config).unwrap_or_default();
+    let (old, new) = (table(old), table(new));
+    let mut keys: Vec<String> = old.keys().chain(new.keys())
+        .filter(|key| old.get(*key) != new.get(*key))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Watch the config file and call `on_change` with the new contents whenever it changes
+/// The parent directory is watched so editors that replace the file on save are handled.
+/// The returned watcher must be kept alive for as long as changes should be delivered.
+#[cfg(any(feature = "tray", feature = "whisper-cpp", feature = "faster-whisper"))]
+pub fn watch_config<F>(mut on_change: F) -> Result<RecommendedWatcher>
+where
+    F: FnMut(Config) + Send + 'static,
+{
+    let path = config_path();
+    let dir = path.parent()
+        .map(Path::to_path_buf)
+        .context("Config path has no parent directory")?;
+    std::fs::create_dir_all(&dir).ok();
+    
+    let file_name = path.file_name().map(|n| n.to_os_string());
+    let mut last = read_config().unwrap_or_default();
+    let watched = path.clone();
+    let mut last_content = std::fs::read_to_string(&path).ok();
+    
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        let event = match res {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Config watch error: {}", e);
+                return;
+            }
+        };
+        
+        let relevant = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_))
+            && event.paths.iter().any(|p| p.file_name().map(|n| n.to_os_string()) == file_name);
+        if !relevant {
+            return;
+        }
+        
+        // Editors emit several events per save; only look at actual content changes
+        let content = std::fs::read_to_string(&watched).ok();
+        if content == last_content {
+            return;
+        }
+        last_content = content.clone();
+        // A half-saved or broken file keeps the settings in effect; only a deleted one
+        // brings back the defaults
+        if let Some(Err(e)) = content.map(|content| content.parse::<toml::Table>()) {
+            warn!("Config file {} is invalid, keeping the previous settings: {}", watched.display(), e);
+            return;
+        }
+        CONFIG_WARNED.store(false, Ordering::Relaxed);
+        let config = read_config().unwrap_or_default();
+        if config != last {
+            // Only the keys: values include the daemon token
+            debug!("Config file changed: {}", changed_keys(&last, &config).join(", "));
+            last = config.clone();
+            on_change(config);
+        }
+    })
+    .context("Failed to create config watcher")?;
+    
+    watcher.watch(&dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", dir.display()))?;
+    debug!("Watching config file: {}", path.display());
+    
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_changed_keys_without_values() {
+        let old = Config { daemon_token: Some("old secret".to_string()), history: Some(true), ..Default::default() };
+        let new = Config { daemon_token: Some("new secret".to_string()), history_window: Some(true), ..old.clone() };
+        assert_eq!(changed_keys(&old, &new), ["daemon_token", "history_window"]);
+        assert!(changed_keys(&old, &old).is_empty());
+        assert_eq!(changed_keys(&old, &Config::default()), ["daemon_token", "history"]);
+    }
+}
@@ -0,0 +1,163 @@
+/// What to do at the cursor: send `erase` backspaces, then type `insert`
+/// Backspaces are counted in characters (code points), the way `typing::erase_typed` counts them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub erase: usize,
+    pub insert: String,
+}
+
+impl Edit {
+    /// Whether the typed text is already the target
+    pub fn is_empty(&self) -> bool {
+        self.erase == 0 && self.insert.is_empty()
+    }
+}
+
+/// The edit that turns `typed` into `target`: only what comes after the part both start
+/// with is erased and typed again
+/// The shared prefix never ends inside a character built from several code points (an
+/// accented letter with a combining mark, an emoji sequence, a flag), since typing half of
+/// one after the other half doesn't reliably combine into it
+pub fn diff(typed: &str, target: &str) -> Edit {
+    let typed: Vec<char> = typed.chars().collect();
+    let target: Vec<char> = target.chars().collect();
+    let mut shared = typed.iter().zip(&target).take_while(|(a, b)| a == b).count();
+    while shared > 0 && !(is_boundary(&typed, shared) && is_boundary(&target, shared)) {
+        shared -= 1;
+    }
+    Edit {
+        erase: typed.len() - shared,
+        insert: target[shared..].iter().collect(),
+    }
+}
+
+/// Whether the characters before `at` end a whole character, so text can be cut there
+fn is_boundary(chars: &[char], at: usize) -> bool {
+    let (Some(&before), Some(&after)) = (at.checked_sub(1).and_then(|i| chars.get(i)), chars.get(at)) else {
+        return true;
+    };
+    if extends(after) || before == ZWJ {
+        return false;
+    }
+    // Flags are pairs of regional indicators, counted from the start of the run
+    if is_regional_indicator(before) && is_regional_indicator(after) {
+        let run = chars[..at].iter().rev().take_while(|&&c| is_regional_indicator(c)).count();
+        return run % 2 == 0;
+    }
+    true
+}
+
+/// Zero width joiner, which glues emoji into one (👩‍💻)
+const ZWJ: char = '\u{200D}';
+
+/// Code points that attach to the one before them
+fn extends(c: char) -> bool {
+    matches!(c,
+        // Combining diacritical marks (and their extensions and supplement)
+        '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE20}'..='\u{FE2F}'
+        // Marks of Hebrew, Arabic and Indic scripts
+        | '\u{0591}'..='\u{05BD}'
+        | '\u{0610}'..='\u{061A}'
+        | '\u{064B}'..='\u{065F}'
+        | '\u{0900}'..='\u{0903}'
+        | '\u{093A}'..='\u{094F}'
+        // Hangul vowel and final jamo
+        | '\u{1160}'..='\u{11FF}'
+        // Japanese voiced sound marks
+        | '\u{3099}'..='\u{309A}'
+        // Joiner, variation selectors, emoji skin tones and tags
+        | ZWJ
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{1F3FB}'..='\u{1F3FF}'
+        | '\u{E0020}'..='\u{E007F}'
+        | '\u{E0100}'..='\u{E01EF}'
+    )
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c, '\u{1F1E6}'..='\u{1F1FF}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(erase: usize, insert: &str) -> Edit {
+        Edit { erase, insert: insert.to_string() }
+    }
+
+    #[test]
+    fn identical_text_needs_nothing() {
+        assert!(diff("hello world", "hello world").is_empty());
+        assert!(diff("", "").is_empty());
+    }
+
+    #[test]
+    fn retypes_only_the_changed_tail() {
+        assert_eq!(diff("I red the book", "I read the book"), edit(10, "ad the book"));
+        assert_eq!(diff("hello wold", "hello world."), edit(2, "rld."));
+    }
+
+    #[test]
+    fn appending_and_shortening() {
+        assert_eq!(diff("hello", "hello world"), edit(0, " world"));
+        assert_eq!(diff("hello world", "hello"), edit(6, ""));
+        assert_eq!(diff("", "hello"), edit(0, "hello"));
+        assert_eq!(diff("hello", ""), edit(5, ""));
+    }
+
+    #[test]
+    fn nothing_in_common() {
+        assert_eq!(diff("cat", "dog"), edit(3, "dog"));
+    }
+
+    #[test]
+    fn wide_characters_take_one_backspace_each() {
+        assert_eq!(diff("你好世界", "你好朋友"), edit(2, "朋友"));
+        assert_eq!(diff("ok 👍", "ok 👍👍"), edit(0, "👍"));
+        assert_eq!(diff("über straße", "über strasse"), edit(2, "sse"));
+    }
+
+    #[test]
+    fn keeps_combining_marks_with_their_letter() {
+        // e + combining acute becoming e + combining grave: the e is typed again too
+        assert_eq!(diff("cafe\u{301}", "cafe\u{300}"), edit(2, "e\u{300}"));
+        // A mark added to the last letter typed
+        assert_eq!(diff("cafe", "cafe\u{301}"), edit(1, "e\u{301}"));
+        // A mark removed
+        assert_eq!(diff("cafe\u{301}", "cafe"), edit(2, "e"));
+        // Precomposed and decomposed forms are different text
+        assert_eq!(diff("caf\u{e9}", "cafe\u{301}"), edit(1, "e\u{301}"));
+    }
+
+    #[test]
+    fn keeps_emoji_sequences_whole() {
+        // 👩‍💻 (woman, joiner, laptop) becoming 👩‍🔬 (woman, joiner, microscope)
+        assert_eq!(diff("hi 👩\u{200D}💻", "hi 👩\u{200D}🔬"), edit(3, "👩\u{200D}🔬"));
+        // Skin tone added
+        assert_eq!(diff("👍", "👍\u{1F3FD}"), edit(1, "👍\u{1F3FD}"));
+        // Variation selector
+        assert_eq!(diff("\u{2764}", "\u{2764}\u{FE0F}"), edit(1, "\u{2764}\u{FE0F}"));
+    }
+
+    #[test]
+    fn keeps_flags_whole() {
+        // 🇩🇪 and 🇩🇰 share their first regional indicator
+        assert_eq!(diff("\u{1F1E9}\u{1F1EA}", "\u{1F1E9}\u{1F1F0}"), edit(2, "\u{1F1E9}\u{1F1F0}"));
+        // After a whole flag, the next one starts a new pair
+        assert_eq!(
+            diff("\u{1F1E9}\u{1F1EA}\u{1F1E9}\u{1F1EA}", "\u{1F1E9}\u{1F1EA}\u{1F1E9}\u{1F1F0}"),
+            edit(2, "\u{1F1E9}\u{1F1F0}")
+        );
+    }
+
+    #[test]
+    fn hangul_jamo_stay_together() {
+        // ᄒ + ᅡ + ᆫ (한) becoming ᄒ + ᅡ (하)
+        assert_eq!(diff("\u{1112}\u{1161}\u{11AB}", "\u{1112}\u{1161}"), edit(3, "\u{1112}\u{1161}"));
+    }
+}
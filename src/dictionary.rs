@@ -0,0 +1,153 @@
+use anyhow::{anyhow, Context, Result};
+use regex::{Regex, RegexBuilder};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use tracing::{debug, warn};
+use crate::config;
+use crate::privacy;
+
+/// The user dictionary: dictionary.toml next to the config file, mapping what whisper
+/// writes to what was meant, e.g. `"kuber netties" = "Kubernetes"`
+pub fn path() -> PathBuf {
+    config::config_path().with_file_name("dictionary.toml")
+}
+
+/// Entries keyed by their normalized spelling
+fn load() -> BTreeMap<String, String> {
+    let path = path();
+    let Ok(content) = fs::read_to_string(&path) else {
+        return BTreeMap::new();
+    };
+    match toml::from_str::<BTreeMap<String, String>>(&content) {
+        Ok(entries) => entries.into_iter().map(|(from, to)| (normalize(&from), to)).collect(),
+        Err(e) => {
+            warn!("Failed to parse dictionary {}: {}", path.display(), e);
+            BTreeMap::new()
+        }
+    }
+}
+
+fn save(entries: &BTreeMap<String, String>) -> Result<()> {
+    let path = path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let content = toml::to_string(entries).context("Failed to serialize dictionary")?;
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Lower-case with single spaces, so "Kuber  Netties" and "kuber netties" are one entry
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// One pattern for every entry, longest first so "nix o s" wins over "nix"
+/// Entries match whole words, case-insensitively, with any whitespace between their words
+fn pattern(entries: &BTreeMap<String, String>) -> Option<Regex> {
+    let mut spellings: Vec<&String> = entries.keys().filter(|from| !from.is_empty()).collect();
+    if spellings.is_empty() {
+        return None;
+    }
+    spellings.sort_by_key(|from| std::cmp::Reverse(from.len()));
+    let alternatives = spellings.iter().map(|from| {
+        let words = from.split(' ').map(regex::escape).collect::<Vec<_>>().join(r"\s+");
+        let starts_word = from.starts_with(|c: char| c.is_alphanumeric() || c == '_');
+        let ends_word = from.ends_with(|c: char| c.is_alphanumeric() || c == '_');
+        format!("{}{}{}", if starts_word { r"\b" } else { "" }, words, if ends_word { r"\b" } else { "" })
+    });
+    let pattern = alternatives.collect::<Vec<_>>().join("|");
+    match RegexBuilder::new(&pattern).case_insensitive(true).build() {
+        Ok(regex) => Some(regex),
+        Err(e) => {
+            warn!("Dictionary is unusable: {}", e);
+            None
+        }
+    }
+}
+
+/// Replace misrecognized spellings with the preferred ones
+pub fn apply(text: &str) -> String {
+    let replaced = replace(text, &load());
+    if replaced != text {
+        debug!("Dictionary corrected: '{}'", privacy::redact(&replaced));
+    }
+    replaced
+}
+
+fn replace(text: &str, entries: &BTreeMap<String, String>) -> String {
+    let Some(pattern) = pattern(entries) else {
+        return text.to_string();
+    };
+    pattern.replace_all(text, |captures: &regex::Captures| {
+        let matched = &captures[0];
+        entries.get(&normalize(matched)).cloned().unwrap_or_else(|| matched.to_string())
+    }).into_owned()
+}
+
+/// `whisp-away dict add`: add or replace an entry
+pub fn add(from: &str, to: &str) -> Result<()> {
+    let from = normalize(from);
+    if from.is_empty() {
+        return Err(anyhow!("Give the spelling to replace"));
+    }
+    let mut entries = load();
+    match entries.insert(from.clone(), to.to_string()) {
+        Some(previous) => println!("\"{}\" → \"{}\" (was \"{}\")", from, to, previous),
+        None => println!("\"{}\" → \"{}\"", from, to),
+    }
+    save(&entries)
+}
+
+/// `whisp-away dict remove`: delete an entry
+pub fn remove(from: &str) -> Result<()> {
+    let mut entries = load();
+    if entries.remove(&normalize(from)).is_none() {
+        return Err(anyhow!("\"{}\" is not in the dictionary ({})", from, path().display()));
+    }
+    save(&entries)
+}
+
+/// `whisp-away dict list`: print every entry
+pub fn list() -> Result<()> {
+    let entries = load();
+    if entries.is_empty() {
+        println!("The dictionary is empty; add entries with `whisp-away dict add <heard> <meant>`");
+        return Ok(());
+    }
+    let width = entries.keys().map(|from| from.chars().count()).max().unwrap_or(0) + 2;
+    for (from, to) in &entries {
+        println!("{:<width$} → {}", format!("\"{}\"", from), to);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(from, to)| (normalize(from), to.to_string())).collect()
+    }
+
+    #[test]
+    fn replaces_whatever_the_case() {
+        let entries = entries(&[("Kuber  Netties", "Kubernetes"), ("postgres", "PostgreSQL")]);
+        assert_eq!(replace("Deploy it to kuber netties.", &entries), "Deploy it to Kubernetes.");
+        assert_eq!(replace("KUBER NETTIES and Postgres", &entries), "Kubernetes and PostgreSQL");
+        // Whisper's line breaks and double spaces between the words still match
+        assert_eq!(replace("kuber\nnetties", &entries), "Kubernetes");
+        assert_eq!(replace("nothing to fix", &entries), "nothing to fix");
+        assert_eq!(replace("kuber netties", &BTreeMap::new()), "kuber netties");
+    }
+
+    #[test]
+    fn replaces_whole_words_only() {
+        let entries = entries(&[("nix", "Nix"), ("nix o s", "NixOS"), ("c++", "C++")]);
+        assert_eq!(replace("nix o s runs nix", &entries), "NixOS runs Nix");
+        assert_eq!(replace("unix and nixpkgs", &entries), "unix and nixpkgs");
+        assert_eq!(replace("nix-shell", &entries), "Nix-shell");
+        // Spellings ending in punctuation match without a word boundary after them
+        assert_eq!(replace("I write c++, mostly", &entries), "I write C++, mostly");
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+use tracing::{debug, info};
+use crate::activity;
+use crate::hooks;
+use crate::pause;
+use crate::recording;
+use crate::socket::{self, DaemonState, Job, JobState};
+use crate::{activation, helpers};
+use crate::helpers::now_secs;
+
+/// How long to wait before watching the daemon again after it went away
+const WATCH_RETRY: Duration = Duration::from_secs(2);
+
+/// The session bus signal every whisp-away process broadcasts its lifecycle events on,
+/// carrying the event's JSON line
+#[cfg(all(target_os = "linux", feature = "tray"))]
+const BUS_PATH: &str = "/io/github/whisp_away";
+#[cfg(all(target_os = "linux", feature = "tray"))]
+const BUS_INTERFACE: &str = "io.github.whisp_away.Events";
+#[cfg(all(target_os = "linux", feature = "tray"))]
+const BUS_SIGNAL: &str = "Event";
+
+/// What an `events` line reports, its `event` field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Kind {
+    /// The first line: whether a recording is running, the pause and the daemon's activity
+    State,
+    RecordingStarted,
+    RecordingStopped,
+    TranscriptionStarted,
+    TranscriptionFinished,
+    TranscriptionCancelled,
+    Error,
+}
+
+/// An event as a JSON line: `event` and `time` (Unix seconds), then its own fields
+fn line(kind: Kind, fields: Map<String, Value>) -> String {
+    let mut event = Map::new();
+    event.insert("event".to_string(), json!(kind));
+    event.insert("time".to_string(), json!(now_secs()));
+    event.extend(fields);
+    Value::Object(event).to_string()
+}
+
+/// Broadcast a hook event of this process to `events --follow` subscribers, with its
+/// metadata (`vars`) as lowercase fields, e.g. "audio_file"
+/// Transcripts aren't broadcast: the daemon reports finished transcriptions without their
+/// text, which stays in history (and out of it in privacy mode)
+pub fn broadcast(event: hooks::Event, vars: &[(&str, &str)]) {
+    let kind = match event {
+        hooks::Event::RecordStart => Kind::RecordingStarted,
+        hooks::Event::RecordStop => Kind::RecordingStopped,
+        hooks::Event::Error => Kind::Error,
+        hooks::Event::Transcript => return,
+    };
+    let fields = vars.iter()
+        .map(|(name, value)| (name.to_lowercase(), json!(value)))
+        .collect();
+    send(&line(kind, fields));
+}
+
+#[cfg(all(target_os = "linux", feature = "tray"))]
+fn send(line: &str) {
+    use dbus::blocking::Connection;
+    use dbus::channel::Sender as _;
+    use dbus::Message;
+
+    let result = Connection::new_session().and_then(|conn| {
+        let signal = Message::new_signal(BUS_PATH, BUS_INTERFACE, BUS_SIGNAL)
+            .map_err(|e| dbus::Error::new_failed(&e))?
+            .append1(line);
+        conn.send(signal).map_err(|()| dbus::Error::new_failed("Failed to send the signal"))?;
+        conn.channel().flush();
+        Ok(())
+    });
+    match result {
+        Ok(()) => debug!("Broadcast event: {}", line),
+        Err(e) => debug!("Failed to broadcast event: {}", e),
+    }
+}
+
+/// Without a session bus (or a build without D-Bus) only the daemon's events reach subscribers
+#[cfg(not(all(target_os = "linux", feature = "tray")))]
+fn send(_line: &str) {}
+
+/// `whisp-away events`: print the current state as a JSON line and, with `follow`, an event
+/// line for every change until stdout is closed
+/// Recordings and errors come from the session bus, transcriptions from watching the daemon
+pub fn run(follow: bool) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    let state = line(Kind::State, current_state());
+    if !print(&mut stdout, &state)? || !follow {
+        return Ok(());
+    }
+
+    let (sender, events) = mpsc::channel();
+    #[cfg(all(target_os = "linux", feature = "tray"))]
+    spawn_bus_listener(sender.clone());
+    spawn_daemon_watcher(sender);
+    for event in events {
+        if !print(&mut stdout, &event)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Print a line right away, for a script reading it as it comes; false once the reader is gone
+fn print(stdout: &mut impl Write, line: &str) -> Result<bool> {
+    match writeln!(stdout, "{}", line).and_then(|()| stdout.flush()) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn current_state() -> Map<String, Value> {
+    let mut state = Map::new();
+    state.insert("recording".to_string(), json!(recording::is_recording()));
+    state.insert("dictation".to_string(), json!(if pause::active().is_some() { "disabled" } else { "enabled" }));
+    state.insert("activity".to_string(), json!(activity::current()));
+    state
+}
+
+/// Spawns a thread passing on the events other whisp-away processes broadcast
+#[cfg(all(target_os = "linux", feature = "tray"))]
+fn spawn_bus_listener(sender: Sender<String>) {
+    use anyhow::Context;
+    use dbus::blocking::Connection;
+    use dbus::message::MatchRule;
+
+    std::thread::spawn(move || {
+        let result = (|| -> Result<()> {
+            let conn = Connection::new_session().context("Failed to connect to the session bus")?;
+            conn.add_match(
+                MatchRule::new_signal(BUS_INTERFACE, BUS_SIGNAL),
+                move |(line,): (String,), _, _| sender.send(line).is_ok(),
+            ).context("Failed to listen for events")?;
+            loop {
+                conn.process(Duration::from_secs(60)).context("Lost the session bus")?;
+            }
+        })();
+        if let Err(e) = result {
+            tracing::warn!("No recording events: {:#}", e);
+        }
+    });
+}
+
+/// Spawns a thread turning the daemon's job changes into transcription events, watching it
+/// again whenever it comes back
+fn spawn_daemon_watcher(sender: Sender<String>) {
+    std::thread::spawn(move || loop {
+        // A daemon systemd starts on demand isn't woken up just to be watched
+        if helpers::resolve_backend() != "openai" && !activation::asleep() {
+            let mut known: Option<HashMap<u64, JobState>> = None;
+            let result = socket::watch_daemon(&helpers::resolve_socket_path(), |state| {
+                for event in job_events(&mut known, &state) {
+                    let _ = sender.send(event);
+                }
+            });
+            match result {
+                Err(e) if e.is::<socket::WatchUnsupported>() => {
+                    info!("{}, no transcription events", e);
+                    return;
+                }
+                Err(e) => debug!("Not watching the daemon: {:#}", e),
+                Ok(()) => {}
+            }
+        }
+        std::thread::sleep(WATCH_RETRY);
+    });
+}
+
+/// Events for the jobs whose state changed since the daemon's last state; `known` is None
+/// for the first state of a connection, whose finished jobs are from before it
+fn job_events(known: &mut Option<HashMap<u64, JobState>>, state: &DaemonState) -> Vec<String> {
+    let first = known.is_none();
+    let known = known.get_or_insert_with(HashMap::new);
+    let mut events = Vec::new();
+    for job in &state.jobs {
+        if known.get(&job.id) == Some(&job.state) || (first && !job.state.is_active()) {
+            continue;
+        }
+        // A short job can run between two states, which still start it before it ends
+        let ran = matches!(job.state, JobState::Running | JobState::Done | JobState::Failed);
+        if ran && known.get(&job.id) != Some(&JobState::Running) {
+            events.push(line(Kind::TranscriptionStarted, job_fields(job)));
+        }
+        let kind = match job.state {
+            JobState::Pending | JobState::Running => continue,
+            JobState::Done => Kind::TranscriptionFinished,
+            JobState::Cancelled => Kind::TranscriptionCancelled,
+            JobState::Failed => Kind::Error,
+        };
+        events.push(line(kind, job_fields(job)));
+    }
+    *known = state.jobs.iter().map(|job| (job.id, job.state)).collect();
+    events
+}
+
+fn job_fields(job: &Job) -> Map<String, Value> {
+    let mut fields = Map::new();
+    fields.insert("job".to_string(), json!(job.id));
+    fields.insert("label".to_string(), json!(job.label));
+    if let Some(model) = &job.model {
+        fields.insert("model".to_string(), json!(model));
+    }
+    if let Some(request_id) = &job.request_id {
+        fields.insert("request_id".to_string(), json!(request_id));
+    }
+    if let Some(finished) = job.finished {
+        fields.insert("duration_secs".to_string(), json!(finished.saturating_sub(job.submitted)));
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity::Activity;
+
+    fn job(id: u64, state: JobState) -> Job {
+        Job {
+            id,
+            label: "dictation".to_string(),
+            model: None,
+            state,
+            submitted: 100,
+            finished: (!state.is_active()).then_some(103),
+            progress: None,
+            request_id: None,
+        }
+    }
+
+    fn daemon_state(jobs: Vec<Job>) -> DaemonState {
+        DaemonState {
+            state: Activity::Idle,
+            backend: "whisper-cpp".to_string(),
+            default_model: "base.en".to_string(),
+            recording: false,
+            jobs,
+        }
+    }
+
+    fn kinds(known: &mut Option<HashMap<u64, JobState>>, jobs: Vec<Job>) -> Vec<String> {
+        job_events(known, &daemon_state(jobs)).iter()
+            .map(|event| serde_json::from_str::<Value>(event).unwrap()["event"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn reports_job_changes() {
+        let mut known = None;
+        assert!(kinds(&mut known, vec![job(1, JobState::Pending)]).is_empty());
+        assert_eq!(kinds(&mut known, vec![job(1, JobState::Running), job(2, JobState::Pending)]), ["transcription_started"]);
+        assert_eq!(kinds(&mut known, vec![job(1, JobState::Done), job(2, JobState::Running)]), ["transcription_finished", "transcription_started"]);
+        assert_eq!(kinds(&mut known, vec![job(1, JobState::Done), job(2, JobState::Failed)]), ["error"]);
+        assert!(kinds(&mut known, vec![job(1, JobState::Done), job(2, JobState::Failed)]).is_empty());
+    }
+
+    #[test]
+    fn starts_jobs_that_ran_between_states() {
+        let mut known = Some(HashMap::from([(1, JobState::Pending)]));
+        assert_eq!(kinds(&mut known, vec![job(1, JobState::Done), job(2, JobState::Cancelled)]), ["transcription_started", "transcription_finished", "transcription_cancelled"]);
+    }
+
+    #[test]
+    fn first_state_skips_finished_jobs() {
+        let mut known = None;
+        assert_eq!(kinds(&mut known, vec![job(1, JobState::Done), job(2, JobState::Running)]), ["transcription_started"]);
+    }
+}
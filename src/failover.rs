@@ -0,0 +1,495 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::time::Instant;
+use tracing::{debug, warn};
+use crate::audio;
+use crate::backends;
+use crate::helpers;
+use crate::latency;
+use crate::privacy;
+use crate::recording;
+use crate::refine;
+use crate::retranscribe;
+use crate::settings::{Layers, Resolved, Source};
+use crate::socket::{self, TranscriptionRequest, TranscriptionResponse};
+use crate::typing::Output;
+#[cfg(feature = "faster-whisper")]
+use crate::faster_whisper;
+#[cfg(feature = "http-backends")]
+use crate::openai;
+#[cfg(feature = "whisper-cpp")]
+use crate::whisper_cpp;
+
+/// One way of transcribing a recording, tried in the order of the failover chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Whichever daemon listens on the socket
+    Daemon,
+    /// The faster-whisper Python script, loading the model for this recording
+    FasterWhisper,
+    /// The built-in whisper.cpp bindings, loading the model for this recording
+    WhisperCpp,
+    /// The whisper.cpp binary (WHISPER_CPP_PATH)
+    WhisperCppCli,
+    /// The OpenAI-compatible API
+    OpenAi,
+    /// The portable build's built-in whisper.cpp, with a model it downloads itself
+    #[cfg(feature = "portable")]
+    Embedded,
+}
+
+impl Step {
+    const ALL: &[Step] = &[
+        Step::Daemon,
+        Step::FasterWhisper,
+        Step::WhisperCpp,
+        Step::WhisperCppCli,
+        Step::OpenAi,
+        #[cfg(feature = "portable")]
+        Step::Embedded,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Step::Daemon => "daemon",
+            Step::FasterWhisper => "faster-whisper",
+            Step::WhisperCpp => "whisper-cpp",
+            Step::WhisperCppCli => "whisper-cpp-cli",
+            Step::OpenAi => "openai",
+            #[cfg(feature = "portable")]
+            Step::Embedded => "embedded",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self> {
+        Self::ALL.iter()
+            .copied()
+            .find(|step| step.name() == name.trim())
+            .ok_or_else(|| {
+                let names = Self::ALL.iter().map(|step| step.name()).collect::<Vec<_>>();
+                anyhow!("Unknown failover step '{}' (expected one of {})", name.trim(), names.join(", "))
+            })
+    }
+
+    /// The backend the transcript is attributed to in the history and for refinement
+    fn backend(self) -> String {
+        match self {
+            Step::Daemon => helpers::read_daemon_config()
+                .and_then(|config| config.backend)
+                .unwrap_or_else(helpers::resolve_backend),
+            Step::FasterWhisper => "faster-whisper".to_string(),
+            Step::WhisperCpp | Step::WhisperCppCli => "whisper-cpp".to_string(),
+            #[cfg(feature = "portable")]
+            Step::Embedded => "whisper-cpp".to_string(),
+            Step::OpenAi => "openai".to_string(),
+        }
+    }
+
+    /// Transcribe without outputting anything
+    fn transcribe(self, request: &TranscriptionRequest, max_retries: u32) -> Result<TranscriptionResponse> {
+        let audio_file = request.audio_path.as_str();
+        let model = helpers::resolve_model();
+        let local = |text: String| TranscriptionResponse {
+            success: true,
+            text: Some(text),
+            model: Some(model.clone()),
+            ..Default::default()
+        };
+        match self {
+            Step::Daemon => {
+                let started = Instant::now();
+                let response = socket::transcribe(&helpers::resolve_socket_path(), request)?;
+                if response.cancelled {
+                    return Err(socket::JobCancelled.into());
+                }
+                if !response.success || response.text.is_none() {
+                    return Err(anyhow!("{}", response.error.as_deref().unwrap_or("no transcript in the response")));
+                }
+                Ok(retranscribe::improve(&helpers::resolve_socket_path(), request, response, &self.backend(), started))
+            }
+            #[cfg(feature = "faster-whisper")]
+            Step::FasterWhisper => faster_whisper::direct::transcribe_audio(
+                audio_file,
+                &model,
+                request.compute_type.as_deref(),
+                request.batch_size,
+            ).map(local),
+            #[cfg(feature = "whisper-cpp")]
+            Step::WhisperCpp => whisper_cpp::direct::transcribe_audio(audio_file, &model).map(local),
+            #[cfg(feature = "whisper-cpp")]
+            Step::WhisperCppCli => {
+                let whisper_path = std::env::var("WHISPER_CPP_PATH").unwrap_or_else(|_| "whisper-cpp".to_string());
+                whisper_cpp::direct::transcribe_audio_cli(audio_file, &model, &whisper_path).map(local)
+            }
+            #[cfg(feature = "http-backends")]
+            Step::OpenAi => openai::transcribe_file(std::path::Path::new(audio_file), max_retries),
+            #[cfg(feature = "portable")]
+            Step::Embedded => {
+                let model = whisper_cpp::embedded::model_setting().value;
+                whisper_cpp::embedded::transcribe_audio(audio_file).map(|text| TranscriptionResponse {
+                    model: Some(model),
+                    ..local(text)
+                })
+            }
+            // Steps of backends left out of this build
+            #[allow(unreachable_patterns)]
+            _ => {
+                let _ = (audio_file, local, max_retries);
+                Err(backends::unavailable(&self.backend()))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A way of transcribing tried for a recording, kept with the transcript in the history and
+/// the `--json` output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attempt {
+    /// The failover step's name: "daemon", "faster-whisper" (the Python script), "whisper-cpp"
+    /// (the bindings), "whisper-cpp-cli" (the binary), "openai" or "embedded"
+    pub path: String,
+    pub secs: f64,
+    /// Why it failed; None for the one that transcribed the recording
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Attempt {
+    pub fn served(step: Step, started: Instant) -> Self {
+        Self { path: step.to_string(), secs: latency::round_ms(started.elapsed()), error: None }
+    }
+
+    pub fn failed(step: Step, started: Instant, e: &anyhow::Error) -> Self {
+        let error = format!("{:#}", e);
+        let error: String = error.lines().next().unwrap_or_default().chars().take(200).collect();
+        Self { path: step.to_string(), secs: latency::round_ms(started.elapsed()), error: Some(error) }
+    }
+}
+
+/// Record how a transcript came about: the attempts and the one that served it, also for the
+/// latency breakdown
+pub fn record_attempts(response: &mut TranscriptionResponse, attempts: Vec<Attempt>) {
+    response.served_by = attempts.iter().rfind(|attempt| attempt.error.is_none()).map(|attempt| attempt.path.clone());
+    latency::attempts(&attempts);
+    debug!(
+        "Transcribed with {} ({})",
+        response.served_by.as_deref().unwrap_or("nothing"),
+        attempts.iter()
+            .map(|attempt| format!("{} {:.2}s{}", attempt.path, attempt.secs, if attempt.error.is_some() { " failed" } else { "" }))
+            .collect::<Vec<_>>()
+            .join(" → ")
+    );
+    response.attempts = attempts;
+}
+
+/// Output a transcript a client's own fallback made without the daemon like one from it,
+/// recording the attempts before it (e.g. the daemon that wasn't running)
+#[cfg(any(feature = "whisper-cpp", feature = "faster-whisper"))]
+pub fn deliver_direct(text: String, model: &str, step: Step, started: Instant, mut attempts: Vec<Attempt>, source: &str, output: &Output) -> Result<()> {
+    let mut response = TranscriptionResponse {
+        success: true,
+        text: Some(text),
+        model: Some(model.to_string()),
+        ..Default::default()
+    };
+    attempts.push(Attempt::served(step, started));
+    record_attempts(&mut response, attempts);
+    let result = socket::deliver(&mut response, &step.backend(), source, output);
+    privacy::wipe_response(&mut response);
+    result
+}
+
+/// Resolves the failover chain with priority:
+/// 1. WA_FAILOVER env var, comma-separated (e.g. "daemon,whisper-cpp,openai")
+/// 2. User config file `failover` (a list)
+/// 3. Default to the backend's own fallback: its daemon, then transcribing directly
+///    (portable builds add the built-in engine last, and use only it while no backend is set)
+pub fn chain_setting() -> Resolved<Vec<String>> {
+    Layers::new("failover chain")
+        .env("WA_FAILOVER", |value| Some(value.split(',').map(|step| step.trim().to_string()).filter(|step| !step.is_empty()).collect()))
+        .config(|c| c.failover)
+        .or_else(|| Resolved { value: default_chain(), source: Source::Default })
+}
+
+fn default_chain() -> Vec<String> {
+    let backend = helpers::backend_setting();
+    // Out of the box there is neither a daemon nor Python, so trying them would only add
+    // a failure to every dictation
+    if cfg!(feature = "portable") && backend.source == Source::Default {
+        return vec!["embedded".to_string()];
+    }
+    let steps: &[&str] = match backend.value.as_str() {
+        "openai" => &["openai"],
+        // The bindings can't take arbitrary flags, so extra args mean the binary
+        #[cfg(feature = "whisper-cpp")]
+        "whisper-cpp" if !whisper_cpp::direct::extra_args_setting().value.is_empty() => &["daemon", "whisper-cpp-cli"],
+        "whisper-cpp" => &["daemon", "whisper-cpp"],
+        _ => &["daemon", "faster-whisper"],
+    };
+    let mut steps: Vec<String> = steps.iter().map(|step| step.to_string()).collect();
+    if cfg!(feature = "portable") {
+        steps.push("embedded".to_string());
+    }
+    steps
+}
+
+/// The failover chain, and whether it replaces the backend's own fallback (when configured,
+/// or in a portable build, whose default chain ends with the built-in engine)
+pub fn chain() -> Result<(Vec<Step>, bool)> {
+    let setting = chain_setting();
+    let steps = setting.value.iter().map(|name| Step::parse(name)).collect::<Result<Vec<_>>>()?;
+    if steps.is_empty() {
+        return Err(anyhow!("The failover chain is empty"));
+    }
+    Ok((steps, setting.source != Source::Default || cfg!(feature = "portable")))
+}
+
+/// The result of going down the chain
+pub struct Outcome {
+    /// Steps that failed before one succeeded (or all of them), with why
+    pub failures: Vec<(Step, anyhow::Error)>,
+    /// The step that transcribed the recording, with its response
+    pub transcript: Option<(Step, TranscriptionResponse)>,
+    /// Every step tried, with how long it took
+    pub attempts: Vec<Attempt>,
+}
+
+impl Outcome {
+    /// What went wrong on the way, for a single notification; None when the first step worked
+    pub fn summary(&self) -> Option<String> {
+        if self.failures.is_empty() {
+            return None;
+        }
+        let headline = match &self.transcript {
+            Some((step, _)) => format!("⚠️ Transcribed with {} after failures", step),
+            None => "❌ Transcription failed with every backend".to_string(),
+        };
+        let reasons = self.failures.iter()
+            .map(|(step, e)| {
+                let reason = format!("{:#}", e);
+                let reason: String = reason.lines().next().unwrap_or_default().chars().take(80).collect();
+                format!("{}: {}", step, reason)
+            })
+            .collect::<Vec<_>>();
+        Some(format!("{}\n{}", headline, reasons.join("\n")))
+    }
+
+    /// The transcript, with the attempts that led to it, or an error listing every failure
+    pub fn into_result(self) -> Result<(Step, TranscriptionResponse)> {
+        if let Some((step, mut response)) = self.transcript {
+            record_attempts(&mut response, self.attempts);
+            return Ok((step, response));
+        }
+        let reasons = self.failures.iter()
+            .map(|(step, e)| format!("{}: {:#}", step, e))
+            .collect::<Vec<_>>();
+        Err(anyhow!("Transcription failed ({})", reasons.join("; ")))
+    }
+}
+
+/// Try each step in turn until one transcribes the recording
+/// A job cancelled in the daemon is an error rather than a reason to try the next step
+pub fn transcribe(request: &TranscriptionRequest, steps: &[Step], max_retries: u32) -> Result<Outcome> {
+    try_steps(steps, |step| step.transcribe(request, max_retries))
+}
+
+fn try_steps(steps: &[Step], mut transcribe: impl FnMut(Step) -> Result<TranscriptionResponse>) -> Result<Outcome> {
+    let mut failures = Vec::new();
+    let mut attempts = Vec::new();
+    for &step in steps {
+        debug!("Transcribing with {}", step);
+        let started = Instant::now();
+        match transcribe(step) {
+            Ok(response) => {
+                attempts.push(Attempt::served(step, started));
+                return Ok(Outcome { failures, transcript: Some((step, response)), attempts });
+            }
+            Err(e) if e.is::<socket::JobCancelled>() => return Err(e),
+            Err(e) => {
+                warn!("{} failed: {:#}", step, e);
+                attempts.push(Attempt::failed(step, started, &e));
+                failures.push((step, e));
+            }
+        }
+    }
+    Ok(Outcome { failures, transcript: None, attempts })
+}
+
+/// Stop recording and transcribe along a configured failover chain
+pub fn stop_and_transcribe(
+    steps: &[Step],
+    output: &Output,
+    compute_type: Option<&str>,
+    batch_size: Option<u32>,
+    final_model: Option<&str>,
+    max_retries: u32,
+) -> Result<()> {
+    let audio_file = match recording::stop_recording(None)? {
+        Some(path) => path,
+        None => {
+            warn!("No recording found");
+            helpers::send_notification("Voice Input", "❌ No recording found", 2000);
+            return Ok(());
+        }
+    };
+
+    match fs::metadata(&audio_file) {
+        Err(_) => {
+            warn!("Audio file does not exist: {}", audio_file);
+            helpers::send_notification("Voice Input", "❌ No audio recorded", 2000);
+            return Ok(());
+        }
+        Ok(metadata) if audio::is_empty(&audio_file) => {
+            warn!("Audio file is empty (no samples after the WAV header): {} bytes", metadata.len());
+            helpers::send_notification("Voice Input", "❌ Audio file is empty", 2000);
+            let _ = privacy::remove_file(&audio_file);
+            return Ok(());
+        }
+        Ok(_) => {}
+    }
+    if recording::reject_silence(&audio_file) {
+        return Ok(());
+    }
+    transcribe_recording(audio_file, steps, output, compute_type, batch_size, final_model, max_retries)
+}
+
+/// Transcribe a finished recording along the chain and output the text; the recording is
+/// removed afterwards, or by the refinement worker
+pub fn transcribe_recording(
+    audio_file: String,
+    steps: &[Step],
+    output: &Output,
+    compute_type: Option<&str>,
+    batch_size: Option<u32>,
+    final_model: Option<&str>,
+    max_retries: u32,
+) -> Result<()> {
+    let chain = steps.iter().map(|step| step.name()).collect::<Vec<_>>().join(" → ");
+    helpers::send_notification(
+        "Voice Input",
+        &format!("⏳ Transcribing...\nBackends: {} | Model: {}", chain, helpers::resolve_model()),
+        2000
+    );
+
+    let request = TranscriptionRequest {
+        compute_type: compute_type.map(str::to_string),
+        batch_size,
+        ..TranscriptionRequest::new(&audio_file)
+    };
+    let outcome = match transcribe(&request, steps, max_retries) {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            let _ = privacy::remove_file(&audio_file);
+            if e.is::<socket::JobCancelled>() {
+                helpers::send_notification("Voice Input", "🚫 Transcription cancelled", 1500);
+                return Ok(());
+            }
+            return Err(e);
+        }
+    };
+    if let Some(summary) = outcome.summary() {
+        helpers::send_notification("Voice Input", &summary, 5000);
+    }
+
+    let (step, mut response) = match outcome.into_result() {
+        Ok(transcript) => transcript,
+        Err(e) => {
+            let _ = privacy::remove_file(&audio_file);
+            return Err(e);
+        }
+    };
+    let backend = step.backend();
+    let source = match step {
+        Step::Daemon => format!("{} daemon", backend),
+        step => step.to_string(),
+    };
+    let result = socket::deliver(&mut response, &backend, &source, output);
+    // The API backend has no final-model pass; otherwise the refinement worker deletes the audio
+    let refining = step != Step::OpenAi
+        && refine::hand_off(&audio_file, response.text.as_deref(), final_model, &backend, output);
+    if !refining {
+        let _ = privacy::remove_file(&audio_file);
+    }
+    privacy::wipe_response(&mut response);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcript(text: &str) -> Result<TranscriptionResponse> {
+        Ok(TranscriptionResponse { success: true, text: Some(text.to_string()), ..Default::default() })
+    }
+
+    #[test]
+    fn parses_step_names() {
+        assert_eq!(Step::parse("daemon").unwrap(), Step::Daemon);
+        assert_eq!(Step::parse(" whisper-cpp-cli ").unwrap(), Step::WhisperCppCli);
+        for step in Step::ALL {
+            assert_eq!(Step::parse(step.name()).unwrap(), *step);
+        }
+        let error = Step::parse("whisper").unwrap_err().to_string();
+        assert!(error.contains("Unknown failover step 'whisper'"), "{}", error);
+        assert!(error.contains("daemon, faster-whisper, whisper-cpp"), "{}", error);
+    }
+
+    #[test]
+    fn stops_at_the_first_step_that_works() {
+        let mut tried = Vec::new();
+        let outcome = try_steps(&[Step::Daemon, Step::WhisperCpp, Step::OpenAi], |step| {
+            tried.push(step);
+            match step {
+                Step::Daemon => Err(anyhow!("Timed out waiting for the daemon")),
+                _ => transcript(step.name()),
+            }
+        }).unwrap();
+        assert_eq!(tried, [Step::Daemon, Step::WhisperCpp]);
+        assert_eq!(outcome.summary().unwrap(), "⚠️ Transcribed with whisper-cpp after failures\ndaemon: Timed out waiting for the daemon");
+
+        let (step, response) = outcome.into_result().unwrap();
+        assert_eq!(step, Step::WhisperCpp);
+        assert_eq!(response.text.as_deref(), Some("whisper-cpp"));
+        assert_eq!(response.served_by.as_deref(), Some("whisper-cpp"));
+        let paths: Vec<_> = response.attempts.iter().map(|attempt| (attempt.path.as_str(), attempt.error.as_deref())).collect();
+        assert_eq!(paths, [("daemon", Some("Timed out waiting for the daemon")), ("whisper-cpp", None)]);
+    }
+
+    #[test]
+    fn no_summary_when_the_first_step_works() {
+        let outcome = try_steps(&[Step::Daemon, Step::OpenAi], |step| transcript(step.name())).unwrap();
+        assert!(outcome.summary().is_none());
+        assert_eq!(outcome.attempts.len(), 1);
+    }
+
+    #[test]
+    fn reports_every_failure_in_order() {
+        let outcome = try_steps(&[Step::OpenAi, Step::Daemon], |step| Err(anyhow!("{} is down", step))).unwrap();
+        assert!(outcome.transcript.is_none());
+        assert_eq!(
+            outcome.summary().unwrap(),
+            "❌ Transcription failed with every backend\nopenai: openai is down\ndaemon: daemon is down"
+        );
+        let error = outcome.into_result().unwrap_err().to_string();
+        assert_eq!(error, "Transcription failed (openai: openai is down; daemon: daemon is down)");
+    }
+
+    #[test]
+    fn cancelled_jobs_are_not_retried() {
+        let mut tried = Vec::new();
+        let result = try_steps(&[Step::Daemon, Step::WhisperCpp], |step| {
+            tried.push(step);
+            Err(socket::JobCancelled.into())
+        });
+        assert!(result.is_err_and(|e| e.is::<socket::JobCancelled>()));
+        assert_eq!(tried, [Step::Daemon]);
+    }
+}
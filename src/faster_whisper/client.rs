@@ -1,12 +1,18 @@
 use anyhow::Result;
 use std::fs;
+use std::time::Instant;
 use tracing::{debug, warn};
+use crate::audio;
+use crate::failover::{Attempt, Step};
+use crate::privacy;
 use crate::recording;
 use crate::socket;
 use crate::helpers;
+use crate::refine;
+use crate::typing::Output;
 use super::direct::transcribe_with_faster_whisper;
 
-pub fn stop_and_transcribe_daemon(socket_path: &str, use_clipboard: bool) -> Result<()> {
+pub fn stop_and_transcribe_daemon(socket_path: &str, output: &Output, compute_type: Option<&str>, batch_size: Option<u32>, final_model: Option<&str>) -> Result<()> {
     debug!("stop_and_transcribe_daemon called, socket_path: {}", socket_path);
     
     let audio_file = match recording::stop_recording(None)? {
@@ -40,17 +46,20 @@ pub fn stop_and_transcribe_daemon(socket_path: &str, use_clipboard: bool) -> Res
         let file_size = metadata.len();
         debug!("Audio file size: {} bytes", file_size);
         
-        if file_size <= 44 {
-            warn!("Audio file is empty (only WAV header): {} bytes", file_size);
+        if audio::is_empty(&audio_file) {
+            warn!("Audio file is empty (no samples after the WAV header): {} bytes", file_size);
             helpers::send_notification(
                 "Voice Input",
                 "❌ Audio file is empty\nBackend: faster-whisper",
                 2000
             );
-            let _ = fs::remove_file(&audio_file);
+            let _ = privacy::remove_file(&audio_file);
             return Ok(());
         }
     }
+    if recording::reject_silence(&audio_file) {
+        return Ok(());
+    }
 
     // Get model for notification
     let model = helpers::resolve_model();
@@ -60,13 +69,37 @@ pub fn stop_and_transcribe_daemon(socket_path: &str, use_clipboard: bool) -> Res
     debug!("Sending transcription request, model: {}, acceleration: {}", model, acceleration);
     helpers::send_notification("Voice Input", &transcribe_msg, 2000);
 
-    match socket::send_transcription_request(socket_path, &audio_file, "faster-whisper", use_clipboard) {
-        Ok(_) => {
+    let mut request = socket::TranscriptionRequest {
+        compute_type: compute_type.map(str::to_string),
+        batch_size,
+        ..socket::TranscriptionRequest::new(&audio_file)
+    };
+    // A relative model path means nothing to the daemon, which runs elsewhere
+    if let Some(model) = &request.model {
+        match super::models::resolve(model) {
+            Ok(model) => request.model = Some(model),
+            Err(e) => {
+                helpers::send_notification("Voice Input", &format!("❌ {:#}", e), 5000);
+                return Err(e);
+            }
+        }
+    }
+
+    let started = Instant::now();
+    match socket::send_transcription_request(socket_path, &request, "faster-whisper", output) {
+        Ok(mut response) => {
             debug!("Daemon transcription completed successfully");
-            let _ = fs::remove_file(&audio_file);
+            // With a final model the refinement worker re-transcribes and then deletes the audio
+            if !refine::hand_off(&audio_file, response.as_ref().and_then(|r| r.text.as_deref()), final_model, "faster-whisper", output) {
+                let _ = privacy::remove_file(&audio_file);
+            }
+            if let Some(response) = response.as_mut() {
+                privacy::wipe_response(response);
+            }
         }
         Err(e) => {
             warn!("Daemon not available ({}), falling back to direct mode", e);
+            let attempts = vec![Attempt::failed(Step::Daemon, started, &e)];
             helpers::send_notification(
                 "Voice Input (daemon)",
                 "⚠️ Daemon not running, using direct mode",
@@ -74,9 +107,9 @@ pub fn stop_and_transcribe_daemon(socket_path: &str, use_clipboard: bool) -> Res
             );
             
             // Use the resolved model, not hardcoded base.en
-            let result = transcribe_with_faster_whisper(&audio_file, &model, use_clipboard);
+            let result = transcribe_with_faster_whisper(&audio_file, &model, output, compute_type, batch_size, attempts);
             
-            let _ = fs::remove_file(&audio_file);
+            let _ = privacy::remove_file(&audio_file);
             
             return result.map_err(|err| anyhow::anyhow!("Fallback transcription failed (daemon was: {}): {}", e, err));
         }
@@ -1,19 +1,155 @@
 use anyhow::{Context, Result};
-use std::process::Command;
-use crate::helpers::{DaemonConfig, write_daemon_config, resolve_use_clipboard};
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::process::{Child, Command};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+use tracing::{info, warn};
+use crate::activation;
+use crate::helpers::{DaemonConfig, write_daemon_config, resolve_use_clipboard, resolve_model, resolve_language, resolve_task, resolve_compute_type, send_notification};
 
-pub fn run_daemon(model: &str, socket_path: &str) -> Result<()> {
-    // Write daemon config so CLI commands can read our settings
+/// Settings the Python daemon is started with, and reloads when the config file changes
+#[derive(Debug, Clone, PartialEq)]
+struct DaemonSettings {
+    model: String,
+    language: String,
+    compute_type: Option<String>,
+}
+
+/// `listener` is the socket systemd passed when it started the daemon on demand; the
+/// Python daemon inherits it (and exits once idle, ending this process too)
+pub fn run_daemon(model: &str, socket_path: &str, cli_compute_type: Option<&str>, listener: Option<UnixListener>) -> Result<()> {
+    let activated = listener.as_ref().map(|listener| {
+        let fd = listener.as_raw_fd();
+        // SAFETY: only clears close-on-exec on a descriptor we own, so the child inherits it
+        unsafe { libc::fcntl(fd, libc::F_SETFD, 0) };
+        fd
+    });
+    activation::write_pid();
+    let result = supervise(model, socket_path, cli_compute_type, activated);
+    activation::remove_pid();
+    result
+}
+
+fn supervise(model: &str, socket_path: &str, cli_compute_type: Option<&str>, activated: Option<RawFd>) -> Result<()> {
+    let mut settings = DaemonSettings {
+        model: super::models::resolve(model)?,
+        language: resolve_language(),
+        compute_type: resolve_compute_type(cli_compute_type.map(str::to_string)),
+    };
+
+    // Hand the Python daemon new settings whenever the config file changes them
+    let (tx, rx) = mpsc::channel();
+    let _config_watcher = match crate::config::watch_config(move |_config| {
+        let _ = tx.send(());
+    }) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            warn!("Config hot-reload disabled: {}", e);
+            None
+        }
+    };
+
+    write_config(&settings, socket_path);
+    let mut child = spawn_python_daemon(&settings, socket_path, activated)?;
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            if !status.success() {
+                return Err(anyhow::anyhow!("Faster-whisper daemon exited with error"));
+            }
+            return Ok(());
+        }
+
+        match rx.recv_timeout(Duration::from_millis(500)) {
+            Ok(()) => {
+                let model = match super::models::resolve(&resolve_model()) {
+                    Ok(model) => model,
+                    Err(e) => {
+                        warn!("Keeping model {}: {:#}", settings.model, e);
+                        settings.model.clone()
+                    }
+                };
+                let new_settings = DaemonSettings {
+                    model,
+                    language: resolve_language(),
+                    compute_type: resolve_compute_type(cli_compute_type.map(str::to_string)),
+                };
+                if new_settings == settings {
+                    info!("Configuration reloaded");
+                    send_notification("Voice Input", "🔄 Configuration reloaded", 2000);
+                    continue;
+                }
+
+                // The running daemon takes the new defaults itself; a restart would
+                // drop the jobs it is working on and a recording in progress
+                match reload(&new_settings, socket_path) {
+                    Ok(()) => {
+                        info!("Configuration reloaded: {:?} -> {:?}", settings, new_settings);
+                        send_notification(
+                            "Voice Input",
+                            &format!("🔄 Configuration reloaded\nModel: {}", new_settings.model),
+                            2000
+                        );
+                        settings = new_settings;
+                        write_config(&settings, socket_path);
+                    }
+                    Err(e) => {
+                        warn!("Keeping the daemon's settings {:?}: {:#}", settings, e);
+                        send_notification("Voice Input", &format!("⚠️ Configuration not applied\n{:#}", e), 5000);
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            // No watcher (sender dropped): keep supervising the child
+            Err(RecvTimeoutError::Disconnected) => std::thread::sleep(Duration::from_millis(500)),
+        }
+    }
+}
+
+/// Write daemon config so CLI commands can read our settings
+fn write_config(settings: &DaemonSettings, socket_path: &str) {
     let config = DaemonConfig {
         backend: Some("faster-whisper".to_string()),
-        model: Some(model.to_string()),
+        model: Some(settings.model.clone()),
         socket_path: Some(socket_path.to_string()),
         use_clipboard: Some(resolve_use_clipboard()),
+        compute_type: settings.compute_type.clone(),
+        language: Some(settings.language.clone()),
     };
     if let Err(e) = write_daemon_config(&config) {
         eprintln!("Warning: Failed to write daemon config: {}", e);
     }
-    
+}
+
+/// Have the running Python daemon switch to `settings`. Always the local socket: a
+/// configured remote daemon is someone else's
+fn reload(settings: &DaemonSettings, socket_path: &str) -> Result<()> {
+    let request = serde_json::json!({
+        "command": "reload",
+        "model": settings.model,
+        "language": settings.language,
+        "compute_type": settings.compute_type,
+    });
+    let mut stream = UnixStream::connect(socket_path).context("Failed to connect to the daemon")?;
+    stream.write_all(request.to_string().as_bytes()).context("Failed to send reload request")?;
+    stream.shutdown(Shutdown::Write).context("Failed to send reload request")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).context("Failed to read the daemon's response")?;
+    let status: serde_json::Value = serde_json::from_str(&response)
+        .context("Could not parse the daemon's response")?;
+    if status.get("success").and_then(|v| v.as_bool()) == Some(false) {
+        return Err(anyhow::anyhow!(
+            "{}",
+            status.get("error").and_then(|v| v.as_str()).unwrap_or("unknown error")
+        ));
+    }
+    Ok(())
+}
+
+fn spawn_python_daemon(settings: &DaemonSettings, socket_path: &str, activated: Option<RawFd>) -> Result<Child> {
     // Get Python interpreter and script paths from environment
     let python_path = std::env::var("FASTER_WHISPER_PYTHON")
         .context("FASTER_WHISPER_PYTHON not set")?;
@@ -21,27 +157,49 @@ pub fn run_daemon(model: &str, socket_path: &str) -> Result<()> {
         .context("FASTER_WHISPER_PYTHONPATH not set")?;
     let script_path = std::env::var("FASTER_WHISPER_DAEMON_SCRIPT")
         .context("FASTER_WHISPER_DAEMON_SCRIPT not set")?;
-    
+
     // Check if script exists
     if !std::path::Path::new(&script_path).exists() {
         return Err(anyhow::anyhow!("whisper_daemon.py not found at {}", script_path));
     }
-    
-    // Run Python with injected environment
-    let status = Command::new(&python_path)
+
+    // Run Python with injected environment; the remote listener's is ours alone, not
+    // whatever the script would inherit
+    let mut command = Command::new(&python_path);
+    for var in ["WA_DAEMON_LISTEN", "WA_DAEMON_TOKEN", "WA_DAEMON_TLS_CERT", "WA_DAEMON_TLS_KEY"] {
+        command.env_remove(var);
+    }
+    command
         .arg(&script_path)
         .env("PYTHONPATH", &pythonpath)
-        .env("WA_WHISPER_MODEL", model)
+        .env("WA_WHISPER_MODEL", &settings.model)
+        .env("WA_WHISPER_LANGUAGE", &settings.language)
+        .env("WA_WHISPER_TASK", resolve_task())
         .env("WA_WHISPER_SOCKET", socket_path)
+        .env("WA_ACTIVITY_FILE", crate::activity::activity_path())
+        // Reported to clients in answer to `hello`
+        .env("WA_VERSION", env!("CARGO_PKG_VERSION"))
+        .env("WA_PRIVACY", crate::privacy::enabled().to_string())
+        .env("WA_MODELS_DIR", crate::paths::faster_whisper_models_dir())
+        .env("WA_MODEL_MEMORY_MB", crate::helpers::resolve_model_memory_mb().to_string())
+        .env("WA_FAST_PATH_SECS", crate::helpers::fast_path_secs_setting().value.to_string())
+        .envs(crate::vad::script_env())
+        .envs(crate::remote::script_env()?)
+        // Runs `whisp-away capture` to record for clients
+        .env("WA_EXE", std::env::current_exe().context("Failed to locate whisp-away executable")?)
         // Pass through CUDA environment if present
         .env("CUDA_VISIBLE_DEVICES", std::env::var("CUDA_VISIBLE_DEVICES").unwrap_or_default())
-        .env("LD_LIBRARY_PATH", std::env::var("LD_LIBRARY_PATH").unwrap_or_default())
-        .status()
-        .context("Failed to run faster-whisper daemon")?;
-    
-    if !status.success() {
-        return Err(anyhow::anyhow!("Faster-whisper daemon exited with error"));
+        .env("LD_LIBRARY_PATH", std::env::var("LD_LIBRARY_PATH").unwrap_or_default());
+    if let Some(compute_type) = &settings.compute_type {
+        command.env("WHISPER_COMPUTE", compute_type);
     }
-    
-    Ok(())
-}
\ No newline at end of file
+    if let Some(fd) = activated {
+        command
+            .env(activation::LISTEN_FD_VAR, fd.to_string())
+            .env("WA_DAEMON_IDLE_EXIT_SECS", (activation::idle_exit_mins_setting().value * 60).to_string());
+    }
+
+    command
+        .spawn()
+        .context("Failed to run faster-whisper daemon")
+}
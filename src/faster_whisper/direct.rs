@@ -1,18 +1,15 @@
 use anyhow::{Context, Result};
 use std::process::Command;
 use tracing::{debug, warn};
-use crate::typing;
+use std::time::Instant;
+use crate::failover::{self, Attempt, Step};
+use crate::privacy;
+use crate::typing::Output;
 use crate::helpers;
 
-/// Transcribe audio with faster-whisper and type the result
-pub fn transcribe_with_faster_whisper(audio_file: &str, model: &str, use_clipboard: bool) -> Result<()> {
-    debug!("Direct transcription with faster-whisper, model: {}, audio: {}", model, audio_file);
-    
-    let acceleration = helpers::get_acceleration_type();
-    let transcribe_msg = format!("⏳ Transcribing... ({})", acceleration);
-    
-    helpers::send_notification("Voice Input (faster-whisper)", &transcribe_msg, 2000);
-
+/// Core transcription function running the faster-whisper script
+pub fn transcribe_audio(audio_file: &str, model: &str, compute_type: Option<&str>, batch_size: Option<u32>) -> Result<String> {
+    let model = super::models::resolve(model)?;
     let python_path = std::env::var("FASTER_WHISPER_PYTHON")
         .unwrap_or_else(|_| "python3".to_string());
     let pythonpath = std::env::var("FASTER_WHISPER_PYTHONPATH")
@@ -24,41 +21,77 @@ pub fn transcribe_with_faster_whisper(audio_file: &str, model: &str, use_clipboa
     debug!("Script path: {}", script_path);
     debug!("PYTHONPATH: {}", pythonpath);
     
-    let output = Command::new(&python_path)
+    let mut command = Command::new(&python_path);
+    command
         .arg(&script_path)
-        .args([audio_file, model])
+        .args([audio_file, &model])
         .env("PYTHONPATH", &pythonpath)
+        .env("WA_WHISPER_LANGUAGE", helpers::resolve_language())
+        .env("WA_WHISPER_TASK", helpers::resolve_task())
+        .env("WA_MODELS_DIR", crate::paths::faster_whisper_models_dir())
+        .envs(crate::vad::script_env())
         .env("CUDA_VISIBLE_DEVICES", std::env::var("CUDA_VISIBLE_DEVICES").unwrap_or_default())
-        .env("LD_LIBRARY_PATH", std::env::var("LD_LIBRARY_PATH").unwrap_or_default())
+        .env("LD_LIBRARY_PATH", std::env::var("LD_LIBRARY_PATH").unwrap_or_default());
+    if let Some(compute_type) = compute_type {
+        command.env("WHISPER_COMPUTE", compute_type);
+    }
+    if let Some(batch_size) = batch_size {
+        command.env("WHISPER_BATCH_SIZE", batch_size.to_string());
+    }
+    if let Some(beam_size) = helpers::overrides().beam_size {
+        command.env("WA_BEAM_SIZE", beam_size.to_string());
+    }
+    if let Some(prompt) = helpers::resolve_initial_prompt() {
+        command.env("WA_INITIAL_PROMPT", prompt);
+    }
+    
+    let result = command
         .output()
         .context("Failed to run faster-whisper transcription")?;
     
-    let transcribed_text = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
+    let transcribed_text = String::from_utf8_lossy(&result.stdout);
+    let stderr = String::from_utf8_lossy(&result.stderr);
     
-    debug!("Exit status: {}", output.status);
-    debug!("Stdout: '{}'", transcribed_text);
+    debug!("Exit status: {}", result.status);
+    debug!("Stdout: '{}'", privacy::redact(&transcribed_text));
     if !stderr.is_empty() {
         debug!("Stderr: '{}'", stderr);
     }
 
-    if output.status.success() {
-        let clean_text = transcribed_text.trim();
-        debug!("Transcription result: '{}' ({} chars)", 
-              if clean_text.len() > 50 { &clean_text[..50] } else { clean_text },
-              clean_text.len());
-        
-        typing::output_text(clean_text, use_clipboard, "faster-whisper")?;
-    } else {
-        warn!("Transcription failed. Exit code: {:?}, stderr: {}", output.status.code(), stderr);
-        helpers::send_notification(
-            "Voice Input (faster-whisper)",
-            &format!("❌ Transcription failed\n{}", 
-                     if stderr.len() > 100 { &stderr[..100] } else { &stderr }),
-            3000
-        );
+    if !result.status.success() {
+        warn!("Transcription failed. Exit code: {:?}, stderr: {}", result.status.code(), stderr);
         return Err(anyhow::anyhow!("Transcription failed: {}", stderr));
     }
+    
+    let clean_text = transcribed_text.trim().to_string();
+    debug!("Transcription result: '{}' ({} chars)", 
+          privacy::redact(if clean_text.len() > 50 { &clean_text[..50] } else { &clean_text }),
+          clean_text.len());
+    Ok(clean_text)
+}
+
+/// Transcribe audio with faster-whisper and type the result
+/// `attempts` are the ways of transcribing that failed before, for the history
+pub fn transcribe_with_faster_whisper(audio_file: &str, model: &str, output: &Output, compute_type: Option<&str>, batch_size: Option<u32>, attempts: Vec<Attempt>) -> Result<()> {
+    debug!("Direct transcription with faster-whisper, model: {}, audio: {}, compute type: {:?}, batch size: {:?}", model, audio_file, compute_type, batch_size);
+    
+    let acceleration = helpers::get_acceleration_type();
+    let transcribe_msg = format!("⏳ Transcribing... ({})", acceleration);
+    
+    helpers::send_notification("Voice Input (faster-whisper)", &transcribe_msg, 2000);
 
-    Ok(())
-}
\ No newline at end of file
+    let started = Instant::now();
+    match transcribe_audio(audio_file, model, compute_type, batch_size) {
+        Ok(text) => failover::deliver_direct(text, model, Step::FasterWhisper, started, attempts, "faster-whisper", output),
+        Err(e) => {
+            let message = e.to_string();
+            helpers::send_notification(
+                "Voice Input (faster-whisper)",
+                &format!("❌ Transcription failed\n{}", 
+                         if message.len() > 100 { &message[..100] } else { &message }),
+                3000
+            );
+            Err(e)
+        }
+    }
+}
@@ -1,6 +1,7 @@
 pub mod client;
 pub mod daemon;
 pub mod direct;
+pub mod models;
 
 pub use client::stop_and_transcribe_daemon;
 pub use daemon::run_daemon;
@@ -0,0 +1,110 @@
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use tracing::{debug, warn};
+use crate::paths;
+
+/// The last model checked, so a direct fallback after the daemon request doesn't check it again
+static LAST_RESOLVED: Mutex<Option<(String, String)>> = Mutex::new(None);
+
+/// Check a faster-whisper model before it reaches the Python side, which accepts:
+/// 1. A known name (`distil-large-v3`, `large-v3-turbo`, ...), or an alias for one (`accurate`)
+/// 2. A Hugging Face repo id (`owner/name`) with a CTranslate2 conversion, downloaded on first use
+/// 3. A local CTranslate2 model directory, returned as an absolute path so the daemon finds it
+///    whatever its working directory
+pub fn resolve(model: &str) -> Result<String> {
+    let model = crate::aliases::resolve(model, "faster-whisper");
+    let model = model.as_str();
+    if crate::models::is_faster_whisper_name(model) {
+        return Ok(model.to_string());
+    }
+    let mut last = LAST_RESOLVED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((name, resolved)) = last.as_ref().filter(|(name, _)| name == model) {
+        debug!("Model {} already checked", name);
+        return Ok(resolved.clone());
+    }
+    let resolved = check(model)?;
+    *last = Some((model.to_string(), resolved.clone()));
+    Ok(resolved)
+}
+
+fn check(model: &str) -> Result<String> {
+    if let Some(message) = crate::models::mismatch(model, "faster-whisper", &crate::models::scan()) {
+        return Err(anyhow!("{}", message));
+    }
+
+    let path = expand_home(model);
+    if model.starts_with(['/', '.', '~']) || path.is_dir() {
+        return local_model(&path);
+    }
+
+    if is_repo_id(model) {
+        check_repo(model)?;
+        return Ok(model.to_string());
+    }
+
+    Err(anyhow!(
+        "Unknown faster-whisper model '{}': use a name like distil-large-v3, a Hugging Face repo id \
+         (owner/name) or the path of a CTranslate2 model directory",
+        model
+    ))
+}
+
+fn expand_home(model: &str) -> PathBuf {
+    match model.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None => PathBuf::from(model),
+    }
+}
+
+/// A converted model directory holds model.bin next to config.json and the tokenizer
+fn local_model(path: &Path) -> Result<String> {
+    let path = path.canonicalize()
+        .map_err(|e| anyhow!("Model directory {} not found: {}", path.display(), e))?;
+    if !path.join("model.bin").is_file() {
+        return Err(anyhow!(
+            "{} is not a CTranslate2 model (no model.bin); convert it with ct2-transformers-converter",
+            path.display()
+        ));
+    }
+    debug!("Using local faster-whisper model {}", path.display());
+    Ok(path.to_string_lossy().into_owned())
+}
+
+fn is_repo_id(model: &str) -> bool {
+    let valid = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+    matches!(model.split_once('/'), Some((owner, name)) if valid(owner) && valid(name))
+}
+
+/// Make sure a repo id exists before the daemon tries to download it
+/// Downloaded repos are used as they are; when Hugging Face can't be reached the download
+/// is left to fail (or succeed from another mirror) on its own
+fn check_repo(repo: &str) -> Result<()> {
+    let cached = paths::faster_whisper_models_dir().join(format!("models--{}", repo.replace('/', "--")));
+    if cached.is_dir() {
+        debug!("{} already downloaded to {}", repo, cached.display());
+        return Ok(());
+    }
+
+    let url = format!("https://huggingface.co/api/models/{}", repo);
+    let status = Command::new("curl")
+        .args(["--silent", "--output", "/dev/null", "--write-out", "%{http_code}", "--max-time", "10", &url])
+        .output();
+    match status.map(|output| String::from_utf8_lossy(&output.stdout).into_owned()) {
+        Ok(code) if code == "200" => {
+            debug!("{} found on Hugging Face, downloading on first use", repo);
+            Ok(())
+        }
+        Ok(code) if code == "404" => Err(anyhow!("Model {} not found on Hugging Face", repo)),
+        // Private and gated repos answer 401 without a token, which the download may have
+        Ok(code) => {
+            warn!("Could not check {} on Hugging Face (HTTP {}), trying anyway", repo, code);
+            Ok(())
+        }
+        Err(e) => {
+            warn!("Could not check {} on Hugging Face ({}), trying anyway", repo, e);
+            Ok(())
+        }
+    }
+}
@@ -6,7 +6,16 @@ Used by voice-input-rust for the start-faster/stop-faster commands.
 
 import sys
 import os
-from faster_whisper import WhisperModel
+from faster_whisper import WhisperModel, decode_audio
+import vad
+
+try:
+    from faster_whisper import BatchedInferencePipeline
+except ImportError:  # faster-whisper < 1.1
+    BatchedInferencePipeline = None
+
+# Recordings at least this long are transcribed with the batched pipeline
+BATCH_MIN_SECONDS = 60.0
 
 def main():
     if len(sys.argv) < 3:
@@ -38,7 +47,7 @@ def main():
         compute_type = 'float16' if device == 'cuda' else 'int8'
     
     # Load model
-    cache_dir = os.path.expanduser('~/.cache/faster-whisper')
+    cache_dir = os.environ.get('WA_MODELS_DIR') or os.path.expanduser('~/.cache/faster-whisper')
     os.makedirs(cache_dir, exist_ok=True)
     
     try:
@@ -49,24 +58,33 @@ def main():
             download_root=cache_dir
         )
         
-        # Check if VAD should be disabled (for debugging)
-        use_vad = os.environ.get("WHISPER_VAD", "true").lower() != "false"
-        
         transcribe_kwargs = dict(
-            language='en',
-            beam_size=5,
+            language=os.environ.get('WA_WHISPER_LANGUAGE', 'en'),
+            task=os.environ.get('WA_WHISPER_TASK', 'transcribe'),
+            beam_size=int(os.environ.get('WA_BEAM_SIZE') or 5),
         )
+        initial_prompt = os.environ.get('WA_INITIAL_PROMPT')
+        if initial_prompt:
+            transcribe_kwargs["initial_prompt"] = initial_prompt
         
-        if use_vad:
-            transcribe_kwargs["vad_filter"] = True
-            transcribe_kwargs["vad_parameters"] = dict(
-                min_silence_duration_ms=300,
-                speech_pad_ms=200,  # More padding around speech
-                threshold=0.3,  # Lower threshold = more sensitive
-            )
+        # Batch long recordings on GPU (WHISPER_BATCH_SIZE=1 disables batching)
+        audio = decode_audio(audio_file)
+        duration = len(audio) / 16000
+        batch_size = int(os.environ.get('WHISPER_BATCH_SIZE') or (8 if device == 'cuda' else 1))
+        vad_options = vad.transcribe_options(audio)
+        if vad_options is None:
+            return
+        transcribe_kwargs.update(vad_options)
+        batched = (BatchedInferencePipeline is not None and batch_size > 1
+                   and vad.batchable() and duration >= BATCH_MIN_SECONDS)
+        print(f"Transcribing {duration:.1f}s of audio (batched: {batched}, batch size: {batch_size})", file=sys.stderr)
         
         # Transcribe
-        segments, info = model.transcribe(audio_file, **transcribe_kwargs)
+        if batched:
+            pipeline = BatchedInferencePipeline(model=model)
+            segments, info = pipeline.transcribe(audio, batch_size=batch_size, **transcribe_kwargs)
+        else:
+            segments, info = model.transcribe(audio, **transcribe_kwargs)
         
         # Output transcribed text
         text = ' '.join(segment.text.strip() for segment in segments)
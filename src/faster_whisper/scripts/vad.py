@@ -0,0 +1,141 @@
+"""
+Voice activity detection for the faster-whisper scripts.
+The engine and its tuning come from WA_VAD_* env vars set by whisp-away.
+"""
+
+import os
+import logging
+
+import numpy
+
+logger = logging.getLogger(__name__)
+
+SAMPLE_RATE = 16000
+
+ENGINES = ("silero", "webrtc", "energy", "off")
+
+
+def engine():
+    """The selected engine; WHISPER_VAD=false (older setting) turns VAD off."""
+    name = os.environ.get("WA_VAD_ENGINE")
+    if not name:
+        return "off" if os.environ.get("WHISPER_VAD", "true").lower() == "false" else "silero"
+    if name not in ENGINES:
+        logger.warning(f"Unknown VAD engine '{name}', using silero")
+        return "silero"
+    return name
+
+
+def tuning(var, default, kind=int):
+    value = os.environ.get(var)
+    if not value:
+        return default
+    try:
+        return kind(value)
+    except ValueError:
+        logger.warning(f"Ignoring {var}={value!r}, using {default}")
+        return default
+
+
+def batchable():
+    """The batched pipeline splits audio on Silero's speech regions, so only Silero batches."""
+    return engine() == "silero"
+
+
+def transcribe_options(audio):
+    """Keyword arguments for transcribe() that apply the VAD engine to `audio` (16 kHz float32).
+    None when the engine found no speech at all."""
+    selected = engine()
+    if selected == "off":
+        logger.info("VAD disabled")
+        return {"vad_filter": False}
+    if selected == "webrtc":
+        regions = webrtc_regions(audio)
+        if regions is not None:
+            return clip_options(regions, "webrtc")
+        selected = "silero"
+    if selected == "energy":
+        return clip_options(energy_regions(audio), "energy")
+    return {
+        "vad_filter": True,
+        "vad_parameters": dict(
+            threshold=tuning("WA_VAD_SILERO_THRESHOLD", 0.3, float),  # Lower threshold = more sensitive to speech
+            min_silence_duration_ms=tuning("WA_VAD_SILERO_MIN_SILENCE_MS", 300),
+            speech_pad_ms=tuning("WA_VAD_SILERO_SPEECH_PAD_MS", 200),  # More padding around speech
+        ),
+    }
+
+
+def clip_options(regions, name):
+    """Transcribe only the speech regions found by an engine other than Silero."""
+    if not regions:
+        logger.info(f"No speech found by the {name} VAD")
+        return None
+    speech = sum(end - start for start, end in regions)
+    logger.info(f"{name} VAD kept {speech:.1f}s of speech in {len(regions)} region(s)")
+    return {
+        "vad_filter": False,
+        "clip_timestamps": [timestamp for region in regions for timestamp in region],
+    }
+
+
+def webrtc_regions(audio):
+    """Speech regions by WebRTC VAD; None when the webrtcvad package isn't installed."""
+    try:
+        import webrtcvad
+    except ImportError:
+        logger.warning("webrtcvad not installed, using the silero VAD")
+        return None
+    aggressiveness = min(max(tuning("WA_VAD_WEBRTC_AGGRESSIVENESS", 2), 0), 3)
+    frame_ms = tuning("WA_VAD_WEBRTC_FRAME_MS", 30)
+    if frame_ms not in (10, 20, 30):
+        logger.warning(f"WebRTC VAD frames must be 10, 20 or 30 ms, not {frame_ms}; using 30")
+        frame_ms = 30
+    detector = webrtcvad.Vad(aggressiveness)
+    pcm = (numpy.clip(audio, -1.0, 1.0) * 32767).astype(numpy.int16)
+    frame_len = SAMPLE_RATE * frame_ms // 1000
+    voiced = [
+        detector.is_speech(pcm[start:start + frame_len].tobytes(), SAMPLE_RATE)
+        for start in range(0, len(pcm) - frame_len + 1, frame_len)
+    ]
+    return regions(voiced, frame_ms, len(audio),
+                   tuning("WA_VAD_WEBRTC_MIN_SILENCE_MS", 300), tuning("WA_VAD_WEBRTC_SPEECH_PAD_MS", 200))
+
+
+def energy_regions(audio, frame_ms=30):
+    """Speech regions where the frame loudness is above a dBFS threshold."""
+    threshold_db = tuning("WA_VAD_ENERGY_THRESHOLD_DB", -45.0, float)
+    frame_len = SAMPLE_RATE * frame_ms // 1000
+    count = len(audio) // frame_len
+    if count == 0:
+        return []
+    frames = audio[:count * frame_len].reshape(count, frame_len)
+    rms = numpy.sqrt(numpy.mean(numpy.square(frames, dtype=numpy.float64), axis=1))
+    loudness = 20 * numpy.log10(rms + 1e-10)
+    return regions(list(loudness > threshold_db), frame_ms, len(audio),
+                   tuning("WA_VAD_ENERGY_MIN_SILENCE_MS", 300), tuning("WA_VAD_ENERGY_SPEECH_PAD_MS", 200))
+
+
+def regions(voiced, frame_ms, samples, min_silence_ms, speech_pad_ms):
+    """Merge voiced frames into (start, end) regions in seconds: gaps shorter than
+    min_silence_ms are bridged and each region is padded by speech_pad_ms."""
+    duration = samples / SAMPLE_RATE
+    frame = frame_ms / 1000
+    found = []
+    for index, is_speech in enumerate(voiced):
+        if not is_speech:
+            continue
+        start, end = index * frame, (index + 1) * frame
+        if found and start - found[-1][1] < min_silence_ms / 1000:
+            found[-1][1] = end
+        else:
+            found.append([start, end])
+    pad = speech_pad_ms / 1000
+    padded = []
+    for start, end in found:
+        start, end = max(0.0, start - pad), min(duration, end + pad)
+        if padded and start <= padded[-1][1]:
+            padded[-1][1] = end
+        else:
+            padded.append([start, end])
+    return [(round(start, 3), round(end, 3)) for start, end in padded]
@@ -1,17 +1,34 @@
 #!/usr/bin/env python3
 """
 Faster-whisper daemon server that keeps the model loaded in memory.
-Listens on a Unix socket for transcription requests.
+Listens on a Unix socket, and on TCP for remote clients, for transcription requests.
 """
 
 import sys
 import os
+import io
+import base64
+import hmac
 import socket
+import ssl
 import json
+import math
 import signal
+import subprocess
+import time
 import logging
+import queue
+import threading
+import gc
+from collections import OrderedDict
 from pathlib import Path
-from faster_whisper import WhisperModel
+from faster_whisper import WhisperModel, decode_audio
+import vad
+
+try:
+    from faster_whisper import BatchedInferencePipeline
+except ImportError:  # faster-whisper < 1.1
+    BatchedInferencePipeline = None
 
 # Configure logging
 logging.basicConfig(
@@ -20,13 +37,158 @@ logging.basicConfig(
 )
 logger = logging.getLogger(__name__)
 
+# Recordings at least this long are transcribed with the batched pipeline
+BATCH_MIN_SECONDS = 60.0
+
+# Finished jobs kept in the job list
+RECENT_JOBS = 10
+
+# Where the tray reads the daemon's activity (idle, transcribing, error, unloaded)
+ACTIVITY_FILE = os.environ.get("WA_ACTIVITY_FILE")
+
+# How long a failed transcription is reported as an error
+ERROR_DISPLAY_SECS = 10
+
+# How often the state sent to watch subscribers is checked for changes, in seconds
+WATCH_INTERVAL = 0.25
+
+# Where clients on other machines connect ("tcp://host:port" or "tls://host:port"), the
+# token they send with each request, and the certificate for TLS; set by whisp-away from
+# its daemon_listen settings
+DAEMON_LISTEN = os.environ.get("WA_DAEMON_LISTEN")
+DAEMON_TOKEN = os.environ.get("WA_DAEMON_TOKEN", "")
+
+# How long a remote client has for the TLS handshake and its whole request, in seconds
+REMOTE_REQUEST_DEADLINE = 300.0
+
+# The most a remote client may send before its token is checked: about 100 minutes of
+# 16 kHz audio inline (base64 adds a third), with room for the rest of the request
+REMOTE_MAX_REQUEST_BYTES = 256 * 1024 * 1024
+
+# Commands a remote client may send: they only read the daemon's state. Loading models,
+# cancelling jobs and recording are for clients on this machine
+REMOTE_COMMANDS = ("hello", "status", "watch", "jobs")
+
+# Socket protocol version and the commands and request fields handled here, reported to
+# clients in answer to hello so they don't send what this daemon would ignore
+PROTOCOL_VERSION = 1
+CAPABILITIES = [
+    "status", "watch", "jobs", "cancel", "request_id", "unload", "load", "record",
+    "model", "language", "task", "beam_size", "initial_prompt", "words", "compute_type", "batch_size",
+    "stream", "audio_base64", "audio_fd", "label",
+]
+
+# The last activity set, for watch subscribers
+activity_record = {"activity": "idle", "updated": 0}
+
+def set_activity(activity):
+    """Publish the daemon's activity for the tray; failures are not fatal."""
+    global activity_record
+    activity_record = {"activity": activity, "updated": int(time.time())}
+    if not ACTIVITY_FILE:
+        return
+    try:
+        with open(ACTIVITY_FILE, "w") as f:
+            json.dump(activity_record, f)
+    except OSError as e:
+        logger.debug(f"Failed to write activity file: {e}")
+
+def current_activity():
+    """The last activity set, with an error reported as idle once it has been shown long enough."""
+    record = activity_record
+    if record["activity"] == "error" and time.time() - record["updated"] > ERROR_DISPLAY_SECS:
+        return "idle"
+    return record["activity"]
+
+# Approximate float16 footprint (MB) of CTranslate2 Whisper models, checked in order
+MODEL_SIZES_MB = [
+    ("turbo", 1620),
+    ("large", 3090),
+    ("medium", 1530),
+    ("small", 485),
+    ("base", 145),
+    ("tiny", 75),
+]
+
+def estimate_model_mb(model_name, compute_type):
+    """Memory estimate used for LRU eviction: local model directories are measured,
+    known model names looked up; int8 weights take roughly half the space."""
+    if os.path.isdir(model_name):
+        return sum(f.stat().st_size for f in Path(model_name).rglob("*") if f.is_file()) // (1024 * 1024)
+    name = model_name.lower()
+    size = next((mb for key, mb in MODEL_SIZES_MB if key in name), 1000)
+    return size // 2 if compute_type and compute_type.startswith("int8") else size
+
+def models_dir():
+    """Model download root; whisp-away passes its XDG models directory."""
+    return os.environ.get("WA_MODELS_DIR") or os.path.expanduser("~/.cache/faster-whisper")
+
+def segment_json(segment):
+    """A segment as the response lists it, with its words when they were asked for."""
+    result = {"start": segment.start, "end": segment.end, "text": segment.text.strip(),
+              "no_speech_prob": segment.no_speech_prob}
+    if segment.words:
+        result["words"] = [{"word": word.word, "probability": word.probability} for word in segment.words]
+    return result
+
+def remove_audio(path):
+    """Remove a recording; in privacy mode it is overwritten with zeros first, like
+    whisp-away does with its own."""
+    if os.environ.get("WA_PRIVACY") == "true":
+        try:
+            with open(path, "r+b") as f:
+                remaining = os.fstat(f.fileno()).st_size
+                while remaining > 0:
+                    chunk = min(remaining, 64 * 1024)
+                    f.write(bytes(chunk))
+                    remaining -= chunk
+                f.flush()
+                os.fsync(f.fileno())
+        except OSError as e:
+            logger.debug(f"Failed to overwrite {path}: {e}")
+    os.remove(path)
+
+# Listening socket inherited from whisp-away when systemd started it on demand
+LISTEN_FD = os.environ.get("WA_LISTEN_FD")
+
+# Started on demand, exit after this long without a transcription (0 keeps running)
+IDLE_EXIT_SECS = float(os.environ.get("WA_DAEMON_IDLE_EXIT_SECS") or 0)
+
+# Response to a client whose job was cancelled
+CANCELLED = {"success": False, "error": "Cancelled", "cancelled": True}
+
+# Response to a transcription while the models are unloaded
+UNLOADED = {"success": False, "error": "Models are unloaded (whisp-away daemon load)", "unloaded": True}
+
 class WhisperDaemon:
-    def __init__(self, model_name="medium.en", socket_path="/tmp/whisp-away-daemon.sock"):
+    def __init__(self, model_name="medium.en", socket_path="/tmp/whisp-away-daemon.sock", language="en"):
         self.model_name = model_name
+        self.language = language
         self.socket_path = socket_path
-        self.model = None
+        # Resident models, least recently used first: name -> {"model", "batched", "size_mb", "last_used"}
+        self.models = OrderedDict()
+        # Guards self.models: the worker loads models while unload/load requests arrive
+        self.models_lock = threading.RLock()
+        # Models were dropped on request; nothing is loaded until asked to again
+        self.unloaded = False
+        # A model is being loaded
+        self.loading = False
+        self.memory_limit_mb = int(os.environ.get("WA_MODEL_MEMORY_MB", "4096"))
+        self.device = None
+        self.compute_type = None
         self.server_socket = None
         self.running = True
+        # Transcription jobs, run one at a time by the worker thread in order of arrival.
+        # Each is a dict with id, label, model, state, submitted and finished,
+        # plus the client connection while it is waiting
+        self.jobs = []
+        self.jobs_lock = threading.Lock()
+        # (process, path) of the `whisp-away capture` recording for a client
+        self.capture = None
+        self.job_queue = queue.Queue()
+        self.next_job_id = 1
+        # When the last transcription finished, for exiting once idle
+        self.last_transcription = time.monotonic()
         
         # Set up signal handlers
         signal.signal(signal.SIGTERM, self.handle_signal)
@@ -40,105 +202,695 @@ class WhisperDaemon:
             self.server_socket.close()
         sys.exit(0)
         
-    def load_model(self):
-        """Load the Whisper model into memory."""
-        logger.info(f"Loading model {self.model_name}...")
-        
+    def load_model(self, compute_type=None):
+        """Load the default Whisper model into memory."""
         # Determine device and compute type
         # Check WHISPER_DEVICE first, then fall back to CUDA_VISIBLE_DEVICES check
-        device = os.environ.get("WHISPER_DEVICE", "cuda" if os.environ.get("CUDA_VISIBLE_DEVICES") else "cpu")
-        compute_type = os.environ.get("WHISPER_COMPUTE", "int8_float16" if device == "cuda" else "int8")
-        
-        # Model cache directory
-        cache_dir = os.path.expanduser("~/.cache/faster-whisper")
-        os.makedirs(cache_dir, exist_ok=True)
+        self.device = os.environ.get("WHISPER_DEVICE", "cuda" if os.environ.get("CUDA_VISIBLE_DEVICES") else "cpu")
+        if not compute_type:
+            compute_type = os.environ.get("WHISPER_COMPUTE", "int8_float16" if self.device == "cuda" else "int8")
+        self.compute_type = compute_type
         
         try:
-            self.model = WhisperModel(
-                self.model_name,
-                device=device,
-                compute_type=compute_type,
-                download_root=cache_dir,
-                num_workers=2  # Use multiple workers for better performance
-            )
-            logger.info(f"Model loaded successfully on {device}")
+            self.get_model(self.model_name)
+            logger.info(f"Model loaded successfully on {self.device} ({compute_type})")
         except Exception as e:
             logger.error(f"Failed to load model: {e}")
             sys.exit(1)
             
-    def transcribe(self, audio_path):
-        """Transcribe an audio file."""
+    def get_model(self, model_name):
+        """Return a resident model, loading it and unloading the least recently
+        used ones when the memory limit is exceeded. The requested model always stays."""
+        with self.models_lock:
+            return self._get_model(model_name)
+            
+    def _get_model(self, model_name):
+        entry = self.models.get(model_name)
+        if entry is None:
+            logger.info(f"Loading model {model_name}...")
+            # Model download directory (resolved by whisp-away, see paths.rs)
+            cache_dir = models_dir()
+            os.makedirs(cache_dir, exist_ok=True)
+            self.loading = True
+            try:
+                model = WhisperModel(
+                    model_name,
+                    device=self.device,
+                    compute_type=self.compute_type,
+                    download_root=cache_dir,
+                    num_workers=2  # Use multiple workers for better performance
+                )
+            finally:
+                self.loading = False
+            entry = {
+                "model": model,
+                "batched": None,
+                "size_mb": estimate_model_mb(model_name, self.compute_type),
+            }
+            self.models[model_name] = entry
+        self.models.move_to_end(model_name)
+        entry["last_used"] = time.time()
+        
+        while len(self.models) > 1 and sum(e["size_mb"] for e in self.models.values()) > self.memory_limit_mb:
+            evicted, evicted_entry = self.models.popitem(last=False)
+            logger.info(f"Unloading model {evicted} (~{evicted_entry['size_mb']} MB) to stay under {self.memory_limit_mb} MB")
+        return entry
+            
+    def unload(self):
+        """Drop every model to free (video) memory; a running transcription keeps its
+        model until it finishes. Transcriptions are refused until load()."""
+        with self.models_lock:
+            freed = sum(entry["size_mb"] for entry in self.models.values())
+            self.models = OrderedDict()
+            self.unloaded = True
+        # CTranslate2 frees the GPU memory once the model objects are collected
+        gc.collect()
+        logger.info(f"Unloaded all models (~{freed} MB)")
+        if not self.busy():
+            set_activity("unloaded")
+        return self.status()
+            
+    def load(self):
+        """Load the default model again after unload()."""
         try:
-            # Check if VAD should be disabled (for debugging)
-            use_vad = os.environ.get("WHISPER_VAD", "true").lower() != "false"
+            with self.models_lock:
+                self.get_model(self.model_name)
+                self.unloaded = False
+        except Exception as e:
+            logger.error(f"Failed to load model {self.model_name}: {e}")
+            return {"success": False, "error": str(e)}
+        logger.info(f"Loaded model {self.model_name} again")
+        if not self.busy():
+            set_activity("idle")
+        return self.status()
+            
+    def ensure_compute_type(self, compute_type):
+        """Reload the models if a request asks for a different compute type.
+        Keeps the current models if the new compute type is unsupported."""
+        if not compute_type or compute_type == self.compute_type:
+            return
+        with self.models_lock:
+            logger.info(f"Switching compute type {self.compute_type} -> {compute_type}")
+            previous_type, previous_models = self.compute_type, self.models
+            self.compute_type, self.models = compute_type, OrderedDict()
+            try:
+                self.get_model(self.model_name)
+            except Exception as e:
+                logger.error(f"Failed to switch compute type, keeping {previous_type}: {e}")
+                self.compute_type, self.models = previous_type, previous_models
+
+    def reload(self, request):
+        """Take new defaults after the config file changed, without a restart.
+        Jobs already running finish with the model they started with; if the new
+        model can't be loaded, nothing changes."""
+        model_name = request.get('model') or self.model_name
+        compute_type = request.get('compute_type') or self.compute_type
+        with self.models_lock:
+            previous = (self.model_name, self.compute_type, self.models)
+            if compute_type != self.compute_type:
+                logger.info(f"Switching compute type {self.compute_type} -> {compute_type}")
+                self.compute_type, self.models = compute_type, OrderedDict()
+            self.model_name = model_name
+            try:
+                if not self.unloaded:
+                    self.get_model(model_name)
+            except Exception as e:
+                logger.error(f"Failed to load model {model_name}, keeping {previous[0]}: {e}")
+                self.model_name, self.compute_type, self.models = previous
+                return {"success": False, "error": str(e)}
+        self.language = request.get('language', self.language)
+        logger.info(f"Reloaded settings: model {self.model_name}, language {self.language}, compute type {self.compute_type}")
+        return self.status()
+            
+    def status(self):
+        """Loaded models, most recently used first."""
+        now = time.time()
+        return {
+            "success": True,
+            "backend": "faster-whisper",
+            "default_model": self.model_name,
+            "compute_type": self.compute_type,
+            "memory_limit_mb": self.memory_limit_mb,
+            "unloaded": self.unloaded,
+            "recording": self.capture is not None,
+            "models": [
+                {"name": name, "size_mb": entry["size_mb"], "idle_secs": int(now - entry["last_used"])}
+                for name, entry in reversed(list(self.models.items()))
+            ],
+        }
+            
+    def watch(self, conn):
+        """Send the daemon's state to a watch subscriber (the tray) as a JSON line, then again
+        whenever it changes, until the subscriber disconnects."""
+        logger.debug("Client subscribed to the daemon's state")
+        last = None
+        try:
+            while self.running:
+                state = dict(self.status())
+                state["state"] = "loading" if self.loading else current_activity()
+                state["jobs"] = self.job_list()
+                line = json.dumps(state)
+                # Idle times tick on their own, so they alone don't send a line
+                compared = json.dumps(dict(state, models=[dict(m, idle_secs=0) for m in state.get("models", [])]))
+                if compared != last:
+                    conn.sendall((line + "\n").encode('utf-8'))
+                    last = compared
+                time.sleep(WATCH_INTERVAL)
+        except OSError as e:
+            logger.debug(f"State subscriber went away: {e}")
+        finally:
+            conn.close()
+            
+    def default_batch_size(self):
+        """Batch size used when the request doesn't set one - batching only pays off on GPU."""
+        batch_size = os.environ.get("WHISPER_BATCH_SIZE")
+        if batch_size:
+            return int(batch_size)
+        return 8 if self.device == "cuda" else 1
+            
+    def batched_pipeline(self, entry):
+        """Batched pipeline sharing a loaded model, created on first use."""
+        if BatchedInferencePipeline is None:
+            return None
+        if entry["batched"] is None:
+            entry["batched"] = BatchedInferencePipeline(model=entry["model"])
+        return entry["batched"]
+            
+    def transcribe(self, audio_path, batch_size=None, language=None, task=None, model_name=None, on_segment=None, beam_size=None, initial_prompt=None, aborted=None, words=False):
+        """Transcribe an audio file; language, task, model, beam size and initial prompt override
+        the daemon's defaults. on_segment is called with each segment as soon as it is decoded.
+        Decoding stops after the segment in progress once aborted() is true. With words, each
+        segment lists its words and their probabilities."""
+        try:
+            model_name = model_name or self.model_name
+            entry = self.get_model(model_name)
             
             transcribe_kwargs = dict(
-                language="en",
+                language=language or self.language,
+                task=task or os.environ.get("WA_WHISPER_TASK", "transcribe"),
                 beam_size=5,
                 best_of=5,
                 temperature=0.0,
             )
             
-            if use_vad:
-                transcribe_kwargs["vad_filter"] = True
-                transcribe_kwargs["vad_parameters"] = dict(
-                    min_silence_duration_ms=300,
-                    speech_pad_ms=200,  # More padding around speech
-                    threshold=0.3,  # Lower threshold = more sensitive to speech
-                )
-            else:
-                logger.info("VAD disabled")
-                transcribe_kwargs["vad_filter"] = False
+            audio = decode_audio(audio_path)
+            duration = len(audio) / 16000
+            batch_size = batch_size or self.default_batch_size()
+            vad_options = vad.transcribe_options(audio)
+            
+            # Quick commands: greedy decoding instead of beam search and sampling, so a few
+            # seconds of audio come back in well under a second
+            if duration < float(os.environ.get("WA_FAST_PATH_SECS", "5")):
+                logger.info(f"{duration:.1f}s of audio, transcribing with the fast path")
+                transcribe_kwargs.update(beam_size=1, best_of=1, condition_on_previous_text=False)
+            # Asked for explicitly, e.g. to retranscribe a low-confidence first pass
+            if beam_size:
+                transcribe_kwargs.update(beam_size=beam_size, best_of=beam_size)
+            if initial_prompt:
+                transcribe_kwargs["initial_prompt"] = initial_prompt
+            # Aligning words to the audio costs a little time, so only when asked
+            if words:
+                transcribe_kwargs["word_timestamps"] = True
             
-            segments, info = self.model.transcribe(audio_path, **transcribe_kwargs)
+            # Batching splits the audio on Silero's speech regions, so it needs Silero and enough audio to fill batches
+            pipeline = None
+            if batch_size > 1 and vad.batchable() and duration >= BATCH_MIN_SECONDS:
+                pipeline = self.batched_pipeline(entry)
+                if pipeline is None:
+                    logger.info("BatchedInferencePipeline unavailable (faster-whisper < 1.1)")
             
-            # Collect text
+            started = time.perf_counter()
+            if vad_options is None:
+                # Nothing but silence or noise: no text, without running the model
+                segments, info = [], None
+            elif pipeline is not None:
+                segments, info = pipeline.transcribe(audio, batch_size=batch_size, **transcribe_kwargs, **vad_options)
+            else:
+                segments, info = entry["model"].transcribe(audio, **transcribe_kwargs, **vad_options)
+            
+            # Collect text and confidence signals (segments are decoded lazily, so time this too)
+            decoded = []
+            for segment in segments:
+                if aborted is not None and aborted():
+                    # Leaving the generator stops CTranslate2 before the next window
+                    logger.info(f"Transcription aborted after {len(decoded)} segments")
+                    return CANCELLED
+                decoded.append(segment)
+                if on_segment is not None:
+                    on_segment({"start": segment.start, "end": segment.end, "text": segment.text.strip(),
+                                "no_speech_prob": segment.no_speech_prob})
+            segments = decoded
+            inference_secs = time.perf_counter() - started
+            logger.info(f"Transcribed {duration:.1f}s of audio in {inference_secs:.2f}s (batched: {pipeline is not None}, batch size: {batch_size})")
             text = " ".join(segment.text.strip() for segment in segments)
-            return {"success": True, "text": text}
+            response = {
+                "success": True,
+                "text": text,
+                "segments": [segment_json(segment) for segment in segments],
+                "language": info.language if info is not None else transcribe_kwargs["language"],
+                "model": model_name,
+                "duration": duration,
+                "inference_secs": inference_secs,
+                "rtf": inference_secs / duration if duration > 0 else None,
+                "batched": pipeline is not None,
+                "batch_size": batch_size,
+            }
+            
+            if segments:
+                avg_logprob = sum(s.avg_logprob for s in segments) / len(segments)
+                response["avg_logprob"] = avg_logprob
+                response["confidence"] = math.exp(avg_logprob)
+                response["no_speech_prob"] = max(s.no_speech_prob for s in segments)
+            
+            return response
             
         except Exception as e:
             logger.error(f"Transcription error: {e}")
             return {"success": False, "error": str(e)}
             
+    def submit_job(self, request, conn):
+        """Queue a transcription request; its client waits on conn for the result."""
+        label = request.get('label')
+        if not label:
+            label = "inline audio" if request.get('audio_base64') else os.path.basename(request.get('audio_path') or "") or "?"
+        with self.jobs_lock:
+            job = {
+                "id": self.next_job_id,
+                "label": label,
+                "model": request.get('model') or self.model_name,
+                "state": "pending",
+                "submitted": int(time.time()),
+                "conn": conn,
+            }
+            if request.get('request_id'):
+                job["request_id"] = request['request_id']
+            self.next_job_id += 1
+            self.jobs.append(job)
+        self.job_queue.put((job, request))
+            
+    def job_list(self):
+        """Jobs without their connections, oldest first."""
+        with self.jobs_lock:
+            return [{k: v for k, v in job.items() if k != "conn"} for job in self.jobs]
+            
+    def finish_job(self, job, state):
+        """Record a job's outcome and keep only the most recent finished jobs. Call with jobs_lock held."""
+        job["state"] = state
+        job["finished"] = int(time.time())
+        job.pop("conn", None)
+        finished = [j for j in self.jobs if j["state"] not in ("pending", "running")]
+        for old in finished[:max(0, len(finished) - RECENT_JOBS)]:
+            self.jobs.remove(old)
+            
+    def cancel_job(self, job_id, request_id=None):
+        """Cancel a pending job, answering its client right away, or abort the running one,
+        which stops decoding after its current segment. The job is found by id, or by the
+        request_id its client gave it."""
+        with self.jobs_lock:
+            if job_id is not None:
+                job = next((j for j in self.jobs if j["id"] == job_id), None)
+                missing = f"No job {job_id}"
+            else:
+                job = next((j for j in reversed(self.jobs) if request_id and j.get("request_id") == request_id), None)
+                missing = f"No job for request {request_id}" if request_id else "No job given"
+            if job is None:
+                return {"success": False, "error": missing}
+            job_id = job["id"]
+            if job["state"] not in ("pending", "running"):
+                return {"success": False, "error": f"Job {job_id} already finished"}
+            logger.info(f"Cancelling {job['state']} job {job_id} ({job['label']})")
+            if job["state"] == "pending":
+                self.reply(job.pop("conn"), CANCELLED)
+            self.finish_job(job, "cancelled")
+        return {"success": True}
+            
+    def busy(self):
+        with self.jobs_lock:
+            return any(j["state"] in ("pending", "running") for j in self.jobs)
+            
+    def reply(self, conn, response):
+        """Send a response and close the connection; the client may have gone away."""
+        try:
+            conn.sendall(json.dumps(response).encode('utf-8'))
+        except OSError as e:
+            logger.debug(f"Failed to send response: {e}")
+        finally:
+            conn.close()
+            
+    def worker(self):
+        """Run queued transcriptions one at a time."""
+        while self.running:
+            job, request = self.job_queue.get()
+            with self.jobs_lock:
+                # Cancelled while pending (its client was already answered)
+                if job["state"] != "pending":
+                    self.release_audio(request)
+                    continue
+                job["state"] = "running"
+                conn = job.pop("conn")
+            
+            set_activity("transcribing")
+            on_segment = None
+            if request.get('stream'):
+                def on_segment(segment):
+                    # One line per segment ahead of the response; the client may have gone away
+                    try:
+                        conn.sendall((json.dumps({"segment": segment}) + "\n").encode('utf-8'))
+                    except OSError as e:
+                        logger.debug(f"Failed to send segment: {e}")
+            try:
+                response = self.handle_transcription(request, on_segment, lambda: job["state"] == "cancelled")
+            except Exception as e:
+                # A bad request (say, broken inline audio) fails its own job, not the worker
+                logger.exception(f"Job {job['id']} failed")
+                response = {"success": False, "error": f"Transcription failed: {e}"}
+            finally:
+                self.release_audio(request)
+            with self.jobs_lock:
+                cancelled = job["state"] == "cancelled"
+                if cancelled:
+                    logger.info(f"Job {job['id']} was cancelled while running, discarding its result")
+                    response = CANCELLED
+                else:
+                    self.finish_job(job, "done" if response.get("success") else "failed")
+            if self.busy():
+                set_activity("transcribing")
+            elif self.unloaded:
+                set_activity("unloaded")
+            else:
+                set_activity("idle" if response.get("success") or cancelled else "error")
+            self.reply(conn, response)
+            self.last_transcription = time.monotonic()
+            
+    def handle_transcription(self, request, on_segment=None, aborted=None):
+        """Transcribe the audio of one request."""
+        audio_path = request.get('audio_path')
+        if request.get('_audio_fd') is not None:
+            # The client passed the audio itself, open in memory or on disk
+            audio_path = f"/proc/self/fd/{request['_audio_fd']}"
+        elif request.get('audio_base64'):
+            # Inline audio from a client that doesn't share our filesystem
+            audio_path = io.BytesIO(base64.b64decode(request['audio_base64']))
+            logger.info(f"Received {len(audio_path.getbuffer())} bytes of inline audio")
+        
+        if not audio_path or (isinstance(audio_path, str) and not os.path.exists(audio_path)):
+            return {"success": False, "error": "Invalid audio path"}
+        if self.unloaded:
+            logger.info("Models are unloaded, refusing the transcription")
+            return UNLOADED
+        
+        self.ensure_compute_type(request.get('compute_type'))
+        return self.transcribe(
+            audio_path,
+            request.get('batch_size'),
+            request.get('language'),
+            request.get('task'),
+            request.get('model'),
+            on_segment,
+            request.get('beam_size'),
+            request.get('initial_prompt'),
+            aborted,
+            bool(request.get('words')),
+        )
+            
+    def read_request(self, conn, deadline=None):
+        """Read one JSON request. Inline audio spans many reads; older clients keep
+        the connection open, so stop as soon as the JSON is complete. An audio file
+        descriptor arrives with the first read over the Unix socket and is kept in the
+        request's "_audio_fd". A remote client's request is read before its token is
+        checked, so it must arrive whole by `deadline` (a time.monotonic() value) and
+        within REMOTE_MAX_REQUEST_BYTES."""
+        def recv():
+            if deadline is not None:
+                left = deadline - time.monotonic()
+                if left <= 0:
+                    raise TimeoutError("the client took too long to send its request")
+                conn.settimeout(left)
+            return conn.recv(65536)
+
+        if conn.family == socket.AF_UNIX:
+            chunk, fds, _, _ = socket.recv_fds(conn, 65536, 1)
+        else:
+            chunk, fds = recv(), []
+        data = bytearray(chunk)
+        request = None
+        while chunk:
+            if deadline is not None and len(data) > REMOTE_MAX_REQUEST_BYTES:
+                raise ValueError(f"Request larger than {REMOTE_MAX_REQUEST_BYTES // (1024 * 1024)} MB")
+            if data.rstrip().endswith(b"}"):
+                try:
+                    request = json.loads(data)
+                    break
+                except ValueError:
+                    pass
+            chunk = recv()
+            data += chunk
+        if request is None and data:
+            try:
+                request = json.loads(data)
+            except ValueError:
+                for fd in fds:
+                    os.close(fd)
+                raise
+        if request is not None:
+            # Fields starting with "_" are the daemon's own
+            for key in [key for key in request if key.startswith('_')]:
+                del request[key]
+        if request is not None and request.get('audio_fd') and fds:
+            request['_audio_fd'] = fds.pop(0)
+        for fd in fds:
+            os.close(fd)
+        return request
+
+    @staticmethod
+    def release_audio(request):
+        """Close the audio file descriptor a request came with, if any, and remove a
+        recording made for it."""
+        fd = request.pop('_audio_fd', None)
+        if fd is not None:
+            os.close(fd)
+        if request.pop('_remove_audio', False):
+            try:
+                remove_audio(request['audio_path'])
+            except OSError:
+                pass
+
+    def start_recording(self, request):
+        """Record for a client until "stop_and_transcribe", with `whisp-away capture`
+        (which prints the file it records to)."""
+        with self.jobs_lock:
+            if self.capture is not None:
+                return {"success": False, "recording": False, "error": "Already recording"}
+            command = [os.environ.get("WA_EXE", "whisp-away"), "capture"]
+            if request.get('source'):
+                command += ["--source", request['source']]
+            try:
+                process = subprocess.Popen(command, stdout=subprocess.PIPE, text=True)
+            except OSError as e:
+                return {"success": False, "recording": False, "error": f"Could not start recording: {e}"}
+            path = process.stdout.readline().strip()
+            if not path:
+                process.wait()
+                return {"success": False, "recording": False, "error": "Recorder failed to start"}
+            self.capture = (process, path)
+        logger.info(f"Recording for a client into {path}")
+        return {"success": True, "recording": True}
+
+    def stop_recording(self):
+        """Stop the client's recording: (path, None), or (None, error response)."""
+        with self.jobs_lock:
+            capture, self.capture = self.capture, None
+        if capture is None:
+            return None, {"success": False, "error": "Not recording"}
+        process, path = capture
+        process.send_signal(signal.SIGINT)
+        try:
+            code = process.wait(timeout=30)
+        except subprocess.TimeoutExpired:
+            process.kill()
+            code = None
+        if code != 0 or not os.path.exists(path):
+            try:
+                remove_audio(path)
+            except OSError:
+                pass
+            return None, {"success": False, "error": "No audio recorded"}
+        return path, None
+            
+    def warm_up(self):
+        """Transcribe a second of silence before taking requests, so the backend's
+        one-time setup (CUDA kernels, buffers) isn't paid for by the first dictation."""
+        started = time.monotonic()
+        try:
+            import numpy
+            segments, _ = self.get_model(self.model_name)["model"].transcribe(
+                numpy.zeros(16000, dtype=numpy.float32), language=self.language or None, beam_size=1)
+            list(segments)
+            logger.info(f"Warmed up in {time.monotonic() - started:.2f}s")
+        except Exception as e:
+            logger.warning(f"Warm-up failed: {e}")
+            
+    def idle(self):
+        """Whether a daemon started on demand has gone without transcriptions long enough to exit."""
+        return IDLE_EXIT_SECS > 0 and not self.busy() and time.monotonic() - self.last_transcription >= IDLE_EXIT_SECS
+            
+    def dispatch(self, conn, request):
+        """Answer a command, or queue a transcription."""
+        command = request.get('command')
+        if command:
+            self.release_audio(request)
+        if command == 'hello':
+            self.reply(conn, {
+                "success": True,
+                "protocol": PROTOCOL_VERSION,
+                "version": os.environ.get("WA_VERSION"),
+                "capabilities": CAPABILITIES,
+            })
+        elif command == 'status':
+            self.reply(conn, self.status())
+        elif command == 'watch':
+            threading.Thread(target=self.watch, args=(conn,), daemon=True).start()
+        elif command == 'jobs':
+            self.reply(conn, {"success": True, "jobs": self.job_list()})
+        elif command == 'cancel':
+            self.reply(conn, self.cancel_job(request.get('job'), request.get('request_id')))
+        elif command == 'unload':
+            self.reply(conn, self.unload())
+        elif command == 'load':
+            self.reply(conn, self.load())
+        elif command == 'reload':
+            self.reply(conn, self.reload(request))
+        elif command == 'start_recording':
+            self.reply(conn, self.start_recording(request))
+        elif command == 'stop_and_transcribe':
+            path, error = self.stop_recording()
+            if error:
+                self.reply(conn, error)
+            else:
+                request['audio_path'] = path
+                request['_remove_audio'] = True
+                self.submit_job(request, conn)
+        else:
+            self.submit_job(request, conn)
+
+    def serve_remote(self):
+        """Take requests from other machines on DAEMON_LISTEN, each with DAEMON_TOKEN."""
+        scheme, _, address = DAEMON_LISTEN.partition("://")
+        host, _, port = address.rpartition(":")
+        host = host.strip("[]")
+        try:
+            context = None
+            if scheme == "tls":
+                context = ssl.SSLContext(ssl.PROTOCOL_TLS_SERVER)
+                context.load_cert_chain(os.environ["WA_DAEMON_TLS_CERT"], os.environ["WA_DAEMON_TLS_KEY"])
+            listener = socket.create_server((host, int(port)), family=socket.AF_INET6 if ":" in host else socket.AF_INET)
+        except (OSError, ValueError, KeyError) as e:
+            logger.error(f"Not listening on {DAEMON_LISTEN}: {e}")
+            return
+        if context is None:
+            logger.warning(f"Listening on {DAEMON_LISTEN} without TLS: requests and transcripts travel unencrypted")
+        logger.info(f"Daemon listening for remote clients on {DAEMON_LISTEN}")
+        while self.running:
+            try:
+                conn, peer = listener.accept()
+            except OSError as e:
+                logger.error(f"Error accepting remote connection: {e}")
+                continue
+            threading.Thread(target=self.handle_remote, args=(conn, peer[0], context), daemon=True).start()
+
+    def handle_remote(self, conn, peer, context):
+        """Read a remote client's request and answer it if it carries the token."""
+        deadline = time.monotonic() + REMOTE_REQUEST_DEADLINE
+        try:
+            conn.settimeout(REMOTE_REQUEST_DEADLINE)
+            if context is not None:
+                conn = context.wrap_socket(conn, server_side=True)
+            request = self.read_request(conn, deadline)
+            conn.settimeout(None)
+        except (OSError, ValueError) as e:
+            logger.warning(f"Remote client {peer}: {e}")
+            try:
+                conn.sendall(json.dumps({"success": False, "error": str(e)}).encode('utf-8'))
+            except OSError:
+                pass
+            conn.close()
+            return
+        if request is None:
+            conn.close()
+            return
+        token = str(request.pop('token', ''))
+        if not hmac.compare_digest(token.encode('utf-8'), DAEMON_TOKEN.encode('utf-8')):
+            logger.warning(f"Refusing a request from {peer} with a wrong or missing token")
+            self.reply(conn, {"success": False, "error": "Invalid daemon token"})
+            return
+        refusal = self.remote_refusal(request)
+        if refusal:
+            logger.warning(f"Refusing a request from {peer}: {refusal}")
+            self.reply(conn, {"success": False, "error": refusal})
+            return
+        self.dispatch(conn, request)
+
+    @staticmethod
+    def remote_refusal(request):
+        """Why a remote client's request is refused: it can transcribe audio it sends
+        inline, but not reach this machine's files or microphone."""
+        command = request.get('command')
+        if command:
+            if command in REMOTE_COMMANDS:
+                return None
+            return f"'{command}' is only available on the daemon's machine"
+        if request.get('audio_fd') or not request.get('audio_base64'):
+            return "Remote requests must send their audio inline"
+        return None
+
     def start_server(self):
         """Start the Unix socket server."""
-        # Remove existing socket if it exists
-        if os.path.exists(self.socket_path):
-            os.unlink(self.socket_path)
-            
-        # Create Unix socket
-        self.server_socket = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
-        self.server_socket.bind(self.socket_path)
-        self.server_socket.listen(1)
-        
-        # Set socket permissions
-        os.chmod(self.socket_path, 0o666)
+        if LISTEN_FD:
+            # systemd's socket: clients are already waiting on it
+            self.server_socket = socket.socket(fileno=int(LISTEN_FD))
+            logger.info(f"Daemon started on demand, listening on inherited socket {LISTEN_FD}")
+        else:
+            # Remove existing socket if it exists
+            if os.path.exists(self.socket_path):
+                os.unlink(self.socket_path)
+                
+            # Create Unix socket
+            self.server_socket = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+            self.server_socket.bind(self.socket_path)
+            self.server_socket.listen(1)
+            
+            # Set socket permissions
+            os.chmod(self.socket_path, 0o666)
+            
+            logger.info(f"Daemon listening on {self.socket_path}")
+        if IDLE_EXIT_SECS > 0:
+            # Wake up now and then to check for idleness
+            self.server_socket.settimeout(min(60.0, max(1.0, IDLE_EXIT_SECS / 10)))
+            logger.info(f"Exiting after {IDLE_EXIT_SECS / 60:.0f} min without a transcription")
         
-        logger.info(f"Daemon listening on {self.socket_path}")
+        # Status and job requests are answered here while the worker transcribes
+        threading.Thread(target=self.worker, daemon=True).start()
+        if DAEMON_LISTEN:
+            threading.Thread(target=self.serve_remote, daemon=True).start()
         
         while self.running:
             try:
                 # Accept connections
-                conn, _ = self.server_socket.accept()
+                try:
+                    conn, _ = self.server_socket.accept()
+                except socket.timeout:
+                    if self.idle():
+                        logger.info("No transcription for a while, exiting until systemd starts the daemon again")
+                        return
+                    continue
                 
                 # Receive request
-                data = conn.recv(4096).decode('utf-8')
-                if not data:
+                request = self.read_request(conn)
+                if request is None:
                     conn.close()
                     continue
                     
-                request = json.loads(data)
-                audio_path = request.get('audio_path')
-                
-                if not audio_path or not os.path.exists(audio_path):
-                    response = {"success": False, "error": "Invalid audio path"}
-                else:
-                    # Transcribe
-                    response = self.transcribe(audio_path)
-                    
-                # Send response
-                conn.send(json.dumps(response).encode('utf-8'))
-                conn.close()
+                self.dispatch(conn, request)
                 
             except socket.error as e:
                 if self.running:
@@ -152,6 +904,9 @@ class WhisperDaemon:
         
         # Load model
         self.load_model()
+        self.warm_up()
+        # Clears an unloaded state left by a previous daemon
+        set_activity("idle")
         
         # Start server
         self.start_server()
@@ -160,9 +915,10 @@ def main():
     # Get model from environment or use default
     model_name = os.environ.get("WA_WHISPER_MODEL", "medium.en")
     socket_path = os.environ.get("WA_WHISPER_SOCKET", "/tmp/whisp-away-daemon.sock")
+    language = os.environ.get("WA_WHISPER_LANGUAGE", "en")
     
     # Create and run daemon
-    daemon = WhisperDaemon(model_name, socket_path)
+    daemon = WhisperDaemon(model_name, socket_path, language)
     daemon.run()
 
 if __name__ == "__main__":
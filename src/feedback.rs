@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, trace};
+use crate::paths;
+use crate::platform::{self, Platform};
+use crate::privacy;
+use crate::settings::{self, Layers, Resolved};
+
+/// A notification shown in the tray tooltip when there is no notification server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Feedback {
+    pub title: String,
+    pub message: String,
+    /// Unix time (ms) after which the tray stops showing it
+    pub expires: u64,
+}
+
+/// Path of the feedback file (written by every command, read by the tray)
+fn feedback_path() -> String {
+    format!("{}/whisp-away-feedback.json", paths::runtime_dir())
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Resolves how feedback is delivered:
+/// 1. WA_NOTIFICATIONS env var
+/// 2. User config file `notifications` (or `[notifications] mode`)
+/// 3. Default to "auto"
+///
+/// - "desktop": always notify-send
+/// - "tray": tray tooltip and the OSD command, never notify-send
+/// - "none": no feedback
+/// - "auto": notify-send when a notification server is on the session bus, otherwise as "tray"
+pub fn mode_setting() -> Resolved<String> {
+    Layers::new("notifications")
+        .env("WA_NOTIFICATIONS", Some)
+        .config(|c| c.notifications.and_then(|n| n.mode()))
+        .or_default("auto".to_string())
+}
+
+/// Resolves whether the result notification shows the start of the transcript with priority:
+/// 1. WA_NOTIFICATION_PREVIEW env var
+/// 2. User config file `[notifications] preview`
+/// 3. Default to false
+pub fn preview_setting() -> Resolved<bool> {
+    Layers::new("notification preview")
+        .env("WA_NOTIFICATION_PREVIEW", settings::flag)
+        .config(|c| c.notifications.and_then(|n| n.preview()))
+        .or_default(false)
+}
+
+/// Resolves whether notifications are also spoken, for screen reader users, with priority:
+/// 1. WA_ANNOUNCE env var
+/// 2. User config file `[notifications] announce`
+/// 3. Default to false
+pub fn announce_setting() -> Resolved<bool> {
+    Layers::new("announce")
+        .env("WA_ANNOUNCE", settings::flag)
+        .config(|c| c.notifications.and_then(|n| n.announce()))
+        .or_default(false)
+}
+
+/// Resolves the on-screen display command (WA_OSD_COMMAND env var, then config `osd_command`)
+pub fn osd_command_setting() -> Resolved<Option<String>> {
+    let set = |command: String| Some(command).filter(|command| !command.trim().is_empty());
+    Layers::new("OSD command")
+        .env("WA_OSD_COMMAND", set)
+        .config(|c| c.osd_command.and_then(set))
+        .optional()
+}
+
+/// Bus names from an org.freedesktop.DBus method, or None when dbus-send can't be run
+fn bus_names(method: &str) -> Option<String> {
+    let output = Command::new("dbus-send")
+        .args([
+            "--session",
+            "--dest=org.freedesktop.DBus",
+            "--type=method_call",
+            "--print-reply",
+            "/org/freedesktop/DBus",
+            &format!("org.freedesktop.DBus.{}", method),
+        ])
+        .output()
+        .ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Whether org.freedesktop.Notifications is running or D-Bus activatable (checked once per process)
+/// Without dbus-send we can't tell, so notify-send keeps being used as before
+fn notification_server_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        let names: Vec<String> = ["ListNames", "ListActivatableNames"]
+            .iter()
+            .filter_map(|method| bus_names(method))
+            .collect();
+        let available = names.is_empty()
+            || names.iter().any(|n| n.contains("\"org.freedesktop.Notifications\""));
+        debug!("Notification server available: {}", available);
+        available
+    })
+}
+
+/// Deliver feedback as a desktop notification, or through the tray/OSD fallback on minimal setups
+pub fn notify(title: &str, message: &str, timeout_ms: u32) {
+    // Spoken whatever the mode, as it is no use to someone who can't see it anyway
+    if announce_setting().value {
+        announce(message);
+    }
+    let mode = mode_setting().value;
+    let desktop = match mode.as_str() {
+        "none" => return,
+        "desktop" => true,
+        "tray" => false,
+        _ => notification_server_available(),
+    };
+
+    if desktop && platform::Current::notify(title, message, timeout_ms) {
+        return;
+    }
+    publish(title, message, timeout_ms);
+    show_osd(message);
+    // Also visible when run from a terminal
+    eprintln!("[whisp-away] {}: {}", title, message);
+}
+
+/// Speak the first line of a message without its emoji, e.g. "Transcription failed";
+/// failures interrupt whatever is being said
+fn announce(message: &str) {
+    let first_line = message.lines().next().unwrap_or_default();
+    let text = first_line.trim_start_matches(|c: char| !c.is_alphanumeric()).trim_end();
+    if text.is_empty() {
+        return;
+    }
+    if !platform::Current::speak(text, first_line.starts_with('❌')) {
+        debug!("Nothing could announce: {}", privacy::redact(text));
+    }
+}
+
+/// Hand the message to the tray, which shows it in its tooltip until it expires
+fn publish(title: &str, message: &str, timeout_ms: u32) {
+    let feedback = Feedback {
+        title: title.to_string(),
+        message: message.to_string(),
+        expires: now_millis() + timeout_ms as u64,
+    };
+    let result = serde_json::to_string(&feedback)
+        .map_err(std::io::Error::other)
+        .and_then(|json| std::fs::write(feedback_path(), json));
+    if let Err(e) = result {
+        debug!("Failed to write feedback file: {}", e);
+    }
+}
+
+/// Run the OSD command with the first line of the message appended as its last argument
+fn show_osd(message: &str) {
+    let Some(command) = osd_command_setting().value else {
+        return;
+    };
+    let mut parts = command.split_whitespace();
+    let Some(program) = parts.next() else {
+        return;
+    };
+    let text = message.lines().next().unwrap_or_default();
+    // Don't wait: an overlay may stay up for its whole display time
+    match Command::new(program)
+        .args(parts)
+        .arg(text)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()
+    {
+        Ok(mut child) => {
+            trace!("OSD shown: {}", privacy::redact(text));
+            // Reap it in the background so long-running daemons don't collect zombies
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => debug!("Failed to run OSD command '{}': {}", command, e),
+    }
+}
+
+/// Feedback the tray should currently show, if any
+#[cfg(target_os = "linux")]
+#[cfg(feature = "tray")]
+pub fn current() -> Option<Feedback> {
+    let content = std::fs::read_to_string(feedback_path()).ok()?;
+    let feedback: Feedback = serde_json::from_str(&content).ok()?;
+    (feedback.expires > now_millis()).then_some(feedback)
+}
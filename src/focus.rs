@@ -0,0 +1,181 @@
+use regex::Regex;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+use crate::helpers;
+use crate::keyboard::command_stdout;
+use crate::settings::{self, Layers, Resolved};
+
+/// How often the focused window is checked while waiting
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Time for the compositor to settle after a focus change (e.g. releasing alt-tab)
+const SETTLE_DELAY: Duration = Duration::from_millis(150);
+
+/// How a window talks to the display server, which decides the tool that can type into it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Wayland,
+    /// An X11 client of a Wayland compositor
+    XWayland,
+    X11,
+}
+
+/// The focused window as reported by the compositor or X server
+#[derive(Debug, Clone)]
+pub struct Window {
+    /// Wayland app id, or WM_CLASS on X11
+    pub app_id: String,
+    pub title: String,
+    /// None when the compositor doesn't say (niri runs X11 apps through xwayland-satellite,
+    /// which it sees as a Wayland client)
+    pub protocol: Option<Protocol>,
+}
+
+/// Resolves the window to wait for before typing with priority:
+/// 1. CLI flag (--wait-for-focus) or profile
+/// 2. WA_WAIT_FOR_FOCUS env var
+/// 3. None: type into whatever window is focused
+pub fn target_setting() -> Resolved<Option<String>> {
+    Layers::new("focus target")
+        .cli(helpers::overrides().wait_for_focus.clone())
+        .env("WA_WAIT_FOR_FOCUS", Some)
+        .optional()
+}
+
+/// Resolves how long to wait for the target window with priority:
+/// 1. WA_FOCUS_TIMEOUT_SECS env var
+/// 2. User config file `focus_timeout_secs`
+/// 3. Default to 30
+pub fn timeout_secs_setting() -> Resolved<u64> {
+    Layers::new("focus timeout (s)")
+        .env("WA_FOCUS_TIMEOUT_SECS", settings::parsed)
+        .config(|c| c.focus_timeout_secs)
+        .or_default(30)
+}
+
+/// Check a --wait-for-focus pattern when the flag is parsed
+pub fn parse_pattern(pattern: &str) -> Result<String, String> {
+    Regex::new(pattern)
+        .map(|_| pattern.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Detect the focused window, trying Hyprland, Sway, niri and X11 in turn
+pub fn focused_window() -> Option<Window> {
+    hyprland_window()
+        .or_else(sway_window)
+        .or_else(niri_window)
+        .or_else(x11_window)
+}
+
+/// Wait until the --wait-for-focus window is focused, matching the pattern against
+/// its app id and title
+/// True right away without a target or when focus can't be detected; false on timeout
+pub fn wait_for_target() -> bool {
+    let Some(pattern) = target_setting().value else {
+        return true;
+    };
+    let target = match Regex::new(&pattern) {
+        Ok(target) => target,
+        Err(e) => {
+            warn!("Ignoring invalid focus target '{}': {}", pattern, e);
+            return true;
+        }
+    };
+
+    let timeout = Duration::from_secs(timeout_secs_setting().value);
+    let deadline = Instant::now() + timeout;
+    let mut waiting = false;
+    loop {
+        match focused_window() {
+            Some(window) if target.is_match(&window.app_id) || target.is_match(&window.title) => {
+                debug!("Target window focused: {:?}", window);
+                if waiting {
+                    std::thread::sleep(SETTLE_DELAY);
+                }
+                return true;
+            }
+            Some(window) => debug!("Waiting for '{}', focused: {:?}", pattern, window),
+            None if !waiting => {
+                warn!("Can't detect the focused window, typing without waiting for '{}'", pattern);
+                return true;
+            }
+            None => {}
+        }
+
+        if Instant::now() >= deadline {
+            debug!("'{}' not focused within {}s", pattern, timeout.as_secs());
+            return false;
+        }
+        if !waiting {
+            helpers::send_notification(
+                "Voice Input",
+                &format!("⏳ Waiting for {} to be focused...", pattern),
+                timeout.as_millis() as u32
+            );
+            waiting = true;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn hyprland_window() -> Option<Window> {
+    std::env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
+    let json: serde_json::Value = serde_json::from_str(&command_stdout("hyprctl", &["activewindow", "-j"])?).ok()?;
+    Some(Window {
+        app_id: json.get("class")?.as_str()?.to_string(),
+        title: json.get("title").and_then(|t| t.as_str()).unwrap_or_default().to_string(),
+        protocol: json.get("xwayland").and_then(|x| x.as_bool())
+            .map(|xwayland| if xwayland { Protocol::XWayland } else { Protocol::Wayland }),
+    })
+}
+
+fn sway_window() -> Option<Window> {
+    std::env::var("SWAYSOCK").ok()?;
+    let json: serde_json::Value = serde_json::from_str(&command_stdout("swaymsg", &["-t", "get_tree", "-r"])?).ok()?;
+    let node = focused_node(&json)?;
+    // XWayland windows have no app_id, only the X11 class
+    let app_id = node.get("app_id").and_then(|a| a.as_str())
+        .or_else(|| node.pointer("/window_properties/class")?.as_str())
+        .unwrap_or_default();
+    Some(Window {
+        app_id: app_id.to_string(),
+        title: node.get("name").and_then(|n| n.as_str()).unwrap_or_default().to_string(),
+        // "xdg_shell" or "xwayland"
+        protocol: node.get("shell").and_then(|s| s.as_str())
+            .map(|shell| if shell == "xwayland" { Protocol::XWayland } else { Protocol::Wayland }),
+    })
+}
+
+/// The focused leaf of a sway tree
+fn focused_node(node: &serde_json::Value) -> Option<&serde_json::Value> {
+    if node.get("focused").and_then(|f| f.as_bool()) == Some(true) {
+        return Some(node);
+    }
+    ["nodes", "floating_nodes"].iter()
+        .filter_map(|key| node.get(key)?.as_array())
+        .flatten()
+        .find_map(focused_node)
+}
+
+fn niri_window() -> Option<Window> {
+    std::env::var("NIRI_SOCKET").ok()?;
+    let json: serde_json::Value = serde_json::from_str(&command_stdout("niri", &["msg", "--json", "focused-window"])?).ok()?;
+    Some(Window {
+        app_id: json.get("app_id").and_then(|a| a.as_str()).unwrap_or_default().to_string(),
+        title: json.get("title").and_then(|t| t.as_str()).unwrap_or_default().to_string(),
+        protocol: None,
+    })
+}
+
+fn x11_window() -> Option<Window> {
+    std::env::var("DISPLAY").ok()?;
+    let title = command_stdout("xdotool", &["getactivewindow", "getwindowname"])?;
+    let app_id = command_stdout("xdotool", &["getactivewindow", "getwindowclassname"]).unwrap_or_default();
+    Some(Window {
+        app_id: app_id.trim().to_string(),
+        title: title.trim().to_string(),
+        // Under an unknown Wayland compositor this is only the focus among X11 windows
+        protocol: std::env::var_os("WAYLAND_DISPLAY").is_none().then_some(Protocol::X11),
+    })
+}
@@ -0,0 +1,271 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use tracing::{debug, warn};
+use crate::helpers;
+use crate::privacy;
+use crate::settings::{Layers, Resolved};
+
+/// LanguageTool rule categories that are applied; style, typography and spelling
+/// suggestions are left alone so the result stays what was said
+const LANGUAGETOOL_CATEGORIES: [&str; 2] = ["GRAMMAR", "CONFUSED_WORDS"];
+
+/// Offline grammar correction applied to transcripts before they are output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Grammar {
+    Off,
+    /// A local LanguageTool server (see `languagetool_url_setting`)
+    LanguageTool,
+    /// A command that reads the text on stdin and prints the corrected text,
+    /// e.g. a script running a small T5/ONNX grammar model
+    Command(String),
+}
+
+impl FromStr for Grammar {
+    type Err = String;
+
+    /// Accepts "off", "languagetool" or "command:<command line>"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Grammar::Off),
+            "languagetool" => Ok(Grammar::LanguageTool),
+            _ => match s.strip_prefix("command:") {
+                Some(command) if !command.trim().is_empty() => Ok(Grammar::Command(command.to_string())),
+                _ => Err(format!("invalid grammar '{}' (expected off, languagetool or command:<command>)", s)),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Grammar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Grammar::Off => write!(f, "off"),
+            Grammar::LanguageTool => write!(f, "languagetool"),
+            Grammar::Command(command) => write!(f, "command:{}", command),
+        }
+    }
+}
+
+/// Resolves the grammar correction step with priority:
+/// 1. CLI flag (--grammar) or profile
+/// 2. WA_GRAMMAR env var
+/// 3. User config file `grammar`
+/// 4. Default to off
+pub fn mode_setting() -> Resolved<Grammar> {
+    let parse = |source: &str, value: String| match value.parse::<Grammar>() {
+        Ok(grammar) => Some(grammar),
+        Err(e) => {
+            warn!("Ignoring grammar from {}: {}", source, e);
+            None
+        }
+    };
+    Layers::new("grammar")
+        .cli(helpers::overrides().grammar.clone())
+        .env("WA_GRAMMAR", |value| parse("env", value))
+        .config(|c| c.grammar.and_then(|value| parse("config file", value)))
+        .or_default(Grammar::Off)
+}
+
+/// Resolves the LanguageTool server with priority:
+/// 1. WA_LANGUAGETOOL_URL env var
+/// 2. User config file `languagetool_url`
+/// 3. Default to "http://localhost:8081" (the port of `languagetool-http-server`)
+pub fn languagetool_url_setting() -> Resolved<String> {
+    Layers::new("LanguageTool URL")
+        .env("WA_LANGUAGETOOL_URL", Some)
+        .config(|c| c.languagetool_url)
+        .or_default("http://localhost:8081".to_string())
+}
+
+/// Correct the grammar of a transcript with the configured step
+/// `language` is the detected language, if the backend reported one
+/// Failures are logged and the text is output as transcribed
+pub fn correct(text: &str, language: Option<&str>) -> String {
+    if text.trim().is_empty() {
+        return text.to_string();
+    }
+
+    let grammar = mode_setting().value;
+    let result = match &grammar {
+        Grammar::Off => return text.to_string(),
+        Grammar::LanguageTool => correct_with_languagetool(text, language),
+        Grammar::Command(command) => correct_with_command(text, command),
+    };
+    match result {
+        Ok(corrected) if corrected != text => {
+            debug!("Grammar corrected ({}): '{}' -> '{}'", grammar, privacy::redact(text), privacy::redact(&corrected));
+            corrected
+        }
+        Ok(_) => text.to_string(),
+        Err(e) => {
+            warn!("Grammar correction failed, keeping the transcript: {:#}", e);
+            text.to_string()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CheckResponse {
+    matches: Vec<Match>,
+}
+
+/// A LanguageTool finding; offset and length count UTF-16 code units
+#[derive(Deserialize)]
+struct Match {
+    offset: usize,
+    length: usize,
+    replacements: Vec<Replacement>,
+    rule: Rule,
+}
+
+#[derive(Deserialize)]
+struct Replacement {
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct Rule {
+    category: Category,
+}
+
+#[derive(Deserialize)]
+struct Category {
+    id: String,
+}
+
+/// Language code for LanguageTool: whisper's two-letter code, else the configured
+/// language (the API backend reports names like "english")
+fn languagetool_language(detected: Option<&str>) -> String {
+    match detected {
+        Some(code) if code.len() == 2 => code.to_string(),
+        _ => helpers::resolve_language(),
+    }
+}
+
+fn correct_with_languagetool(text: &str, language: Option<&str>) -> Result<String> {
+    let url = format!("{}/v2/check", languagetool_url_setting().value.trim_end_matches('/'));
+    let language = languagetool_language(language);
+    debug!("Checking grammar with LanguageTool at {} ({})", url, language);
+
+    // The text goes through stdin so long dictations don't hit argument limits
+    let mut child = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--max-time", "10",
+            "--data-urlencode", &format!("language={}", language),
+            "--data-urlencode", "text@-",
+            &url,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run curl")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let output = child.wait_with_output().context("Failed to run curl")?;
+    if !output.status.success() {
+        return Err(anyhow!("LanguageTool: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    let response: CheckResponse = serde_json::from_slice(&output.stdout)
+        .context("Unexpected LanguageTool response")?;
+    Ok(apply_matches(text, response.matches))
+}
+
+/// Apply the first suggestion of each grammar match, last to first so offsets stay valid
+fn apply_matches(text: &str, mut matches: Vec<Match>) -> String {
+    let mut units: Vec<u16> = text.encode_utf16().collect();
+    matches.retain(|m| LANGUAGETOOL_CATEGORIES.contains(&m.rule.category.id.as_str()) && !m.replacements.is_empty());
+    matches.sort_by_key(|m| std::cmp::Reverse(m.offset));
+
+    // Overlapping matches are skipped; the one further back has already been applied
+    let mut applied_from = units.len();
+    for m in matches {
+        let end = m.offset + m.length;
+        if end > applied_from {
+            continue;
+        }
+        let replacement: Vec<u16> = m.replacements[0].value.encode_utf16().collect();
+        units.splice(m.offset..end, replacement);
+        applied_from = m.offset;
+    }
+    String::from_utf16_lossy(&units)
+}
+
+fn correct_with_command(text: &str, command: &str) -> Result<String> {
+    debug!("Checking grammar with: {}", command);
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run grammar command '{}'", command))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Grammar command exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let corrected = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    // An empty result is more likely a broken command than a correction
+    if corrected.is_empty() {
+        return Err(anyhow!("Grammar command printed nothing"));
+    }
+    Ok(corrected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(json: &str) -> Vec<Match> {
+        serde_json::from_str::<CheckResponse>(json).unwrap().matches
+    }
+
+    #[test]
+    fn applies_matches_at_utf16_offsets() {
+        // "😀" is two UTF-16 units, so "buy" starts at 25 rather than 24
+        let text = "He go to the café 😀 and buy a apple.";
+        let found = matches(r#"{"matches": [
+            {"offset": 3, "length": 2, "replacements": [{"value": "goes"}, {"value": "went"}], "rule": {"category": {"id": "GRAMMAR"}}},
+            {"offset": 29, "length": 7, "replacements": [{"value": "an apple"}], "rule": {"category": {"id": "GRAMMAR"}}},
+            {"offset": 25, "length": 3, "replacements": [{"value": "buys"}], "rule": {"category": {"id": "CONFUSED_WORDS"}}}
+        ]}"#);
+        assert_eq!(apply_matches(text, found), "He goes to the café 😀 and buys an apple.");
+    }
+
+    #[test]
+    fn skips_other_categories_and_matches_without_replacements() {
+        let text = "He go to teh shop.";
+        let found = matches(r#"{"matches": [
+            {"offset": 9, "length": 3, "replacements": [{"value": "the"}], "rule": {"category": {"id": "TYPOS"}}},
+            {"offset": 3, "length": 2, "replacements": [], "rule": {"category": {"id": "GRAMMAR"}}}
+        ]}"#);
+        assert_eq!(apply_matches(text, found), text);
+    }
+
+    #[test]
+    fn skips_overlapping_matches() {
+        let text = "buy a apple";
+        let found = matches(r#"{"matches": [
+            {"offset": 4, "length": 7, "replacements": [{"value": "an apple"}], "rule": {"category": {"id": "GRAMMAR"}}},
+            {"offset": 6, "length": 5, "replacements": [{"value": "pear"}], "rule": {"category": {"id": "GRAMMAR"}}},
+            {"offset": 8, "length": 10, "replacements": [{"value": "past the end"}], "rule": {"category": {"id": "GRAMMAR"}}}
+        ]}"#);
+        assert_eq!(apply_matches(text, found), "buy a pear");
+    }
+}
@@ -0,0 +1,234 @@
+use tracing::debug;
+use crate::privacy;
+use crate::settings::{self, Layers, Resolved, Source};
+use crate::socket::TranscriptionResponse;
+
+/// Phrases whisper emits on silence or noise, learned from subtitled videos; compared
+/// after lower-casing and dropping punctuation
+/// Short ones that people really say ("thank you", "bye") are left out
+const DEFAULT_PHRASES: [&str; 14] = [
+    "thanks for watching",
+    "thank you for watching",
+    "thank you so much for watching",
+    "thanks for watching and see you next time",
+    "please subscribe",
+    "like and subscribe",
+    "please like and subscribe",
+    "don't forget to like and subscribe",
+    "subscribe to my channel",
+    "see you in the next video",
+    "subtitles by the amara.org community",
+    "subtitles by steamteam",
+    "transcription by castingwords",
+    "translated by amara.org community",
+];
+
+/// Resolves whether hallucinated text is dropped with priority:
+/// 1. WA_HALLUCINATION_FILTER env var
+/// 2. User config file `hallucination_filter`
+/// 3. Default to true
+pub fn filter_setting() -> Resolved<bool> {
+    Layers::new("hallucination filter")
+        .env("WA_HALLUCINATION_FILTER", settings::flag)
+        .config(|c| c.hallucination_filter)
+        .or_default(true)
+}
+
+/// Resolves the no-speech probability above which a segment is dropped with priority:
+/// 1. WA_NO_SPEECH_THRESHOLD env var
+/// 2. User config file `no_speech_threshold`
+/// 3. Default to 0.8
+pub fn no_speech_threshold_setting() -> Resolved<f64> {
+    Layers::new("no-speech threshold")
+        .env("WA_NO_SPEECH_THRESHOLD", settings::parsed)
+        .config(|c| c.no_speech_threshold)
+        .or_default(0.8)
+}
+
+/// Resolves the hallucination phrase list with priority:
+/// 1. User config file `hallucination_phrases` (replaces the built-in list)
+/// 2. Default to the built-in list
+pub fn phrases_setting() -> Resolved<Vec<String>> {
+    Layers::new("hallucination phrases")
+        .config(|c| c.hallucination_phrases)
+        .or_else(|| Resolved {
+            value: DEFAULT_PHRASES.iter().map(|phrase| phrase.to_string()).collect(),
+            source: Source::Default,
+        })
+}
+
+/// Lower-case words without punctuation, so "Thanks for watching!" matches "thanks for watching"
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn is_phrase(text: &str, phrases: &[String]) -> bool {
+    let text = normalize(text);
+    phrases.iter().any(|phrase| normalize(phrase) == text)
+}
+
+/// Drop segments whisper most likely made up: a no-speech probability above the
+/// threshold, or nothing but a listed phrase
+/// The text is rebuilt from the remaining segments when any were dropped
+pub fn filter(response: &mut TranscriptionResponse) {
+    if response.segments.is_empty() || !filter_setting().value {
+        return;
+    }
+    drop_segments(response, no_speech_threshold_setting().value, &phrases_setting().value);
+}
+
+fn drop_segments(response: &mut TranscriptionResponse, threshold: f64, phrases: &[String]) {
+    let before = response.segments.len();
+    response.segments.retain(|segment| {
+        if let Some(probability) = segment.no_speech_prob.filter(|p| *p > threshold) {
+            debug!("Dropped segment '{}' at {:.1}s: no-speech probability {:.2}", privacy::redact(&segment.text), segment.start, probability);
+            return false;
+        }
+        if is_phrase(&segment.text, phrases) {
+            debug!("Dropped segment '{}' at {:.1}s: hallucination phrase", privacy::redact(&segment.text), segment.start);
+            return false;
+        }
+        true
+    });
+    if response.segments.len() < before {
+        let text: Vec<&str> = response.segments.iter().map(|segment| segment.text.trim()).collect();
+        response.text = Some(text.join(" "));
+    }
+}
+
+/// Remove listed phrases said as whole sentences ("... and that's it. Thanks for watching!"),
+/// and transcripts of nothing but punctuation ("...", "♪")
+/// Also covers backends without segments; text without either is returned unchanged
+pub fn filter_text(text: &str) -> String {
+    if !filter_setting().value || text.trim().is_empty() {
+        return text.to_string();
+    }
+    drop_sentences(text, &phrases_setting().value)
+}
+
+fn drop_sentences(text: &str, phrases: &[String]) -> String {
+    if normalize(text).is_empty() {
+        debug!("Dropped transcript '{}': no words", privacy::redact(text));
+        return String::new();
+    }
+
+    let sentences = sentences(text);
+    let kept: Vec<&str> = sentences.iter()
+        .copied()
+        .filter(|sentence| {
+            let hallucinated = is_phrase(sentence, phrases);
+            if hallucinated {
+                debug!("Dropped sentence '{}': hallucination phrase", privacy::redact(sentence.trim()));
+            }
+            !hallucinated
+        })
+        .collect();
+    if kept.len() == sentences.len() {
+        return text.to_string();
+    }
+    kept.iter().map(|sentence| sentence.trim()).collect::<Vec<_>>().join(" ")
+}
+
+/// Split text after sentence-ending punctuation followed by whitespace, keeping the punctuation
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let ends_sentence = ".!?…".contains(c)
+            && chars.peek().is_some_and(|(_, next)| next.is_whitespace());
+        if ends_sentence {
+            let end = i + c.len_utf8();
+            sentences.push(&text[start..end]);
+            start = end;
+        }
+    }
+    if !text[start..].trim().is_empty() {
+        sentences.push(&text[start..]);
+    }
+    sentences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::Segment;
+
+    fn defaults() -> Vec<String> {
+        DEFAULT_PHRASES.iter().map(|phrase| phrase.to_string()).collect()
+    }
+
+    fn response(segments: &[(&str, Option<f64>)]) -> TranscriptionResponse {
+        let segments: Vec<Segment> = segments.iter()
+            .enumerate()
+            .map(|(i, (text, no_speech_prob))| Segment {
+                start: i as f64,
+                end: i as f64 + 1.0,
+                text: text.to_string(),
+                no_speech_prob: *no_speech_prob,
+                ..Default::default()
+            })
+            .collect();
+        let text = segments.iter().map(|segment| segment.text.trim()).collect::<Vec<_>>().join(" ");
+        TranscriptionResponse { success: true, text: Some(text), segments, ..Default::default() }
+    }
+
+    #[test]
+    fn drops_segments_above_the_no_speech_threshold() {
+        let mut transcript = response(&[(" Hello.", Some(0.1)), (" Hmm.", Some(0.95)), (" Bye.", Some(0.8)), (" Okay.", None)]);
+        drop_segments(&mut transcript, 0.8, &defaults());
+        // The threshold itself is kept, and segments without a probability are trusted
+        assert_eq!(transcript.text.as_deref(), Some("Hello. Bye. Okay."));
+        assert_eq!(transcript.segments.len(), 3);
+
+        let mut transcript = response(&[(" Hello.", Some(0.1)), (" Bye.", Some(0.8))]);
+        drop_segments(&mut transcript, 0.5, &defaults());
+        assert_eq!(transcript.text.as_deref(), Some("Hello."));
+    }
+
+    #[test]
+    fn drops_repeated_phrase_segments() {
+        let mut transcript = response(&[
+            (" So that's the plan.", Some(0.2)),
+            (" Thanks for watching!", Some(0.3)),
+            (" THANKS FOR WATCHING.", Some(0.3)),
+            (" Please subscribe", None),
+        ]);
+        drop_segments(&mut transcript, 0.8, &defaults());
+        assert_eq!(transcript.text.as_deref(), Some("So that's the plan."));
+
+        // The text is left as the daemon wrote it when nothing was dropped
+        let mut transcript = response(&[(" Thanks  for watching the game with me.", Some(0.2))]);
+        transcript.text = Some("Thanks  for watching the game with me.".to_string());
+        drop_segments(&mut transcript, 0.8, &defaults());
+        assert_eq!(transcript.text.as_deref(), Some("Thanks  for watching the game with me."));
+    }
+
+    #[test]
+    fn drops_phrase_sentences() {
+        let phrases = defaults();
+        assert_eq!(drop_sentences("That's it. Thanks for watching! Thanks for watching!", &phrases), "That's it.");
+        assert_eq!(drop_sentences("Thanks for watching. Thanks for watching.", &phrases), "");
+        assert_eq!(drop_sentences("Thank you. See you tomorrow.", &phrases), "Thank you. See you tomorrow.");
+        assert_eq!(drop_sentences("...", &phrases), "");
+        assert_eq!(drop_sentences("♪ ♪", &phrases), "");
+        // Listed phrases inside a sentence are something the speaker said
+        assert_eq!(drop_sentences("I said please subscribe to the newsletter.", &phrases), "I said please subscribe to the newsletter.");
+        // A custom list replaces the built-in one
+        let custom = vec!["Okay, bye".to_string()];
+        assert_eq!(drop_sentences("Done. Okay bye! Thanks for watching.", &custom), "Done. Thanks for watching.");
+    }
+
+    #[test]
+    fn splits_sentences_before_whitespace() {
+        assert_eq!(sentences("One. Two?  Three… four"), ["One.", " Two?", "  Three…", " four"]);
+        assert_eq!(sentences("Version 1.2 is out."), ["Version 1.2 is out."]);
+        assert!(sentences("").is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use crate::settings::{self, Layers, Resolved};
+
+/// Resolves whether audio goes to the daemon as a file descriptor passed over the socket
+/// (SCM_RIGHTS) instead of as a path with priority:
+/// 1. WA_FD_AUDIO env var
+/// 2. User config file `fd_audio`
+/// 3. Default to false
+pub fn fd_audio_setting() -> Resolved<bool> {
+    Layers::new("fd audio")
+        .env("WA_FD_AUDIO", settings::flag)
+        .config(|c| c.fd_audio)
+        .or_default(false)
+}
+
+/// Whether audio is handed over by file descriptor (Linux only, for memfd and /proc)
+pub fn enabled() -> bool {
+    cfg!(target_os = "linux") && fd_audio_setting().value
+}
+
+/// Flags for sending: no SIGPIPE when the daemon has gone away
+#[cfg(target_os = "linux")]
+const SEND_FLAGS: libc::c_int = libc::MSG_NOSIGNAL;
+#[cfg(not(target_os = "linux"))]
+const SEND_FLAGS: libc::c_int = 0;
+
+/// Flags for receiving: descriptors arrive close-on-exec, so transcription helpers don't inherit them
+#[cfg(all(target_os = "linux", any(feature = "whisper-cpp", test)))]
+const RECV_FLAGS: libc::c_int = libc::MSG_CMSG_CLOEXEC;
+#[cfg(all(not(target_os = "linux"), any(feature = "whisper-cpp", test)))]
+const RECV_FLAGS: libc::c_int = 0;
+
+/// Audio held in memory rather than the runtime directory: a memfd that is sealed once
+/// written, so the daemon reading it knows it won't change underneath
+#[cfg(target_os = "linux")]
+pub struct MemAudio {
+    fd: OwnedFd,
+}
+
+#[cfg(target_os = "linux")]
+impl MemAudio {
+    pub fn new(name: &str) -> io::Result<Self> {
+        let name = std::ffi::CString::new(name).map_err(io::Error::other)?;
+        // SAFETY: name is a valid C string; the returned descriptor is checked before use
+        let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: memfd_create returned a new descriptor nothing else owns
+        Ok(Self { fd: unsafe { OwnedFd::from_raw_fd(fd) } })
+    }
+
+    /// Path that opens the audio again, for this process and the ones it starts (ffmpeg,
+    /// the direct backends)
+    pub fn path(&self) -> String {
+        format!("/proc/{}/fd/{}", std::process::id(), self.fd.as_raw_fd())
+    }
+
+    /// Forbid further writes and size changes
+    pub fn seal(&self) -> io::Result<()> {
+        let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+        // SAFETY: fcntl on a descriptor we own
+        if unsafe { libc::fcntl(self.fd.as_raw_fd(), libc::F_ADD_SEALS, seals) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Send `data` with `fd` attached, returning how much of `data` went out; the rest is
+/// written as usual
+pub fn send_with_fd(stream: &UnixStream, data: &[u8], fd: RawFd) -> io::Result<usize> {
+    let fd_len = std::mem::size_of::<RawFd>() as u32;
+    // SAFETY: CMSG_SPACE only computes a size
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(fd_len) } as usize];
+    let mut iov = libc::iovec { iov_base: data.as_ptr() as *mut libc::c_void, iov_len: data.len() };
+    // SAFETY: an all-zero msghdr is valid; the fields that matter are set below
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = control.len() as _;
+    // SAFETY: the control buffer has room for one header carrying one descriptor, and
+    // iov and control outlive the sendmsg call
+    let sent = unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(fd_len) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+        libc::sendmsg(stream.as_raw_fd(), &msg, SEND_FLAGS)
+    };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(sent as usize)
+}
+
+/// Read into `buffer`, taking a file descriptor sent along with the data if there is one
+/// Descriptors beyond the first are closed
+#[cfg(any(feature = "whisper-cpp", test))]
+pub fn recv_with_fd(stream: &UnixStream, buffer: &mut [u8]) -> io::Result<(usize, Option<OwnedFd>)> {
+    let fd_len = std::mem::size_of::<RawFd>() as u32;
+    // SAFETY: CMSG_SPACE only computes a size
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(fd_len * 4) } as usize];
+    let mut iov = libc::iovec { iov_base: buffer.as_mut_ptr().cast(), iov_len: buffer.len() };
+    // SAFETY: as in send_with_fd
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = control.len() as _;
+    // SAFETY: buffer and control outlive the call and their lengths are given
+    let received = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut msg, RECV_FLAGS) };
+    if received < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut first = None;
+    // SAFETY: the headers are walked with the CMSG macros within msg_controllen, and each
+    // descriptor the kernel installed is owned exactly once
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg);
+                let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / fd_len as usize;
+                for i in 0..count {
+                    let fd = OwnedFd::from_raw_fd(std::ptr::read_unaligned((data as *const RawFd).add(i)));
+                    if first.is_none() {
+                        first = Some(fd);
+                    }
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Ok((received as usize, first))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn hands_sealed_audio_over_a_socket() {
+        let audio = MemAudio::new("whisp-away-test").unwrap();
+        std::fs::OpenOptions::new().write(true).open(audio.path()).unwrap().write_all(b"RIFF audio").unwrap();
+        audio.seal().unwrap();
+
+        let (client, daemon) = UnixStream::pair().unwrap();
+        let request = b"{\"audio_fd\":true}";
+        assert_eq!(send_with_fd(&client, request, audio.fd.as_raw_fd()).unwrap(), request.len());
+        drop(audio);
+
+        let mut buffer = [0u8; 64];
+        let (received, fd) = recv_with_fd(&daemon, &mut buffer).unwrap();
+        assert_eq!(&buffer[..received], request);
+        let mut bytes = Vec::new();
+        std::fs::File::from(fd.expect("descriptor sent along")).read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, b"RIFF audio");
+    }
+
+    #[test]
+    fn receives_data_without_a_descriptor() {
+        let (client, daemon) = UnixStream::pair().unwrap();
+        (&client).write_all(b"path").unwrap();
+        let mut buffer = [0u8; 16];
+        let (received, fd) = recv_with_fd(&daemon, &mut buffer).unwrap();
+        assert_eq!(&buffer[..received], b"path");
+        assert!(fd.is_none());
+    }
+}
@@ -1,7 +1,16 @@
 use anyhow::Result;
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
+use crate::grammar::Grammar;
+use crate::instance;
+use crate::keyboard;
+#[cfg(any(feature = "whisper-cpp", feature = "faster-whisper"))]
+use crate::paths;
+use crate::settings::{self, Layers, Resolved};
+use crate::typing::Output;
 
 /// Daemon configuration - written by daemon, read by CLI commands
 /// This ensures CLI commands use the same settings as the running daemon
@@ -11,44 +20,112 @@ pub struct DaemonConfig {
     pub model: Option<String>,
     pub socket_path: Option<String>,
     pub use_clipboard: Option<bool>,
+    pub compute_type: Option<String>,
+    pub language: Option<String>,
 }
 
-/// Get the path to the daemon config file
-fn get_daemon_config_path() -> String {
-    format!("{}/whisp-away-daemon.json", get_runtime_dir())
+/// Schema version of the daemon config file, bumped when its fields change meaning
+/// A file with another version was written by a different whisp-away and is ignored
+const DAEMON_CONFIG_VERSION: u32 = 1;
+
+/// The daemon config as stored, tagged with its schema version
+#[derive(Serialize, Deserialize)]
+struct VersionedDaemonConfig {
+    /// Missing in files from before versioning
+    #[serde(default)]
+    version: u32,
+    #[serde(flatten)]
+    config: DaemonConfig,
+}
+
+/// Set once a problem with the daemon config has been logged; settings read it many times
+/// per command
+static DAEMON_CONFIG_WARNED: AtomicBool = AtomicBool::new(false);
+
+impl DaemonConfig {
+    /// Problems that make the config unusable, e.g. from a hand-edited or foreign file
+    fn validate(&self) -> Result<(), String> {
+        if let Some(backend) = &self.backend {
+            if backend != "whisper-cpp" && backend != "faster-whisper" {
+                return Err(format!("unknown backend '{}'", backend));
+            }
+        }
+        if self.model.as_deref().is_some_and(|model| model.trim().is_empty()) {
+            return Err("empty model".to_string());
+        }
+        if self.socket_path.as_deref().is_some_and(|path| path.trim().is_empty()) {
+            return Err("empty socket path".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Get the path to the daemon config file (one per daemon instance)
+pub fn get_daemon_config_path() -> String {
+    instance::runtime_path("whisp-away-daemon", "json")
 }
 
 /// Write daemon configuration (called when daemon starts)
+#[cfg(any(feature = "whisper-cpp", feature = "faster-whisper"))]
 pub fn write_daemon_config(config: &DaemonConfig) -> Result<()> {
     let config_path = get_daemon_config_path();
-    let runtime_dir = get_runtime_dir();
+    let runtime_dir = paths::runtime_dir();
     
     // Ensure runtime dir exists
     std::fs::create_dir_all(&runtime_dir).ok();
     
-    let json = serde_json::to_string_pretty(config)?;
-    std::fs::write(&config_path, json)?;
+    let versioned = VersionedDaemonConfig { version: DAEMON_CONFIG_VERSION, config: config.clone() };
+    let json = serde_json::to_string_pretty(&versioned)?;
+    // Every command reads this file, so it must never be seen half-written
+    paths::write_atomic(std::path::Path::new(&config_path), json.as_bytes())?;
     debug!("Wrote daemon config to: {}", config_path);
     Ok(())
 }
 
 /// Read daemon configuration (called by CLI commands)
+/// A corrupt, invalid or other-version file is ignored with a warning, since the settings
+/// it would have provided silently fall back to other layers
 pub fn read_daemon_config() -> Option<DaemonConfig> {
     let config_path = get_daemon_config_path();
-    if let Ok(content) = std::fs::read_to_string(&config_path) {
-        match serde_json::from_str::<DaemonConfig>(&content) {
-            Ok(config) => {
-                trace!("Read daemon config from: {}", config_path);
-                Some(config)
-            }
-            Err(e) => {
-                debug!("Failed to parse daemon config: {}", e);
-                None
-            }
+    let content = match std::fs::read_to_string(&config_path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            trace!("No daemon config file found at: {}", config_path);
+            return None;
+        }
+        Err(e) => {
+            warn_daemon_config(&config_path, &format!("can't be read: {}", e));
+            return None;
+        }
+    };
+
+    let versioned = match serde_json::from_str::<VersionedDaemonConfig>(&content) {
+        Ok(versioned) => versioned,
+        Err(e) => {
+            warn_daemon_config(&config_path, &format!("is corrupt ({}); restart the daemon to rewrite it", e));
+            return None;
         }
+    };
+    if versioned.version != DAEMON_CONFIG_VERSION {
+        warn_daemon_config(&config_path, &format!(
+            "has schema version {} but this whisp-away expects {}; restart the daemon",
+            versioned.version, DAEMON_CONFIG_VERSION
+        ));
+        return None;
+    }
+    if let Err(e) = versioned.config.validate() {
+        warn_daemon_config(&config_path, &format!("is invalid: {}", e));
+        return None;
+    }
+    trace!("Read daemon config from: {}", config_path);
+    Some(versioned.config)
+}
+
+fn warn_daemon_config(config_path: &str, problem: &str) {
+    if DAEMON_CONFIG_WARNED.swap(true, Ordering::Relaxed) {
+        debug!("Ignoring daemon config {}: it {}", config_path, problem);
     } else {
-        trace!("No daemon config file found at: {}", config_path);
-        None
+        warn!("Ignoring daemon config {}: it {}", config_path, problem);
     }
 }
 
@@ -62,99 +139,315 @@ pub fn is_process_running(pid: u32) -> bool {
     running
 }
 
+/// Format the current local time with a strftime pattern (e.g. "%Y-%m-%d %H:%M")
+pub fn local_timestamp(format: &str) -> String {
+    format_local_time(unsafe { libc::time(std::ptr::null_mut()) }, format)
+}
 
-
-pub fn wav_to_samples(wav_data: &[u8]) -> Result<Vec<f32>> {
-    // Skip WAV header (44 bytes) and convert to f32 samples
-    // This assumes 16-bit PCM mono audio at 16kHz
-    
-    if wav_data.len() < 44 {
-        return Err(anyhow::anyhow!("Invalid WAV file: too short"));
-    }
-    
-    let raw_samples = &wav_data[44..];
-    let mut samples = Vec::with_capacity(raw_samples.len() / 2);
-    
-    for chunk in raw_samples.chunks_exact(2) {
-        let sample = i16::from_le_bytes([chunk[0], chunk[1]]);
-        samples.push(sample as f32 / i16::MAX as f32);
-    }
-    
-    Ok(samples)
+/// Seconds since the Unix epoch
+pub fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
 }
 
-/// Get the runtime directory (XDG_RUNTIME_DIR or /tmp fallback)
-pub fn get_runtime_dir() -> String {
-    std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| {
-        let uid = unsafe { libc::getuid() };
-        format!("/tmp/whisp-away-{}", uid)
-    })
+/// Format a Unix time as local time with a strftime pattern
+pub fn format_local_time(time: libc::time_t, format: &str) -> String {
+    let format = match std::ffi::CString::new(format) {
+        Ok(format) => format,
+        Err(_) => return String::new(),
+    };
+    let mut buffer = [0u8; 128];
+    let written = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&time, &mut tm);
+        libc::strftime(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len(), format.as_ptr(), &tm)
+    };
+    String::from_utf8_lossy(&buffer[..written]).to_string()
 }
 
 /// Resolves the socket path with priority:
-/// 1. WA_WHISPER_SOCKET env var (set via NixOS service config)
+/// 1. WA_WHISPER_SOCKET env var (set via NixOS service config; ignored for a named
+///    instance, as one variable can't name every instance's socket)
 /// 2. Daemon config file (written by running daemon)
-/// 3. Default to "/tmp/whisp-away-daemon.sock"
+/// 3. Default to "/tmp/whisp-away-daemon.sock" ("/tmp/whisp-away-daemon-<instance>.sock" for a named instance)
 pub fn resolve_socket_path() -> String {
-    if let Ok(path) = std::env::var("WA_WHISPER_SOCKET") {
-        debug!("Using socket path from env: {}", path);
-        return path;
-    }
-    
-    if let Some(config) = read_daemon_config() {
-        if let Some(path) = config.socket_path {
-            debug!("Using socket path from daemon config: {}", path);
-            return path;
-        }
-    }
-    
-    let path = "/tmp/whisp-away-daemon.sock".to_string();
-    debug!("Using default socket path: {}", path);
-    path
+    socket_path_setting().value
+}
+
+pub fn socket_path_setting() -> Resolved<String> {
+    let layers = Layers::new("socket path");
+    let layers = if instance::name().is_none() { layers.env("WA_WHISPER_SOCKET", Some) } else { layers };
+    layers
+        .daemon(|d| d.socket_path)
+        .or_default(instance::default_socket_path())
 }
 
 /// Resolves the backend with priority:
 /// 1. WA_WHISPER_BACKEND env var (set via NixOS service config)
-/// 2. Daemon config file (written by running daemon)
-/// 3. Default to "faster-whisper"
+/// 2. User config file
+/// 3. Daemon config file (written by running daemon)
+/// 4. Default to "faster-whisper"
 pub fn resolve_backend() -> String {
-    if let Ok(backend) = std::env::var("WA_WHISPER_BACKEND") {
-        debug!("Using backend from env: {}", backend);
-        return backend;
-    }
-    
-    if let Some(config) = read_daemon_config() {
-        if let Some(backend) = config.backend {
-            debug!("Using backend from daemon config: {}", backend);
-            return backend;
-        }
-    }
-    
-    let backend = "faster-whisper".to_string();
-    debug!("Using default backend: {}", backend);
-    backend
+    backend_setting().value
+}
+
+pub fn backend_setting() -> Resolved<String> {
+    Layers::new("backend")
+        .env("WA_WHISPER_BACKEND", Some)
+        .config(|c| c.backend)
+        .daemon(|d| d.backend)
+        .or_default("faster-whisper".to_string())
+}
+
+/// Per-invocation settings from CLI flags (--model, --language, --task, --beam-size,
+/// --initial-prompt, --grammar, --spell, --wait-for-focus, --whisper-arg) and --profile
+/// Set once at startup; they take precedence over env vars and config files
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    pub model: Option<String>,
+    pub language: Option<String>,
+    pub task: Option<String>,
+    pub beam_size: Option<u32>,
+    pub initial_prompt: Option<String>,
+    pub grammar: Option<Grammar>,
+    pub spell: bool,
+    pub wait_for_focus: Option<String>,
+    #[cfg(feature = "whisper-cpp")]
+    pub whisper_args: Vec<String>,
+}
+
+static OVERRIDES: OnceLock<Overrides> = OnceLock::new();
+
+/// Install the overrides for this process (only the first call has an effect)
+pub fn set_overrides(overrides: Overrides) {
+    debug!("Overrides: {:?}", overrides);
+    let _ = OVERRIDES.set(overrides);
+}
+
+pub fn overrides() -> &'static Overrides {
+    OVERRIDES.get_or_init(Overrides::default)
 }
 
 /// Resolves the model to use with priority:
-/// 1. WA_WHISPER_MODEL env var (set via NixOS service config)
-/// 2. Daemon config file (written by running daemon)
-/// 3. Default to "base.en"
+/// 1. CLI flag (--model) or profile
+/// 2. WA_WHISPER_MODEL env var (set via NixOS service config)
+/// 3. User config file
+/// 4. Daemon config file (written by running daemon)
+/// 5. Default to "base.en"
 pub fn resolve_model() -> String {
-    if let Ok(model) = std::env::var("WA_WHISPER_MODEL") {
-        debug!("Using model from env: {}", model);
-        return model;
-    }
-    
-    if let Some(config) = read_daemon_config() {
-        if let Some(model) = config.model {
-            debug!("Using model from daemon config: {}", model);
-            return model;
-        }
-    }
-    
-    let model = "base.en".to_string();
-    debug!("Using default model: {}", model);
-    model
+    model_setting().value
+}
+
+pub fn model_setting() -> Resolved<String> {
+    Layers::new("model")
+        .cli(overrides().model.clone())
+        .env("WA_WHISPER_MODEL", Some)
+        .config(|c| c.model)
+        .daemon(|d| d.model)
+        .or_default("base.en".to_string())
+}
+
+/// Resolves the model for the second pass of two-stage transcription with priority:
+/// 1. CLI flag (--final-model)
+/// 2. WA_FINAL_MODEL env var
+/// 3. User config file
+/// 4. None - single-stage transcription
+pub fn resolve_final_model(cli_value: Option<String>) -> Option<String> {
+    final_model_setting(cli_value).value
+}
+
+pub fn final_model_setting(cli_value: Option<String>) -> Resolved<Option<String>> {
+    Layers::new("final model")
+        .cli(cli_value)
+        .env("WA_FINAL_MODEL", Some)
+        .config(|c| c.final_model)
+        .optional()
+}
+
+/// Resolves the transcription language with priority:
+/// 1. CLI flag (--language) or profile
+/// 2. Active keyboard layout, with `layout_language` on
+/// 3. WA_WHISPER_LANGUAGE env var
+/// 4. User config file
+/// 5. Daemon config file (written by running daemon)
+/// 6. Default to "en"
+pub fn resolve_language() -> String {
+    language_setting().value
+}
+
+pub fn language_setting() -> Resolved<String> {
+    Layers::new("language")
+        .cli(overrides().language.clone())
+        .detected("keyboard layout", keyboard::layout_language)
+        .env("WA_WHISPER_LANGUAGE", Some)
+        .config(|c| c.language)
+        .daemon(|d| d.language)
+        .or_default("en".to_string())
+}
+
+/// Resolves the whisper task ("transcribe" or "translate" to English) with priority:
+/// 1. CLI flag (--task) or profile
+/// 2. WA_WHISPER_TASK env var
+/// 3. User config file
+/// 4. Default to "transcribe"
+pub fn resolve_task() -> String {
+    task_setting().value
+}
+
+pub fn task_setting() -> Resolved<String> {
+    Layers::new("task")
+        .cli(overrides().task.clone())
+        .env("WA_WHISPER_TASK", Some)
+        .config(|c| c.task)
+        .or_default("transcribe".to_string())
+}
+
+/// Resolves the text whisper is primed with (names, jargon, the style of punctuation) with priority:
+/// 1. CLI flag (--initial-prompt) or profile
+/// 2. WA_INITIAL_PROMPT env var
+/// 3. User config file `initial_prompt`
+/// 4. None
+pub fn initial_prompt_setting() -> Resolved<Option<String>> {
+    Layers::new("initial prompt")
+        .cli(overrides().initial_prompt.clone())
+        .env("WA_INITIAL_PROMPT", Some)
+        .config(|c| c.initial_prompt)
+        .optional()
+}
+
+pub fn resolve_initial_prompt() -> Option<String> {
+    initial_prompt_setting().value.filter(|prompt| !prompt.trim().is_empty())
+}
+
+/// Whether the resolved task translates to English
+#[cfg(any(feature = "whisper-cpp", feature = "http-backends"))]
+pub fn resolve_translate() -> bool {
+    resolve_task() == "translate"
+}
+
+/// Resolves the faster-whisper compute type with priority:
+/// 1. CLI flag (--compute-type)
+/// 2. WA_COMPUTE_TYPE env var
+/// 3. User config file
+/// 4. Daemon config file (written by running daemon)
+/// 5. None - let the Python side pick based on the device
+pub fn resolve_compute_type(cli_value: Option<String>) -> Option<String> {
+    compute_type_setting(cli_value).value
+}
+
+pub fn compute_type_setting(cli_value: Option<String>) -> Resolved<Option<String>> {
+    Layers::new("compute type")
+        .cli(cli_value)
+        .env("WA_COMPUTE_TYPE", Some)
+        .config(|c| c.compute_type)
+        .daemon(|d| d.compute_type)
+        .optional()
+}
+
+/// Resolves the faster-whisper batch size with priority:
+/// 1. CLI flag (--batch-size)
+/// 2. WA_BATCH_SIZE env var
+/// 3. User config file
+/// 4. None - the Python side batches on GPU only
+pub fn resolve_batch_size(cli_value: Option<u32>) -> Option<u32> {
+    batch_size_setting(cli_value).value
+}
+
+pub fn batch_size_setting(cli_value: Option<u32>) -> Resolved<Option<u32>> {
+    Layers::new("batch size")
+        .cli(cli_value)
+        .env("WA_BATCH_SIZE", settings::parsed)
+        .config(|c| c.batch_size)
+        .optional()
+}
+
+/// Resolves how many times a failed upload to an API backend is retried:
+/// 1. CLI flag (--max-retries)
+/// 2. WA_MAX_RETRIES env var
+/// 3. User config file
+/// 4. Default to 3
+pub fn resolve_max_retries(cli_value: Option<u32>) -> u32 {
+    max_retries_setting(cli_value).value
+}
+
+pub fn max_retries_setting(cli_value: Option<u32>) -> Resolved<u32> {
+    Layers::new("max retries")
+        .cli(cli_value)
+        .env("WA_MAX_RETRIES", settings::parsed)
+        .config(|c| c.max_retries)
+        .or_default(3)
+}
+
+/// Resolves the free space (MB) the runtime directory needs before recordings spill to disk:
+/// 1. WA_SPILL_THRESHOLD_MB env var
+/// 2. User config file
+/// 3. Default to 256 (a bit over two hours of audio)
+pub fn resolve_spill_threshold_mb() -> u64 {
+    spill_threshold_mb_setting().value
+}
+
+pub fn spill_threshold_mb_setting() -> Resolved<u64> {
+    Layers::new("spill threshold (MB)")
+        .env("WA_SPILL_THRESHOLD_MB", settings::parsed)
+        .config(|c| c.spill_threshold_mb)
+        .or_default(256)
+}
+
+/// Resolves whether audio is sent inline over the socket instead of as a path:
+/// 1. WA_INLINE_AUDIO env var
+/// 2. User config file
+/// 3. Default to false (the daemon reads the file directly)
+pub fn resolve_inline_audio() -> bool {
+    inline_audio_setting().value
+}
+
+pub fn inline_audio_setting() -> Resolved<bool> {
+    Layers::new("inline audio")
+        .env("WA_INLINE_AUDIO", settings::flag)
+        .config(|c| c.inline_audio)
+        .or_default(false)
+}
+
+/// Resolves how much memory (MB) the daemon may spend on resident models:
+/// 1. WA_MODEL_MEMORY_MB env var
+/// 2. User config file
+/// 3. Default to 4096
+#[cfg(any(feature = "whisper-cpp", feature = "faster-whisper"))]
+pub fn resolve_model_memory_mb() -> u64 {
+    model_memory_mb_setting().value
+}
+
+pub fn model_memory_mb_setting() -> Resolved<u64> {
+    Layers::new("model memory limit (MB)")
+        .env("WA_MODEL_MEMORY_MB", settings::parsed)
+        .config(|c| c.model_memory_mb)
+        .or_default(4096)
+}
+
+/// Resolves the length (seconds) under which the daemon transcribes with the fast path preset:
+/// no temperature fallback and no beam search, for quick commands rather than dictation
+/// 1. WA_FAST_PATH_SECS env var
+/// 2. User config file `fast_path_secs`
+/// 3. Default to 5 (0 turns the fast path off)
+pub fn fast_path_secs_setting() -> Resolved<f64> {
+    Layers::new("fast path (s)")
+        .env("WA_FAST_PATH_SECS", settings::parsed)
+        .config(|c| c.fast_path_secs)
+        .or_default(5.0)
+}
+
+/// Resolves the confidence below which a proofreading warning is shown
+/// from WA_CONFIDENCE_THRESHOLD (0.0-1.0), defaulting to 0.4
+pub fn resolve_confidence_threshold() -> f64 {
+    confidence_threshold_setting().value
+}
+
+pub fn confidence_threshold_setting() -> Resolved<f64> {
+    Layers::new("confidence threshold")
+        .env("WA_CONFIDENCE_THRESHOLD", settings::parsed)
+        .or_default(0.4)
 }
 
 /// Get the acceleration type from environment variable
@@ -163,54 +456,56 @@ pub fn get_acceleration_type() -> String {
 }
 
 /// Send a notification, handling errors gracefully
+/// Falls back to the tray tooltip and OSD command when there is no notification server
 pub fn send_notification(title: &str, message: &str, timeout_ms: u32) {
-    use std::process::Command;
     debug!("Sending notification: {} - {}", title, message);
-    
-    match Command::new("notify-send")
-        .args([
-            title,
-            message,
-            "-t", &timeout_ms.to_string(),
-            "-h", "string:x-canonical-private-synchronous:voice"
-        ])
-        .output()
-    {
-        Ok(output) => {
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                debug!("notify-send failed: {}", stderr);
-                // Fallback: print to console
-                eprintln!("[whisp-away] {}: {}", title, message);
-            }
-        }
+    crate::feedback::notify(title, message, timeout_ms);
+}
+
+/// Resolves where transcribed text goes with priority:
+/// 1. CLI flag (--output)
+/// 2. WA_OUTPUT env var
+/// 3. User config file `output`
+/// 4. Clipboard setting (see resolve_use_clipboard)
+pub fn resolve_output(cli_value: Option<Output>) -> Output {
+    output_setting(cli_value).value
+}
+
+pub fn output_setting(cli_value: Option<Output>) -> Resolved<Output> {
+    let parse = |source: &str, value: String| match value.parse::<Output>() {
+        Ok(output) => Some(output),
         Err(e) => {
-            debug!("Failed to run notify-send: {}", e);
-            // Fallback: print to console
-            eprintln!("[whisp-away] {}: {}", title, message);
+            warn!("Ignoring output from {}: {}", source, e);
+            None
         }
-    }
+    };
+    Layers::new("output")
+        .cli(cli_value)
+        .env("WA_OUTPUT", |value| parse("env", value))
+        .config(|c| c.output.and_then(|value| parse("config file", value)))
+        .or_else(|| {
+            let use_clipboard = use_clipboard_setting();
+            Resolved {
+                value: Output::from_use_clipboard(use_clipboard.value),
+                source: use_clipboard.source,
+            }
+        })
 }
 
 /// Resolves whether to use clipboard with priority:
 /// 1. WA_USE_CLIPBOARD env var (set via NixOS service config)
-/// 2. Daemon config file (written by running daemon)
-/// 3. Default to false
+/// 2. User config file
+/// 3. Daemon config file (written by running daemon)
+/// 4. Default to false
+#[cfg(any(feature = "whisper-cpp", feature = "faster-whisper"))]
 pub fn resolve_use_clipboard() -> bool {
-    if let Ok(val) = std::env::var("WA_USE_CLIPBOARD") {
-        let use_clipboard = val.to_lowercase() == "true";
-        debug!("Using clipboard setting from env: {}", use_clipboard);
-        return use_clipboard;
-    }
-    
-    if let Some(config) = read_daemon_config() {
-        if let Some(use_clipboard) = config.use_clipboard {
-            debug!("Using clipboard setting from daemon config: {}", use_clipboard);
-            return use_clipboard;
-        }
-    }
-    
-    debug!("Using default clipboard setting: false");
-    false
+    use_clipboard_setting().value
 }
 
+pub fn use_clipboard_setting() -> Resolved<bool> {
+    Layers::new("clipboard setting")
+        .env("WA_USE_CLIPBOARD", settings::flag)
+        .config(|c| c.use_clipboard)
+        .daemon(|d| d.use_clipboard)
+        .or_default(false)
+}
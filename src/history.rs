@@ -0,0 +1,508 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, warn};
+use crate::focus;
+use crate::helpers::{self, now_secs};
+use crate::paths;
+use crate::pause;
+use crate::privacy;
+use crate::settings::{self, Layers, Resolved};
+use crate::socket::TranscriptionResponse;
+
+/// One line of the history file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Unix time the transcript was output
+    pub timestamp: u64,
+    pub backend: String,
+    /// App id (or X11 class) of the window focused when the transcript was output,
+    /// with `history_window`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    /// Title of that window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<String>,
+    #[serde(flatten)]
+    pub response: TranscriptionResponse,
+}
+
+/// Transcription history, one JSON object per line
+pub fn history_path() -> PathBuf {
+    paths::state_dir().join("history.jsonl")
+}
+
+/// Resolves whether transcripts are kept in the history:
+/// 1. WA_HISTORY env var
+/// 2. User config file `history`
+/// 3. Default to true
+pub fn enabled_setting() -> Resolved<bool> {
+    Layers::new("history")
+        .env("WA_HISTORY", |val| Some(val.to_lowercase() != "false"))
+        .config(|c| c.history)
+        .or_default(true)
+}
+
+/// Resolves whether the focused app and window title are kept with each transcript:
+/// 1. WA_HISTORY_WINDOW env var
+/// 2. User config file `history_window`
+/// 3. Default to false (titles can name private documents and chats)
+pub fn window_setting() -> Resolved<bool> {
+    Layers::new("history window")
+        .env("WA_HISTORY_WINDOW", settings::flag)
+        .config(|c| c.history_window)
+        .or_default(false)
+}
+
+/// How long transcripts are kept, shown as written ("1d", "12h")
+#[derive(Debug, Clone, Copy)]
+pub struct MaxAge(pub Duration);
+
+impl std::fmt::Display for MaxAge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", pause::format_duration(self.0.as_secs()))
+    }
+}
+
+/// Resolves how many transcripts are kept with priority:
+/// 1. WA_HISTORY_MAX_ENTRIES env var
+/// 2. User config file `history_max_entries`
+/// 3. None: no limit
+pub fn max_entries_setting() -> Resolved<Option<usize>> {
+    Layers::new("history max entries")
+        .env("WA_HISTORY_MAX_ENTRIES", settings::parsed)
+        .config(|c| c.history_max_entries)
+        .optional()
+}
+
+/// Resolves how long transcripts are kept with priority:
+/// 1. WA_HISTORY_MAX_AGE env var
+/// 2. User config file `history_max_age`
+/// 3. None: forever
+pub fn max_age_setting() -> Resolved<Option<MaxAge>> {
+    let parse = |source: &str, value: String| match pause::parse_duration(&value) {
+        Ok(age) => Some(MaxAge(age)),
+        Err(e) => {
+            warn!("Ignoring history max age from {}: {}", source, e);
+            None
+        }
+    };
+    Layers::new("history max age")
+        .env("WA_HISTORY_MAX_AGE", |value| parse("env", value))
+        .config(|c| c.history_max_age.and_then(|value| parse("config file", value)))
+        .optional()
+}
+
+/// Resolves how large the history file may grow with priority:
+/// 1. WA_HISTORY_MAX_MB env var
+/// 2. User config file `history_max_mb`
+/// 3. None: no limit
+pub fn max_mb_setting() -> Resolved<Option<u64>> {
+    Layers::new("history max size (MB)")
+        .env("WA_HISTORY_MAX_MB", settings::parsed)
+        .config(|c| c.history_max_mb)
+        .optional()
+}
+
+/// Append a transcript to the history (no-op when history is disabled)
+pub fn record(response: &TranscriptionResponse, backend: &str) {
+    if !enabled_setting().value {
+        return;
+    }
+    if privacy::enabled() {
+        debug!("Privacy mode, not recording history");
+        return;
+    }
+    // Recorded before the text is output, while the window it goes to is still focused
+    let window = window_setting().value.then(focus::focused_window).flatten();
+    let entry = Entry {
+        timestamp: now_secs(),
+        backend: backend.to_string(),
+        app: window.as_ref().map(|window| window.app_id.clone()).filter(|app| !app.is_empty()),
+        window: window.map(|window| window.title).filter(|title| !title.is_empty()),
+        response: response.clone(),
+    };
+    if let Err(e) = append(&entry) {
+        warn!("Failed to record history: {:#}", e);
+    }
+    enforce_retention();
+}
+
+fn append(entry: &Entry) -> Result<()> {
+    let path = history_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let line = serde_json::to_string(entry)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", line)?;
+    debug!("Recorded history entry in {}", path.display());
+    Ok(())
+}
+
+/// A line of the history file with just enough parsed to decide whether it's kept
+struct Line<'a> {
+    timestamp: u64,
+    text: &'a str,
+}
+
+#[derive(Deserialize)]
+struct Timestamp {
+    timestamp: u64,
+}
+
+/// Rewrite the history with the lines `select` keeps (oldest first), returning how many
+/// were dropped
+fn rewrite(select: impl FnOnce(Vec<Line>) -> Vec<Line>) -> Result<usize> {
+    let path = history_path();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    // Lines that don't parse can't be shown either, so they go too
+    let lines: Vec<Line> = content.lines()
+        .filter_map(|text| {
+            let Timestamp { timestamp } = serde_json::from_str(text).ok()?;
+            Some(Line { timestamp, text })
+        })
+        .collect();
+    let total = content.lines().count();
+    let kept = select(lines);
+    if kept.len() == total {
+        return Ok(0);
+    }
+
+    if kept.is_empty() {
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        return Ok(total);
+    }
+    let mut data = String::new();
+    for line in &kept {
+        data.push_str(line.text);
+        data.push('\n');
+    }
+    // A crash mid-write must not lose the history
+    paths::write_atomic(&path, data.as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(total - kept.len())
+}
+
+/// Drop transcripts beyond the configured age, count and size limits
+/// Runs after every new transcript and before the history is shown, so expired
+/// dictations disappear even when nothing new is recorded
+pub fn enforce_retention() {
+    let max_entries = max_entries_setting().value;
+    let max_age = max_age_setting().value;
+    let max_bytes = max_mb_setting().value.map(|mb| mb * 1024 * 1024);
+    if max_entries.is_none() && max_age.is_none() && max_bytes.is_none() {
+        return;
+    }
+
+    let limits = Limits { max_entries, max_age, max_bytes };
+    let result = rewrite(|lines| limits.apply(lines, now_secs()));
+    match result {
+        Ok(0) => {}
+        Ok(dropped) => debug!("History retention dropped {} transcripts", dropped),
+        Err(e) => warn!("Failed to apply history retention: {:#}", e),
+    }
+}
+
+/// The retention settings in effect
+struct Limits {
+    max_entries: Option<usize>,
+    max_age: Option<MaxAge>,
+    max_bytes: Option<u64>,
+}
+
+impl Limits {
+    /// The lines (oldest first) that are within every limit at Unix time `now`
+    fn apply<'a>(&self, mut lines: Vec<Line<'a>>, now: u64) -> Vec<Line<'a>> {
+        if let Some(MaxAge(age)) = self.max_age {
+            let cutoff = now.saturating_sub(age.as_secs());
+            lines.retain(|line| line.timestamp >= cutoff);
+        }
+        if let Some(max_entries) = self.max_entries {
+            let skip = lines.len().saturating_sub(max_entries);
+            lines.drain(..skip);
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            // Keep the newest lines that fit
+            let mut size = 0;
+            let fits = lines.iter().rev()
+                .take_while(|line| {
+                    size += line.text.len() as u64 + 1;
+                    size <= max_bytes
+                })
+                .count();
+            let skip = lines.len() - fits;
+            lines.drain(..skip);
+        }
+        lines
+    }
+}
+
+/// Parse a `history purge --before` value: a local date ("2024-05-31", from midnight)
+/// or an age ("7d", "12h"), as a Unix time
+pub fn parse_before(input: &str) -> Result<u64, String> {
+    if let Ok(age) = pause::parse_duration(input) {
+        return Ok(now_secs().saturating_sub(age.as_secs()));
+    }
+    let parts: Vec<&str> = input.trim().split('-').collect();
+    let [year, month, day] = parts.as_slice() else {
+        return Err(format!("invalid date '{}' (e.g. 2024-05-31, or an age like 7d)", input));
+    };
+    let (Ok(year), Ok(month), Ok(day)) = (year.parse::<i32>(), month.parse::<i32>(), day.parse::<i32>()) else {
+        return Err(format!("invalid date '{}' (e.g. 2024-05-31, or an age like 7d)", input));
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(format!("invalid date '{}'", input));
+    }
+    let time = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        tm.tm_year = year - 1900;
+        tm.tm_mon = month - 1;
+        tm.tm_mday = day;
+        tm.tm_isdst = -1;
+        libc::mktime(&mut tm)
+    };
+    if time < 0 {
+        return Err(format!("invalid date '{}'", input));
+    }
+    Ok(time as u64)
+}
+
+/// Delete transcripts recorded before `before` (a Unix time), or all of them
+pub fn purge(before: Option<u64>) -> Result<()> {
+    let removed = rewrite(|mut lines| {
+        match before {
+            Some(before) => lines.retain(|line| line.timestamp >= before),
+            None => lines.clear(),
+        }
+        lines
+    })?;
+    let path = history_path();
+    match before {
+        Some(before) => println!(
+            "Removed {} transcripts from before {} in {}",
+            removed,
+            helpers::format_local_time(before as libc::time_t, "%Y-%m-%d %H:%M"),
+            path.display()
+        ),
+        None => println!("Removed {} transcripts from {}", removed, path.display()),
+    }
+    Ok(())
+}
+
+/// Which transcripts `history` shows
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Text that the transcript, app or window title contains, ignoring case
+    pub search: Option<String>,
+    /// Unix time of the oldest transcript
+    pub since: Option<u64>,
+}
+
+impl Filter {
+    fn matches(&self, entry: &Entry) -> bool {
+        if self.since.is_some_and(|since| entry.timestamp < since) {
+            return false;
+        }
+        let Some(search) = &self.search else {
+            return true;
+        };
+        let search = search.to_lowercase();
+        [entry.response.text.as_deref(), entry.app.as_deref(), entry.window.as_deref()]
+            .into_iter()
+            .flatten()
+            .any(|field| field.to_lowercase().contains(&search))
+    }
+}
+
+/// The last `limit` entries the filter matches, oldest first
+pub fn read_recent(limit: usize, filter: &Filter) -> Result<Vec<Entry>> {
+    let path = history_path();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    Ok(recent(&content, limit, filter))
+}
+
+fn recent(content: &str, limit: usize, filter: &Filter) -> Vec<Entry> {
+    // Skip lines that don't parse (e.g. a write cut short) rather than losing the whole history
+    let entries: Vec<Entry> = content.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .filter(|entry| filter.matches(entry))
+        .collect();
+    let skip = entries.len().saturating_sub(limit);
+    entries.into_iter().skip(skip).collect()
+}
+
+/// Print recent transcripts, or the raw entries with --json
+pub fn show(limit: usize, json: bool, filter: &Filter) -> Result<()> {
+    enforce_retention();
+    let entries = read_recent(limit, filter)?;
+    if entries.is_empty() && !json {
+        if filter.search.is_some() || filter.since.is_some() {
+            println!("No matching transcripts in {}", history_path().display());
+        } else {
+            println!("No transcripts in {}", history_path().display());
+        }
+        return Ok(());
+    }
+    for entry in &entries {
+        if json {
+            println!("{}", serde_json::to_string(entry)?);
+            continue;
+        }
+        let response = &entry.response;
+        let target = match (&entry.app, &entry.window) {
+            (Some(app), Some(window)) => format!("  {}: {}", app, window),
+            (Some(target), None) | (None, Some(target)) => format!("  {}", target),
+            (None, None) => String::new(),
+        };
+        // Transcripts the daemon didn't make name the fallback that did
+        let backend = match response.served_by.as_deref() {
+            Some(path) if path != "daemon" && path != entry.backend => format!("{} ({})", entry.backend, path),
+            _ => entry.backend.clone(),
+        };
+        let confidence = response.confidence.map(|c| format!("  {:.0}%", c * 100.0)).unwrap_or_default();
+        println!(
+            "{}  {} {}  {}  {}{}{}",
+            helpers::format_local_time(entry.timestamp as libc::time_t, "%Y-%m-%d %H:%M"),
+            backend,
+            response.model.as_deref().unwrap_or("-"),
+            response.language.as_deref().unwrap_or("-"),
+            response.duration.map(|d| format!("{:.1}s", d)).unwrap_or_else(|| "-".to_string()),
+            confidence,
+            target
+        );
+        println!("  {}", response.text.as_deref().unwrap_or_default());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_keep_the_confidence() {
+        let entry = Entry {
+            timestamp: 1_700_000_000,
+            backend: "whisper-cpp".to_string(),
+            app: None,
+            window: None,
+            response: TranscriptionResponse {
+                success: true,
+                text: Some("hello".to_string()),
+                confidence: Some(0.62),
+                no_speech_prob: Some(0.05),
+                ..Default::default()
+            },
+        };
+        let line = serde_json::to_string(&entry).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["confidence"], 0.62);
+        assert_eq!(value["no_speech_prob"], 0.05);
+
+        let read: Entry = serde_json::from_str(&line).unwrap();
+        assert_eq!(read.response.confidence, Some(0.62));
+        assert_eq!(read.response.no_speech_prob, Some(0.05));
+    }
+
+    fn line(timestamp: u64, text: &str, app: Option<&str>) -> String {
+        serde_json::to_string(&Entry {
+            timestamp,
+            backend: "openai".to_string(),
+            app: app.map(str::to_string),
+            window: None,
+            response: TranscriptionResponse { success: true, text: Some(text.to_string()), ..Default::default() },
+        }).unwrap()
+    }
+
+    fn texts(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.response.text.as_deref().unwrap_or_default()).collect()
+    }
+
+    #[test]
+    fn reads_the_most_recent_entries() {
+        let content = [
+            line(100, "first", None),
+            // A write cut short by a crash
+            r#"{"timestamp": 150, "backend": "open"#.to_string(),
+            line(200, "second", Some("Slack")),
+            line(300, "third", None),
+        ].join("\n");
+
+        let all = Filter::default();
+        assert_eq!(texts(&recent(&content, 10, &all)), ["first", "second", "third"]);
+        assert_eq!(texts(&recent(&content, 2, &all)), ["second", "third"]);
+        assert!(recent(&content, 0, &all).is_empty());
+        assert!(recent("", 10, &all).is_empty());
+
+        let since = Filter { since: Some(200), ..Default::default() };
+        assert_eq!(texts(&recent(&content, 10, &since)), ["second", "third"]);
+        let search = Filter { search: Some("slack".to_string()), ..Default::default() };
+        assert_eq!(texts(&recent(&content, 10, &search)), ["second"]);
+        let search = Filter { search: Some("THIRD".to_string()), ..Default::default() };
+        assert_eq!(texts(&recent(&content, 10, &search)), ["third"]);
+    }
+
+    fn limits(max_entries: Option<usize>, max_age: Option<&str>, max_bytes: Option<u64>) -> Limits {
+        let max_age = max_age.map(|age| MaxAge(pause::parse_duration(age).unwrap()));
+        Limits { max_entries, max_age, max_bytes }
+    }
+
+    fn timestamps(lines: &[Line]) -> Vec<u64> {
+        lines.iter().map(|line| line.timestamp).collect()
+    }
+
+    #[test]
+    fn retention_keeps_what_every_limit_allows() {
+        let now = 1_000_000;
+        let lines = || vec![
+            Line { timestamp: now - 7200, text: "two hours ago" },
+            Line { timestamp: now - 3600, text: "one hour ago" },
+            Line { timestamp: now - 60, text: "a minute ago" },
+            Line { timestamp: now, text: "now" },
+        ];
+
+        assert_eq!(timestamps(&limits(None, None, None).apply(lines(), now)), [now - 7200, now - 3600, now - 60, now]);
+        // The cutoff itself is still kept
+        assert_eq!(timestamps(&limits(None, Some("1h"), None).apply(lines(), now)), [now - 3600, now - 60, now]);
+        assert_eq!(timestamps(&limits(None, Some("59m"), None).apply(lines(), now)), [now - 60, now]);
+        assert_eq!(timestamps(&limits(Some(3), None, None).apply(lines(), now)), [now - 3600, now - 60, now]);
+        assert!(limits(Some(0), None, None).apply(lines(), now).is_empty());
+        // "a minute ago\n" and "now\n" are 17 bytes
+        assert_eq!(timestamps(&limits(None, None, Some(17)).apply(lines(), now)), [now - 60, now]);
+        assert_eq!(timestamps(&limits(None, None, Some(16)).apply(lines(), now)), [now]);
+        assert_eq!(timestamps(&limits(Some(3), Some("1h"), Some(4)).apply(lines(), now)), [now]);
+        // An age longer than the clock has run keeps everything
+        assert_eq!(limits(None, Some("100000d"), None).apply(lines(), now).len(), 4);
+    }
+
+    #[test]
+    fn parses_purge_cutoffs() {
+        let before = parse_before("2024-05-31").unwrap();
+        assert_eq!(helpers::format_local_time(before as libc::time_t, "%Y-%m-%d %H:%M"), "2024-05-31 00:00");
+
+        let before = parse_before("7d").unwrap();
+        let expected = now_secs() - 7 * 86400;
+        assert!(expected.abs_diff(before) <= 1, "{} vs {}", before, expected);
+
+        for invalid in ["", "yesterday", "7x", "2024-05", "2024-13-01", "2024-00-10", "2024-05-32", "2024-05-31-1", "99999999999999999999d"] {
+            assert!(parse_before(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use anyhow::{Context, Result};
+use std::fmt;
+use std::os::fd::AsFd;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+use crate::events;
+use crate::helpers;
+use crate::settings::{self, Layers, Resolved};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A point in the dictation lifecycle that can run a hook command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Event {
+    RecordStart,
+    RecordStop,
+    Transcript,
+    Error,
+}
+
+impl Event {
+    /// Key in `[hooks]`, e.g. "on_record_start"
+    fn key(self) -> &'static str {
+        match self {
+            Event::RecordStart => "on_record_start",
+            Event::RecordStop => "on_record_stop",
+            Event::Transcript => "on_transcript",
+            Event::Error => "on_error",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::RecordStart => write!(f, "record-start"),
+            Event::RecordStop => write!(f, "record-stop"),
+            Event::Transcript => write!(f, "transcript"),
+            Event::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Resolves the shell command run at an event with priority:
+/// 1. WA_HOOK_ON_RECORD_START, WA_HOOK_ON_RECORD_STOP, WA_HOOK_ON_TRANSCRIPT or WA_HOOK_ON_ERROR env var
+/// 2. User config file `[hooks]` (`on_record_start`, ...)
+/// 3. Default to none
+pub fn command_setting(event: Event) -> Resolved<Option<String>> {
+    let (name, var) = match event {
+        Event::RecordStart => ("on_record_start hook", "WA_HOOK_ON_RECORD_START"),
+        Event::RecordStop => ("on_record_stop hook", "WA_HOOK_ON_RECORD_STOP"),
+        Event::Transcript => ("on_transcript hook", "WA_HOOK_ON_TRANSCRIPT"),
+        Event::Error => ("on_error hook", "WA_HOOK_ON_ERROR"),
+    };
+    Layers::new(name)
+        .env(var, |value| (!value.trim().is_empty()).then_some(value))
+        .config(|c| {
+            let hooks = c.hooks?;
+            match event {
+                Event::RecordStart => hooks.on_record_start,
+                Event::RecordStop => hooks.on_record_stop,
+                Event::Transcript => hooks.on_transcript,
+                Event::Error => hooks.on_error,
+            }
+        })
+        .optional()
+}
+
+/// Resolves how long a hook may run before it is killed with priority:
+/// 1. WA_HOOK_TIMEOUT_SECS env var
+/// 2. User config file `hooks.timeout_secs`
+/// 3. Default to 10
+pub fn timeout_secs_setting() -> Resolved<u64> {
+    Layers::new("hook timeout")
+        .env("WA_HOOK_TIMEOUT_SECS", settings::parsed)
+        .config(|c| c.hooks?.timeout_secs)
+        .or_default(10)
+}
+
+/// Broadcast the event to `events --follow` and run its hook, if one is set, without
+/// waiting for it
+/// `vars` are WHISP_AWAY_* metadata on top of the event, backend and model, e.g.
+/// ("AUDIO_FILE", path); a hook that can't be started is only logged
+pub fn fire(event: Event, vars: &[(&str, &str)]) {
+    events::broadcast(event, vars);
+    if command_setting(event).value.is_none() {
+        return;
+    }
+    if let Err(e) = spawn_runner(event, vars) {
+        warn!("Failed to run the {} hook: {:#}", event.key(), e);
+    }
+}
+
+/// Fire the error hook when recording or transcribing failed, passing the result on
+pub fn on_error<T>(result: Result<T>) -> Result<T> {
+    if let Err(e) = &result {
+        fire(Event::Error, &[("ERROR", &format!("{:#}", e))]);
+    }
+    result
+}
+
+/// The hook runs under a detached `whisp-away run-hook`, which enforces the timeout after
+/// the command that fired it has exited
+fn spawn_runner(event: Event, vars: &[(&str, &str)]) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to locate whisp-away executable")?;
+    // Nothing may reach stdout, which carries transcripts for `record --foreground` and `--json`
+    let stderr = std::io::stderr().as_fd().try_clone_to_owned().context("Failed to pass on stderr")?;
+    let mut command = Command::new(exe);
+    command
+        .args(["run-hook", &event.to_string()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(stderr))
+        .env("WHISP_AWAY_EVENT", event.to_string())
+        .env("WHISP_AWAY_BACKEND", helpers::resolve_backend())
+        .env("WHISP_AWAY_MODEL", helpers::resolve_model())
+        // Own process group so the hook outlives the hotkey invocation
+        .process_group(0);
+    for (name, value) in vars {
+        command.env(format!("WHISP_AWAY_{}", name), value);
+    }
+    let child = command.spawn().context("Failed to start hook runner")?;
+    debug!("Hook runner for {} started (PID: {})", event, child.id());
+    Ok(())
+}
+
+/// Runner: run the event's hook with `sh -c`, killing it once the timeout passes
+/// Failures are logged to the stderr of the command that fired it
+pub fn run(event: Event) -> Result<()> {
+    let Some(hook) = command_setting(event).value else {
+        return Ok(());
+    };
+    let timeout = Duration::from_secs(timeout_secs_setting().value);
+    let started = Instant::now();
+    debug!("Running {} hook: {}", event.key(), hook);
+
+    let mut child = Command::new("sh")
+        .args(["-c", &hook])
+        .stdin(Stdio::null())
+        // Its own group, so a timeout also kills what the shell started
+        .process_group(0)
+        .spawn()
+        .with_context(|| format!("Failed to run {} hook '{}'", event.key(), hook))?;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            if status.success() {
+                debug!("{} hook finished in {:?}", event.key(), started.elapsed());
+            } else {
+                warn!("{} hook '{}' failed ({})", event.key(), hook, status);
+            }
+            return Ok(());
+        }
+        if started.elapsed() >= timeout {
+            warn!("{} hook '{}' still running after {}s, killing it", event.key(), hook, timeout.as_secs());
+            unsafe { libc::kill(-(child.id() as i32), libc::SIGTERM) };
+            let _ = child.wait();
+            return Ok(());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,51 @@
+use tracing::debug;
+use crate::paths;
+use crate::settings::{Layers, Resolved};
+
+/// Names the daemon instance commands talk to; kept in the environment so the daemons,
+/// workers and helpers a command starts use the same instance
+pub const INSTANCE_VAR: &str = "WA_INSTANCE";
+
+/// Check an instance name given with --instance or in a profile; it ends up in file names
+pub fn parse_name(name: &str) -> Result<String, String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("invalid instance name '{}' (use letters, digits, '-' and '_')", name));
+    }
+    Ok(name.to_string())
+}
+
+/// Use the named instance for the rest of this invocation
+pub fn select(name: &str) {
+    debug!("Using daemon instance '{}'", name);
+    std::env::set_var(INSTANCE_VAR, name);
+}
+
+/// Resolves the daemon instance with priority:
+/// 1. --instance flag, or the selected profile's `instance` (both kept in WA_INSTANCE)
+/// 2. WA_INSTANCE env var
+/// 3. Default to none, the default daemon
+pub fn setting() -> Resolved<Option<String>> {
+    Layers::new("instance")
+        .env(INSTANCE_VAR, |name| parse_name(&name).ok())
+        .optional()
+}
+
+/// The selected instance; None for the default daemon
+pub fn name() -> Option<String> {
+    setting().value
+}
+
+/// Appended to the names of an instance's files: "" for the default daemon, "-<name>" otherwise
+pub fn suffix() -> String {
+    name().map(|name| format!("-{}", name)).unwrap_or_default()
+}
+
+/// A file of the selected instance in the runtime directory, e.g. whisp-away-daemon-work.json
+pub fn runtime_path(stem: &str, extension: &str) -> String {
+    format!("{}/{}{}.{}", paths::runtime_dir(), stem, suffix(), extension)
+}
+
+/// The socket the selected instance's daemon listens on unless one is set
+pub fn default_socket_path() -> String {
+    format!("/tmp/whisp-away-daemon{}.sock", suffix())
+}
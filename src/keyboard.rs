@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::process::Command;
+use tracing::{debug, trace};
+use crate::settings::{self, Layers, Resolved};
+
+/// Layouts where xdotool/wtype keycode mapping reliably produces the intended characters
+const TYPE_SAFE_LAYOUTS: &[&str] = &["us", "english (us)", "gb", "english (uk)"];
+
+/// Whisper languages of keyboard layouts: the xkb layout code, the first word of the
+/// layout's name as compositors show it, and the language
+const LAYOUT_LANGUAGES: &[(&str, &str, &str)] = &[
+    ("us", "english", "en"),
+    ("gb", "english", "en"),
+    ("de", "german", "de"),
+    ("at", "german", "de"),
+    ("ch", "swiss", "de"),
+    ("fr", "french", "fr"),
+    ("be", "belgian", "fr"),
+    ("es", "spanish", "es"),
+    ("latam", "spanish", "es"),
+    ("pt", "portuguese", "pt"),
+    ("br", "portuguese", "pt"),
+    ("it", "italian", "it"),
+    ("nl", "dutch", "nl"),
+    ("se", "swedish", "sv"),
+    ("no", "norwegian", "no"),
+    ("dk", "danish", "da"),
+    ("fi", "finnish", "fi"),
+    ("is", "icelandic", "is"),
+    ("pl", "polish", "pl"),
+    ("cz", "czech", "cs"),
+    ("sk", "slovak", "sk"),
+    ("hu", "hungarian", "hu"),
+    ("ro", "romanian", "ro"),
+    ("si", "slovenian", "sl"),
+    ("hr", "croatian", "hr"),
+    ("rs", "serbian", "sr"),
+    ("ba", "bosnian", "bs"),
+    ("mk", "macedonian", "mk"),
+    ("bg", "bulgarian", "bg"),
+    ("gr", "greek", "el"),
+    ("tr", "turkish", "tr"),
+    ("ru", "russian", "ru"),
+    ("ua", "ukrainian", "uk"),
+    ("by", "belarusian", "be"),
+    ("ee", "estonian", "et"),
+    ("lv", "latvian", "lv"),
+    ("lt", "lithuanian", "lt"),
+    ("il", "hebrew", "he"),
+    ("ara", "arabic", "ar"),
+    ("ir", "persian", "fa"),
+    ("in", "indian", "hi"),
+    ("th", "thai", "th"),
+    ("vn", "vietnamese", "vi"),
+    ("cn", "chinese", "zh"),
+    ("jp", "japanese", "ja"),
+    ("kr", "korean", "ko"),
+];
+
+/// Resolves whether the transcription language follows the active keyboard layout with priority:
+/// 1. WA_LAYOUT_LANGUAGE env var
+/// 2. User config file `layout_language`
+/// 3. Default to false
+pub fn layout_language_setting() -> Resolved<bool> {
+    Layers::new("layout language")
+        .env("WA_LAYOUT_LANGUAGE", settings::flag)
+        .config(|c| c.layout_language)
+        .or_default(false)
+}
+
+/// The language of the active keyboard layout, when `layout_language` is on and the layout
+/// is one it knows; `layout_languages` in the config file adds to or overrides the built-in table
+pub fn layout_language() -> Option<String> {
+    if !layout_language_setting().value {
+        return None;
+    }
+    let layout = active_layout()?;
+    let overrides = Layers::new("layout languages")
+        .config(|c| c.layout_languages)
+        .or_default(BTreeMap::new())
+        .value;
+    let language = language_of_layout(&layout, &overrides);
+    if language.is_none() {
+        debug!("No language known for keyboard layout '{}'", layout);
+    }
+    language
+}
+
+/// Look a layout up by its full name in `overrides`, then by xkb code or name in the
+/// built-in table ("de(nodeadkeys)" and "German (no dead keys)" are both German)
+fn language_of_layout(layout: &str, overrides: &BTreeMap<String, String>) -> Option<String> {
+    let layout = layout.trim();
+    if let Some((_, language)) = overrides.iter().find(|(name, _)| name.eq_ignore_ascii_case(layout)) {
+        return Some(language.clone());
+    }
+    let layout = layout.to_lowercase();
+    let code = layout.split(['(', ':', '+']).next().unwrap_or_default().trim();
+    let name = layout.split(|c: char| c.is_whitespace() || c == '(').next().unwrap_or_default();
+    LAYOUT_LANGUAGES.iter()
+        .find(|(layout_code, _, _)| *layout_code == code)
+        .or_else(|| LAYOUT_LANGUAGES.iter().find(|(_, layout_name, _)| *layout_name == name))
+        .map(|(_, _, language)| language.to_string())
+}
+
+/// Detect the active keyboard layout, trying Hyprland, Sway, niri and X11 in turn
+/// Returns the layout name as reported by the compositor (e.g. "English (US)" or "us")
+pub fn active_layout() -> Option<String> {
+    let layout = hyprland_layout()
+        .or_else(sway_layout)
+        .or_else(niri_layout)
+        .or_else(x11_layout);
+    debug!("Detected keyboard layout: {:?}", layout);
+    layout
+}
+
+/// Whether direct typing is known to work on this layout
+pub fn is_type_safe(layout: &str) -> bool {
+    let layout = layout.trim().to_lowercase();
+    TYPE_SAFE_LAYOUTS.contains(&layout.as_str())
+}
+
+/// Stdout of a command, None when it can't be run or fails
+pub fn command_stdout(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        trace!("{} exited with {}", program, output.status);
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn hyprland_layout() -> Option<String> {
+    std::env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
+    let json: serde_json::Value = serde_json::from_str(&command_stdout("hyprctl", &["devices", "-j"])?).ok()?;
+    let keyboards = json.get("keyboards")?.as_array()?;
+    let keyboard = keyboards.iter()
+        .find(|k| k.get("main").and_then(|m| m.as_bool()).unwrap_or(false))
+        .or_else(|| keyboards.first())?;
+    keyboard.get("active_keymap")?.as_str().map(str::to_string)
+}
+
+fn sway_layout() -> Option<String> {
+    std::env::var("SWAYSOCK").ok()?;
+    let json: serde_json::Value = serde_json::from_str(&command_stdout("swaymsg", &["-t", "get_inputs", "-r"])?).ok()?;
+    json.as_array()?.iter()
+        .filter(|input| input.get("type").and_then(|t| t.as_str()) == Some("keyboard"))
+        .find_map(|input| input.get("xkb_active_layout_name")?.as_str().map(str::to_string))
+}
+
+fn niri_layout() -> Option<String> {
+    std::env::var("NIRI_SOCKET").ok()?;
+    let json: serde_json::Value = serde_json::from_str(&command_stdout("niri", &["msg", "--json", "keyboard-layouts"])?).ok()?;
+    let current = json.get("current_idx")?.as_u64()? as usize;
+    json.get("names")?.as_array()?.get(current)?.as_str().map(str::to_string)
+}
+
+fn x11_layout() -> Option<String> {
+    std::env::var("DISPLAY").ok()?;
+    // xkb-switch knows the active group, when it is installed
+    if let Some(layout) = command_stdout("xkb-switch", &["-p"]).filter(|layout| !layout.trim().is_empty()) {
+        return Some(layout.trim().to_string());
+    }
+    let query = command_stdout("setxkbmap", &["-query"])?;
+    query.lines()
+        .find_map(|line| line.strip_prefix("layout:"))
+        // setxkbmap can't tell which group is active, assume the first
+        .and_then(|layouts| layouts.trim().split(',').next())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn language(layout: &str) -> Option<String> {
+        language_of_layout(layout, &BTreeMap::new())
+    }
+
+    #[test]
+    fn knows_layouts_by_code_and_name() {
+        assert_eq!(language("us").as_deref(), Some("en"));
+        assert_eq!(language(" ru ").as_deref(), Some("ru"));
+        assert_eq!(language("de(nodeadkeys)").as_deref(), Some("de"));
+        assert_eq!(language("ua+us").as_deref(), Some("uk"));
+        assert_eq!(language("German (no dead keys)").as_deref(), Some("de"));
+        assert_eq!(language("English (US)").as_deref(), Some("en"));
+        assert_eq!(language("Russian (phonetic)").as_deref(), Some("ru"));
+        assert_eq!(language("PORTUGUESE (Brazil)").as_deref(), Some("pt"));
+        assert_eq!(language("Klingon"), None);
+        assert_eq!(language(""), None);
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let overrides = BTreeMap::from([
+            ("english (intl., with altgr dead keys)".to_string(), "nl".to_string()),
+            ("Colemak".to_string(), "en".to_string()),
+        ]);
+        assert_eq!(language_of_layout("English (intl., with AltGr dead keys)", &overrides).as_deref(), Some("nl"));
+        assert_eq!(language_of_layout("colemak", &overrides).as_deref(), Some("en"));
+        // Other layouts still come from the built-in table
+        assert_eq!(language_of_layout("English (US)", &overrides).as_deref(), Some("en"));
+        assert_eq!(language_of_layout("fr", &overrides).as_deref(), Some("fr"));
+    }
+}
@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::debug;
+use crate::failover::Attempt;
+use crate::settings::{self, Layers, Resolved};
+
+/// Resolves whether a timing breakdown is added to the result notification and the JSON
+/// output with priority:
+/// 1. WA_LATENCY_BREAKDOWN env var
+/// 2. User config file `latency_breakdown`
+/// 3. Default to false
+pub fn breakdown_setting() -> Resolved<bool> {
+    Layers::new("latency breakdown")
+        .env("WA_LATENCY_BREAKDOWN", settings::flag)
+        .config(|c| c.latency_breakdown)
+        .or_default(false)
+}
+
+/// Where the time between stopping a dictation and its text appearing went
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Breakdown {
+    /// From the stop command until the recording is finished and downmixed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_ready_secs: Option<f64>,
+    /// Spent on ways of transcribing that failed before one worked (e.g. a daemon that
+    /// timed out)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_secs: Option<f64>,
+    /// From sending the audio to the daemon (or API) until its answer, inference included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub round_trip_secs: Option<f64>,
+    /// What the daemon reports spending on the transcription itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inference_secs: Option<f64>,
+    /// Typing, pasting or copying the text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typing_secs: Option<f64>,
+    /// From the stop command until now
+    pub total_secs: f64,
+    /// The ways of transcribing tried, for the notification (the JSON output has them as
+    /// `attempts` already)
+    #[serde(skip)]
+    pub attempts: Vec<Attempt>,
+}
+
+impl Breakdown {
+    /// One line for notifications, e.g. "audio 0.12s · daemon failed 5.00s · whisper-cpp 1.84s ·
+    /// round trip 0.85s (inference 0.70s) · typing 0.40s · total 1.45s"
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(secs) = self.audio_ready_secs {
+            parts.push(format!("audio {:.2}s", secs));
+        }
+        for attempt in &self.attempts {
+            match attempt.error {
+                Some(_) => parts.push(format!("{} failed {:.2}s", attempt.path, attempt.secs)),
+                None => parts.push(format!("{} {:.2}s", attempt.path, attempt.secs)),
+            }
+        }
+        if let Some(secs) = self.fallback_secs.filter(|_| self.attempts.is_empty()) {
+            parts.push(format!("failed attempts {:.2}s", secs));
+        }
+        if let Some(secs) = self.round_trip_secs {
+            match self.inference_secs {
+                Some(inference) => parts.push(format!("round trip {:.2}s (inference {:.2}s)", secs, inference)),
+                None => parts.push(format!("round trip {:.2}s", secs)),
+            }
+        }
+        if let Some(secs) = self.typing_secs {
+            parts.push(format!("typing {:.2}s", secs));
+        }
+        parts.push(format!("total {:.2}s", self.total_secs));
+        parts.join(" · ")
+    }
+}
+
+/// When each stage of the dictation in this process happened
+struct Clock {
+    stopped: Instant,
+    audio_ready: Option<Instant>,
+    request_sent: Option<Instant>,
+    response_received: Option<Instant>,
+    inference_secs: Option<f64>,
+    attempts: Vec<Attempt>,
+    /// Typing finished so far (streamed segments are typed in several goes)
+    typed: Option<Duration>,
+    typing_since: Option<Instant>,
+}
+
+/// None until a recording is stopped in this process
+static CLOCK: Mutex<Option<Clock>> = Mutex::new(None);
+
+fn mark(update: impl FnOnce(&mut Clock)) {
+    if let Some(clock) = CLOCK.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        update(clock);
+    }
+}
+
+/// The stop command started; the breakdown counts from here
+pub fn stop_requested() {
+    *CLOCK.lock().unwrap_or_else(|e| e.into_inner()) = Some(Clock {
+        stopped: Instant::now(),
+        audio_ready: None,
+        request_sent: None,
+        response_received: None,
+        inference_secs: None,
+        attempts: Vec::new(),
+        typed: None,
+        typing_since: None,
+    });
+}
+
+pub fn audio_ready() {
+    mark(|clock| clock.audio_ready = Some(Instant::now()));
+}
+
+pub fn request_sent() {
+    mark(|clock| clock.request_sent = Some(Instant::now()));
+}
+
+pub fn response_received(inference_secs: Option<f64>) {
+    mark(|clock| {
+        clock.response_received = Some(Instant::now());
+        clock.inference_secs = inference_secs;
+    });
+}
+
+/// The ways of transcribing tried, ending with the one that worked
+pub fn attempts(attempts: &[Attempt]) {
+    mark(|clock| clock.attempts = attempts.to_vec());
+}
+
+pub fn typing_started() {
+    mark(|clock| clock.typing_since = Some(Instant::now()));
+}
+
+pub fn typing_finished() {
+    mark(|clock| {
+        if let Some(since) = clock.typing_since.take() {
+            clock.typed = Some(clock.typed.unwrap_or_default() + since.elapsed());
+        }
+    });
+}
+
+/// The breakdown so far (typing still under way counts up to now); None when it is off or
+/// nothing was stopped in this process
+pub fn breakdown() -> Option<Breakdown> {
+    if !breakdown_setting().value {
+        return None;
+    }
+    let guard = CLOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let clock = guard.as_ref()?;
+    let stopped = clock.stopped;
+    let typing = match clock.typing_since {
+        Some(since) => Some(clock.typed.unwrap_or_default() + since.elapsed()),
+        None => clock.typed,
+    };
+    let failed: f64 = clock.attempts.iter().filter(|attempt| attempt.error.is_some()).map(|attempt| attempt.secs).sum();
+    let breakdown = Breakdown {
+        audio_ready_secs: clock.audio_ready.map(|ready| round_ms(ready - stopped)),
+        fallback_secs: (failed > 0.0).then(|| (failed * 1000.0).round() / 1000.0),
+        round_trip_secs: clock.request_sent.zip(clock.response_received).map(|(sent, received)| round_ms(received - sent)),
+        inference_secs: clock.inference_secs.map(|secs| (secs * 1000.0).round() / 1000.0),
+        typing_secs: typing.map(round_ms),
+        total_secs: round_ms(stopped.elapsed()),
+        attempts: clock.attempts.clone(),
+    };
+    debug!("Latency breakdown: {:?}", breakdown);
+    Some(breakdown)
+}
+
+pub fn round_ms(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_lists_each_attempt() {
+        let breakdown = Breakdown {
+            audio_ready_secs: Some(0.12),
+            fallback_secs: Some(5.0),
+            typing_secs: Some(0.4),
+            total_secs: 7.4,
+            attempts: vec![
+                Attempt { path: "daemon".to_string(), secs: 5.0, error: Some("Timed out".to_string()) },
+                Attempt { path: "whisper-cpp".to_string(), secs: 1.84, error: None },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(breakdown.summary(), "audio 0.12s · daemon failed 5.00s · whisper-cpp 1.84s · typing 0.40s · total 7.40s");
+
+        // Read back from `--json` output, which keeps the attempts apart
+        let breakdown = Breakdown { attempts: Vec::new(), ..breakdown };
+        assert_eq!(breakdown.summary(), "audio 0.12s · failed attempts 5.00s · typing 0.40s · total 7.40s");
+    }
+}
@@ -0,0 +1,137 @@
+use tracing::debug;
+use crate::settings::{self, Layers, Resolved};
+use crate::socket::Segment;
+use crate::uncertain::{core, find_word};
+
+/// Resolves the pause after which the transcript goes on in a new line with priority:
+/// 1. WA_LINE_BREAK_PAUSE_SECS env var
+/// 2. User config file `line_breaks.pause_secs`
+/// 3. Default to none, one paragraph
+pub fn pause_secs_setting() -> Resolved<Option<f64>> {
+    Layers::new("line break pause")
+        .env("WA_LINE_BREAK_PAUSE_SECS", settings::parsed)
+        .config(|c| c.line_breaks?.pause_secs)
+        .optional()
+}
+
+/// Resolves whether a new line starts where the speaker changes with priority:
+/// 1. WA_LINE_BREAK_SPEAKER env var
+/// 2. User config file `line_breaks.speaker_change`
+/// 3. Default to false
+pub fn speaker_change_setting() -> Resolved<bool> {
+    Layers::new("line break on speaker change")
+        .env("WA_LINE_BREAK_SPEAKER", settings::flag)
+        .config(|c| c.line_breaks?.speaker_change)
+        .or_default(false)
+}
+
+pub fn enabled() -> bool {
+    pause_secs_setting().value.is_some() || speaker_change_setting().value
+}
+
+/// The post-processed transcript with a new line at every pause longer than `pause_secs`
+/// and, with `speaker_change`, wherever the daemon reports another speaker
+/// Backends without segments (or speakers) leave the text as it is
+pub fn apply(text: &str, segments: &[Segment]) -> String {
+    if !enabled() {
+        return text.to_string();
+    }
+    break_lines(text, segments, pause_secs_setting().value, speaker_change_setting().value)
+}
+
+/// Whether `segment` starts a new turn after `previous`
+fn new_turn(previous: &Segment, segment: &Segment, pause_secs: Option<f64>, speaker_change: bool) -> bool {
+    let paused = pause_secs.is_some_and(|pause_secs| segment.start - previous.end >= pause_secs);
+    let changed = speaker_change && matches!(
+        (&previous.speaker, &segment.speaker),
+        (Some(previous), Some(speaker)) if previous != speaker
+    );
+    paused || changed
+}
+
+/// Like marking uncertain words, segments are found in the post-processed text by their
+/// words in order; a turn whose first word post-processing changed isn't broken
+fn break_lines(text: &str, segments: &[Segment], pause_secs: Option<f64>, speaker_change: bool) -> String {
+    let mut breaks = Vec::new();
+    let mut at = 0;
+    let mut previous: Option<&Segment> = None;
+    for segment in segments {
+        let mut turn = previous.is_some_and(|previous| new_turn(previous, segment, pause_secs, speaker_change));
+        previous = Some(segment);
+        for word in segment.text.split_whitespace().map(core).filter(|word| !word.is_empty()) {
+            let Some(start) = find_word(text, word, at) else {
+                turn = false;
+                continue;
+            };
+            if std::mem::take(&mut turn) {
+                breaks.push(start);
+            }
+            at = start + word.len();
+        }
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    for start in &breaks {
+        // An opening quote or bracket goes to the new line with its word
+        let start = text[..*start].trim_end_matches(|c: char| !c.is_alphanumeric() && !c.is_whitespace()).len();
+        let line = text[copied..start].trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        out.push_str(line);
+        out.push('\n');
+        copied = start;
+    }
+    out.push_str(&text[copied..]);
+    if !breaks.is_empty() {
+        debug!("Broke the transcript into {} lines", breaks.len() + 1);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: f64, end: f64, text: &str, speaker: Option<&str>) -> Segment {
+        Segment { start, end, text: text.to_string(), speaker: speaker.map(str::to_string), ..Default::default() }
+    }
+
+    #[test]
+    fn breaks_at_long_pauses() {
+        let segments = [
+            segment(0.0, 2.0, " How did you start?", None),
+            segment(4.5, 6.0, " By accident, really.", None),
+            segment(6.3, 8.0, " I was a teacher.", None),
+        ];
+        assert_eq!(
+            break_lines("How did you start? By accident, really. I was a teacher.", &segments, Some(1.5), false),
+            "How did you start?\nBy accident, really. I was a teacher."
+        );
+    }
+
+    #[test]
+    fn breaks_where_the_speaker_changes() {
+        let segments = [
+            segment(0.0, 2.0, " Ready?", Some("SPEAKER_00")),
+            segment(2.1, 3.0, " \"Always,\" she said.", Some("SPEAKER_01")),
+        ];
+        assert_eq!(break_lines("Ready? \"Always,\" she said.", &segments, None, true), "Ready?\n\"Always,\" she said.");
+        assert_eq!(break_lines("Ready? \"Always,\" she said.", &segments, None, false), "Ready? \"Always,\" she said.");
+    }
+
+    #[test]
+    fn skips_turns_post_processing_changed() {
+        let segments = [
+            segment(0.0, 2.0, " Write to", None),
+            segment(5.0, 6.0, " john at example dot com", None),
+        ];
+        assert_eq!(break_lines("Write to john@example.com", &segments, Some(1.0), false), "Write to\njohn@example.com");
+        let segments = [
+            segment(0.0, 2.0, " Write to", None),
+            segment(5.0, 6.0, " five apples", None),
+        ];
+        assert_eq!(break_lines("Write to 5 apples", &segments, Some(1.0), false), "Write to 5 apples");
+    }
+}
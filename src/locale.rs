@@ -0,0 +1,303 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+use regex::{Captures, Regex};
+use tracing::{debug, warn};
+use crate::helpers;
+use crate::privacy;
+use crate::settings::{Layers, Resolved};
+
+/// Narrow no-break space: French puts it before `; : ! ?`, inside guillemets and between
+/// thousands
+const NARROW_NBSP: char = '\u{202F}';
+
+/// Writing conventions text is normalized to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    /// "quotes", 1,234.56
+    En,
+    /// „Anführungszeichen“, 1.234,56 and 16.10.2026
+    De,
+    /// « guillemets », 1 234,56 and a narrow space before `; : ! ?`
+    Fr,
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    /// Accepts a language code, with or without a region ("de", "de-AT", "fr_CH")
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let language = s.split(['-', '_']).next().unwrap_or_default().to_lowercase();
+        match language.as_str() {
+            "en" => Ok(Locale::En),
+            "de" => Ok(Locale::De),
+            "fr" => Ok(Locale::Fr),
+            _ => Err(format!("no text conventions for '{}' (known: en, de, fr)", s)),
+        }
+    }
+}
+
+/// Resolves which conventions transcripts follow with priority:
+/// 1. WA_LOCALE env var
+/// 2. User config file `locale`
+/// 3. Default to "auto": those of the transcript's language ("off" leaves the text alone)
+pub fn locale_setting() -> Resolved<String> {
+    Layers::new("locale")
+        .env("WA_LOCALE", Some)
+        .config(|c| c.locale)
+        .or_default("auto".to_string())
+}
+
+/// The conventions for a transcript in `language` (the detected one, if the backend
+/// reported it, else the transcription language); None for languages without any
+fn for_language(language: Option<&str>) -> Option<Locale> {
+    let setting = locale_setting().value;
+    match setting.as_str() {
+        "off" => None,
+        "auto" => {
+            let language = language.map(str::to_string).unwrap_or_else(helpers::resolve_language);
+            language.parse().ok()
+        }
+        chosen => match chosen.parse() {
+            Ok(locale) => Some(locale),
+            Err(e) => {
+                warn!("Ignoring locale: {}", e);
+                None
+            }
+        },
+    }
+}
+
+/// Write quotes, numbers, dates and the spacing around punctuation the way the
+/// transcript's language does, e.g. `"Das kostet 1,234.50"` becomes `„Das kostet 1.234,50“`
+pub fn normalize(text: &str, language: Option<&str>) -> String {
+    let Some(locale) = for_language(language) else {
+        return text.to_string();
+    };
+    let normalized = numbers(&dates(&spacing(&quotes(text, locale), locale), locale), locale);
+    if normalized != text {
+        debug!("Normalized for {:?}: '{}' -> '{}'", locale, privacy::redact(text), privacy::redact(&normalized));
+    }
+    normalized
+}
+
+/// Double quotes as the locale writes them; a quote opens at the start or after a space or
+/// bracket, and closes otherwise, so a segment on its own gets them right too
+fn quotes(text: &str, locale: Locale) -> String {
+    let (open, close) = match locale {
+        Locale::En => return text.to_string(),
+        Locale::De => ('„', '“'),
+        Locale::Fr => ('«', '»'),
+    };
+    let mut out = String::with_capacity(text.len());
+    let mut previous: Option<char> = None;
+    for c in text.chars() {
+        if matches!(c, '"' | '“' | '”') {
+            let opens = previous.is_none_or(|p| p.is_whitespace() || "([{".contains(p));
+            out.push(if opens { open } else { close });
+        } else {
+            out.push(c);
+        }
+        previous = Some(c);
+    }
+    out
+}
+
+/// Punctuation that follows a word without a space in English and German; French puts a
+/// narrow space before the last four
+const PUNCTUATION: &str = ".,;:!?";
+const FRENCH_SPACED: &str = ";:!?»";
+
+/// Whether the punctuation starting at `i` stands on its own: followed by a space, the end
+/// or a closing quote or bracket, unlike the colon of "10:30" or the dot of ".com"
+fn ends_cleanly(chars: &[char], i: usize) -> bool {
+    let mut j = i;
+    while chars.get(j).is_some_and(|c| PUNCTUATION.contains(*c) || *c == '…') {
+        j += 1;
+    }
+    chars.get(j).is_none_or(|c| c.is_whitespace() || ")]}»“\"".contains(*c))
+}
+
+/// Spaces before punctuation dropped, and for French the narrow space before `; : ! ?`
+/// and inside guillemets
+fn spacing(text: &str, locale: Locale) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let french = locale == Locale::Fr;
+    let mut out = String::with_capacity(text.len());
+    for (i, &c) in chars.iter().enumerate() {
+        if c == ' ' || c == NARROW_NBSP {
+            let next = chars[i + 1..].iter().position(|c| *c != ' ').map(|offset| i + 1 + offset);
+            let before_punctuation = next.is_some_and(|at| {
+                (PUNCTUATION.contains(chars[at]) && ends_cleanly(&chars, at)) || (french && chars[at] == '»')
+            });
+            // The narrow space after « is already there
+            if (before_punctuation && !out.is_empty()) || out.ends_with(NARROW_NBSP) {
+                continue;
+            }
+        }
+        let last = out.chars().last();
+        if french
+            && FRENCH_SPACED.contains(c)
+            && last.is_some_and(|last| !last.is_whitespace() && !FRENCH_SPACED.contains(last) && last != '«')
+            && (c == '»' || ends_cleanly(&chars, i))
+        {
+            out.push(NARROW_NBSP);
+        }
+        out.push(c);
+        if french && c == '«' {
+            out.push(NARROW_NBSP);
+        }
+    }
+    out
+}
+
+/// German writes a date with dots: 16/10/2026 becomes 16.10.2026
+/// Only dates that can only be read day first change: 10/16/2026 is American and
+/// 5/10/2026 could be either, so both stay as they are
+fn dates(text: &str, locale: Locale) -> String {
+    static DATE: OnceLock<Regex> = OnceLock::new();
+    if locale != Locale::De {
+        return text.to_string();
+    }
+    let date = DATE.get_or_init(|| Regex::new(r"\b(\d{1,2})/(\d{1,2})/(\d{4}|\d{2})\b").expect("valid date pattern"));
+    date.replace_all(text, |captures: &Captures| {
+        let day: u32 = captures[1].parse().expect("digits");
+        let month: u32 = captures[2].parse().expect("digits");
+        if (13..=31).contains(&day) && (1..=12).contains(&month) {
+            format!("{}.{}.{}", &captures[1], &captures[2], &captures[3])
+        } else {
+            captures[0].to_string()
+        }
+    }).into_owned()
+}
+
+/// Numbers the transcriber wrote the English way, with the locale's decimal and
+/// thousands separators
+/// Only unambiguous ones change: "1,234" and "1.234" could be either convention, and
+/// "1.2.3" or "17.30 Uhr" aren't amounts
+fn numbers(text: &str, locale: Locale) -> String {
+    static NUMBER: OnceLock<Regex> = OnceLock::new();
+    let (thousands, decimal) = match locale {
+        Locale::En => return text.to_string(),
+        Locale::De => ('.', ','),
+        Locale::Fr => (NARROW_NBSP, ','),
+    };
+    let number = NUMBER.get_or_init(|| Regex::new(r"\d[\d.,]*\d").expect("valid number pattern"));
+    number.replace_all(text, |captures: &Captures| {
+        let found = captures.get(0).expect("whole match");
+        let token = found.as_str();
+        let before = text[..found.start()].chars().last();
+        let after = &text[found.end()..];
+        let part_of_word = before.is_some_and(|c| c.is_alphanumeric() || c == '.' || c == ',');
+        let time = after.starts_with(" Uhr") || after.starts_with(" h ");
+        match english_number(token) {
+            Some((integer, fraction)) if !part_of_word && !time => {
+                let mut converted = String::new();
+                for (i, group) in integer.iter().enumerate() {
+                    if i > 0 {
+                        converted.push(thousands);
+                    }
+                    converted.push_str(group);
+                }
+                if let Some(fraction) = fraction {
+                    converted.push(decimal);
+                    converted.push_str(fraction);
+                }
+                converted
+            }
+            _ => token.to_string(),
+        }
+    }).into_owned()
+}
+
+/// The thousands groups and decimals of an unambiguously English number: "1,234.5",
+/// "1,234,567" or "3.75"
+fn english_number(token: &str) -> Option<(Vec<&str>, Option<&str>)> {
+    let (integer, fraction) = match token.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (token, None),
+    };
+    if fraction.is_some_and(|fraction| fraction.is_empty() || !fraction.chars().all(|c| c.is_ascii_digit())) {
+        return None;
+    }
+    let groups: Vec<&str> = integer.split(',').collect();
+    let grouped = groups.len() > 1;
+    let well_formed = !groups[0].is_empty()
+        && (!grouped || groups[0].len() <= 3)
+        && groups.iter().all(|group| group.chars().all(|c| c.is_ascii_digit()))
+        && groups[1..].iter().all(|group| group.len() == 3);
+    let unambiguous = match fraction {
+        // "1.234" is a thousand in German
+        Some(fraction) => grouped || fraction.len() != 3,
+        // "1,234" is a decimal in German
+        None => groups.len() > 2,
+    };
+    (well_formed && unambiguous).then_some((groups, fraction))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_languages() {
+        assert_eq!("de".parse(), Ok(Locale::De));
+        assert_eq!("fr_CH".parse(), Ok(Locale::Fr));
+        assert_eq!("en-GB".parse(), Ok(Locale::En));
+        assert!("ja".parse::<Locale>().is_err());
+    }
+
+    #[test]
+    fn quotes_german_and_french() {
+        assert_eq!(quotes("Er sagte \"Hallo\" und ging.", Locale::De), "Er sagte „Hallo“ und ging.");
+        assert_eq!(quotes("“Ja”, sagte sie.", Locale::De), "„Ja“, sagte sie.");
+        assert_eq!(quotes("Il a dit \"bonjour\".", Locale::Fr), "Il a dit «bonjour».");
+        assert_eq!(quotes("He said \"hi\".", Locale::En), "He said \"hi\".");
+        assert_eq!(quotes("it's", Locale::De), "it's");
+    }
+
+    #[test]
+    fn spaces_punctuation() {
+        assert_eq!(spacing("Hello , world !", Locale::En), "Hello, world!");
+        assert_eq!(spacing("Wirklich ?", Locale::De), "Wirklich?");
+        assert_eq!(spacing("Vraiment? Oui !", Locale::Fr), "Vraiment\u{202F}? Oui\u{202F}!");
+        assert_eq!(spacing("Quoi?!", Locale::Fr), "Quoi\u{202F}?!");
+        assert_eq!(spacing("Il a dit «bonjour».", Locale::Fr), "Il a dit «\u{202F}bonjour\u{202F}».");
+        assert_eq!(spacing("« Oui »", Locale::Fr), "«\u{202F}Oui\u{202F}»");
+    }
+
+    #[test]
+    fn leaves_times_and_addresses_alone() {
+        assert_eq!(spacing("À 10:30 sur https://example.com", Locale::Fr), "À 10:30 sur https://example.com");
+        assert_eq!(spacing("the .com bubble", Locale::En), "the .com bubble");
+    }
+
+    #[test]
+    fn converts_numbers() {
+        assert_eq!(numbers("Das kostet 1,234.56 Euro", Locale::De), "Das kostet 1.234,56 Euro");
+        assert_eq!(numbers("3.5 Liter und 1,000,000 Leute", Locale::De), "3,5 Liter und 1.000.000 Leute");
+        assert_eq!(numbers("1,234.56 €", Locale::Fr), "1\u{202F}234,56 €");
+        assert_eq!(numbers("It costs 1,234.56", Locale::En), "It costs 1,234.56");
+    }
+
+    #[test]
+    fn leaves_ambiguous_numbers_alone() {
+        for text in ["1.234 Leute", "1,234 Leute", "Version 1.2.3", "um 17.30 Uhr", "v2.5", "1.234,56", "2026"] {
+            assert_eq!(numbers(text, Locale::De), text);
+        }
+    }
+
+    #[test]
+    fn writes_german_dates() {
+        assert_eq!(dates("am 16/10/2026", Locale::De), "am 16.10.2026");
+        assert_eq!(dates("le 16/10/2026", Locale::Fr), "le 16/10/2026");
+        assert_eq!(dates("a/b/c and 1/2", Locale::De), "a/b/c and 1/2");
+    }
+
+    #[test]
+    fn leaves_ambiguous_dates_alone() {
+        for text in ["am 5/10/2026", "am 10/16/2026", "am 12/12/26", "am 32/10/2026", "am 16/0/2026"] {
+            assert_eq!(dates(text, Locale::De), text);
+        }
+        assert_eq!(dates("am 31/1/26", Locale::De), "am 31.1.26");
+    }
+}
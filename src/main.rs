@@ -1,20 +1,95 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use tracing::{debug, Level};
+use clap::{Args, Parser, Subcommand};
+use tracing::{debug, warn, Level};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::FmtSubscriber;
 
+#[cfg(all(target_os = "linux", feature = "tray"))]
 mod tray;
+#[cfg(all(target_os = "linux", feature = "tray"))]
+mod shortcuts;
+#[cfg(all(target_os = "linux", feature = "tray"))]
+mod portal;
+mod activation;
+mod activity;
+mod bilingual;
+mod handoff;
 mod helpers;
+mod instance;
+mod feedback;
+mod paths;
+mod aliases;
+mod models;
+mod platform;
+mod config;
+mod audio;
+mod vad;
 mod recording;
 mod typing;
+mod keyboard;
+mod terminal;
 mod socket;
+mod remote;
+mod sinks;
+mod template;
+mod chooser;
+mod failover;
+mod transcribe;
+mod meeting;
+mod refine;
+mod retranscribe;
+mod pause;
+mod status;
+mod settings;
+mod validate;
+mod transcript;
+mod clipboard;
+mod commands;
+mod delta;
+mod focus;
+mod grammar;
+mod hallucination;
+mod postprocess;
+mod privacy;
+mod locale;
+mod uncertain;
+mod line_breaks;
+mod dictionary;
+mod hooks;
+mod events;
+mod latency;
+mod spoken;
+mod history;
+mod watch;
+mod batch;
+mod backends;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "http-backends")]
+mod openai;
+#[cfg(feature = "whisper-cpp")]
 mod whisper_cpp;
+#[cfg(feature = "faster-whisper")]
 mod faster_whisper;
 
+/// CTranslate2 compute types accepted by faster-whisper
+const COMPUTE_TYPES: [&str; 4] = ["int8", "int8_float16", "float16", "float32"];
+
+/// Whisper tasks: keep the spoken language or translate to English
+const TASKS: [&str; 2] = ["transcribe", "translate"];
+
+/// Backends `backend` selects
+const BACKENDS: [&str; 3] = ["whisper-cpp", "faster-whisper", "openai"];
+
 #[derive(Parser)]
 #[command(name = "whisp-away")]
 #[command(about = "Simple dictation tool using whisper.cpp or faster-whisper", long_about = None)]
 struct Cli {
+    /// Named daemon instance to run or talk to, with its own socket and [instances.<name>]
+    /// settings (overrides WA_INSTANCE)
+    #[arg(long, global = true, value_name = "NAME", value_parser = instance::parse_name)]
+    instance: Option<String>,
+    
     #[command(subcommand)]
     command: Commands,
 }
@@ -22,33 +97,538 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Start recording audio
-    Start,
+    Start {
+        /// Audio to record: mic, monitor (system audio, e.g. the other side of a call) or both (overrides WA_RECORD_SOURCE)
+        #[arg(long)]
+        source: Option<recording::Source>,
+    },
     
     /// Toggle recording: start if not recording, stop and transcribe if recording
     /// Configuration comes from WA_* environment variables or daemon config
-    Toggle,
+    Toggle {
+        #[command(flatten)]
+        args: TranscribeArgs,
+        
+        /// Audio to record when this starts a recording: mic, monitor or both (overrides WA_RECORD_SOURCE)
+        #[arg(long)]
+        source: Option<recording::Source>,
+    },
+    
+    /// Push-to-talk for a key's press binding: record until SIGTERM or SIGINT (the release
+    /// binding, e.g. `pkill -INT -f 'whisp-away hold'`), then transcribe and output like `stop`
+    Hold {
+        #[command(flatten)]
+        args: TranscribeArgs,
+        
+        /// Audio to record: mic, monitor or both (overrides WA_RECORD_SOURCE)
+        #[arg(long)]
+        source: Option<recording::Source>,
+    },
     
     /// Stop recording and transcribe
     /// Configuration comes from WA_* environment variables or daemon config
-    Stop,
+    Stop {
+        #[command(flatten)]
+        args: TranscribeArgs,
+    },
     
     /// Run as a daemon server with model preloaded
     /// Uses WA_WHISPER_BACKEND, WA_WHISPER_MODEL, WA_WHISPER_SOCKET, WA_USE_CLIPBOARD env vars
-    Daemon,
+    Daemon {
+        #[command(subcommand)]
+        action: Option<DaemonAction>,
+        
+        /// faster-whisper compute type the model is loaded with (overrides WA_COMPUTE_TYPE)
+        #[arg(long, value_parser = COMPUTE_TYPES)]
+        compute_type: Option<String>,
+    },
     
     /// Run system tray icon for daemon control
     Tray,
+    
+    /// Full-screen terminal dashboard: recording state and level, recent transcripts and the
+    /// daemon, with keys to toggle recording, pick a model and replay the last recording
+    Tui,
+    
+    /// Show recording and pause state and the models the daemon has loaded
+    Status,
+    
+    /// List the daemon's queued, running and recently finished transcriptions, and the
+    /// batch jobs of `watch`
+    Jobs {
+        #[command(subcommand)]
+        action: Option<JobsAction>,
+        
+        /// Cancel a pending or running job by its number
+        #[arg(long, value_name = "ID")]
+        cancel: Option<u64>,
+    },
+    
+    /// Stop a transcription in progress, e.g. after recording the wrong thing
+    Cancel {
+        /// Abort the transcription this machine is waiting for, or else the daemon's running
+        /// job, and free the GPU
+        #[arg(long, required = true)]
+        transcription: bool,
+    },
+    
+    /// Print the current state as a JSON line, e.g. for a status bar
+    Events {
+        /// Keep printing a JSON line for every recording started or stopped, transcription
+        /// started, finished or cancelled, and error
+        #[arg(long)]
+        follow: bool,
+    },
+    
+    /// Show recent transcripts
+    History {
+        #[command(subcommand)]
+        action: Option<HistoryAction>,
+        
+        /// Number of transcripts to show
+        #[arg(long, short = 'n', default_value_t = 10)]
+        limit: usize,
+        
+        /// Print the full entries (segments, language, timing) as JSON lines
+        #[arg(long)]
+        json: bool,
+        
+        /// Only transcripts containing this text, or dictated into a matching app or window
+        #[arg(long, value_name = "TEXT")]
+        search: Option<String>,
+        
+        /// Only transcripts from this date (e.g. 2024-05-31) or younger than this age (e.g. 1d)
+        #[arg(long, value_name = "DATE", value_parser = history::parse_before)]
+        since: Option<u64>,
+    },
+    
+    /// Disable dictation so Start/Toggle do nothing (e.g. during screen shares)
+    Disable {
+        /// Re-enable automatically after this long (e.g. 30m, 1h, 1h30m)
+        #[arg(long = "for", value_name = "DURATION", value_parser = pause::parse_duration)]
+        duration: Option<std::time::Duration>,
+    },
+    
+    /// Re-enable dictation after `disable`
+    Enable,
+    
+    /// Work with the models on disk
+    Model {
+        #[command(subcommand)]
+        action: ModelAction,
+    },
+    
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    
+    /// Background worker for two-stage transcription (spawned by stop/toggle with --final-model)
+    #[command(hide = true)]
+    Refine {
+        #[arg(long)]
+        audio_file: String,
+        #[arg(long, allow_hyphen_values = true)]
+        draft: String,
+        #[arg(long)]
+        model: String,
+        #[arg(long)]
+        backend: String,
+        /// The draft was typed at the cursor (so it can be replaced)
+        #[arg(long)]
+        typed: bool,
+    },
+    
+    /// Clipboard owner spawned by clipboard output, so the text outlives the command
+    #[command(hide = true)]
+    ServeClipboard {
+        #[arg(long, default_value_t = 0)]
+        hold_secs: u64,
+        #[arg(long)]
+        sensitive: bool,
+    },
+    
+    /// Records for the faster-whisper daemon until SIGINT, printing the file first (spawned by
+    /// it with `daemon_recording`)
+    #[command(hide = true)]
+    Capture {
+        #[arg(long)]
+        source: Option<recording::Source>,
+    },
+    
+    /// Rotates a long dictation into numbered segment files (spawned by start with `segment_mins`)
+    #[command(hide = true)]
+    RotateSegments {
+        #[arg(long)]
+        audio_file: String,
+        #[arg(long)]
+        source: recording::Source,
+    },
+    
+    /// Runs a hook command with its timeout (spawned at lifecycle events with a hook set)
+    #[command(hide = true)]
+    RunHook {
+        event: hooks::Event,
+    },
+    
+    /// Transcribe an existing audio file (any format ffmpeg reads) and print the text
+    Transcribe {
+        /// Audio file to transcribe
+        #[arg(required_unless_present = "from_clipboard")]
+        file: Option<std::path::PathBuf>,
+        
+        /// Take the file path (or file:// URI) from the clipboard and copy the transcript back to it,
+        /// e.g. for a voice message saved from a messenger
+        #[arg(long, conflicts_with = "file")]
+        from_clipboard: bool,
+        
+        /// Model to transcribe with (overrides WA_WHISPER_MODEL)
+        #[arg(long)]
+        model: Option<String>,
+        
+        /// Spoken language, e.g. en, de (overrides WA_WHISPER_LANGUAGE)
+        #[arg(long)]
+        language: Option<String>,
+    },
+    
+    /// Transcribe audio files as they appear in a directory (e.g. voice memos synced from a phone),
+    /// writing .txt and .srt files next to them
+    Watch {
+        /// Directory to watch
+        dir: std::path::PathBuf,
+        
+        /// Model to transcribe with (overrides WA_WHISPER_MODEL)
+        #[arg(long)]
+        model: Option<String>,
+        
+        /// Spoken language, e.g. en, de (overrides WA_WHISPER_LANGUAGE)
+        #[arg(long)]
+        language: Option<String>,
+    },
+    
+    /// Toggle recording whenever the trigger text (WA_CLIPBOARD_TRIGGER) is copied, for apps that
+    /// can set the clipboard but can't run commands, e.g. some remote desktop clients
+    ClipboardTrigger {
+        /// Flags passed on to toggle, after `--`, e.g. `-- --profile meeting`
+        #[arg(last = true)]
+        toggle_args: Vec<String>,
+    },
+    
+    /// Record until signalled and print the transcript on stdout, for scripts and multiplexer keybindings
+    /// SIGUSR1 stops and transcribes; SIGUSR2, SIGINT and SIGTERM cancel
+    Record {
+        /// Stay attached instead of recording in the background like `start`
+        #[arg(long)]
+        foreground: bool,
+        
+        /// Audio to record: mic, monitor or both (overrides WA_RECORD_SOURCE)
+        #[arg(long)]
+        source: Option<recording::Source>,
+        
+        /// Model to transcribe with in the foreground (overrides WA_WHISPER_MODEL)
+        #[arg(long)]
+        model: Option<String>,
+        
+        /// Spoken language in the foreground, e.g. en, de (overrides WA_WHISPER_LANGUAGE)
+        #[arg(long)]
+        language: Option<String>,
+    },
+    
+    /// Show a live input level meter to check the microphone before dictating
+    Monitor {
+        /// Audio to monitor: mic, monitor (system audio) or both (overrides WA_RECORD_SOURCE)
+        #[arg(long)]
+        source: Option<recording::Source>,
+    },
+    
+    /// Record a meeting and save a timestamped Markdown transcript
+    /// Audio is transcribed in chunks through the daemon while recording continues
+    Meeting {
+        #[command(subcommand)]
+        action: MeetingAction,
+    },
+    
+    /// Edit the dictionary of misrecognized spellings and what they should be
+    Dict {
+        #[command(subcommand)]
+        action: DictAction,
+    },
+    
+    /// Run a command with one-off settings, leaving the config file and environment alone
+    /// e.g. `whisp-away with -p 'model = "small.en"' -p 'output = "clipboard"' -- toggle`
+    With {
+        /// Settings as inline TOML, in the config file's format; later ones win
+        #[arg(short = 'p', long = "patch", required = true)]
+        patches: Vec<String>,
+        
+        /// The command to run, after `--`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+}
+
+/// Transcription settings shared by `toggle` and `stop`
+#[derive(Args)]
+struct TranscribeArgs {
+    /// Model to transcribe with (overrides WA_WHISPER_MODEL)
+    #[arg(long)]
+    model: Option<String>,
+    
+    /// Spoken language, e.g. en, de (overrides WA_WHISPER_LANGUAGE)
+    #[arg(long)]
+    language: Option<String>,
+    
+    /// transcribe, or translate to English (overrides WA_WHISPER_TASK)
+    #[arg(long, value_parser = TASKS)]
+    task: Option<String>,
+    
+    /// Decode with beam search of this width instead of the backend's default decoding
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    beam_size: Option<u32>,
+    
+    /// Text to prime whisper with, e.g. names and jargon to spell your way (overrides WA_INITIAL_PROMPT)
+    #[arg(long, value_name = "TEXT")]
+    initial_prompt: Option<String>,
+    
+    /// Apply the [profiles.<name>] settings from config.toml; other flags take precedence
+    #[arg(long)]
+    profile: Option<String>,
+    
+    /// faster-whisper compute type (overrides WA_COMPUTE_TYPE)
+    #[arg(long, value_parser = COMPUTE_TYPES)]
+    compute_type: Option<String>,
+    
+    /// faster-whisper batch size for recordings over a minute, 1 disables batching (overrides WA_BATCH_SIZE)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    batch_size: Option<u32>,
+    
+    /// Where to send the text: type, clipboard, fifo, plugin:<name> or file:<name> (overrides WA_OUTPUT)
+    #[arg(long)]
+    output: Option<typing::Output>,
+    
+    /// When stopping, pick the output from a rofi/wofi/fuzzel/dmenu menu listing the built-in
+    /// outputs and sink plugins (menu from WA_CHOOSER)
+    #[arg(long)]
+    choose_output: bool,
+    
+    /// Type a fast draft, then re-transcribe with this model in the background (overrides WA_FINAL_MODEL)
+    #[arg(long)]
+    final_model: Option<String>,
+    
+    /// Grammar correction before output: off, languagetool or command:<command> (overrides WA_GRAMMAR)
+    #[arg(long)]
+    grammar: Option<grammar::Grammar>,
+    
+    /// Spelling mode: the dictation is a code spelled in NATO alphabet words and digits, e.g. "alpha bravo seven" -> ab7
+    #[arg(long)]
+    spell: bool,
+    
+    /// Before typing, wait until a window whose app id or title matches this regex is focused;
+    /// copies to the clipboard instead after WA_FOCUS_TIMEOUT_SECS (overrides WA_WAIT_FOR_FOCUS)
+    #[arg(long, value_name = "REGEX", value_parser = focus::parse_pattern)]
+    wait_for_focus: Option<String>,
+    
+    /// Extra flag for the whisper.cpp binary, repeatable (e.g. --whisper-arg=--max-context --whisper-arg=64);
+    /// without the daemon, transcribes with the binary instead of the built-in bindings (overrides WA_WHISPER_ARGS)
+    #[cfg(feature = "whisper-cpp")]
+    #[arg(long = "whisper-arg", value_name = "ARG", allow_hyphen_values = true)]
+    whisper_args: Vec<String>,
+    
+    /// Retries after a transient upload failure with the openai backend (overrides WA_MAX_RETRIES)
+    #[arg(long)]
+    max_retries: Option<u32>,
+    
+    /// Print the transcript with segments, language and timing as JSON on stdout
+    #[arg(long)]
+    json: bool,
+    
+    /// Also write the transcript as SRT subtitles to this file
+    #[arg(long, value_name = "FILE")]
+    srt: Option<std::path::PathBuf>,
+}
+
+impl TranscribeArgs {
+    /// Fill unset flags from the selected profile and install the model/language/task overrides
+    fn apply_profile(mut self) -> Result<Self> {
+        if let Some(name) = &self.profile {
+            let profile = config::read_profile(name)?;
+            debug!("Using profile '{}': {:?}", name, profile);
+            self.model = self.model.or(profile.model);
+            self.language = self.language.or(profile.language);
+            self.task = self.task.or(profile.task);
+            self.beam_size = self.beam_size.or(profile.beam_size);
+            self.initial_prompt = self.initial_prompt.or(profile.initial_prompt);
+            self.compute_type = self.compute_type.or(profile.compute_type);
+            self.batch_size = self.batch_size.or(profile.batch_size);
+            self.final_model = self.final_model.or(profile.final_model);
+            if self.output.is_none() {
+                self.output = profile.output
+                    .map(|output| output.parse())
+                    .transpose()
+                    .map_err(|e| anyhow::anyhow!("Profile '{}': {}", name, e))?;
+            }
+            self.spell = self.spell || profile.spell.unwrap_or(false);
+            self.wait_for_focus = self.wait_for_focus.or(profile.wait_for_focus);
+            if let Some(instance_name) = profile.instance.filter(|_| instance::name().is_none()) {
+                let instance_name = instance::parse_name(&instance_name)
+                    .map_err(|e| anyhow::anyhow!("Profile '{}': {}", name, e))?;
+                instance::select(&instance_name);
+            }
+            if self.grammar.is_none() {
+                self.grammar = profile.grammar
+                    .map(|grammar| grammar.parse())
+                    .transpose()
+                    .map_err(|e| anyhow::anyhow!("Profile '{}': {}", name, e))?;
+            }
+        }
+        
+        if let Some(model) = &self.model {
+            models::check(model)?;
+        }
+        helpers::set_overrides(helpers::Overrides {
+            model: self.model.clone(),
+            language: self.language.clone(),
+            task: self.task.clone(),
+            beam_size: self.beam_size,
+            initial_prompt: self.initial_prompt.clone(),
+            grammar: self.grammar.clone(),
+            spell: self.spell,
+            wait_for_focus: self.wait_for_focus.clone(),
+            #[cfg(feature = "whisper-cpp")]
+            whisper_args: self.whisper_args.clone(),
+        });
+        transcript::set_reports(transcript::Reports {
+            json: self.json,
+            srt: self.srt.clone(),
+        });
+        Ok(self)
+    }
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print every effective setting and where it came from (flag, env var, config file, daemon config, default)
+    /// Accepts the toggle/stop flags to show their effect, e.g. --profile
+    Show {
+        #[command(flatten)]
+        args: Box<TranscribeArgs>,
+    },
+    /// Check the config file for values of the wrong type, unknown keys and values settings
+    /// don't accept, which would otherwise be ignored for their defaults
+    Validate {
+        /// File to check instead of the config file, e.g. before putting it in place
+        file: Option<std::path::PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ModelAction {
+    /// List the whisper.cpp (ggml/gguf) and faster-whisper (CTranslate2) models in the download
+    /// directories and `model_dirs`, marking the one in use
+    List {
+        /// Print each model as a JSON line (name, format, backend, path, bytes)
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DictAction {
+    /// Replace a spelling in every transcript, e.g. `dict add "kuber netties" Kubernetes`
+    Add {
+        /// What whisper writes; matched as whole words, ignoring case
+        heard: String,
+        /// What to write instead
+        meant: String,
+    },
+    /// Delete an entry
+    Remove {
+        heard: String,
+    },
+    /// Print every entry
+    List,
+}
+
+#[derive(Subcommand)]
+enum DaemonAction {
+    /// Free the (video) memory of the running daemon's models without stopping it, e.g. before
+    /// starting a game; dictations are refused until `load`
+    Unload,
+    
+    /// Load the running daemon's default model again after `unload`
+    Load,
+}
+
+#[derive(Subcommand)]
+enum JobsAction {
+    /// List the jobs (the default)
+    List,
+    
+    /// Queue failed batch jobs again, or one by its number
+    Retry {
+        id: Option<u64>,
+    },
+    
+    /// Forget finished batch jobs
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum HistoryAction {
+    /// Delete transcripts from the history
+    Purge {
+        /// Only delete transcripts from before this date (e.g. 2024-05-31) or older than this age (e.g. 7d)
+        #[arg(long, value_name = "DATE", value_parser = history::parse_before)]
+        before: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum MeetingAction {
+    /// Start recording the meeting in the background
+    Start {
+        /// Markdown file to write (default: ~/Documents/whisp-away/meeting-<date>.md)
+        #[arg(long)]
+        output: Option<String>,
+        
+        /// Length of each transcribed chunk in seconds
+        #[arg(long, default_value_t = 60)]
+        chunk_secs: u64,
+        
+        /// Audio to record: mic, monitor (the other side of a call) or both, tagged by speaker (overrides WA_RECORD_SOURCE)
+        #[arg(long)]
+        source: Option<recording::Source>,
+    },
+    
+    /// Stop recording, transcribe the last chunk and save the transcript
+    Stop,
+    
+    /// Background worker spawned by `meeting start`
+    #[command(hide = true)]
+    Worker,
 }
 
 fn main() -> Result<()> {
+    let cli = Cli::parse();
+    
     // Initialize logging - quiet by default, use RUST_LOG=debug for verbose output
     let log_level = std::env::var("RUST_LOG")
         .ok()
         .and_then(|s| s.parse().ok())
         .unwrap_or(Level::WARN);
     
+    // The dashboard has the terminal to itself: its logs go to stderr with RUST_LOG set, to be
+    // redirected, and nowhere otherwise
+    let writer = match cli.command {
+        Commands::Tui if std::env::var_os("RUST_LOG").is_some() => BoxMakeWriter::new(std::io::stderr),
+        Commands::Tui => BoxMakeWriter::new(std::io::sink),
+        _ => BoxMakeWriter::new(std::io::stdout),
+    };
+    
     // Use try_init to avoid panic if subscriber already set
     let _ = FmtSubscriber::builder()
+        .with_writer(writer)
         .with_max_level(log_level)
         .with_target(false)
         .with_thread_ids(false)
@@ -59,77 +639,292 @@ fn main() -> Result<()> {
     
     debug!("whisp-away starting");
     
-    let cli = Cli::parse();
+    if let Some(name) = &cli.instance {
+        instance::select(name);
+    }
+    paths::migrate_legacy_files();
+    run(cli.command, cli.instance.as_deref())
+}
 
-    match cli.command {
-        Commands::Start => {
+/// `instance` is the --instance flag the command was given, if any
+fn run(command: Commands, instance: Option<&str>) -> Result<()> {
+    match command {
+        Commands::Start { source } => {
             debug!("Start command");
-            recording::start_recording()
+            if recording::capture::daemon_recording_setting().value {
+                return hooks::on_error(recording::capture::start_in_daemon(source));
+            }
+            hooks::on_error(recording::start_recording(source))
         }
         
-        Commands::Toggle => {
-            let backend = helpers::resolve_backend();
-            debug!("Toggle command - backend: {}", backend);
+        Commands::Toggle { args, source } => {
+            // Applied before starting too, so the recording notification shows the right model
+            let args = args.apply_profile()?;
+            debug!("Toggle command - backend: {}", helpers::resolve_backend());
             
-            // Check if recording is in progress
-            if recording::is_recording() {
-                // Stop and transcribe
-                debug!("Recording in progress, stopping and transcribing");
-                let socket_path = helpers::resolve_socket_path();
-                let use_clipboard = helpers::resolve_use_clipboard();
-                
-                match backend.as_str() {
-                    "whisper-cpp" => {
-                        whisper_cpp::stop_and_transcribe_daemon(&socket_path, None, None, true, None, use_clipboard)
-                    }
-                    "faster-whisper" => {
-                        faster_whisper::stop_and_transcribe_daemon(&socket_path, use_clipboard)
+            // The daemon records, and answers one request at a time
+            if recording::capture::daemon_recording_setting().value {
+                return hooks::on_error(recording::capture::daemon_is_recording().and_then(|recording| {
+                    if recording {
+                        stop_and_transcribe(args)
+                    } else {
+                        recording::capture::start_in_daemon(source)
                     }
-                    _ => Err(anyhow::anyhow!("Unknown backend: {}", backend))
-                }
-            } else {
-                // Start recording
-                debug!("No recording in progress, starting");
-                recording::start_recording()
+                }));
             }
+            
+            // Rapid presses wait for each other, so each one sees the previous one's result
+            hooks::on_error(recording::toggle(
+                || {
+                    debug!("No recording in progress, starting");
+                    recording::start_recording(source)
+                },
+                || {
+                    debug!("Recording in progress, stopping and transcribing");
+                    stop_and_transcribe(args)
+                },
+            ))
         }
         
-        Commands::Stop => {
-            let backend = helpers::resolve_backend();
-            let socket_path = helpers::resolve_socket_path();
-            let use_clipboard = helpers::resolve_use_clipboard();
-            debug!("Stop command - backend: {}, socket: {}, clipboard: {}", 
-                   backend, socket_path, use_clipboard);
-            
-            match backend.as_str() {
-                "whisper-cpp" => {
-                    whisper_cpp::stop_and_transcribe_daemon(&socket_path, None, None, true, None, use_clipboard)
-                }
-                "faster-whisper" => {
-                    faster_whisper::stop_and_transcribe_daemon(&socket_path, use_clipboard)
-                }
-                _ => Err(anyhow::anyhow!("Unknown backend: {}", backend))
-            }
+        Commands::Stop { args } => {
+            debug!("Stop command");
+            hooks::on_error(args.apply_profile().and_then(stop_and_transcribe))
+        }
+        
+        Commands::Hold { args, source } => {
+            debug!("Hold command");
+            hooks::on_error(args.apply_profile().and_then(|args| hold(args, source)))
         }
         
-        Commands::Daemon => {
+        Commands::Daemon { action: Some(DaemonAction::Unload), .. } => status::unload(),
+        Commands::Daemon { action: Some(DaemonAction::Load), .. } => status::load(),
+        Commands::Daemon { action: None, compute_type } => {
             let backend = helpers::resolve_backend();
             let model = helpers::resolve_model();
             let socket_path = helpers::resolve_socket_path();
-            debug!("Daemon command - backend: {}, model: {}, socket: {}", 
-                   backend, model, socket_path);
+            debug!("Daemon command - backend: {}, model: {}, socket: {}, compute type override: {:?}", 
+                   backend, model, socket_path, compute_type);
+            // Taken before any threads start, as it clears systemd's variables
+            let listener = activation::systemd_listener();
             
-            match backend.as_str() {
-                "whisper-cpp" => whisper_cpp::run_daemon(&model),
-                "faster-whisper" => faster_whisper::run_daemon(&model, &socket_path),
-                unknown => Err(anyhow::anyhow!("Unknown backend: {}", unknown)),
-            }
+            backends::run_daemon(&backend, &model, &socket_path, compute_type.as_deref(), listener)
         }
         
+        #[cfg(all(target_os = "linux", feature = "tray"))]
         Commands::Tray => {
             let backend = helpers::resolve_backend();
             debug!("Tray command - backend: {}", backend);
             tokio::runtime::Runtime::new()?.block_on(tray::run_tray(backend))
         }
+        
+        // The tray is a StatusNotifierItem, which needs a Linux desktop's D-Bus session
+        #[cfg(not(target_os = "linux"))]
+        Commands::Tray => Err(anyhow::anyhow!("The tray icon is only available on Linux")),
+        
+        #[cfg(all(target_os = "linux", not(feature = "tray")))]
+        Commands::Tray => Err(anyhow::anyhow!("whisp-away was built without the tray (cargo feature \"tray\")")),
+        
+        #[cfg(feature = "tui")]
+        Commands::Tui => tui::run(),
+        
+        #[cfg(not(feature = "tui"))]
+        Commands::Tui => Err(anyhow::anyhow!("whisp-away was built without the dashboard (cargo feature \"tui\")")),
+        
+        Commands::Status => status::run(),
+        
+        Commands::Jobs { action: Some(JobsAction::Retry { id }), .. } => status::retry_batch(id),
+        Commands::Jobs { action: Some(JobsAction::Clear), .. } => status::clear_batch(),
+        Commands::Jobs { action: None | Some(JobsAction::List), cancel } => status::jobs(cancel),
+        
+        Commands::Cancel { .. } => status::cancel_transcription(),
+        
+        Commands::Events { follow } => events::run(follow),
+        
+        Commands::History { action: Some(HistoryAction::Purge { before }), .. } => history::purge(before),
+        Commands::History { action: None, limit, json, search, since } => history::show(limit, json, &history::Filter { search, since }),
+        
+        Commands::Disable { duration } => {
+            debug!("Disable command - duration: {:?}", duration);
+            pause::disable(duration)
+        }
+        
+        Commands::Enable => pause::enable(),
+        
+        Commands::Model { action: ModelAction::List { json } } => models::list(json),
+        
+        Commands::Config { action: ConfigAction::Show { args } } => {
+            let args = args.apply_profile()?;
+            settings::show(settings::CliValues {
+                output: args.output,
+                compute_type: args.compute_type,
+                batch_size: args.batch_size,
+                final_model: args.final_model,
+                max_retries: args.max_retries,
+            });
+            Ok(())
+        }
+        
+        Commands::Config { action: ConfigAction::Validate { file } } => validate::run(file),
+        
+        Commands::Refine { audio_file, draft, model, backend, typed } => {
+            debug!("Refine command - model: {}, backend: {}, typed: {}", model, backend, typed);
+            refine::run(&audio_file, &draft, &model, &backend, typed)
+        }
+        
+        Commands::ServeClipboard { hold_secs, sensitive } => clipboard::serve(hold_secs, sensitive),
+        
+        Commands::RunHook { event } => hooks::run(event),
+        
+        Commands::Transcribe { file, from_clipboard, model, language } => {
+            if let Some(model) = &model {
+                models::check(model)?;
+            }
+            helpers::set_overrides(helpers::Overrides { model, language, ..Default::default() });
+            transcribe::run(file, from_clipboard)
+        }
+        
+        Commands::Watch { dir, model, language } => {
+            if let Some(model) = &model {
+                models::check(model)?;
+            }
+            helpers::set_overrides(helpers::Overrides { model, language, ..Default::default() });
+            watch::run(&dir)
+        }
+        
+        Commands::ClipboardTrigger { toggle_args } => clipboard::watch_trigger(&toggle_args),
+        
+        Commands::Record { foreground: false, source, .. } => hooks::on_error(recording::start_recording(source)),
+        
+        Commands::Record { foreground: true, source, model, language } => {
+            if let Some(model) = &model {
+                models::check(model)?;
+            }
+            helpers::set_overrides(helpers::Overrides { model, language, ..Default::default() });
+            hooks::on_error(recording::foreground::run(source))
+        }
+        
+        Commands::Monitor { source } => recording::monitor::run(source),
+        
+        Commands::Capture { source } => recording::capture::run(source),
+        
+        Commands::RotateSegments { audio_file, source } => recording::segments::run_rotator(&audio_file, source),
+        
+        Commands::Meeting { action } => match action {
+            MeetingAction::Start { output, chunk_secs, source } => {
+                debug!("Meeting start - output: {:?}, chunk: {}s, source: {:?}", output, chunk_secs, source);
+                meeting::start(output, chunk_secs, source)
+            }
+            MeetingAction::Stop => meeting::stop(),
+            MeetingAction::Worker => meeting::run_worker(),
+        },
+        
+        Commands::Dict { action } => match action {
+            DictAction::Add { heard, meant } => dictionary::add(&heard, &meant),
+            DictAction::Remove { heard } => dictionary::remove(&heard),
+            DictAction::List => dictionary::list(),
+        },
+        
+        Commands::With { patches, command } => {
+            config::set_overlay(&patches)?;
+            let cli = Cli::parse_from(std::iter::once("whisp-away".to_string()).chain(command));
+            let instance = wrapped_instance(instance, cli.instance.as_deref())?;
+            if let Some(name) = instance {
+                instance::select(name);
+            }
+            run(cli.command, instance)
+        }
+    }
+}
+
+/// The instance for a command run by `with`: --instance counts the same given before `with`
+/// or inside the wrapped command, as long as the two agree
+fn wrapped_instance<'a>(outer: Option<&'a str>, inner: Option<&'a str>) -> Result<Option<&'a str>> {
+    match (outer, inner) {
+        (Some(outer), Some(inner)) if outer != inner => Err(anyhow::anyhow!(
+            "--instance {} before `with` conflicts with --instance {} in the command", outer, inner
+        )),
+        _ => Ok(inner.or(outer)),
+    }
+}
+
+/// Where the text goes, picked from a menu with --choose-output
+fn resolve_output(args: &TranscribeArgs) -> typing::Output {
+    let output = helpers::resolve_output(args.output.clone());
+    if !args.choose_output {
+        return output;
+    }
+    // The recording is transcribed either way, so a broken menu doesn't lose the dictation
+    match chooser::choose_output(&output) {
+        Ok(Some(chosen)) => chosen,
+        Ok(None) => {
+            debug!("Chooser dismissed, keeping output {}", output);
+            output
+        }
+        Err(e) => {
+            warn!("{:#}, keeping output {}", e, output);
+            output
+        }
     }
-}
\ No newline at end of file
+}
+
+/// Record while the key is held (`whisp-away hold`), then transcribe along the failover
+/// chain, which defaults to the backend's daemon and then transcribing directly
+fn hold(args: TranscribeArgs, source: Option<recording::Source>) -> Result<()> {
+    recording::foreground::hold(source, |audio_file| {
+        let output = resolve_output(&args);
+        let (steps, _) = failover::chain()?;
+        failover::transcribe_recording(
+            audio_file,
+            &steps,
+            &output,
+            helpers::resolve_compute_type(args.compute_type).as_deref(),
+            helpers::resolve_batch_size(args.batch_size),
+            helpers::resolve_final_model(args.final_model).as_deref(),
+            helpers::resolve_max_retries(args.max_retries),
+        )
+    })
+}
+
+/// Stop the recording and transcribe it with the resolved settings
+fn stop_and_transcribe(args: TranscribeArgs) -> Result<()> {
+    let backend = helpers::resolve_backend();
+    let socket_path = helpers::resolve_socket_path();
+    let output = resolve_output(&args);
+    let compute_type = helpers::resolve_compute_type(args.compute_type);
+    let batch_size = helpers::resolve_batch_size(args.batch_size);
+    let final_model = helpers::resolve_final_model(args.final_model);
+    debug!("Stopping - backend: {}, socket: {}, output: {:?}, compute type: {:?}, batch size: {:?}", 
+           backend, socket_path, output, compute_type, batch_size);
+    
+    if recording::capture::daemon_recording_setting().value {
+        return recording::capture::stop_in_daemon(&output);
+    }
+    
+    // A configured chain (or a portable build's) replaces the backend's own daemon-then-direct fallback
+    let max_retries = helpers::resolve_max_retries(args.max_retries);
+    let (steps, use_chain) = failover::chain()?;
+    if use_chain {
+        return failover::stop_and_transcribe(&steps, &output, compute_type.as_deref(), batch_size, final_model.as_deref(), max_retries);
+    }
+    
+    backends::stop_and_transcribe(&backend, &socket_path, &output, compute_type.as_deref(), batch_size, final_model.as_deref(), max_retries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_keeps_an_instance_given_inside_the_command() {
+        let cli = Cli::parse_from(["whisp-away", "with", "-p", "model = \"small\"", "--", "--instance", "german", "status"]);
+        let Commands::With { command, .. } = cli.command else { panic!("not parsed as `with`") };
+        let wrapped = Cli::parse_from(std::iter::once("whisp-away".to_string()).chain(command));
+        assert_eq!(wrapped.instance.as_deref(), Some("german"));
+
+        assert_eq!(wrapped_instance(None, Some("german")).unwrap(), Some("german"));
+        assert_eq!(wrapped_instance(Some("german"), None).unwrap(), Some("german"));
+        assert_eq!(wrapped_instance(Some("german"), Some("german")).unwrap(), Some("german"));
+        assert_eq!(wrapped_instance(None, None).unwrap(), None);
+        assert!(wrapped_instance(Some("english"), Some("german")).is_err());
+    }
+}
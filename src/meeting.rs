@@ -0,0 +1,317 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use crate::helpers::{self, is_process_running};
+use crate::paths;
+use crate::privacy;
+use crate::recording;
+use crate::socket;
+
+/// Set by the signal handler when `meeting stop` asks the worker to finish
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// How long `meeting stop` waits for the worker to transcribe the final chunk
+const STOP_TIMEOUT_SECS: u64 = 300;
+
+/// Meeting session state - written by `meeting start`, read by the worker and `meeting stop`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MeetingState {
+    pid: Option<u32>,
+    chunk_secs: u64,
+    /// Transcript being built while the meeting runs
+    transcript_path: String,
+    /// Where the transcript is moved when the meeting stops
+    output_path: String,
+    #[serde(default)]
+    source: recording::Source,
+}
+
+/// A recorded chunk waiting for transcription
+struct Chunk {
+    audio_file: String,
+    /// System audio recorded separately when recording both sources
+    monitor_file: Option<String>,
+    offset_secs: u64,
+}
+
+fn state_path() -> String {
+    format!("{}/whisp-away-meeting.json", paths::runtime_dir())
+}
+
+fn read_state() -> Option<MeetingState> {
+    let content = fs::read_to_string(state_path()).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn write_state(state: &MeetingState) -> Result<()> {
+    fs::write(state_path(), serde_json::to_string_pretty(state)?)
+        .context("Failed to write meeting state")
+}
+
+/// Default output location: ~/Documents/whisp-away/meeting-<date>.md
+fn default_output_path() -> PathBuf {
+    dirs::document_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("whisp-away")
+        .join(format!("meeting-{}.md", helpers::local_timestamp("%Y-%m-%d-%H%M")))
+}
+
+/// Format seconds as a [HH:MM:SS] transcript timestamp
+fn format_offset(secs: u64) -> String {
+    format!("[{:02}:{:02}:{:02}]", secs / 3600, (secs % 3600) / 60, secs % 60)
+}
+
+/// Start a meeting: spawn a detached worker that records and transcribes in chunks
+pub fn start(output: Option<String>, chunk_secs: u64, source: Option<recording::Source>) -> Result<()> {
+    if let Some(state) = read_state() {
+        if state.pid.map(is_process_running).unwrap_or(false) {
+            helpers::send_notification("Meeting Notes", "⚠️ A meeting is already being recorded", 2000);
+            return Err(anyhow::anyhow!("A meeting is already being recorded"));
+        }
+        debug!("Removing stale meeting state");
+    }
+
+    let runtime_dir = paths::runtime_dir();
+    fs::create_dir_all(&runtime_dir).ok();
+
+    let output_path = output
+        .map(PathBuf::from)
+        .unwrap_or_else(default_output_path);
+    let mut state = MeetingState {
+        pid: None,
+        chunk_secs: chunk_secs.max(10),
+        transcript_path: format!("{}/whisp-away-meeting-transcript.md", runtime_dir),
+        output_path: output_path.to_string_lossy().to_string(),
+        source: recording::source_setting(source).value,
+    };
+    write_state(&state)?;
+
+    // Run the worker in its own process group so it outlives the hotkey invocation
+    let exe = std::env::current_exe().context("Failed to locate whisp-away executable")?;
+    let child = Command::new(exe)
+        .args(["meeting", "worker"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .context("Failed to start meeting worker")?;
+
+    state.pid = Some(child.id());
+    write_state(&state)?;
+    info!("Meeting worker started (PID: {})", child.id());
+
+    helpers::send_notification(
+        "Meeting Notes",
+        &format!("🎙️ Meeting recording started\nRecording {}, transcribing every {}s", state.source.describe(), state.chunk_secs),
+        3000
+    );
+    Ok(())
+}
+
+/// Stop the meeting and wait for the worker to write the final transcript
+pub fn stop() -> Result<()> {
+    let state = read_state().context("No meeting is being recorded")?;
+    let pid = state.pid.context("Meeting worker has no PID")?;
+
+    if !is_process_running(pid) {
+        let _ = fs::remove_file(state_path());
+        return Err(anyhow::anyhow!("Meeting worker (PID {}) is not running", pid));
+    }
+
+    debug!("Asking meeting worker {} to stop", pid);
+    unsafe { libc::kill(pid as i32, libc::SIGTERM) };
+    helpers::send_notification("Meeting Notes", "⏳ Finishing meeting transcript...", 3000);
+
+    let deadline = Instant::now() + Duration::from_secs(STOP_TIMEOUT_SECS);
+    while is_process_running(pid) {
+        if Instant::now() > deadline {
+            return Err(anyhow::anyhow!("Meeting worker did not finish within {}s", STOP_TIMEOUT_SECS));
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    println!("{}", state.output_path);
+    Ok(())
+}
+
+extern "C" fn handle_stop_signal(_signal: libc::c_int) {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Worker process: rotate recordings every chunk_secs and transcribe finished chunks in order
+pub fn run_worker() -> Result<()> {
+    let state = read_state().context("Meeting state missing")?;
+    unsafe {
+        libc::signal(libc::SIGTERM, handle_stop_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        libc::signal(libc::SIGINT, handle_stop_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+
+    fs::write(
+        &state.transcript_path,
+        format!("# Meeting notes - {}\n\n", helpers::local_timestamp("%Y-%m-%d %H:%M")),
+    )
+    .context("Failed to create meeting transcript")?;
+
+    // Transcribe on a separate thread so recording never waits for the model
+    let (tx, rx) = mpsc::channel::<Chunk>();
+    let transcript_path = state.transcript_path.clone();
+    let transcriber = std::thread::spawn(move || {
+        let socket_path = helpers::resolve_socket_path();
+        for chunk in rx {
+            append_chunk(&socket_path, &transcript_path, &chunk);
+        }
+    });
+
+    let recorded = record_chunks(&state, &tx);
+    drop(tx);
+    let _ = transcriber.join();
+
+    // What was transcribed is saved even when recording broke off
+    let output_path = PathBuf::from(&state.output_path);
+    save_transcript(Path::new(&state.transcript_path), &output_path)?;
+    let _ = fs::remove_file(state_path());
+
+    if let Err(e) = recorded {
+        warn!("Meeting recording stopped early: {:#}", e);
+        helpers::send_notification(
+            "Meeting Notes",
+            &format!("❌ Meeting recording failed, notes so far saved\n{}", output_path.display()),
+            5000
+        );
+        return Err(e);
+    }
+    info!("Meeting transcript saved to {}", output_path.display());
+    helpers::send_notification(
+        "Meeting Notes",
+        &format!("📝 Meeting notes saved\n{}", output_path.display()),
+        5000
+    );
+    Ok(())
+}
+
+/// Rotate recordings every chunk_secs until asked to stop, handing each finished chunk to
+/// the transcriber
+/// When the next chunk's recorder doesn't start, the current one keeps recording and the
+/// rotation is tried again a chunk later
+fn record_chunks(state: &MeetingState, chunks: &mpsc::Sender<Chunk>) -> Result<()> {
+    let started = Instant::now();
+    let chunk_duration = Duration::from_secs(state.chunk_secs);
+    let mut index = 0;
+    let (mut recorder, mut chunk) = start_chunk(index, 0, state.source)?;
+    let mut chunk_started = Instant::now();
+
+    loop {
+        std::thread::sleep(Duration::from_millis(200));
+
+        if STOP_REQUESTED.load(Ordering::SeqCst) {
+            stop_recorder(recorder);
+            let _ = chunks.send(chunk);
+            return Ok(());
+        }
+
+        if chunk_started.elapsed() >= chunk_duration {
+            // Start the next recorder before stopping the current one to avoid gaps
+            match start_chunk(index + 1, started.elapsed().as_secs(), state.source) {
+                Ok((next_recorder, next_chunk)) => {
+                    index += 1;
+                    stop_recorder(recorder);
+                    let _ = chunks.send(chunk);
+                    recorder = next_recorder;
+                    chunk = next_chunk;
+                }
+                Err(e) => warn!("Failed to start meeting chunk {}, chunk {} records on: {:#}", index + 1, index, e),
+            }
+            chunk_started = Instant::now();
+        }
+    }
+}
+
+fn start_chunk(index: u32, offset_secs: u64, source: recording::Source) -> Result<(Child, Chunk)> {
+    let audio_file = format!("{}/whisp-away-meeting-chunk-{}.wav", recording::recording_dir(), index);
+    let recorder = recording::spawn_recorder(&audio_file, source)?;
+    debug!("Recording meeting chunk {} at offset {}s", index, offset_secs);
+    let monitor_file = (source == recording::Source::Both).then(|| recording::monitor_file(&audio_file));
+    Ok((recorder, Chunk { audio_file, monitor_file, offset_secs }))
+}
+
+fn stop_recorder(mut recorder: Child) {
+    unsafe { libc::kill(recorder.id() as i32, libc::SIGINT) };
+    let _ = recorder.wait();
+}
+
+fn append_chunk(socket_path: &str, transcript_path: &str, chunk: &Chunk) {
+    if let Err(e) = recording::downmix(&chunk.audio_file) {
+        warn!("Failed to downmix {}: {:#}", chunk.audio_file, e);
+    }
+    let offset = format_offset(chunk.offset_secs);
+    let lines = match &chunk.monitor_file {
+        // The microphone is the user, the system audio everyone else on the call
+        Some(monitor_file) => [("You", &chunk.audio_file), ("Others", monitor_file)]
+            .into_iter()
+            .filter_map(|(speaker, audio_file)| {
+                transcribe_chunk(socket_path, audio_file, chunk.offset_secs)
+                    .map(|text| format!("{} **{}:** {}\n\n", offset, speaker, text))
+            })
+            .collect::<String>(),
+        None => transcribe_chunk(socket_path, &chunk.audio_file, chunk.offset_secs)
+            .map(|text| format!("{} {}\n\n", offset, text))
+            .unwrap_or_default(),
+    };
+    if lines.is_empty() {
+        return;
+    }
+
+    let result = OpenOptions::new()
+        .append(true)
+        .open(transcript_path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()));
+    if let Err(e) = result {
+        warn!("Failed to append to meeting transcript: {}", e);
+    }
+}
+
+/// Transcribe one recorded file of a chunk; None when it contained no speech
+/// A failure becomes a note in the transcript and the audio is kept
+fn transcribe_chunk(socket_path: &str, audio_file: &str, offset_secs: u64) -> Option<String> {
+    let request = socket::TranscriptionRequest {
+        batch_size: helpers::resolve_batch_size(None),
+        label: Some(format!("meeting {}", format_offset(offset_secs))),
+        ..socket::TranscriptionRequest::new(audio_file)
+    };
+    match socket::transcribe_to_string(socket_path, &request) {
+        Ok(text) => {
+            let _ = privacy::remove_file(audio_file);
+            if text.is_empty() {
+                debug!("{} at {}s contained no speech", audio_file, offset_secs);
+                return None;
+            }
+            Some(text)
+        }
+        Err(e) => {
+            // Keep the audio so nothing is lost when the daemon is unavailable
+            warn!("Failed to transcribe meeting chunk {}: {}", audio_file, e);
+            Some(format!("_(not transcribed: {}, audio kept at {})_", e, audio_file))
+        }
+    }
+}
+
+/// Move the transcript to its final location (copy + remove works across filesystems)
+fn save_transcript(transcript: &Path, output: &Path) -> Result<()> {
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    fs::copy(transcript, output)
+        .with_context(|| format!("Failed to save transcript to {}", output.display()))?;
+    let _ = privacy::remove_file(transcript);
+    Ok(())
+}
@@ -0,0 +1,385 @@
+use anyhow::{anyhow, Result};
+use serde_json::json;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+use crate::aliases;
+use crate::helpers;
+use crate::paths;
+use crate::settings::{Layers, Resolved};
+
+/// How deep a models directory is searched, enough for Hugging Face's cache layout
+/// (models--owner--name/snapshots/<revision>/model.bin)
+const MAX_DEPTH: usize = 4;
+
+/// "ggml" as the little-endian magic number at the start of a whisper.cpp model
+pub const GGML_MAGIC: u32 = 0x6767_6d6c;
+
+/// Model files start with "GGUF" in the newer single-file format
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+
+/// Names faster-whisper maps to its converted models on Hugging Face
+const FASTER_WHISPER_NAMES: [&str; 19] = [
+    "tiny.en", "tiny", "base.en", "base", "small.en", "small", "medium.en", "medium",
+    "large-v1", "large-v2", "large-v3", "large", "large-v3-turbo", "turbo",
+    "distil-small.en", "distil-medium.en", "distil-large-v2", "distil-large-v3", "distil-large-v3.5",
+];
+
+/// How a model is stored, which decides the backend that can load it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A whisper.cpp model file
+    Ggml,
+    /// A whisper.cpp model file in the newer single-file format
+    Gguf,
+    /// A CTranslate2 model directory (model.bin, config.json and the tokenizer)
+    Ct2,
+}
+
+impl Format {
+    pub fn backend(self) -> &'static str {
+        match self {
+            Format::Ggml | Format::Gguf => "whisper-cpp",
+            Format::Ct2 => "faster-whisper",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Format::Ggml => "ggml",
+            Format::Gguf => "gguf",
+            Format::Ct2 => "CTranslate2",
+        }
+    }
+}
+
+/// A model found on disk
+#[derive(Debug, Clone)]
+pub struct Model {
+    /// What `--model` takes for it: the name for models in the backend's download
+    /// directory, otherwise the path
+    pub name: String,
+    pub format: Format,
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+/// Resolves extra directories searched for models with priority:
+/// 1. WA_MODEL_DIRS env var, separated by colons
+/// 2. User config file `model_dirs` (a list)
+/// 3. Default to none, only the download directories
+pub fn dirs_setting() -> Resolved<Vec<PathBuf>> {
+    Layers::new("model directories")
+        .env("WA_MODEL_DIRS", |value| Some(std::env::split_paths(&value).filter(|dir| !dir.as_os_str().is_empty()).collect()))
+        .config(|c| c.model_dirs.map(|dirs| dirs.iter().map(|dir| expand_home(dir)).collect()))
+        .or_default(Vec::new())
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// The directories searched: the download directories of both backends (and where they
+/// used to be), then `model_dirs`
+fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![
+        paths::models_dir().join("whisper-cpp"),
+        paths::legacy_whisper_cpp_models_dir(),
+        paths::faster_whisper_models_dir(),
+    ];
+    for dir in dirs_setting().value {
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+/// Every model in the searched directories, whisper.cpp ones first; a model in both the
+/// download directory and where it used to be is listed once
+pub fn scan() -> Vec<Model> {
+    let mut models = Vec::new();
+    for dir in search_dirs() {
+        scan_dir(&dir, 0, &mut models);
+    }
+    let mut seen = std::collections::HashSet::new();
+    models.retain(|model| seen.insert((model.format.backend(), model.name.clone())));
+    models.sort_by(|a, b| a.format.backend().cmp(b.format.backend()).reverse().then_with(|| a.name.cmp(&b.name)));
+    debug!("Found {} models", models.len());
+    models
+}
+
+fn scan_dir(dir: &Path, depth: usize, models: &mut Vec<Model>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    entries.sort();
+    for path in entries {
+        // Hugging Face keeps the actual files in blobs/ and lock files in .locks/
+        let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden || path.file_name().is_some_and(|name| name == "blobs") {
+            continue;
+        }
+        match detect(&path) {
+            Some(format) => models.push(Model {
+                name: name_of(&path, format),
+                format,
+                bytes: size(&path),
+                path,
+            }),
+            None if path.is_dir() && depth < MAX_DEPTH => scan_dir(&path, depth + 1, models),
+            None => {}
+        }
+    }
+}
+
+pub fn is_faster_whisper_name(model: &str) -> bool {
+    FASTER_WHISPER_NAMES.contains(&model)
+}
+
+/// The name faster-whisper maps to a Hugging Face repo, e.g. "distil-large-v3" for
+/// Systran/faster-distil-whisper-large-v3
+fn faster_whisper_name(repo: &str) -> Option<&'static str> {
+    let name = if let Some(name) = repo.strip_prefix("Systran/faster-distil-whisper-") {
+        format!("distil-{}", name)
+    } else if let Some(name) = repo.strip_prefix("distil-whisper/").and_then(|name| name.strip_suffix("-ct2")) {
+        name.to_string()
+    } else {
+        repo.strip_prefix("Systran/faster-whisper-")
+            .or_else(|| repo.strip_prefix("mobiuslabsgmbh/faster-whisper-"))?
+            .to_string()
+    };
+    FASTER_WHISPER_NAMES.iter().copied().find(|known| *known == name)
+}
+
+/// The format of a model file or directory, by its contents rather than its name
+pub fn detect(path: &Path) -> Option<Format> {
+    if path.is_dir() {
+        return (path.join("model.bin").is_file() && path.join("config.json").is_file()).then_some(Format::Ct2);
+    }
+    let extension = path.extension()?.to_str()?;
+    if extension != "bin" && extension != "gguf" {
+        return None;
+    }
+    let mut magic = [0u8; 4];
+    File::open(path).and_then(|mut file| file.read_exact(&mut magic)).ok()?;
+    if &magic == GGUF_MAGIC {
+        Some(Format::Gguf)
+    } else if u32::from_le_bytes(magic) == GGML_MAGIC {
+        Some(Format::Ggml)
+    } else {
+        None
+    }
+}
+
+/// What `--model` takes for a model found at `path`
+fn name_of(path: &Path, format: Format) -> String {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    match format {
+        // ggml-base.en.bin in the download directory is "base.en"
+        Format::Ggml | Format::Gguf => {
+            let name = file_name.strip_prefix("ggml-").and_then(|name| name.strip_suffix(".bin"));
+            match name {
+                Some(name) if Path::new(&paths::whisper_cpp_model_path(name)) == path => name.to_string(),
+                _ => path.to_string_lossy().into_owned(),
+            }
+        }
+        // A download is models--owner--name/snapshots/<revision>, known by its repo id or
+        // the name faster-whisper maps to it
+        Format::Ct2 => {
+            let repo = path.ancestors()
+                .filter_map(|dir| dir.file_name()?.to_str()?.strip_prefix("models--"))
+                .next()
+                .map(|repo| repo.replacen("--", "/", 1));
+            match repo {
+                Some(repo) => faster_whisper_name(&repo).map(str::to_string).unwrap_or(repo),
+                None => path.to_string_lossy().into_owned(),
+            }
+        }
+    }
+}
+
+/// Bytes of a model file, or of the files in a model directory
+fn size(path: &Path) -> u64 {
+    if !path.is_dir() {
+        return fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+    }
+    fs::read_dir(path).into_iter().flatten().flatten()
+        .filter_map(|entry| fs::metadata(entry.path()).ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn format_size(bytes: u64) -> String {
+    let mb = bytes as f64 / 1_000_000.0;
+    if mb >= 1000.0 {
+        format!("{:.1} GB", mb / 1000.0)
+    } else {
+        format!("{:.0} MB", mb)
+    }
+}
+
+/// Why `model` can't be used with `backend` when it is a model of the other backend's
+/// format, with what to do instead; None for anything else, which the backend checks itself
+pub fn mismatch(model: &str, backend: &str, models: &[Model]) -> Option<String> {
+    if backend == "openai" {
+        return None;
+    }
+    let path = expand_home(model);
+    let format = if model.contains('/') && path.exists() {
+        detect(&path)?
+    } else {
+        // Names are looked up among the models on disk, unless the backend takes them anyway
+        let takes_name = match backend {
+            "faster-whisper" => is_faster_whisper_name(model),
+            _ => Path::new(&paths::whisper_cpp_model_path(model)).exists(),
+        };
+        if takes_name || models.iter().any(|found| found.name == model && found.format.backend() == backend) {
+            return None;
+        }
+        models.iter().find(|found| found.name == model)?.format
+    };
+    if format.backend() == backend {
+        return None;
+    }
+
+    let mut message = format!(
+        "{} is a {} model for {}, which the {} backend can't load; set WA_WHISPER_BACKEND={} to use it",
+        model, format.label(), format.backend(), backend, format.backend()
+    );
+    let own: Vec<&str> = models.iter()
+        .filter(|found| found.format.backend() == backend && !found.name.contains('/'))
+        .map(|found| found.name.as_str())
+        .collect();
+    if !own.is_empty() {
+        message.push_str(&format!(", or pick a {} model: {}", backend, own.join(", ")));
+    } else if backend == "faster-whisper" && is_faster_whisper_name(name_of_file(model)) {
+        message.push_str(&format!(", or use --model {} to download it for {}", name_of_file(model), backend));
+    }
+    Some(message)
+}
+
+/// "base.en" for ggml-base.en.bin (or a path to it), for suggesting the same model in the
+/// other format
+fn name_of_file(model: &str) -> &str {
+    let file_name = model.rsplit('/').next().unwrap_or(model);
+    file_name.strip_prefix("ggml-").and_then(|name| name.strip_suffix(".bin")).unwrap_or(file_name)
+}
+
+/// A model as the backend's daemon takes it: faster-whisper models are checked first, and
+/// local paths made absolute for a daemon running from another directory
+pub fn resolve_for(backend: &str, model: &str) -> Result<String> {
+    match backend {
+        #[cfg(feature = "faster-whisper")]
+        "faster-whisper" => crate::faster_whisper::models::resolve(model),
+        _ => Ok(model.to_string()),
+    }
+}
+
+/// Check a `--model` argument against the backend before anything is recorded: a model of
+/// the other backend's format is rejected with a suggestion
+pub fn check(model: &str) -> Result<()> {
+    let backend = helpers::resolve_backend();
+    let model = aliases::resolve(model, &backend);
+    match mismatch(&model, &backend, &scan()) {
+        Some(message) => {
+            helpers::send_notification("Voice Input", &format!("❌ {}", message), 5000);
+            Err(anyhow!("{}", message))
+        }
+        None => Ok(()),
+    }
+}
+
+/// `whisp-away model list`: the models on disk by backend, the current one starred
+pub fn list(json: bool) -> Result<()> {
+    let models = scan();
+    if json {
+        for model in &models {
+            println!("{}", json!({
+                "name": model.name,
+                "format": model.format.label().to_lowercase(),
+                "backend": model.format.backend(),
+                "path": model.path,
+                "bytes": model.bytes,
+            }));
+        }
+        return Ok(());
+    }
+    if models.is_empty() {
+        println!("No models found in:");
+        for dir in search_dirs() {
+            println!("  {}", dir.display());
+        }
+        return Ok(());
+    }
+
+    let backend = helpers::resolve_backend();
+    let current = aliases::resolve(&helpers::resolve_model(), &backend);
+    let width = models.iter().map(|model| model.name.len()).filter(|len| *len <= 40).max().unwrap_or(0);
+    let mut heading = "";
+    for model in &models {
+        if model.format.backend() != heading {
+            heading = model.format.backend();
+            println!("{}:", heading);
+        }
+        let star = if heading == backend && model.name == current { "*" } else { " " };
+        // Models outside the download directories are named by their path already
+        let path = model.path.to_string_lossy();
+        let location = if path == model.name { String::new() } else { format!("  {}", path) };
+        println!(
+            "{} {:width$}  {:>7}  {:<11}{}",
+            star, model.name, format_size(model.bytes), model.format.label(), location,
+            width = width
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(name: &str, format: Format) -> Model {
+        Model { name: name.to_string(), format, path: PathBuf::from(name), bytes: 0 }
+    }
+
+    #[test]
+    fn detects_formats_by_contents() {
+        let dir = std::env::temp_dir().join(format!("whisp-away-models-{}", std::process::id()));
+        let ct2 = dir.join("whisper-small-ct2");
+        fs::create_dir_all(&ct2).unwrap();
+        fs::write(ct2.join("model.bin"), b"\x00").unwrap();
+        fs::write(ct2.join("config.json"), b"{}").unwrap();
+        fs::write(dir.join("ggml-tiny.bin"), GGML_MAGIC.to_le_bytes()).unwrap();
+        fs::write(dir.join("tiny.gguf"), b"GGUF\x03\x00").unwrap();
+        fs::write(dir.join("notes.bin"), b"text").unwrap();
+
+        assert_eq!(detect(&ct2), Some(Format::Ct2));
+        assert_eq!(detect(&dir.join("ggml-tiny.bin")), Some(Format::Ggml));
+        assert_eq!(detect(&dir.join("tiny.gguf")), Some(Format::Gguf));
+        assert_eq!(detect(&dir.join("notes.bin")), None);
+        assert_eq!(detect(&dir), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn suggests_the_backend_of_the_other_format() {
+        let models = [
+            model("Systran/faster-whisper-custom", Format::Ct2),
+            model("small.en", Format::Ggml),
+        ];
+        let message = mismatch("Systran/faster-whisper-custom", "whisper-cpp", &models).unwrap();
+        assert!(message.contains("WA_WHISPER_BACKEND=faster-whisper"), "{}", message);
+        assert!(message.contains("pick a whisper-cpp model: small.en"), "{}", message);
+        // faster-whisper downloads names it knows, whatever is on disk
+        assert_eq!(mismatch("small.en", "faster-whisper", &models), None);
+        assert_eq!(mismatch("unknown-model", "faster-whisper", &models), None);
+        assert_eq!(mismatch("small.en", "openai", &models), None);
+    }
+}
@@ -0,0 +1,383 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+use crate::aliases;
+use crate::audio;
+use crate::bilingual;
+use crate::failover::{self, Attempt, Step};
+use crate::hallucination;
+use crate::latency;
+use crate::line_breaks;
+use crate::postprocess;
+use crate::helpers;
+use crate::paths;
+use crate::privacy;
+use crate::recording;
+use crate::settings::{Layers, Resolved, Source};
+use crate::socket::{Segment, TranscriptionResponse};
+use crate::transcript;
+use crate::typing::Output;
+
+/// Delay before the first retry, doubled after every further failure
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest wait between two attempts
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// curl exit codes worth retrying: DNS, connect, timeout, TLS handshake and dropped connections
+const TRANSIENT_CURL_CODES: [i32; 8] = [5, 6, 7, 28, 35, 52, 55, 56];
+
+/// The parts of a `verbose_json` response we use
+#[derive(Deserialize)]
+struct ApiTranscript {
+    text: String,
+    #[serde(default)]
+    language: Option<String>,
+    #[serde(default)]
+    duration: Option<f64>,
+    #[serde(default)]
+    segments: Vec<Segment>,
+}
+
+/// Why an upload failed: transient failures are retried, the rest are reported right away
+enum UploadError {
+    Transient(String),
+    Fatal(String),
+}
+
+/// Resolves the API base URL with priority:
+/// 1. WA_API_URL env var
+/// 2. User config file `api_url`
+/// 3. Default to "https://api.openai.com/v1"
+pub fn api_url_setting() -> Resolved<String> {
+    Layers::new("API URL")
+        .env("WA_API_URL", Some)
+        .config(|c| c.api_url)
+        .or_default("https://api.openai.com/v1".to_string())
+}
+
+/// Resolves the API model with priority:
+/// 1. WA_API_MODEL env var
+/// 2. User config file `api_model`
+/// 3. Default to "whisper-1"
+pub fn api_model_setting() -> Resolved<String> {
+    Layers::new("API model")
+        .env("WA_API_MODEL", Some)
+        .config(|c| c.api_model)
+        .or_default("whisper-1".to_string())
+}
+
+/// The model sent to the API: `api_model`, or when it isn't set and `model` is an alias
+/// with an API model (`--model accurate`), that one
+pub fn api_model() -> String {
+    let setting = api_model_setting();
+    if setting.source == Source::Default {
+        let model = helpers::resolve_model();
+        let api_model = aliases::resolve(&model, "openai");
+        if api_model != model {
+            return api_model;
+        }
+    }
+    aliases::resolve(&setting.value, "openai")
+}
+
+/// API key from WA_API_KEY, then OPENAI_API_KEY (self-hosted servers may not need one)
+pub fn api_key_setting() -> Resolved<Option<String>> {
+    // Not through Layers, which logs the value
+    ["WA_API_KEY", "OPENAI_API_KEY"].into_iter()
+        .find_map(|var| {
+            let key = std::env::var(var).ok().filter(|key| !key.trim().is_empty())?;
+            Some(Resolved { value: Some(key), source: Source::Env(var) })
+        })
+        .unwrap_or(Resolved { value: None, source: Source::Default })
+}
+
+/// Stop recording and transcribe through an OpenAI-compatible API
+/// The audio is spooled to the state directory first and only deleted once the upload
+/// succeeded, so a dropped connection doesn't lose the dictation.
+pub fn stop_and_transcribe(output: &Output, max_retries: u32) -> Result<()> {
+    let audio_file = match recording::stop_recording(None)? {
+        Some(path) => path,
+        None => {
+            warn!("No recording found");
+            helpers::send_notification("Voice Input", "❌ No recording found", 2000);
+            return Ok(());
+        }
+    };
+
+    match fs::metadata(&audio_file) {
+        Err(_) => {
+            warn!("Audio file does not exist: {}", audio_file);
+            helpers::send_notification("Voice Input", "❌ No audio recorded\nBackend: openai", 2000);
+            return Ok(());
+        }
+        Ok(metadata) if audio::is_empty(&audio_file) => {
+            warn!("Audio file is empty (no samples after the WAV header): {} bytes", metadata.len());
+            helpers::send_notification("Voice Input", "❌ Audio file is empty\nBackend: openai", 2000);
+            let _ = privacy::remove_file(&audio_file);
+            return Ok(());
+        }
+        Ok(_) => {}
+    }
+    if recording::reject_silence(&audio_file) {
+        return Ok(());
+    }
+
+    let spooled = spool(&audio_file)?;
+    debug!("Spooled audio to {}", spooled.display());
+
+    let model = api_model();
+    let transcribe_msg = format!("⏳ Transcribing...\nBackend: openai | Model: {}", model);
+    helpers::send_notification("Voice Input", &transcribe_msg, 2000);
+
+    // Bilingual mode translates in a second upload, so the first keeps the spoken language
+    let translate = helpers::resolve_translate() && !bilingual::enabled();
+    latency::request_sent();
+    let started = Instant::now();
+    match upload_with_retries(&spooled, &model, translate, max_retries) {
+        Ok(mut response) => {
+            latency::response_received(response.inference_secs);
+            failover::record_attempts(&mut response, vec![Attempt::served(Step::OpenAi, started)]);
+            if bilingual::enabled() {
+                response.translation = if bilingual::is_english(response.language.as_deref()) {
+                    response.text.clone()
+                } else {
+                    upload_with_retries(&spooled, &model, true, max_retries)
+                        .and_then(bilingual::translated_text)
+                        .map_err(|e| warn!("Failed to translate the dictation: {:#}", e))
+                        .ok()
+                };
+            }
+            let _ = privacy::remove_file(&spooled);
+            hallucination::filter(&mut response);
+            if let Some(text) = response.text.take() {
+                let text = postprocess::apply(&text, response.language.as_deref());
+                response.text = Some(line_breaks::apply(&text, &response.segments));
+            }
+            transcript::publish(&mut response, "openai");
+            let typed = bilingual::output(&response, output, "openai");
+            transcript::report_latency(&mut response);
+            typed
+        }
+        Err(e) => {
+            helpers::send_notification(
+                "Voice Input",
+                &format!("❌ Transcription failed\n{}\nAudio kept at {}", e, spooled.display()),
+                10000
+            );
+            Err(anyhow!("{} (audio kept at {})", e, spooled.display()))
+        }
+    }
+}
+
+/// Transcribe a file through the API, retrying transient failures, without outputting it
+pub fn transcribe_file(audio_file: &Path, max_retries: u32) -> Result<TranscriptionResponse> {
+    upload_with_retries(audio_file, &api_model(), helpers::resolve_translate(), max_retries)
+}
+
+/// Move the recording out of the runtime directory (tmpfs, cleared on logout) into the spool
+fn spool(audio_file: &str) -> Result<PathBuf> {
+    let dir = paths::state_dir().join("spool");
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create spool directory {}", dir.display()))?;
+
+    let path = dir.join(format!("{}-{}.wav", helpers::local_timestamp("%Y%m%d-%H%M%S"), std::process::id()));
+    // The runtime directory is usually another filesystem, where rename fails
+    if fs::rename(audio_file, &path).is_err() {
+        fs::copy(audio_file, &path)
+            .with_context(|| format!("Failed to spool audio to {}", path.display()))?;
+        let _ = privacy::remove_file(audio_file);
+    }
+    Ok(path)
+}
+
+/// Upload, retrying transient failures with exponential backoff and jitter
+fn upload_with_retries(audio_file: &Path, model: &str, translate: bool, max_retries: u32) -> Result<TranscriptionResponse> {
+    let mut retries = 0;
+    loop {
+        match upload(audio_file, model, translate) {
+            Ok(response) => return Ok(response),
+            Err(UploadError::Transient(e)) if retries < max_retries => {
+                let delay = backoff(retries);
+                retries += 1;
+                warn!("Upload failed ({}), retry {}/{} in {:?}", e, retries, max_retries, delay);
+                helpers::send_notification(
+                    "Voice Input",
+                    &format!("🔄 Upload failed, retrying in {:.1}s ({}/{})\n{}", delay.as_secs_f32(), retries, max_retries, e),
+                    delay.as_millis() as u32 + 1000
+                );
+                std::thread::sleep(delay);
+            }
+            Err(UploadError::Transient(e)) => {
+                return Err(anyhow!("{} (gave up after {} attempts)", e, retries + 1));
+            }
+            Err(UploadError::Fatal(e)) => return Err(anyhow!(e)),
+        }
+    }
+}
+
+/// Delay before retry `retry` (0-based): half of the exponential delay plus a random share of the
+/// other half, so clients that failed together don't retry in lockstep
+fn backoff(retry: u32) -> Duration {
+    let delay = BASE_DELAY.saturating_mul(1 << retry.min(16)).min(MAX_DELAY);
+    let half = delay.as_millis() as u64 / 2;
+    let random = RandomState::new().build_hasher().finish();
+    Duration::from_millis(half + random % (half + 1))
+}
+
+/// POST the audio to the transcription (or translation) endpoint with curl
+fn upload(audio_file: &Path, model: &str, translate: bool) -> Result<TranscriptionResponse, UploadError> {
+    let endpoint = if translate { "translations" } else { "transcriptions" };
+    let url = format!("{}/audio/{}", api_url_setting().value.trim_end_matches('/'), endpoint);
+    let api_key = api_key_setting().value;
+    debug!("Uploading {} to {}", audio_file.display(), url);
+
+    // Audio held in memory has a /proc path without an extension, and the API goes by the file name
+    let mut file_form = format!("file=@{}", audio_file.display());
+    if audio_file.extension().is_none() {
+        file_form.push_str(";filename=audio.wav");
+    }
+
+    let mut command = Command::new("curl");
+    command.args([
+        "--silent",
+        "--show-error",
+        "--connect-timeout", "10",
+        "--max-time", "300",
+        "--write-out", "\n%{http_code}",
+        "--form", &file_form,
+        // --form-string for every value from the config, as one starting with @ or < would
+        // otherwise name a file to upload
+        "--form-string", &format!("model={}", model),
+        "--form-string", "response_format=verbose_json",
+    ]);
+    // The translation endpoint always produces English
+    let language = helpers::resolve_language();
+    if !translate && language != "auto" {
+        command.args(["--form-string", &format!("language={}", language)]);
+    }
+    if let Some(prompt) = helpers::resolve_initial_prompt() {
+        command.args(["--form-string", &format!("prompt={}", prompt)]);
+    }
+    // Read the header from stdin so the key doesn't show up in the process list
+    if api_key.is_some() {
+        command.args(["--header", "@-"]);
+    }
+    command.arg(&url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let started = Instant::now();
+    let mut child = command.spawn()
+        .map_err(|e| UploadError::Fatal(format!("Failed to run curl: {}", e)))?;
+    if let (Some(mut stdin), Some(key)) = (child.stdin.take(), api_key) {
+        let _ = writeln!(stdin, "Authorization: Bearer {}", key);
+    }
+    let result = child.wait_with_output()
+        .map_err(|e| UploadError::Fatal(format!("Failed to run curl: {}", e)))?;
+
+    if !result.status.success() {
+        let message = String::from_utf8_lossy(&result.stderr).trim().to_string();
+        return Err(curl_failure(result.status.code(), message));
+    }
+
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    let (body, status) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+    let status: u16 = status.trim().parse().unwrap_or(0);
+    debug!("API responded with HTTP {}", status);
+
+    if (200..300).contains(&status) {
+        let transcript: ApiTranscript = serde_json::from_str(body)
+            .map_err(|_| UploadError::Fatal(format!("Unexpected API response: {}", body.trim())))?;
+        // Includes the upload, which is most of the time on slow connections
+        let inference_secs = started.elapsed().as_secs_f64();
+        return Ok(TranscriptionResponse {
+            success: true,
+            text: Some(transcript.text.trim().to_string()),
+            segments: transcript.segments.into_iter()
+                .map(|segment| Segment { text: segment.text.trim().to_string(), ..segment })
+                .collect(),
+            language: transcript.language,
+            model: Some(model.to_string()),
+            duration: transcript.duration,
+            inference_secs: Some(inference_secs),
+            rtf: transcript.duration.filter(|d| *d > 0.0).map(|d| inference_secs / d),
+            ..Default::default()
+        });
+    }
+
+    let response: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+
+    let detail = response.pointer("/error/message")
+        .and_then(|message| message.as_str())
+        .unwrap_or(body.trim());
+    Err(http_failure(status, format!("HTTP {}: {}", status, detail)))
+}
+
+/// A failed curl run, by its exit code (None when killed by a signal)
+fn curl_failure(code: Option<i32>, message: String) -> UploadError {
+    match code {
+        Some(code) if TRANSIENT_CURL_CODES.contains(&code) => UploadError::Transient(message),
+        _ => UploadError::Fatal(message),
+    }
+}
+
+/// An error response from the API: timeouts, rate limits and server errors are worth retrying
+fn http_failure(status: u16, message: String) -> UploadError {
+    match status {
+        408 | 429 | 500..=599 => UploadError::Transient(message),
+        _ => UploadError::Fatal(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transient(error: UploadError) -> bool {
+        matches!(error, UploadError::Transient(_))
+    }
+
+    #[test]
+    fn backoff_doubles_with_jitter_up_to_the_cap() {
+        for retry in 0..8 {
+            let full = BASE_DELAY.saturating_mul(1 << retry).min(MAX_DELAY);
+            for _ in 0..50 {
+                let delay = backoff(retry);
+                assert!(delay >= full / 2 && delay <= full, "retry {}: {:?} outside {:?}", retry, delay, full);
+            }
+        }
+        // Long past the cap, and with shifts that would overflow
+        for retry in [5, 16, 17, 40, u32::MAX] {
+            let delay = backoff(retry);
+            assert!(delay >= MAX_DELAY / 2 && delay <= MAX_DELAY, "retry {}: {:?}", retry, delay);
+        }
+    }
+
+    #[test]
+    fn retries_only_transient_failures() {
+        for code in TRANSIENT_CURL_CODES {
+            assert!(transient(curl_failure(Some(code), String::new())), "curl exit {}", code);
+        }
+        // Bad URL, unreadable file, killed by a signal
+        for code in [Some(3), Some(26), None] {
+            assert!(!transient(curl_failure(code, String::new())), "curl exit {:?}", code);
+        }
+
+        for status in [408, 429, 500, 502, 503, 599] {
+            assert!(transient(http_failure(status, String::new())), "HTTP {}", status);
+        }
+        // Bad request, bad key, unknown model, too large; 0 when curl wrote no status
+        for status in [0, 400, 401, 404, 413, 600] {
+            assert!(!transient(http_failure(status, String::new())), "HTTP {}", status);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// Runtime artifacts (recordings, pidfiles, daemon config): XDG_RUNTIME_DIR or /tmp fallback
+pub fn runtime_dir() -> String {
+    std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| {
+        let uid = unsafe { libc::getuid() };
+        format!("/tmp/whisp-away-{}", uid)
+    })
+}
+
+fn home_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Replace a file in one step: the contents go to a temporary file next to it, which is
+/// renamed over the target, so readers see the old file or the new one, never half of it
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.tmp-{}", file_name, std::process::id()));
+    let result = fs::File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Persistent data: $XDG_DATA_HOME/whisp-away
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| home_dir().join(".local/share"))
+        .join("whisp-away")
+}
+
+/// History and other state worth keeping across runs: $XDG_STATE_HOME/whisp-away
+pub fn state_dir() -> PathBuf {
+    dirs::state_dir()
+        .unwrap_or_else(|| home_dir().join(".local/state"))
+        .join("whisp-away")
+}
+
+/// Regenerable caches: $XDG_CACHE_HOME/whisp-away
+pub fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| home_dir().join(".cache"))
+        .join("whisp-away")
+}
+
+/// Downloaded models, one subdirectory per backend
+pub fn models_dir() -> PathBuf {
+    data_dir().join("models")
+}
+
+/// Where models lived before the XDG layout
+pub fn legacy_whisper_cpp_models_dir() -> PathBuf {
+    home_dir().join(".cache/whisper-cpp/models")
+}
+
+fn legacy_faster_whisper_models_dir() -> PathBuf {
+    home_dir().join(".cache/faster-whisper")
+}
+
+/// ggml model file for a whisper.cpp model name (e.g. "base.en") or alias ("accurate")
+/// Models that couldn't be migrated are still found in ~/.cache/whisper-cpp/models
+pub fn whisper_cpp_model_path(model: &str) -> String {
+    let model = crate::aliases::resolve(model, "whisper-cpp");
+    // An alias may name a model file anywhere
+    if model.contains('/') {
+        return model;
+    }
+    let extension = if model.ends_with(".bin") { "" } else { ".bin" };
+    let file_name = format!("ggml-{}{}", model, extension);
+
+    let path = models_dir().join("whisper-cpp").join(&file_name);
+    let legacy = legacy_whisper_cpp_models_dir().join(&file_name);
+    let path = if !path.exists() && legacy.exists() { legacy } else { path };
+    path.to_string_lossy().into_owned()
+}
+
+/// Download root for faster-whisper (CTranslate2) models
+/// Existing ~/.cache/faster-whisper downloads keep being used rather than downloaded again
+pub fn faster_whisper_models_dir() -> PathBuf {
+    let path = models_dir().join("faster-whisper");
+    let legacy = legacy_faster_whisper_models_dir();
+    if !has_entries(&path) && has_entries(&legacy) {
+        return legacy;
+    }
+    path
+}
+
+fn has_entries(dir: &Path) -> bool {
+    fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_some())
+}
+
+/// Files from older versions: (old location, new location)
+fn legacy_runtime_files() -> Vec<(PathBuf, PathBuf)> {
+    let runtime = PathBuf::from(runtime_dir());
+    let uid = unsafe { libc::getuid() };
+    vec![
+        (PathBuf::from("/tmp/whisp-away-recording.pid"), runtime.join("whisp-away-recording.pid")),
+        (PathBuf::from(format!("/run/user/{}/voice-audio-file.tmp", uid)), runtime.join("whisp-away-audio-path")),
+    ]
+}
+
+/// Move a file, falling back to copy + remove across filesystems
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+/// Move files from the pre-XDG locations into the new layout
+/// Cheap when there is nothing to migrate, so it runs on every invocation
+pub fn migrate_legacy_files() {
+    for (old, new) in legacy_runtime_files() {
+        if old.exists() && !new.exists() {
+            match move_file(&old, &new) {
+                Ok(()) => debug!("Migrated {} -> {}", old.display(), new.display()),
+                Err(e) => debug!("Failed to migrate {}: {}", old.display(), e),
+            }
+        }
+    }
+    // The lock is recreated on demand
+    let _ = fs::remove_file("/tmp/whisp-away-recording.lock");
+
+    // Models are only renamed, never copied: a multi-GB copy is not worth it and
+    // whisper_cpp_model_path still finds models left in the legacy directory
+    let legacy_models = legacy_whisper_cpp_models_dir();
+    let Ok(entries) = fs::read_dir(&legacy_models) else {
+        return;
+    };
+    let target = models_dir().join("whisper-cpp");
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        if !name.to_string_lossy().starts_with("ggml-") || target.join(&name).exists() {
+            continue;
+        }
+        if let Err(e) = fs::create_dir_all(&target) {
+            warn!("Failed to create {}: {}", target.display(), e);
+            return;
+        }
+        match fs::rename(entry.path(), target.join(&name)) {
+            Ok(()) => info!("Moved model {} to {}", name.to_string_lossy(), target.display()),
+            Err(e) => debug!("Leaving model {} in {}: {}", name.to_string_lossy(), legacy_models.display(), e),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::Duration;
+use tracing::debug;
+use crate::helpers::{self, now_secs};
+use crate::paths;
+
+/// Do-not-dictate state - written by `disable`, checked before every recording start
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PauseState {
+    /// Unix time the pause was started
+    pub since: u64,
+    /// Unix time the pause expires; None means until `enable`
+    pub until: Option<u64>,
+}
+
+impl PauseState {
+    /// Human readable description for notifications and the tray
+    pub fn describe(&self) -> String {
+        match self.until {
+            Some(until) => format!("for another {}", format_duration(until.saturating_sub(now_secs()))),
+            None => "until re-enabled".to_string(),
+        }
+    }
+}
+
+fn state_path() -> String {
+    format!("{}/whisp-away-disabled.json", paths::runtime_dir())
+}
+
+/// Parse durations like "90s", "15m", "1h", "1h30m" or "7d" (bare numbers are minutes)
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let too_long = || format!("duration '{}' is too long", input);
+    if let Ok(minutes) = input.parse::<u64>() {
+        return minutes.checked_mul(60).map(Duration::from_secs).ok_or_else(too_long);
+    }
+
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value: u64 = number.parse().map_err(|_| format!("invalid duration '{}'", input))?;
+        number.clear();
+        let unit = match c {
+            'd' => 86400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(format!("invalid duration unit '{}' in '{}' (use d, h, m or s)", c, input)),
+        };
+        total = value.checked_mul(unit).and_then(|secs| total.checked_add(secs)).ok_or_else(too_long)?;
+    }
+    if !number.is_empty() || total == 0 {
+        return Err(format!("invalid duration '{}' (e.g. 30m, 1h, 1h30m)", input));
+    }
+    Ok(Duration::from_secs(total))
+}
+
+pub fn format_duration(secs: u64) -> String {
+    if secs >= 86400 && secs.is_multiple_of(86400) {
+        return format!("{}d", secs / 86400);
+    }
+    match (secs / 3600, (secs % 3600) / 60) {
+        (0, 0) => format!("{}s", secs),
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h{}m", h, m),
+    }
+}
+
+/// Current pause, if any. Expired pauses are removed on read.
+pub fn active() -> Option<PauseState> {
+    let content = fs::read_to_string(state_path()).ok()?;
+    let state: PauseState = match serde_json::from_str(&content) {
+        Ok(state) => state,
+        Err(e) => {
+            debug!("Ignoring unreadable pause state: {}", e);
+            return None;
+        }
+    };
+
+    if state.until.is_some_and(|until| until <= now_secs()) {
+        debug!("Dictation pause expired");
+        let _ = fs::remove_file(state_path());
+        return None;
+    }
+    Some(state)
+}
+
+/// Disable dictation, optionally expiring after `duration`
+pub fn disable(duration: Option<Duration>) -> Result<()> {
+    let now = now_secs();
+    let state = PauseState {
+        since: now,
+        until: duration.map(|d| now.saturating_add(d.as_secs())),
+    };
+
+    fs::create_dir_all(paths::runtime_dir()).ok();
+    fs::write(state_path(), serde_json::to_string_pretty(&state)?)
+        .context("Failed to write pause state")?;
+    debug!("Dictation disabled: {:?}", state);
+
+    helpers::send_notification("Voice Input", &format!("🔇 Dictation disabled {}", state.describe()), 3000);
+    Ok(())
+}
+
+/// Re-enable dictation
+pub fn enable() -> Result<()> {
+    match fs::remove_file(state_path()) {
+        Ok(()) => {
+            debug!("Dictation re-enabled");
+            helpers::send_notification("Voice Input", "🎙️ Dictation enabled", 2000);
+            Ok(())
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            debug!("Dictation was not disabled");
+            Ok(())
+        }
+        Err(e) => Err(e).context("Failed to remove pause state"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("15"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration(" 7d "), Ok(Duration::from_secs(7 * 86400)));
+        for invalid in ["", "0m", "1x", "h", "1h30", "-5"] {
+            assert!(parse_duration(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn rejects_durations_that_overflow() {
+        assert!(parse_duration(&u64::MAX.to_string()).is_err());
+        assert!(parse_duration(&format!("{}d", u64::MAX / 86400 + 1)).is_err());
+        assert!(parse_duration(&format!("{}s1s", u64::MAX)).is_err());
+        assert_eq!(parse_duration(&format!("{}s", u64::MAX)), Ok(Duration::from_secs(u64::MAX)));
+    }
+}
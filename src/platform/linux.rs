@@ -0,0 +1,410 @@
+use anyhow::{anyhow, Context, Result};
+use std::process::{Child, Command};
+#[cfg(feature = "tui")]
+use std::process::Stdio;
+#[cfg(feature = "tray")]
+use std::time::Duration;
+use tracing::debug;
+use crate::focus::{self, Protocol};
+use crate::recording::{self, Source};
+use crate::typing;
+#[cfg(feature = "tray")]
+use crate::portal::access_microphone;
+use super::Platform;
+
+/// pw-record flags for 16kHz 16-bit audio (what whisper expects) from the microphone; the
+/// channels come from `channels` and the volume from `mic_gain`, and stereo is downmixed
+/// after recording
+const MIC_ARGS: [&str; 4] = ["--rate", "16000", "--format", "s16"];
+
+/// The same from the monitor of the default output, which already plays at listening volume
+const MONITOR_ARGS: [&str; 8] = ["--channels", "1", "--rate", "16000", "--format", "s16", "-P", "{ stream.capture.sink=true }"];
+
+/// parecord flags for the same audio through the PulseAudio protocol, which a sandbox reaches
+/// through its PulseAudio socket (served by pipewire-pulse on PipeWire desktops)
+const PULSE_ARGS: [&str; 3] = ["--rate=16000", "--format=s16le", "--file-format=wav"];
+
+/// The monitor of the default output for parecord
+const PULSE_MONITOR_ARGS: [&str; 2] = ["--channels=1", "--device=@DEFAULT_MONITOR@"];
+
+/// Programs that open capture devices on behalf of everyone else; any other program holding
+/// one keeps PipeWire from recording it
+const SOUND_SERVERS: &[&str] = &["pipewire", "wireplumber", "pulseaudio", "jackd", "jackdbg"];
+
+/// Screen lockers that don't tell logind the session is locked (swaylock and the other
+/// ext-session-lock lockers, X11 ones), by process name
+const SCREEN_LOCKERS: &[&str] = &["swaylock", "hyprlock", "gtklock", "waylock", "i3lock", "xsecurelock", "slock", "physlock"];
+
+#[cfg(feature = "tray")]
+const LOGIND: &str = "org.freedesktop.login1";
+
+#[cfg(feature = "tray")]
+const LOGIND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// PipeWire for audio, wtype (Wayland) or xdotool (XWayland, X11) for keystrokes, notify-send
+pub struct Linux;
+
+impl Platform for Linux {
+    /// pw-record, or in a sandbox parecord once the portal has let us at the microphone
+    fn spawn_recorder(audio_file: &str, source: Source) -> Result<Child> {
+        let channels = recording::channels_setting().value.captured();
+        let gain = recording::mic_gain_setting().value;
+        if !recording::sandbox_setting().value {
+            let (channels, gain) = (channels.to_string(), gain.to_string());
+            let mic_args = [["--channels", channels.as_str(), "--volume", gain.as_str()].as_slice(), &MIC_ARGS].concat();
+            return spawn_recorder("pw-record", &mic_args, &MONITOR_ARGS, audio_file, source);
+        }
+        if source != Source::Monitor {
+            access_microphone()?;
+        }
+        let channels = format!("--channels={}", channels);
+        // PulseAudio volumes are linear, 65536 being 100%
+        let volume = format!("--volume={}", (gain * 65536.0).round() as u64);
+        let mic_args = [[channels.as_str(), volume.as_str()].as_slice(), &PULSE_ARGS].concat();
+        let monitor_args = [PULSE_MONITOR_ARGS.as_slice(), &PULSE_ARGS].concat();
+        spawn_recorder("parecord", &mic_args, &monitor_args, audio_file, source)
+    }
+
+    /// pw-play, or paplay where recordings go through PulseAudio
+    #[cfg(feature = "tui")]
+    fn spawn_player(audio_file: &str) -> Result<Child> {
+        let player = if recording::sandbox_setting().value { "paplay" } else { "pw-play" };
+        Command::new(player)
+            .arg(audio_file)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to start {}", player))
+    }
+
+    /// Asks wpctl about the default source (or output, for the monitor); without wpctl
+    /// nothing can be told
+    fn source_problem(source: Source) -> Option<String> {
+        // PipeWire can't be reached from a sandbox, only its PulseAudio server
+        if recording::sandbox_setting().value {
+            return None;
+        }
+        let nodes: &[(&str, &str)] = match source {
+            Source::Mic => &[("@DEFAULT_AUDIO_SOURCE@", "microphone")],
+            Source::Monitor => &[("@DEFAULT_AUDIO_SINK@", "audio output to record")],
+            Source::Both => &[("@DEFAULT_AUDIO_SOURCE@", "microphone"), ("@DEFAULT_AUDIO_SINK@", "audio output to record")],
+        };
+        for (node, what) in nodes {
+            let output = match Command::new("wpctl").args(["inspect", node]).output() {
+                Ok(output) => output,
+                Err(e) => {
+                    debug!("Can't check the {}: {}", what, e);
+                    return None;
+                }
+            };
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                if stderr.contains("connect") {
+                    return Some("PipeWire isn't running".to_string());
+                }
+                return Some(format!("no {} found", what));
+            }
+            if *node != "@DEFAULT_AUDIO_SOURCE@" {
+                continue;
+            }
+            // The microphone's sound card, for a program that took it before PipeWire could
+            let inspected = String::from_utf8_lossy(&output.stdout);
+            let card = inspected.lines().find_map(|line| {
+                let value = line.split_once("alsa.card = ")?.1;
+                Some(value.trim().trim_matches('"').to_string())
+            });
+            if let Some(holder) = card.and_then(|card| capture_holder(&card)) {
+                return Some(format!("the microphone is in use by {}", holder));
+            }
+        }
+        None
+    }
+
+    /// wpctl reports "Volume: 0.40 [MUTED]", pactl (PulseAudio, pipewire-pulse) "Mute: yes"
+    fn mic_muted() -> Option<bool> {
+        let output = |program: &str, args: &[&str]| {
+            let output = Command::new(program).args(args).output().ok()?;
+            output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+        };
+        if let Some(volume) = output("wpctl", &["get-volume", "@DEFAULT_AUDIO_SOURCE@"]) {
+            return Some(volume.contains("[MUTED]"));
+        }
+        output("pactl", &["get-source-mute", "@DEFAULT_SOURCE@"]).map(|mute| mute.trim() == "Mute: yes")
+    }
+
+    fn set_mic_muted(muted: bool) -> Result<()> {
+        let value = if muted { "1" } else { "0" };
+        if let Ok(status) = Command::new("wpctl").args(["set-mute", "@DEFAULT_AUDIO_SOURCE@", value]).status() {
+            if status.success() {
+                return Ok(());
+            }
+        }
+        debug!("wpctl set-mute failed, falling back to pactl");
+        let status = Command::new("pactl")
+            .args(["set-source-mute", "@DEFAULT_SOURCE@", value])
+            .status()
+            .context("Failed to change the microphone mute (tried wpctl and pactl)")?;
+        if !status.success() {
+            return Err(anyhow!("pactl set-source-mute failed"));
+        }
+        Ok(())
+    }
+
+    fn type_text(text: &str) -> Result<()> {
+        let wtype_delay = typing::wayland_delay_ms_setting().value.to_string();
+        let xdotool_delay = typing::xwayland_delay_ms_setting().value.to_string();
+        inject(
+            "Typing",
+            &["-d", &wtype_delay, text],
+            &["type", "--clearmodifiers", "--delay", &xdotool_delay, "--", text],
+        )
+    }
+
+    fn paste() -> Result<()> {
+        inject("Paste keystroke", &["-M", "ctrl", "-k", "v", "-m", "ctrl"], &["key", "--clearmodifiers", "ctrl+v"])
+    }
+
+    /// Ctrl+V is quoted insert in shells and readline
+    fn paste_in_terminal() -> Result<()> {
+        inject(
+            "Terminal paste keystroke",
+            &["-M", "ctrl", "-M", "shift", "-k", "v", "-m", "shift", "-m", "ctrl"],
+            &["key", "--clearmodifiers", "ctrl+shift+v"],
+        )
+    }
+
+    fn erase(count: usize) -> Result<()> {
+        let mut wtype_args = Vec::with_capacity(count * 2);
+        for _ in 0..count {
+            wtype_args.extend(["-k", "BackSpace"]);
+        }
+        let count = count.to_string();
+        inject(
+            "BackSpace keystrokes",
+            &wtype_args,
+            &["key", "--clearmodifiers", "--repeat", &count, "BackSpace"],
+        )
+    }
+
+    /// Desktops that lock through logind (GNOME, KDE, and lockers started by `loginctl
+    /// lock-session` that set the hint) report it on the session, otherwise a running locker
+    /// gives it away
+    fn screen_locked() -> bool {
+        if locked_hint() == Some(true) {
+            debug!("logind reports the session as locked");
+            return true;
+        }
+        match running_locker() {
+            Some(locker) => {
+                debug!("Screen locker {} is running", locker);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn notify(title: &str, message: &str, timeout_ms: u32) -> bool {
+        match Command::new("notify-send")
+            .args([
+                title,
+                message,
+                "-t", &timeout_ms.to_string(),
+                "-h", "string:x-canonical-private-synchronous:voice"
+            ])
+            .output()
+        {
+            Ok(output) if output.status.success() => true,
+            Ok(output) => {
+                debug!("notify-send failed: {}", String::from_utf8_lossy(&output.stderr));
+                false
+            }
+            Err(e) => {
+                debug!("Failed to run notify-send: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Through speech-dispatcher, which Orca speaks with too, so the voice and rate are the
+    /// screen reader's and messages queue with its own
+    fn speak(text: &str, important: bool) -> bool {
+        let priority = if important { "important" } else { "message" };
+        match Command::new("spd-say")
+            .args(["--application-name", "whisp-away", "--priority", priority, "--", text])
+            .output()
+        {
+            Ok(output) if output.status.success() => true,
+            Ok(output) => {
+                debug!("spd-say failed: {}", String::from_utf8_lossy(&output.stderr));
+                false
+            }
+            Err(e) => {
+                debug!("Failed to run spd-say: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// The tool that sends keystrokes to the focused window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Injector {
+    /// Native Wayland clients, through the virtual keyboard protocol
+    Wtype,
+    /// X11 clients, including those running under XWayland
+    Xdotool,
+}
+
+impl Injector {
+    /// The tool `typing_tool` names, or the one for the focused window
+    /// None when neither is known, to try wtype and fall back to xdotool
+    fn select() -> Option<Self> {
+        match typing::tool_setting().value.as_str() {
+            "wtype" => return Some(Injector::Wtype),
+            "xdotool" => return Some(Injector::Xdotool),
+            _ => {}
+        }
+        if std::env::var_os("WAYLAND_DISPLAY").is_none() {
+            return Some(Injector::Xdotool);
+        }
+        let window = focus::focused_window()?;
+        debug!("Focused window {:?} ({:?})", window.app_id, window.protocol);
+        match window.protocol? {
+            Protocol::Wayland => Some(Injector::Wtype),
+            Protocol::XWayland | Protocol::X11 => Some(Injector::Xdotool),
+        }
+    }
+}
+
+/// Run `recorder` with the arguments for `source` and the file last
+/// "both" runs a recorder per source under one shell, so stopping it stops both
+/// (background jobs of a non-interactive shell ignore SIGINT, hence the SIGTERM)
+fn spawn_recorder(recorder: &str, mic_args: &[&str], monitor_args: &[&str], audio_file: &str, source: Source) -> Result<Child> {
+    debug!("Starting {} ({})...", recorder, source);
+    let mut command;
+    match source {
+        Source::Mic => {
+            command = Command::new(recorder);
+            command.args(mic_args).arg(audio_file);
+        }
+        Source::Monitor => {
+            command = Command::new(recorder);
+            command.args(monitor_args).arg(audio_file);
+        }
+        Source::Both => {
+            let quoted = |args: &[&str]| args.iter().map(|arg| format!("'{}'", arg)).collect::<Vec<_>>().join(" ");
+            let script = format!(
+                "{recorder} {} \"$1\" & mic=$!; {recorder} {} \"$2\" & monitor=$!; \
+                 trap 'kill -TERM $mic $monitor 2>/dev/null' INT TERM; wait; wait",
+                quoted(mic_args), quoted(monitor_args)
+            );
+            command = Command::new("sh");
+            command.args(["-c", &script, "sh", audio_file, &recording::monitor_file(audio_file)]);
+        }
+    }
+    command.spawn().with_context(|| format!("Failed to start {}", recorder))
+}
+
+/// Send keystrokes with the tool for the focused window
+/// `what` names them in errors; without a known tool, wtype failing falls back to xdotool
+fn inject(what: &str, wtype_args: &[&str], xdotool_args: &[&str]) -> Result<()> {
+    let injector = Injector::select();
+    if injector != Some(Injector::Xdotool) {
+        match Command::new("wtype").args(wtype_args).status() {
+            Ok(status) if status.success() => {
+                debug!("{} sent with wtype", what);
+                return Ok(());
+            }
+            Ok(status) => debug!("wtype failed with status: {}", status),
+            Err(e) => debug!("Failed to run wtype: {}", e),
+        }
+        if injector == Some(Injector::Wtype) {
+            return Err(anyhow!("{} failed (wtype)", what));
+        }
+        debug!("Falling back to xdotool (X11)");
+    }
+
+    let status = Command::new("xdotool")
+        .args(xdotool_args)
+        .status()
+        .with_context(|| format!("{} failed: could not run xdotool", what))?;
+    if !status.success() {
+        return Err(anyhow!("{} failed (xdotool: {})", what, status));
+    }
+    debug!("{} sent with xdotool", what);
+    Ok(())
+}
+
+/// The program other than the sound server that has a capture device of ALSA card `card`
+/// open, as "name (PID n)"
+fn capture_holder(card: &str) -> Option<String> {
+    let card_dir = std::fs::read_dir(format!("/proc/asound/card{}", card)).ok()?;
+    let capture_devices = card_dir.flatten().filter(|entry| {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        name.starts_with("pcm") && name.ends_with('c')
+    });
+    for device in capture_devices {
+        let Ok(substreams) = std::fs::read_dir(device.path()) else {
+            continue;
+        };
+        for substream in substreams.flatten() {
+            // "closed", or the state followed by "owner_pid   : 1234"
+            let Ok(status) = std::fs::read_to_string(substream.path().join("status")) else {
+                continue;
+            };
+            let owner = status.lines().find_map(|line| line.strip_prefix("owner_pid")?.split(':').nth(1));
+            let Some(pid) = owner.and_then(|pid| pid.trim().parse::<u32>().ok()) else {
+                continue;
+            };
+            let name = std::fs::read_to_string(format!("/proc/{}/comm", pid)).unwrap_or_default().trim().to_string();
+            if !SOUND_SERVERS.contains(&name.as_str()) {
+                return Some(format!("{} (PID {})", name, pid));
+            }
+        }
+    }
+    None
+}
+
+/// logind's LockedHint for this session, or for the user's graphical session when this
+/// process isn't part of one (a systemd user service)
+#[cfg(feature = "tray")]
+fn locked_hint() -> Option<bool> {
+    use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+    use dbus::blocking::Connection;
+
+    let conn = Connection::new_system().map_err(|e| debug!("No system bus: {}", e)).ok()?;
+    let session = |path: dbus::Path<'static>| -> Result<bool, dbus::Error> {
+        conn.with_proxy(LOGIND, path, LOGIND_TIMEOUT).get("org.freedesktop.login1.Session", "LockedHint")
+    };
+    let result = session(dbus::Path::from("/org/freedesktop/login1/session/auto")).or_else(|_| {
+        let (_, path): (String, dbus::Path<'static>) = conn
+            .with_proxy(LOGIND, "/org/freedesktop/login1/user/self", LOGIND_TIMEOUT)
+            .get("org.freedesktop.login1.User", "Display")?;
+        session(path)
+    });
+    result.map_err(|e| debug!("Failed to read the session's lock state from logind: {}", e)).ok()
+}
+
+/// The portal is reached over D-Bus, which this build left out; the sandbox's PulseAudio
+/// access decides, as it does where the portal doesn't take the request
+#[cfg(not(feature = "tray"))]
+fn access_microphone() -> Result<()> {
+    debug!("Built without D-Bus (cargo feature \"tray\"), recording without asking the desktop portal");
+    Ok(())
+}
+
+/// Without D-Bus the lock state comes from the running screen locker alone
+#[cfg(not(feature = "tray"))]
+fn locked_hint() -> Option<bool> {
+    None
+}
+
+/// The name of a running screen locker process
+fn running_locker() -> Option<String> {
+    std::fs::read_dir("/proc").ok()?.flatten().find_map(|entry| {
+        let name = std::fs::read_to_string(entry.path().join("comm")).ok()?;
+        let name = name.trim();
+        SCREEN_LOCKERS.contains(&name).then(|| name.to_string())
+    })
+}
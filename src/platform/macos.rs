@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Context, Result};
+use std::process::{Child, Command, Stdio};
+use tracing::debug;
+use crate::recording::{self, Source};
+use super::Platform;
+
+/// sox `rec` flags for 16kHz 16-bit audio (what whisper expects) from the default input;
+/// the channels come from `channels`, and stereo is downmixed after recording
+const REC_ARGS: [&str; 7] = ["-q", "-r", "16000", "-b", "16", "-e", "signed-integer"];
+
+/// CoreAudio through sox for recording, System Events (osascript) for keystrokes and notifications
+/// Typing needs whichever app runs whisp-away (e.g. the terminal or skhd) to be allowed under
+/// Privacy & Security > Accessibility
+pub struct MacOs;
+
+/// Run an AppleScript, passing `args` as `item N of argv` so the text never needs escaping
+fn osascript(lines: &[&str], args: &[&str]) -> Result<()> {
+    let mut command = Command::new("osascript");
+    command.args(["-e", "on run argv"]);
+    for line in lines {
+        command.args(["-e", line]);
+    }
+    command.args(["-e", "end run"]).args(args);
+
+    let output = command.output().context("Failed to run osascript")?;
+    if !output.status.success() {
+        return Err(anyhow!("osascript failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+impl Platform for MacOs {
+    fn spawn_recorder(audio_file: &str, source: Source) -> Result<Child> {
+        if source != Source::Mic {
+            return Err(anyhow!(
+                "Recording system audio isn't supported on macOS: route it into a loopback device \
+                 (e.g. BlackHole), make that the default input and record the mic"
+            ));
+        }
+        debug!("Starting rec ({})...", source);
+        let channels = recording::channels_setting().value.captured().to_string();
+        Command::new("rec")
+            .args(REC_ARGS)
+            .args(["-c", &channels])
+            .arg(audio_file)
+            .args(["vol", &recording::mic_gain_setting().value.to_string()])
+            .spawn()
+            .context("Failed to start rec (install sox: `brew install sox`)")
+    }
+
+    #[cfg(feature = "tui")]
+    fn spawn_player(audio_file: &str) -> Result<Child> {
+        Command::new("afplay")
+            .arg(audio_file)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to start afplay")
+    }
+
+    /// macOS has no input mute, so an input volume of 0 counts as muted
+    fn mic_muted() -> Option<bool> {
+        let output = Command::new("osascript")
+            .args(["-e", "input volume of (get volume settings)"])
+            .output()
+            .ok()?;
+        let volume: u32 = String::from_utf8_lossy(&output.stdout).trim().parse().ok()?;
+        Some(volume == 0)
+    }
+
+    /// Unmuting sets the input volume to 75%, since the level before muting isn't known
+    fn set_mic_muted(muted: bool) -> Result<()> {
+        let volume = if muted { "0" } else { "75" };
+        osascript(&["set volume input volume (item 1 of argv as integer)"], &[volume])
+    }
+
+    fn type_text(text: &str) -> Result<()> {
+        osascript(&[r#"tell application "System Events" to keystroke (item 1 of argv)"#], &[text])
+    }
+
+    fn paste() -> Result<()> {
+        osascript(&[r#"tell application "System Events" to keystroke "v" using command down"#], &[])
+    }
+
+    fn erase(count: usize) -> Result<()> {
+        // Key code 51 is Delete (backspace)
+        osascript(
+            &[r#"tell application "System Events""#,
+              "repeat (item 1 of argv as integer) times",
+              "key code 51",
+              "end repeat",
+              "end tell"],
+            &[&count.to_string()]
+        )
+    }
+
+    /// Notification Center decides how long notifications stay, so the timeout is ignored
+    fn notify(title: &str, message: &str, _timeout_ms: u32) -> bool {
+        match osascript(&["display notification (item 2 of argv) with title (item 1 of argv)"], &[title, message]) {
+            Ok(()) => true,
+            Err(e) => {
+                debug!("{:#}", e);
+                false
+            }
+        }
+    }
+    /// With `say`, in the system voice VoiceOver also uses; `say` has no queue to jump, so
+    /// `important` makes no difference
+    fn speak(text: &str, _important: bool) -> bool {
+        match Command::new("say").arg(text).stdin(Stdio::null()).spawn() {
+            Ok(mut child) => {
+                // It runs for as long as the text takes to say
+                std::thread::spawn(move || child.wait());
+                true
+            }
+            Err(e) => {
+                debug!("Failed to run say: {}", e);
+                false
+            }
+        }
+    }
+}
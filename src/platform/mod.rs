@@ -0,0 +1,71 @@
+use anyhow::Result;
+use std::process::Child;
+use crate::recording::Source;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(windows)]
+mod windows;
+
+/// The implementation for the OS being built
+#[cfg(target_os = "linux")]
+pub type Current = linux::Linux;
+#[cfg(target_os = "macos")]
+pub type Current = macos::MacOs;
+#[cfg(windows)]
+pub type Current = windows::Windows;
+
+/// The OS-specific pieces: audio capture and mute state, synthetic keystrokes, desktop
+/// notifications and speech
+/// Everything else (sockets, locks, signals) is plain Unix and shared
+pub trait Platform {
+    /// Start recording `source` as 16kHz 16-bit WAV into `audio_file`, mono unless `channels`
+    /// asks for both microphone channels
+    /// The recorder must finish the file when sent SIGINT or SIGTERM
+    fn spawn_recorder(audio_file: &str, source: Source) -> Result<Child>;
+
+    /// Why `source` can't be recorded right now (no such device, or another program holding
+    /// it for itself); None when it looks fine or that can't be told
+    fn source_problem(_source: Source) -> Option<String> {
+        None
+    }
+
+    /// Play a recording on the default output, without waiting for it to finish
+    #[cfg(feature = "tui")]
+    fn spawn_player(audio_file: &str) -> Result<Child>;
+
+    /// Type text at the cursor
+    fn type_text(text: &str) -> Result<()>;
+
+    /// Send the paste shortcut to the focused window
+    fn paste() -> Result<()>;
+
+    /// Send a terminal's paste shortcut to the focused window, where the usual one may mean
+    /// something else to the program running inside
+    fn paste_in_terminal() -> Result<()> {
+        Self::paste()
+    }
+
+    /// Delete `count` characters before the cursor
+    fn erase(count: usize) -> Result<()>;
+
+    /// Whether the default microphone is muted; None when that can't be told
+    fn mic_muted() -> Option<bool>;
+
+    /// Mute or unmute the default microphone
+    fn set_mic_muted(muted: bool) -> Result<()>;
+
+    /// Whether the screen is locked, so keystrokes would go to the lock screen
+    fn screen_locked() -> bool {
+        false
+    }
+
+    /// Show a desktop notification; false when it couldn't be shown
+    fn notify(title: &str, message: &str, timeout_ms: u32) -> bool;
+
+    /// Say `text` with the speech service screen readers use, without waiting for it to be
+    /// spoken; `important` interrupts whatever is being said; false when nothing can speak
+    fn speak(text: &str, important: bool) -> bool;
+}
@@ -0,0 +1,53 @@
+use anyhow::{anyhow, Result};
+use std::process::Child;
+use crate::recording::Source;
+use super::Platform;
+
+/// Placeholder so the platform layer builds on Windows: nothing here records, types or
+/// notifies yet, and every action says so
+/// The rest is still Unix-only (the recording lock, stop signal and daemon socket), so this
+/// is where a port would start rather than a working one
+pub struct Windows;
+
+fn unsupported(what: &str) -> anyhow::Error {
+    anyhow!("{} isn't supported on Windows yet", what)
+}
+
+impl Platform for Windows {
+    fn spawn_recorder(_audio_file: &str, _source: Source) -> Result<Child> {
+        Err(unsupported("Recording"))
+    }
+
+    #[cfg(feature = "tui")]
+    fn spawn_player(_audio_file: &str) -> Result<Child> {
+        Err(unsupported("Playing recordings"))
+    }
+
+    fn type_text(_text: &str) -> Result<()> {
+        Err(unsupported("Typing"))
+    }
+
+    fn paste() -> Result<()> {
+        Err(unsupported("Pasting"))
+    }
+
+    fn erase(_count: usize) -> Result<()> {
+        Err(unsupported("Erasing typed text"))
+    }
+
+    fn mic_muted() -> Option<bool> {
+        None
+    }
+
+    fn set_mic_muted(_muted: bool) -> Result<()> {
+        Err(unsupported("Muting the microphone"))
+    }
+
+    fn notify(_title: &str, _message: &str, _timeout_ms: u32) -> bool {
+        false
+    }
+
+    fn speak(_text: &str, _important: bool) -> bool {
+        false
+    }
+}
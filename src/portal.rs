@@ -0,0 +1,158 @@
+use anyhow::{anyhow, Context, Result};
+use dbus::arg::{AppendAll, PropMap, Variant};
+use dbus::blocking::Connection;
+use dbus::message::MatchRule;
+use dbus::Path as ObjectPath;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+const DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PATH: &str = "/org/freedesktop/portal/desktop";
+const REQUEST: &str = "org.freedesktop.portal.Request";
+const DEVICE: &str = "org.freedesktop.portal.Device";
+
+const CALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Requests can open a dialog, e.g. where the user confirms or picks shortcut keys
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Recording waits on the microphone request, so it gets a few seconds rather than minutes;
+/// once the desktop has remembered the answer, the portal replies right away
+const MICROPHONE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Error for a request the user turned down in the portal's dialog
+#[derive(Debug)]
+pub struct Cancelled(String);
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} was cancelled", self.0)
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Error for a portal interface the desktop doesn't provide
+#[derive(Debug)]
+pub struct Unavailable(String);
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The desktop portal doesn't offer {}", self.0)
+    }
+}
+
+impl std::error::Error for Unavailable {}
+
+/// Error for a call the portal won't take from us at all, e.g. one only unsandboxed apps may make
+#[derive(Debug)]
+pub struct Refused(String);
+
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The desktop portal refused {}", self.0)
+    }
+}
+
+impl std::error::Error for Refused {}
+
+/// Tell the portal which app this is: it can't tell a host (non-Flatpak) app's ID from the
+/// process, and without one some desktops refuse to hand out shortcuts
+/// Portals older than the registry (1.19) don't need it
+pub fn register_app(conn: &Connection) {
+    let result: Result<(), dbus::Error> = conn
+        .with_proxy(DESTINATION, PATH, CALL_TIMEOUT)
+        .method_call("org.freedesktop.host.portal.Registry", "Register", ("whisp-away", PropMap::new()));
+    if let Err(e) = result {
+        debug!("Not registered with the portal as whisp-away: {}", e);
+    }
+}
+
+pub fn handle_options(handle_token: &str) -> PropMap {
+    let mut options = PropMap::new();
+    options.insert("handle_token".to_string(), Variant(Box::new(handle_token.to_string())));
+    options
+}
+
+/// Call a portal method and wait for the Response signal on its request object, which the
+/// portal creates under a path made of our bus name and `handle_token`
+pub fn request<A: AppendAll>(conn: &Connection, interface: &str, method: &str, handle_token: &str, args: A) -> Result<PropMap> {
+    request_within(conn, interface, method, handle_token, args, RESPONSE_TIMEOUT)
+}
+
+/// `request`, giving up when the response takes longer than `timeout`
+fn request_within<A: AppendAll>(conn: &Connection, interface: &str, method: &str, handle_token: &str, args: A, timeout: Duration) -> Result<PropMap> {
+    let sender = conn.unique_name().trim_start_matches(':').replace('.', "_");
+    let path = format!("{}/request/{}/{}", PATH, sender, handle_token);
+
+    // Listening before the call, so a quick response isn't missed
+    let response: Arc<Mutex<Option<(u32, PropMap)>>> = Arc::new(Mutex::new(None));
+    let slot = Arc::clone(&response);
+    conn.add_match(
+        MatchRule::new_signal(REQUEST, "Response").with_path(ObjectPath::from(path)),
+        move |answer: (u32, PropMap), _, _| {
+            *slot.lock().unwrap() = Some(answer);
+            false
+        },
+    ).context("Failed to listen for the portal's response")?;
+
+    let _: (ObjectPath,) = conn
+        .with_proxy(DESTINATION, PATH, CALL_TIMEOUT)
+        .method_call(interface, method, args)
+        .map_err(|e| match e.name() {
+            Some("org.freedesktop.DBus.Error.UnknownMethod" | "org.freedesktop.DBus.Error.ServiceUnknown") => {
+                Unavailable(interface.trim_start_matches("org.freedesktop.portal.").to_string()).into()
+            }
+            Some("org.freedesktop.portal.Error.NotAllowed" | "org.freedesktop.DBus.Error.AccessDenied") => {
+                Refused(format!("{}: {}", method, e.message().unwrap_or_default())).into()
+            }
+            _ => anyhow!("{} failed: {}", method, e),
+        })?;
+
+    let started = Instant::now();
+    loop {
+        if let Some((code, results)) = response.lock().unwrap().take() {
+            return match code {
+                0 => Ok(results),
+                1 => Err(Cancelled(method.to_string()).into()),
+                _ => Err(anyhow!("{} failed", method)),
+            };
+        }
+        if started.elapsed() > timeout {
+            return Err(anyhow!("The portal didn't answer {} within {}s", method, timeout.as_secs()));
+        }
+        conn.process(Duration::from_millis(500)).context("Lost the session bus")?;
+    }
+}
+
+/// Ask the Device portal for the microphone before recording from a sandbox, which shows
+/// the desktop's permission dialog the first time and remembers the answer
+/// xdg-desktop-portal only takes this call from unsandboxed apps (it is meant for sound
+/// servers vetting their clients), and there's no portal for microphones otherwise, the Camera
+/// portal being for video only. Inside a Flatpak or snap the refusal is logged and the
+/// sandbox's own PulseAudio access (`--socket=pulseaudio`) decides. A denial in the dialog, or
+/// no answer within a few seconds, is an error
+pub fn access_microphone() -> Result<()> {
+    let conn = Connection::new_session().context("Failed to connect to the session bus")?;
+    let token = format!("whisp_away_mic_{}", std::process::id());
+    let args = (std::process::id(), vec!["microphone"], handle_options(&token));
+    let result = request_within(&conn, DEVICE, "AccessDevice", &token, args, MICROPHONE_TIMEOUT);
+    match result {
+        Ok(_) => {
+            debug!("Microphone access granted by the portal");
+            Ok(())
+        }
+        Err(e) if e.is::<Cancelled>() => Err(anyhow!("Microphone access was denied")),
+        Err(e) if e.is::<Refused>() => {
+            warn!("{}; recording relies on the sandbox's PulseAudio access (Flatpak: --socket=pulseaudio)", e);
+            Ok(())
+        }
+        Err(e) if e.is::<Unavailable>() => {
+            debug!("Recording without asking the portal: {}", e);
+            Ok(())
+        }
+        Err(e) => Err(e.context("Failed to get microphone access from the desktop portal")),
+    }
+}
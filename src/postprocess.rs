@@ -0,0 +1,119 @@
+use std::str::FromStr;
+use tracing::warn;
+use crate::{dictionary, grammar, hallucination, locale, spoken};
+use crate::settings::{Layers, Resolved};
+
+/// How a dictation ends
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Ending {
+    /// As the model punctuated it
+    #[default]
+    Keep,
+    /// As the model punctuated it, then a space, so the next dictation starts a word of its
+    /// own; only for text typed or copied
+    Space,
+    /// Without the punctuation at the end, for chat messages
+    Strip,
+}
+
+impl FromStr for Ending {
+    type Err = String;
+
+    /// Accepts "keep", "space" or "strip"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Ending::Keep),
+            "space" => Ok(Ending::Space),
+            "strip" => Ok(Ending::Strip),
+            _ => Err(format!("invalid ending '{}' (expected keep, space or strip)", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Ending {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ending::Keep => write!(f, "keep"),
+            Ending::Space => write!(f, "space"),
+            Ending::Strip => write!(f, "strip"),
+        }
+    }
+}
+
+/// Resolves how a dictation ends with priority:
+/// 1. WA_ENDING env var
+/// 2. User config file `ending`
+/// 3. Default to keep, the model's punctuation
+pub fn ending_setting() -> Resolved<Ending> {
+    let parse = |value: String| match value.parse::<Ending>() {
+        Ok(ending) => Some(ending),
+        Err(e) => {
+            warn!("Ignoring WA_ENDING: {}", e);
+            None
+        }
+    };
+    Layers::new("ending")
+        .env("WA_ENDING", parse)
+        .config(|c| c.ending.and_then(|value| value.parse().ok()))
+        .or_default(Ending::Keep)
+}
+
+/// Text steps between the transcriber and the output, in order:
+/// hallucinated phrases dropped, spelling mode or the user dictionary and spoken addresses,
+/// grammar correction, the language's quotes and numbers (`locale`), then the ending
+/// (`ending = "strip"`)
+/// `language` is the detected language, if the backend reported one
+pub fn apply(text: &str, language: Option<&str>) -> String {
+    let text = apply_to_segment(text, language);
+    // A spelled code keeps the punctuation that was spelled out
+    if spoken::spell_setting().value || ending_setting().value != Ending::Strip {
+        return text;
+    }
+    strip_ending(&text).to_string()
+}
+
+/// The steps of `apply` for a segment typed as soon as it is decoded: all but the ending,
+/// which only the whole transcript has
+pub fn apply_to_segment(text: &str, language: Option<&str>) -> String {
+    let text = hallucination::filter_text(text);
+    // A spelled code isn't a sentence, so it skips grammar correction
+    if spoken::spell_setting().value {
+        return spoken::spell(&text);
+    }
+    let corrected = grammar::correct(&spoken::normalize(&dictionary::apply(&text)), language);
+    locale::normalize(&corrected, language)
+}
+
+/// Punctuation `ending = "strip"` removes, including the full-width forms of CJK text
+const ENDING_PUNCTUATION: &[char] = &['.', ',', '!', '?', ';', ':', '…', '。', '，', '、', '！', '？', '；', '：'];
+
+/// The text without the punctuation it ends with; a closing quote or bracket stays, along
+/// with what it closes
+fn strip_ending(text: &str) -> &str {
+    text.trim_end()
+        .trim_end_matches(ENDING_PUNCTUATION)
+        .trim_end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_the_final_punctuation() {
+        assert_eq!(strip_ending("See you at five."), "See you at five");
+        assert_eq!(strip_ending("Really?! "), "Really");
+        assert_eq!(strip_ending("Well..."), "Well");
+        assert_eq!(strip_ending("Wait… "), "Wait");
+        assert_eq!(strip_ending("明天见。"), "明天见");
+    }
+
+    #[test]
+    fn leaves_the_rest_alone() {
+        assert_eq!(strip_ending("Hi. See you at 5.30"), "Hi. See you at 5.30");
+        assert_eq!(strip_ending("She said \"no.\""), "She said \"no.\"");
+        assert_eq!(strip_ending("(see above.)"), "(see above.)");
+        assert_eq!(strip_ending("ok"), "ok");
+        assert_eq!(strip_ending(""), "");
+    }
+}
@@ -0,0 +1,159 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{compiler_fence, Ordering};
+use tracing::debug;
+use crate::settings::{self, Layers, Resolved};
+use crate::socket::TranscriptionResponse;
+
+/// Resolves whether privacy mode is on with priority:
+/// 1. WA_PRIVACY env var
+/// 2. User config file `privacy`
+/// 3. Default to false
+pub fn enabled_setting() -> Resolved<bool> {
+    Layers::new("privacy")
+        .env("WA_PRIVACY", settings::flag)
+        .config(|c| c.privacy)
+        .or_default(false)
+}
+
+pub fn enabled() -> bool {
+    enabled_setting().value
+}
+
+/// Transcript text in a log line: the text, or in privacy mode only its length
+pub struct Redacted<'a>(&'a str);
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if enabled() {
+            f.write_str(&hidden(self.0))
+        } else {
+            f.write_str(self.0)
+        }
+    }
+}
+
+pub fn redact(text: &str) -> Redacted<'_> {
+    Redacted(text)
+}
+
+/// What privacy mode logs in place of `text`
+fn hidden(text: &str) -> String {
+    format!("<{} chars>", text.chars().count())
+}
+
+/// Chunk of zeros a file is overwritten with
+const SHRED_CHUNK: usize = 64 * 1024;
+
+/// Remove a recording or a transcript's working file; in privacy mode it is first
+/// overwritten with zeros and synced, so it doesn't linger in the free blocks of the disk
+/// (on copy-on-write filesystems and SSDs that is as far as a program can go)
+pub fn remove_file(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    if enabled() {
+        if let Err(e) = overwrite(path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                debug!("Failed to overwrite {}: {}", path.display(), e);
+            }
+        }
+    }
+    fs::remove_file(path)
+}
+
+fn overwrite(path: &Path) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let mut remaining = file.metadata()?.len() as usize;
+    let zeros = vec![0u8; SHRED_CHUNK];
+    while remaining > 0 {
+        let chunk = remaining.min(SHRED_CHUNK);
+        file.write_all(&zeros[..chunk])?;
+        remaining -= chunk;
+    }
+    file.sync_all()?;
+    debug!("Overwrote {}", path.display());
+    Ok(())
+}
+
+/// Overwrite bytes with zeros in a way the compiler can't skip because they are freed next
+fn zero(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        // SAFETY: `byte` is a valid, aligned and exclusive reference
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Zero a string holding a transcript (or audio) in privacy mode before it is dropped
+/// Copies made while it grew are beyond reach, so this narrows what stays in memory rather
+/// than guaranteeing nothing does
+pub fn wipe(text: &mut String) {
+    if !enabled() {
+        return;
+    }
+    // SAFETY: all zeros is valid UTF-8, and the string is cleared right after
+    zero(unsafe { text.as_bytes_mut() });
+    text.clear();
+}
+
+/// `wipe` for raw bytes, e.g. audio read into memory
+pub fn wipe_bytes(bytes: &mut Vec<u8>) {
+    if !enabled() {
+        return;
+    }
+    zero(bytes);
+    bytes.clear();
+}
+
+/// Wipe the text of a transcript that has been output: the whole text, its translation and
+/// its segments
+pub fn wipe_response(response: &mut TranscriptionResponse) {
+    if !enabled() {
+        return;
+    }
+    for text in [&mut response.text, &mut response.translation].into_iter().flatten() {
+        wipe(text);
+    }
+    for segment in &mut response.segments {
+        wipe(&mut segment.text);
+        for word in &mut segment.words {
+            wipe(&mut word.word);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hidden_text_shows_only_its_length() {
+        assert_eq!(hidden(""), "<0 chars>");
+        assert_eq!(hidden("Meet me at noon"), "<15 chars>");
+        // Characters, not bytes
+        assert_eq!(hidden("Grüße aus Köln"), "<14 chars>");
+        assert_eq!(hidden("first line\nsecond line"), "<22 chars>");
+        assert!(!hidden("secret plans").contains("secret"));
+    }
+
+    #[test]
+    fn zero_clears_every_byte() {
+        let mut bytes = b"the transcript".to_vec();
+        zero(&mut bytes);
+        assert_eq!(bytes, vec![0; 14]);
+    }
+
+    #[test]
+    fn overwrite_zeroes_files_of_any_size() {
+        for size in [0, 10, SHRED_CHUNK, SHRED_CHUNK * 2 + 7] {
+            let path = std::env::temp_dir().join(format!("whisp-away-shred-{}-{}", size, std::process::id()));
+            fs::write(&path, vec![0x5a; size]).unwrap();
+            overwrite(&path).unwrap();
+            let content = fs::read(&path).unwrap();
+            let _ = fs::remove_file(&path);
+            assert_eq!(content.len(), size);
+            assert!(content.iter().all(|&byte| byte == 0));
+        }
+    }
+}
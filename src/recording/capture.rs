@@ -0,0 +1,196 @@
+use anyhow::{anyhow, Result};
+use std::path::Path;
+#[cfg(feature = "whisper-cpp")]
+use std::process::Child;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, SystemTime};
+use tracing::debug;
+#[cfg(feature = "whisper-cpp")]
+use tracing::{info, warn};
+use crate::{audio, helpers, hooks, privacy, settings, socket};
+use crate::settings::{Layers, Resolved};
+use crate::typing::Output;
+use super::{mute, Source};
+
+/// The signal that ended `whisp-away capture`, 0 while it runs
+static RECEIVED_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    RECEIVED_SIGNAL.store(signal, Ordering::SeqCst);
+}
+
+/// Resolves whether the daemon records instead of the client with priority:
+/// 1. WA_DAEMON_RECORDING env var
+/// 2. User config file `daemon_recording`
+/// 3. Default to false
+pub fn daemon_recording_setting() -> Resolved<bool> {
+    Layers::new("daemon recording")
+        .env("WA_DAEMON_RECORDING", settings::flag)
+        .config(|c| c.daemon_recording)
+        .or_default(false)
+}
+
+/// A recording the daemon makes on a client's behalf (`start_recording` over the socket),
+/// for hotkey helpers in a sandbox without access to PipeWire or the runtime directory
+#[cfg(feature = "whisper-cpp")]
+pub struct Capture {
+    recorder: Child,
+    audio_file: String,
+    _unmuted: Option<mute::Unmuted>,
+}
+
+/// A new file for a client's recording
+fn capture_file() -> String {
+    let millis = SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    format!("{}/whisp-away-capture-{}.wav", super::recording_dir(), millis)
+}
+
+#[cfg(feature = "whisper-cpp")]
+impl Capture {
+    pub fn start(source: Source) -> Result<Self> {
+        let audio_file = capture_file();
+        let recorder = super::spawn_recorder(&audio_file, source)?;
+        let unmuted = mute::check(source);
+        hooks::fire(hooks::Event::RecordStart, &[("AUDIO_FILE", &audio_file), ("SOURCE", &source.to_string())]);
+        info!("Recording {} for a client into {} (PID {})", source.describe(), audio_file, recorder.id());
+        Ok(Self { recorder, audio_file, _unmuted: unmuted })
+    }
+
+    fn end(&mut self) {
+        // SAFETY: signals the recorder we spawned and still own
+        unsafe { libc::kill(self.recorder.id() as i32, libc::SIGINT) };
+        let _ = self.recorder.wait();
+    }
+
+    /// Stop the recorder and return the finished recording, mono and with system audio mixed
+    /// in like a dictation's; the caller removes it after transcription
+    pub fn stop(mut self) -> Result<String> {
+        self.end();
+        let audio_file = std::mem::take(&mut self.audio_file);
+        if let Err(e) = super::downmix(&audio_file) {
+            warn!("Failed to downmix {}: {:#}", audio_file, e);
+        }
+        if let Err(e) = super::mix_monitor(&audio_file) {
+            warn!("Failed to mix system audio into {}: {:#}", audio_file, e);
+        }
+        hooks::fire(hooks::Event::RecordStop, &[("AUDIO_FILE", &audio_file)]);
+        if !Path::new(&audio_file).exists() || audio::is_empty(&audio_file) {
+            let _ = privacy::remove_file(&audio_file);
+            return Err(anyhow!("No audio recorded"));
+        }
+        if super::is_silent(&audio_file) {
+            let _ = privacy::remove_file(&audio_file);
+            return Err(anyhow!("No speech detected"));
+        }
+        super::check_levels(&audio_file);
+        debug!("Client recording ready: {}", audio_file);
+        Ok(audio_file)
+    }
+}
+
+#[cfg(feature = "whisper-cpp")]
+impl Drop for Capture {
+    /// A recording that isn't stopped (cancelled, or the daemon shutting down) is discarded
+    fn drop(&mut self) {
+        if self.audio_file.is_empty() {
+            return;
+        }
+        self.end();
+        hooks::fire(hooks::Event::RecordStop, &[]);
+        let _ = privacy::remove_file(&self.audio_file);
+        let _ = privacy::remove_file(super::monitor_file(&self.audio_file));
+        debug!("Discarded client recording {}", self.audio_file);
+    }
+}
+
+/// `whisp-away capture`: print the path of a new recording, record into it until SIGINT or
+/// SIGTERM, then finish it like a dictation; the faster-whisper daemon runs it to record for
+/// its clients
+pub fn run(source: Option<Source>) -> Result<()> {
+    let source = super::source_setting(source).value;
+    let audio_file = &capture_file();
+    // SAFETY: the handler only stores to an atomic
+    unsafe {
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            libc::signal(signal, handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        }
+    }
+    let mut recorder = super::spawn_recorder(audio_file, source)?;
+    println!("{}", audio_file);
+    let unmuted = mute::check(source);
+    hooks::fire(hooks::Event::RecordStart, &[("AUDIO_FILE", audio_file), ("SOURCE", &source.to_string())]);
+    while RECEIVED_SIGNAL.load(Ordering::SeqCst) == 0 {
+        if let Some(status) = recorder.try_wait()? {
+            let _ = privacy::remove_file(audio_file);
+            return Err(anyhow!("Recorder exited ({})", status));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    // SAFETY: signals the recorder we spawned
+    unsafe { libc::kill(recorder.id() as i32, libc::SIGINT) };
+    let _ = recorder.wait();
+    drop(unmuted);
+    super::downmix(audio_file)?;
+    super::mix_monitor(audio_file)?;
+    hooks::fire(hooks::Event::RecordStop, &[("AUDIO_FILE", audio_file)]);
+    if !Path::new(audio_file).exists() || audio::is_empty(audio_file) {
+        let _ = privacy::remove_file(audio_file);
+        return Err(anyhow!("No audio recorded"));
+    }
+    if super::is_silent(audio_file) {
+        let _ = privacy::remove_file(audio_file);
+        return Err(anyhow!("No speech detected"));
+    }
+    Ok(())
+}
+
+/// Start a recording in the daemon instead of here (`daemon_recording`)
+pub fn start_in_daemon(source: Option<Source>) -> Result<()> {
+    if let Some(pause) = crate::pause::active() {
+        debug!("Dictation disabled, not recording: {:?}", pause);
+        helpers::send_notification("Voice Input", &format!("🔇 Dictation disabled {}\nRun `whisp-away enable` to resume", pause.describe()), 2000);
+        return Ok(());
+    }
+    let source = super::source_setting(source).value;
+    socket::start_recording(&helpers::resolve_socket_path(), source)?;
+
+    let mut recording_msg = format!(
+        "Recording... (release to stop)\nBackend: {} daemon | Model: {}",
+        helpers::resolve_backend(),
+        helpers::resolve_model()
+    );
+    if source != Source::Mic {
+        recording_msg.push_str(&format!("\nSource: {}", source.describe()));
+    }
+    helpers::send_notification("Voice Input", &recording_msg, 30000);
+    Ok(())
+}
+
+/// Whether the daemon is recording for a client
+pub fn daemon_is_recording() -> Result<bool> {
+    let status = socket::daemon_status(&helpers::resolve_socket_path())?;
+    Ok(status.get("recording").and_then(|v| v.as_bool()).unwrap_or(false))
+}
+
+/// Have the daemon stop its recording and transcribe it, then output the text as usual
+/// The audio never leaves the daemon, so there is no fallback to another backend
+pub fn stop_in_daemon(output: &Output) -> Result<()> {
+    crate::latency::stop_requested();
+    let backend = helpers::resolve_backend();
+    helpers::send_notification(
+        "Voice Input",
+        &format!("⏳ Transcribing...\nBackend: {} daemon | Model: {}", backend, helpers::resolve_model()),
+        2000
+    );
+    let request = socket::TranscriptionRequest {
+        command: Some("stop_and_transcribe".to_string()),
+        ..socket::TranscriptionRequest::new("")
+    };
+    socket::send_transcription_request(&helpers::resolve_socket_path(), &request, &backend, output)?;
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::Duration;
+use tracing::debug;
+use crate::{audio, helpers, hooks, postprocess, privacy, transcribe};
+use super::Source;
+
+/// The signal that ended the recording, 0 while it runs
+static RECEIVED_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    RECEIVED_SIGNAL.store(signal, Ordering::SeqCst);
+}
+
+/// Record in the foreground until signalled, then print the transcript on stdout
+/// No pidfile or recording lock is involved, so it runs alongside hotkey dictation
+/// - SIGUSR1: stop and transcribe
+/// - SIGUSR2, SIGINT (Ctrl+C), SIGTERM: cancel, discarding the audio
+pub fn run(source: Option<Source>) -> Result<()> {
+    let source = super::source_setting(source).value;
+    let audio_file = format!("{}/whisp-away-record-{}.wav", super::recording_dir(), std::process::id());
+    let pid = std::process::id();
+    let signal = record(&audio_file, source, &format!(
+        "Recording {} (PID {}): `kill -USR1 {}` transcribes, `kill -USR2 {}` or Ctrl+C cancels",
+        source.describe(), pid, pid, pid
+    ))?;
+    if signal != libc::SIGUSR1 {
+        return Err(discard(&audio_file));
+    }
+    finish(&audio_file)?;
+    if super::is_silent(&audio_file) {
+        let _ = privacy::remove_file(&audio_file);
+        return Err(anyhow!("No speech detected"));
+    }
+
+    let result = transcribe::file(&audio_file);
+    let _ = privacy::remove_file(&audio_file);
+    let text = postprocess::apply(&result?, None);
+    println!("{}", text);
+    Ok(())
+}
+
+/// Push-to-talk in one process (`whisp-away hold`), for a key's press binding: record until
+/// SIGTERM or SIGINT (sent by the release binding), then hand the recording to `transcribe`,
+/// which outputs the text like `stop`
+/// SIGUSR2 cancels. No pidfile or recording lock is involved, so nothing is left behind
+/// when the process is killed outright
+pub fn hold(source: Option<Source>, transcribe: impl FnOnce(String) -> Result<()>) -> Result<()> {
+    if let Some(pause) = crate::pause::active() {
+        debug!("Dictation disabled, not recording: {:?}", pause);
+        helpers::send_notification("Voice Input", &format!("🔇 Dictation disabled {}\nRun `whisp-away enable` to resume", pause.describe()), 2000);
+        return Ok(());
+    }
+    let source = super::source_setting(source).value;
+    let audio_file = format!("{}/whisp-away-hold-{}.wav", super::recording_dir(), std::process::id());
+    super::notify_recording(source);
+    let pid = std::process::id();
+    let signal = record(&audio_file, source, &format!(
+        "Recording {} (PID {}) until released: `kill {}` transcribes, `kill -USR2 {}` cancels",
+        source.describe(), pid, pid, pid
+    ))?;
+    crate::latency::stop_requested();
+    if signal == libc::SIGUSR2 {
+        debug!("{}", discard(&audio_file));
+        helpers::send_notification("Voice Input", "🚫 Recording cancelled", 1500);
+        return Ok(());
+    }
+    if let Err(e) = finish(&audio_file) {
+        helpers::send_notification("Voice Input", &format!("❌ {}", e), 2000);
+        return Err(e);
+    }
+    if super::reject_silence(&audio_file) {
+        return Ok(());
+    }
+    super::check_levels(&audio_file);
+    crate::latency::audio_ready();
+    transcribe(audio_file)
+}
+
+/// Record `source` into `audio_file` until one of the handled signals arrives, and return it
+fn record(audio_file: &str, source: Source, banner: &str) -> Result<libc::c_int> {
+    // SAFETY: the handler only stores to an atomic
+    unsafe {
+        for signal in [libc::SIGUSR1, libc::SIGUSR2, libc::SIGINT, libc::SIGTERM] {
+            libc::signal(signal, handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        }
+    }
+    let mut recorder = super::spawn_recorder(audio_file, source)?;
+    let unmuted = super::mute::check(source);
+    hooks::fire(hooks::Event::RecordStart, &[("AUDIO_FILE", audio_file), ("SOURCE", &source.to_string())]);
+    eprintln!("{}", banner);
+
+    let signal = loop {
+        let signal = RECEIVED_SIGNAL.load(Ordering::SeqCst);
+        if signal != 0 {
+            break signal;
+        }
+        if let Some(status) = recorder.try_wait()? {
+            let _ = privacy::remove_file(audio_file);
+            return Err(anyhow!("Recorder exited ({})", status));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+    debug!("Received signal {}, stopping recorder PID {}", signal, recorder.id());
+    // SAFETY: signals the recorder we spawned
+    unsafe { libc::kill(recorder.id() as i32, libc::SIGINT) };
+    let _ = recorder.wait();
+    drop(unmuted);
+    Ok(signal)
+}
+
+/// Throw a cancelled recording away
+fn discard(audio_file: &str) -> anyhow::Error {
+    hooks::fire(hooks::Event::RecordStop, &[]);
+    let _ = privacy::remove_file(audio_file);
+    let _ = privacy::remove_file(super::monitor_file(audio_file));
+    anyhow!("Recording cancelled")
+}
+
+/// Finish a recording to be transcribed, unless nothing was recorded
+fn finish(audio_file: &str) -> Result<()> {
+    // "both" is transcribed as one mixed recording, as in dictation
+    super::downmix(audio_file)?;
+    super::mix_monitor(audio_file)?;
+    hooks::fire(hooks::Event::RecordStop, &[("AUDIO_FILE", audio_file)]);
+
+    if !Path::new(audio_file).exists() || audio::is_empty(audio_file) {
+        let _ = privacy::remove_file(audio_file);
+        return Err(anyhow!("No audio recorded"));
+    }
+    Ok(())
+}
@@ -0,0 +1,705 @@
+pub mod capture;
+pub mod foreground;
+pub mod monitor;
+pub mod mute;
+pub mod segments;
+mod session;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::process::Child;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tracing::{debug, info, warn};
+use session::{RecordingSession, SessionPaths, SystemFs, SystemProcesses};
+use crate::audio;
+use crate::platform::{self, Platform};
+use crate::privacy;
+use crate::settings::{self, Layers, Resolved};
+
+const MAX_RECORDING_AGE_SECS: u64 = 600; // 10 minutes
+
+const MB: u64 = 1024 * 1024;
+
+/// Below this much free space no recording is started: about five minutes of audio
+const MIN_FREE_MB: u64 = 10;
+
+/// A channel whose loudest sample stays below this is taken for unconnected (all zeros, or
+/// the odd bit of noise), about -66 dBFS
+const DEAD_CHANNEL_PEAK: i32 = 16;
+
+/// A recording with more of its samples at full scale than this is clipping audibly; the
+/// odd clipped plosive is harmless
+const CLIPPING_FRACTION: f64 = 0.001;
+
+/// A DC offset beyond this share of full scale points at a faulty input or driver
+const DC_OFFSET_LIMIT: f64 = 0.02;
+
+/// Levels of the last recording stopped in this process
+static LEVELS: Mutex<Option<audio::Levels>> = Mutex::new(None);
+
+/// Audio a recording captures
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// The default microphone
+    #[default]
+    Mic,
+    /// The monitor of the default output, i.e. what the other side of a call says
+    Monitor,
+    /// Both, recorded to separate files: mixed for dictation, tagged by speaker in meeting notes
+    Both,
+}
+
+impl Source {
+    /// Human-readable name for notifications
+    pub fn describe(self) -> &'static str {
+        match self {
+            Source::Mic => "microphone",
+            Source::Monitor => "system audio",
+            Source::Both => "microphone and system audio",
+        }
+    }
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    /// Accepts "mic", "monitor" or "both"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mic" => Ok(Source::Mic),
+            "monitor" => Ok(Source::Monitor),
+            "both" => Ok(Source::Both),
+            _ => Err(format!("invalid source '{}' (expected mic, monitor or both)", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Mic => write!(f, "mic"),
+            Source::Monitor => write!(f, "monitor"),
+            Source::Both => write!(f, "both"),
+        }
+    }
+}
+
+/// Microphone channels a recording captures and how they become the mono audio whisper needs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Channels {
+    /// One channel, downmixed by the sound server
+    #[default]
+    Mono,
+    /// Both channels, averaged; a silent one is left out
+    Stereo,
+    /// Only the left channel of a stereo input
+    Left,
+    /// Only the right channel of a stereo input
+    Right,
+}
+
+impl Channels {
+    /// Channels the recorder captures
+    pub fn captured(self) -> u16 {
+        if self == Channels::Mono { 1 } else { 2 }
+    }
+}
+
+impl FromStr for Channels {
+    type Err = String;
+
+    /// Accepts "mono", "stereo", "left" or "right"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mono" => Ok(Channels::Mono),
+            "stereo" => Ok(Channels::Stereo),
+            "left" => Ok(Channels::Left),
+            "right" => Ok(Channels::Right),
+            _ => Err(format!("invalid channels '{}' (expected mono, stereo, left or right)", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Channels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Channels::Mono => write!(f, "mono"),
+            Channels::Stereo => write!(f, "stereo"),
+            Channels::Left => write!(f, "left"),
+            Channels::Right => write!(f, "right"),
+        }
+    }
+}
+
+/// Resolves the microphone channels with priority:
+/// 1. WA_RECORD_CHANNELS env var
+/// 2. User config file `channels`
+/// 3. Default to mono
+pub fn channels_setting() -> Resolved<Channels> {
+    let parse = |source: &str, value: String| match value.parse::<Channels>() {
+        Ok(channels) => Some(channels),
+        Err(e) => {
+            warn!("Ignoring recording channels from {}: {}", source, e);
+            None
+        }
+    };
+    Layers::new("recording channels")
+        .env("WA_RECORD_CHANNELS", |value| parse("env", value))
+        .config(|c| c.channels.and_then(|value| parse("config file", value)))
+        .or_default(Channels::Mono)
+}
+
+/// Resolves the gain applied to the microphone while recording with priority:
+/// 1. WA_MIC_GAIN env var
+/// 2. User config file `mic_gain`
+/// 3. Default to 1.5, a little boost for quiet microphones (1.0 records as is)
+pub fn mic_gain_setting() -> Resolved<f64> {
+    Layers::new("mic gain")
+        .env("WA_MIC_GAIN", settings::parsed)
+        .config(|c| c.mic_gain)
+        .or_default(1.5)
+}
+
+/// Resolves whether recordings go through the desktop portal and PulseAudio, for a sandbox
+/// (Flatpak, snap) without direct access to PipeWire, with priority:
+/// 1. WA_SANDBOX env var
+/// 2. Detected: a Flatpak (/.flatpak-info) or a snap (SNAP env var)
+/// 3. Default to false
+pub fn sandbox_setting() -> Resolved<bool> {
+    Layers::new("sandboxed recording")
+        .env("WA_SANDBOX", settings::flag)
+        .detected("sandbox", detect_sandbox)
+        .or_default(false)
+}
+
+fn detect_sandbox() -> Option<bool> {
+    let sandboxed = std::path::Path::new("/.flatpak-info").exists() || std::env::var_os("SNAP").is_some();
+    sandboxed.then_some(true)
+}
+
+/// Measure a finished recording, warning when the input clips or has a DC offset
+/// The levels are kept for the transcript's JSON report
+pub fn check_levels(audio_file: &str) {
+    let levels = match audio::levels(audio_file) {
+        Ok(levels) => levels,
+        Err(e) => {
+            debug!("Not measuring {}: {:#}", audio_file, e);
+            return;
+        }
+    };
+    debug!("Recording levels: {:?}", levels);
+    if levels.clipped_fraction > CLIPPING_FRACTION {
+        let gain = mic_gain_setting().value;
+        warn!("{:.1}% of the recording is clipped (mic gain {})", levels.clipped_fraction * 100.0, gain);
+        crate::helpers::send_notification(
+            "Voice Input",
+            &format!(
+                "⚠️ Microphone clipping ({:.1}% of samples at full scale)\nLower `mic_gain` (now {}) or the input volume",
+                levels.clipped_fraction * 100.0, gain
+            ),
+            4000
+        );
+    }
+    if levels.dc_offset.abs() > DC_OFFSET_LIMIT {
+        warn!("The recording has a DC offset of {:.1}% of full scale; the input may be faulty", levels.dc_offset * 100.0);
+    }
+    *LEVELS.lock().unwrap_or_else(|e| e.into_inner()) = Some(levels);
+}
+
+/// Resolves how much sound a recording needs to be transcribed with priority:
+/// 1. WA_MIN_SOUND_SECS env var
+/// 2. User config file `min_sound_secs`
+/// 3. Default to 0.2, about a syllable (0 transcribes every recording)
+pub fn min_sound_secs_setting() -> Resolved<f64> {
+    Layers::new("min sound secs")
+        .env("WA_MIN_SOUND_SECS", settings::parsed)
+        .config(|c| c.min_sound_secs)
+        .or_default(0.2)
+}
+
+/// Resolves whether a copy of the last recording is kept for replaying (`tui`) with priority:
+/// 1. WA_KEEP_LAST_RECORDING env var
+/// 2. User config file `keep_last_recording`
+/// 3. Default to false, recordings are deleted once transcribed
+pub fn keep_last_setting() -> Resolved<bool> {
+    Layers::new("keep last recording")
+        .env("WA_KEEP_LAST_RECORDING", settings::flag)
+        .config(|c| c.keep_last_recording)
+        .or_default(false)
+}
+
+/// Where the copy of the last recording is kept, in the runtime directory so it is gone
+/// after logging out
+pub fn last_recording_file() -> String {
+    format!("{}/whisp-away-last-recording.wav", crate::paths::runtime_dir())
+}
+
+/// Copy a finished recording for replaying, with `keep_last_recording` and outside privacy mode
+fn keep_last(audio_file: &str) {
+    if !keep_last_setting().value || privacy::enabled() {
+        return;
+    }
+    let last = last_recording_file();
+    match fs::copy(audio_file, &last) {
+        Ok(_) => debug!("Kept a copy of {} in {}", audio_file, last),
+        Err(e) => debug!("Failed to keep a copy of {}: {}", audio_file, e),
+    }
+}
+
+/// Whether a finished recording is essentially silence, as after an accidental toggle: less
+/// than `min_sound_secs` of it is louder than silence, so a key click doesn't count
+/// Recordings that can't be measured are transcribed
+pub fn is_silent(audio_file: &str) -> bool {
+    let min_sound_secs = min_sound_secs_setting().value;
+    if min_sound_secs <= 0.0 {
+        return false;
+    }
+    match audio::levels(audio_file) {
+        Ok(levels) if levels.loud_secs < min_sound_secs => {
+            info!("{:.2}s of sound in {} (peak {:.0} dBFS), not transcribing it", levels.loud_secs, audio_file, levels.peak_dbfs);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Skip the backend for a silent recording: notify, remove it and return true
+/// The last segment of a dictation recorded in segments goes on, as the earlier ones had speech
+pub fn reject_silence(audio_file: &str) -> bool {
+    if segments::pending() || !is_silent(audio_file) {
+        return false;
+    }
+    crate::helpers::send_notification("Voice Input", "⚠️ No speech detected", 2000);
+    let _ = privacy::remove_file(audio_file);
+    true
+}
+
+/// Levels of the last recording stopped in this process, if it was measured
+pub fn last_levels() -> Option<audio::Levels> {
+    *LEVELS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Resolves the audio source with priority:
+/// 1. CLI flag (--source)
+/// 2. WA_RECORD_SOURCE env var
+/// 3. User config file `source`
+/// 4. Default to the microphone
+pub fn source_setting(cli_value: Option<Source>) -> Resolved<Source> {
+    let parse = |origin: &str, value: String| match value.parse::<Source>() {
+        Ok(source) => Some(source),
+        Err(e) => {
+            warn!("Ignoring recording source from {}: {}", origin, e);
+            None
+        }
+    };
+    Layers::new("recording source")
+        .cli(cli_value)
+        .env("WA_RECORD_SOURCE", |value| parse("env", value))
+        .config(|c| c.source.and_then(|value| parse("config file", value)))
+        .or_default(Source::Mic)
+}
+
+/// File the system audio of a "both" recording is written to, next to the microphone's
+pub fn monitor_file(audio_file: &str) -> String {
+    match audio_file.strip_suffix(".wav") {
+        Some(stem) => format!("{}-monitor.wav", stem),
+        None => format!("{}-monitor", audio_file),
+    }
+}
+
+type SystemSession = RecordingSession<SystemFs, SystemProcesses>;
+
+/// Session backed by the real filesystem and processes
+fn system_session() -> SystemSession {
+    let runtime_dir = crate::paths::runtime_dir();
+    RecordingSession::new(SystemFs, SystemProcesses, SessionPaths {
+        pid_file: format!("{}/whisp-away-recording.pid", runtime_dir),
+        lock_file: format!("{}/whisp-away-recording.lock", runtime_dir),
+        command_lock_file: format!("{}/whisp-away-command.lock", runtime_dir),
+        audio_path_file: format!("{}/whisp-away-audio-path", runtime_dir),
+        runtime_dir,
+    })
+}
+
+thread_local! {
+    /// Shared by everything a command does, so a toggle's start or stop reuses the
+    /// command lock the toggle already holds
+    static SESSION: SystemSession = system_session();
+}
+
+fn with_session<T>(f: impl FnOnce(&SystemSession) -> T) -> T {
+    SESSION.with(f)
+}
+
+/// Check if a recording is currently in progress
+pub fn is_recording() -> bool {
+    with_session(|session| session.is_recording())
+}
+
+/// The WAV the recording in progress is written to
+pub fn recording_file() -> Option<String> {
+    with_session(|session| session.audio_file())
+}
+
+/// Go on recording into `next_audio_file`, returning `audio_file` once closed; None when it
+/// isn't being recorded anymore
+fn rotate(audio_file: &str, next_audio_file: &str, source: Source) -> Result<Option<session::Rotated>> {
+    with_session(|session| session.rotate(audio_file, next_audio_file, source))
+}
+
+/// Start a recording with `start`, or stop it with `stop`, without racing other invocations
+/// `stop` is expected to call `stop_recording`, which lets other commands run again
+/// once the recorder has stopped, before transcription
+pub fn toggle<T>(start: impl FnOnce() -> Result<T>, stop: impl FnOnce() -> Result<T>) -> Result<T> {
+    with_session(|session| session.toggle(start, stop))
+}
+
+/// Disk-backed directory recordings spill to when the runtime directory is short on space
+fn spill_dir() -> String {
+    crate::paths::cache_dir().join("recordings").to_string_lossy().into_owned()
+}
+
+/// Free and total bytes of the filesystem holding `dir`
+fn filesystem_space(dir: &str) -> Option<(u64, u64)> {
+    let path = std::ffi::CString::new(dir).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let block = stat.f_frsize as u64;
+    Some((stat.f_bavail as u64 * block, stat.f_blocks as u64 * block))
+}
+
+/// Directory a new recording is written to
+/// XDG_RUNTIME_DIR is a tmpfs of a few hundred MB on most systems, and an hour of audio
+/// takes ~115 MB, so when it has less than the spill threshold free the recording goes to disk
+pub fn recording_dir() -> String {
+    let runtime_dir = crate::paths::runtime_dir();
+    // The /tmp fallback doesn't exist until first use
+    fs::create_dir_all(&runtime_dir).ok();
+    
+    let Some((free, total)) = filesystem_space(&runtime_dir) else {
+        return runtime_dir;
+    };
+    let threshold = crate::helpers::resolve_spill_threshold_mb() * MB;
+    debug!("Runtime dir {}: {} MB free of {} MB (spill threshold {} MB)", runtime_dir, free / MB, total / MB, threshold / MB);
+    if free >= threshold {
+        return runtime_dir;
+    }
+    
+    let spill_dir = spill_dir();
+    if let Err(e) = fs::create_dir_all(&spill_dir) {
+        warn!("Runtime dir is nearly full but {} can't be created: {}", spill_dir, e);
+        return runtime_dir;
+    }
+    warn!("Runtime dir {} has only {} MB free, recording to {}", runtime_dir, free / MB, spill_dir);
+    spill_dir
+}
+
+/// Clean up old recording files (older than MAX_RECORDING_AGE_SECS)
+fn cleanup_old_recordings(runtime_dir: &str, current_audio_file: Option<&str>) {
+    debug!("Cleaning up old recording files in {}", runtime_dir);
+    
+    let now = SystemTime::now();
+    let mut cleaned = 0;
+    
+    if let Ok(entries) = fs::read_dir(runtime_dir) {
+        for entry in entries.flatten() {
+            if let Some(name) = entry.file_name().to_str() {
+                // Clean up voice recording files and the segments of long ones
+                if name.starts_with("voice-recording-") && (name.ends_with(".wav") || name.ends_with("-segments.json")) {
+                    let path = entry.path();
+                    
+                    // Don't delete the current recording file
+                    if current_audio_file == path.to_str() {
+                        continue;
+                    }
+                    
+                    // Check file age
+                    if let Ok(metadata) = entry.metadata() {
+                        if let Ok(modified) = metadata.modified() {
+                            if let Ok(age) = now.duration_since(modified) {
+                                if age.as_secs() > MAX_RECORDING_AGE_SECS {
+                                    debug!("Removing old recording: {} (age: {}s)", name, age.as_secs());
+                                    if privacy::remove_file(&path).is_ok() {
+                                        cleaned += 1;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    
+    if cleaned > 0 {
+        debug!("Cleaned up {} old recording file(s)", cleaned);
+    }
+}
+
+/// Stop the recording process and return the audio file path
+pub fn stop_recording(audio_file_override: Option<&str>) -> Result<Option<String>> {
+    debug!("Stopping recording...");
+    crate::latency::stop_requested();
+    let audio_file = with_session(|session| session.stop(audio_file_override))?;
+    mute::restore_for_session();
+
+    if let Some(path) = &audio_file {
+        if let Err(e) = downmix(path) {
+            warn!("Failed to downmix {}: {:#}", path, e);
+        }
+        if let Err(e) = mix_monitor(path) {
+            warn!("Failed to mix system audio into {}: {:#}", path, e);
+        }
+        if let Err(e) = segments::collect(path) {
+            warn!("Failed to put the dictation's segments together: {:#}", e);
+        }
+        crate::hooks::fire(crate::hooks::Event::RecordStop, &[("AUDIO_FILE", path)]);
+        check_levels(path);
+        keep_last(path);
+        if let Ok(wav) = audio::probe(path) {
+            debug!("Audio file ready: {} ({:.1}s, {} bytes of samples)", path, wav.duration_secs(), wav.data_len);
+        }
+        crate::latency::audio_ready();
+    }
+
+    Ok(audio_file)
+}
+
+/// Turn a stereo microphone recording into mono as `channels` says; mono recordings are
+/// left alone
+/// Interfaces that put the mic on one side of a stereo pair leave the other all zeros, so
+/// that side is dropped rather than averaged in at half the volume
+pub fn downmix(audio_file: &str) -> Result<()> {
+    let samples = audio::Samples::open(audio_file)?;
+    let info = samples.info;
+    if info.channels != 2 || info.bits_per_sample != 16 {
+        return Ok(());
+    }
+
+    // Read twice rather than held in memory: once for the peaks, once to write the mono file
+    let frame = |bytes: &[u8]| (i16::from_le_bytes([bytes[0], bytes[1]]), i16::from_le_bytes([bytes[2], bytes[3]]));
+    let (mut left_peak, mut right_peak) = (0i32, 0i32);
+    samples.for_each_block(|block| {
+        for (left, right) in block.chunks_exact(4).map(frame) {
+            left_peak = left_peak.max((left as i32).abs());
+            right_peak = right_peak.max((right as i32).abs());
+        }
+        Ok(())
+    })?;
+    let left_dead = left_peak < DEAD_CHANNEL_PEAK;
+    let right_dead = right_peak < DEAD_CHANNEL_PEAK;
+
+    let channels = channels_setting().value;
+    let keep = match channels {
+        Channels::Left | Channels::Right => {
+            let (chosen_dead, other_dead) = if channels == Channels::Left { (left_dead, right_dead) } else { (right_dead, left_dead) };
+            if chosen_dead && !other_dead {
+                warn!("The {} channel of {} is silent; the microphone is probably on the other side", channels, audio_file);
+            }
+            channels
+        }
+        _ if left_dead && !right_dead => Channels::Right,
+        _ if right_dead && !left_dead => Channels::Left,
+        _ => Channels::Stereo,
+    };
+    debug!("Downmixing {} ({} frames, keeping {})", audio_file, info.data_len / 4, keep);
+
+    let mut mono = audio::WavWriter::create(audio_file, 1, info.sample_rate)?;
+    let mut samples = Vec::new();
+    audio::Samples::open(audio_file)?.for_each_block(|block| {
+        samples.clear();
+        for (left, right) in block.chunks_exact(4).map(frame) {
+            let sample = match keep {
+                Channels::Left => left,
+                Channels::Right => right,
+                _ => ((left as i32 + right as i32) / 2) as i16,
+            };
+            samples.extend_from_slice(&sample.to_le_bytes());
+        }
+        mono.write(&samples)
+    })?;
+    mono.finish()
+}
+
+/// Mix the system audio of a "both" recording into the microphone recording
+/// Both are 16kHz mono s16 and started together, so samples are added in place
+fn mix_monitor(audio_file: &str) -> Result<()> {
+    let monitor_file = monitor_file(audio_file);
+    if !std::path::Path::new(&monitor_file).exists() {
+        return Ok(());
+    }
+    let mut mic = audio::Samples::open(audio_file)?;
+    let mut monitor = audio::Samples::open(&monitor_file).with_context(|| format!("Failed to read {}", monitor_file))?;
+    let mut mixed = audio::WavWriter::create(audio_file, mic.info.channels, mic.info.sample_rate)?;
+
+    // Block by block: both are read in equal blocks, and whichever recorder stopped last
+    // sets the length
+    let mut samples = Vec::new();
+    loop {
+        let (mic_block, monitor_block) = (mic.next_block()?, monitor.next_block()?);
+        if mic_block.is_empty() && monitor_block.is_empty() {
+            break;
+        }
+        samples.clear();
+        samples.extend_from_slice(mic_block);
+        if monitor_block.len() > samples.len() {
+            samples.resize(monitor_block.len(), 0);
+        }
+        for (i, pair) in monitor_block.chunks_exact(2).enumerate() {
+            let at = i * 2;
+            let sum = i16::from_le_bytes([samples[at], samples[at + 1]]) as i32 + i16::from_le_bytes([pair[0], pair[1]]) as i32;
+            let sample = sum.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+            samples[at..at + 2].copy_from_slice(&sample.to_le_bytes());
+        }
+        mixed.write(&samples)?;
+    }
+    mixed.finish()?;
+    let _ = privacy::remove_file(&monitor_file);
+    debug!("Mixed {} into {}", monitor_file, audio_file);
+    Ok(())
+}
+
+/// Spawn the recorder capturing `source` into `audio_file` (pw-record on Linux, sox on macOS)
+/// Fails with a notification when the recording can't work, rather than leaving an empty or
+/// cut-off file to be found when it stops
+pub fn spawn_recorder(audio_file: &str, source: Source) -> Result<Child> {
+    if let Err(e) = preflight(audio_file, source) {
+        crate::helpers::send_notification("Voice Input", &format!("❌ Can't record: {:#}", e), 5000);
+        return Err(e);
+    }
+    platform::Current::spawn_recorder(audio_file, source)
+}
+
+/// Check the directory `audio_file` goes to has room for it, and that `source` is there and
+/// free to record
+fn preflight(audio_file: &str, source: Source) -> Result<()> {
+    let dir = std::path::Path::new(audio_file).parent().and_then(|dir| dir.to_str()).unwrap_or(".");
+    let c_dir = std::ffi::CString::new(dir)?;
+    // SAFETY: access only reads the NUL-terminated path
+    if unsafe { libc::access(c_dir.as_ptr(), libc::W_OK) } != 0 {
+        return Err(anyhow::anyhow!("{} isn't writable ({})", dir, std::io::Error::last_os_error()));
+    }
+    if let Some((free, _)) = filesystem_space(dir) {
+        if free < MIN_FREE_MB * MB {
+            return Err(anyhow::anyhow!("only {} MB free in {}", free / MB, dir));
+        }
+    }
+    if let Some(problem) = platform::Current::source_problem(source) {
+        return Err(anyhow::anyhow!(problem));
+    }
+    Ok(())
+}
+
+/// Common function to start recording audio
+pub fn start_recording(source: Option<Source>) -> Result<()> {
+    debug!("Starting recording...");
+    
+    // Dictation is paused: the hotkey becomes a no-op
+    if let Some(pause) = crate::pause::active() {
+        debug!("Dictation disabled, not recording: {:?}", pause);
+        crate::helpers::send_notification("Voice Input", &format!("🔇 Dictation disabled {}\nRun `whisp-away enable` to resume", pause.describe()), 2000);
+        return Ok(());
+    }
+    
+    // Clean up old recordings first (older than 10 minutes)
+    cleanup_old_recordings(&crate::paths::runtime_dir(), None);
+    cleanup_old_recordings(&spill_dir(), None);
+    
+    let recording_dir = recording_dir();
+    if recording_dir != crate::paths::runtime_dir() {
+        crate::helpers::send_notification(
+            "Voice Input",
+            &format!("⚠️ Runtime directory almost full\nRecording to {}", recording_dir),
+            4000
+        );
+    }
+    
+    // Generate unique audio file name
+    let audio_file = format!("{}/voice-recording-{}.wav", recording_dir, 
+        SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis());
+    
+    debug!("Audio file will be: {}", audio_file);
+
+    // Kills any leftover recorder, then starts pw-record under the recording lock
+    let source = source_setting(source).value;
+    let pid = with_session(|session| session.start(&audio_file, source))?;
+    debug!("pw-record started with PID: {}", pid);
+    mute::check_for_session(source);
+    crate::hooks::fire(crate::hooks::Event::RecordStart, &[("AUDIO_FILE", &audio_file), ("SOURCE", &source.to_string())]);
+    segments::spawn_rotator(&audio_file, source);
+    notify_recording(source);
+    
+    debug!("Recording started successfully");
+    Ok(())
+}
+
+/// The "Recording..." notification, with the backend and model the recording will go to
+pub fn notify_recording(source: Source) {
+    let model = crate::helpers::resolve_model();
+    let backend = crate::helpers::resolve_backend();
+    let acceleration = crate::helpers::get_acceleration_type();
+    let mut recording_msg = format!("Recording... (release to stop)\nBackend: {} ({}) | Model: {}", backend, acceleration, model);
+    if source != Source::Mic {
+        recording_msg.push_str(&format!("\nSource: {}", source.describe()));
+    }
+    
+    crate::helpers::send_notification("Voice Input", &recording_msg, 30000);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sources() {
+        assert_eq!("mic".parse(), Ok(Source::Mic));
+        assert_eq!("monitor".parse(), Ok(Source::Monitor));
+        assert_eq!("both".parse(), Ok(Source::Both));
+        for source in [Source::Mic, Source::Monitor, Source::Both] {
+            assert_eq!(source.to_string().parse(), Ok(source));
+        }
+        for invalid in ["", "Mic", "speakers", "mic,monitor"] {
+            assert!(invalid.parse::<Source>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    fn write_wav(path: &str, samples: &[i16]) {
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut wav = audio::header(1, 16000, data.len() as u32);
+        wav.extend_from_slice(&data);
+        fs::write(path, wav).unwrap();
+    }
+
+    fn read_samples(path: &str) -> Vec<i16> {
+        let wav = fs::read(path).unwrap();
+        audio::probe_bytes(&wav).unwrap().data(&wav).chunks_exact(2).map(|s| i16::from_le_bytes([s[0], s[1]])).collect()
+    }
+
+    #[test]
+    fn mixes_system_audio_into_the_microphone() {
+        let dir = std::env::temp_dir().join(format!("whisp-away-mix-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mic = dir.join("rec.wav").to_string_lossy().into_owned();
+        write_wav(&mic, &[100, -100, 30000]);
+        // The monitor recorder stopped last, and sums past full scale are clipped
+        write_wav(&monitor_file(&mic), &[1, 1, 10000, 7]);
+
+        mix_monitor(&mic).unwrap();
+        assert_eq!(read_samples(&mic), [101, -99, i16::MAX, 7]);
+        assert!(!std::path::Path::new(&monitor_file(&mic)).exists());
+
+        // Without system audio the recording is left alone
+        mix_monitor(&mic).unwrap();
+        assert_eq!(read_samples(&mic), [101, -99, i16::MAX, 7]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
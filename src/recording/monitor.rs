@@ -0,0 +1,244 @@
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing::debug;
+use crate::keyboard::command_stdout;
+use crate::audio;
+use crate::privacy;
+use super::Source;
+
+/// Set by the signal handler on Ctrl+C
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the peak marker stays at the highest recent level
+const PEAK_HOLD: Duration = Duration::from_millis(1500);
+
+/// How long the clipping warning stays up after the last clipped sample
+const CLIP_WARNING: Duration = Duration::from_secs(2);
+
+const METER_WIDTH: usize = 40;
+
+/// Level at the left end of the meter
+const FLOOR_DB: f32 = -60.0;
+
+/// Samples at full scale (after the `mic_gain` boost) are clipped
+const CLIP_LEVEL: i32 = i16::MAX as i32;
+
+/// Peaks below this are too quiet for reliable transcription
+const QUIET_DB: f32 = -30.0;
+
+/// Level meter for one recorded source, fed by the WAV file pw-record is writing
+struct Meter {
+    label: &'static str,
+    path: String,
+    file: Option<File>,
+    /// Bytes of the file already measured
+    offset: u64,
+    /// Channels and sample rate from the WAV header
+    format: Option<(u16, u32)>,
+    level_db: f32,
+    peak_db: f32,
+    peak_at: Instant,
+    max_db: f32,
+    clipped: u64,
+    clipped_at: Option<Instant>,
+}
+
+impl Meter {
+    fn new(label: &'static str, path: String) -> Self {
+        Meter {
+            label,
+            path,
+            file: None,
+            offset: 0,
+            format: None,
+            level_db: FLOOR_DB,
+            peak_db: FLOOR_DB,
+            peak_at: Instant::now(),
+            max_db: FLOOR_DB,
+            clipped: 0,
+            clipped_at: None,
+        }
+    }
+
+    /// Measure the samples written since the last update
+    fn update(&mut self) -> Result<()> {
+        if self.file.is_none() {
+            // pw-record creates the file once the stream is running
+            self.file = File::open(&self.path).ok();
+        }
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+
+        if self.format.is_none() {
+            // Until pw-record has written the whole header there's nothing to measure
+            let Ok(wav) = audio::probe(&self.path) else {
+                return Ok(());
+            };
+            self.format = Some((wav.channels, wav.sample_rate));
+            self.offset = wav.data_offset;
+        }
+
+        let mut bytes = Vec::new();
+        file.seek(SeekFrom::Start(self.offset))?;
+        file.read_to_end(&mut bytes)?;
+        // A sample split across writes is measured next time
+        let whole = bytes.len() - bytes.len() % 2;
+        if whole == 0 {
+            return Ok(());
+        }
+        self.offset += whole as u64;
+
+        let mut sum_squares = 0f64;
+        let mut peak = 0i32;
+        for sample in bytes[..whole].chunks_exact(2) {
+            let sample = i16::from_le_bytes([sample[0], sample[1]]) as i32;
+            sum_squares += (sample * sample) as f64;
+            peak = peak.max(sample.abs());
+            if sample.abs() >= CLIP_LEVEL {
+                self.clipped += 1;
+                self.clipped_at = Some(Instant::now());
+            }
+        }
+        let rms = (sum_squares / (whole / 2) as f64).sqrt();
+        self.level_db = to_db(rms);
+
+        let peak_db = to_db(peak as f64);
+        if peak_db >= self.peak_db || self.peak_at.elapsed() > PEAK_HOLD {
+            self.peak_db = peak_db;
+            self.peak_at = Instant::now();
+        }
+        self.max_db = self.max_db.max(peak_db);
+        Ok(())
+    }
+
+    /// One line: format, meter bar with the held peak, level and clipping warning
+    fn render(&self) -> String {
+        let position = |db: f32| (((db - FLOOR_DB) / -FLOOR_DB) * METER_WIDTH as f32).round() as usize;
+        let filled = position(self.level_db).min(METER_WIDTH);
+        let peak = position(self.peak_db).min(METER_WIDTH - 1);
+        let bar: String = (0..METER_WIDTH)
+            .map(|i| if i < filled { '#' } else if i == peak { '|' } else { '-' })
+            .collect();
+
+        let format = match self.format {
+            Some((channels, rate)) => format!("{} Hz {}", rate, if channels == 1 { "mono" } else { "stereo" }),
+            None => "waiting...".to_string(),
+        };
+        let warning = match self.clipped_at {
+            Some(at) if at.elapsed() < CLIP_WARNING => "  ⚠️ CLIPPING",
+            _ => "",
+        };
+        format!("{:<7}{:<17}[{}] {:>6.1} dBFS{}", self.label, format, bar, self.level_db, warning)
+    }
+
+    /// What the session showed, with advice when the input needs adjusting
+    fn summary(&self) -> String {
+        let mut summary = format!("{}: peak {:.1} dBFS, {} clipped samples", self.label, self.max_db, self.clipped);
+        if self.clipped > 0 {
+            summary.push_str("\n  ⚠️ The input clips: lower `mic_gain` or its volume (pavucontrol, or `wpctl set-volume @DEFAULT_AUDIO_SOURCE@ 80%`)");
+        } else if self.max_db < QUIET_DB {
+            summary.push_str("\n  ⚠️ Very quiet: move closer to the microphone or raise its volume");
+        }
+        summary
+    }
+}
+
+/// Full-scale-relative level of a 16-bit amplitude
+fn to_db(amplitude: f64) -> f32 {
+    if amplitude <= 0.0 {
+        return FLOOR_DB;
+    }
+    ((20.0 * (amplitude / 32768.0).log10()) as f32).max(FLOOR_DB)
+}
+
+/// Description of a PipeWire node such as @DEFAULT_AUDIO_SOURCE@, via wpctl
+fn device_name(node: &str) -> String {
+    command_stdout("wpctl", &["inspect", node])
+        .and_then(|output| {
+            output.lines().find_map(|line| {
+                let (key, value) = line.trim_start_matches([' ', '*']).split_once(" = ")?;
+                (key == "node.description").then(|| value.trim_matches('"').to_string())
+            })
+        })
+        .unwrap_or_else(|| "default".to_string())
+}
+
+extern "C" fn handle_stop_signal(_signal: libc::c_int) {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Show live input levels for the configured source until Ctrl+C
+/// Records through pw-record with the same settings as dictation, so the meter shows
+/// exactly what the transcriber would get
+pub fn run(source: Option<Source>) -> Result<()> {
+    let source = super::source_setting(source).value;
+    let audio_file = format!("{}/whisp-away-monitor-{}.wav", super::recording_dir(), std::process::id());
+    let mut meters = match source {
+        Source::Mic => vec![Meter::new("mic", audio_file.clone())],
+        Source::Monitor => vec![Meter::new("system", audio_file.clone())],
+        Source::Both => vec![
+            Meter::new("mic", audio_file.clone()),
+            Meter::new("system", super::monitor_file(&audio_file)),
+        ],
+    };
+
+    println!("Monitoring {} (Ctrl+C to stop)", source.describe());
+    if source != Source::Monitor {
+        println!("Input:  {}", device_name("@DEFAULT_AUDIO_SOURCE@"));
+    }
+    if source != Source::Mic {
+        println!("Output: {}", device_name("@DEFAULT_AUDIO_SINK@"));
+    }
+    println!();
+
+    unsafe {
+        libc::signal(libc::SIGTERM, handle_stop_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        libc::signal(libc::SIGINT, handle_stop_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+    let mut recorder = super::spawn_recorder(&audio_file, source)?;
+    debug!("Monitoring with pw-record PID {}", recorder.id());
+
+    let mut stdout = std::io::stdout();
+    let mut drawn = false;
+    let result = loop {
+        if STOP_REQUESTED.load(Ordering::SeqCst) {
+            break Ok(());
+        }
+        if let Some(status) = recorder.try_wait()? {
+            break Err(anyhow!("pw-record exited ({})", status));
+        }
+        if let Err(e) = meters.iter_mut().try_for_each(Meter::update) {
+            break Err(e);
+        }
+
+        // Redraw the meters in place
+        if drawn {
+            write!(stdout, "\x1b[{}A", meters.len())?;
+        }
+        for meter in &meters {
+            writeln!(stdout, "\r\x1b[K{}", meter.render())?;
+        }
+        stdout.flush()?;
+        drawn = true;
+        std::thread::sleep(REFRESH_INTERVAL);
+    };
+
+    unsafe { libc::kill(recorder.id() as i32, libc::SIGINT) };
+    let _ = recorder.wait();
+    for meter in &meters {
+        let _ = privacy::remove_file(&meter.path);
+    }
+
+    result?;
+    println!();
+    for meter in &meters {
+        println!("{}", meter.summary());
+    }
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use std::fs;
+use tracing::{debug, warn};
+use crate::helpers;
+use crate::platform::{self, Platform};
+use crate::settings::{self, Layers, Resolved};
+use super::Source;
+
+/// Resolves whether a muted microphone is unmuted for the recording with priority:
+/// 1. WA_AUTO_UNMUTE env var
+/// 2. User config file `auto_unmute`
+/// 3. Default to false (warn instead)
+pub fn auto_unmute_setting() -> Resolved<bool> {
+    Layers::new("auto unmute")
+        .env("WA_AUTO_UNMUTE", settings::flag)
+        .config(|c| c.auto_unmute)
+        .or_default(false)
+}
+
+/// Marks that `start` unmuted the microphone, so `stop` mutes it again
+fn marker_file() -> String {
+    format!("{}/whisp-away-unmuted", crate::paths::runtime_dir())
+}
+
+/// Mutes the microphone again when dropped
+pub struct Unmuted;
+
+impl Drop for Unmuted {
+    fn drop(&mut self) {
+        match platform::Current::set_mic_muted(true) {
+            Ok(()) => debug!("Muted the microphone again"),
+            Err(e) => warn!("Could not mute the microphone again: {:#}", e),
+        }
+    }
+}
+
+/// When recording `source`: warn when the microphone is muted, or unmute it when configured
+/// The returned guard mutes it again once the recording is over
+pub fn check(source: Source) -> Option<Unmuted> {
+    if source == Source::Monitor {
+        return None;
+    }
+    match platform::Current::mic_muted() {
+        Some(true) => {}
+        Some(false) => return None,
+        None => {
+            debug!("Could not tell whether the microphone is muted");
+            return None;
+        }
+    }
+
+    if !auto_unmute_setting().value {
+        warn!("The microphone is muted");
+        helpers::send_notification(
+            "Voice Input",
+            "🔇 MICROPHONE IS MUTED\nUnmute it, or the recording will be silent",
+            5000
+        );
+        return None;
+    }
+    match platform::Current::set_mic_muted(false) {
+        Ok(()) => {
+            debug!("Unmuted the microphone for the recording");
+            Some(Unmuted)
+        }
+        Err(e) => {
+            warn!("Could not unmute the microphone: {:#}", e);
+            helpers::send_notification("Voice Input", &format!("🔇 MICROPHONE IS MUTED\nUnmuting failed: {:#}", e), 5000);
+            None
+        }
+    }
+}
+
+/// `check` for `start`, leaving the microphone to the `stop` invocation to mute again
+pub fn check_for_session(source: Source) {
+    if let Some(unmuted) = check(source) {
+        std::mem::forget(unmuted);
+        if let Err(e) = fs::write(marker_file(), "") {
+            warn!("Could not remember to mute the microphone after recording: {}", e);
+        }
+    }
+}
+
+/// For `stop`: mute the microphone again when its `start` unmuted it
+pub fn restore_for_session() {
+    if fs::remove_file(marker_file()).is_ok() {
+        drop(Unmuted);
+    }
+}
@@ -0,0 +1,447 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use crate::helpers::{self, is_process_running};
+use crate::settings::{self, Layers, Resolved};
+use crate::{audio, paths, postprocess, privacy, transcribe};
+use super::Source;
+
+/// How often the rotator checks the dictation is still being recorded
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How often the rotator checks the runtime directory still has room for the dictation
+const SPACE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long stopping waits for the rotator to finish the segment it is transcribing
+const FINISH_TIMEOUT_SECS: u64 = 300;
+
+/// Text of the segments closed before the last one of the dictation stopped in this process
+static EARLIER: Mutex<Option<String>> = Mutex::new(None);
+
+/// Resolves the minutes after which a dictation goes on in a new segment file with priority:
+/// 1. WA_SEGMENT_MINS env var
+/// 2. User config file `segment_mins`
+/// 3. Default to none, one file however long the dictation (0 too)
+pub fn minutes_setting() -> Resolved<Option<u64>> {
+    Layers::new("segment minutes")
+        .env("WA_SEGMENT_MINS", settings::parsed)
+        .config(|c| c.segment_mins)
+        .optional()
+}
+
+fn minutes() -> Option<u64> {
+    minutes_setting().value.filter(|minutes| *minutes > 0)
+}
+
+/// A dictation's segments, written by its rotator and read when the dictation stops
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    rotator_pid: u32,
+    /// Closed segments in order
+    closed: Vec<Closed>,
+    /// Set when the rotator exits, as a rotator its parent didn't reap still looks alive
+    finished: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Closed {
+    audio_file: String,
+    /// None until transcribed, and for the segments after one that failed
+    text: Option<String>,
+}
+
+/// The dictation's first file, which its numbered segments are named after: both
+/// "voice-recording-1.wav" and "voice-recording-1-part2.wav" have the stem "voice-recording-1"
+fn stem(audio_file: &str) -> &str {
+    let stem = audio_file.strip_suffix(".wav").unwrap_or(audio_file);
+    match stem.rsplit_once("-part") {
+        Some((first, number)) if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) => first,
+        _ => stem,
+    }
+}
+
+/// Segment `number` of the dictation, in `dir`: segments go to disk once the runtime
+/// directory fills up, while the dictation's first file stays where it was started
+fn segment_file(audio_file: &str, number: u32, dir: &str) -> String {
+    format!("{}/{}-part{}.wav", dir, file_name(stem(audio_file)), number)
+}
+
+/// The state is kept in the runtime directory, wherever the segments are
+fn state_path(audio_file: &str) -> String {
+    format!("{}/{}-segments.json", paths::runtime_dir(), file_name(stem(audio_file)))
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit_once('/').map_or(path, |(_, name)| name)
+}
+
+fn in_runtime_dir(audio_file: &str) -> bool {
+    std::path::Path::new(audio_file).starts_with(paths::runtime_dir())
+}
+
+fn read_state(path: &str) -> Option<State> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+fn write_state(path: &str, state: &State) -> Result<()> {
+    fs::write(path, serde_json::to_string(state)?).context("Failed to write the segment state")
+}
+
+/// Start the process rotating the dictation being recorded into `audio_file` into numbered
+/// segment files: every `segment_mins`, and when the runtime directory (a tmpfs, so memory)
+/// runs short of room for it, into the disk-backed spill directory
+/// Without either the dictation is recorded in one file, as when the rotator can't be started
+pub fn spawn_rotator(audio_file: &str, source: Source) {
+    let minutes = minutes();
+    if minutes.is_none() && !in_runtime_dir(audio_file) {
+        return;
+    }
+    let result = std::env::current_exe()
+        .context("Failed to locate whisp-away executable")
+        .and_then(|exe| {
+            Command::new(exe)
+                .args(["rotate-segments", "--audio-file", audio_file, "--source", &source.to_string()])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                // Carry over what the dictation is transcribed with, which the rotator can't see otherwise
+                .env("WA_WHISPER_BACKEND", helpers::resolve_backend())
+                .env("WA_WHISPER_MODEL", helpers::resolve_model())
+                .env("WA_WHISPER_LANGUAGE", helpers::resolve_language())
+                .env("WA_WHISPER_TASK", helpers::resolve_task())
+                // Own process group so it outlives the hotkey invocation
+                .process_group(0)
+                .spawn()
+                .context("Failed to start the segment rotator")
+        });
+    match result {
+        Ok(child) => match minutes {
+            Some(minutes) => debug!("Segment rotator started (PID: {}), a new segment every {} min", child.id(), minutes),
+            None => debug!("Segment rotator started (PID: {}), moving the dictation to disk if the runtime dir fills up", child.id()),
+        },
+        Err(e) => warn!("{:#}, recording the dictation in one file", e),
+    }
+}
+
+/// `whisp-away rotate-segments` (spawned by `start`): every `segment_mins`, or once the
+/// runtime directory runs short, go on recording the dictation in the next numbered file and
+/// transcribe the one closed meanwhile, until the dictation is stopped
+pub fn run_rotator(audio_file: &str, source: Source) -> Result<()> {
+    let every = minutes().map(|minutes| Duration::from_secs(minutes.saturating_mul(60)));
+    let state_path = state_path(audio_file);
+    let mut state = State { rotator_pid: std::process::id(), ..Default::default() };
+    if privacy::enabled() {
+        // The text would be written to the state file, so the segments are only transcribed
+        // with the last one, on stop
+        debug!("Privacy mode, not transcribing segments while recording");
+    }
+    write_state(&state_path, &state)?;
+    let result = rotate_until_stopped(audio_file, source, every, &state_path, &mut state);
+    state.finished = true;
+    write_state(&state_path, &state)?;
+    result
+}
+
+fn rotate_until_stopped(audio_file: &str, source: Source, every: Option<Duration>, state_path: &str, state: &mut State) -> Result<()> {
+    let mut current = audio_file.to_string();
+    let mut number = 1;
+    let mut opened = Instant::now();
+    let mut space_checked = Instant::now();
+    // After a failure the rest is left to `stop`, to keep the transcript in order
+    let mut transcribing = !privacy::enabled();
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        if super::recording_file().as_deref() != Some(current.as_str()) {
+            debug!("Dictation stopped after {} segments", number);
+            return Ok(());
+        }
+        let due = every.is_some_and(|every| opened.elapsed() >= every);
+        let spill = !due && in_runtime_dir(&current) && space_checked.elapsed() >= SPACE_CHECK_INTERVAL && {
+            space_checked = Instant::now();
+            !in_runtime_dir(&super::recording_dir())
+        };
+        if !due && !spill {
+            continue;
+        }
+
+        let dir = super::recording_dir();
+        if spill {
+            helpers::send_notification("Voice Input", &format!("⚠️ Runtime directory almost full\nGoing on recording to {}", dir), 4000);
+        }
+        let next = segment_file(audio_file, number + 1, &dir);
+        let Some(rotated) = super::rotate(&current, &next, source)? else {
+            return Ok(());
+        };
+        let closed = rotated.audio_file;
+        number += 1;
+        opened = Instant::now();
+        current = next;
+        info!("Recording segment {} of the dictation into {}", number, current);
+
+        if let Err(e) = trim_overlap(&closed, &current, rotated.next_len) {
+            warn!("Failed to trim the audio {} shares with {}: {:#}", closed, current, e);
+        }
+        finish_segment(&closed);
+        state.closed.push(Closed { audio_file: closed.clone(), text: None });
+        write_state(state_path, state)?;
+        if !transcribing {
+            continue;
+        }
+        match transcribe_segment(&closed) {
+            Ok(text) => {
+                if let Some(segment) = state.closed.last_mut() {
+                    segment.text = Some(text);
+                }
+                write_state(state_path, state)?;
+                let _ = privacy::remove_file(&closed);
+            }
+            Err(e) => {
+                warn!("Failed to transcribe {}, leaving it and the segments after it to the stop: {:#}", closed, e);
+                transcribing = false;
+            }
+        }
+    }
+}
+
+/// Cut from the end of `audio_file` the audio the next segment's recorder captured too: all
+/// it had written into `next_audio_file` (`next_len` bytes, header included) when the
+/// recorder of `audio_file` was stopped
+fn trim_overlap(audio_file: &str, next_audio_file: &str, next_len: u64) -> Result<()> {
+    let next = audio::probe(next_audio_file)?;
+    let overlap = next_len.saturating_sub(next.data_offset);
+    if overlap == 0 {
+        return Ok(());
+    }
+    let samples = audio::Samples::open(audio_file)?;
+    let info = samples.info;
+    let frame = info.block_align().max(1);
+    let keep = info.data_len.saturating_sub(overlap) / frame * frame;
+
+    let mut trimmed = audio::WavWriter::create(audio_file, info.channels, info.sample_rate)?;
+    let mut left = keep;
+    samples.for_each_block(|block| {
+        let take = (block.len() as u64).min(left);
+        left -= take;
+        trimmed.write(&block[..take as usize])
+    })?;
+    trimmed.finish()?;
+    debug!("Trimmed {} bytes {} shares with {}", info.data_len - keep, audio_file, next_audio_file);
+    Ok(())
+}
+
+/// Make a closed segment mono with the system audio mixed in, like a stopped dictation
+fn finish_segment(audio_file: &str) {
+    if let Err(e) = super::downmix(audio_file) {
+        warn!("Failed to downmix {}: {:#}", audio_file, e);
+    }
+    if let Err(e) = super::mix_monitor(audio_file) {
+        warn!("Failed to mix system audio into {}: {:#}", audio_file, e);
+    }
+}
+
+/// Transcribe a closed segment along the failover chain, like `stop` would, post-processed
+/// but for the ending, which only the whole transcript has
+fn transcribe_segment(audio_file: &str) -> Result<String> {
+    if super::is_silent(audio_file) {
+        return Ok(String::new());
+    }
+    let text = transcribe::file(audio_file)?;
+    Ok(postprocess::apply_to_segment(&text, None))
+}
+
+/// Once the dictation whose last segment is `audio_file` has stopped: wait for its rotator,
+/// keep the text of the segments it transcribed for `prepend`, and put the audio of those
+/// it didn't in front of the last segment's, so they're transcribed with it
+/// A dictation recorded in one file is left as it is
+pub fn collect(audio_file: &str) -> Result<()> {
+    *EARLIER.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    let state_path = state_path(audio_file);
+    let Some(state) = read_state(&state_path) else {
+        return Ok(());
+    };
+    let state = wait_for_rotator(&state_path, state);
+    let _ = privacy::remove_file(&state_path);
+    if state.closed.is_empty() {
+        return Ok(());
+    }
+
+    let transcribed = state.closed.iter().take_while(|segment| segment.text.is_some()).count();
+    let earlier: Vec<&str> = state.closed[..transcribed].iter()
+        .filter_map(|segment| segment.text.as_deref())
+        .filter(|text| !text.is_empty())
+        .collect();
+    debug!("Dictation recorded in {} segments, {} transcribed while recording", state.closed.len() + 1, transcribed);
+    *EARLIER.lock().unwrap_or_else(|e| e.into_inner()) = Some(earlier.join(" "));
+
+    let untranscribed: Vec<&str> = state.closed[transcribed..].iter()
+        .map(|segment| segment.audio_file.as_str())
+        .collect();
+    if !untranscribed.is_empty() {
+        merge(&untranscribed, audio_file)?;
+    }
+    Ok(())
+}
+
+/// The rotator's last state once it has exited, or after FINISH_TIMEOUT_SECS when it's
+/// still transcribing, which stops it
+fn wait_for_rotator(state_path: &str, mut state: State) -> State {
+    let deadline = Instant::now() + Duration::from_secs(FINISH_TIMEOUT_SECS);
+    while !state.finished && is_process_running(state.rotator_pid) {
+        if Instant::now() > deadline {
+            warn!("Segment rotator (PID {}) didn't finish within {}s, stopping it", state.rotator_pid, FINISH_TIMEOUT_SECS);
+            // SAFETY: kill only sends a signal
+            unsafe { libc::kill(state.rotator_pid as i32, libc::SIGTERM) };
+            break;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+        state = read_state(state_path).unwrap_or(state);
+    }
+    state
+}
+
+/// Put the audio of the `earlier` segments, in order, in front of that of `audio_file`
+/// They were finished like it, so the samples are simply joined, a block at a time
+fn merge(earlier: &[&str], audio_file: &str) -> Result<()> {
+    let last = audio::Samples::open(audio_file)?;
+    let mut merged = audio::WavWriter::create(audio_file, last.info.channels, last.info.sample_rate)?;
+    for segment in earlier {
+        if !std::path::Path::new(segment).exists() {
+            warn!("Segment {} is missing from the dictation", segment);
+            continue;
+        }
+        audio::Samples::open(segment)
+            .with_context(|| format!("Failed to read {}", segment))?
+            .for_each_block(|block| merged.write(block))?;
+    }
+    last.for_each_block(|block| merged.write(block))?;
+    merged.finish()?;
+    for segment in earlier {
+        let _ = privacy::remove_file(segment);
+    }
+    debug!("Put {} untranscribed segments in front of {}", earlier.len(), audio_file);
+    Ok(())
+}
+
+/// Whether the dictation stopped in this process was recorded in segments, whose text goes
+/// in front of its transcript
+pub fn pending() -> bool {
+    EARLIER.lock().unwrap_or_else(|e| e.into_inner()).is_some()
+}
+
+/// The whole dictation's transcript: the text of its earlier segments, then `text`, that of
+/// the last one
+pub fn prepend(text: &str) -> String {
+    match EARLIER.lock().unwrap_or_else(|e| e.into_inner()).as_deref() {
+        Some(earlier) if !earlier.is_empty() && !text.is_empty() => format!("{} {}", earlier, text),
+        Some(earlier) if !earlier.is_empty() => earlier.to_string(),
+        _ => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_share_the_first_file_stem() {
+        assert_eq!(stem("/run/voice-recording-1712.wav"), "/run/voice-recording-1712");
+        assert_eq!(stem("/run/voice-recording-1712-part12.wav"), "/run/voice-recording-1712");
+        assert_eq!(segment_file("/run/voice-recording-1712-part2.wav", 3, "/run"), "/run/voice-recording-1712-part3.wav");
+        // Spilled to disk halfway through
+        assert_eq!(segment_file("/run/voice-recording-1712.wav", 2, "/cache"), "/cache/voice-recording-1712-part2.wav");
+        assert_eq!(
+            state_path("/cache/voice-recording-1712-part3.wav"),
+            format!("{}/voice-recording-1712-segments.json", paths::runtime_dir())
+        );
+        assert_eq!(stem("/run/meeting-party.wav"), "/run/meeting-party");
+    }
+
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("whisp-away-segments-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_wav(path: &str, samples: &[u8]) {
+        let mut wav = audio::header(1, 16000, samples.len() as u32);
+        wav.extend_from_slice(samples);
+        fs::write(path, wav).unwrap();
+    }
+
+    fn samples(path: &str) -> Vec<u8> {
+        let wav = fs::read(path).unwrap();
+        audio::probe_bytes(&wav).unwrap().data(&wav).to_vec()
+    }
+
+    #[test]
+    fn merges_segments_in_front_of_the_last() {
+        let dir = scratch_dir("merge");
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        write_wav(&path("rec-part2.wav"), &[1, 1, 2, 2]);
+        write_wav(&path("rec-part3.wav"), &[3, 3]);
+        write_wav(&path("rec-part4.wav"), &[4, 4]);
+
+        // A missing segment is skipped rather than failing the dictation
+        merge(&[&path("rec-part2.wav"), &path("gone.wav"), &path("rec-part3.wav")], &path("rec-part4.wav")).unwrap();
+        assert_eq!(samples(&path("rec-part4.wav")), [1, 1, 2, 2, 3, 3, 4, 4]);
+        assert!(!dir.join("rec-part2.wav").exists() && !dir.join("rec-part3.wav").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn trims_the_audio_both_recorders_captured() {
+        let dir = scratch_dir("trim");
+        let (closed, next) = (dir.join("rec.wav"), dir.join("rec-part2.wav"));
+        let (closed, next) = (closed.to_str().unwrap(), next.to_str().unwrap());
+        write_wav(closed, &[1, 1, 2, 2, 3, 3]);
+        // The next recorder had written one frame when the first was stopped, and went on
+        write_wav(next, &[3, 3, 4, 4]);
+        trim_overlap(closed, next, audio::CANONICAL_HEADER_LEN as u64 + 2).unwrap();
+        assert_eq!(samples(closed), [1, 1, 2, 2]);
+
+        // Nothing written yet, nothing trimmed
+        trim_overlap(closed, next, audio::CANONICAL_HEADER_LEN as u64).unwrap();
+        assert_eq!(samples(closed), [1, 1, 2, 2]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn collects_text_and_untranscribed_audio() {
+        let dir = scratch_dir("collect");
+        // The state goes to the runtime directory, under the name of the dictation
+        let stem = format!("whisp-away-test-{}", std::process::id());
+        let path = |part: &str| dir.join(format!("{}{}.wav", stem, part)).to_string_lossy().into_owned();
+        fs::create_dir_all(paths::runtime_dir()).unwrap();
+        let last = path("-part4");
+        write_wav(&path("-part3"), &[3, 3]);
+        write_wav(&path("-part4"), &[4, 4]);
+        // The first two were transcribed while recording (the second was silent) and the
+        // third failed, so its audio goes in front of the last segment's
+        let state = State {
+            rotator_pid: u32::MAX,
+            closed: vec![
+                Closed { audio_file: path(""), text: Some("first".to_string()) },
+                Closed { audio_file: path("-part2"), text: Some(String::new()) },
+                Closed { audio_file: path("-part3"), text: None },
+            ],
+            finished: true,
+        };
+        write_state(&state_path(&last), &state).unwrap();
+
+        collect(&last).unwrap();
+        assert!(pending());
+        assert_eq!(prepend("last"), "first last");
+        assert_eq!(samples(&last), [3, 3, 4, 4]);
+        assert!(!std::path::Path::new(&state_path(&last)).exists());
+
+        // A dictation recorded in one file has nothing to collect
+        collect(&path("-other")).unwrap();
+        assert!(!pending());
+        assert_eq!(prepend("last"), "last");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,956 @@
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, error, info, warn};
+use crate::helpers::is_process_running;
+use super::Source;
+
+/// Filesystem operations the recording session depends on
+pub trait FileSystem {
+    /// Guard that holds the recording lock until dropped
+    type Lock;
+
+    fn read_to_string(&self, path: &str) -> io::Result<String>;
+    fn write(&self, path: &str, contents: &str) -> io::Result<()>;
+    fn remove_file(&self, path: &str) -> io::Result<()>;
+    fn exists(&self, path: &str) -> bool;
+    /// Size of the file in bytes, 0 when it doesn't exist
+    fn len(&self, path: &str) -> u64;
+    fn copy(&self, from: &str, to: &str) -> io::Result<()>;
+    /// Try to take the exclusive lock; Ok(None) when another process holds it
+    fn try_lock(&self, path: &str) -> io::Result<Option<Self::Lock>>;
+    /// Take the exclusive lock, waiting while another process holds it
+    fn lock(&self, path: &str) -> io::Result<Self::Lock>;
+}
+
+/// Process operations the recording session depends on
+pub trait Processes {
+    fn is_running(&self, pid: u32) -> bool;
+    fn signal(&self, pid: u32, signal: Signal);
+    /// Spawn the audio recorder capturing `source` into `audio_file`, returning its PID
+    fn spawn_recorder(&self, audio_file: &str, source: Source) -> Result<u32>;
+    fn sleep(&self, duration: Duration);
+}
+
+/// How long rotating waits for the next recorder to write its first audio
+const RECORDER_START_TIMEOUT: Duration = Duration::from_secs(2);
+const RECORDER_START_POLL: Duration = Duration::from_millis(10);
+
+/// A segment closed by `rotate`
+#[derive(Debug, PartialEq, Eq)]
+pub struct Rotated {
+    pub audio_file: String,
+    /// Size of the next segment's file when this one's recorder was stopped: both recorders
+    /// captured the audio in it, so it is at the end of this segment too
+    pub next_len: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt,
+    Terminate,
+    Kill,
+}
+
+impl Signal {
+    fn as_kill_arg(self) -> &'static str {
+        match self {
+            Signal::Interrupt => "-INT",
+            Signal::Terminate => "-TERM",
+            Signal::Kill => "-KILL",
+        }
+    }
+}
+
+/// What the pidfile and lock file say about the current recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordingState {
+    /// No recorder and no lock held
+    Idle,
+    /// Recorder process is alive
+    Recording { pid: u32 },
+    /// Another process holds the lock while it starts a recording
+    Starting,
+    /// Pidfile exists but is empty, unparsable, or names a dead process
+    Stale,
+}
+
+/// Files shared between the start and stop invocations
+#[derive(Debug, Clone)]
+pub struct SessionPaths {
+    pub pid_file: String,
+    pub lock_file: String,
+    /// Serializes start, stop and toggle invocations
+    pub command_lock_file: String,
+    /// Holds the path of the WAV being recorded
+    pub audio_path_file: String,
+    /// Where override audio files are copied for cleanup
+    pub runtime_dir: String,
+}
+
+/// Recording lifecycle over pidfile, lock file and recorder process
+/// Every start/stop/kill decision goes through here so it can be tested without real processes
+pub struct RecordingSession<F: FileSystem, P: Processes> {
+    fs: F,
+    processes: P,
+    paths: SessionPaths,
+    /// Command lock held by this session, see `lock_commands`
+    command_lock: RefCell<Option<F::Lock>>,
+}
+
+impl<F: FileSystem, P: Processes> RecordingSession<F, P> {
+    pub fn new(fs: F, processes: P, paths: SessionPaths) -> Self {
+        Self { fs, processes, paths, command_lock: RefCell::new(None) }
+    }
+
+    /// Wait for other start/stop/toggle invocations, then hold them off until
+    /// `unlock_commands` or until the recorder has been started or stopped
+    /// Without this, two rapid toggles can both see "not recording" and both start
+    /// Re-entrant: a toggle keeps the lock through the start or stop it decides on
+    pub fn lock_commands(&self) -> Result<()> {
+        if self.command_lock.borrow().is_some() {
+            return Ok(());
+        }
+        let lock = self.fs.lock(&self.paths.command_lock_file)
+            .context("Failed to acquire command lock")?;
+        debug!("Acquired command lock");
+        *self.command_lock.borrow_mut() = Some(lock);
+        Ok(())
+    }
+
+    pub fn unlock_commands(&self) {
+        if self.command_lock.borrow_mut().take().is_some() {
+            debug!("Released command lock");
+        }
+    }
+
+    /// Run one command phase under the command lock, releasing it afterwards
+    fn serialized<T>(&self, phase: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.lock_commands()?;
+        let result = phase();
+        self.unlock_commands();
+        result
+    }
+
+    fn read_pid(&self) -> Option<u32> {
+        self.fs.read_to_string(&self.paths.pid_file).ok()?.trim().parse().ok()
+    }
+
+    pub fn state(&self) -> RecordingState {
+        if let Some(pid) = self.read_pid() {
+            if self.processes.is_running(pid) {
+                info!("Recording in progress (PID: {})", pid);
+                return RecordingState::Recording { pid };
+            }
+            debug!("PID {} in pidfile is not running", pid);
+        }
+
+        if self.fs.exists(&self.paths.lock_file) {
+            match self.fs.try_lock(&self.paths.lock_file) {
+                Ok(None) => {
+                    info!("Recording lock is held by another process");
+                    return RecordingState::Starting;
+                }
+                // We got the lock, it is released when dropped
+                Ok(Some(_lock)) => {}
+                Err(e) => debug!("Couldn't check lock file: {}", e),
+            }
+        }
+
+        if self.fs.exists(&self.paths.pid_file) {
+            RecordingState::Stale
+        } else {
+            RecordingState::Idle
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.state(), RecordingState::Recording { .. } | RecordingState::Starting)
+    }
+
+    /// Stop a process, escalating SIGINT -> SIGTERM -> SIGKILL, waiting `grace` between steps
+    /// Returns whether the process is gone afterwards
+    fn terminate(&self, pid: u32, grace: Duration) -> bool {
+        self.processes.signal(pid, Signal::Interrupt);
+        self.processes.sleep(grace);
+
+        if self.processes.is_running(pid) {
+            debug!("Process still running after SIGINT, sending SIGTERM");
+            self.processes.signal(pid, Signal::Terminate);
+            self.processes.sleep(grace);
+        }
+
+        if self.processes.is_running(pid) {
+            warn!("Process still running after SIGTERM, sending SIGKILL");
+            self.processes.signal(pid, Signal::Kill);
+            self.processes.sleep(Duration::from_millis(50));
+        }
+
+        !self.processes.is_running(pid)
+    }
+
+    /// Kill any existing recording process forcefully
+    pub fn kill_existing(&self) -> Result<()> {
+        if let Some(pid) = self.read_pid() {
+            if self.processes.is_running(pid) {
+                debug!("Killing existing recording process (PID: {})", pid);
+                if !self.terminate(pid, Duration::from_millis(100)) {
+                    error!("Failed to kill recording process (PID: {})", pid);
+                    return Err(anyhow::anyhow!("Failed to kill existing recording process"));
+                }
+                debug!("Successfully killed existing recording process");
+            }
+        }
+        let _ = self.fs.remove_file(&self.paths.pid_file);
+        Ok(())
+    }
+
+    /// Start recording `source` into `audio_file`, returning the recorder PID
+    pub fn start(&self, audio_file: &str, source: Source) -> Result<u32> {
+        self.serialized(|| self.start_locked(audio_file, source))
+    }
+
+    fn start_locked(&self, audio_file: &str, source: Source) -> Result<u32> {
+        // Kill any existing recording process FIRST
+        self.kill_existing()?;
+
+        // The lock only guards the start phase; it is released when this function returns
+        let _lock = match self.fs.try_lock(&self.paths.lock_file).context("Failed to acquire lock")? {
+            Some(lock) => lock,
+            None => {
+                warn!("Another recording is already in progress (lock held)");
+                return Err(anyhow::anyhow!("Another recording is already in progress"));
+            }
+        };
+        debug!("Successfully acquired recording lock");
+
+        // Store the audio file path for later retrieval
+        self.fs.write(&self.paths.audio_path_file, audio_file)
+            .context("Failed to write audio file path")?;
+
+        let pid = self.processes.spawn_recorder(audio_file, source)?;
+        self.fs.write(&self.paths.pid_file, &pid.to_string())
+            .context("Failed to write PID file")?;
+        debug!("Wrote PID {} to {}", pid, self.paths.pid_file);
+
+        Ok(pid)
+    }
+
+    /// Stop the recorder and return the audio file to transcribe
+    pub fn stop(&self, audio_file_override: Option<&str>) -> Result<Option<String>> {
+        self.serialized(|| self.stop_locked(audio_file_override))
+    }
+
+    /// Run `stop` if recording, otherwise `start`, deciding under the command lock
+    /// They're expected to call `start`/`stop` on this session, which reuse the lock and
+    /// release it once the recorder is running or stopped (transcription runs unlocked)
+    /// Overlapping toggles therefore alternate instead of both starting or both stopping
+    pub fn toggle<T>(&self, start: impl FnOnce() -> Result<T>, stop: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.serialized(|| if self.is_recording() { stop() } else { start() })
+    }
+
+    fn stop_locked(&self, audio_file_override: Option<&str>) -> Result<Option<String>> {
+        // Wait a bit for the pidfile to appear if it doesn't exist yet
+        let mut attempts = 0;
+        while !self.fs.exists(&self.paths.pid_file) && attempts < 10 {
+            debug!("Waiting for pidfile (attempt {})", attempts + 1);
+            self.processes.sleep(Duration::from_millis(20));
+            attempts += 1;
+        }
+
+        if let Ok(pid_str) = self.fs.read_to_string(&self.paths.pid_file) {
+            let pid_str = pid_str.trim();
+            if pid_str.is_empty() {
+                debug!("Pidfile is empty");
+                let _ = self.fs.remove_file(&self.paths.pid_file);
+                return Ok(None);
+            }
+
+            if let Ok(pid) = pid_str.parse::<u32>() {
+                if !self.processes.is_running(pid) {
+                    debug!("Recording process {} is not running", pid);
+                    let _ = self.fs.remove_file(&self.paths.pid_file);
+                    let _ = self.fs.remove_file(&self.paths.audio_path_file);
+                    return Ok(None);
+                }
+
+                // Let the recorder flush its last buffer before asking it to stop
+                self.processes.sleep(Duration::from_millis(100));
+                debug!("Sending SIGINT to recording process (PID: {})", pid);
+                self.terminate(pid, Duration::from_millis(50));
+                debug!("Recording stopped");
+            }
+        } else {
+            debug!("No pidfile found at {}", self.paths.pid_file);
+        }
+
+        let _ = self.fs.remove_file(&self.paths.pid_file);
+
+        // Release any lock that might be held
+        if self.fs.exists(&self.paths.lock_file) {
+            let _ = self.fs.remove_file(&self.paths.lock_file);
+            debug!("Removed stale lock file");
+        }
+
+        if let Some(override_path) = audio_file_override {
+            debug!("Using override audio file: {}", override_path);
+            // Copy the override file to a temporary location so it can be cleaned up
+            let temp_audio = format!("{}/voice-recording-override-{}.wav", self.paths.runtime_dir,
+                SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis());
+            self.fs.copy(override_path, &temp_audio)
+                .context("Failed to copy audio file to temporary location")?;
+            debug!("Copied override audio to: {}", temp_audio);
+            return Ok(Some(temp_audio));
+        }
+
+        match self.fs.read_to_string(&self.paths.audio_path_file) {
+            Ok(path) => {
+                let path = path.trim().to_string();
+                let _ = self.fs.remove_file(&self.paths.audio_path_file);
+                if !self.fs.exists(&path) {
+                    warn!("Audio file does not exist: {}", path);
+                }
+                Ok(Some(path))
+            }
+            Err(e) => {
+                debug!("Could not read audio file path: {}", e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// The WAV being recorded, if any
+    pub fn audio_file(&self) -> Option<String> {
+        let path = self.fs.read_to_string(&self.paths.audio_path_file).ok()?;
+        Some(path.trim().to_string()).filter(|path| !path.is_empty())
+    }
+
+    /// Go on recording `source` into `next_audio_file` and stop the recorder writing
+    /// `audio_file`, returning it closed; the next recorder starts first so no audio falls
+    /// between the two
+    /// None when `audio_file` isn't being recorded anymore (the recording was stopped)
+    pub fn rotate(&self, audio_file: &str, next_audio_file: &str, source: Source) -> Result<Option<Rotated>> {
+        self.serialized(|| self.rotate_locked(audio_file, next_audio_file, source))
+    }
+
+    fn rotate_locked(&self, audio_file: &str, next_audio_file: &str, source: Source) -> Result<Option<Rotated>> {
+        let Some(pid) = self.read_pid().filter(|pid| self.processes.is_running(*pid)) else {
+            return Ok(None);
+        };
+        if self.audio_file().as_deref() != Some(audio_file) {
+            return Ok(None);
+        }
+
+        let next_pid = self.processes.spawn_recorder(next_audio_file, source)?;
+        self.fs.write(&self.paths.audio_path_file, next_audio_file)
+            .context("Failed to write audio file path")?;
+        self.fs.write(&self.paths.pid_file, &next_pid.to_string())
+            .context("Failed to write PID file")?;
+        debug!("Recording into {} (PID {}), stopping PID {}", next_audio_file, next_pid, pid);
+        // Recorders write in blocks, so what is still buffered is left doubled rather than
+        // any audio lost
+        self.wait_for_audio(next_audio_file, next_pid);
+        let next_len = self.fs.len(next_audio_file);
+        self.terminate(pid, Duration::from_millis(50));
+        Ok(Some(Rotated { audio_file: audio_file.to_string(), next_len }))
+    }
+
+    /// Wait (up to RECORDER_START_TIMEOUT) for the recorder `pid` to write its first audio
+    /// into `audio_file`, so stopping the one before it leaves no gap
+    fn wait_for_audio(&self, audio_file: &str, pid: u32) {
+        let polls = RECORDER_START_TIMEOUT.as_millis() / RECORDER_START_POLL.as_millis();
+        for _ in 0..polls {
+            if self.fs.len(audio_file) > crate::audio::CANONICAL_HEADER_LEN as u64 {
+                return;
+            }
+            if !self.processes.is_running(pid) {
+                break;
+            }
+            self.processes.sleep(RECORDER_START_POLL);
+        }
+        debug!("No audio from the recorder writing {} yet", audio_file);
+    }
+}
+
+/// Real filesystem with flock-based locking
+pub struct SystemFs;
+
+impl FileSystem for SystemFs {
+    type Lock = File;
+
+    fn read_to_string(&self, path: &str) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn write(&self, path: &str, contents: &str) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn remove_file(&self, path: &str) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        std::path::Path::new(path).exists()
+    }
+
+    fn len(&self, path: &str) -> u64 {
+        fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0)
+    }
+
+    fn copy(&self, from: &str, to: &str) -> io::Result<()> {
+        fs::copy(from, to).map(|_| ())
+    }
+
+    fn try_lock(&self, path: &str) -> io::Result<Option<File>> {
+        let lock_file = open_lock_file(path)?;
+
+        // Try to acquire exclusive lock (non-blocking)
+        let result = unsafe { libc::flock(lock_file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+        if result != 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::WouldBlock {
+                return Ok(None);
+            }
+            return Err(err);
+        }
+        Ok(Some(lock_file))
+    }
+
+    fn lock(&self, path: &str) -> io::Result<File> {
+        let lock_file = open_lock_file(path)?;
+        loop {
+            if unsafe { libc::flock(lock_file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+                return Ok(lock_file);
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+fn open_lock_file(path: &str) -> io::Result<File> {
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+}
+
+/// Real processes controlled through kill(1) and pw-record
+pub struct SystemProcesses;
+
+impl Processes for SystemProcesses {
+    fn is_running(&self, pid: u32) -> bool {
+        is_process_running(pid)
+    }
+
+    fn signal(&self, pid: u32, signal: Signal) {
+        let _ = Command::new("kill")
+            .args([signal.as_kill_arg(), &pid.to_string()])
+            .status();
+    }
+
+    fn spawn_recorder(&self, audio_file: &str, source: Source) -> Result<u32> {
+        let mut child = super::spawn_recorder(audio_file, source)?;
+        let pid = child.id();
+        // A process that lives on (the segment rotator) reaps it once stopped, or it'd be left
+        // a zombie that still looks like it's recording
+        std::thread::spawn(move || child.wait());
+        Ok(pid)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::collections::{HashMap, HashSet};
+    use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct FakeFs {
+        files: RefCell<HashMap<String, String>>,
+        lock_held_elsewhere: Cell<bool>,
+    }
+
+    impl FakeFs {
+        fn with(self, path: &str, contents: &str) -> Self {
+            self.files.borrow_mut().insert(path.to_string(), contents.to_string());
+            self
+        }
+
+        fn has(&self, path: &str) -> bool {
+            self.files.borrow().contains_key(path)
+        }
+
+        fn get(&self, path: &str) -> Option<String> {
+            self.files.borrow().get(path).cloned()
+        }
+    }
+
+    impl FileSystem for &FakeFs {
+        type Lock = ();
+
+        fn read_to_string(&self, path: &str) -> io::Result<String> {
+            self.get(path).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        }
+
+        fn write(&self, path: &str, contents: &str) -> io::Result<()> {
+            self.files.borrow_mut().insert(path.to_string(), contents.to_string());
+            Ok(())
+        }
+
+        fn remove_file(&self, path: &str) -> io::Result<()> {
+            self.files.borrow_mut().remove(path)
+                .map(|_| ())
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        }
+
+        fn exists(&self, path: &str) -> bool {
+            self.has(path)
+        }
+
+        fn len(&self, path: &str) -> u64 {
+            self.get(path).map_or(0, |contents| contents.len() as u64)
+        }
+
+        fn copy(&self, from: &str, to: &str) -> io::Result<()> {
+            let contents = self.read_to_string(from)?;
+            self.write(to, &contents)
+        }
+
+        fn try_lock(&self, path: &str) -> io::Result<Option<()>> {
+            self.files.borrow_mut().entry(path.to_string()).or_default();
+            Ok(if self.lock_held_elsewhere.get() { None } else { Some(()) })
+        }
+
+        fn lock(&self, path: &str) -> io::Result<()> {
+            self.files.borrow_mut().entry(path.to_string()).or_default();
+            Ok(())
+        }
+    }
+
+    /// Processes that exit on the first signal at or above `dies_on`
+    #[derive(Default)]
+    struct FakeProcesses {
+        running: RefCell<HashSet<u32>>,
+        dies_on: RefCell<HashMap<u32, Signal>>,
+        signals: RefCell<Vec<(u32, Signal)>>,
+        next_pid: Cell<u32>,
+    }
+
+    impl FakeProcesses {
+        fn with_running(self, pid: u32, dies_on: Option<Signal>) -> Self {
+            self.running.borrow_mut().insert(pid);
+            if let Some(signal) = dies_on {
+                self.dies_on.borrow_mut().insert(pid, signal);
+            }
+            self
+        }
+
+        fn signals_to(&self, pid: u32) -> Vec<Signal> {
+            self.signals.borrow().iter().filter(|(p, _)| *p == pid).map(|(_, s)| *s).collect()
+        }
+    }
+
+    fn rank(signal: Signal) -> u8 {
+        match signal {
+            Signal::Interrupt => 0,
+            Signal::Terminate => 1,
+            Signal::Kill => 2,
+        }
+    }
+
+    impl Processes for &FakeProcesses {
+        fn is_running(&self, pid: u32) -> bool {
+            self.running.borrow().contains(&pid)
+        }
+
+        fn signal(&self, pid: u32, signal: Signal) {
+            self.signals.borrow_mut().push((pid, signal));
+            let dies = self.dies_on.borrow().get(&pid).map(|d| rank(signal) >= rank(*d)).unwrap_or(false);
+            if dies {
+                self.running.borrow_mut().remove(&pid);
+            }
+        }
+
+        fn spawn_recorder(&self, _audio_file: &str, _source: Source) -> Result<u32> {
+            let pid = self.next_pid.get().max(1000) + 1;
+            self.next_pid.set(pid);
+            self.running.borrow_mut().insert(pid);
+            self.dies_on.borrow_mut().insert(pid, Signal::Interrupt);
+            Ok(pid)
+        }
+
+        fn sleep(&self, _duration: Duration) {}
+    }
+
+    fn paths() -> SessionPaths {
+        SessionPaths {
+            pid_file: "/run/test.pid".to_string(),
+            lock_file: "/run/test.lock".to_string(),
+            command_lock_file: "/run/test-command.lock".to_string(),
+            audio_path_file: "/run/audio-path.tmp".to_string(),
+            runtime_dir: "/run".to_string(),
+        }
+    }
+
+    fn session<'a>(fs: &'a FakeFs, processes: &'a FakeProcesses) -> RecordingSession<&'a FakeFs, &'a FakeProcesses> {
+        RecordingSession::new(fs, processes, paths())
+    }
+
+    #[test]
+    fn idle_without_any_files() {
+        let (fs, processes) = (FakeFs::default(), FakeProcesses::default());
+        assert_eq!(session(&fs, &processes).state(), RecordingState::Idle);
+        assert!(!session(&fs, &processes).is_recording());
+    }
+
+    #[test]
+    fn live_pid_is_recording() {
+        let fs = FakeFs::default().with("/run/test.pid", "42\n");
+        let processes = FakeProcesses::default().with_running(42, Some(Signal::Interrupt));
+        assert_eq!(session(&fs, &processes).state(), RecordingState::Recording { pid: 42 });
+        assert!(session(&fs, &processes).is_recording());
+    }
+
+    #[test]
+    fn dead_pid_is_stale_not_recording() {
+        let fs = FakeFs::default().with("/run/test.pid", "42");
+        let processes = FakeProcesses::default();
+        assert_eq!(session(&fs, &processes).state(), RecordingState::Stale);
+        assert!(!session(&fs, &processes).is_recording());
+    }
+
+    #[test]
+    fn empty_and_garbage_pidfiles_are_stale() {
+        for contents in ["", "   \n", "not-a-pid", "-1"] {
+            let fs = FakeFs::default().with("/run/test.pid", contents);
+            let processes = FakeProcesses::default();
+            assert_eq!(session(&fs, &processes).state(), RecordingState::Stale, "pidfile {:?}", contents);
+        }
+    }
+
+    #[test]
+    fn lock_held_elsewhere_counts_as_recording() {
+        let fs = FakeFs::default().with("/run/test.lock", "");
+        fs.lock_held_elsewhere.set(true);
+        let processes = FakeProcesses::default();
+        assert_eq!(session(&fs, &processes).state(), RecordingState::Starting);
+        assert!(session(&fs, &processes).is_recording());
+    }
+
+    #[test]
+    fn start_fails_while_lock_is_held() {
+        let fs = FakeFs::default();
+        fs.lock_held_elsewhere.set(true);
+        let processes = FakeProcesses::default();
+        let err = session(&fs, &processes).start("/run/a.wav", Source::Mic).unwrap_err();
+        assert!(err.to_string().contains("already in progress"));
+        assert!(!fs.has("/run/test.pid"));
+    }
+
+    #[test]
+    fn start_writes_pid_and_audio_path() {
+        let (fs, processes) = (FakeFs::default(), FakeProcesses::default());
+        let pid = session(&fs, &processes).start("/run/a.wav", Source::Mic).unwrap();
+        assert_eq!(fs.get("/run/test.pid"), Some(pid.to_string()));
+        assert_eq!(fs.get("/run/audio-path.tmp"), Some("/run/a.wav".to_string()));
+        assert!(session(&fs, &processes).is_recording());
+    }
+
+    #[test]
+    fn start_replaces_stale_pidfile() {
+        let fs = FakeFs::default().with("/run/test.pid", "42");
+        let processes = FakeProcesses::default();
+        let pid = session(&fs, &processes).start("/run/a.wav", Source::Mic).unwrap();
+        assert_ne!(pid, 42);
+        assert!(processes.signals_to(42).is_empty());
+        assert_eq!(fs.get("/run/test.pid"), Some(pid.to_string()));
+    }
+
+    #[test]
+    fn start_kills_running_recorder_with_escalation() {
+        let fs = FakeFs::default().with("/run/test.pid", "42");
+        let processes = FakeProcesses::default().with_running(42, Some(Signal::Kill));
+        session(&fs, &processes).start("/run/a.wav", Source::Mic).unwrap();
+        assert_eq!(processes.signals_to(42), vec![Signal::Interrupt, Signal::Terminate, Signal::Kill]);
+    }
+
+    #[test]
+    fn start_fails_when_recorder_cannot_be_killed() {
+        let fs = FakeFs::default().with("/run/test.pid", "42");
+        let processes = FakeProcesses::default().with_running(42, None);
+        assert!(session(&fs, &processes).start("/run/a.wav", Source::Mic).is_err());
+        assert!(processes.running.borrow().contains(&42));
+    }
+
+    #[test]
+    fn stop_interrupts_recorder_and_returns_audio() {
+        let fs = FakeFs::default()
+            .with("/run/test.pid", "42")
+            .with("/run/test.lock", "")
+            .with("/run/audio-path.tmp", "/run/a.wav\n")
+            .with("/run/a.wav", "RIFF");
+        let processes = FakeProcesses::default().with_running(42, Some(Signal::Interrupt));
+
+        let audio = session(&fs, &processes).stop(None).unwrap();
+
+        assert_eq!(audio, Some("/run/a.wav".to_string()));
+        assert_eq!(processes.signals_to(42), vec![Signal::Interrupt]);
+        assert!(!fs.has("/run/test.pid"));
+        assert!(!fs.has("/run/test.lock"));
+        assert!(!fs.has("/run/audio-path.tmp"));
+    }
+
+    #[test]
+    fn stop_escalates_to_terminate() {
+        let fs = FakeFs::default()
+            .with("/run/test.pid", "42")
+            .with("/run/audio-path.tmp", "/run/a.wav");
+        let processes = FakeProcesses::default().with_running(42, Some(Signal::Terminate));
+        session(&fs, &processes).stop(None).unwrap();
+        assert_eq!(processes.signals_to(42), vec![Signal::Interrupt, Signal::Terminate]);
+    }
+
+    #[test]
+    fn stop_with_empty_pidfile_returns_nothing() {
+        let fs = FakeFs::default()
+            .with("/run/test.pid", "")
+            .with("/run/audio-path.tmp", "/run/a.wav");
+        let processes = FakeProcesses::default();
+        assert_eq!(session(&fs, &processes).stop(None).unwrap(), None);
+        assert!(!fs.has("/run/test.pid"));
+    }
+
+    #[test]
+    fn stop_with_dead_recorder_discards_audio_path() {
+        let fs = FakeFs::default()
+            .with("/run/test.pid", "42")
+            .with("/run/audio-path.tmp", "/run/a.wav");
+        let processes = FakeProcesses::default();
+        assert_eq!(session(&fs, &processes).stop(None).unwrap(), None);
+        assert!(!fs.has("/run/test.pid"));
+        assert!(!fs.has("/run/audio-path.tmp"));
+    }
+
+    #[test]
+    fn stop_without_any_recording_returns_nothing() {
+        let (fs, processes) = (FakeFs::default(), FakeProcesses::default());
+        assert_eq!(session(&fs, &processes).stop(None).unwrap(), None);
+    }
+
+    #[test]
+    fn stop_with_override_copies_into_runtime_dir() {
+        let fs = FakeFs::default().with("/home/me/memo.wav", "RIFF-data");
+        let processes = FakeProcesses::default();
+
+        let audio = session(&fs, &processes).stop(Some("/home/me/memo.wav")).unwrap().unwrap();
+
+        assert!(audio.starts_with("/run/voice-recording-override-"));
+        assert_eq!(fs.get(&audio), Some("RIFF-data".to_string()));
+        assert!(fs.has("/home/me/memo.wav"));
+    }
+
+    #[test]
+    fn stop_with_missing_override_fails() {
+        let (fs, processes) = (FakeFs::default(), FakeProcesses::default());
+        assert!(session(&fs, &processes).stop(Some("/nope.wav")).is_err());
+    }
+
+    #[test]
+    fn start_then_stop_round_trip() {
+        let (fs, processes) = (FakeFs::default(), FakeProcesses::default());
+        let pid = session(&fs, &processes).start("/run/a.wav", Source::Mic).unwrap();
+        assert_eq!(session(&fs, &processes).stop(None).unwrap(), Some("/run/a.wav".to_string()));
+        assert!(!processes.running.borrow().contains(&pid));
+        assert_eq!(session(&fs, &processes).state(), RecordingState::Idle);
+    }
+
+    #[test]
+    fn rotate_hands_over_to_the_next_recorder() {
+        let (fs, processes) = (FakeFs::default(), FakeProcesses::default());
+        let session = session(&fs, &processes);
+        let first = session.start("/run/a.wav", Source::Mic).unwrap();
+        let rotated = session.rotate("/run/a.wav", "/run/a-part2.wav", Source::Mic).unwrap();
+        assert_eq!(rotated, Some(Rotated { audio_file: "/run/a.wav".to_string(), next_len: 0 }));
+        assert_eq!(processes.signals_to(first), vec![Signal::Interrupt]);
+        assert!(session.is_recording());
+        assert_eq!(session.audio_file().as_deref(), Some("/run/a-part2.wav"));
+
+        // A stale rotation (the recording it was for is gone) leaves the recording alone
+        assert_eq!(session.rotate("/run/a.wav", "/run/a-part3.wav", Source::Mic).unwrap(), None);
+        assert_eq!(session.stop(None).unwrap(), Some("/run/a-part2.wav".to_string()));
+        assert_eq!(session.rotate("/run/a-part2.wav", "/run/a-part3.wav", Source::Mic).unwrap(), None);
+    }
+
+    #[test]
+    fn rotate_reports_the_audio_both_recorders_captured() {
+        let fs = FakeFs::default().with("/run/a-part2.wav", &"x".repeat(1044));
+        let processes = FakeProcesses::default();
+        let session = session(&fs, &processes);
+        session.start("/run/a.wav", Source::Mic).unwrap();
+        let rotated = session.rotate("/run/a.wav", "/run/a-part2.wav", Source::Mic).unwrap();
+        assert_eq!(rotated, Some(Rotated { audio_file: "/run/a.wav".to_string(), next_len: 1044 }));
+    }
+
+    #[test]
+    fn toggle_starts_then_stops() {
+        let (fs, processes) = (FakeFs::default(), FakeProcesses::default());
+        let session = session(&fs, &processes);
+        let toggle = || session.toggle(
+            || session.start("/run/a.wav", Source::Mic).map(|_| "started"),
+            || session.stop(None).map(|_| "stopped"),
+        );
+        assert_eq!(toggle().unwrap(), "started");
+        assert!(session.is_recording());
+        assert_eq!(toggle().unwrap(), "stopped");
+        assert_eq!(session.state(), RecordingState::Idle);
+    }
+
+    #[test]
+    fn toggle_releases_command_lock_when_start_fails() {
+        let (fs, processes) = (FakeFs::default(), FakeProcesses::default());
+        let session = session(&fs, &processes);
+        let result: Result<()> = session.toggle(|| Err(anyhow::anyhow!("no recorder")), || Ok(()));
+        assert!(result.is_err());
+        assert!(session.command_lock.borrow().is_none());
+    }
+
+    /// Recorders shared between threads, each thread acting as a separate invocation
+    #[derive(Default)]
+    struct SharedProcesses {
+        running: Mutex<HashSet<u32>>,
+        next_pid: AtomicU32,
+        /// Set when a recorder was spawned while another one was still running
+        overlapped: AtomicBool,
+    }
+
+    impl Processes for &SharedProcesses {
+        fn is_running(&self, pid: u32) -> bool {
+            self.running.lock().unwrap().contains(&pid)
+        }
+
+        fn signal(&self, pid: u32, _signal: Signal) {
+            self.running.lock().unwrap().remove(&pid);
+        }
+
+        fn spawn_recorder(&self, _audio_file: &str, _source: Source) -> Result<u32> {
+            let mut running = self.running.lock().unwrap();
+            if !running.is_empty() {
+                self.overlapped.store(true, Ordering::SeqCst);
+            }
+            let pid = 1000 + self.next_pid.fetch_add(1, Ordering::SeqCst);
+            running.insert(pid);
+            Ok(pid)
+        }
+
+        // Give other invocations a chance to interleave wherever a real one would wait
+        fn sleep(&self, _duration: Duration) {
+            std::thread::yield_now();
+        }
+    }
+
+    /// Session files in a fresh temporary directory, locked with real flock
+    fn temp_paths(name: &str) -> SessionPaths {
+        let dir = std::env::temp_dir().join(format!("whisp-away-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_string_lossy().to_string();
+        SessionPaths {
+            pid_file: format!("{}/recording.pid", dir),
+            lock_file: format!("{}/recording.lock", dir),
+            command_lock_file: format!("{}/command.lock", dir),
+            audio_path_file: format!("{}/audio-path", dir),
+            runtime_dir: dir,
+        }
+    }
+
+    #[test]
+    fn rapid_concurrent_toggles_alternate() {
+        const INVOCATIONS: usize = 8;
+        const PRESSES: usize = 25;
+        let paths = temp_paths("toggles");
+        let processes = SharedProcesses::default();
+        let started = AtomicUsize::new(0);
+        let stopped_with_audio = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for invocation in 0..INVOCATIONS {
+                let (paths, processes, started, stopped) = (&paths, &processes, &started, &stopped_with_audio);
+                scope.spawn(move || {
+                    // Its own lock file handle, like a separate process
+                    let session = RecordingSession::new(SystemFs, processes, paths.clone());
+                    for press in 0..PRESSES {
+                        let audio_file = format!("{}/{}-{}.wav", paths.runtime_dir, invocation, press);
+                        session.toggle(
+                            || session.start(&audio_file, Source::Mic).map(|_| {
+                                started.fetch_add(1, Ordering::SeqCst);
+                            }),
+                            || session.stop(None).map(|audio| {
+                                if audio.is_some() {
+                                    stopped.fetch_add(1, Ordering::SeqCst);
+                                }
+                            }),
+                        ).unwrap();
+                    }
+                });
+            }
+        });
+
+        // An even number of presses: every start was matched by a stop that got its audio
+        let presses = INVOCATIONS * PRESSES;
+        assert_eq!(started.load(Ordering::SeqCst), presses / 2);
+        assert_eq!(stopped_with_audio.load(Ordering::SeqCst), presses / 2);
+        assert!(!processes.overlapped.load(Ordering::SeqCst));
+        let session = RecordingSession::new(SystemFs, &processes, paths.clone());
+        assert_eq!(session.state(), RecordingState::Idle);
+        let _ = fs::remove_dir_all(&paths.runtime_dir);
+    }
+
+    #[test]
+    fn toggle_waits_for_a_start_in_progress() {
+        let paths = temp_paths("waits");
+        let processes = SharedProcesses::default();
+        let starting = RecordingSession::new(SystemFs, &processes, paths.clone());
+        starting.lock_commands().unwrap();
+
+        let toggled = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| {
+                let session = RecordingSession::new(SystemFs, &processes, paths.clone());
+                let result = session.toggle(
+                    || Ok("started"),
+                    || session.stop(None).map(|_| "stopped"),
+                );
+                toggled.store(true, Ordering::SeqCst);
+                result.unwrap()
+            });
+
+            std::thread::sleep(Duration::from_millis(100));
+            assert!(!toggled.load(Ordering::SeqCst), "toggle ran while the command lock was held");
+            // Starting releases the lock once the recorder runs, and the waiting toggle stops it
+            let audio_file = format!("{}/a.wav", paths.runtime_dir);
+            starting.start(&audio_file, Source::Mic).unwrap();
+            assert_eq!(waiter.join().unwrap(), "stopped");
+        });
+        let _ = fs::remove_dir_all(&paths.runtime_dir);
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::{Context, Result};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use crate::clipboard;
+use crate::backends;
+use crate::commands;
+use crate::grammar;
+use crate::postprocess;
+use crate::privacy;
+use crate::recording;
+use crate::spoken;
+use crate::helpers;
+use crate::models;
+use crate::settings::{Layers, Resolved};
+use crate::socket;
+use crate::typing::{self, Output};
+
+/// Backspacing over the draft is only attempted this soon after it was typed;
+/// later the user has probably moved on and the clipboard is used instead
+const REPLACE_WINDOW: Duration = Duration::from_secs(30);
+
+/// How a refined transcript replaces the draft, from WA_REFINE_STRATEGY:
+/// "clipboard" (default) copies it, "replace" backspaces over the draft and retypes it
+pub fn strategy_setting() -> Resolved<String> {
+    Layers::new("refine strategy")
+        .env("WA_REFINE_STRATEGY", Some)
+        .or_default("clipboard".to_string())
+}
+
+/// Hand the audio of a typed draft to a background worker that re-transcribes it with
+/// `final_model`. Returns true when the worker took ownership of (and will delete) the audio.
+pub fn hand_off(audio_file: &str, draft: Option<&str>, final_model: Option<&str>, backend: &str, output: &Output) -> bool {
+    let (Some(draft), Some(final_model)) = (draft, final_model) else {
+        return false;
+    };
+    if draft.trim().is_empty() || final_model == helpers::resolve_model() {
+        debug!("Skipping refinement (empty draft or same model)");
+        return false;
+    }
+    if recording::segments::pending() {
+        debug!("Skipping refinement (only the last segment of the dictation's audio is left)");
+        return false;
+    }
+
+    match spawn_worker(audio_file, draft, final_model, backend, *output == Output::Type) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Failed to start refinement: {}", e);
+            false
+        }
+    }
+}
+
+fn spawn_worker(audio_file: &str, draft: &str, final_model: &str, backend: &str, typed: bool) -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to locate whisp-away executable")?;
+    let mut command = Command::new(exe);
+    command
+        .args(["refine", "--audio-file", audio_file, "--model", final_model, "--backend", backend, "--draft", draft])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        // Carry over --language/--task/--grammar/--spell, which the worker can't see otherwise
+        .env("WA_WHISPER_LANGUAGE", helpers::resolve_language())
+        .env("WA_WHISPER_TASK", helpers::resolve_task())
+        .env("WA_GRAMMAR", grammar::mode_setting().value.to_string())
+        .env("WA_SPELL", spoken::spell_setting().value.to_string())
+        // Own process group so the refinement outlives the hotkey invocation
+        .process_group(0);
+    if typed {
+        command.arg("--typed");
+    }
+    let child = command.spawn().context("Failed to start refinement worker")?;
+    debug!("Refinement worker started (PID: {}) with model {}", child.id(), final_model);
+    Ok(())
+}
+
+/// Worker: transcribe with the final model, then fix up the draft
+/// Strategy comes from WA_REFINE_STRATEGY:
+/// - "clipboard" (default): copy the refined text and notify
+/// - "replace": backspace over a typed draft and retype it, when still within the replace window
+pub fn run(audio_file: &str, draft: &str, model: &str, backend: &str, typed: bool) -> Result<()> {
+    let started = Instant::now();
+    info!("Refining draft with {} ({})", model, backend);
+
+    let compute_type = helpers::resolve_compute_type(None);
+    let batch_size = helpers::resolve_batch_size(None);
+    
+    // The worker may run from another directory than the daemon, so model paths are made absolute
+    let model = models::resolve_for(backend, model)?;
+    let model = model.as_str();
+
+    // The daemon loads the final model next to the draft model and keeps it resident
+    let request = socket::TranscriptionRequest {
+        model: Some(model.to_string()),
+        language: Some(helpers::resolve_language()),
+        task: Some(helpers::resolve_task()),
+        compute_type: compute_type.clone(),
+        batch_size,
+        label: Some(format!("refine ({})", model)),
+        ..socket::TranscriptionRequest::new(audio_file)
+    };
+    let result = match socket::transcribe_to_string(&helpers::resolve_socket_path(), &request) {
+        Ok(text) => Ok(text),
+        Err(e) if e.is::<socket::JobCancelled>() => Err(e),
+        Err(e) => {
+            debug!("Daemon unavailable for refinement ({}), transcribing directly", e);
+            match backend {
+                #[cfg(feature = "whisper-cpp")]
+                "whisper-cpp" => crate::whisper_cpp::direct::transcribe_audio(audio_file, model),
+                #[cfg(feature = "faster-whisper")]
+                "faster-whisper" => crate::faster_whisper::direct::transcribe_audio(
+                    audio_file,
+                    model,
+                    compute_type.as_deref(),
+                    batch_size,
+                ),
+                backend => Err(backends::unavailable(backend)),
+            }
+        }
+    };
+    let _ = privacy::remove_file(audio_file);
+    // The draft was post-processed the same way, so only real differences remain
+    let refined = postprocess::apply(&result?, None);
+
+    // Typing normalizes whitespace, so compare (and count) what was actually typed
+    let draft = draft.split_whitespace().collect::<Vec<_>>().join(" ");
+    let refined = refined.split_whitespace().collect::<Vec<_>>().join(" ");
+    // A "scratch that" the draft started with was acted on already and not typed
+    let (draft, refined) = if commands::enabled_setting().value {
+        (commands::leading_scratches(&draft).1.to_string(), commands::leading_scratches(&refined).1.to_string())
+    } else {
+        (draft, refined)
+    };
+    if refined.is_empty() || refined == draft {
+        debug!("Refined transcript matches the draft");
+        return Ok(());
+    }
+    debug!("Refined transcript in {:?}: '{}' -> '{}'", started.elapsed(), privacy::redact(&draft), privacy::redact(&refined));
+
+    let strategy = strategy_setting().value;
+    if typed && typing::screen_locked() {
+        // The draft is in history; editing it would type into the lock screen
+        debug!("Screen is locked, leaving the draft as it is");
+        return Ok(());
+    }
+    if typed && strategy == "replace" && started.elapsed() < REPLACE_WINDOW {
+        typing::retype(&draft, &refined)?;
+        commands::forget_last_typed();
+        commands::remember_typed(&refined);
+        typing::notify_done("✅ Transcribed", &refined, &format!("{} (refined)", model));
+        return Ok(());
+    }
+
+    clipboard::copy(&refined)?;
+    let preview: String = refined.chars().take(80).collect();
+    helpers::send_notification(
+        "Voice Input",
+        &format!("✨ Refined transcript copied to clipboard\n{}", preview),
+        5000
+    );
+    Ok(())
+}
@@ -0,0 +1,555 @@
+use anyhow::{anyhow, Context, Result};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+#[cfg(feature = "whisper-cpp")]
+use std::net::TcpListener;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+#[cfg(feature = "whisper-cpp")]
+use std::time::Instant;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+#[cfg(feature = "whisper-cpp")]
+use rustls::{ServerConfig, ServerConnection};
+use tracing::{debug, warn};
+#[cfg(feature = "whisper-cpp")]
+use tracing::info;
+use crate::settings::{Layers, Resolved};
+
+/// The daemon's error for a remote request without the right token
+pub const TOKEN_REFUSED: &str = "Invalid daemon token";
+
+/// How long connecting to a remote daemon may take before the client falls back
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a remote client has for the TLS handshake and its whole request, which can be
+/// a long recording sent inline
+#[cfg(feature = "whisper-cpp")]
+pub const REQUEST_DEADLINE: Duration = Duration::from_secs(300);
+
+/// The most a remote client may send before its token is checked: about 100 minutes of
+/// 16 kHz audio inline (base64 adds a third), with room for the rest of the request
+#[cfg(feature = "whisper-cpp")]
+pub const MAX_REQUEST_BYTES: usize = 256 * 1024 * 1024;
+
+/// Certificate bundles of the common distributions, for verifying a daemon's certificate
+/// when `daemon_tls_ca` isn't set
+const SYSTEM_CA_BUNDLES: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/cert.pem",
+];
+
+/// Trusted certificates for remote daemons, read once
+static CLIENT_CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+
+/// A daemon's address on the network: `host:port` or `tcp://host:port` for plain TCP,
+/// `tls://host:port` for TLS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub host_port: String,
+    pub tls: bool,
+}
+
+impl Address {
+    /// The host without the port, and without the brackets of an IPv6 address
+    fn host(&self) -> &str {
+        let host = self.host_port.rsplit_once(':').map_or(self.host_port.as_str(), |(host, _)| host);
+        host.trim_start_matches('[').trim_end_matches(']')
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (host_port, tls) = match s.split_once("://") {
+            Some(("tcp", rest)) => (rest, false),
+            Some(("tls", rest)) => (rest, true),
+            Some((scheme, _)) => return Err(format!("unknown scheme '{}' in '{}' (expected tcp:// or tls://)", scheme, s)),
+            None => (s, false),
+        };
+        match host_port.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(Address { host_port: host_port.to_string(), tls }),
+            _ => Err(format!("invalid address '{}' (expected host:port)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}", if self.tls { "tls" } else { "tcp" }, self.host_port)
+    }
+}
+
+/// The pre-shared token remote clients send with each request; shown and logged as
+/// "(set)" rather than the token itself
+#[derive(Clone, PartialEq, Eq)]
+pub struct Token(pub String);
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(set)")
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(set)")
+    }
+}
+
+impl Token {
+    /// Whether `sent` is this token, compared in constant time
+    #[cfg(any(feature = "whisper-cpp", test))]
+    pub fn matches(&self, sent: Option<&str>) -> bool {
+        let (expected, sent) = (self.0.as_bytes(), sent.unwrap_or_default().as_bytes());
+        expected.len() == sent.len() && expected.iter().zip(sent).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+fn parse_address(var: &'static str) -> impl FnOnce(String) -> Option<Address> {
+    move |value: String| match value.parse::<Address>() {
+        Ok(address) => Some(address),
+        Err(e) => {
+            warn!("Ignoring {}: {}", var, e);
+            None
+        }
+    }
+}
+
+/// Resolves the remote daemon clients talk to instead of the local socket with priority:
+/// 1. WA_DAEMON_ADDRESS env var
+/// 2. User config file `daemon_address`
+/// 3. Default to none, the Unix socket
+pub fn address_setting() -> Resolved<Option<Address>> {
+    Layers::new("daemon address")
+        .env("WA_DAEMON_ADDRESS", parse_address("WA_DAEMON_ADDRESS"))
+        .config(|c| c.daemon_address.and_then(|value| value.parse().ok()))
+        .optional()
+}
+
+/// Resolves where the daemon also takes requests from other machines with priority:
+/// 1. WA_DAEMON_LISTEN env var
+/// 2. User config file `daemon_listen`
+/// 3. Default to none, only the Unix socket
+pub fn listen_setting() -> Resolved<Option<Address>> {
+    Layers::new("daemon listen address")
+        .env("WA_DAEMON_LISTEN", parse_address("WA_DAEMON_LISTEN"))
+        .config(|c| c.daemon_listen.and_then(|value| value.parse().ok()))
+        .optional()
+}
+
+/// Resolves the token remote connections carry with priority:
+/// 1. WA_DAEMON_TOKEN env var
+/// 2. User config file `daemon_token`
+/// 3. Default to none (a daemon won't listen on the network without one)
+pub fn token_setting() -> Resolved<Option<Token>> {
+    Layers::new("daemon token")
+        .env("WA_DAEMON_TOKEN", |value| (!value.trim().is_empty()).then_some(Token(value)))
+        .config(|c| c.daemon_token.filter(|value| !value.trim().is_empty()).map(Token))
+        .optional()
+}
+
+/// Resolves the daemon's TLS certificate chain (PEM) with priority:
+/// 1. WA_DAEMON_TLS_CERT env var
+/// 2. User config file `daemon_tls_cert`
+/// 3. Default to none, required for `daemon_listen = "tls://..."`
+pub fn tls_cert_setting() -> Resolved<Option<String>> {
+    Layers::new("daemon TLS certificate")
+        .env("WA_DAEMON_TLS_CERT", Some)
+        .config(|c| c.daemon_tls_cert)
+        .optional()
+}
+
+/// Resolves the private key (PEM) of the daemon's TLS certificate with priority:
+/// 1. WA_DAEMON_TLS_KEY env var
+/// 2. User config file `daemon_tls_key`
+/// 3. Default to none, required for `daemon_listen = "tls://..."`
+pub fn tls_key_setting() -> Resolved<Option<String>> {
+    Layers::new("daemon TLS key")
+        .env("WA_DAEMON_TLS_KEY", Some)
+        .config(|c| c.daemon_tls_key)
+        .optional()
+}
+
+/// Resolves the certificates (PEM) a client trusts for a `tls://` daemon with priority:
+/// 1. WA_DAEMON_TLS_CA env var
+/// 2. User config file `daemon_tls_ca`
+/// 3. Default to none, the system's certificate bundle
+pub fn tls_ca_setting() -> Resolved<Option<String>> {
+    Layers::new("daemon TLS CA")
+        .env("WA_DAEMON_TLS_CA", Some)
+        .config(|c| c.daemon_tls_ca)
+        .optional()
+}
+
+/// A connection to the daemon, or the daemon's end of one
+pub struct Connection(Inner);
+
+enum Inner {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+    /// Shared, so the daemon can stream segments from another thread as it does with a
+    /// cloned socket
+    Tls(Arc<Mutex<Tls>>),
+}
+
+enum Tls {
+    Client(StreamOwned<ClientConnection, TcpStream>),
+    #[cfg(feature = "whisper-cpp")]
+    Server(StreamOwned<ServerConnection, TcpStream>),
+}
+
+impl Tls {
+    fn socket(&self) -> &TcpStream {
+        match self {
+            Tls::Client(stream) => &stream.sock,
+            #[cfg(feature = "whisper-cpp")]
+            Tls::Server(stream) => &stream.sock,
+        }
+    }
+}
+
+impl Drop for Tls {
+    /// Tell the other end the data is complete, so a response isn't taken for a cut-off one
+    fn drop(&mut self) {
+        let _ = match self {
+            Tls::Client(stream) => {
+                stream.conn.send_close_notify();
+                stream.flush()
+            }
+            #[cfg(feature = "whisper-cpp")]
+            Tls::Server(stream) => {
+                stream.conn.send_close_notify();
+                stream.flush()
+            }
+        };
+    }
+}
+
+impl Connection {
+    /// Connect to the daemon's Unix socket
+    pub fn unix(socket_path: &str) -> io::Result<Self> {
+        UnixStream::connect(socket_path).map(|stream| Connection(Inner::Unix(stream)))
+    }
+
+    /// The daemon's end of a Unix socket connection
+    #[cfg(feature = "whisper-cpp")]
+    pub fn accepted(stream: UnixStream) -> Self {
+        Connection(Inner::Unix(stream))
+    }
+
+    /// Connect to a remote daemon, finishing the TLS handshake for a `tls://` address so a
+    /// certificate problem shows up here rather than as a failed request
+    pub fn remote(address: &Address) -> Result<Self> {
+        let addrs: Vec<SocketAddr> = address.host_port.to_socket_addrs()
+            .with_context(|| format!("Failed to resolve {}", address.host_port))?
+            .collect();
+        let mut last_error = None;
+        let tcp = addrs.iter()
+            .find_map(|addr| TcpStream::connect_timeout(addr, CONNECT_TIMEOUT).map_err(|e| last_error = Some(e)).ok())
+            .ok_or_else(|| match last_error {
+                Some(e) => anyhow!("Failed to connect to {}: {}", address, e),
+                None => anyhow!("{} resolves to no address", address.host_port),
+            })?;
+        let _ = tcp.set_nodelay(true);
+        if !address.tls {
+            return Ok(Connection(Inner::Tcp(tcp)));
+        }
+        let name = ServerName::try_from(address.host().to_string())
+            .map_err(|e| anyhow!("Invalid TLS server name '{}': {}", address.host(), e))?;
+        let conn = ClientConnection::new(client_config()?, name)?;
+        let mut stream = StreamOwned::new(conn, tcp);
+        while stream.conn.is_handshaking() {
+            stream.conn.complete_io(&mut stream.sock)
+                .with_context(|| format!("TLS handshake with {} failed", address))?;
+        }
+        Ok(Connection(Inner::Tls(Arc::new(Mutex::new(Tls::Client(stream))))))
+    }
+
+    /// The Unix socket under this connection, for passing file descriptors
+    pub fn as_unix(&self) -> Option<&UnixStream> {
+        match &self.0 {
+            Inner::Unix(stream) => Some(stream),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a network connection, which carries the token and can't see our files
+    pub fn is_remote(&self) -> bool {
+        !matches!(self.0, Inner::Unix(_))
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match &self.0 {
+            Inner::Unix(stream) => stream.set_read_timeout(timeout),
+            Inner::Tcp(stream) => stream.set_read_timeout(timeout),
+            Inner::Tls(tls) => tls.lock().unwrap_or_else(|e| e.into_inner()).socket().set_read_timeout(timeout),
+        }
+    }
+
+    /// Signal the end of a request; TLS connections stay open both ways, since daemons
+    /// stop reading once the request's JSON is complete
+    pub fn shutdown_write(&self) {
+        let _ = match &self.0 {
+            Inner::Unix(stream) => stream.shutdown(Shutdown::Write),
+            Inner::Tcp(stream) => stream.shutdown(Shutdown::Write),
+            Inner::Tls(_) => Ok(()),
+        };
+    }
+
+    #[cfg(feature = "whisper-cpp")]
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Connection(match &self.0 {
+            Inner::Unix(stream) => Inner::Unix(stream.try_clone()?),
+            Inner::Tcp(stream) => Inner::Tcp(stream.try_clone()?),
+            Inner::Tls(tls) => Inner::Tls(Arc::clone(tls)),
+        }))
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = match &mut self.0 {
+            Inner::Unix(stream) => stream.read(buf),
+            Inner::Tcp(stream) => stream.read(buf),
+            Inner::Tls(tls) => match &mut *tls.lock().unwrap_or_else(|e| e.into_inner()) {
+                Tls::Client(stream) => stream.read(buf),
+                #[cfg(feature = "whisper-cpp")]
+                Tls::Server(stream) => stream.read(buf),
+            },
+        };
+        match result {
+            // The Python daemon closes without a close_notify; a cut-off response doesn't
+            // parse, so this is the end of the data either way
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && self.is_remote() => Ok(0),
+            result => result,
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Inner::Unix(stream) => stream.write(buf),
+            Inner::Tcp(stream) => stream.write(buf),
+            Inner::Tls(tls) => match &mut *tls.lock().unwrap_or_else(|e| e.into_inner()) {
+                Tls::Client(stream) => stream.write(buf),
+                #[cfg(feature = "whisper-cpp")]
+                Tls::Server(stream) => stream.write(buf),
+            },
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Inner::Unix(stream) => stream.flush(),
+            Inner::Tcp(stream) => stream.flush(),
+            Inner::Tls(tls) => match &mut *tls.lock().unwrap_or_else(|e| e.into_inner()) {
+                Tls::Client(stream) => stream.flush(),
+                #[cfg(feature = "whisper-cpp")]
+                Tls::Server(stream) => stream.flush(),
+            },
+        }
+    }
+}
+
+/// Whether `response` is a daemon refusing our token; the error says so, rather than the
+/// failed response passing for an older daemon's
+pub fn check_refused(response: &str) -> Result<()> {
+    if !response.contains(TOKEN_REFUSED) {
+        return Ok(());
+    }
+    match serde_json::from_str::<serde_json::Value>(response) {
+        Ok(value) if value.get("error").and_then(|e| e.as_str()) == Some(TOKEN_REFUSED) => {
+            Err(anyhow!("The daemon refused the request: daemon_token is missing or wrong"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// `payload`, a JSON object, with the token for a remote daemon as its first field
+pub fn with_token(payload: &str, token: &Token) -> String {
+    let token = serde_json::to_string(&token.0).unwrap_or_default();
+    match payload.trim_start().strip_prefix('{') {
+        Some(rest) if rest.trim_start().starts_with('}') => format!("{{\"token\":{}{}", token, rest),
+        Some(rest) => format!("{{\"token\":{},{}", token, rest),
+        None => payload.to_string(),
+    }
+}
+
+/// Certificates from a PEM file
+fn read_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to read certificates from {}", path))
+}
+
+/// What a client trusts: `daemon_tls_ca`, or the system's certificate bundle
+fn client_config() -> Result<Arc<ClientConfig>> {
+    if let Some(config) = CLIENT_CONFIG.get() {
+        return Ok(Arc::clone(config));
+    }
+    let bundle = match tls_ca_setting().value {
+        Some(ca) => ca,
+        None => std::env::var("SSL_CERT_FILE").ok()
+            .or_else(|| SYSTEM_CA_BUNDLES.iter().find(|path| Path::new(path).exists()).map(|path| path.to_string()))
+            .ok_or_else(|| anyhow!("No certificate bundle found; set daemon_tls_ca to the daemon's certificate"))?,
+    };
+    let mut roots = RootCertStore::empty();
+    let (added, ignored) = roots.add_parsable_certificates(read_certs(&bundle)?);
+    if added == 0 {
+        return Err(anyhow!("No usable certificates in {}", bundle));
+    }
+    debug!("Trusting {} certificates from {} ({} unusable)", added, bundle, ignored);
+    let config = Arc::new(ClientConfig::builder().with_root_certificates(roots).with_no_client_auth());
+    Ok(Arc::clone(CLIENT_CONFIG.get_or_init(|| config)))
+}
+
+/// The daemon's listener for clients on other machines (`daemon_listen`)
+#[cfg(feature = "whisper-cpp")]
+pub struct Listener {
+    listener: TcpListener,
+    tls: Option<Arc<ServerConfig>>,
+    pub token: Token,
+}
+
+#[cfg(feature = "whisper-cpp")]
+impl Listener {
+    /// Listen on `daemon_listen`, if set; every request must carry `daemon_token`, so
+    /// listening without one is an error, as is a `tls://` address without a certificate
+    pub fn bind() -> Result<Option<Self>> {
+        let Some(address) = listen_setting().value else {
+            return Ok(None);
+        };
+        let token = token_setting().value
+            .ok_or_else(|| anyhow!("daemon_listen needs daemon_token, which remote clients must send"))?;
+        let tls = if address.tls { Some(Arc::new(server_config()?)) } else { None };
+        let listener = TcpListener::bind(&address.host_port)
+            .with_context(|| format!("Failed to listen on {}", address))?;
+        if !address.tls {
+            warn!("Listening on {} without TLS: requests and transcripts travel unencrypted", address);
+        }
+        info!("Daemon listening for remote clients on {}", address);
+        Ok(Some(Self { listener, tls, token }))
+    }
+
+    pub fn incoming(&self) -> impl Iterator<Item = io::Result<TcpStream>> + '_ {
+        self.listener.incoming()
+    }
+
+    /// A remote client's connection, after a TLS handshake finished by `deadline`
+    pub fn open(&self, tcp: TcpStream, deadline: Instant) -> Result<Connection> {
+        let _ = tcp.set_nodelay(true);
+        let Some(tls) = &self.tls else {
+            return Ok(Connection(Inner::Tcp(tcp)));
+        };
+        let mut stream = StreamOwned::new(ServerConnection::new(Arc::clone(tls))?, tcp);
+        while stream.conn.is_handshaking() {
+            stream.sock.set_read_timeout(Some(time_left(deadline)?))?;
+            stream.conn.complete_io(&mut stream.sock).context("TLS handshake failed")?;
+        }
+        Ok(Connection(Inner::Tls(Arc::new(Mutex::new(Tls::Server(stream))))))
+    }
+}
+
+/// Time left for the next read from a remote client, or an error once `deadline` has passed
+#[cfg(feature = "whisper-cpp")]
+pub fn time_left(deadline: Instant) -> io::Result<Duration> {
+    deadline.checked_duration_since(Instant::now())
+        .filter(|left| !left.is_zero())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "the client took too long to send its request"))
+}
+
+/// Environment for the faster-whisper daemon script: where it listens for remote clients,
+/// the token they send and its certificate; empty when it only takes the socket
+/// Checked like `Listener::bind` checks them, though the script loads the files itself
+#[cfg(feature = "faster-whisper")]
+pub fn script_env() -> Result<Vec<(&'static str, String)>> {
+    let Some(address) = listen_setting().value else {
+        return Ok(Vec::new());
+    };
+    let token = token_setting().value
+        .ok_or_else(|| anyhow!("daemon_listen needs daemon_token, which remote clients must send"))?;
+    let mut env = vec![("WA_DAEMON_LISTEN", address.to_string()), ("WA_DAEMON_TOKEN", token.0)];
+    if address.tls {
+        let (cert, key) = tls_files()?;
+        env.extend([("WA_DAEMON_TLS_CERT", cert), ("WA_DAEMON_TLS_KEY", key)]);
+    }
+    Ok(env)
+}
+
+/// The daemon's certificate chain and key files, both required for a `tls://` listener
+#[cfg(any(feature = "whisper-cpp", feature = "faster-whisper"))]
+fn tls_files() -> Result<(String, String)> {
+    match (tls_cert_setting().value, tls_key_setting().value) {
+        (Some(cert), Some(key)) => Ok((cert, key)),
+        _ => Err(anyhow!("A tls:// daemon_listen needs daemon_tls_cert and daemon_tls_key")),
+    }
+}
+
+/// The daemon's certificate and key (`daemon_tls_cert`, `daemon_tls_key`)
+#[cfg(feature = "whisper-cpp")]
+fn server_config() -> Result<ServerConfig> {
+    let (cert, key) = tls_files()?;
+    let certs = read_certs(&cert)?;
+    let file = File::open(&key).with_context(|| format!("Failed to open {}", key))?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to read {}", key))?
+        .ok_or_else(|| anyhow!("No private key in {}", key))?;
+    ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Invalid daemon TLS certificate or key")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_addresses() {
+        let address: Address = "gpu-box:7700".parse().unwrap();
+        assert_eq!(address, Address { host_port: "gpu-box:7700".to_string(), tls: false });
+        assert_eq!(address.to_string(), "tcp://gpu-box:7700");
+        let address: Address = "tls://[::1]:7700".parse().unwrap();
+        assert!(address.tls);
+        assert_eq!(address.host(), "::1");
+        assert!("gpu-box".parse::<Address>().is_err());
+        assert!("gpu-box:port".parse::<Address>().is_err());
+        assert!("http://gpu-box:7700".parse::<Address>().is_err());
+    }
+
+    #[test]
+    fn adds_the_token() {
+        let token = Token("s3\"cret".to_string());
+        assert_eq!(with_token(r#"{"command":"status"}"#, &token), r#"{"token":"s3\"cret","command":"status"}"#);
+        assert_eq!(with_token("{}", &token), r#"{"token":"s3\"cret"}"#);
+    }
+
+    #[test]
+    fn compares_tokens() {
+        let token = Token("secret".to_string());
+        assert!(token.matches(Some("secret")));
+        assert!(!token.matches(Some("secreT")));
+        assert!(!token.matches(Some("secret2")));
+        assert!(!token.matches(None));
+    }
+
+    #[cfg(feature = "whisper-cpp")]
+    #[test]
+    fn reads_end_at_the_deadline() {
+        let left = time_left(Instant::now() + Duration::from_secs(5)).unwrap();
+        assert!(left > Duration::from_secs(4) && left <= Duration::from_secs(5), "{:?}", left);
+        assert_eq!(time_left(Instant::now()).unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert_eq!(time_left(Instant::now() - Duration::from_secs(1)).unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+use crate::models;
+use crate::settings::{self, Layers, Resolved};
+use crate::socket::{self, TranscriptionRequest, TranscriptionResponse};
+
+/// Resolves the confidence below which a dictation is transcribed again with priority:
+/// 1. WA_RETRANSCRIBE_THRESHOLD env var (0.0-1.0)
+/// 2. User config file `retranscribe.threshold`
+/// 3. Default to 0.6
+pub fn threshold_setting() -> Resolved<f64> {
+    Layers::new("retranscribe threshold")
+        .env("WA_RETRANSCRIBE_THRESHOLD", settings::parsed)
+        .config(|c| c.retranscribe?.threshold)
+        .or_default(0.6)
+}
+
+/// Resolves the model of the second pass with priority:
+/// 1. WA_RETRANSCRIBE_MODEL env var
+/// 2. User config file `retranscribe.model`
+/// 3. Default to none (the first pass's model)
+pub fn model_setting() -> Resolved<Option<String>> {
+    Layers::new("retranscribe model")
+        .env("WA_RETRANSCRIBE_MODEL", Some)
+        .config(|c| c.retranscribe?.model)
+        .optional()
+}
+
+/// Resolves the beam search width of the second pass with priority:
+/// 1. WA_RETRANSCRIBE_BEAM_SIZE env var
+/// 2. User config file `retranscribe.beam_size`
+/// 3. Default to none (the daemon's usual decoding)
+pub fn beam_size_setting() -> Resolved<Option<u32>> {
+    Layers::new("retranscribe beam size")
+        .env("WA_RETRANSCRIBE_BEAM_SIZE", settings::parsed)
+        .config(|c| c.retranscribe?.beam_size)
+        .optional()
+}
+
+/// Resolves how long a dictation may take, first pass included, before the first pass's
+/// text is used without waiting for a second with priority:
+/// 1. WA_RETRANSCRIBE_BUDGET_SECS env var
+/// 2. User config file `retranscribe.budget_secs`
+/// 3. Default to 10
+pub fn budget_secs_setting() -> Resolved<f64> {
+    Layers::new("retranscribe budget (s)")
+        .env("WA_RETRANSCRIBE_BUDGET_SECS", settings::parsed)
+        .config(|c| c.retranscribe?.budget_secs)
+        .or_default(10.0)
+}
+
+/// A second pass is only possible with something to do differently
+pub fn enabled() -> bool {
+    model_setting().value.is_some() || beam_size_setting().value.is_some()
+}
+
+/// Transcribe the audio again when the daemon wasn't confident of `first`, keeping whichever
+/// pass it is more confident of; `started` is when the first pass began, which the budget
+/// counts from
+/// Any failure of the second pass (including running out of budget) keeps the first
+pub fn improve(
+    socket_path: &str,
+    request: &TranscriptionRequest,
+    first: TranscriptionResponse,
+    backend_name: &str,
+    started: Instant,
+) -> TranscriptionResponse {
+    if !enabled() {
+        return first;
+    }
+    if request.command.is_some() {
+        debug!("Final text from the first pass (the daemon recorded the audio and has removed it)");
+        return first;
+    }
+    let threshold = threshold_setting().value;
+    let Some(confidence) = first.confidence else {
+        debug!("Final text from the first pass (no confidence reported)");
+        return first;
+    };
+    if confidence >= threshold {
+        debug!("Final text from the first pass (confidence {:.2}, threshold {:.2})", confidence, threshold);
+        return first;
+    }
+    let budget = Duration::from_secs_f64(budget_secs_setting().value.max(0.0));
+    let Some(remaining) = budget.checked_sub(started.elapsed()).filter(|remaining| !remaining.is_zero()) else {
+        debug!("Final text from the first pass (confidence {:.2}, but the {:.1}s budget is spent)", confidence, budget.as_secs_f64());
+        return first;
+    };
+
+    let retry_started = Instant::now();
+    info!("Confidence {:.2} below {:.2}, transcribing again within {:.1}s", confidence, threshold, remaining.as_secs_f64());
+    let second = second_pass_request(request, backend_name).and_then(|retry| {
+        let second = socket::transcribe_within(socket_path, &retry, remaining)?;
+        if second.cancelled || !second.success || second.text.is_none() {
+            return Err(anyhow!("{}", second.error.as_deref().unwrap_or("no transcript in the response")));
+        }
+        Ok((retry, second))
+    });
+    let (retry, second) = match second {
+        Ok(passes) => passes,
+        Err(e) => {
+            debug!("Final text from the first pass (confidence {:.2}; second pass failed: {:#})", confidence, e);
+            return first;
+        }
+    };
+    // Without a confidence the second pass is trusted: it was set up to do better
+    if second.confidence.is_some_and(|second_confidence| second_confidence < confidence) {
+        debug!(
+            "Final text from the first pass (confidence {:.2}; second pass only {:.2} after {:?})",
+            confidence, second.confidence.unwrap_or_default(), retry_started.elapsed()
+        );
+        return first;
+    }
+    debug!(
+        "Final text from the second pass (model {:?}, beam size {:?}): confidence {:.2} → {:?} after {:?}",
+        second.model, retry.beam_size, confidence, second.confidence, retry_started.elapsed()
+    );
+    second
+}
+
+/// The first pass's request with the second pass's model and beam size, never streamed
+fn second_pass_request(request: &TranscriptionRequest, backend_name: &str) -> Result<TranscriptionRequest> {
+    let model = match model_setting().value {
+        // A relative model path means nothing to the faster-whisper daemon, which runs elsewhere
+        Some(model) => Some(models::resolve_for(backend_name, &model)?),
+        None => request.model.clone(),
+    };
+    Ok(TranscriptionRequest {
+        label: Some(format!("retranscribe ({})", model.as_deref().unwrap_or("same model"))),
+        model,
+        beam_size: beam_size_setting().value,
+        stream: false,
+        ..request.clone()
+    })
+}
@@ -0,0 +1,398 @@
+use std::fmt;
+use std::path::Path;
+use tracing::debug;
+use crate::config::{self, read_config, Config};
+use crate::helpers::{self, read_daemon_config, DaemonConfig};
+use crate::typing::Output;
+use crate::hooks::{self, Event};
+use crate::{activation, bilingual, chooser, clipboard, commands, failover, feedback, focus, grammar, hallucination, handoff, history, instance, keyboard, latency, line_breaks, locale, models, postprocess, privacy, recording, refine, remote, retranscribe, sinks, spoken, terminal, transcript, typing, uncertain, vad};
+#[cfg(feature = "http-backends")]
+use crate::openai;
+#[cfg(feature = "whisper-cpp")]
+use crate::whisper_cpp;
+
+/// Where an effective setting came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Command line flag or the selected --profile
+    Cli,
+    /// `whisp-away with -p`
+    With,
+    Env(&'static str),
+    /// Read off the desktop, e.g. the keyboard layout
+    Detected(&'static str),
+    ConfigFile,
+    DaemonConfig,
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Cli => write!(f, "CLI flag/profile"),
+            Source::With => write!(f, "with -p"),
+            Source::Env(var) => write!(f, "env {}", var),
+            Source::Detected(what) => write!(f, "{}", what),
+            Source::ConfigFile => write!(f, "config file"),
+            Source::DaemonConfig => write!(f, "daemon config"),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// An effective setting and the layer it came from
+#[derive(Debug, Clone)]
+pub struct Resolved<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+/// Resolves a setting by trying each layer in priority order until one is set
+/// Later layers aren't evaluated once a value is found, so files are only read when needed
+pub struct Layers<T> {
+    name: &'static str,
+    resolved: Option<Resolved<T>>,
+}
+
+impl<T: fmt::Debug> Layers<T> {
+    pub fn new(name: &'static str) -> Self {
+        Self { name, resolved: None }
+    }
+
+    fn layer(mut self, source: Source, get: impl FnOnce() -> Option<T>) -> Self {
+        if self.resolved.is_none() {
+            if let Some(value) = get() {
+                debug!("Using {} from {}: {:?}", self.name, source, value);
+                self.resolved = Some(Resolved { value, source });
+            }
+        }
+        self
+    }
+
+    pub fn cli(self, value: Option<T>) -> Self {
+        self.layer(Source::Cli, || value)
+    }
+
+    /// Environment variable, converted with `parse` (unparsable values are skipped)
+    pub fn env(self, var: &'static str, parse: impl FnOnce(String) -> Option<T>) -> Self {
+        self.layer(Source::Env(var), || std::env::var(var).ok().and_then(parse))
+    }
+
+    /// A value read off the desktop (`what` says from where), when there is one
+    pub fn detected(self, what: &'static str, get: impl FnOnce() -> Option<T>) -> Self {
+        self.layer(Source::Detected(what), get)
+    }
+
+    /// User config file; settings given to `whisp-away with` also take precedence over
+    /// the layers before it, except CLI flags
+    pub fn config(self, get: impl Fn(Config) -> Option<T>) -> Self {
+        self.config_from(config::read_overlay, read_config, get)
+    }
+
+    /// `config`, with the `with -p` settings and the config file read by `overlay` and `file`
+    fn config_from(
+        mut self,
+        overlay: impl FnOnce() -> Option<Config>,
+        file: impl FnOnce() -> Option<Config>,
+        get: impl Fn(Config) -> Option<T>,
+    ) -> Self {
+        if self.resolved.as_ref().is_none_or(|resolved| resolved.source != Source::Cli) {
+            if let Some(value) = overlay().and_then(&get) {
+                debug!("Using {} from {}: {:?}", self.name, Source::With, value);
+                self.resolved = Some(Resolved { value, source: Source::With });
+                return self;
+            }
+        }
+        self.layer(Source::ConfigFile, || file().and_then(get))
+    }
+
+    pub fn daemon(self, get: impl FnOnce(DaemonConfig) -> Option<T>) -> Self {
+        self.layer(Source::DaemonConfig, || read_daemon_config().and_then(get))
+    }
+
+    pub fn or_default(self, value: T) -> Resolved<T> {
+        let name = self.name;
+        self.resolved.unwrap_or_else(|| {
+            debug!("Using default {}: {:?}", name, value);
+            Resolved { value, source: Source::Default }
+        })
+    }
+
+    /// Fall back to another resolution when no layer is set
+    pub fn or_else(self, fallback: impl FnOnce() -> Resolved<T>) -> Resolved<T> {
+        self.resolved.unwrap_or_else(fallback)
+    }
+
+    /// For settings without a default: unset resolves to None
+    pub fn optional(self) -> Resolved<Option<T>> {
+        match self.resolved {
+            Some(Resolved { value, source }) => Resolved { value: Some(value), source },
+            None => Resolved { value: None, source: Source::Default },
+        }
+    }
+}
+
+/// Parse an environment value, ignoring values of the wrong type
+pub fn parsed<T: std::str::FromStr>(value: String) -> Option<T> {
+    value.trim().parse().ok()
+}
+
+/// Parse an environment flag: "true" (any case) enables, anything else disables
+pub fn flag(value: String) -> Option<bool> {
+    Some(value.to_lowercase() == "true")
+}
+
+/// Values from `config show` flags for settings that take a CLI value directly
+/// (model, language and task come from the overrides)
+#[derive(Debug, Default)]
+pub struct CliValues {
+    pub output: Option<Output>,
+    pub compute_type: Option<String>,
+    pub batch_size: Option<u32>,
+    pub final_model: Option<String>,
+    pub max_retries: Option<u32>,
+}
+
+/// One line of `config show`
+struct Row {
+    name: &'static str,
+    value: String,
+    source: Source,
+}
+
+fn row<T: fmt::Display>(name: &'static str, setting: Resolved<T>) -> Row {
+    Row { name, value: setting.value.to_string(), source: setting.source }
+}
+
+/// Settings without a default show as "-"
+fn optional_row<T: fmt::Display>(name: &'static str, setting: Resolved<Option<T>>) -> Row {
+    let value = setting.value.map_or_else(|| "-".to_string(), |value| value.to_string());
+    Row { name, value, source: setting.source }
+}
+
+/// Every effective setting with the layer it came from
+fn effective(cli: CliValues) -> Vec<Row> {
+    let hallucination_phrases = hallucination::phrases_setting();
+    let failover = failover::chain_setting();
+    let terminal_apps = terminal::apps_setting();
+    let model_dirs = models::dirs_setting();
+    let rows = vec![
+        row("backend", helpers::backend_setting()),
+        row("model", helpers::model_setting()),
+        row("language", helpers::language_setting()),
+        row("layout_language", keyboard::layout_language_setting()),
+        row("task", helpers::task_setting()),
+        optional_row("initial_prompt", helpers::initial_prompt_setting()),
+        row("output", helpers::output_setting(cli.output)),
+        row("fifo_path", sinks::fifo_path_setting()),
+        row("chooser", chooser::chooser_setting()),
+        optional_row("final_model", helpers::final_model_setting(cli.final_model)),
+        optional_row("compute_type", helpers::compute_type_setting(cli.compute_type)),
+        optional_row("batch_size", helpers::batch_size_setting(cli.batch_size)),
+        optional_row("instance", instance::setting()),
+        row("socket_path", helpers::socket_path_setting()),
+        row("inline_audio", helpers::inline_audio_setting()),
+        row("fd_audio", handoff::fd_audio_setting()),
+        optional_row("daemon_address", remote::address_setting()),
+        optional_row("daemon_listen", remote::listen_setting()),
+        optional_row("daemon_token", remote::token_setting()),
+        optional_row("daemon_tls_cert", remote::tls_cert_setting()),
+        optional_row("daemon_tls_key", remote::tls_key_setting()),
+        optional_row("daemon_tls_ca", remote::tls_ca_setting()),
+        Row {
+            name: "model_dirs",
+            value: if model_dirs.value.is_empty() {
+                "-".to_string()
+            } else {
+                model_dirs.value.iter().map(|dir| dir.display().to_string()).collect::<Vec<_>>().join(":")
+            },
+            source: model_dirs.source,
+        },
+        row("model_memory_mb", helpers::model_memory_mb_setting()),
+        row("fast_path_secs", helpers::fast_path_secs_setting()),
+        row("daemon_idle_exit_mins", activation::idle_exit_mins_setting()),
+        row("confidence_threshold", helpers::confidence_threshold_setting()),
+        row("retranscribe.threshold", retranscribe::threshold_setting()),
+        optional_row("retranscribe.model", retranscribe::model_setting()),
+        optional_row("retranscribe.beam_size", retranscribe::beam_size_setting()),
+        row("retranscribe.budget_secs", retranscribe::budget_secs_setting()),
+        row("bilingual.enabled", bilingual::enabled_setting()),
+        row("bilingual.output", bilingual::output_template_setting()),
+        row("bilingual.copy", bilingual::copy_template_setting()),
+        row("uncertain.enabled", uncertain::enabled_setting()),
+        row("uncertain.threshold", uncertain::threshold_setting()),
+        row("uncertain.marker", uncertain::marker_setting()),
+        optional_row("line_breaks.pause_secs", line_breaks::pause_secs_setting()),
+        row("line_breaks.speaker_change", line_breaks::speaker_change_setting()),
+        row("typing_strategy", typing::strategy_setting()),
+        row("stream_segments", typing::stream_setting()),
+        row("max_typed_chars", typing::max_typed_chars_setting()),
+        row("ending", postprocess::ending_setting()),
+        row("locale", locale::locale_setting()),
+        row("typing_tool", typing::tool_setting()),
+        row("type_delay_ms", typing::wayland_delay_ms_setting()),
+        row("xwayland_type_delay_ms", typing::xwayland_delay_ms_setting()),
+        row("respect_screen_lock", typing::respect_screen_lock_setting()),
+        row("terminal_output", terminal::output_setting()),
+        Row {
+            name: "terminal_apps",
+            value: format!("{} apps", terminal_apps.value.len()),
+            source: terminal_apps.source,
+        },
+        row("clipboard_hold_secs", clipboard::hold_secs_setting()),
+        row("clipboard.sensitive", clipboard::sensitive_setting()),
+        row("clipboard.trigger", clipboard::trigger_setting()),
+        optional_row("wait_for_focus", focus::target_setting()),
+        row("focus_timeout_secs", focus::timeout_secs_setting()),
+        row("refine_strategy", refine::strategy_setting()),
+        row("notifications", feedback::mode_setting()),
+        row("notifications.preview", feedback::preview_setting()),
+        row("notifications.announce", feedback::announce_setting()),
+        optional_row("osd_command", feedback::osd_command_setting()),
+        row("history", history::enabled_setting()),
+        optional_row("history_max_entries", history::max_entries_setting()),
+        optional_row("history_max_age", history::max_age_setting()),
+        optional_row("history_max_mb", history::max_mb_setting()),
+        row("history_window", history::window_setting()),
+        row("privacy", privacy::enabled_setting()),
+        row("speech_stats", transcript::stats_notification_setting()),
+        row("latency_breakdown", latency::breakdown_setting()),
+        row("source", recording::source_setting(None)),
+        row("channels", recording::channels_setting()),
+        row("mic_gain", recording::mic_gain_setting()),
+        row("min_sound_secs", recording::min_sound_secs_setting()),
+        optional_row("segment_mins", recording::segments::minutes_setting()),
+        row("keep_last_recording", recording::keep_last_setting()),
+        row("auto_unmute", recording::mute::auto_unmute_setting()),
+        row("daemon_recording", recording::capture::daemon_recording_setting()),
+        row("sandbox", recording::sandbox_setting()),
+        row("spill_threshold_mb", helpers::spill_threshold_mb_setting()),
+        row("max_retries", helpers::max_retries_setting(cli.max_retries)),
+        Row {
+            name: "failover",
+            value: failover.value.join(" → "),
+            source: failover.source,
+        },
+        row("hallucination_filter", hallucination::filter_setting()),
+        row("no_speech_threshold", hallucination::no_speech_threshold_setting()),
+        Row {
+            name: "hallucination_phrases",
+            value: format!("{} phrases", hallucination_phrases.value.len()),
+            source: hallucination_phrases.source,
+        },
+        row("spoken_forms", spoken::spoken_forms_setting()),
+        row("spell", spoken::spell_setting()),
+        row("voice_commands", commands::enabled_setting()),
+        row("grammar", grammar::mode_setting()),
+        row("languagetool_url", grammar::languagetool_url_setting()),
+        optional_row("hooks.on_record_start", hooks::command_setting(Event::RecordStart)),
+        optional_row("hooks.on_record_stop", hooks::command_setting(Event::RecordStop)),
+        optional_row("hooks.on_transcript", hooks::command_setting(Event::Transcript)),
+        optional_row("hooks.on_error", hooks::command_setting(Event::Error)),
+        row("hooks.timeout_secs", hooks::timeout_secs_setting()),
+        row("vad.engine", vad::engine_setting()),
+    ];
+    let vad_tunings = vad::tunings(vad::engine()).into_iter()
+        .map(|tuning| Row { name: tuning.name, value: tuning.value.value, source: tuning.value.source });
+    let rows: Vec<Row> = rows.into_iter().chain(vad_tunings).collect();
+    #[cfg(feature = "whisper-cpp")]
+    let rows: Vec<Row> = rows.into_iter().chain([{
+        let whisper_args = whisper_cpp::direct::extra_args_setting();
+        Row {
+            name: "whisper_args",
+            value: if whisper_args.value.is_empty() { "-".to_string() } else { whisper_args.value.join(" ") },
+            source: whisper_args.source,
+        }
+    }]).collect();
+    #[cfg(feature = "http-backends")]
+    let rows: Vec<Row> = rows.into_iter().chain({
+        let api_key = openai::api_key_setting();
+        [
+            row("api_url", openai::api_url_setting()),
+            row("api_model", openai::api_model_setting()),
+            // Never print the key itself
+            Row {
+                name: "api_key",
+                value: if api_key.value.is_some() { "(set)" } else { "-" }.to_string(),
+                source: api_key.source,
+            },
+        ]
+    }).collect();
+    #[cfg(all(target_os = "linux", feature = "tray"))]
+    let rows: Vec<Row> = rows.into_iter().chain([optional_row("tray.shortcut", crate::shortcuts::shortcut_setting())]).collect();
+    #[cfg(feature = "portable")]
+    let rows: Vec<Row> = rows.into_iter().chain([row("embedded_model", whisper_cpp::embedded::model_setting())]).collect();
+    rows
+}
+
+fn describe_file(path: &Path) -> String {
+    let state = if path.exists() { "" } else { " (not found)" };
+    format!("{}{}", path.display(), state)
+}
+
+/// Print every effective setting and where it came from (`whisp-away config show`)
+pub fn show(cli: CliValues) {
+    println!("Config file:   {}", describe_file(&config::config_path()));
+    println!("Daemon config: {}", describe_file(Path::new(&helpers::get_daemon_config_path())));
+    println!("Recordings:    {}", recording::recording_dir());
+    println!();
+
+    let rows = effective(cli);
+    let name_width = rows.iter().map(|row| row.name.len()).max().unwrap_or(0);
+    let value_width = rows.iter().map(|row| row.value.chars().count()).max().unwrap_or(0).min(40);
+    for row in rows {
+        println!("{:<name_width$}  {:<value_width$}  {}", row.name, row.value, row.source);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENV_VAR: &str = "WA_TEST_LAYERS_MODEL";
+
+    fn config(model: &str) -> Option<Config> {
+        Some(Config { model: Some(model.to_string()), ..Default::default() })
+    }
+
+    /// The model resolved from the layers that are set, in the order settings use
+    fn resolve(cli: Option<&str>, with: Option<&str>, file: Option<&str>) -> Resolved<String> {
+        Layers::new("model")
+            .cli(cli.map(str::to_string))
+            .env(ENV_VAR, Some)
+            .config_from(|| with.and_then(config), || file.and_then(config), |c| c.model)
+            .or_default("default".to_string())
+    }
+
+    #[test]
+    fn layers_resolve_cli_then_with_then_env_then_config_then_default() {
+        assert_eq!(resolve(None, None, None).source, Source::Default);
+        assert_eq!(resolve(None, None, None).value, "default");
+        let file = resolve(None, None, Some("file"));
+        assert_eq!((file.value.as_str(), file.source), ("file", Source::ConfigFile));
+
+        // The only test to set this variable, so no other test sees it
+        std::env::set_var(ENV_VAR, "env");
+        let env = resolve(None, None, Some("file"));
+        assert_eq!((env.value.as_str(), env.source), ("env", Source::Env(ENV_VAR)));
+        let with = resolve(None, Some("with"), Some("file"));
+        assert_eq!((with.value.as_str(), with.source), ("with", Source::With));
+        let cli = resolve(Some("cli"), Some("with"), Some("file"));
+        assert_eq!((cli.value.as_str(), cli.source), ("cli", Source::Cli));
+        std::env::remove_var(ENV_VAR);
+    }
+
+    #[test]
+    fn layers_stop_reading_once_a_value_is_found() {
+        let resolved = Layers::new("model")
+            .cli(Some("cli".to_string()))
+            .config_from(|| panic!("overlay read"), || panic!("config file read"), |c: Config| c.model)
+            .optional();
+        assert_eq!(resolved.value.as_deref(), Some("cli"));
+
+        let resolved = Layers::<String>::new("model")
+            .config_from(|| None, || None, |c| c.model)
+            .optional();
+        assert_eq!((resolved.value, resolved.source), (None, Source::Default));
+    }
+}
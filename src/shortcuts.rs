@@ -0,0 +1,291 @@
+use anyhow::{Context, Result};
+use dbus::arg::{PropMap, RefArg, Variant};
+use dbus::blocking::Connection;
+use dbus::message::MatchRule;
+use dbus::Path as ObjectPath;
+use std::fmt;
+use std::time::Duration;
+use tracing::{debug, info};
+use crate::keyboard::command_stdout;
+use crate::portal::{self, handle_options};
+use crate::settings::{Layers, Resolved};
+
+const GLOBAL_SHORTCUTS: &str = "org.freedesktop.portal.GlobalShortcuts";
+
+/// The one shortcut the tray registers
+const TOGGLE_ID: &str = "toggle";
+
+/// Resolves the key combination the tray asks the GlobalShortcuts portal to bind to
+/// toggling dictation with priority:
+/// 1. WA_TRAY_SHORTCUT env var
+/// 2. User config file `tray.shortcut`
+/// 3. Default to none: the tray registers nothing and only shows the compositor's bindings
+///
+/// Uses the XDG shortcut format, e.g. "CTRL+ALT+D" or "LOGO+space"; "none" turns it off
+pub fn shortcut_setting() -> Resolved<Option<String>> {
+    Layers::new("tray shortcut")
+        .env("WA_TRAY_SHORTCUT", Some)
+        .config(|c| c.tray?.shortcut)
+        .optional()
+}
+
+/// The trigger to register, unless the setting turns registration off
+pub fn preferred_trigger() -> Option<String> {
+    shortcut_setting().value
+        .map(|shortcut| shortcut.trim().to_string())
+        .filter(|shortcut| !shortcut.is_empty() && !shortcut.eq_ignore_ascii_case("none"))
+}
+
+/// A key combination that runs whisp-away, and who binds it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    /// Keys as the compositor or portal describes them, e.g. "SUPER+D"
+    pub trigger: String,
+    /// What the keys run, e.g. "toggle"
+    pub action: String,
+    pub source: &'static str,
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} → {} ({})", self.trigger, self.action, self.source)
+    }
+}
+
+/// Keys bound to whisp-away commands in the running compositor's configuration
+/// Hyprland lists its binds over IPC; sway only hands out its main config file, so binds in
+/// included files are missed; other compositors have no way to ask
+pub fn compositor_bindings() -> Vec<Binding> {
+    hyprland_bindings()
+        .or_else(sway_bindings)
+        .unwrap_or_default()
+}
+
+/// The whisp-away subcommand (and arguments) a bound command runs
+fn whisp_away_action(command: &str) -> Option<String> {
+    let (_, args) = command.split_once("whisp-away")?;
+    let args = args.trim();
+    Some(if args.is_empty() { "whisp-away".to_string() } else { args.to_string() })
+}
+
+fn hyprland_bindings() -> Option<Vec<Binding>> {
+    std::env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
+    parse_hyprland_binds(&command_stdout("hyprctl", &["binds", "-j"])?)
+}
+
+/// Binds running whisp-away in `hyprctl binds -j` output
+fn parse_hyprland_binds(output: &str) -> Option<Vec<Binding>> {
+    let json: serde_json::Value = serde_json::from_str(output).ok()?;
+    let bindings = json.as_array()?.iter().filter_map(|bind| {
+        if bind.get("dispatcher")?.as_str()? != "exec" {
+            return None;
+        }
+        let action = whisp_away_action(bind.get("arg")?.as_str()?)?;
+        let mut keys = hyprland_modifiers(bind.get("modmask")?.as_u64()?);
+        keys.push(bind.get("key")?.as_str()?.to_string());
+        let release = bind.get("release").and_then(|r| r.as_bool()) == Some(true);
+        Some(Binding {
+            trigger: format!("{}{}", keys.join("+"), if release { " (release)" } else { "" }),
+            action,
+            source: "Hyprland",
+        })
+    });
+    Some(bindings.collect())
+}
+
+/// Hyprland's modifier bit mask as key names
+fn hyprland_modifiers(mask: u64) -> Vec<String> {
+    [(64, "SUPER"), (4, "CTRL"), (8, "ALT"), (1, "SHIFT")]
+        .iter()
+        .filter(|(bit, _)| mask & bit != 0)
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+fn sway_bindings() -> Option<Vec<Binding>> {
+    std::env::var("SWAYSOCK").ok()?;
+    let json: serde_json::Value = serde_json::from_str(&command_stdout("swaymsg", &["-t", "get_config", "-r"])?).ok()?;
+    Some(parse_sway_config(json.get("config")?.as_str()?))
+}
+
+/// `bindsym`/`bindcode` lines running whisp-away in a sway config file
+fn parse_sway_config(config: &str) -> Vec<Binding> {
+    config.lines().filter_map(|line| {
+        let mut words = line.split_whitespace();
+        if !matches!(words.next()?, "bindsym" | "bindcode") {
+            return None;
+        }
+        let mut release = false;
+        // Flags come before the keys
+        let keys = words.by_ref().find(|word| {
+            release |= *word == "--release";
+            !word.starts_with("--")
+        })?;
+        let command = words.collect::<Vec<_>>().join(" ");
+        Some(Binding {
+            trigger: format!("{}{}", keys, if release { " (release)" } else { "" }),
+            action: whisp_away_action(command.strip_prefix("exec")?)?,
+            source: "sway",
+        })
+    }).collect()
+}
+
+/// Register the toggle shortcut with the GlobalShortcuts portal, then call `on_toggle` each
+/// time it is pressed; `on_bound` hears the keys it ended up on, and again whenever the user
+/// changes them in the desktop's settings
+/// Blocks for as long as the session lasts, so run it on its own thread
+pub fn run_portal(
+    preferred_trigger: &str,
+    on_bound: impl Fn(Vec<Binding>) + Send + 'static,
+    on_toggle: impl Fn() + Send + 'static,
+) -> Result<()> {
+    let conn = Connection::new_session().context("Failed to connect to the session bus")?;
+    portal::register_app(&conn);
+    let token = format!("whisp_away_{}", std::process::id());
+
+    let mut options = handle_options(&format!("{}_create", token));
+    options.insert("session_handle_token".to_string(), Variant(Box::new(token.clone())));
+    let results = portal::request(&conn, GLOBAL_SHORTCUTS, "CreateSession", &format!("{}_create", token), (options,))?;
+    // A string in older portals, an object path in newer ones
+    let session = results.get("session_handle")
+        .and_then(|handle| handle.as_str())
+        .map(|handle| ObjectPath::from(handle.to_string()))
+        .context("The portal didn't open a shortcuts session")?;
+    debug!("Shortcuts session {}", session);
+
+    let mut shortcut = PropMap::new();
+    shortcut.insert("description".to_string(), Variant(Box::new("Toggle dictation".to_string())));
+    shortcut.insert("preferred_trigger".to_string(), Variant(Box::new(preferred_trigger.to_string())));
+    let bind_token = format!("{}_bind", token);
+    let results = portal::request(
+        &conn,
+        GLOBAL_SHORTCUTS,
+        "BindShortcuts",
+        &bind_token,
+        (session.clone(), vec![(TOGGLE_ID.to_string(), shortcut)], String::new(), handle_options(&bind_token)),
+    )?;
+    let bound = results.get("shortcuts").map(|shortcuts| bound_shortcuts(&*shortcuts.0)).unwrap_or_default();
+    info!("Portal shortcut registered: {:?}", bound);
+    on_bound(portal_bindings(bound));
+
+    let own_session = session.clone();
+    conn.add_match(
+        MatchRule::new_signal(GLOBAL_SHORTCUTS, "Activated"),
+        move |(handle, id): (ObjectPath, String), _, _| {
+            if handle == own_session && id == TOGGLE_ID {
+                debug!("Portal shortcut {} pressed", id);
+                on_toggle();
+            }
+            true
+        },
+    ).context("Failed to listen for shortcut presses")?;
+    conn.add_match(
+        MatchRule::new_signal(GLOBAL_SHORTCUTS, "ShortcutsChanged"),
+        move |(handle, shortcuts): (ObjectPath, Vec<(String, PropMap)>), _, _| {
+            if handle == session {
+                let bound = shortcuts.iter()
+                    .map(|(id, properties)| (id.clone(), trigger_description(properties)))
+                    .collect();
+                on_bound(portal_bindings(bound));
+            }
+            true
+        },
+    ).context("Failed to listen for shortcut changes")?;
+
+    loop {
+        conn.process(Duration::from_secs(60)).context("Lost the session bus")?;
+    }
+}
+
+/// (id, trigger) of every shortcut in a response's `a(sa{sv})`
+fn bound_shortcuts(shortcuts: &dyn RefArg) -> Vec<(String, Option<String>)> {
+    let Some(shortcuts) = shortcuts.as_iter() else {
+        return Vec::new();
+    };
+    shortcuts.filter_map(|shortcut| {
+        let mut fields = shortcut.as_iter()?;
+        let id = fields.next()?.as_str()?.to_string();
+        // A dict iterates as key, value, key, value...
+        let mut properties = fields.next()?.as_iter()?;
+        let mut trigger = None;
+        while let (Some(key), Some(value)) = (properties.next(), properties.next()) {
+            if key.as_str() == Some("trigger_description") {
+                trigger = value.as_str().map(str::to_string);
+            }
+        }
+        Some((id, trigger))
+    }).collect()
+}
+
+fn trigger_description(properties: &PropMap) -> Option<String> {
+    properties.get("trigger_description")?.as_str().map(str::to_string)
+}
+
+/// The portal leaves the trigger empty until the user assigns keys
+fn portal_bindings(shortcuts: Vec<(String, Option<String>)>) -> Vec<Binding> {
+    shortcuts.into_iter().map(|(id, trigger)| Binding {
+        trigger: trigger.filter(|t| !t.is_empty()).unwrap_or_else(|| "not assigned".to_string()),
+        action: id,
+        source: "portal",
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(bindings: &[Binding]) -> Vec<String> {
+        bindings.iter().map(|binding| binding.to_string()).collect()
+    }
+
+    #[test]
+    fn reads_hyprland_binds() {
+        let output = r#"[
+            {"locked": false, "mouse": false, "release": false, "repeat": false, "modmask": 64, "submap": "", "key": "D", "keycode": 0, "dispatcher": "exec", "arg": "whisp-away toggle"},
+            {"locked": false, "mouse": false, "release": true, "repeat": false, "modmask": 69, "submap": "", "key": "D", "keycode": 0, "dispatcher": "exec", "arg": "/run/current-system/sw/bin/whisp-away stop --output clipboard"},
+            {"locked": false, "mouse": false, "release": false, "repeat": false, "modmask": 8, "submap": "", "key": "W", "keycode": 0, "dispatcher": "exec", "arg": "whisp-away"},
+            {"locked": false, "mouse": false, "release": false, "repeat": false, "modmask": 64, "submap": "", "key": "Return", "keycode": 0, "dispatcher": "exec", "arg": "kitty"},
+            {"locked": false, "mouse": false, "release": false, "repeat": false, "modmask": 64, "submap": "", "key": "Q", "keycode": 0, "dispatcher": "killactive", "arg": "whisp-away"}
+        ]"#;
+        assert_eq!(summary(&parse_hyprland_binds(output).unwrap()), [
+            "SUPER+D → toggle (Hyprland)",
+            "SUPER+CTRL+SHIFT+D (release) → stop --output clipboard (Hyprland)",
+            "ALT+W → whisp-away (Hyprland)",
+        ]);
+        assert_eq!(parse_hyprland_binds("[]"), Some(Vec::new()));
+        assert_eq!(parse_hyprland_binds("hyprctl: no such instance"), None);
+    }
+
+    #[test]
+    fn reads_sway_binds() {
+        let config = "\
+set $mod Mod4
+bindsym $mod+d exec whisp-away toggle
+    bindsym --release --no-repeat $mod+Shift+d exec --no-startup-id whisp-away stop --output clipboard
+bindcode 133+40 exec ~/.local/bin/whisp-away start
+bindsym $mod+Return exec foot
+bindsym $mod+w kill
+# bindsym $mod+x exec whisp-away toggle
+exec whisp-away tray
+";
+        assert_eq!(summary(&parse_sway_config(config)), [
+            "$mod+d → toggle (sway)",
+            "$mod+Shift+d (release) → stop --output clipboard (sway)",
+            "133+40 → start (sway)",
+        ]);
+    }
+
+    #[test]
+    fn describes_portal_shortcuts() {
+        let bindings = portal_bindings(vec![
+            ("toggle".to_string(), Some("Ctrl+Alt+D".to_string())),
+            ("toggle".to_string(), Some(String::new())),
+            ("toggle".to_string(), None),
+        ]);
+        assert_eq!(summary(&bindings), [
+            "Ctrl+Alt+D → toggle (portal)",
+            "not assigned → toggle (portal)",
+            "not assigned → toggle (portal)",
+        ]);
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::{anyhow, Context, Result};
+use std::ffi::CString;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tracing::{debug, warn};
+use crate::config;
+use crate::helpers;
+use crate::paths;
+use crate::settings::{Layers, Resolved};
+use crate::template;
+
+/// Entry appended by a file output without a `template`
+const DEFAULT_FILE_TEMPLATE: &str = "{{ text }}\n";
+
+/// Directory holding sink executables: $XDG_CONFIG_HOME/whisp-away/sinks
+pub fn sinks_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from(".config"))
+        .join("whisp-away")
+        .join("sinks")
+}
+
+/// Resolves the named pipe for `--output fifo` with priority:
+/// 1. WA_FIFO_PATH env var
+/// 2. User config file `fifo_path`
+/// 3. Default to $XDG_RUNTIME_DIR/whisp-away.fifo
+pub fn fifo_path_setting() -> Resolved<String> {
+    Layers::new("FIFO path")
+        .env("WA_FIFO_PATH", Some)
+        .config(|c| c.fifo_path)
+        .or_default(format!("{}/whisp-away.fifo", paths::runtime_dir()))
+}
+
+/// Write the transcript as one line to the FIFO, creating it (mode 0600) if needed
+/// Fails right away when no program has the pipe open for reading, instead of blocking
+/// until one does
+pub fn write_fifo(text: &str) -> Result<String> {
+    let path = fifo_path_setting().value;
+    ensure_fifo(Path::new(&path))?;
+
+    // Non-blocking open: ENXIO when there is no reader
+    let mut fifo = OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(&path)
+        .map_err(|e| match e.raw_os_error() {
+            Some(libc::ENXIO) => anyhow!("Nothing is reading {}", path),
+            _ => anyhow!("Failed to open {}: {}", path, e),
+        })?;
+    // Then block while writing, so a transcript longer than the pipe buffer waits for the reader
+    unsafe {
+        let fd = fifo.as_raw_fd();
+        libc::fcntl(fd, libc::F_SETFL, libc::fcntl(fd, libc::F_GETFL) & !libc::O_NONBLOCK);
+    }
+    // Transcripts are single lines (whitespace is collapsed), so a reader can split on newlines
+    fifo.write_all(format!("{}\n", text).as_bytes())
+        .with_context(|| format!("Failed to write to {}", path))?;
+    debug!("Wrote {} chars to {}", text.len(), path);
+    Ok(path)
+}
+
+fn ensure_fifo(path: &Path) -> Result<()> {
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.file_type().is_fifo() => return Ok(()),
+        Ok(_) => return Err(anyhow!("{} exists but is not a named pipe", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to check {}", path.display())),
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to create named pipe {}", path.display()));
+    }
+    debug!("Created named pipe {}", path.display());
+    Ok(())
+}
+
+/// Find the executable for a sink name, rejecting anything that would escape the sinks dir
+fn find_sink(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        return Err(anyhow::anyhow!("Invalid sink name: '{}'", name));
+    }
+
+    let path = sinks_dir().join(name);
+    let metadata = std::fs::metadata(&path)
+        .with_context(|| format!("Sink '{}' not found in {}", name, sinks_dir().display()))?;
+    if !metadata.is_file() || metadata.permissions().mode() & 0o111 == 0 {
+        return Err(anyhow::anyhow!("Sink '{}' is not an executable file", path.display()));
+    }
+    Ok(path)
+}
+
+/// Names of the executable sinks in the sinks directory, sorted
+pub fn list_sinks() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(sinks_dir()) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| find_sink(name).is_ok())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Run a sink plugin: the transcript goes to stdin, metadata to WHISP_AWAY_* env vars.
+/// A non-zero exit status is reported as an error with the sink's stderr.
+pub fn run_sink(name: &str, text: &str, backend_name: &str) -> Result<()> {
+    let path = find_sink(name)?;
+    let timestamp = helpers::now_secs();
+    debug!("Running sink {} ({} chars)", path.display(), text.len());
+
+    let mut child = Command::new(&path)
+        .env("WHISP_AWAY_BACKEND", backend_name)
+        .env("WHISP_AWAY_MODEL", helpers::resolve_model())
+        .env("WHISP_AWAY_LANGUAGE", helpers::resolve_language())
+        .env("WHISP_AWAY_TIMESTAMP", timestamp.to_string())
+        .env("WHISP_AWAY_WORD_COUNT", text.split_whitespace().count().to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run sink {}", path.display()))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A sink that exits without reading stdin is not an error by itself
+        if let Err(e) = stdin.write_all(text.as_bytes()) {
+            warn!("Failed to write transcript to sink '{}': {}", name, e);
+        }
+    }
+
+    let output = child.wait_with_output()
+        .with_context(|| format!("Failed to wait for sink {}", path.display()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("Sink '{}' failed ({}): {}", name, output.status, stderr.trim()));
+    }
+
+    debug!("Sink '{}' finished", name);
+    Ok(())
+}
+
+/// Names of the `[files.<name>]` sections in the config file, sorted
+pub fn list_files() -> Vec<String> {
+    config::read_config()
+        .and_then(|c| c.files)
+        .map(|files| files.into_keys().collect())
+        .unwrap_or_default()
+}
+
+/// Append a transcript to the file of `[files.<name>]`, returning the file's path
+/// The path, header and entry are templates with these variables: text, date, time,
+/// datetime, timestamp, backend, model, language, words and tags
+/// A file that doesn't exist yet is created with its directory, and starts with the header
+pub fn append_file(name: &str, text: &str, backend_name: &str) -> Result<PathBuf> {
+    let sink = config::read_file_sink(name)?;
+    let path = sink.path.as_deref()
+        .ok_or_else(|| anyhow!("[files.{}] has no path", name))?;
+    let timestamp = helpers::now_secs() as libc::time_t;
+    let vars = [
+        ("text", text.to_string()),
+        ("date", helpers::format_local_time(timestamp, "%Y-%m-%d")),
+        ("time", helpers::format_local_time(timestamp, "%H:%M")),
+        ("datetime", helpers::format_local_time(timestamp, "%Y-%m-%dT%H:%M:%S%z")),
+        ("timestamp", timestamp.to_string()),
+        ("backend", backend_name.to_string()),
+        ("model", helpers::resolve_model()),
+        ("language", helpers::resolve_language()),
+        ("words", text.split_whitespace().count().to_string()),
+        ("tags", template::yaml_list(&sink.tags.unwrap_or_default())),
+    ];
+    let path = template::render(path, &vars).context("Invalid path template")?;
+    let path = match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None => PathBuf::from(path),
+    };
+    let entry = template::render(sink.template.as_deref().unwrap_or(DEFAULT_FILE_TEMPLATE), &vars)
+        .context("Invalid entry template")?;
+    let header = sink.header.as_deref()
+        .map(|header| template::render(header, &vars))
+        .transpose()
+        .context("Invalid header template")?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    // Written in one go, so a note app syncing the file doesn't see half an entry
+    let mut contents = String::new();
+    if file.metadata().map(|metadata| metadata.len() == 0).unwrap_or(false) {
+        contents.push_str(header.as_deref().unwrap_or_default());
+    }
+    contents.push_str(&entry);
+    file.write_all(contents.as_bytes())
+        .with_context(|| format!("Failed to write to {}", path.display()))?;
+    debug!("Appended {} chars to {}", contents.len(), path.display());
+    Ok(path)
+}
@@ -1,92 +1,1088 @@
 use anyhow::{Context, Result};
-use std::io::{Read, Write};
-use std::os::unix::net::UnixStream;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use tracing::{debug, warn};
+use crate::activity::Activity;
+use crate::audio;
+use crate::bilingual;
+use crate::failover::{self, Attempt, Step};
+use crate::focus;
+use crate::handoff;
+use crate::hallucination;
+use crate::keyboard;
+use crate::latency;
+use crate::line_breaks;
+use crate::postprocess;
+use crate::privacy;
+use crate::remote::{self, Connection};
+use crate::recording::{self, Source};
+use crate::retranscribe;
+use crate::spoken;
+use crate::transcript;
 use crate::typing;
+use crate::uncertain;
 use crate::helpers;
+use crate::instance;
+
+/// Version of the socket protocol, which daemons report in answer to `hello`
+#[cfg(feature = "whisper-cpp")]
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// How long `hello` waits for an answer; a daemon from before it queues the request as a
+/// transcription, which can sit behind a running job
+const HELLO_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Answers to `hello` by socket path and the socket file's inode; a restarted daemon
+/// creates the socket again, and may be another version
+/// A remote daemon is listed by its address with inode 0, and asked once per process
+static HELLOS: Mutex<Vec<(String, u64, Option<Hello>)>> = Mutex::new(Vec::new());
+
+/// The daemon's answer to `hello`: the protocol it speaks and what it can do, by command
+/// ("status", "watch", "jobs", "cancel", "unload", "load", "record") and by request field
+/// ("model", "language", "task", "beam_size", "initial_prompt", "compute_type",
+/// "batch_size", "stream", "audio_base64", "audio_fd", "label", "request_id")
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Hello {
+    pub protocol: u32,
+    /// whisp-away version of the daemon, when it knows it
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+impl Hello {
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|supported| supported == capability)
+    }
+}
+
+/// Request sent to the daemon over the Unix socket
+/// Optional fields are omitted from the JSON so older daemons keep working
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TranscriptionRequest {
+    pub audio_path: String,
+    /// Model to transcribe with; the daemon keeps several resident and loads others on demand
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// WAV bytes sent inline so the daemon doesn't need access to audio_path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio_base64: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compute_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch_size: Option<u32>,
+    /// Only sent when overridden for this invocation; the daemon resolves its own otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// "transcribe" or "translate"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    /// What the job is, for the daemon's job list (the audio file name when unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Send each segment as a line as soon as it is decoded, ahead of the response
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+    /// Beam search width instead of the daemon's default decoding
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub beam_size: Option<u32>,
+    /// Text whisper is primed with before decoding
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_prompt: Option<String>,
+    /// The audio's file descriptor is passed with the request (SCM_RIGHTS); `audio_path`
+    /// still names it for daemons that don't take descriptors
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub audio_fd: bool,
+    /// "stop_and_transcribe" transcribes the daemon's own recording instead of `audio_path`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Names the job for `cancel`, so a client can cancel its own transcription
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Report each word with its probability, for marking the uncertain ones
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub words: bool,
+}
+
+/// A line the daemon streams ahead of its response
+#[derive(Deserialize)]
+struct SegmentLine {
+    segment: Segment,
+}
+
+/// One timed piece of a transcript
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    /// Seconds from the start of the audio
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    /// Probability that the segment is silence or noise rather than speech
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_speech_prob: Option<f64>,
+    /// The segment's words, when the request asked for them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<Word>,
+    /// Who spoke it, from a daemon that tells speakers apart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<String>,
+}
+
+/// A word as the model decoded it, with the space before it and any punctuation after it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Word {
+    pub word: String,
+    /// How sure the model was of it (0.0-1.0)
+    pub probability: f64,
+}
+
+/// Daemon response to a transcription request
+/// Everything but `success` is optional so responses from older daemons still parse
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TranscriptionResponse {
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<Segment>,
+    /// Detected language (the requested one unless it was "auto")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Model that produced the transcript
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Audio length in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    /// Transcription time in seconds, excluding model loading
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inference_secs: Option<f64>,
+    /// Real-time factor: inference time divided by audio length
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtf: Option<f64>,
+    /// Mean token probability (0.0-1.0)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    /// Highest no-speech probability across segments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_speech_prob: Option<f64>,
+    /// Whether faster-whisper used batched inference
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batched: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_size: Option<u32>,
+    /// Speaking pace and pauses (computed by the client from the segments)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<transcript::SpeechStats>,
+    /// Levels of the recording, when it was made in this process (filled in by the client)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<audio::Levels>,
+    /// Where the time went, with the latency breakdown on (filled in by the client)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency: Option<latency::Breakdown>,
+    /// English translation of the transcript, in bilingual mode (filled in by the client)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translation: Option<String>,
+    /// The way of transcribing that produced the transcript, e.g. "daemon" or "whisper-cpp"
+    /// (filled in by the client)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub served_by: Option<String>,
+    /// Every way tried, in order, with how long it took (filled in by the client)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<failover::Attempt>,
+    /// The job was cancelled from the daemon's queue (e.g. in the tray)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
+    /// The daemon's models are unloaded (`whisp-away daemon unload`), so it didn't transcribe
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unloaded: bool,
+}
+
+/// Where a job is in the daemon's queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Pending,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    /// Pending and running jobs can still be cancelled
+    pub fn is_active(self) -> bool {
+        matches!(self, JobState::Pending | JobState::Running)
+    }
+}
+
+/// A transcription request in the daemon's queue
+/// The daemon runs one job at a time and keeps the last few finished ones
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    pub label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub state: JobState,
+    /// Unix time the request arrived
+    pub submitted: u64,
+    /// Unix time the job finished, failed or was cancelled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished: Option<u64>,
+    /// Share of the audio transcribed so far, for long recordings transcribed in windows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f32>,
+    /// The id the client gave its request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// Error for a transcription cancelled from the daemon's queue
+/// Callers check for it so a cancelled job doesn't fall back to another backend
+#[derive(Debug)]
+pub struct JobCancelled;
+
+impl std::fmt::Display for JobCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Transcription was cancelled")
+    }
+}
+
+impl std::error::Error for JobCancelled {}
+
+/// Error for a daemon from before `watch`, which tried to transcribe the request instead
+/// Watching it again won't help; callers fall back to polling
+#[derive(Debug)]
+pub struct WatchUnsupported;
+
+impl std::fmt::Display for WatchUnsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Daemon can't be watched (restart it after upgrading)")
+    }
+}
+
+impl std::error::Error for WatchUnsupported {}
+
+/// What the daemon reports to `watch` subscribers
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DaemonState {
+    pub state: Activity,
+    pub backend: String,
+    pub default_model: String,
+    /// Recording for a client (`daemon_recording`)
+    #[serde(default)]
+    pub recording: bool,
+    #[serde(default)]
+    pub jobs: Vec<Job>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JobsResponse {
+    #[serde(default)]
+    jobs: Option<Vec<Job>>,
+}
+
+impl TranscriptionRequest {
+    pub fn new(audio_path: &str) -> Self {
+        let overrides = helpers::overrides();
+        Self {
+            audio_path: audio_path.to_string(),
+            model: overrides.model.clone(),
+            // Otherwise the daemon's language, unless the keyboard layout picks one
+            language: overrides.language.clone().or_else(keyboard::layout_language),
+            // Bilingual mode translates in a second pass, so the first keeps the spoken language
+            task: if bilingual::enabled() { Some("transcribe".to_string()) } else { overrides.task.clone() },
+            label: Some("dictation".to_string()),
+            beam_size: overrides.beam_size,
+            initial_prompt: helpers::resolve_initial_prompt(),
+            request_id: Some(new_request_id()),
+            words: uncertain::enabled(),
+            ..Default::default()
+        }
+    }
+    
+    /// Copy of this request carrying the audio file contents inline
+    fn with_inline_audio(&self) -> Result<Self> {
+        let mut audio = std::fs::read(&self.audio_path)
+            .with_context(|| format!("Failed to read audio file {}", self.audio_path))?;
+        let audio_base64 = base64::engine::general_purpose::STANDARD.encode(&audio);
+        privacy::wipe_bytes(&mut audio);
+        Ok(Self {
+            audio_base64: Some(audio_base64),
+            ..self.clone()
+        })
+    }
+}
+
+/// A new id for a request: this process and the time, unique enough on one machine
+fn new_request_id() -> String {
+    let micros = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros();
+    format!("{}-{}", std::process::id(), micros)
+}
+
+/// File naming the transcription this machine last sent and still waits for, which
+/// `whisp-away cancel --transcription` cancels
+fn in_flight_path() -> String {
+    instance::runtime_path("whisp-away-transcribing", "id")
+}
+
+/// Records a transcription request as in flight while its response is awaited
+struct InFlight(Option<String>);
+
+impl InFlight {
+    fn record(request: &TranscriptionRequest) -> Self {
+        let Some(id) = request.request_id.clone() else {
+            return Self(None);
+        };
+        if let Err(e) = crate::paths::write_atomic(Path::new(&in_flight_path()), id.as_bytes()) {
+            debug!("Failed to record the request in flight: {}", e);
+        }
+        Self(Some(id))
+    }
+}
+
+impl Drop for InFlight {
+    /// Removes the file, unless a newer request has taken it over
+    fn drop(&mut self) {
+        let path = in_flight_path();
+        if self.0.is_some() && std::fs::read_to_string(&path).ok() == self.0 {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
+
+/// Connect to the daemon: the remote one when `daemon_address` is set, otherwise the socket
+fn connect(socket_path: &str) -> Result<Connection> {
+    let connected = match remote::address_setting().value {
+        Some(address) => {
+            debug!("Connecting to daemon at {}", address);
+            Connection::remote(&address)
+        }
+        None => {
+            debug!("Connecting to daemon at {}", socket_path);
+            Connection::unix(socket_path).map_err(anyhow::Error::new)
+        }
+    };
+    connected.map_err(|e| {
+        debug!("Failed to connect to daemon: {:#}", e);
+        // Return the error so the caller can handle fallback logic
+        anyhow::anyhow!("Failed to connect to daemon: {:#}", e)
+    })
+}
+
+/// Connect to the daemon and send one JSON payload
+fn send(socket_path: &str, payload: &str) -> Result<Connection> {
+    send_with_audio(socket_path, payload, None)
+}
+
+/// Like `send`, passing the audio file's descriptor along with the payload
+fn send_with_audio(socket_path: &str, payload: &str, audio: Option<&File>) -> Result<Connection> {
+    let mut stream = connect(socket_path)?;
+    let with_token;
+    let payload = match remote::token_setting().value {
+        Some(token) if stream.is_remote() => {
+            with_token = remote::with_token(payload, &token);
+            &with_token
+        }
+        _ => payload,
+    };
+    
+    debug!("Sending request ({} bytes)", payload.len());
+    let mut sent = 0;
+    if let (Some(audio), Some(unix)) = (audio, stream.as_unix()) {
+        sent = handoff::send_with_fd(unix, payload.as_bytes(), audio.as_raw_fd())
+            .context("Failed to pass the audio to the daemon")?;
+    }
+    stream.write_all(&payload.as_bytes()[sent..])
+        .context("Failed to send request to daemon")?;
+    // Signal the end of the request; large inline requests are read until this point
+    stream.shutdown_write();
+    Ok(stream)
+}
+
+/// Where the daemon is, for telling its answer to `hello` apart from another's: the socket
+/// path and its inode, or a remote daemon's address
+fn daemon_identity(socket_path: &str) -> Option<(String, u64)> {
+    match remote::address_setting().value {
+        Some(address) => Some((address.to_string(), 0)),
+        None => Some((socket_path.to_string(), std::fs::metadata(socket_path).ok()?.ino())),
+    }
+}
+
+/// Ask the daemon what it can do, once for as long as it runs
+/// None for a daemon from before `hello` (or one that isn't running), which is sent requests
+/// as before and judged by its responses
+pub fn hello(socket_path: &str) -> Option<Hello> {
+    let (daemon, inode) = daemon_identity(socket_path)?;
+    {
+        let hellos = HELLOS.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, _, hello)) = hellos.iter().find(|(known, known_inode, _)| *known == daemon && *known_inode == inode) {
+            return hello.clone();
+        }
+    }
+    let mut stream = send(socket_path, r#"{"command":"hello"}"#).ok()?;
+    let mut response = String::new();
+    let answered = stream.set_read_timeout(Some(HELLO_TIMEOUT))
+        .and_then(|()| stream.read_to_string(&mut response));
+    let hello = match answered {
+        // Not remembered, so the daemon is asked again once the token is fixed
+        Ok(_) if remote::check_refused(&response).is_err() => return None,
+        // Older daemons answer with a failed transcription, or not in time
+        Ok(_) => serde_json::from_str::<Hello>(&response).ok(),
+        Err(e) => {
+            debug!("No answer to hello: {}", e);
+            None
+        }
+    };
+    match &hello {
+        Some(hello) => debug!("Daemon speaks protocol {} ({})", hello.protocol, hello.capabilities.join(", ")),
+        None => debug!("Daemon doesn't answer hello, talking to it as before"),
+    }
+    let mut hellos = HELLOS.lock().unwrap_or_else(|e| e.into_inner());
+    hellos.retain(|(known, _, _)| *known != daemon);
+    hellos.push((daemon, inode, hello.clone()));
+    hello
+}
+
+/// Whether the daemon can do `capability`; assumed for daemons from before `hello`
+pub fn supports(socket_path: &str, capability: &str) -> bool {
+    hello(socket_path).is_none_or(|hello| hello.supports(capability))
+}
+
+/// Send one JSON payload to the daemon and return its raw response
+fn round_trip(socket_path: &str, payload: &str) -> Result<String> {
+    read_response(send(socket_path, payload)?)
+}
+
+/// Read the daemon's whole response to a request sent on `stream`
+fn read_response(mut stream: Connection) -> Result<String> {
+    let mut response = String::new();
+    stream.read_to_string(&mut response)
+        .context("Failed to read response from daemon")?;
+    
+    debug!("Received response: {}", privacy::redact(&response));
+    remote::check_refused(&response)?;
+    Ok(response)
+}
+
+/// Like `transcribe`, giving up when the daemon hasn't answered within `timeout`
+/// The daemon still finishes the job; its answer just goes unread
+pub fn transcribe_within(socket_path: &str, request: &TranscriptionRequest, timeout: Duration) -> Result<TranscriptionResponse> {
+    let _in_flight = InFlight::record(request);
+    let mut stream = send_request(socket_path, request)?;
+    stream.set_read_timeout(Some(timeout)).context("Failed to set a read timeout")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(|e| match e.kind() {
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => anyhow::anyhow!("No response within {:.1}s", timeout.as_secs_f64()),
+        _ => anyhow::Error::new(e).context("Failed to read response from daemon"),
+    })?;
+    debug!("Received response: {}", privacy::redact(&response));
+    remote::check_refused(&response)?;
+    serde_json::from_str(&response)
+        .with_context(|| format!("Could not parse daemon response: {}", privacy::redact(&response)))
+}
+
+/// Send a request to the daemon and return its raw JSON response
+fn exchange(socket_path: &str, request: &TranscriptionRequest) -> Result<String> {
+    let _in_flight = InFlight::record(request);
+    read_response(send_request(socket_path, request)?)
+}
+
+/// Like `exchange`, handing each segment the daemon streams ahead of its response to `on_segment`
+/// Daemons that don't stream just send the response
+fn exchange_streaming(socket_path: &str, request: &TranscriptionRequest, mut on_segment: impl FnMut(Segment)) -> Result<String> {
+    let _in_flight = InFlight::record(request);
+    let stream = send_request(socket_path, request)?;
+    let mut response = String::new();
+    for line in BufReader::new(stream).lines() {
+        let line = line.context("Failed to read response from daemon")?;
+        match serde_json::from_str::<SegmentLine>(&line) {
+            Ok(streamed) => on_segment(streamed.segment),
+            Err(_) => response.push_str(&line),
+        }
+    }
+    debug!("Received response: {}", privacy::redact(&response));
+    remote::check_refused(&response)?;
+    Ok(response)
+}
+
+/// Send a transcription request, with the audio inline or its file descriptor attached
+/// when configured
+fn send_request(socket_path: &str, request: &TranscriptionRequest) -> Result<Connection> {
+    debug!("Sending transcription request for: {}", request.audio_path);
+    let hello = hello(socket_path);
+    let request = &match &hello {
+        Some(hello) => without_unsupported(request, hello),
+        None => request.clone(),
+    };
+    let supports = |capability: &str| hello.as_ref().is_none_or(|hello| hello.supports(capability));
+    
+    // The daemon's own recording has no audio to send
+    if request.command.is_some() {
+        return send(socket_path, &payload(request)?);
+    }
+    // Send the audio itself when the daemon can't see our files (container, other host)
+    let remote = remote::address_setting().value.is_some();
+    if (remote || helpers::resolve_inline_audio()) && request.audio_base64.is_none() {
+        if supports("audio_base64") {
+            let mut inline = request.with_inline_audio()?;
+            let mut payload = payload(&inline)?;
+            let sent = send(socket_path, &payload);
+            privacy::wipe(&mut payload);
+            if let Some(audio) = inline.audio_base64.as_mut() {
+                privacy::wipe(audio);
+            }
+            return sent;
+        }
+        warn!("Daemon doesn't take inline audio, sending the path instead (restart it after upgrading)");
+    }
+    // Or the open file, which the daemon reads without a path of its own and without copies
+    if !remote && handoff::enabled() && request.audio_base64.is_none() && supports("audio_fd") {
+        match File::open(&request.audio_path) {
+            Ok(audio) => {
+                let request = TranscriptionRequest { audio_fd: true, ..request.clone() };
+                return send_with_audio(socket_path, &payload(&request)?, Some(&audio));
+            }
+            Err(e) => debug!("Sending the path, {} can't be opened: {}", request.audio_path, e),
+        }
+    }
+    send(socket_path, &payload(request)?)
+}
+
+/// Copy of `request` without the options the daemon said it doesn't take, rather than
+/// sending fields it would ignore
+fn without_unsupported(request: &TranscriptionRequest, hello: &Hello) -> TranscriptionRequest {
+    fn strip<T>(field: &mut Option<T>, name: &'static str, hello: &Hello, dropped: &mut Vec<&'static str>) {
+        if field.is_some() && !hello.supports(name) {
+            *field = None;
+            dropped.push(name);
+        }
+    }
+    let mut request = request.clone();
+    let mut dropped = Vec::new();
+    strip(&mut request.model, "model", hello, &mut dropped);
+    strip(&mut request.compute_type, "compute_type", hello, &mut dropped);
+    strip(&mut request.batch_size, "batch_size", hello, &mut dropped);
+    strip(&mut request.language, "language", hello, &mut dropped);
+    strip(&mut request.task, "task", hello, &mut dropped);
+    strip(&mut request.beam_size, "beam_size", hello, &mut dropped);
+    strip(&mut request.initial_prompt, "initial_prompt", hello, &mut dropped);
+    // The label and request id only name the job, so they go without a word
+    if !hello.supports("label") {
+        request.label = None;
+    }
+    if !hello.supports("request_id") {
+        request.request_id = None;
+    }
+    if request.words && !hello.supports("words") {
+        request.words = false;
+        debug!("Daemon doesn't report words, so none are marked uncertain");
+    }
+    // Without streaming the typer gets the whole transcript at the end
+    if request.stream && !hello.supports("stream") {
+        request.stream = false;
+        debug!("Daemon doesn't stream segments");
+    }
+    if !dropped.is_empty() {
+        warn!("Daemon doesn't take {} for this request (restart it after upgrading)", dropped.join(", "));
+    }
+    request
+}
+
+fn payload(request: &TranscriptionRequest) -> Result<String> {
+    serde_json::to_string(request)
+        .context("Failed to serialize request")
+}
+
+/// Ask the daemon which models it has loaded
+pub fn daemon_status(socket_path: &str) -> Result<serde_json::Value> {
+    let unsupported = || anyhow::anyhow!("Daemon doesn't report status (restart it after upgrading)");
+    if !supports(socket_path, "status") {
+        return Err(unsupported());
+    }
+    let response = round_trip(socket_path, r#"{"command":"status"}"#)?;
+    let status: serde_json::Value = serde_json::from_str(&response)
+        .context("Could not parse daemon status")?;
+    // Daemons without status support try to transcribe an empty path
+    if status.get("models").is_none() {
+        return Err(unsupported());
+    }
+    Ok(status)
+}
+
+/// Follow the daemon's state: `on_state` gets it right away and again whenever it changes,
+/// until the daemon goes away (returning Ok) or the connection fails
+pub fn watch_daemon(socket_path: &str, mut on_state: impl FnMut(DaemonState)) -> Result<()> {
+    if !supports(socket_path, "watch") {
+        return Err(WatchUnsupported.into());
+    }
+    let stream = send(socket_path, r#"{"command":"watch"}"#)?;
+    let mut lines = BufReader::new(stream).lines();
+    let mut last = None;
+    while let Some(line) = lines.next().transpose().context("Failed to read daemon state")? {
+        let state: DaemonState = match serde_json::from_str(&line) {
+            Ok(state) => state,
+            // Older daemons answer with a failed transcription
+            Err(_) if last.is_none() => return Err(WatchUnsupported.into()),
+            Err(e) => return Err(anyhow::anyhow!("Could not parse daemon state: {}", e)),
+        };
+        // The daemon's status changes every second (model idle times) without this changing
+        if last.as_ref() != Some(&state) {
+            last = Some(state.clone());
+            on_state(state);
+        }
+    }
+    Ok(())
+}
+
+/// Drop the daemon's models to free (video) memory, keeping the daemon running; transcription
+/// requests are refused until `load_models`
+/// Returns the daemon's status afterwards
+pub fn unload_models(socket_path: &str) -> Result<serde_json::Value> {
+    models_command(socket_path, "unload")
+}
+
+/// Load the daemon's default model again after `unload_models`
+/// Returns the daemon's status afterwards
+pub fn load_models(socket_path: &str) -> Result<serde_json::Value> {
+    models_command(socket_path, "load")
+}
+
+fn models_command(socket_path: &str, command: &str) -> Result<serde_json::Value> {
+    let unsupported = || anyhow::anyhow!("Daemon can't {} models (restart it after upgrading)", command);
+    if !supports(socket_path, command) {
+        return Err(unsupported());
+    }
+    let request = serde_json::json!({ "command": command }).to_string();
+    let status: serde_json::Value = serde_json::from_str(&round_trip(socket_path, &request)?)
+        .context("Could not parse daemon response")?;
+    if status.get("success").and_then(|v| v.as_bool()) == Some(false) {
+        return Err(anyhow::anyhow!(
+            "Daemon could not {}: {}",
+            command,
+            status.get("error").and_then(|v| v.as_str()).unwrap_or("unknown error")
+        ));
+    }
+    // Daemons without the command try to transcribe an empty path
+    if status.get("models").is_none() {
+        return Err(unsupported());
+    }
+    Ok(status)
+}
+
+/// Have the daemon record `source` for us, until a "stop_and_transcribe" request
+pub fn start_recording(socket_path: &str, source: Source) -> Result<()> {
+    let unsupported = || anyhow::anyhow!("Daemon can't record (restart it after upgrading)");
+    if !supports(socket_path, "record") {
+        return Err(unsupported());
+    }
+    let request = serde_json::json!({ "command": "start_recording", "source": source }).to_string();
+    let response: serde_json::Value = serde_json::from_str(&round_trip(socket_path, &request)?)
+        .context("Could not parse daemon response")?;
+    // Daemons without the command try to transcribe an empty path
+    if response.get("recording").is_none() {
+        return Err(unsupported());
+    }
+    if response.get("success").and_then(|v| v.as_bool()) != Some(true) {
+        return Err(anyhow::anyhow!(
+            "Daemon could not start recording: {}",
+            response.get("error").and_then(|v| v.as_str()).unwrap_or("unknown error")
+        ));
+    }
+    debug!("Daemon is recording {}", source);
+    Ok(())
+}
+
+/// Whether anything accepts connections on the socket; for telling a dead daemon from an
+/// old one that can't answer a request
+#[cfg(feature = "tray")]
+pub fn daemon_listening(socket_path: &str) -> bool {
+    connect(socket_path).is_ok()
+}
+
+/// Ask the daemon for its queued, running and recently finished jobs (oldest first)
+pub fn jobs(socket_path: &str) -> Result<Vec<Job>> {
+    let unsupported = || anyhow::anyhow!("Daemon has no job queue (restart it after upgrading)");
+    if !supports(socket_path, "jobs") {
+        return Err(unsupported());
+    }
+    let response = round_trip(socket_path, r#"{"command":"jobs"}"#)?;
+    let response: JobsResponse = serde_json::from_str(&response)
+        .context("Could not parse daemon job list")?;
+    // Daemons without a queue try to transcribe an empty path
+    response.jobs.ok_or_else(unsupported)
+}
+
+/// Cancel a pending or running job; the client waiting on it gets a cancelled response
+/// A running job stops decoding rather than finishing in the background
+pub fn cancel_job(socket_path: &str, id: u64) -> Result<()> {
+    send_cancel(socket_path, serde_json::json!({ "command": "cancel", "job": id }), &format!("job {}", id))
+}
+
+/// Cancel a job by the id its client gave the request
+pub fn cancel_request(socket_path: &str, request_id: &str) -> Result<()> {
+    if !supports(socket_path, "request_id") {
+        return Err(anyhow::anyhow!("Daemon can't cancel by request id (restart it after upgrading)"));
+    }
+    let request = serde_json::json!({ "command": "cancel", "request_id": request_id });
+    send_cancel(socket_path, request, &format!("request {}", request_id))
+}
+
+fn send_cancel(socket_path: &str, request: serde_json::Value, what: &str) -> Result<()> {
+    if !supports(socket_path, "cancel") {
+        return Err(anyhow::anyhow!("Daemon can't cancel jobs (restart it after upgrading)"));
+    }
+    let response: TranscriptionResponse = serde_json::from_str(&round_trip(socket_path, &request.to_string())?)
+        .context("Could not parse daemon response")?;
+    if !response.success {
+        return Err(anyhow::anyhow!(
+            "Could not cancel {}: {}",
+            what,
+            response.error.as_deref().unwrap_or("unknown error")
+        ));
+    }
+    debug!("Cancelled {}", what);
+    Ok(())
+}
+
+/// Cancel the transcription this machine is waiting for (the newest, when several are), or
+/// else the daemon's running job (`whisp-away cancel --transcription`)
+/// Returns what was cancelled
+pub fn cancel_transcription(socket_path: &str) -> Result<String> {
+    if let Ok(request_id) = std::fs::read_to_string(in_flight_path()) {
+        if supports(socket_path, "request_id") {
+            match cancel_request(socket_path, request_id.trim()) {
+                Ok(()) => return Ok(format!("request {}", request_id.trim())),
+                // Already finished, or sent to another daemon; the running job is the next best guess
+                Err(e) => debug!("{:#}", e),
+            }
+        }
+    }
+    let running = jobs(socket_path)?
+        .into_iter()
+        .find(|job| job.state == JobState::Running)
+        .ok_or_else(|| anyhow::anyhow!("No transcription is running"))?;
+    cancel_job(socket_path, running.id)?;
+    Ok(format!("job {} ({})", running.id, running.label))
+}
+
+/// Send a transcription request to the daemon and parse its response
+pub fn transcribe(socket_path: &str, request: &TranscriptionRequest) -> Result<TranscriptionResponse> {
+    let response = exchange(socket_path, request)?;
+    serde_json::from_str(&response)
+        .with_context(|| format!("Could not parse daemon response: {}", privacy::redact(&response)))
+}
+
+/// Send a transcription request to the daemon and return the text without outputting it
+pub fn transcribe_to_string(socket_path: &str, request: &TranscriptionRequest) -> Result<String> {
+    let mut response = transcribe(socket_path, request)?;
+    if response.cancelled {
+        return Err(JobCancelled.into());
+    }
+    if !response.success {
+        return Err(anyhow::anyhow!(
+            "Daemon transcription failed: {}",
+            response.error.as_deref().unwrap_or("unknown error")
+        ));
+    }
+    if response.text.is_none() {
+        return Err(anyhow::anyhow!("Daemon response has no text"));
+    }
+    hallucination::filter(&mut response);
+    Ok(response.text.unwrap_or_default().trim().to_string())
+}
 
 /// Send a transcription request to the daemon via Unix socket
+/// Returns the response whose text was output, if any
 pub fn send_transcription_request(
     socket_path: &str,
-    audio_file: &str,
+    request: &TranscriptionRequest,
     backend_name: &str,
-    use_clipboard: bool,
-) -> Result<()> {
-    debug!("Connecting to daemon at {}", socket_path);
-    
-    match UnixStream::connect(socket_path) {
-        Ok(mut stream) => {
-            debug!("Connected to daemon, sending transcription request for: {}", audio_file);
-            
-            // Send request
-            let request = format!(r#"{{"audio_path": "{}"}}"#, audio_file);
-            debug!("Sending request: {}", request);
-            stream.write_all(request.as_bytes())
-                .context("Failed to send request to daemon")?;
-            
-            // Read response
-            let mut response = String::new();
-            stream.read_to_string(&mut response)
-                .context("Failed to read response from daemon")?;
-            
-            debug!("Received response: {}", response);
-            
-            // Check if transcription was successful
-            let success = response.contains(r#""success":true"#) || response.contains(r#""success": true"#);
-            
-            if success {
-                // Parse the transcribed text from JSON response
-                let text = extract_text_from_response(&response);
-                
-                if let Some(transcribed_text) = text {
-                    debug!("Transcription result: '{}' ({} chars)", 
-                          if transcribed_text.len() > 50 { &transcribed_text[..50] } else { &transcribed_text },
-                          transcribed_text.len());
-                    typing::output_text(transcribed_text.trim(), use_clipboard, &format!("{} daemon", backend_name))?;
-                } else {
-                    debug!("Could not parse text from response");
-                    helpers::send_notification(
-                        "Voice Input",
-                        &format!("⚠️ Could not parse response\nBackend: {}", backend_name),
-                        2000
-                    );
+    output: &typing::Output,
+) -> Result<Option<TranscriptionResponse>> {
+    let started = Instant::now();
+    latency::request_sent();
+    let mut typer = streams(output).then(typing::StreamTyper::default);
+    let mut typing_error = None;
+    let mut raw = match typer.as_mut() {
+        Some(typer) => {
+            let request = TranscriptionRequest { stream: true, ..request.clone() };
+            let mut streamed = StreamedText::default();
+            let raw = exchange_streaming(socket_path, &request, |segment| {
+                // After a failure the rest isn't typed either, so nothing goes missing mid-text
+                if typing_error.is_none() {
+                    typing_error = typer.push(&streamed.push(segment)).err();
                 }
-            } else {
-                warn!("Transcription failed, response: {}", response);
-                helpers::send_notification(
-                    "Voice Input",
-                    &format!("❌ Transcription failed\nBackend: {}", backend_name),
-                    2000
-                );
+            })?;
+            if typing_error.is_none() {
+                typing_error = typer.push(&streamed.finish()).err();
             }
-            
-            Ok(())
+            raw
         }
+        None => exchange(socket_path, request)?,
+    };
+    let typed = typer.as_ref().map_or("", |typer| typer.typed());
+    if let Some(e) = typing_error {
+        warn!("Typing streamed segments failed: {:#}", e);
+        helpers::send_notification(
+            "Voice Input",
+            &format!("❌ Typing failed\nBackend: {}", backend_name),
+            2000
+        );
+        return Ok(None);
+    }
+    // The daemon answered, so a bad response doesn't fall back to direct mode
+    let mut response: TranscriptionResponse = match serde_json::from_str(&raw) {
+        Ok(response) => response,
         Err(e) => {
-            debug!("Failed to connect to daemon: {}", e);
-            // Return the error so the caller can handle fallback logic
-            Err(anyhow::anyhow!("Failed to connect to daemon: {}", e))
+            debug!("Could not parse daemon response ({}): {}", e, privacy::redact(&raw));
+            privacy::wipe(&mut raw);
+            helpers::send_notification(
+                "Voice Input",
+                &format!("⚠️ Could not parse response\nBackend: {}", backend_name),
+                2000
+            );
+            return Ok(None);
+        }
+    };
+    privacy::wipe(&mut raw);
+    
+    latency::response_received(response.inference_secs);
+    
+    if response.cancelled {
+        debug!("Transcription was cancelled");
+        if !typed.is_empty() {
+            typing::erase_typed(typed)?;
         }
+        helpers::send_notification(
+            "Voice Input",
+            &format!("🚫 Transcription cancelled\nBackend: {}", backend_name),
+            1500
+        );
+        return Ok(None);
+    }
+    
+    if response.unloaded {
+        debug!("Daemon has its models unloaded");
+        helpers::send_notification(
+            "Voice Input",
+            &format!("💤 Model unloaded\nRun `whisp-away daemon load` or use the tray\nBackend: {}", backend_name),
+            3000
+        );
+        return Ok(None);
     }
+    
+    if !response.success {
+        warn!("Transcription failed: {:?}", response.error);
+        helpers::send_notification(
+            "Voice Input",
+            &format!("❌ Transcription failed\nBackend: {}", backend_name),
+            2000
+        );
+        return Ok(None);
+    }
+    
+    let Some(transcribed_text) = response.text.take().map(|text| text.trim().to_string()) else {
+        debug!("Daemon response has no text");
+        helpers::send_notification(
+            "Voice Input",
+            &format!("⚠️ Could not parse response\nBackend: {}", backend_name),
+            2000
+        );
+        return Ok(None);
+    };
+    
+    debug!("Transcription result: '{}' ({} chars, language: {:?}, {} segments, {:?}s for {:?}s of audio)",
+          privacy::redact(if transcribed_text.len() > 50 { &transcribed_text[..50] } else { &transcribed_text }),
+          transcribed_text.len(), response.language, response.segments.len(),
+          response.inference_secs, response.duration);
+    response.text = Some(transcribed_text);
+    // Streamed text is already typed; streaming is off while retranscription or bilingual mode is on
+    if typer.is_none() {
+        response = retranscribe::improve(socket_path, request, response, backend_name, started);
+        if bilingual::enabled() {
+            bilingual::add_translation(socket_path, request, &mut response);
+        }
+    }
+    failover::record_attempts(&mut response, vec![Attempt::served(Step::Daemon, started)]);
+    match typer.filter(|typer| typer.started()) {
+        Some(mut typer) => deliver_streamed(&mut response, &mut typer, backend_name, &format!("{} daemon", backend_name)),
+        None => deliver(&mut response, backend_name, &format!("{} daemon", backend_name), output)?,
+    }
+    Ok(Some(response))
 }
 
-/// Extract the "text" field value from a JSON response string
-fn extract_text_from_response(response: &str) -> Option<String> {
-    if let Some(text_start_idx) = response.find(r#""text":"#) {
-        let after_text = &response[text_start_idx + 7..];
-        let content_start = after_text.trim_start();
-        
-        if content_start.starts_with('"') {
-            let text_content = &content_start[1..];
-            if let Some(end_quote) = text_content.find('"') {
-                Some(text_content[..end_quote].to_string())
-            } else {
-                None
-            }
-        } else {
-            None
+/// Segments are typed as they arrive only at the cursor, and not when the transcript has to
+/// be seen whole (spelling mode, a possible second pass, a translation to go with it, uncertain
+/// words to mark, lines to break, earlier segments to go in front) or typed into a window that may not be focused yet or a
+/// locked screen
+fn streams(output: &typing::Output) -> bool {
+    *output == typing::Output::Type
+        && typing::stream_setting().value
+        && !spoken::spell_setting().value
+        && !retranscribe::enabled()
+        && !bilingual::enabled()
+        && !uncertain::enabled()
+        && !line_breaks::enabled()
+        && !recording::segments::pending()
+        && focus::target_setting().value.is_none()
+        && !typing::screen_locked()
+}
+
+/// Words of the streamed text not typed until the next segment shows how they go on, so a
+/// spoken form spanning two segments ("... at example dot" and "com") comes out whole
+const HELD_BACK_WORDS: usize = 4;
+
+/// Streamed segments as they are typed: each is filtered on its own, then the text so far is
+/// post-processed as a whole, like a whole transcript
+#[derive(Default)]
+struct StreamedText {
+    /// The filtered segments, joined
+    raw: String,
+    /// The post-processed text handed out for typing so far
+    released: String,
+}
+
+impl StreamedText {
+    /// Add a segment; returns the text that can be typed now
+    fn push(&mut self, segment: Segment) -> String {
+        let mut piece = TranscriptionResponse { text: Some(segment.text.clone()), segments: vec![segment], ..Default::default() };
+        hallucination::filter(&mut piece);
+        self.raw.push(' ');
+        self.raw.push_str(piece.text.as_deref().unwrap_or_default().trim());
+        self.release(HELD_BACK_WORDS)
+    }
+
+    /// The held-back rest, once the last segment is in
+    fn finish(&mut self) -> String {
+        self.release(0)
+    }
+
+    /// The post-processed text that follows what was released, all but its last `hold` words
+    /// Where post-processing changed words already released, the words after the part that
+    /// still matches are handed out; the typer's correction at the end tidies that up
+    fn release(&mut self, hold: usize) -> String {
+        let processed = postprocess::apply_to_segment(self.raw.trim(), None);
+        let words: Vec<&str> = processed.split_whitespace().collect();
+        let released: Vec<&str> = self.released.split_whitespace().collect();
+        let ready = words.len().saturating_sub(hold);
+        let matching = words.iter().zip(&released).take_while(|(word, typed)| word == typed).count();
+        if matching < released.len() && hold > 0 {
+            // Wait for the end rather than type around a change
+            return String::new();
+        }
+        if ready <= matching {
+            return String::new();
+        }
+        self.released = words[..ready].join(" ");
+        words[matching..ready].join(" ")
+    }
+}
+
+/// Record a transcript that was typed segment by segment, then warn when it looks unreliable
+fn deliver_streamed(response: &mut TranscriptionResponse, typer: &mut typing::StreamTyper, backend_name: &str, source: &str) {
+    hallucination::filter(response);
+    let whole = postprocess::apply(response.text.as_deref().unwrap_or_default(), response.language.as_deref());
+    if let Err(e) = typer.correct(&whole) {
+        warn!("Correcting the streamed transcript failed: {:#}", e);
+    }
+    if postprocess::ending_setting().value == postprocess::Ending::Space {
+        if let Err(e) = typer.end_with_space() {
+            warn!("Typing the space after the transcript failed: {:#}", e);
+        }
+    }
+    // What was typed (and copied when too long), which is what the history and hooks should see
+    response.text = Some(typer.text());
+    transcript::publish(response, backend_name);
+    typer.finish(source);
+    transcript::report_latency(response);
+    log_batching(response);
+    warn_if_low_confidence(response, backend_name);
+}
+
+/// Filter, post-process, break lines, mark uncertain words, put the text of earlier segments in front, record and output a transcript (with its translation in bilingual
+/// mode), then warn when it looks unreliable
+/// `source` names what transcribed it in the result notification
+pub fn deliver(response: &mut TranscriptionResponse, backend_name: &str, source: &str, output: &typing::Output) -> Result<()> {
+    hallucination::filter(response);
+    let text = postprocess::apply(response.text.as_deref().unwrap_or_default(), response.language.as_deref());
+    let text = line_breaks::apply(&text, &response.segments);
+    response.text = Some(recording::segments::prepend(&uncertain::mark(&text, &response.segments)));
+    transcript::publish(response, backend_name);
+    let typed = bilingual::output(response, output, source);
+    transcript::report_latency(response);
+    typed?;
+    log_batching(response);
+    warn_if_low_confidence(response, backend_name);
+    Ok(())
+}
+
+/// Log whether the daemon used batched inference (faster-whisper only reports this)
+fn log_batching(response: &TranscriptionResponse) {
+    if let Some(batched) = response.batched {
+        debug!(
+            "Batched inference: {} (batch size: {:?}, audio: {:?}s)",
+            batched, response.batch_size, response.duration
+        );
+    }
+}
+
+/// Notify the user when the daemon reports a confidence below the configured threshold
+fn warn_if_low_confidence(response: &TranscriptionResponse, backend_name: &str) {
+    debug!("Transcript confidence: {:?}, no-speech probability: {:?}", response.confidence, response.no_speech_prob);
+    
+    if let Some(confidence) = response.confidence {
+        let threshold = helpers::resolve_confidence_threshold();
+        if confidence < threshold {
+            warn!("Low transcript confidence: {:.2} (threshold {:.2})", confidence, threshold);
+            helpers::send_notification(
+                "Voice Input",
+                &format!("⚠️ Low confidence ({:.0}%) - please proofread\nBackend: {}", confidence * 100.0, backend_name),
+                3000
+            );
         }
-    } else {
-        None
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(text: &str) -> Segment {
+        Segment { text: text.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn streamed_text_keeps_spoken_forms_across_segments() {
+        let mut streamed = StreamedText::default();
+        let mut pieces = vec![streamed.push(segment("Please write to me at jane at example dot"))];
+        pieces.push(streamed.push(segment("com by Friday.")));
+        pieces.push(streamed.finish());
+        let typed: Vec<String> = pieces.into_iter().filter(|piece| !piece.is_empty()).collect();
+        assert_eq!(typed.join(" "), "Please write to me at jane@example.com by Friday.");
+        assert_eq!(typed[0], "Please write to me at");
+    }
+
+    #[test]
+    fn streamed_text_drops_likely_hallucinations() {
+        let mut streamed = StreamedText::default();
+        streamed.push(segment("Meet Siobhan at nine."));
+        let silence = Segment { no_speech_prob: Some(0.99), ..segment("Thank you.") };
+        streamed.push(silence);
+        assert_eq!(streamed.finish(), "Meet Siobhan at nine.");
+    }
+}
@@ -0,0 +1,301 @@
+use crate::helpers;
+use crate::settings::{self, Layers, Resolved};
+
+/// Top-level domains that end a spoken address; requiring one keeps "dot" and "at"
+/// in ordinary sentences alone
+const TLDS: [&str; 40] = [
+    "com", "org", "net", "io", "dev", "edu", "gov", "co", "uk", "de", "fr", "eu", "ai", "app",
+    "me", "info", "biz", "us", "ca", "au", "nl", "ch", "at", "se", "no", "rs", "it", "es", "pl",
+    "ru", "jp", "in", "xyz", "tech", "sh", "gg", "tv", "ly", "cz", "be",
+];
+
+/// Words that aren't the start of an address ("email me at example dot com",
+/// "the dot com bubble")
+const STOPWORDS: [&str; 24] = [
+    "a", "an", "the", "this", "that", "me", "us", "you", "him", "her", "them", "it", "we",
+    "they", "is", "are", "was", "were", "be", "look", "site", "website", "page", "and",
+];
+
+/// Letters of the NATO alphabet (with common spelling variants)
+const NATO: [(&str, char); 30] = [
+    ("alpha", 'a'), ("alfa", 'a'), ("bravo", 'b'), ("charlie", 'c'), ("delta", 'd'),
+    ("echo", 'e'), ("foxtrot", 'f'), ("golf", 'g'), ("hotel", 'h'), ("india", 'i'),
+    ("juliet", 'j'), ("juliett", 'j'), ("kilo", 'k'), ("lima", 'l'), ("mike", 'm'),
+    ("november", 'n'), ("oscar", 'o'), ("papa", 'p'), ("quebec", 'q'), ("romeo", 'r'),
+    ("sierra", 's'), ("tango", 't'), ("uniform", 'u'), ("victor", 'v'), ("whiskey", 'w'),
+    ("whisky", 'w'), ("x-ray", 'x'), ("xray", 'x'), ("yankee", 'y'), ("zulu", 'z'),
+];
+
+const DIGITS: [(&str, char); 11] = [
+    ("zero", '0'), ("one", '1'), ("two", '2'), ("three", '3'), ("four", '4'), ("five", '5'),
+    ("six", '6'), ("seven", '7'), ("eight", '8'), ("nine", '9'), ("niner", '9'),
+];
+
+/// Spoken symbols, shared by addresses and spelling
+const SYMBOLS: [(&str, char); 6] = [
+    ("dot", '.'), ("underscore", '_'), ("dash", '-'), ("hyphen", '-'), ("slash", '/'), ("at", '@'),
+];
+
+/// Resolves whether spoken addresses are normalized with priority:
+/// 1. WA_SPOKEN_FORMS env var
+/// 2. User config file `spoken_forms`
+/// 3. Default to true
+pub fn spoken_forms_setting() -> Resolved<bool> {
+    Layers::new("spoken forms")
+        .env("WA_SPOKEN_FORMS", settings::flag)
+        .config(|c| c.spoken_forms)
+        .or_default(true)
+}
+
+/// Resolves spelling mode with priority:
+/// 1. CLI flag (--spell) or profile
+/// 2. WA_SPELL env var
+/// 3. Default to false
+pub fn spell_setting() -> Resolved<bool> {
+    Layers::new("spelling mode")
+        .cli(helpers::overrides().spell.then_some(true))
+        .env("WA_SPELL", settings::flag)
+        .or_default(false)
+}
+
+/// A whitespace-separated word split into its lowercase core and trailing punctuation
+struct Word<'a> {
+    core: String,
+    trailing: &'a str,
+}
+
+impl<'a> Word<'a> {
+    fn new(token: &'a str) -> Self {
+        let core = token.trim_end_matches(|c: char| ".,!?;:".contains(c));
+        Word {
+            core: core.to_lowercase(),
+            trailing: &token[core.len()..],
+        }
+    }
+
+    /// Whether the word can be part of an address: letters and digits, or something
+    /// the transcriber already wrote as an address fragment ("doe.smith", "example.com")
+    fn is_label(&self) -> bool {
+        !self.core.is_empty()
+            && self.core.chars().all(|c| c.is_alphanumeric() || "._-+".contains(c))
+            && self.core.chars().any(|c| c.is_alphanumeric())
+    }
+
+    fn is(&self, word: &str) -> bool {
+        self.core == word && self.trailing.is_empty()
+    }
+
+    fn symbol(&self) -> Option<char> {
+        SYMBOLS.iter().find(|(name, _)| self.core == *name).map(|(_, symbol)| *symbol)
+    }
+}
+
+/// Whether a label ends in a top-level domain ("com", or "example.com" as transcribed)
+fn ends_in_tld(label: &str) -> bool {
+    TLDS.contains(&label.rsplit('.').next().unwrap_or(label))
+}
+
+/// Convert spoken addresses when enabled:
+/// "john dot doe at example dot com" -> "john.doe@example.com",
+/// "w w w dot example dot com slash docs" -> "www.example.com/docs"
+pub fn normalize(text: &str) -> String {
+    if !spoken_forms_setting().value {
+        return text.to_string();
+    }
+
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    let words: Vec<Word> = tokens.iter().map(|token| Word::new(token)).collect();
+    let mut out: Vec<String> = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if let Some((address, next)) = email_at(&words, i).or_else(|| url_at(&words, i)) {
+            out.push(address);
+            i = next;
+        } else {
+            out.push(tokens[i].to_string());
+            i += 1;
+        }
+    }
+    out.join(" ")
+}
+
+/// Parse "<label> (dot <label>)*" ending in a top-level domain, starting at `start`
+/// Returns the domain and the index of the word after it
+fn domain_at(words: &[Word], start: usize) -> Option<(String, usize)> {
+    let first = words.get(start).filter(|w| w.is_label())?;
+    let mut domain = first.core.clone();
+    // Already written out ("example.com")
+    let mut best = (domain.contains('.') && ends_in_tld(&domain)).then(|| (domain.clone(), start + 1));
+
+    // A label only continues the domain if the previous word ends cleanly ("example, dot com" doesn't)
+    let mut i = start;
+    while words[i].trailing.is_empty() && words.get(i + 1).is_some_and(|w| w.is("dot")) {
+        let Some(label) = words.get(i + 2).filter(|w| w.is_label()) else {
+            break;
+        };
+        domain.push('.');
+        domain.push_str(&label.core);
+        i += 2;
+        if ends_in_tld(&label.core) {
+            best = Some((domain.clone(), i + 1));
+        }
+    }
+    best
+}
+
+/// "<local part> at <domain>", where the local part is labels joined by spoken
+/// dots, underscores and dashes
+fn email_at(words: &[Word], start: usize) -> Option<(String, usize)> {
+    let first = &words[start];
+    if !first.is_label() || !first.trailing.is_empty() || STOPWORDS.contains(&first.core.as_str()) {
+        return None;
+    }
+
+    let mut local = first.core.clone();
+    let mut i = start + 1;
+    loop {
+        let word = words.get(i)?;
+        if word.is("at") {
+            break;
+        }
+        let symbol = word.symbol().filter(|&s| s != '/' && s != '@' && word.trailing.is_empty())?;
+        let label = words.get(i + 1).filter(|w| w.is_label() && w.trailing.is_empty())?;
+        local.push(symbol);
+        local.push_str(&label.core);
+        i += 2;
+    }
+
+    let (domain, next) = domain_at(words, i + 1)?;
+    Some((format!("{}@{}{}", local, domain, words[next - 1].trailing), next))
+}
+
+/// "[www dot] <domain> (slash <path>)*"; without "www" the domain must be spoken
+/// ("example dot com"), since a lone "example.com" is already written out
+fn url_at(words: &[Word], start: usize) -> Option<(String, usize)> {
+    // "w w w" as separate letters, or "www"
+    let (prefix, domain_start) = if (0..3).all(|k| words.get(start + k).is_some_and(|w| w.is("w")))
+        && words.get(start + 3).is_some_and(|w| w.is("dot"))
+    {
+        ("www.", start + 4)
+    } else if words[start].is("www") && words.get(start + 1).is_some_and(|w| w.is("dot")) {
+        ("www.", start + 2)
+    } else {
+        ("", start)
+    };
+
+    let first = words.get(domain_start)?;
+    if prefix.is_empty() && STOPWORDS.contains(&first.core.as_str()) {
+        return None;
+    }
+    let (mut url, mut next) = domain_at(words, domain_start)?;
+    if prefix.is_empty() && next == domain_start + 1 {
+        return None;
+    }
+    url.insert_str(0, prefix);
+
+    while words[next - 1].trailing.is_empty()
+        && words.get(next).is_some_and(|w| w.is("slash"))
+    {
+        let Some(segment) = words.get(next + 1).filter(|w| w.is_label()) else {
+            break;
+        };
+        url.push('/');
+        url.push_str(&segment.core);
+        next += 2;
+    }
+    Some((format!("{}{}", url, words[next - 1].trailing), next))
+}
+
+/// Spelling mode: the whole dictation is one code spelled out in NATO alphabet
+/// words, letters and digits ("alpha bravo seven" -> "ab7")
+/// "capital" upper-cases the next letter and "space" inserts a space; words that
+/// aren't part of the alphabet are kept as spoken
+pub fn spell(text: &str) -> String {
+    let mut out = String::new();
+    let mut capital = false;
+    for token in text.split_whitespace() {
+        let word = token.trim_matches(|c: char| ".,!?;:\"'".contains(c)).to_lowercase();
+        if word.is_empty() {
+            continue;
+        }
+        if word == "capital" || word == "uppercase" {
+            capital = true;
+            continue;
+        }
+
+        let spelled = if word == "space" {
+            Some(' ')
+        } else {
+            NATO.iter()
+                .chain(DIGITS.iter())
+                .chain(SYMBOLS.iter())
+                .find(|(name, _)| word == *name)
+                .map(|(_, c)| *c)
+        };
+        let piece = match spelled {
+            Some(c) => c.to_string(),
+            // Single letters and digits as transcribed ("a", "7", "42")
+            None if word.chars().count() == 1 || word.chars().all(|c| c.is_ascii_digit()) => word,
+            None => token.trim_matches(|c: char| ".,!?;:\"'".contains(c)).to_string(),
+        };
+        if capital {
+            out.push_str(&piece.to_uppercase());
+            capital = false;
+        } else {
+            out.push_str(&piece);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<Word<'_>> {
+        text.split_whitespace().map(Word::new).collect()
+    }
+
+    #[test]
+    fn reads_spoken_emails() {
+        let sentence = words("mail john dot doe at example dot co dot uk, please");
+        assert_eq!(email_at(&sentence, 1), Some(("john.doe@example.co.uk,".to_string(), 10)));
+        assert_eq!(email_at(&words("jane underscore roe at mail dot example dot org"), 0).unwrap().0, "jane_roe@mail.example.org");
+        // Stopwords, and addresses without a top-level domain
+        assert_eq!(email_at(&words("me at example dot com"), 0), None);
+        assert_eq!(email_at(&words("meet at the cafe"), 0), None);
+        assert_eq!(email_at(&words("john at example dot notatld"), 0), None);
+    }
+
+    #[test]
+    fn reads_spoken_urls() {
+        assert_eq!(url_at(&words("w w w dot example dot com slash docs."), 0), Some(("www.example.com/docs.".to_string(), 9)));
+        assert_eq!(url_at(&words("www dot example dot io"), 0).unwrap().0, "www.example.io");
+        assert_eq!(url_at(&words("example dot dev slash blog slash rust"), 0).unwrap().0, "example.dev/blog/rust");
+        // Already written out, or not a domain
+        assert_eq!(url_at(&words("example.com"), 0), None);
+        assert_eq!(url_at(&words("the dot com bubble"), 0), None);
+        assert_eq!(url_at(&words("example, dot com"), 0), None);
+    }
+
+    #[test]
+    fn normalizes_addresses_in_sentences() {
+        assert_eq!(
+            normalize("Send it to jane dot roe at example dot com and see w w w dot example dot org slash faq."),
+            "Send it to jane.roe@example.com and see www.example.org/faq."
+        );
+        assert_eq!(normalize("Look at the dot in the middle"), "Look at the dot in the middle");
+        assert_eq!(normalize(""), "");
+    }
+
+    #[test]
+    fn spells_codes() {
+        assert_eq!(spell("alpha bravo seven"), "ab7");
+        assert_eq!(spell("Capital x-ray, yankee, zulu."), "Xyz");
+        assert_eq!(spell("niner dash 4 2 space kilo"), "9-42 k");
+        assert_eq!(spell("whiskey at echo dot india underscore oscar"), "w@e.i_o");
+        // Words outside the alphabet are kept
+        assert_eq!(spell("delta banana"), "dbanana");
+        assert_eq!(spell(""), "");
+    }
+}
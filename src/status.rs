@@ -0,0 +1,178 @@
+use anyhow::Result;
+use tracing::debug;
+use crate::activation;
+use crate::batch;
+use crate::helpers;
+use crate::pause;
+use crate::recording;
+use crate::remote;
+use crate::socket::{self, Job, JobState};
+
+/// Print what whisp-away is doing: recording, pause state and the daemon's loaded models
+pub fn run() -> Result<()> {
+    println!("Recording: {}", if recording::is_recording() { "yes" } else { "no" });
+    match pause::active() {
+        Some(pause) => println!("Dictation: disabled {}", pause.describe()),
+        None => println!("Dictation: enabled"),
+    }
+
+    let socket_path = helpers::resolve_socket_path();
+    if activation::asleep() {
+        println!("Daemon:    stopped, started by systemd on the next transcription");
+        return Ok(());
+    }
+    let status = match socket::daemon_status(&socket_path) {
+        Ok(status) => status,
+        Err(e) => {
+            println!("Daemon:    not available ({})", e);
+            return Ok(());
+        }
+    };
+
+    let field = |name: &str| status.get(name).and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
+    let number = |value: &serde_json::Value, name: &str| value.get(name).and_then(|v| v.as_u64()).unwrap_or(0);
+    let location = remote::address_setting().value.map_or_else(|| socket_path.clone(), |address| address.to_string());
+    println!("Daemon:    {} on {}", field("backend"), location);
+    match socket::hello(&socket_path) {
+        Some(hello) => println!(
+            "           protocol {}{}",
+            hello.protocol,
+            hello.version.map(|version| format!(", whisp-away {}", version)).unwrap_or_default()
+        ),
+        None => println!("           from an older whisp-away, restart it to use newer features"),
+    }
+    if status.get("recording").and_then(|v| v.as_bool()).unwrap_or(false) {
+        println!("           recording for a client");
+    }
+    println!("Default:   {}", field("default_model"));
+
+    if unloaded(&status) {
+        println!("Models:    unloaded, `whisp-away daemon load` to restore");
+        return Ok(());
+    }
+    let models = status.get("models").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+    let resident: u64 = models.iter().map(|model| number(model, "size_mb")).sum();
+    println!("Models:    {} loaded, {} / {} MB", models.len(), resident, number(&status, "memory_limit_mb"));
+    // Most recently used first
+    for model in &models {
+        println!(
+            "  {:<24} {:>6} MB   idle {}s",
+            model.get("name").and_then(|v| v.as_str()).unwrap_or("?"),
+            number(model, "size_mb"),
+            number(model, "idle_secs")
+        );
+    }
+    Ok(())
+}
+
+/// Whether a daemon status says its models are unloaded
+fn unloaded(status: &serde_json::Value) -> bool {
+    status.get("unloaded").and_then(|v| v.as_bool()).unwrap_or(false)
+}
+
+/// Free the daemon's models (`whisp-away daemon unload`)
+pub fn unload() -> Result<()> {
+    let socket_path = helpers::resolve_socket_path();
+    if activation::asleep() {
+        println!("Daemon is stopped, nothing to unload");
+        return Ok(());
+    }
+    socket::unload_models(&socket_path)?;
+    println!("Models unloaded; the daemon keeps running and refuses dictations until `whisp-away daemon load`");
+    Ok(())
+}
+
+/// Load the daemon's default model again (`whisp-away daemon load`)
+pub fn load() -> Result<()> {
+    let status = socket::load_models(&helpers::resolve_socket_path())?;
+    println!("Loaded {}", status.get("default_model").and_then(|v| v.as_str()).unwrap_or("the default model"));
+    Ok(())
+}
+
+/// Short marker for a job state, shared with the tray
+pub fn job_icon(state: JobState) -> &'static str {
+    match state {
+        JobState::Pending => "🕒",
+        JobState::Running => "⏳",
+        JobState::Done => "✅",
+        JobState::Failed => "❌",
+        JobState::Cancelled => "🚫",
+    }
+}
+
+/// One line describing a job, e.g. "⏳ #4 dictation (running, submitted 12s ago)"
+pub fn describe_job(job: &Job) -> String {
+    let state = format!("{:?}", job.state).to_lowercase();
+    let when = match job.finished {
+        Some(finished) => format!("{} at {}", state, helpers::format_local_time(finished as libc::time_t, "%H:%M:%S")),
+        None => {
+            let now = helpers::now_secs();
+            let progress = job.progress.map(|progress| format!(" {:.0}%", progress * 100.0)).unwrap_or_default();
+            format!("{}{}, submitted {}s ago", state, progress, now.saturating_sub(job.submitted))
+        }
+    };
+    format!("{} #{} {} ({})", job_icon(job.state), job.id, job.label, when)
+}
+
+/// List the daemon's jobs and the batch queue (`whisp-away jobs`), or cancel a daemon job
+/// with --cancel
+pub fn jobs(cancel: Option<u64>) -> Result<()> {
+    let socket_path = helpers::resolve_socket_path();
+    if let Some(id) = cancel {
+        socket::cancel_job(&socket_path, id)?;
+        println!("Cancelled job {}", id);
+        return Ok(());
+    }
+
+    let batch = batch::list()?;
+    // Without a daemon the batch queue may still have something to show
+    let jobs = match socket::jobs(&socket_path) {
+        Ok(jobs) => jobs,
+        Err(e) if !batch.is_empty() => {
+            debug!("No daemon jobs: {:#}", e);
+            Vec::new()
+        }
+        Err(e) => return Err(e),
+    };
+    if jobs.is_empty() && batch.is_empty() {
+        println!("No jobs");
+    }
+    for job in &jobs {
+        println!("{}", describe_job(job));
+    }
+    if !batch.is_empty() {
+        if !jobs.is_empty() {
+            println!();
+        }
+        println!("Batch jobs (whisp-away watch):");
+        for queued in &batch {
+            println!("{}", describe_job(&queued.job));
+            if let Some(error) = &queued.error {
+                println!("    {}", error);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Queue failed batch jobs again (`whisp-away jobs retry`)
+pub fn retry_batch(id: Option<u64>) -> Result<()> {
+    match batch::retry(id)? {
+        0 => println!("No failed batch jobs"),
+        count => println!("Queued {} batch job(s) again; `whisp-away watch` transcribes them", count),
+    }
+    Ok(())
+}
+
+/// Forget finished batch jobs (`whisp-away jobs clear`)
+pub fn clear_batch() -> Result<()> {
+    println!("Cleared {} finished batch job(s)", batch::clear()?);
+    Ok(())
+}
+
+/// Abort the transcription in progress (`whisp-away cancel --transcription`)
+pub fn cancel_transcription() -> Result<()> {
+    let cancelled = socket::cancel_transcription(&helpers::resolve_socket_path())?;
+    println!("Cancelled {}", cancelled);
+    Ok(())
+}
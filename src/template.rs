@@ -0,0 +1,81 @@
+use anyhow::{anyhow, Result};
+
+/// Fill in a template: each `{{ name }}` is replaced with that variable's value, and
+/// `{{ name | yaml }}` with the value quoted as a YAML string, for frontmatter fields
+/// Unknown variables and filters are errors, so a typo doesn't end up in the note
+pub fn render(template: &str, vars: &[(&str, String)]) -> Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find("}}").ok_or_else(|| anyhow!("Unclosed '{{{{' in template"))?;
+        let (name, filter) = match after[..end].split_once('|') {
+            Some((name, filter)) => (name.trim(), Some(filter.trim())),
+            None => (after[..end].trim(), None),
+        };
+        let value = vars.iter()
+            .find(|(var, _)| *var == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| {
+                let known: Vec<&str> = vars.iter().map(|(var, _)| *var).collect();
+                anyhow!("Unknown template variable '{}' (known: {})", name, known.join(", "))
+            })?;
+        match filter {
+            None => rendered.push_str(value),
+            Some("yaml") => rendered.push_str(&yaml_string(value)),
+            Some(filter) => return Err(anyhow!("Unknown template filter '{}' (known: yaml)", filter)),
+        }
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// A double-quoted YAML string; JSON's escaping is valid YAML
+pub fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// A YAML flow list of quoted strings, e.g. `["voice", "inbox"]`
+pub fn yaml_list(values: &[String]) -> String {
+    let items: Vec<String> = values.iter().map(|value| yaml_string(value)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Vec<(&'static str, String)> {
+        vec![("text", "Buy milk: two litres".to_string()), ("date", "2026-10-16".to_string())]
+    }
+
+    #[test]
+    fn fills_in_variables() {
+        assert_eq!(render("{{ date }}\n{{text}}\n", &vars()).unwrap(), "2026-10-16\nBuy milk: two litres\n");
+        assert_eq!(render("no variables", &vars()).unwrap(), "no variables");
+        assert_eq!(render("", &vars()).unwrap(), "");
+    }
+
+    #[test]
+    fn quotes_for_yaml() {
+        assert_eq!(render("title: {{ text | yaml }}", &vars()).unwrap(), "title: \"Buy milk: two litres\"");
+        assert_eq!(yaml_string("say \"hi\"\n"), "\"say \\\"hi\\\"\\n\"");
+        assert_eq!(yaml_list(&["voice".to_string(), "inbox".to_string()]), "[\"voice\", \"inbox\"]");
+        assert_eq!(yaml_list(&[]), "[]");
+    }
+
+    #[test]
+    fn rejects_mistakes() {
+        assert!(render("{{ txt }}", &vars()).is_err());
+        assert!(render("{{ text | upper }}", &vars()).is_err());
+        assert!(render("{{ text", &vars()).is_err());
+    }
+
+    #[test]
+    fn values_are_not_templates() {
+        let vars = vec![("text", "{{ date }}".to_string()), ("date", "today".to_string())];
+        assert_eq!(render("{{ text }}", &vars).unwrap(), "{{ date }}");
+    }
+}
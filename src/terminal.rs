@@ -0,0 +1,98 @@
+use tracing::debug;
+use crate::focus;
+use crate::settings::{Layers, Resolved};
+
+/// App ids (Wayland) and WM_CLASS names (X11) of common terminal emulators
+const DEFAULT_APPS: &[&str] = &[
+    "foot", "footclient", "kitty", "alacritty", "wezterm", "org.wezfurlong.wezterm",
+    "ghostty", "com.mitchellh.ghostty", "gnome-terminal-server", "org.gnome.terminal",
+    "org.gnome.console", "kgx", "org.gnome.ptyxis", "konsole", "org.kde.konsole",
+    "xfce4-terminal", "terminator", "tilix", "com.gexperts.tilix", "xterm", "urxvt",
+    "st", "st-256color", "rio", "contour", "termite", "blackbox", "com.raggesilver.blackbox",
+    "io.elementary.terminal", "lxterminal", "qterminal", "sakura", "warp", "dev.warp.warp",
+];
+
+/// How text goes into a terminal, where a typed newline runs whatever is on the prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handling {
+    /// Through the clipboard and the terminal's paste shortcut, so shells that support it
+    /// receive the text as one bracketed paste rather than keystrokes
+    Paste,
+    /// Typed, with newlines and control characters turned into spaces
+    Type,
+}
+
+/// Resolves the app ids and window classes treated as terminals with priority:
+/// 1. WA_TERMINAL_APPS env var, comma-separated
+/// 2. User config file `terminal_apps` (a list)
+/// 3. Default to common terminal emulators (foot, kitty, Alacritty, WezTerm, Ghostty, ...)
+pub fn apps_setting() -> Resolved<Vec<String>> {
+    Layers::new("terminal apps")
+        .env("WA_TERMINAL_APPS", |value| Some(value.split(',').map(|app| app.trim().to_string()).filter(|app| !app.is_empty()).collect()))
+        .config(|c| c.terminal_apps)
+        .or_default(DEFAULT_APPS.iter().map(|app| app.to_string()).collect())
+}
+
+/// Resolves how text is output at the cursor when a terminal is focused with priority:
+/// 1. WA_TERMINAL_OUTPUT env var ("paste", "type" or "off")
+/// 2. User config file `terminal_output`
+/// 3. Default to "paste"
+pub fn output_setting() -> Resolved<String> {
+    Layers::new("terminal output")
+        .env("WA_TERMINAL_OUTPUT", Some)
+        .config(|c| c.terminal_output)
+        .or_default("paste".to_string())
+}
+
+/// How to output text into the focused window; None when it isn't a terminal (or can't be
+/// detected) or terminal handling is off
+pub fn handling() -> Option<Handling> {
+    let handling = match output_setting().value.as_str() {
+        "off" => return None,
+        "type" => Handling::Type,
+        _ => Handling::Paste,
+    };
+    let window = focus::focused_window()?;
+    let apps = apps_setting().value;
+    if !apps.iter().any(|app| app.eq_ignore_ascii_case(&window.app_id)) {
+        return None;
+    }
+    debug!("Focused window {} is a terminal, outputting with {:?}", window.app_id, handling);
+    Some(handling)
+}
+
+/// Text safe to send to a terminal: no control characters (an escape could end a bracketed
+/// paste early), and without `keep_newlines` no line breaks either, so nothing is run
+pub fn sanitize(text: &str, keep_newlines: bool) -> String {
+    let cleaned: String = text.chars()
+        .map(|c| match c {
+            '\n' if keep_newlines => '\n',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    cleaned.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_control_characters() {
+        // An escape would let the text end a bracketed paste and run what follows
+        assert_eq!(sanitize("ls\x1b[201~; rm -rf ~", true), "ls [201~; rm -rf ~");
+        assert_eq!(sanitize("one\ttwo\x07three\u{7f}", true), "one two three");
+        assert_eq!(sanitize("grüße 👋", false), "grüße 👋");
+    }
+
+    #[test]
+    fn keeps_newlines_only_when_asked() {
+        assert_eq!(sanitize("first line\nsecond line\n", true), "first line\nsecond line");
+        assert_eq!(sanitize("first line\nsecond line\n", false), "first line second line");
+        assert_eq!(sanitize("windows\r\nline", true), "windows \nline");
+        assert_eq!(sanitize("windows\r\nline", false), "windows  line");
+        // A trailing newline would run the command
+        assert_eq!(sanitize("git status\n\n", false), "git status");
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::debug;
+use crate::{clipboard, failover, hallucination, handoff, helpers, line_breaks, paths, postprocess, privacy, socket, uncertain};
+use crate::socket::TranscriptionResponse;
+
+/// Transcribe an audio file along the failover chain (by default the daemon, then the backend
+/// directly, like `stop`), returning the text without post-processing or output
+pub fn file(audio_file: &str) -> Result<String> {
+    let response = file_response(audio_file)?;
+    Ok(response.text.unwrap_or_default().trim().to_string())
+}
+
+/// Like `file`, keeping the segments and timing of the transcript
+pub fn file_response(audio_file: &str) -> Result<TranscriptionResponse> {
+    let (steps, _) = failover::chain()?;
+    let request = socket::TranscriptionRequest {
+        compute_type: helpers::resolve_compute_type(None),
+        batch_size: helpers::resolve_batch_size(None),
+        label: Some("file".to_string()),
+        ..socket::TranscriptionRequest::new(audio_file)
+    };
+    let outcome = failover::transcribe(&request, &steps, helpers::resolve_max_retries(None))?;
+    if let Some(summary) = outcome.summary() {
+        debug!("{}", summary);
+    }
+    let (_, mut response) = outcome.into_result()?;
+    hallucination::filter(&mut response);
+    Ok(response)
+}
+
+/// Transcribe audio in any format ffmpeg reads, converting it to WAV first
+pub fn audio(path: &Path) -> Result<TranscriptionResponse> {
+    let wav = converted(path)?;
+    let result = file_response(&wav.as_ref().map_or_else(|| path.to_string_lossy().into_owned(), Converted::path));
+    if let Some(Converted::File(wav)) = wav {
+        let _ = privacy::remove_file(wav);
+    }
+    result
+}
+
+/// `whisp-away transcribe`: transcribe an existing audio file (e.g. a saved voice message)
+/// The text is printed, or with `from_clipboard` the path is taken from the clipboard and
+/// the text copied back to it
+pub fn run(path: Option<PathBuf>, from_clipboard: bool) -> Result<()> {
+    let path = match path {
+        Some(path) => path,
+        None if from_clipboard => {
+            let contents = clipboard::paste()?;
+            file_from_clipboard(&contents).ok_or_else(|| {
+                let shown: String = contents.trim().chars().take(80).collect();
+                anyhow!("The clipboard doesn't hold the path of an existing file: '{}'", shown)
+            })?
+        }
+        None => return Err(anyhow!("Give an audio file, or --from-clipboard")),
+    };
+    debug!("Transcribing {}", path.display());
+    if from_clipboard {
+        helpers::send_notification(
+            "Voice Input",
+            &format!("⏳ Transcribing {}...", path.file_name().unwrap_or_default().to_string_lossy()),
+            2000
+        );
+    }
+
+    let text = match audio(&path) {
+        Ok(response) => {
+            let text = postprocess::apply(response.text.as_deref().unwrap_or_default().trim(), None);
+            let text = line_breaks::apply(&text, &response.segments);
+            uncertain::mark(&text, &response.segments)
+        }
+        Err(e) => {
+            if from_clipboard {
+                helpers::send_notification("Voice Input", &format!("❌ Transcription failed\n{:#}", e), 5000);
+            }
+            return Err(e);
+        }
+    };
+
+    if !from_clipboard {
+        println!("{}", text);
+        return Ok(());
+    }
+    if text.is_empty() {
+        helpers::send_notification("Voice Input", "⚠️ No speech detected", 2000);
+        return Ok(());
+    }
+    clipboard::copy(&text)?;
+    let preview: String = text.chars().take(80).collect();
+    helpers::send_notification("Voice Input", &format!("✅ Transcript copied to clipboard\n{}", preview), 4000);
+    Ok(())
+}
+
+/// The first existing file in copied text: a path, a file:// URI, or a file manager's
+/// list of them (GNOME's "copy\nfile:///...", KDE's text/uri-list)
+fn file_from_clipboard(contents: &str) -> Option<PathBuf> {
+    contents.lines()
+        .map(str::trim)
+        .filter_map(|line| {
+            if let Some(uri) = line.strip_prefix("file://") {
+                // file://host/path is rare; the local host is written as nothing or "localhost"
+                let path = uri.strip_prefix("localhost").unwrap_or(uri);
+                return Some(PathBuf::from(percent_decode(path)));
+            }
+            if let Some(rest) = line.strip_prefix("~/") {
+                return dirs::home_dir().map(|home| home.join(rest));
+            }
+            line.starts_with('/').then(|| PathBuf::from(line))
+        })
+        .find(|path| path.is_file())
+}
+
+/// Decode %XX escapes of a URI path (spaces and non-ASCII names arrive as %20, %C3%A9, ...)
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Converted audio: a WAV file in the runtime directory, or with fd audio on, a sealed memfd
+/// that is gone once dropped
+enum Converted {
+    File(PathBuf),
+    #[cfg(target_os = "linux")]
+    Memory(handoff::MemAudio),
+}
+
+impl Converted {
+    fn path(&self) -> String {
+        match self {
+            Converted::File(path) => path.to_string_lossy().into_owned(),
+            #[cfg(target_os = "linux")]
+            Converted::Memory(audio) => audio.path(),
+        }
+    }
+}
+
+/// Where ffmpeg writes the converted audio
+fn conversion_target() -> Result<Converted> {
+    #[cfg(target_os = "linux")]
+    if handoff::enabled() {
+        let audio = handoff::MemAudio::new("whisp-away-transcribe").context("Failed to create in-memory audio")?;
+        return Ok(Converted::Memory(audio));
+    }
+    Ok(Converted::File(PathBuf::from(format!(
+        "{}/whisp-away-transcribe-{}.wav", paths::runtime_dir(), std::process::id()
+    ))))
+}
+
+/// Convert the file to 16kHz mono 16-bit WAV (what whisper expects) with ffmpeg, so voice
+/// messages in Opus, AAC or MP3 work with every backend
+/// None when WAV files are used as they are because ffmpeg isn't installed
+fn converted(path: &Path) -> Result<Option<Converted>> {
+    let wav = conversion_target()?;
+    let result = Command::new("ffmpeg")
+        .args(["-nostdin", "-loglevel", "error", "-y", "-i"])
+        .arg(path)
+        .args(["-ar", "16000", "-ac", "1", "-c:a", "pcm_s16le", "-f", "wav"])
+        .arg(wav.path())
+        .output();
+    let output = match result {
+        Err(e) if e.kind() == ErrorKind::NotFound && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("wav")) => {
+            debug!("ffmpeg not installed, transcribing {} as it is", path.display());
+            return Ok(None);
+        }
+        result => result.context("Failed to run ffmpeg, which converts the audio for transcription")?,
+    };
+    if !output.status.success() {
+        if let Converted::File(wav) = &wav {
+            let _ = privacy::remove_file(wav);
+        }
+        return Err(anyhow!(
+            "Could not read {} as audio: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    #[cfg(target_os = "linux")]
+    if let Converted::Memory(audio) = &wav {
+        audio.seal().context("Failed to seal the converted audio")?;
+    }
+    Ok(Some(wav))
+}
@@ -0,0 +1,245 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tracing::{debug, warn};
+use crate::history;
+use crate::hooks;
+use crate::latency;
+use crate::recording;
+use crate::settings::{self, Layers, Resolved};
+use crate::socket::{Segment, TranscriptionResponse};
+
+/// Extra destinations for a finished transcript, from the --json and --srt flags
+#[derive(Debug, Clone, Default)]
+pub struct Reports {
+    /// Print the full response as JSON on stdout
+    pub json: bool,
+    /// Write the segments as SRT subtitles to this file
+    pub srt: Option<PathBuf>,
+}
+
+static REPORTS: OnceLock<Reports> = OnceLock::new();
+
+/// Install the reports for this process (only the first call has an effect)
+pub fn set_reports(reports: Reports) {
+    debug!("Reports: {:?}", reports);
+    let _ = REPORTS.set(reports);
+}
+
+fn reports() -> &'static Reports {
+    REPORTS.get_or_init(Reports::default)
+}
+
+/// Speaking pace and pauses, derived from the segment timings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpeechStats {
+    pub words: usize,
+    /// Words per minute of speech, pauses excluded
+    pub words_per_minute: f64,
+    pub speech_secs: f64,
+    /// Recording time without speech, including before the first and after the last segment
+    pub silence_secs: f64,
+    /// Longest gap between two segments
+    pub longest_pause_secs: f64,
+}
+
+impl SpeechStats {
+    /// None without segments (backends that don't report timing)
+    pub fn from_response(response: &TranscriptionResponse) -> Option<Self> {
+        let mut segments: Vec<&Segment> = response.segments.iter()
+            .filter(|segment| !segment.text.trim().is_empty())
+            .collect();
+        if segments.is_empty() {
+            return None;
+        }
+        segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+        // Segments may overlap, so only count time past the end of the previous ones
+        let mut speech_secs = 0.0;
+        let mut longest_pause_secs: f64 = 0.0;
+        let mut spoken_until = segments[0].start;
+        for segment in &segments {
+            longest_pause_secs = longest_pause_secs.max(segment.start - spoken_until);
+            speech_secs += (segment.end - segment.start.max(spoken_until)).max(0.0);
+            spoken_until = spoken_until.max(segment.end);
+        }
+
+        let duration = response.duration.unwrap_or(spoken_until);
+        let words = response.text.as_deref().unwrap_or_default().split_whitespace().count();
+        Some(Self {
+            words,
+            words_per_minute: if speech_secs > 0.0 { round_ms(words as f64 * 60.0 / speech_secs) } else { 0.0 },
+            speech_secs: round_ms(speech_secs),
+            silence_secs: round_ms((duration - speech_secs).max(0.0)),
+            longest_pause_secs: round_ms(longest_pause_secs),
+        })
+    }
+
+    /// One line for notifications, e.g. "142 wpm · 12.3s speech · 3.1s silence · longest pause 1.2s"
+    pub fn summary(&self) -> String {
+        format!(
+            "{:.0} wpm · {:.1}s speech · {:.1}s silence · longest pause {:.1}s",
+            self.words_per_minute, self.speech_secs, self.silence_secs, self.longest_pause_secs
+        )
+    }
+}
+
+/// Round to milliseconds, so sums of segment times don't show float noise in the JSON
+fn round_ms(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+/// Resolves whether the speech statistics are added to the result notification:
+/// 1. WA_SPEECH_STATS env var
+/// 2. User config file `speech_stats`
+/// 3. Default to false
+pub fn stats_notification_setting() -> Resolved<bool> {
+    Layers::new("speech stats notification")
+        .env("WA_SPEECH_STATS", settings::flag)
+        .config(|c| c.speech_stats)
+        .or_default(false)
+}
+
+/// Extra line for the result notification of the transcript being output
+static FOOTER: Mutex<Option<String>> = Mutex::new(None);
+
+/// Footer for the result notification, if the last published transcript has one
+pub fn notification_footer() -> Option<String> {
+    FOOTER.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Hand a finished transcript to history and the requested reports
+/// Fills in the speech statistics first, so they are part of both
+/// Called before the text is output, so it can be recovered from history if that fails
+/// Failures are only logged
+pub fn publish(response: &mut TranscriptionResponse, backend: &str) {
+    response.stats = SpeechStats::from_response(response);
+    response.audio = response.audio.or_else(recording::last_levels);
+    if let Some(stats) = &response.stats {
+        debug!("Speech stats: {:?}", stats);
+        if stats_notification_setting().value {
+            *FOOTER.lock().unwrap_or_else(|e| e.into_inner()) = Some(stats.summary());
+        }
+    }
+
+    history::record(response, backend);
+    hooks::fire(hooks::Event::Transcript, &[
+        ("TEXT", response.text.as_deref().unwrap_or_default()),
+        ("LANGUAGE", response.language.as_deref().unwrap_or_default()),
+    ]);
+
+    let reports = reports();
+    // With the latency breakdown the JSON waits for the text to be output, to include it
+    if reports.json && !latency::breakdown_setting().value {
+        print_json(response);
+    }
+    if let Some(path) = &reports.srt {
+        if let Err(e) = write_srt(path, response) {
+            warn!("{:#}", e);
+        }
+    }
+}
+
+/// Add the latency breakdown once the text is output, and print the JSON report held back
+/// for it; does nothing with the breakdown off
+pub fn report_latency(response: &mut TranscriptionResponse) {
+    let Some(breakdown) = latency::breakdown() else {
+        return;
+    };
+    response.latency = Some(breakdown);
+    if reports().json {
+        print_json(response);
+    }
+}
+
+fn print_json(response: &TranscriptionResponse) {
+    match serde_json::to_string(response) {
+        Ok(json) => println!("{}", json),
+        Err(e) => warn!("Failed to serialize transcript: {}", e),
+    }
+}
+
+/// Write a transcript's segments as SRT subtitles
+pub fn write_srt(path: &Path, response: &TranscriptionResponse) -> Result<()> {
+    let text = response.text.as_deref().unwrap_or_default();
+    // Backends without timing still get one cue for the whole recording
+    let whole = [Segment {
+        start: 0.0,
+        end: response.duration.unwrap_or(0.0),
+        text: text.to_string(),
+        ..Default::default()
+    }];
+    let segments = if response.segments.is_empty() { &whole[..] } else { &response.segments };
+
+    std::fs::write(path, to_srt(segments))
+        .with_context(|| format!("Failed to write subtitles to {}", path.display()))?;
+    debug!("Wrote {} subtitle cues to {}", segments.len(), path.display());
+    Ok(())
+}
+
+/// Render segments as SubRip subtitles
+pub fn to_srt(segments: &[Segment]) -> String {
+    let mut srt = String::new();
+    for (index, segment) in segments.iter().filter(|s| !s.text.trim().is_empty()).enumerate() {
+        let _ = write!(
+            srt,
+            "{}\n{} --> {}\n{}\n\n",
+            index + 1,
+            srt_time(segment.start),
+            srt_time(segment.end),
+            segment.text.trim()
+        );
+    }
+    srt
+}
+
+/// Format seconds as an SRT timestamp (HH:MM:SS,mmm)
+fn srt_time(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: f64, end: f64, text: &str) -> Segment {
+        Segment { start, end, text: text.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn formats_srt_times() {
+        assert_eq!(srt_time(0.0), "00:00:00,000");
+        assert_eq!(srt_time(1.5), "00:00:01,500");
+        assert_eq!(srt_time(59.9996), "00:01:00,000");
+        assert_eq!(srt_time(61.042), "00:01:01,042");
+        assert_eq!(srt_time(3599.999), "00:59:59,999");
+        assert_eq!(srt_time(3600.0), "01:00:00,000");
+        assert_eq!(srt_time(36_000.0 + 125.25), "10:02:05,250");
+        // Timestamps before the start are clamped rather than wrapping
+        assert_eq!(srt_time(-0.4), "00:00:00,000");
+    }
+
+    #[test]
+    fn renders_segments_as_srt() {
+        let segments = [
+            segment(0.0, 2.5, " Hello there."),
+            segment(2.5, 3.0, "   "),
+            segment(3599.5, 3601.25, "Still talking "),
+        ];
+        assert_eq!(
+            to_srt(&segments),
+            "1\n00:00:00,000 --> 00:00:02,500\nHello there.\n\n\
+             2\n00:59:59,500 --> 01:00:01,250\nStill talking\n\n"
+        );
+        assert_eq!(to_srt(&[]), "");
+    }
+}
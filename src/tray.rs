@@ -1,26 +1,209 @@
-use anyhow::Result;
-use ksni::{menu::StandardItem, Handle, MenuItem, Tray, TrayService};
-use std::time::Duration;
-use tracing::{debug, info};
+use anyhow::{anyhow, Context, Result};
+use ksni::{menu::{StandardItem, SubMenu}, Handle, MenuItem, Tray, TrayService};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use crate::activation;
+use crate::activity::{self, Activity};
+use crate::config::{read_config, TrayIcons};
+use crate::feedback;
+use crate::models::{self, Model};
+use crate::shortcuts::{self, Binding};
+use crate::socket::{self, DaemonState, Job, JobState};
+use crate::status;
+
+/// The daemon's job list is fetched every this many status polls (about once a second)
+const JOB_POLL_TICKS: u32 = 5;
+
+/// systemd user unit installed by the NixOS and Home Manager modules, or one you add for a
+/// named instance (whisp-away-daemon-<name>.service)
+fn daemon_unit() -> String {
+    format!("whisp-away-daemon{}.service", crate::instance::suffix())
+}
+
+/// How long "Start Daemon" waits for the socket before offering to start it again
+const DAEMON_START_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long the daemon watcher waits before subscribing again
+const WATCH_RETRY: Duration = Duration::from_secs(2);
+
+/// Whether the daemon's state arrives over a `watch` subscription; meanwhile the status
+/// poller leaves the daemon's activity, jobs and model alone
+static WATCHING: AtomicBool = AtomicBool::new(false);
+
+/// What the indicator shows, derived from the recording, pause and daemon activity
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TrayState {
+    Idle,
+    Recording,
+    Transcribing,
+    Error,
+    Disabled,
+    /// The backend needs a daemon and none listens on the socket
+    NoDaemon,
+    /// The daemon runs with its models unloaded
+    Unloaded,
+    /// The daemon is loading a model
+    Loading,
+}
 
 /// Status information displayed by the tray
 #[derive(Debug, Clone)]
 struct TrayStatus {
     recording: bool,
+    /// The daemon records for a client (`daemon_recording`)
+    daemon_recording: bool,
+    /// Dictation paused with `whisp-away disable`
+    disabled: bool,
+    /// What the daemon is doing (transcribing, failed)
+    activity: Activity,
+    /// Icon overrides from the config file
+    icons: TrayIcons,
+    /// Latest notification, shown when there is no notification server
+    feedback: Option<String>,
+    /// The daemon's queued, running and recently finished jobs
+    jobs: Vec<Job>,
+    /// Nothing listens on the socket although the backend uses a daemon
+    daemon_down: bool,
+    /// When "Start Daemon" was clicked, until the socket comes up
+    daemon_starting: Option<Instant>,
+    /// Keys the compositor's config binds to whisp-away commands
+    compositor_bindings: Vec<Binding>,
+    /// The toggle shortcut registered through the portal, once it is bound
+    portal_bindings: Vec<Binding>,
     backend: String,
     model: String,
     acceleration: String,
+    /// The models on disk, scanned at start and when the config changes
+    models: Vec<Model>,
 }
 
 impl Default for TrayStatus {
     fn default() -> Self {
         Self {
             recording: false,
+            daemon_recording: false,
+            disabled: crate::pause::active().is_some(),
+            activity: activity::current(),
+            icons: configured_icons(),
+            feedback: None,
+            jobs: Vec::new(),
+            daemon_down: daemon_down(),
+            daemon_starting: None,
+            compositor_bindings: shortcuts::compositor_bindings(),
+            portal_bindings: Vec::new(),
             backend: crate::helpers::resolve_backend(),
             model: crate::helpers::resolve_model(),
             acceleration: crate::helpers::get_acceleration_type(),
+            models: models::scan(),
+        }
+    }
+}
+
+impl TrayStatus {
+    fn state(&self) -> TrayState {
+        if self.recording || self.daemon_recording {
+            TrayState::Recording
+        } else if self.activity == Activity::Loading {
+            TrayState::Loading
+        } else if self.activity == Activity::Transcribing {
+            TrayState::Transcribing
+        } else if self.activity == Activity::Error {
+            TrayState::Error
+        } else if self.disabled {
+            TrayState::Disabled
+        } else if self.daemon_down {
+            TrayState::NoDaemon
+        } else if self.activity == Activity::Unloaded {
+            TrayState::Unloaded
+        } else {
+            TrayState::Idle
         }
     }
+
+    /// Show what the daemon reports about itself, rather than what the tray's environment
+    /// and config say it should be running
+    fn show_daemon(&mut self, state: DaemonState) {
+        self.activity = state.state;
+        self.jobs = state.jobs;
+        self.backend = state.backend;
+        // whisper.cpp daemons report the model file: ggml-base.en.bin is shown as base.en
+        self.model = match Path::new(&state.default_model).file_stem() {
+            Some(stem) if state.default_model.contains('/') => {
+                let stem = stem.to_string_lossy();
+                stem.strip_prefix("ggml-").unwrap_or(&stem).to_string()
+            }
+            _ => state.default_model,
+        };
+        self.daemon_recording = state.recording;
+        self.daemon_down = false;
+        self.daemon_starting = None;
+    }
+}
+
+/// Whether the backend needs a daemon that isn't there (the API backend has none)
+/// A daemon systemd starts on demand isn't down while stopped, and connecting would start it
+fn daemon_down() -> bool {
+    crate::helpers::resolve_backend() != "openai"
+        && !activation::on_demand()
+        && !socket::daemon_listening(&crate::helpers::resolve_socket_path())
+}
+
+/// Start the daemon from the tray: through its systemd user unit when one is installed,
+/// otherwise as a child of the tray
+/// A daemon that exits while starting is reported by notification, as nobody watches the
+/// tray's output
+fn start_daemon() -> Result<()> {
+    let unit = daemon_unit();
+    if systemd_unit_installed(&unit) {
+        info!("Starting {}", unit);
+        let output = Command::new("systemctl")
+            .args(["--user", "start", &unit])
+            .output()
+            .context("Failed to run systemctl")?;
+        if !output.status.success() {
+            return Err(anyhow!("systemctl failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        return Ok(());
+    }
+
+    let exe = std::env::current_exe().context("Failed to locate whisp-away executable")?;
+    let mut child = Command::new(exe)
+        .arg("daemon")
+        .stdin(Stdio::null())
+        // Own process group, so it keeps running when the tray quits
+        .process_group(0)
+        .spawn()
+        .context("Failed to start the daemon")?;
+    info!("Daemon started (PID: {})", child.id());
+    let started = Instant::now();
+    // Reap it when it exits
+    std::thread::spawn(move || {
+        let status = child.wait();
+        warn!("Daemon exited: {:?}", status);
+        if started.elapsed() < DAEMON_START_TIMEOUT {
+            crate::helpers::send_notification("Voice Input", "❌ The daemon exited while starting\nRun `whisp-away daemon` to see why", 5000);
+        }
+    });
+    Ok(())
+}
+
+fn systemd_unit_installed(unit: &str) -> bool {
+    Command::new("systemctl")
+        .args(["--user", "list-unit-files", "--no-legend", unit])
+        .output()
+        .is_ok_and(|output| output.status.success() && !output.stdout.trim_ascii().is_empty())
+}
+
+/// Icon overrides from `[tray.icons]` in the config file
+fn configured_icons() -> TrayIcons {
+    read_config()
+        .and_then(|c| c.tray)
+        .and_then(|t| t.icons)
+        .unwrap_or_default()
 }
 
 #[derive(Debug)]
@@ -35,48 +218,199 @@ impl VoiceInputTray {
         }
     }
 
-    fn get_icon_name(&self) -> String {
-        if self.status.recording {
+    /// Configured icon for the current state: a theme icon name or a path to an image file
+    fn get_icon(&self) -> String {
+        let icons = &self.status.icons;
+        let (configured, default) = match self.status.state() {
             // Full/active microphone - recording in progress
-            "microphone-sensitivity-high-symbolic"
-        } else {
+            TrayState::Recording => (&icons.recording, "microphone-sensitivity-high-symbolic"),
+            TrayState::Transcribing => (&icons.transcribing, "emblem-synchronizing-symbolic"),
+            TrayState::Loading => (&icons.loading, "content-loading-symbolic"),
+            TrayState::Error => (&icons.error, "dialog-error-symbolic"),
+            // Crossed-out microphone - dictation paused
+            TrayState::Disabled => (&icons.disabled, "microphone-disabled-symbolic"),
+            TrayState::NoDaemon => (&icons.no_daemon, "dialog-warning-symbolic"),
+            TrayState::Unloaded => (&icons.unloaded, "system-suspend-symbolic"),
             // Empty/inactive microphone - not recording
-            "microphone-sensitivity-muted-symbolic"
-        }
-        .to_string()
+            TrayState::Idle => (&icons.idle, "microphone-sensitivity-muted-symbolic"),
+        };
+        configured.clone().unwrap_or_else(|| default.to_string())
     }
 
-    fn get_tooltip(&self) -> String {
-        let backend_display = match self.status.backend.as_str() {
-            "faster-whisper" => "Faster Whisper",
-            "whisper-cpp" => "Whisper.cpp",
-            other => other,
-        };
+    /// Icon name for the host; for image paths this is the file name without extension,
+    /// looked up in the directory returned by `get_icon_theme_path`
+    fn get_icon_name(&self) -> String {
+        let icon = self.get_icon();
+        if icon.contains('/') {
+            Path::new(&icon)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or(icon)
+        } else {
+            icon
+        }
+    }
 
-        if self.status.recording {
-            format!(
-                "Voice Input - 🎙️ Recording...\n\nBackend: {}\nModel: {}\nAcceleration: {}",
-                backend_display,
-                self.status.model,
-                self.status.acceleration.to_uppercase()
-            )
+    fn get_icon_theme_path(&self) -> String {
+        let icon = self.get_icon();
+        if icon.contains('/') {
+            Path::new(&icon)
+                .parent()
+                .map(|dir| dir.to_string_lossy().to_string())
+                .unwrap_or_default()
         } else {
-            format!(
-                "Voice Input - Ready\n\nBackend: {}\nModel: {}\nAcceleration: {}",
-                backend_display,
-                self.status.model,
-                self.status.acceleration.to_uppercase()
-            )
+            String::new()
         }
     }
+
+    fn get_state_label(&self) -> &'static str {
+        match self.status.state() {
+            TrayState::Recording => "🎙️ Recording...",
+            TrayState::Transcribing => "⏳ Transcribing...",
+            TrayState::Loading => "⏳ Loading model...",
+            TrayState::Error => "❌ Transcription failed",
+            TrayState::Disabled => "🔇 Disabled",
+            TrayState::NoDaemon => "⚠️ Daemon not running",
+            TrayState::Unloaded => "💤 Model unloaded",
+            TrayState::Idle => "Ready",
+        }
+    }
+
+    fn get_tooltip(&self) -> String {
+        let feedback = self.status.feedback.as_deref()
+            .map(|message| format!("{}\n\n", message))
+            .unwrap_or_default();
+        format!(
+            "Voice Input - {}\n\n{}Backend: {}\nModel: {}\nAcceleration: {}",
+            self.get_state_label(),
+            feedback,
+            self.get_backend_display(),
+            self.status.model,
+            self.status.acceleration.to_uppercase()
+        )
+    }
     
     fn get_backend_display(&self) -> &str {
         match self.status.backend.as_str() {
             "faster-whisper" => "Faster Whisper",
             "whisper-cpp" => "Whisper.cpp",
+            "openai" => "OpenAI API",
             other => other,
         }
     }
+    
+    /// The model in use, with the models on disk in a submenu: the backend's own first, then
+    /// those of the other format, which it can't load
+    fn models_menu(&self) -> MenuItem<Self> {
+        let label = format!("Model: {}", self.status.model);
+        if self.status.models.is_empty() {
+            return MenuItem::Standard(StandardItem { label, enabled: false, ..Default::default() });
+        }
+        let item = |model: &Model| {
+            let current = model.format.backend() == self.status.backend && model.name == self.status.model;
+            let name = if model.name.contains('/') && model.path.to_string_lossy() == model.name {
+                model.path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+            } else {
+                model.name.clone()
+            };
+            MenuItem::Standard(StandardItem {
+                label: format!("{} {} ({})", if current { "●" } else { "○" }, name, model.format.label()),
+                enabled: false,
+                ..Default::default()
+            })
+        };
+        let (own, other): (Vec<&Model>, Vec<&Model>) = self.status.models.iter()
+            .partition(|model| model.format.backend() == self.status.backend);
+        let mut submenu: Vec<MenuItem<Self>> = own.into_iter().map(item).collect();
+        if !other.is_empty() {
+            if !submenu.is_empty() {
+                submenu.push(MenuItem::Separator);
+            }
+            submenu.push(MenuItem::Standard(StandardItem {
+                label: format!("For {}:", other[0].format.backend()),
+                enabled: false,
+                ..Default::default()
+            }));
+            submenu.extend(other.into_iter().map(item));
+        }
+        MenuItem::SubMenu(SubMenu { label, submenu, ..Default::default() })
+    }
+    
+    /// Every hotkey that controls dictation, wherever it is bound
+    fn hotkey_items(&self) -> Vec<MenuItem<Self>> {
+        let bindings: Vec<&Binding> = self.status.portal_bindings.iter()
+            .chain(&self.status.compositor_bindings)
+            .collect();
+        if bindings.is_empty() {
+            return vec![MenuItem::Standard(StandardItem {
+                label: "Hotkey: none found".to_string(),
+                enabled: false,
+                ..Default::default()
+            })];
+        }
+        bindings.into_iter().map(|binding| MenuItem::Standard(StandardItem {
+            label: format!("Hotkey: {}", binding),
+            enabled: false,
+            ..Default::default()
+        })).collect()
+    }
+
+    /// Submenu listing the daemon's jobs, newest first; active ones cancel on click
+    fn jobs_menu(&self) -> MenuItem<Self> {
+        let active = self.status.jobs.iter().filter(|job| job.state.is_active()).count();
+        let submenu = self.status.jobs.iter().rev().map(|job| {
+            let id = job.id;
+            let label = if job.state.is_active() {
+                format!("{} - click to cancel", status::describe_job(job))
+            } else {
+                status::describe_job(job)
+            };
+            MenuItem::Standard(StandardItem {
+                label,
+                enabled: job.state.is_active(),
+                activate: Box::new(move |tray: &mut Self| cancel_job(tray, id)),
+                ..Default::default()
+            })
+        }).collect();
+        
+        MenuItem::SubMenu(SubMenu {
+            label: format!("Jobs ({} active)", active),
+            submenu,
+            ..Default::default()
+        })
+    }
+}
+
+/// Cancel a job from the jobs submenu, showing it cancelled until the next poll
+fn cancel_job(tray: &mut VoiceInputTray, id: u64) {
+    match socket::cancel_job(&crate::helpers::resolve_socket_path(), id) {
+        Ok(()) => {
+            if let Some(job) = tray.status.jobs.iter_mut().find(|job| job.id == id) {
+                job.state = JobState::Cancelled;
+            }
+        }
+        Err(e) => {
+            warn!("Failed to cancel job {}: {}", id, e);
+            crate::helpers::send_notification("Voice Input", &format!("❌ Could not cancel job {}", id), 2000);
+        }
+    }
+}
+
+/// Unload or load the daemon's models from the menu, off the menu's thread as loading takes
+/// a while; the daemon's activity file updates the tray
+fn set_models_loaded(load: bool) {
+    std::thread::spawn(move || {
+        let socket_path = crate::helpers::resolve_socket_path();
+        let result = if load { socket::load_models(&socket_path) } else { socket::unload_models(&socket_path) };
+        match result {
+            Ok(_) if load => crate::helpers::send_notification("Voice Input", "✅ Model loaded", 2000),
+            Ok(_) => crate::helpers::send_notification("Voice Input", "💤 Model unloaded\nDictation resumes after Load Model", 3000),
+            Err(e) => {
+                warn!("Failed to {} models: {:#}", if load { "load" } else { "unload" }, e);
+                crate::helpers::send_notification("Voice Input", &format!("❌ {:#}", e), 3000);
+            }
+        }
+    });
 }
 
 impl Tray for VoiceInputTray {
@@ -92,6 +426,10 @@ impl Tray for VoiceInputTray {
         self.get_icon_name()
     }
 
+    fn icon_theme_path(&self) -> String {
+        self.get_icon_theme_path()
+    }
+
     fn tool_tip(&self) -> ksni::ToolTip {
         ksni::ToolTip {
             title: self.get_tooltip(),
@@ -100,14 +438,20 @@ impl Tray for VoiceInputTray {
     }
 
     fn menu(&self) -> Vec<MenuItem<Self>> {
-        vec![
+        let mut menu = vec![
             // Recording status indicator
             MenuItem::Standard(StandardItem {
-                label: if self.status.recording {
-                    "🎙️ Recording in progress".to_string()
-                } else {
-                    "⏸️ Not recording".to_string()
-                },
+                label: match self.status.state() {
+                    TrayState::Recording => "🎙️ Recording in progress",
+                    TrayState::Transcribing => "⏳ Transcribing",
+                    TrayState::Loading => "⏳ Loading model",
+                    TrayState::Error => "❌ Last transcription failed",
+                    TrayState::Disabled => "🔇 Dictation disabled",
+                    TrayState::NoDaemon => "⚠️ Daemon not running",
+                    TrayState::Unloaded => "💤 Model unloaded",
+                    TrayState::Idle => "⏸️ Not recording",
+                }
+                .to_string(),
                 enabled: false,
                 ..Default::default()
             }),
@@ -118,16 +462,72 @@ impl Tray for VoiceInputTray {
                 enabled: false,
                 ..Default::default()
             }),
-            // Model info
+            self.models_menu(),
+            // Acceleration info
             MenuItem::Standard(StandardItem {
-                label: format!("Model: {}", self.status.model),
+                label: format!("Acceleration: {}", self.status.acceleration.to_uppercase()),
                 enabled: false,
                 ..Default::default()
             }),
-            // Acceleration info
+            MenuItem::Separator,
+        ];
+        menu.extend(self.hotkey_items());
+        menu.push(MenuItem::Separator);
+        
+        // Only while the backend's daemon is missing
+        if self.status.daemon_down {
+            let starting = self.status.daemon_starting.is_some();
+            menu.push(MenuItem::Standard(StandardItem {
+                label: if starting { "Starting Daemon..." } else { "Start Daemon" }.to_string(),
+                enabled: !starting,
+                activate: Box::new(|tray: &mut Self| match start_daemon() {
+                    Ok(()) => tray.status.daemon_starting = Some(Instant::now()),
+                    Err(e) => {
+                        warn!("Failed to start daemon: {:#}", e);
+                        crate::helpers::send_notification("Voice Input", &format!("❌ Could not start the daemon\n{:#}", e), 5000);
+                    }
+                }),
+                ..Default::default()
+            }));
+            menu.push(MenuItem::Separator);
+        }
+        
+        // Only with a daemon running: a stopped on-demand daemon holds no model anyway
+        if self.status.backend != "openai" && !self.status.daemon_down && !activation::asleep() {
+            let unloaded = self.status.activity == Activity::Unloaded;
+            menu.push(MenuItem::Standard(StandardItem {
+                label: if unloaded { "Load Model" } else { "Unload Model (free memory)" }.to_string(),
+                activate: Box::new(move |_tray: &mut Self| set_models_loaded(unloaded)),
+                ..Default::default()
+            }));
+            menu.push(MenuItem::Separator);
+        }
+        
+        // Only while the daemon has queued or recent jobs
+        if !self.status.jobs.is_empty() {
+            menu.push(self.jobs_menu());
+            menu.push(MenuItem::Separator);
+        }
+        
+        menu.extend([
+            // Pause toggle
             MenuItem::Standard(StandardItem {
-                label: format!("Acceleration: {}", self.status.acceleration.to_uppercase()),
-                enabled: false,
+                label: if self.status.disabled {
+                    "Enable Dictation".to_string()
+                } else {
+                    "Disable Dictation".to_string()
+                },
+                activate: Box::new(|tray: &mut Self| {
+                    let result = if tray.status.disabled {
+                        crate::pause::enable()
+                    } else {
+                        crate::pause::disable(None)
+                    };
+                    match result {
+                        Ok(()) => tray.status.disabled = !tray.status.disabled,
+                        Err(e) => warn!("Failed to toggle dictation: {}", e),
+                    }
+                }),
                 ..Default::default()
             }),
             MenuItem::Separator,
@@ -139,7 +539,8 @@ impl Tray for VoiceInputTray {
                 }),
                 ..Default::default()
             }),
-        ]
+        ]);
+        menu
     }
 }
 
@@ -147,21 +548,80 @@ impl Tray for VoiceInputTray {
 fn spawn_status_poller(handle: Handle<VoiceInputTray>) {
     std::thread::spawn(move || {
         let mut last_recording_state = false;
+        let mut last_disabled_state = crate::pause::active().is_some();
+        let mut last_activity = activity::current();
+        let mut last_feedback = None;
+        let mut last_jobs = Vec::new();
+        let mut last_daemon_down = daemon_down();
+        let mut tick: u32 = 0;
         info!("Polling thread started");
         
         loop {
             let is_recording = crate::recording::is_recording();
+            // Also catches pauses expiring and `disable`/`enable` run from a terminal
+            let is_disabled = crate::pause::active().is_some();
+            let watching = WATCHING.load(Ordering::SeqCst);
+            let current_activity = if watching { last_activity } else { activity::current() };
+            let current_feedback = feedback::current().map(|f| f.message);
+            
+            if current_feedback != last_feedback {
+                last_feedback = current_feedback.clone();
+                handle.update(|tray| tray.status.feedback = current_feedback);
+            }
+            
+            // Without a daemon (or with one that has no queue) the list stays empty
+            if tick.is_multiple_of(JOB_POLL_TICKS) && !watching {
+                let (current_jobs, is_daemon_down) = if activation::asleep() {
+                    // A daemon systemd starts on demand isn't woken up just to list its jobs
+                    (Vec::new(), false)
+                } else {
+                    match socket::jobs(&crate::helpers::resolve_socket_path()) {
+                        Ok(jobs) => (jobs, false),
+                        // Only connect without a request when the daemon didn't answer, so a
+                        // live one doesn't log an empty request every second
+                        Err(_) => (Vec::new(), daemon_down()),
+                    }
+                };
+                if current_jobs != last_jobs {
+                    last_jobs = current_jobs.clone();
+                    handle.update(|tray| tray.status.jobs = current_jobs);
+                }
+                if is_daemon_down != last_daemon_down {
+                    info!("Daemon {}", if is_daemon_down { "went away" } else { "is up" });
+                    last_daemon_down = is_daemon_down;
+                    handle.update(|tray| {
+                        tray.status.daemon_down = is_daemon_down;
+                        tray.status.daemon_starting = None;
+                    });
+                }
+                // Let the user try again, e.g. after fixing what stopped it
+                if is_daemon_down {
+                    handle.update(|tray| {
+                        if tray.status.daemon_starting.is_some_and(|since| since.elapsed() > DAEMON_START_TIMEOUT) {
+                            warn!("Daemon didn't come up within {}s", DAEMON_START_TIMEOUT.as_secs());
+                            tray.status.daemon_starting = None;
+                        }
+                    });
+                }
+            }
+            tick = tick.wrapping_add(1);
             
             // Only update when state changes to avoid unnecessary updates
-            if is_recording != last_recording_state {
-                info!("Recording state changed: {} -> {}", last_recording_state, is_recording);
+            if is_recording != last_recording_state || is_disabled != last_disabled_state || current_activity != last_activity {
+                info!("Recording state changed: {} -> {} (disabled: {}, daemon: {:?})", last_recording_state, is_recording, is_disabled, current_activity);
                 last_recording_state = is_recording;
+                last_disabled_state = is_disabled;
+                last_activity = current_activity;
                 
                 // Update the tray through the handle - this triggers a refresh
                 handle.update(|tray| {
                     tray.status.recording = is_recording;
-                    tray.status.backend = crate::helpers::resolve_backend();
-                    tray.status.model = crate::helpers::resolve_model();
+                    tray.status.disabled = is_disabled;
+                    if !watching {
+                        tray.status.activity = current_activity;
+                        tray.status.backend = crate::helpers::resolve_backend();
+                        tray.status.model = crate::helpers::resolve_model();
+                    }
                     tray.status.acceleration = crate::helpers::get_acceleration_type();
                     debug!("Tray updated: recording={}", is_recording);
                 });
@@ -173,6 +633,110 @@ fn spawn_status_poller(handle: Handle<VoiceInputTray>) {
     });
 }
 
+/// Spawns a background thread that subscribes to the daemon's state and shows it as it
+/// changes, so the tray follows the daemon's model and queue without polling
+/// Daemons from before `watch` are left to the status poller
+fn spawn_daemon_watcher(handle: Handle<VoiceInputTray>) {
+    std::thread::spawn(move || loop {
+        // A daemon systemd starts on demand isn't woken up just to be watched
+        if crate::helpers::resolve_backend() != "openai" && !activation::asleep() {
+            let result = socket::watch_daemon(&crate::helpers::resolve_socket_path(), |state| {
+                if !WATCHING.swap(true, Ordering::SeqCst) {
+                    info!("Watching the daemon's state");
+                }
+                handle.update(|tray| tray.status.show_daemon(state));
+            });
+            if WATCHING.swap(false, Ordering::SeqCst) {
+                info!("Stopped watching the daemon");
+                let (current_activity, is_daemon_down) = (activity::current(), daemon_down());
+                handle.update(|tray| {
+                    tray.status.activity = current_activity;
+                    tray.status.daemon_recording = false;
+                    tray.status.daemon_down = is_daemon_down;
+                    tray.status.jobs.clear();
+                });
+            }
+            match result {
+                Err(e) if e.is::<socket::WatchUnsupported>() => {
+                    info!("{}, polling it instead", e);
+                    return;
+                }
+                Err(e) => debug!("Not watching the daemon: {:#}", e),
+                Ok(()) => {}
+            }
+        }
+        std::thread::sleep(WATCH_RETRY);
+    });
+}
+
+/// Register the toggle shortcut with the GlobalShortcuts portal when one is configured,
+/// showing the keys it is bound to in the menu
+/// Desktops without the portal (most tiling compositors) keep their own binds, which the
+/// menu lists all the same
+fn spawn_portal_shortcut(handle: Handle<VoiceInputTray>) {
+    let Some(trigger) = shortcuts::preferred_trigger() else {
+        return;
+    };
+    std::thread::spawn(move || {
+        let on_bound = move |bindings| handle.update(|tray| tray.status.portal_bindings = bindings);
+        if let Err(e) = shortcuts::run_portal(&trigger, on_bound, run_toggle) {
+            warn!("Tray shortcut unavailable: {:#}", e);
+        }
+    });
+}
+
+/// What a press of the portal shortcut does: the same as a `whisp-away toggle` keybind
+fn run_toggle() {
+    let result = std::env::current_exe()
+        .context("Failed to locate whisp-away executable")
+        .and_then(|exe| {
+            Command::new(exe)
+                .arg("toggle")
+                .stdin(Stdio::null())
+                // Own process group, so the recording outlives a tray restart
+                .process_group(0)
+                .spawn()
+                .context("Failed to run whisp-away toggle")
+        });
+    match result {
+        // Reap it when it exits
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => warn!("{:#}", e),
+    }
+}
+
+/// Refresh the displayed settings whenever the user config file changes
+fn spawn_config_watcher(handle: Handle<VoiceInputTray>) -> Option<notify::RecommendedWatcher> {
+    let watcher = crate::config::watch_config(move |config| {
+        info!("Configuration reloaded");
+        let icons = config.tray.and_then(|t| t.icons).unwrap_or_default();
+        let compositor_bindings = shortcuts::compositor_bindings();
+        let models = models::scan();
+        handle.update(|tray| {
+            tray.status.icons = icons;
+            tray.status.compositor_bindings = compositor_bindings;
+            tray.status.models = models;
+            // A watched daemon reports its own backend and model once it has reloaded
+            if !WATCHING.load(Ordering::SeqCst) {
+                tray.status.backend = crate::helpers::resolve_backend();
+                tray.status.model = crate::helpers::resolve_model();
+            }
+            tray.status.acceleration = crate::helpers::get_acceleration_type();
+            debug!("Tray updated after config reload: model={}", tray.status.model);
+        });
+    });
+    
+    match watcher {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            warn!("Config hot-reload disabled: {}", e);
+            None
+        }
+    }
+}
+
 pub async fn run_tray(_daemon_type: String) -> Result<()> {
     info!("Starting tray indicator...");
     
@@ -190,7 +754,12 @@ pub async fn run_tray(_daemon_type: String) -> Result<()> {
     
     // Spawn the background polling thread
     info!("Spawning recording status polling thread...");
-    spawn_status_poller(handle);
+    spawn_status_poller(handle.clone());
+    spawn_daemon_watcher(handle.clone());
+    spawn_portal_shortcut(handle.clone());
+    
+    // Keep the watcher alive while the tray service runs
+    let _config_watcher = spawn_config_watcher(handle);
 
     // Run the tray service (this blocks)
     info!("Running tray service (this blocks)");
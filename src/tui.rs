@@ -0,0 +1,436 @@
+use anyhow::{Context, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, List, ListItem, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::debug;
+use crate::activity::{self, Activity};
+use crate::history::{self, Entry};
+use crate::models::{self, Model};
+use crate::pause::{self, PauseState};
+use crate::platform::{self, Platform};
+use crate::socket::{self, Job};
+use crate::{activation, audio, helpers, recording, remote, status};
+
+/// How often the screen is redrawn and the recording's level measured
+const TICK: Duration = Duration::from_millis(100);
+
+/// How often the daemon and the history are looked at again
+const REFRESH: Duration = Duration::from_secs(2);
+
+/// Transcripts listed, newest first
+const HISTORY_ENTRIES: usize = 20;
+
+/// Audio the level meter measures, from the end of the recording
+const METER_SECS: f64 = 0.1;
+
+/// Level at the left end of the meter
+const METER_FLOOR_DBFS: f32 = -60.0;
+
+/// How long a message about a key press stays on the bottom line
+const MESSAGE_SECS: u64 = 5;
+
+/// What the poller last heard from the daemon
+enum Daemon {
+    /// Not heard from yet
+    Unknown,
+    /// The API backend transcribes without one
+    None,
+    /// Stopped, started by systemd on the next transcription
+    Asleep,
+    Unavailable(String),
+    Up { status: serde_json::Value, jobs: Vec<Job> },
+}
+
+/// What the poller gathers off the screen's thread, as the daemon may be slow to answer
+struct Snapshot {
+    daemon: Daemon,
+    history: Vec<Entry>,
+}
+
+struct Dashboard {
+    backend: String,
+    /// The models on disk the backend can load, scanned at start
+    models: Vec<Model>,
+    /// The one picked for the dictations toggled here; None for the configured model
+    model: Option<usize>,
+    /// Since when the recording in progress has been seen
+    recording_since: Option<SystemTime>,
+    level: Option<f32>,
+    paused: Option<PauseState>,
+    activity: Activity,
+    snapshot: Snapshot,
+    player: Option<Child>,
+    message: Option<(String, Instant)>,
+}
+
+impl Dashboard {
+    fn new() -> Self {
+        let backend = helpers::resolve_backend();
+        let models = models::scan().into_iter()
+            .filter(|model| model.format.backend() == backend)
+            .collect();
+        Self {
+            backend,
+            models,
+            model: None,
+            recording_since: None,
+            level: None,
+            paused: None,
+            activity: Activity::Idle,
+            snapshot: Snapshot { daemon: Daemon::Unknown, history: Vec::new() },
+            player: None,
+            message: None,
+        }
+    }
+
+    /// The model dictations toggled here are transcribed with
+    fn model_name(&self) -> String {
+        match self.model {
+            Some(index) => self.models[index].name.clone(),
+            None => helpers::resolve_model(),
+        }
+    }
+
+    fn say(&mut self, message: impl Into<String>) {
+        self.message = Some((message.into(), Instant::now()));
+    }
+
+    /// Catch up with the recording, the pause and the daemon's activity, which are cheap to read
+    fn update(&mut self) {
+        let audio_file = recording::recording_file().filter(|_| recording::is_recording());
+        match &audio_file {
+            Some(file) => {
+                if self.recording_since.is_none() {
+                    // A recording started before the dashboard counts from its file
+                    self.recording_since = Some(std::fs::metadata(file)
+                        .and_then(|metadata| metadata.created())
+                        .unwrap_or_else(|_| SystemTime::now()));
+                }
+                self.level = audio::recent_level(file, METER_SECS).ok();
+            }
+            None => {
+                self.recording_since = None;
+                self.level = None;
+            }
+        }
+        self.paused = pause::active();
+        self.activity = activity::current();
+        if self.player.as_mut().is_some_and(|player| !matches!(player.try_wait(), Ok(None))) {
+            self.player = None;
+        }
+        if self.message.as_ref().is_some_and(|(_, since)| since.elapsed().as_secs() >= MESSAGE_SECS) {
+            self.message = None;
+        }
+    }
+
+    /// Start or stop a dictation like the hotkey, with the model picked here
+    fn toggle(&mut self) {
+        let mut args = vec!["toggle".to_string()];
+        if self.model.is_some() {
+            args.extend(["--model".to_string(), self.model_name()]);
+        }
+        let result = std::env::current_exe()
+            .context("Failed to locate whisp-away executable")
+            .and_then(|exe| {
+                Command::new(exe)
+                    .args(&args)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    // Own process group, so the recording outlives the dashboard
+                    .process_group(0)
+                    .spawn()
+                    .context("Failed to run whisp-away toggle")
+            });
+        match result {
+            // Reap it when it exits
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+            }
+            Err(e) => self.say(format!("{:#}", e)),
+        }
+    }
+
+    /// Go on to the next model on disk, and from the last back to the configured one
+    fn next_model(&mut self) {
+        if self.models.is_empty() {
+            self.say(format!("No {} models found on disk (`whisp-away model list`)", self.backend));
+            return;
+        }
+        self.model = match self.model {
+            None => Some(0),
+            Some(index) if index + 1 < self.models.len() => Some(index + 1),
+            Some(_) => None,
+        };
+        let message = match self.model {
+            Some(_) => format!("Dictations toggled here use {}", self.model_name()),
+            None => format!("Dictations toggled here use the configured model ({})", self.model_name()),
+        };
+        self.say(message);
+    }
+
+    /// Play the copy of the last recording, or stop it playing
+    fn replay(&mut self) {
+        if let Some(mut player) = self.player.take() {
+            let _ = player.kill();
+            let _ = player.wait();
+            return;
+        }
+        let last = recording::last_recording_file();
+        if !std::path::Path::new(&last).exists() {
+            let message = if recording::keep_last_setting().value {
+                "No recording to replay yet"
+            } else {
+                "Recordings aren't kept; set keep_last_recording = true to replay them"
+            };
+            self.say(message);
+            return;
+        }
+        match platform::Current::spawn_player(&last) {
+            Ok(player) => self.player = Some(player),
+            Err(e) => self.say(format!("{:#}", e)),
+        }
+    }
+}
+
+/// `whisp-away tui`: a full-screen dashboard of the recording, the daemon and recent
+/// transcripts, with keys to toggle recording, pick a model and replay the last recording
+pub fn run() -> Result<()> {
+    let snapshots = spawn_poller();
+    let mut terminal = ratatui::init();
+    let result = run_dashboard(&mut terminal, snapshots);
+    ratatui::restore();
+    result
+}
+
+fn run_dashboard(terminal: &mut DefaultTerminal, snapshots: Receiver<Snapshot>) -> Result<()> {
+    let mut dashboard = Dashboard::new();
+    loop {
+        if let Some(snapshot) = snapshots.try_iter().last() {
+            dashboard.snapshot = snapshot;
+        }
+        dashboard.update();
+        terminal.draw(|frame| draw(frame, &dashboard)).context("Failed to draw the dashboard")?;
+
+        if !event::poll(TICK)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => break,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+            KeyCode::Char(' ') | KeyCode::Char('r') => dashboard.toggle(),
+            KeyCode::Char('m') => dashboard.next_model(),
+            KeyCode::Char('p') => dashboard.replay(),
+            _ => {}
+        }
+    }
+    if let Some(mut player) = dashboard.player.take() {
+        let _ = player.kill();
+        let _ = player.wait();
+    }
+    Ok(())
+}
+
+/// Ask the daemon about its models and jobs and read the history every REFRESH, until the
+/// dashboard is closed
+fn spawn_poller() -> Receiver<Snapshot> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || loop {
+        let history = history::read_recent(HISTORY_ENTRIES, &history::Filter::default()).unwrap_or_else(|e| {
+            debug!("Failed to read the history: {:#}", e);
+            Vec::new()
+        });
+        let snapshot = Snapshot { daemon: poll_daemon(), history };
+        if sender.send(snapshot).is_err() {
+            return;
+        }
+        std::thread::sleep(REFRESH);
+    });
+    receiver
+}
+
+fn poll_daemon() -> Daemon {
+    if helpers::resolve_backend() == "openai" {
+        return Daemon::None;
+    }
+    // A daemon systemd starts on demand isn't woken up just to be shown
+    if activation::asleep() {
+        return Daemon::Asleep;
+    }
+    let socket_path = helpers::resolve_socket_path();
+    match socket::daemon_status(&socket_path) {
+        Ok(status) => Daemon::Up { status, jobs: socket::jobs(&socket_path).unwrap_or_default() },
+        Err(e) => Daemon::Unavailable(format!("{:#}", e)),
+    }
+}
+
+fn draw(frame: &mut Frame, dashboard: &Dashboard) {
+    let [state_area, meter_area, main_area, keys_area] = Layout::vertical([
+        Constraint::Length(5),
+        Constraint::Length(3),
+        Constraint::Min(6),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+    let [history_area, daemon_area] = Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .areas(main_area);
+
+    frame.render_widget(Paragraph::new(state_lines(dashboard)).block(Block::bordered().title(" whisp-away ")), state_area);
+    frame.render_widget(meter(dashboard.recording_since.is_some(), dashboard.level), meter_area);
+    frame.render_widget(history_list(&dashboard.snapshot.history), history_area);
+    frame.render_widget(
+        Paragraph::new(daemon_lines(&dashboard.snapshot.daemon, &dashboard.snapshot.history))
+            .block(Block::bordered().title(" Daemon "))
+            .wrap(Wrap { trim: false }),
+        daemon_area,
+    );
+
+    let keys = "space/r toggle recording · m next model · p replay last recording · q quit";
+    let bottom = match &dashboard.message {
+        Some((message, _)) => Line::from(Span::styled(message.as_str(), Style::default().fg(Color::Yellow))),
+        None => Line::from(Span::styled(keys, Style::default().fg(Color::DarkGray))),
+    };
+    frame.render_widget(Paragraph::new(bottom), keys_area);
+}
+
+fn state_lines(dashboard: &Dashboard) -> Vec<Line<'_>> {
+    let bold = |text: String, color: Color| Span::styled(text, Style::default().fg(color).add_modifier(Modifier::BOLD));
+    let state = match (&dashboard.recording_since, &dashboard.paused, dashboard.activity) {
+        (Some(since), _, _) => {
+            let secs = since.elapsed().unwrap_or_default().as_secs();
+            bold(format!("● Recording {}:{:02}", secs / 60, secs % 60), Color::Red)
+        }
+        (None, Some(pause), _) => bold(format!("Dictation disabled {}", pause.describe()), Color::DarkGray),
+        (None, None, Activity::Transcribing) => bold("⏳ Transcribing".to_string(), Color::Yellow),
+        (None, None, Activity::Loading) => bold("⏳ Loading model".to_string(), Color::Yellow),
+        (None, None, Activity::Error) => bold("❌ Transcription failed".to_string(), Color::Red),
+        (None, None, Activity::Unloaded) => bold("Models unloaded".to_string(), Color::DarkGray),
+        (None, None, Activity::Idle) => bold("Idle".to_string(), Color::Green),
+    };
+    let model = match dashboard.model {
+        Some(_) => format!("{} (picked here)", dashboard.model_name()),
+        None => dashboard.model_name(),
+    };
+    let playing = if dashboard.player.is_some() { "  ▶ replaying the last recording" } else { "" };
+    vec![
+        Line::from(vec![state, Span::raw(playing)]),
+        Line::from(format!("Backend: {}   Model: {}", dashboard.backend, model)),
+        Line::from(format!("Language: {}   Task: {}", helpers::resolve_language(), helpers::resolve_task())),
+    ]
+}
+
+/// The recording's level, green up to -20 dBFS, yellow to -6 and red above, where it may clip
+fn meter(recording: bool, level: Option<f32>) -> Gauge<'static> {
+    let block = Block::bordered().title(" Level ");
+    let Some(level) = level else {
+        // Recorders that only write the file when stopped can't be measured
+        let label = if recording { "no audio written yet" } else { "not recording" };
+        return Gauge::default().block(block).ratio(0.0).label(label);
+    };
+    let color = match level {
+        level if level > -6.0 => Color::Red,
+        level if level > -20.0 => Color::Yellow,
+        _ => Color::Green,
+    };
+    let ratio = ((level - METER_FLOOR_DBFS) / -METER_FLOOR_DBFS).clamp(0.0, 1.0) as f64;
+    Gauge::default()
+        .block(block)
+        .gauge_style(Style::default().fg(color))
+        .ratio(ratio)
+        .label(format!("{:.0} dBFS", level))
+}
+
+fn history_list(history: &[Entry]) -> List<'_> {
+    let block = Block::bordered().title(" Recent transcripts ");
+    if history.is_empty() {
+        return List::new([ListItem::new("No transcripts yet")]).block(block);
+    }
+    let items = history.iter().rev().map(|entry| {
+        let response = &entry.response;
+        let mut details = vec![
+            helpers::format_local_time(entry.timestamp as libc::time_t, "%H:%M"),
+            response.model.clone().unwrap_or_else(|| entry.backend.clone()),
+        ];
+        if let Some(duration) = response.duration {
+            details.push(format!("{:.1}s", duration));
+        }
+        if let Some(app) = &entry.app {
+            details.push(app.clone());
+        }
+        ListItem::new(vec![
+            Line::from(Span::styled(details.join("  "), Style::default().fg(Color::DarkGray))),
+            Line::from(response.text.clone().unwrap_or_default()),
+        ])
+    });
+    List::new(items).block(block)
+}
+
+fn daemon_lines<'a>(daemon: &'a Daemon, history: &[Entry]) -> Vec<Line<'a>> {
+    let mut lines = match daemon {
+        Daemon::Unknown => vec![Line::from("Connecting...")],
+        Daemon::None => vec![Line::from("The openai backend transcribes through the API")],
+        Daemon::Asleep => vec![Line::from("Stopped, started by systemd on the next transcription")],
+        Daemon::Unavailable(e) => vec![Line::from(format!("Not available: {}", e))],
+        Daemon::Up { status, jobs } => up_lines(status, jobs),
+    };
+
+    // What the recent transcripts took
+    let timed: Vec<(f64, f64)> = history.iter()
+        .filter_map(|entry| Some((entry.response.inference_secs?, entry.response.rtf?)))
+        .collect();
+    if let Some((inference, rtf)) = timed.last() {
+        let mean_rtf = timed.iter().map(|(_, rtf)| rtf).sum::<f64>() / timed.len() as f64;
+        lines.push(Line::from(""));
+        lines.push(Line::from(format!("Last:      {:.2}s inference, RTF {:.2}", inference, rtf)));
+        lines.push(Line::from(format!("Recent:    RTF {:.2} on average of the last {}", mean_rtf, timed.len())));
+    }
+    lines
+}
+
+fn up_lines<'a>(daemon_status: &serde_json::Value, jobs: &[Job]) -> Vec<Line<'a>> {
+    let field = |name: &str| daemon_status.get(name).and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
+    let number = |value: &serde_json::Value, name: &str| value.get(name).and_then(|v| v.as_u64()).unwrap_or(0);
+    let location = remote::address_setting().value
+        .map_or_else(helpers::resolve_socket_path, |address| address.to_string());
+    let mut lines = vec![
+        Line::from(format!("{} on {}", field("backend"), location)),
+        Line::from(format!("Default:   {}", field("default_model"))),
+    ];
+
+    if daemon_status.get("unloaded").and_then(|v| v.as_bool()).unwrap_or(false) {
+        lines.push(Line::from("Models:    unloaded"));
+    } else {
+        let models = daemon_status.get("models").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+        let resident: u64 = models.iter().map(|model| number(model, "size_mb")).sum();
+        lines.push(Line::from(format!(
+            "Models:    {} loaded, {} / {} MB",
+            models.len(), resident, number(daemon_status, "memory_limit_mb")
+        )));
+        for model in &models {
+            lines.push(Line::from(format!(
+                "  {} {} MB, idle {}s",
+                model.get("name").and_then(|v| v.as_str()).unwrap_or("?"),
+                number(model, "size_mb"),
+                number(model, "idle_secs")
+            )));
+        }
+    }
+
+    let active = jobs.iter().filter(|job| job.state.is_active()).count();
+    lines.push(Line::from(format!("Jobs:      {} active, {} recent", active, jobs.len() - active)));
+    // Newest first
+    lines.extend(jobs.iter().rev().map(|job| Line::from(format!("  {}", status::describe_job(job)))));
+    lines
+}
@@ -1,23 +1,160 @@
-use anyhow::{Context, Result};
-use std::process::{Command, Stdio};
-use std::io::Write;
-use tracing::debug;
+use anyhow::Result;
+use std::str::FromStr;
+use tracing::{debug, warn};
+use crate::clipboard;
+use crate::commands;
+use crate::delta;
+use crate::feedback;
+use crate::focus;
 use crate::helpers;
+use crate::keyboard;
+use crate::latency;
+use crate::line_breaks;
+use crate::platform::{self, Platform};
+use crate::postprocess;
+use crate::privacy;
+use crate::settings::{self, Layers, Resolved};
+use crate::sinks;
+use crate::terminal;
+use crate::transcript;
+
+/// Where transcribed text is delivered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    /// Type (or paste, depending on the keyboard layout) at the cursor
+    Type,
+    /// Copy to the clipboard
+    Clipboard,
+    /// Pipe to an executable in ~/.config/whisp-away/sinks/
+    Plugin(String),
+    /// Write a line to a named pipe (see `sinks::fifo_path_setting`)
+    Fifo,
+    /// Append to the file of a `[files.<name>]` config section (see `sinks::append_file`)
+    File(String),
+}
+
+impl Output {
+    pub fn from_use_clipboard(use_clipboard: bool) -> Self {
+        if use_clipboard { Output::Clipboard } else { Output::Type }
+    }
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    /// Accepts "type", "clipboard", "fifo", "plugin:<name>" or "file:<name>"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "type" => Ok(Output::Type),
+            "clipboard" => Ok(Output::Clipboard),
+            "fifo" => Ok(Output::Fifo),
+            _ => match (s.strip_prefix("plugin:"), s.strip_prefix("file:")) {
+                (Some(name), _) if !name.is_empty() => Ok(Output::Plugin(name.to_string())),
+                (_, Some(name)) if !name.is_empty() => Ok(Output::File(name.to_string())),
+                _ => Err(format!("invalid output '{}' (expected type, clipboard, fifo, plugin:<name> or file:<name>)", s)),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Output::Type => write!(f, "type"),
+            Output::Clipboard => write!(f, "clipboard"),
+            Output::Plugin(name) => write!(f, "plugin:{}", name),
+            Output::Fifo => write!(f, "fifo"),
+            Output::File(name) => write!(f, "file:{}", name),
+        }
+    }
+}
+
+/// Characters of the transcript shown in the result notification with `notifications.preview`
+const PREVIEW_CHARS: usize = 80;
+
+/// The start of the transcript, cut at a word boundary
+fn preview(text: &str) -> String {
+    if text.chars().count() <= PREVIEW_CHARS {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(PREVIEW_CHARS).collect();
+    let cut = cut.rsplit_once(' ').map_or(cut.as_str(), |(words, _)| words);
+    format!("{}…", cut.trim_end())
+}
+
+/// Result notification, with the transcript preview and speech statistics when enabled
+/// (shown longer to be readable)
+pub fn notify_done(message: &str, text: &str, backend_name: &str) {
+    latency::typing_finished();
+    // Notification servers keep their own history, which privacy mode can't reach
+    let show_preview = feedback::preview_setting().value && !privacy::enabled();
+    let footer = transcript::notification_footer();
+    let breakdown = latency::breakdown();
+
+    let mut body = message.to_string();
+    if show_preview {
+        body.push_str(&format!("\n“{}”", preview(text)));
+    }
+    body.push_str(&format!("\nBackend: {}", backend_name));
+    if let Some(footer) = &footer {
+        body.push_str(&format!("\n📊 {}", footer));
+    }
+    if let Some(breakdown) = &breakdown {
+        body.push_str(&format!("\n⏱️ {}", breakdown.summary()));
+    }
+    let timeout = if show_preview || footer.is_some() || breakdown.is_some() { 4000 } else { 1000 };
+    helpers::send_notification("Voice Input", &body, timeout);
+}
 
 /// Normalize text by collapsing multiple whitespace characters into single spaces
+/// With line breaks on, the lines they start are kept
 fn normalize_whitespace(text: &str) -> String {
-    text.split_whitespace().collect::<Vec<_>>().join(" ")
+    let collapse = |line: &str| line.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !line_breaks::enabled() {
+        return collapse(text);
+    }
+    text.lines().map(collapse).filter(|line| !line.is_empty()).collect::<Vec<_>>().join("\n")
 }
 
-/// Output transcribed text to clipboard or type at cursor
-pub fn output_text(text: &str, use_clipboard: bool, backend_name: &str) -> Result<()> {
-    debug!("output_text called: text='{}', use_clipboard={}, backend={}", 
-           if text.len() > 50 { &text[..50] } else { text },
-           use_clipboard, backend_name);
+/// Output transcribed text to clipboard, a sink plugin, a file, a FIFO, or type at cursor
+pub fn output_text(text: &str, output: &Output, backend_name: &str) -> Result<()> {
+    debug!("output_text called: text='{}', output={:?}, backend={}", 
+           privacy::redact(if text.len() > 50 { &text[..50] } else { text }),
+           output, backend_name);
     
     // Normalize whitespace: collapse multiple spaces into single space
     let normalized_text = normalize_whitespace(text);
     
+    // A transcription that finishes after the screen was locked would be typed into the
+    // lock screen's password field; it stays in history instead, or on the clipboard in
+    // privacy mode, which keeps no history
+    if *output == Output::Type && !normalized_text.is_empty() && screen_locked() {
+        let message = if !privacy::enabled() {
+            "🔒 Screen is locked, transcript not typed"
+        } else {
+            match clipboard::copy(&normalized_text) {
+                Ok(()) => "🔒 Screen is locked, transcript copied to clipboard",
+                Err(e) => {
+                    warn!("Failed to copy the transcript while the screen is locked: {:#}", e);
+                    "🔒 Screen is locked, transcript lost (privacy mode keeps no history)"
+                }
+            }
+        };
+        helpers::send_notification("Voice Input", &format!("{}\nBackend: {}", message, backend_name), 3000);
+        return Ok(());
+    }
+    
+    // "Scratch that" backspaces over the previous dictation, then the rest is typed
+    let normalized_text = if *output == Output::Type && !normalized_text.is_empty() {
+        let rest = commands::interpret(&normalized_text)?;
+        if rest.is_empty() {
+            return Ok(());
+        }
+        rest
+    } else {
+        normalized_text
+    };
+    
     if normalized_text.is_empty() {
         debug!("No speech detected (empty text received)");
         helpers::send_notification(
@@ -28,107 +165,408 @@ pub fn output_text(text: &str, use_clipboard: bool, backend_name: &str) -> Resul
         return Ok(());
     }
 
-    if use_clipboard {
+    // The next dictation starts a word of its own
+    let normalized_text = if matches!(output, Output::Type | Output::Clipboard)
+        && postprocess::ending_setting().value == postprocess::Ending::Space
+    {
+        normalized_text + " "
+    } else {
+        normalized_text
+    };
+
+    latency::typing_started();
+    if let Output::Plugin(name) = output {
+        debug!("Sending to sink '{}' ({} chars)", name, normalized_text.len());
+        if let Err(e) = sinks::run_sink(name, &normalized_text, backend_name) {
+            helpers::send_notification(
+                "Voice Input",
+                &format!("❌ Output plugin '{}' failed\nBackend: {}", name, backend_name),
+                3000
+            );
+            return Err(e);
+        }
+        
+        notify_done(&format!("✅ Sent to {}", name), &normalized_text, backend_name);
+    } else if let Output::File(name) = output {
+        match sinks::append_file(name, &normalized_text, backend_name) {
+            Ok(path) => notify_done(&format!("✅ Added to {}", path.display()), &normalized_text, backend_name),
+            Err(e) => {
+                helpers::send_notification(
+                    "Voice Input",
+                    &format!("❌ File output '{}' failed: {:#}\nBackend: {}", name, e, backend_name),
+                    3000
+                );
+                return Err(e);
+            }
+        }
+    } else if *output == Output::Fifo {
+        match sinks::write_fifo(&normalized_text) {
+            Ok(path) => notify_done(&format!("✅ Written to {}", path), &normalized_text, backend_name),
+            Err(e) => {
+                helpers::send_notification(
+                    "Voice Input",
+                    &format!("❌ {:#}
+Backend: {}", e, backend_name),
+                    3000
+                );
+                return Err(e);
+            }
+        }
+    } else if *output == Output::Clipboard {
         debug!("Copying to clipboard ({} chars)", normalized_text.len());
-        copy_to_clipboard(&normalized_text)?;
+        clipboard::copy(&normalized_text)?;
         
-        helpers::send_notification(
-            "Voice Input",
-            &format!("✅ Copied to clipboard\nBackend: {}", backend_name),
-            1000
+        notify_done("✅ Copied to clipboard", &normalized_text, backend_name);
+    } else if too_long_to_type(&normalized_text) {
+        // A long recording typed into a chat box would be sent line by line
+        clipboard::copy(&normalized_text)?;
+        notify_done(
+            &format!("⚠️ Too long to type ({} characters), copied to clipboard", normalized_text.chars().count()),
+            &normalized_text,
+            backend_name,
         );
+    } else if !focus::wait_for_target() {
+        // Typing now would land in whatever window the user switched to
+        clipboard::copy(&normalized_text)?;
+        notify_done("⚠️ Target window not focused, copied to clipboard", &normalized_text, backend_name);
+    } else if let Some(handling) = terminal::handling() {
+        let text = terminal::sanitize(&normalized_text, handling == terminal::Handling::Paste);
+        debug!("Outputting into a terminal with {:?} ({} chars)", handling, text.len());
+        match handling {
+            terminal::Handling::Paste => paste_in_terminal(&text, backend_name)?,
+            terminal::Handling::Type => type_at_cursor(&text, backend_name)?,
+        }
+        commands::remember_typed(&text);
+    } else if should_paste() {
+        debug!("Pasting at cursor ({} chars)", normalized_text.len());
+        paste_at_cursor(&normalized_text, backend_name)?;
+        commands::remember_typed(&normalized_text);
     } else {
         debug!("Typing at cursor ({} chars)", normalized_text.len());
         // Small delay before typing
         std::thread::sleep(std::time::Duration::from_millis(30));
         
         type_at_cursor(&normalized_text, backend_name)?;
+        commands::remember_typed(&normalized_text);
     }
 
     Ok(())
 }
 
-/// Type text at cursor using wtype (Wayland) or xdotool (X11)
-fn type_at_cursor(text: &str, backend_name: &str) -> Result<()> {
-    debug!("Attempting to type at cursor using wtype (Wayland)");
-    
-    // Try wtype first (Wayland)
-    let wtype_result = Command::new("wtype")
-        .arg(text)
-        .spawn()
-        .and_then(|mut child| child.wait());
-    
-    if let Ok(status) = wtype_result {
-        if status.success() {
-            debug!("Successfully typed using wtype");
-            helpers::send_notification(
-                "Voice Input",
-                &format!("✅ Transcribed\nBackend: {}", backend_name),
-                1000
-            );
-            return Ok(());
-        }
-        debug!("wtype failed with status: {}", status);
+/// Resolves whether daemon transcripts are typed segment by segment as they are decoded with priority:
+/// 1. WA_STREAM_SEGMENTS env var
+/// 2. User config file `stream_segments`
+/// 3. Default to true
+pub fn stream_setting() -> Resolved<bool> {
+    Layers::new("stream segments")
+        .env("WA_STREAM_SEGMENTS", settings::flag)
+        .config(|c| c.stream_segments)
+        .or_default(true)
+}
+
+/// Resolves how many characters may be typed at the cursor in one go with priority:
+/// 1. WA_MAX_TYPED_CHARS env var
+/// 2. User config file `max_typed_chars`
+/// 3. Default to 5000; longer transcripts are copied to the clipboard instead, 0 removes the limit
+pub fn max_typed_chars_setting() -> Resolved<usize> {
+    Layers::new("max typed chars")
+        .env("WA_MAX_TYPED_CHARS", settings::parsed)
+        .config(|c| c.max_typed_chars)
+        .or_default(5000)
+}
+
+fn too_long_to_type(text: &str) -> bool {
+    let limit = max_typed_chars_setting().value;
+    let too_long = limit > 0 && text.chars().count() > limit;
+    if too_long {
+        warn!("Transcript is longer than {} characters, copying it instead of typing", limit);
     }
-    
-    debug!("Falling back to xdotool (X11)");
-    
-    // Fallback to xdotool (X11)
-    Command::new("xdotool")
-        .args(["type", "--clearmodifiers", "--", text])
-        .spawn()
-        .context("Failed to run typing command (tried wtype and xdotool)")?
-        .wait()?;
-    
-    debug!("Successfully typed using xdotool");
-    helpers::send_notification(
-        "Voice Input",
-        &format!("✅ Transcribed\nBackend: {}", backend_name),
-        1000
-    );
-    
-    Ok(())
+    too_long
 }
 
-/// Copy text to clipboard using wl-copy (Wayland) or xclip (X11)
-fn copy_to_clipboard(text: &str) -> Result<()> {
-    // Try wl-copy first (Wayland)
-    let wl_copy_result = Command::new("wl-copy")
-        .stdin(Stdio::piped())
-        .spawn()
-        .and_then(|mut child| {
-            if let Some(mut stdin) = child.stdin.take() {
-                stdin.write_all(text.as_bytes())?;
-                drop(stdin);
-            }
-            child.wait()
+/// Types a transcript piece by piece while the rest is still being transcribed
+/// The pieces are joined by spaces, as `output_text` would join them
+#[derive(Default)]
+pub struct StreamTyper {
+    typed: String,
+    /// Pieces held back once the transcript grew past `max_typed_chars`, copied at the end
+    overflow: String,
+    /// A piece scratched an earlier dictation
+    scratched: bool,
+    /// Whether a terminal is focused (and how to output into it), then whether to paste
+    /// rather than type; both are checked once
+    method: Option<(Option<terminal::Handling>, bool)>,
+}
+
+impl StreamTyper {
+    /// Type the next piece at the cursor
+    pub fn push(&mut self, text: &str) -> Result<()> {
+        let mut text = normalize_whitespace(text);
+        // Until something is typed, pieces may start with "scratch that"
+        if self.typed.is_empty() && !text.is_empty() {
+            let rest = commands::interpret(&text)?;
+            self.scratched |= rest.len() != text.len();
+            text = rest;
+        }
+        if text.is_empty() {
+            return Ok(());
+        }
+        // Neither the focused window nor the layout changes mid-transcript
+        let (terminal, paste) = *self.method.get_or_insert_with(|| {
+            let terminal = terminal::handling();
+            (terminal, terminal.is_none() && should_paste())
         });
-    
-    if let Ok(status) = wl_copy_result {
-        if status.success() {
+        let text = match terminal {
+            Some(handling) => terminal::sanitize(&text, handling == terminal::Handling::Paste),
+            None => text,
+        };
+        let piece = if self.typed.is_empty() { text } else { format!(" {}", text) };
+        if !self.overflow.is_empty() || too_long_to_type(&format!("{}{}", self.typed, piece)) {
+            self.overflow.push_str(&piece);
             return Ok(());
         }
+        latency::typing_started();
+        let result = insert(&piece, terminal, paste);
+        latency::typing_finished();
+        result?;
+        debug!("Typed segment ({} chars)", piece.len());
+        self.typed.push_str(&piece);
+        Ok(())
     }
-    
-    // Fallback to xclip (X11)
-    let mut child = Command::new("xclip")
-        .args(&["-selection", "clipboard"])
-        .stdin(Stdio::piped())
-        .spawn()
-        .context("Failed to run clipboard command (tried wl-copy and xclip)")?;
-    
-    if let Some(mut stdin) = child.stdin.take() {
-        stdin.write_all(text.as_bytes())?;
-        drop(stdin);
+
+    /// Bring what was typed in line with the whole transcript, which can come out different
+    /// from its segments (a dictionary phrase spanning two of them, a segment the filter drops
+    /// once it sees the rest), retyping only from the first difference
+    pub fn correct(&mut self, transcript: &str) -> Result<()> {
+        // Held-back text is copied as it is, and terminals get sanitized text
+        if self.typed.is_empty() || !self.overflow.is_empty() || !matches!(self.method, Some((None, _))) {
+            return Ok(());
+        }
+        let mut target = normalize_whitespace(transcript);
+        // A leading "scratch that" was acted on, not typed
+        if self.scratched {
+            target = commands::leading_scratches(&target).1.to_string();
+        }
+        if target.is_empty() || target == self.typed || too_long_to_type(&target) {
+            return Ok(());
+        }
+        let paste = self.method.is_some_and(|(_, paste)| paste);
+        apply(&delta::diff(&self.typed, &target), None, paste)?;
+        self.typed = target;
+        Ok(())
+    }
+
+    /// Type a space after the transcript (`ending = "space"`); text held back for the
+    /// clipboard is left as it is
+    pub fn end_with_space(&mut self) -> Result<()> {
+        if self.typed.is_empty() || !self.overflow.is_empty() || self.typed.ends_with(' ') {
+            return Ok(());
+        }
+        let (terminal, paste) = self.method.unwrap_or_default();
+        insert(" ", terminal, paste)?;
+        self.typed.push(' ');
+        Ok(())
+    }
+
+    /// Everything typed so far
+    pub fn typed(&self) -> &str {
+        &self.typed
+    }
+
+    /// The whole transcript: what was typed and what was held back
+    pub fn text(&self) -> String {
+        format!("{}{}", self.typed, self.overflow).trim_start().to_string()
+    }
+
+    /// Whether the transcript was acted on: typed, or a spoken command that scratched
+    pub fn started(&self) -> bool {
+        !self.typed.is_empty() || !self.overflow.is_empty() || self.scratched
+    }
+
+    /// Result notification once the last piece is typed
+    pub fn finish(&self, backend_name: &str) {
+        if !self.overflow.is_empty() {
+            let rest = self.overflow.trim_start();
+            commands::remember_typed(&self.typed);
+            if let Err(e) = clipboard::copy(rest) {
+                warn!("Failed to copy the rest of the transcript: {:#}", e);
+            }
+            let message = if self.typed.is_empty() {
+                format!("⚠️ Too long to type ({} characters), copied to clipboard", rest.chars().count())
+            } else {
+                "⚠️ Too long to type, the rest was copied to clipboard".to_string()
+            };
+            notify_done(&message, &self.text(), backend_name);
+            return;
+        }
+        // Only scratched an earlier dictation, which was notified already
+        if self.typed.is_empty() {
+            return;
+        }
+        commands::remember_typed(&self.typed);
+        notify_done("✅ Transcribed", &self.typed, backend_name);
+    }
+}
+
+/// Resolves whether nothing is typed while the screen is locked with priority:
+/// 1. WA_RESPECT_SCREEN_LOCK env var
+/// 2. User config file `respect_screen_lock`
+/// 3. Default to true
+pub fn respect_screen_lock_setting() -> Resolved<bool> {
+    Layers::new("respect screen lock")
+        .env("WA_RESPECT_SCREEN_LOCK", settings::flag)
+        .config(|c| c.respect_screen_lock)
+        .or_default(true)
+}
+
+/// Whether typing is held back because the screen is locked; unlocking lets the next
+/// transcript through again
+pub fn screen_locked() -> bool {
+    respect_screen_lock_setting().value && platform::Current::screen_locked()
+}
+
+/// Typing strategy from WA_TYPING_STRATEGY, defaulting to "auto"
+pub fn strategy_setting() -> Resolved<String> {
+    Layers::new("typing strategy")
+        .env("WA_TYPING_STRATEGY", Some)
+        .or_default("auto".to_string())
+}
+
+/// Resolves the tool that sends keystrokes on Linux with priority:
+/// 1. WA_TYPING_TOOL env var
+/// 2. User config file `typing_tool`
+/// 3. Default to "auto": wtype for native Wayland windows, xdotool for XWayland and X11 ones,
+///    and wtype falling back to xdotool when the focused window can't be detected
+pub fn tool_setting() -> Resolved<String> {
+    Layers::new("typing tool")
+        .env("WA_TYPING_TOOL", Some)
+        .config(|c| c.typing_tool)
+        .or_default("auto".to_string())
+}
+
+/// Resolves the delay between typed keys in native Wayland windows (wtype) with priority:
+/// 1. WA_TYPE_DELAY_MS env var
+/// 2. User config file `type_delay_ms`
+/// 3. Default to 0
+pub fn wayland_delay_ms_setting() -> Resolved<u64> {
+    Layers::new("type delay (ms)")
+        .env("WA_TYPE_DELAY_MS", settings::parsed)
+        .config(|c| c.type_delay_ms)
+        .or_default(0)
+}
+
+/// Resolves the delay between typed keys in XWayland and X11 windows (xdotool) with priority:
+/// 1. WA_XWAYLAND_TYPE_DELAY_MS env var
+/// 2. User config file `xwayland_type_delay_ms`
+/// 3. Default to 12 (xdotool's own; some X11 apps drop keys that come faster)
+pub fn xwayland_delay_ms_setting() -> Resolved<u64> {
+    Layers::new("XWayland type delay (ms)")
+        .env("WA_XWAYLAND_TYPE_DELAY_MS", settings::parsed)
+        .config(|c| c.xwayland_type_delay_ms)
+        .or_default(12)
+}
+
+/// Decide between typing and clipboard-paste based on WA_TYPING_STRATEGY:
+/// - "type": always type keystrokes
+/// - "paste": always paste via clipboard
+/// - "auto" (default): paste when the active keyboard layout breaks keycode mapping
+fn should_paste() -> bool {
+    match strategy_setting().value.as_str() {
+        "type" => false,
+        "paste" => true,
+        _ => match keyboard::active_layout() {
+            Some(layout) => {
+                let paste = !keyboard::is_type_safe(&layout);
+                debug!("Layout '{}' type-safe: {}", layout, !paste);
+                paste
+            }
+            // Unknown layout: keep the historical typing behavior
+            None => false,
+        },
     }
+}
+
+/// Paste text at cursor by placing it on the clipboard and sending the paste shortcut
+fn paste_at_cursor(text: &str, backend_name: &str) -> Result<()> {
+    clipboard::copy(text)?;
+    // Give the clipboard owner a moment before the paste keystroke
+    std::thread::sleep(std::time::Duration::from_millis(50));
     
-    child.wait()
-        .context("Clipboard command failed")?;
+    platform::Current::paste()?;
     
+    debug!("Successfully pasted via clipboard");
+    notify_done("✅ Transcribed (pasted)", text, backend_name);
+    Ok(())
+}
+
+/// Paste into a terminal with its own shortcut; shells with bracketed paste take the text
+/// as one paste, so it isn't run even if it spans lines
+fn paste_in_terminal(text: &str, backend_name: &str) -> Result<()> {
+    clipboard::copy(text)?;
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    platform::Current::paste_in_terminal()?;
+    debug!("Successfully pasted into the terminal");
+    notify_done("✅ Transcribed (pasted into terminal)", text, backend_name);
     Ok(())
 }
 
+/// Type text at cursor using wtype (Wayland) or xdotool (X11), or System Events on macOS
+fn type_at_cursor(text: &str, backend_name: &str) -> Result<()> {
+    platform::Current::type_text(text)?;
+    notify_done("✅ Transcribed", text, backend_name);
+    Ok(())
+}
+
+/// Delete previously typed text by sending one BackSpace per character
+pub fn erase_typed(text: &str) -> Result<()> {
+    let count = text.chars().count();
+    debug!("Erasing {} typed characters", count);
+    platform::Current::erase(count)
+}
+
+/// Turn text typed at the cursor into `target`, backspacing only to where they differ
+/// rather than over all of it
+pub fn retype(typed: &str, target: &str) -> Result<()> {
+    let terminal = terminal::handling();
+    let paste = terminal.is_none() && should_paste();
+    let target = match terminal {
+        Some(handling) => terminal::sanitize(target, handling == terminal::Handling::Paste),
+        None => target.to_string(),
+    };
+    apply(&delta::diff(typed, &target), terminal, paste)
+}
+
+fn apply(edit: &delta::Edit, terminal: Option<terminal::Handling>, paste: bool) -> Result<()> {
+    if edit.is_empty() {
+        return Ok(());
+    }
+    debug!("Correcting typed text: {} backspaces, then {} characters", edit.erase, edit.insert.chars().count());
+    if edit.erase > 0 {
+        platform::Current::erase(edit.erase)?;
+    }
+    if !edit.insert.is_empty() {
+        insert(&edit.insert, terminal, paste)?;
+    }
+    Ok(())
+}
+
+/// Put text at the cursor the way a dictation's pieces go out: pasted into terminals that
+/// take pastes, pasted when the keyboard layout breaks typing, and typed otherwise
+fn insert(text: &str, terminal: Option<terminal::Handling>, paste: bool) -> Result<()> {
+    match terminal {
+        Some(terminal::Handling::Paste) => clipboard::copy(text).and_then(|()| {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            platform::Current::paste_in_terminal()
+        }),
+        _ if paste => clipboard::copy(text).and_then(|()| {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            platform::Current::paste()
+        }),
+        _ => platform::Current::type_text(text),
+    }
+}
+
 /// Legacy function for backwards compatibility - uses typing mode
 pub fn type_text(text: &str, backend_name: &str) -> Result<()> {
-    output_text(text, false, backend_name)
+    output_text(text, &Output::Type, backend_name)
 }
\ No newline at end of file
@@ -0,0 +1,152 @@
+use tracing::debug;
+use crate::settings::{self, Layers, Resolved};
+use crate::socket::{Segment, Word};
+
+/// Resolves whether words the model wasn't sure of are marked with priority:
+/// 1. WA_MARK_UNCERTAIN env var
+/// 2. User config file `uncertain.enabled`
+/// 3. Default to false
+pub fn enabled_setting() -> Resolved<bool> {
+    Layers::new("mark uncertain words")
+        .env("WA_MARK_UNCERTAIN", settings::flag)
+        .config(|c| c.uncertain?.enabled)
+        .or_default(false)
+}
+
+/// Resolves the word probability below which a word is marked with priority:
+/// 1. WA_UNCERTAIN_THRESHOLD env var (0.0-1.0)
+/// 2. User config file `uncertain.threshold`
+/// 3. Default to 0.5
+pub fn threshold_setting() -> Resolved<f64> {
+    Layers::new("uncertain threshold")
+        .env("WA_UNCERTAIN_THRESHOLD", settings::parsed)
+        .config(|c| c.uncertain?.threshold)
+        .or_default(0.5)
+}
+
+/// Resolves how a marked word is written, `{word}` standing for it, with priority:
+/// 1. WA_UNCERTAIN_MARKER env var
+/// 2. User config file `uncertain.marker`
+/// 3. Default to "⟦{word}⟧"
+pub fn marker_setting() -> Resolved<String> {
+    Layers::new("uncertain marker")
+        .env("WA_UNCERTAIN_MARKER", Some)
+        .config(|c| c.uncertain?.marker)
+        .or_default("⟦{word}⟧".to_string())
+}
+
+pub fn enabled() -> bool {
+    enabled_setting().value
+}
+
+/// The post-processed transcript with the words the model wasn't sure of marked, when
+/// marking is on; segments without words (a backend that doesn't report them) mark nothing
+pub fn mark(text: &str, segments: &[Segment]) -> String {
+    if !enabled() {
+        return text.to_string();
+    }
+    let words: Vec<&Word> = segments.iter().flat_map(|segment| &segment.words).collect();
+    if words.is_empty() {
+        debug!("No word probabilities to mark uncertain words by");
+        return text.to_string();
+    }
+    mark_words(text, &words, threshold_setting().value, &marker_setting().value)
+}
+
+/// The letters and digits of a decoded word, without the space and punctuation around it
+pub fn core(word: &str) -> &str {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+}
+
+/// Where `word` next stands on its own in `text`, from byte `from`
+pub fn find_word(text: &str, word: &str, from: usize) -> Option<usize> {
+    let mut at = from;
+    while let Some(offset) = text[at..].find(word) {
+        let start = at + offset;
+        let end = start + word.len();
+        let before = text[..start].chars().last();
+        let after = text[end..].chars().next();
+        if !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric) {
+            return Some(start);
+        }
+        at = start + word.chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+/// The text went through the dictionary, grammar correction and the rest of
+/// post-processing, so the decoded words are found in it in order rather than by position:
+/// every word moves past its own occurrence, so an uncertain "the" isn't confused with a
+/// certain one before it. Words post-processing changed aren't found and stay unmarked
+fn mark_words(text: &str, words: &[&Word], threshold: f64, marker: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut marked = 0;
+    for word in words {
+        let core = core(&word.word);
+        if core.is_empty() {
+            continue;
+        }
+        let Some(start) = find_word(text, core, copied) else {
+            continue;
+        };
+        let end = start + core.len();
+        out.push_str(&text[copied..start]);
+        if word.probability < threshold {
+            out.push_str(&marker.replace("{word}", core));
+            marked += 1;
+        } else {
+            out.push_str(core);
+        }
+        copied = end;
+    }
+    out.push_str(&text[copied..]);
+    if marked > 0 {
+        debug!("Marked {} uncertain word(s) below {:.2}", marked, threshold);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(decoded: &[(&str, f64)]) -> Vec<Word> {
+        decoded.iter().map(|(word, probability)| Word { word: word.to_string(), probability: *probability }).collect()
+    }
+
+    fn marked(text: &str, decoded: &[(&str, f64)]) -> String {
+        let words = words(decoded);
+        mark_words(text, &words.iter().collect::<Vec<_>>(), 0.5, "⟦{word}⟧")
+    }
+
+    #[test]
+    fn marks_uncertain_words() {
+        assert_eq!(
+            marked("Meet Sioban at nine.", &[(" Meet", 0.9), (" Sioban", 0.2), (" at", 0.95), (" nine.", 0.4)]),
+            "Meet ⟦Sioban⟧ at ⟦nine⟧."
+        );
+    }
+
+    #[test]
+    fn follows_the_words_in_order() {
+        assert_eq!(
+            marked("the cat and the dog", &[(" the", 0.9), (" cat", 0.9), (" and", 0.9), (" the", 0.3), (" dog", 0.9)]),
+            "the cat and ⟦the⟧ dog"
+        );
+    }
+
+    #[test]
+    fn skips_words_post_processing_changed() {
+        assert_eq!(
+            marked("Mail john@example.com today", &[(" Mail", 0.9), (" john", 0.9), (" at", 0.2), (" example", 0.9), (" dot", 0.3), (" com", 0.9), (" today", 0.1)]),
+            "Mail john@example.com ⟦today⟧"
+        );
+    }
+
+    #[test]
+    fn finds_whole_words_only() {
+        assert_eq!(marked("another other", &[(" another", 0.9), (" other", 0.1)]), "another ⟦other⟧");
+        assert_eq!(marked("other another", &[(" other", 0.1), (" another", 0.9)]), "⟦other⟧ another");
+    }
+}
@@ -0,0 +1,120 @@
+use tracing::warn;
+use crate::config::VadConfig;
+use crate::settings::{self, Layers, Resolved};
+
+/// Voice activity detection that trims silence and noise before faster-whisper transcribes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// faster-whisper's built-in Silero model; holds up against fan and room noise
+    Silero,
+    /// Google's WebRTC VAD; fast, needs the `webrtcvad` Python package
+    Webrtc,
+    /// Loudness threshold; cheapest, fine on a quiet desktop
+    Energy,
+    Off,
+}
+
+impl Engine {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "silero" | "silero-onnx" => Some(Engine::Silero),
+            "webrtc" | "webrtc-vad" => Some(Engine::Webrtc),
+            "energy" | "energy-threshold" => Some(Engine::Energy),
+            "off" | "none" => Some(Engine::Off),
+            _ => None,
+        }
+    }
+
+    /// Name the transcription scripts read from WA_VAD_ENGINE
+    #[cfg(feature = "faster-whisper")]
+    pub fn name(self) -> &'static str {
+        match self {
+            Engine::Silero => "silero",
+            Engine::Webrtc => "webrtc",
+            Engine::Energy => "energy",
+            Engine::Off => "off",
+        }
+    }
+}
+
+/// Resolves the VAD engine used by the faster-whisper backend with priority:
+/// 1. WA_VAD_ENGINE env var ("silero", "webrtc", "energy" or "off")
+/// 2. User config file `vad.engine`
+/// 3. WHISPER_VAD=false turns it off (older setting)
+/// 4. Default to "silero"
+pub fn engine_setting() -> Resolved<String> {
+    Layers::new("VAD engine")
+        .env("WA_VAD_ENGINE", Some)
+        .config(|c| c.vad?.engine)
+        .env("WHISPER_VAD", |value| value.eq_ignore_ascii_case("false").then(|| "off".to_string()))
+        .or_default("silero".to_string())
+}
+
+pub fn engine() -> Engine {
+    let name = engine_setting().value;
+    Engine::parse(&name).unwrap_or_else(|| {
+        warn!("Unknown VAD engine '{}', using silero", name);
+        Engine::Silero
+    })
+}
+
+/// One tuning option of the selected engine, passed to the transcription scripts in `var`
+pub struct Tuning {
+    /// Name in `config show`, e.g. "vad.silero.threshold"
+    pub name: &'static str,
+    #[cfg(feature = "faster-whisper")]
+    pub var: &'static str,
+    pub value: Resolved<String>,
+}
+
+/// Resolves a tuning option with priority:
+/// 1. Its env var (e.g. WA_VAD_SILERO_THRESHOLD)
+/// 2. User config file `vad.<engine>.<option>`
+/// 3. Default to `default`
+fn tuning<T>(name: &'static str, var: &'static str, get: fn(VadConfig) -> Option<T>, default: T) -> Tuning
+where
+    T: std::str::FromStr + std::fmt::Display + std::fmt::Debug,
+{
+    let resolved = Layers::new(name)
+        .env(var, settings::parsed)
+        .config(|c| c.vad.and_then(get))
+        .or_default(default);
+    Tuning {
+        name,
+        #[cfg(feature = "faster-whisper")]
+        var,
+        value: Resolved { value: resolved.value.to_string(), source: resolved.source },
+    }
+}
+
+/// The tuning options of an engine
+pub fn tunings(engine: Engine) -> Vec<Tuning> {
+    match engine {
+        Engine::Silero => vec![
+            tuning("vad.silero.threshold", "WA_VAD_SILERO_THRESHOLD", |v| v.silero?.threshold, 0.3),
+            tuning("vad.silero.min_silence_ms", "WA_VAD_SILERO_MIN_SILENCE_MS", |v| v.silero?.min_silence_ms, 300),
+            tuning("vad.silero.speech_pad_ms", "WA_VAD_SILERO_SPEECH_PAD_MS", |v| v.silero?.speech_pad_ms, 200),
+        ],
+        Engine::Webrtc => vec![
+            tuning("vad.webrtc.aggressiveness", "WA_VAD_WEBRTC_AGGRESSIVENESS", |v| v.webrtc?.aggressiveness, 2),
+            tuning("vad.webrtc.frame_ms", "WA_VAD_WEBRTC_FRAME_MS", |v| v.webrtc?.frame_ms, 30),
+            tuning("vad.webrtc.min_silence_ms", "WA_VAD_WEBRTC_MIN_SILENCE_MS", |v| v.webrtc?.min_silence_ms, 300),
+            tuning("vad.webrtc.speech_pad_ms", "WA_VAD_WEBRTC_SPEECH_PAD_MS", |v| v.webrtc?.speech_pad_ms, 200),
+        ],
+        Engine::Energy => vec![
+            tuning("vad.energy.threshold_db", "WA_VAD_ENERGY_THRESHOLD_DB", |v| v.energy?.threshold_db, -45.0),
+            tuning("vad.energy.min_silence_ms", "WA_VAD_ENERGY_MIN_SILENCE_MS", |v| v.energy?.min_silence_ms, 300),
+            tuning("vad.energy.speech_pad_ms", "WA_VAD_ENERGY_SPEECH_PAD_MS", |v| v.energy?.speech_pad_ms, 200),
+        ],
+        Engine::Off => Vec::new(),
+    }
+}
+
+/// Environment for the faster-whisper scripts: the engine and its resolved tuning
+#[cfg(feature = "faster-whisper")]
+pub fn script_env() -> Vec<(&'static str, String)> {
+    let engine = engine();
+    let mut env = vec![("WA_VAD_ENGINE", engine.name().to_string())];
+    env.extend(tunings(engine).into_iter().map(|tuning| (tuning.var, tuning.value.value)));
+    env
+}
@@ -0,0 +1,207 @@
+use anyhow::{anyhow, Context, Result};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::config::{self, Config, Problem, Profile};
+use crate::failover::Step;
+use crate::grammar::Grammar;
+use crate::locale::Locale;
+use crate::postprocess::Ending;
+use crate::recording::{Channels, Source};
+use crate::remote::Address;
+use crate::typing::Output;
+use crate::vad::Engine;
+use crate::{focus, pause, BACKENDS, COMPUTE_TYPES, TASKS};
+
+/// `[notifications] mode` values
+const NOTIFICATION_MODES: [&str; 4] = ["auto", "desktop", "tray", "none"];
+
+const TYPING_TOOLS: [&str; 3] = ["auto", "wtype", "xdotool"];
+
+const TERMINAL_OUTPUTS: [&str; 3] = ["paste", "type", "off"];
+
+/// `[vad.webrtc] frame_ms` values the VAD takes
+const WEBRTC_FRAMES_MS: [u32; 3] = [10, 20, 30];
+
+/// Problems found in one config table
+#[derive(Default)]
+struct Problems(Vec<Problem>);
+
+impl Problems {
+    fn add(&mut self, key: &str, message: String) {
+        self.0.push(Problem { key: key.to_string(), message });
+    }
+
+    /// A value parsed the way its setting parses it
+    fn parse<T: FromStr<Err = String>>(&mut self, key: &str, value: Option<&String>) {
+        if let Some(Err(e)) = value.map(|value| value.parse::<T>()) {
+            self.add(key, e);
+        }
+    }
+
+    /// A value from a fixed list; `what` names it in the message
+    fn one_of<T: PartialEq + fmt::Display>(&mut self, key: &str, what: &str, value: Option<T>, allowed: &[T]) {
+        if let Some(value) = value.filter(|value| !allowed.contains(value)) {
+            let allowed: Vec<String> = allowed.iter().map(|allowed| allowed.to_string()).collect();
+            self.add(key, format!("unknown {} '{}' (expected {})", what, value, allowed.join(", ")));
+        }
+    }
+
+    fn range<T: PartialOrd + fmt::Display>(&mut self, key: &str, value: Option<T>, min: T, max: T) {
+        if let Some(value) = value.filter(|value| *value < min || *value > max) {
+            self.add(key, format!("{} is out of range (expected {} to {})", value, min, max));
+        }
+    }
+
+    fn at_least<T: PartialOrd + fmt::Display>(&mut self, key: &str, value: Option<T>, min: T) {
+        if let Some(value) = value.filter(|value| *value < min) {
+            self.add(key, format!("{} is below the minimum of {}", value, min));
+        }
+    }
+}
+
+/// Values the settings wouldn't accept, which they'd otherwise ignore for their default,
+/// e.g. "failover[1]: Unknown failover step 'demon' (...)"; keys are dotted paths
+pub fn check(config: &Config) -> Vec<Problem> {
+    let mut problems = Problems::default();
+    problems.one_of("backend", "backend", config.backend.as_deref(), &BACKENDS);
+    problems.parse::<Output>("output", config.output.as_ref());
+    problems.range("batch_size", config.batch_size, 1, u32::MAX);
+    problems.one_of("compute_type", "compute type", config.compute_type.as_deref(), &COMPUTE_TYPES);
+    problems.one_of("task", "task", config.task.as_deref(), &TASKS);
+    problems.parse::<Address>("daemon_address", config.daemon_address.as_ref());
+    problems.parse::<Address>("daemon_listen", config.daemon_listen.as_ref());
+    problems.at_least("fast_path_secs", config.fast_path_secs, 0.0);
+    let mode = config.notifications.clone().and_then(|notifications| notifications.mode());
+    problems.one_of("notifications.mode", "notification mode", mode.as_deref(), &NOTIFICATION_MODES);
+    if let Some(Err(e)) = config.history_max_age.as_deref().map(pause::parse_duration) {
+        problems.add("history_max_age", e);
+    }
+    problems.parse::<Source>("source", config.source.as_ref());
+    problems.parse::<Channels>("channels", config.channels.as_ref());
+    problems.at_least("mic_gain", config.mic_gain, 0.0);
+    problems.at_least("min_sound_secs", config.min_sound_secs, 0.0);
+    problems.parse::<Ending>("ending", config.ending.as_ref());
+    if let Some(locale) = config.locale.as_ref().filter(|locale| *locale != "auto" && *locale != "off") {
+        problems.parse::<Locale>("locale", Some(locale));
+    }
+    problems.one_of("typing_tool", "typing tool", config.typing_tool.as_deref(), &TYPING_TOOLS);
+    problems.one_of("terminal_output", "terminal output", config.terminal_output.as_deref(), &TERMINAL_OUTPUTS);
+    for (i, step) in config.failover.iter().flatten().enumerate() {
+        if let Err(e) = Step::parse(step) {
+            problems.add(&format!("failover[{}]", i), e.to_string());
+        }
+    }
+    problems.parse::<Grammar>("grammar", config.grammar.as_ref());
+    problems.range("no_speech_threshold", config.no_speech_threshold, 0.0, 1.0);
+    if let Some(retranscribe) = &config.retranscribe {
+        problems.range("retranscribe.threshold", retranscribe.threshold, 0.0, 1.0);
+        problems.at_least("retranscribe.budget_secs", retranscribe.budget_secs, 0.0);
+    }
+    if let Some(uncertain) = &config.uncertain {
+        problems.range("uncertain.threshold", uncertain.threshold, 0.0, 1.0);
+    }
+    if let Some(line_breaks) = &config.line_breaks {
+        problems.at_least("line_breaks.pause_secs", line_breaks.pause_secs, 0.0);
+    }
+    if let Some(vad) = &config.vad {
+        if let Some(engine) = vad.engine.as_ref().filter(|engine| Engine::parse(engine).is_none()) {
+            problems.add("vad.engine", format!("unknown VAD engine '{}' (expected silero, webrtc, energy or off)", engine));
+        }
+        problems.range("vad.silero.threshold", vad.silero.as_ref().and_then(|silero| silero.threshold), 0.0, 1.0);
+        problems.range("vad.webrtc.aggressiveness", vad.webrtc.as_ref().and_then(|webrtc| webrtc.aggressiveness), 0, 3);
+        let frame_ms = vad.webrtc.as_ref().and_then(|webrtc| webrtc.frame_ms);
+        problems.one_of("vad.webrtc.frame_ms", "frame length", frame_ms, &WEBRTC_FRAMES_MS);
+    }
+    for (name, profile) in config.profiles.iter().flatten() {
+        check_profile(&mut problems, &format!("profiles.{}.", name), profile);
+    }
+    for (name, file) in config.files.iter().flatten() {
+        if file.path.is_none() {
+            problems.add(&format!("files.{}", name), "no path".to_string());
+        }
+    }
+    problems.0
+}
+
+fn check_profile(problems: &mut Problems, prefix: &str, profile: &Profile) {
+    let key = |name: &str| format!("{}{}", prefix, name);
+    problems.parse::<Output>(&key("output"), profile.output.as_ref());
+    problems.one_of(&key("task"), "task", profile.task.as_deref(), &TASKS);
+    problems.one_of(&key("compute_type"), "compute type", profile.compute_type.as_deref(), &COMPUTE_TYPES);
+    problems.range(&key("batch_size"), profile.batch_size, 1, u32::MAX);
+    problems.parse::<Grammar>(&key("grammar"), profile.grammar.as_ref());
+    if let Some(Err(e)) = profile.wait_for_focus.as_deref().map(focus::parse_pattern) {
+        problems.add(&key("wait_for_focus"), e);
+    }
+}
+
+/// Check the config file, or `file` (e.g. before putting it in place), and list what's wrong
+/// with it; Err when anything is
+pub fn run(file: Option<PathBuf>) -> Result<()> {
+    let path = file.unwrap_or_else(config::config_path);
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let problems = config::problems(&content)
+        .with_context(|| format!("{} isn't valid TOML", path.display()))?;
+    if problems.is_empty() {
+        println!("✅ {} is valid", path.display());
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    Err(anyhow!("{} problem(s) in {}", problems.len(), path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(content: &str) -> Vec<String> {
+        config::problems(content).unwrap().into_iter().map(|problem| problem.key).collect()
+    }
+
+    #[test]
+    fn reports_each_setting_by_its_path() {
+        let content = r#"
+            backend = "whisper-cp"
+            failover = ["daemon", "demon"]
+            outptu = "type"
+            [vad.silero]
+            threshold = 1.5
+            [profiles.work]
+            output = "clipbaord"
+        "#;
+        assert_eq!(keys(content), ["outptu", "backend", "failover[1]", "vad.silero.threshold", "profiles.work.output"]);
+    }
+
+    #[test]
+    fn a_bad_value_leaves_the_rest_of_its_section() {
+        let content = r#"
+            model = "small"
+            [uncertain]
+            threshold = "low"
+            marker = "[{word}]"
+            [instances.second]
+            tsk = "translate"
+        "#;
+        assert_eq!(keys(content), ["uncertain.threshold", "instances.second.tsk"]);
+        let (config, _) = config::from_table(content.parse().unwrap());
+        assert_eq!(config.model.as_deref(), Some("small"));
+        assert_eq!(config.uncertain.and_then(|uncertain| uncertain.marker).as_deref(), Some("[{word}]"));
+    }
+
+    #[test]
+    fn valid_config_has_no_problems() {
+        let content = r#"
+            backend = "faster-whisper"
+            output = "file:notes"
+            locale = "auto"
+            failover = ["daemon", "openai"]
+            [vad.webrtc]
+            frame_ms = 20
+        "#;
+        assert!(keys(content).is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Context, Result};
+use notify::{RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+use crate::socket::TranscriptionResponse;
+use crate::{batch, helpers, line_breaks, paths, postprocess, transcribe, transcript, uncertain};
+
+/// Extensions of the audio files picked up, in any case
+const AUDIO_EXTENSIONS: &[&str] = &["wav", "mp3", "m4a", "aac", "ogg", "oga", "opus", "flac", "webm", "amr", "3gp", "mp4"];
+
+/// A file is only transcribed once nothing has written to it for this long, so one that is
+/// still being synced or copied isn't read half-way
+const SETTLE: Duration = Duration::from_secs(3);
+
+/// With nothing happening in the directory it is still looked at this often
+const IDLE_RESCAN: Duration = Duration::from_secs(3600);
+
+/// What became of a file, so it isn't transcribed again after a restart
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Processed {
+    /// Size and modification time it had; a file that changes since is transcribed again
+    stamp: String,
+    /// Why it couldn't be transcribed (it is only tried again once it changes)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Processed files by absolute path, for every watched directory
+fn state_path() -> PathBuf {
+    paths::state_dir().join("watched.json")
+}
+
+fn load_state() -> BTreeMap<String, Processed> {
+    let Ok(content) = fs::read_to_string(state_path()) else {
+        return BTreeMap::new();
+    };
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        warn!("Failed to parse {}, transcribing everything again: {}", state_path().display(), e);
+        BTreeMap::new()
+    })
+}
+
+/// Entries of files that are gone are dropped, so the state doesn't grow forever
+fn save_state(state: &mut BTreeMap<String, Processed>) {
+    state.retain(|path, _| Path::new(path).exists());
+    let path = state_path();
+    let result = fs::create_dir_all(paths::state_dir())
+        .and_then(|()| serde_json::to_string_pretty(state).map_err(std::io::Error::other))
+        .and_then(|json| fs::write(&path, json));
+    if let Err(e) = result {
+        warn!("Failed to save {}: {}", path.display(), e);
+    }
+}
+
+fn stamp(metadata: &fs::Metadata) -> Option<String> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!("{} {}", metadata.len(), modified.as_nanos()))
+}
+
+fn is_audio(path: &Path) -> bool {
+    // Sync tools write hidden temporary files before renaming them into place
+    let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    !hidden && path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.iter().any(|audio| ext.eq_ignore_ascii_case(audio)))
+}
+
+/// `whisp-away watch`: transcribe every audio file that appears in `dir` (and those already
+/// there that weren't transcribed yet), writing `.txt` and `.srt` files next to it
+/// Files go through the batch queue, so after a crash or reboot the next run carries on
+/// with the file it was on. Runs until interrupted
+pub fn run(dir: &Path) -> Result<()> {
+    let dir = dir.canonicalize().with_context(|| format!("Can't watch {}", dir.display()))?;
+    if !dir.is_dir() {
+        return Err(anyhow!("{} is not a directory", dir.display()));
+    }
+    let (events, changes) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(events).context("Failed to create directory watcher")?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", dir.display()))?;
+    batch::resume()?;
+    // `whisp-away jobs retry` changes the queue there
+    watcher.watch(&paths::state_dir(), RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", paths::state_dir().display()))?;
+    eprintln!("Watching {} for audio files (Ctrl+C to stop)", dir.display());
+
+    let mut state = load_state();
+    loop {
+        let unsettled = process_settled(&dir, &mut state)?;
+        let timeout = if unsettled { SETTLE } else { IDLE_RESCAN };
+        match changes.recv_timeout(timeout) {
+            Ok(Ok(event)) => debug!("{:?} {:?}", event.kind, event.paths),
+            Ok(Err(e)) => warn!("Watch error: {}", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Err(anyhow!("Stopped watching {}", dir.display())),
+        }
+        // A copy or sync comes as a burst of events; one look at the directory covers them all
+        while changes.try_recv().is_ok() {}
+    }
+}
+
+/// Queue the files that are new or changed and have settled, then transcribe the queue;
+/// true when some haven't settled yet
+fn process_settled(dir: &Path, state: &mut BTreeMap<String, Processed>) -> Result<bool> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_file() && is_audio(path))
+        .collect();
+    files.sort();
+
+    let mut unsettled = false;
+    for path in files {
+        let Ok(metadata) = fs::metadata(&path) else {
+            continue;
+        };
+        let (Some(stamp), Ok(modified)) = (stamp(&metadata), metadata.modified()) else {
+            continue;
+        };
+        let key = path.to_string_lossy().into_owned();
+        if state.get(&key).is_some_and(|processed| processed.stamp == stamp) {
+            continue;
+        }
+        if SystemTime::now().duration_since(modified).unwrap_or_default() < SETTLE {
+            unsettled = true;
+            continue;
+        }
+        batch::enqueue(&path, Some(helpers::resolve_model()))?;
+    }
+
+    while let Some(job) = batch::next(dir)? {
+        let path = PathBuf::from(&job.path);
+        let Some(stamp) = fs::metadata(&path).ok().and_then(|metadata| stamp(&metadata)) else {
+            batch::finish(job.job.id, Some("The file is gone".to_string()))?;
+            continue;
+        };
+        info!("Transcribing {} (batch job {})", path.display(), job.job.id);
+        let error = match transcribe_to_files(&path) {
+            Ok(written) => {
+                let names: Vec<String> = written.iter()
+                    .filter_map(|file| Some(file.file_name()?.to_string_lossy().into_owned()))
+                    .collect();
+                println!("{} → {}", path.display(), names.join(", "));
+                None
+            }
+            Err(e) => {
+                warn!("Failed to transcribe {}: {:#}", path.display(), e);
+                Some(format!("{:#}", e))
+            }
+        };
+        batch::finish(job.job.id, error.clone())?;
+        state.insert(job.path, Processed { stamp, error });
+        save_state(state);
+    }
+    Ok(unsettled)
+}
+
+/// Transcribe one file into `<name>.txt` and `<name>.srt` next to it
+fn transcribe_to_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let response = transcribe::audio(path)?;
+    let text = postprocess::apply(response.text.as_deref().unwrap_or_default().trim(), response.language.as_deref());
+    let text = line_breaks::apply(&text, &response.segments);
+    let text = uncertain::mark(&text, &response.segments);
+
+    let text_path = path.with_extension("txt");
+    fs::write(&text_path, format!("{}\n", text))
+        .with_context(|| format!("Failed to write {}", text_path.display()))?;
+    let subtitles_path = path.with_extension("srt");
+    transcript::write_srt(&subtitles_path, &TranscriptionResponse { text: Some(text), ..response })?;
+    Ok(vec![text_path, subtitles_path])
+}
@@ -1,52 +1,35 @@
 use anyhow::Result;
-use std::fs;
-use std::process::Command;
+use std::time::Instant;
+use crate::failover::{Attempt, Step};
+use crate::privacy;
 use crate::recording;
 use crate::socket;
+use crate::refine;
+use crate::typing::Output;
 use super::direct::{transcribe_with_whisper_rs, transcribe_with_cli};
 
-pub fn stop_and_transcribe_daemon(socket_path: &str, audio_file_override: Option<&str>, model: Option<String>, bindings: bool, whisper_path: Option<String>, use_clipboard: bool) -> Result<()> {
+pub fn stop_and_transcribe_daemon(socket_path: &str, audio_file_override: Option<&str>, model: Option<String>, bindings: bool, whisper_path: Option<String>, output: &Output, final_model: Option<&str>) -> Result<()> {
     let audio_file = match recording::stop_recording(audio_file_override)? {
         Some(path) => path,
         None => {
-            Command::new("notify-send")
-                .args(&[
-                    "Voice Input (whisper.cpp daemon)",
-                    "❌ No recording found",
-                    "-t", "2000",
-                    "-h", "string:x-canonical-private-synchronous:voice"
-                ])
-                .spawn()?;
+            crate::helpers::send_notification("Voice Input (whisper.cpp daemon)", "❌ No recording found", 2000);
             return Ok(());
         }
     };
 
     let audio_path = std::path::Path::new(&audio_file);
     if !audio_path.exists() {
-        Command::new("notify-send")
-            .args(&[
-                "Voice Input (whisper.cpp daemon)",
-                "❌ No audio recorded",
-                "-t", "2000",
-                "-h", "string:x-canonical-private-synchronous:voice"
-            ])
-            .spawn()?;
+        crate::helpers::send_notification("Voice Input (whisper.cpp daemon)", "❌ No audio recorded", 2000);
         return Ok(());
     }
     
-    if let Ok(metadata) = fs::metadata(&audio_file) {
-        if metadata.len() <= 44 {
-            Command::new("notify-send")
-                .args(&[
-                    "Voice Input",
-                    "❌ Audio file is empty\nBackend: whisper-cpp",
-                    "-t", "2000",
-                    "-h", "string:x-canonical-private-synchronous:voice"
-                ])
-                .spawn()?;
-            let _ = fs::remove_file(&audio_file);
-            return Ok(());
-        }
+    if crate::audio::is_empty(&audio_file) {
+        crate::helpers::send_notification("Voice Input", "❌ Audio file is empty\nBackend: whisper-cpp", 2000);
+        let _ = privacy::remove_file(&audio_file);
+        return Ok(());
+    }
+    if recording::reject_silence(&audio_file) {
+        return Ok(());
     }
 
     let start_time = std::time::Instant::now();
@@ -57,23 +40,24 @@ pub fn stop_and_transcribe_daemon(socket_path: &str, audio_file_override: Option
     let acceleration = crate::helpers::get_acceleration_type();
     let transcribe_msg = format!("⏳ Transcribing...\nBackend: whisper-cpp ({}) | Model: {}", acceleration, resolved_model);
     
-    Command::new("notify-send")
-        .args(&[
-            "Voice Input",
-            &transcribe_msg,
-            "-t", "2000",
-            "-h", "string:x-canonical-private-synchronous:voice"
-        ])
-        .spawn()?;
+    crate::helpers::send_notification("Voice Input", &transcribe_msg, 2000);
 
     eprintln!("DEBUG: Connecting to daemon socket at: {}", socket_path);
     
-    match socket::send_transcription_request(socket_path, &audio_file, "whisper-cpp", use_clipboard) {
-        Ok(_) => {
+    let started = Instant::now();
+    match socket::send_transcription_request(socket_path, &socket::TranscriptionRequest::new(&audio_file), "whisper-cpp", output) {
+        Ok(mut response) => {
             eprintln!("DEBUG: Total time: {:?}", start_time.elapsed());
-            let _ = fs::remove_file(&audio_file);
+            // With a final model the refinement worker re-transcribes and then deletes the audio
+            if !refine::hand_off(&audio_file, response.as_ref().and_then(|r| r.text.as_deref()), final_model, "whisper-cpp", output) {
+                let _ = privacy::remove_file(&audio_file);
+            }
+            if let Some(response) = response.as_mut() {
+                privacy::wipe_response(response);
+            }
         }
         Err(e) => {
+            let attempts = vec![Attempt::failed(Step::Daemon, started, &e)];
             // Resolve model from env/daemon config
             let model = crate::helpers::resolve_model();
             
@@ -83,14 +67,7 @@ pub fn stop_and_transcribe_daemon(socket_path: &str, audio_file_override: Option
                 format!("⚠️ Daemon not running, using fallback\nBackend: whisper-cpp (CLI) | Model: {}", model)
             };
             
-            Command::new("notify-send")
-                .args(&[
-                    "Voice Input",
-                    &fallback_msg,
-                    "-t", "2000",
-                    "-h", "string:x-canonical-private-synchronous:voice"
-                ])
-                .spawn()?;
+            crate::helpers::send_notification("Voice Input", &fallback_msg, 2000);
             
             // By default, fallback uses whisper-rs bindings (same as daemon)
             // With --no-bindings flag, it uses the CLI binary instead
@@ -99,13 +76,13 @@ pub fn stop_and_transcribe_daemon(socket_path: &str, audio_file_override: Option
                 let whisper_path = whisper_path.unwrap_or_else(|| 
                     std::env::var("WHISPER_CPP_PATH").unwrap_or_else(|_| "whisper-cpp".to_string())
                 );
-                transcribe_with_cli(&audio_file, &model, &whisper_path, use_clipboard)
+                transcribe_with_cli(&audio_file, &model, &whisper_path, output, attempts)
             } else {
                 // Use whisper-rs bindings for fallback (default, same as daemon)
-                transcribe_with_whisper_rs(&audio_file, &model, "", use_clipboard)
+                transcribe_with_whisper_rs(&audio_file, &model, "", output, attempts)
             };
             
-            let _ = fs::remove_file(&audio_file);
+            let _ = privacy::remove_file(&audio_file);
             
             return result.map_err(|err| anyhow::anyhow!("Fallback transcription failed (daemon was: {}): {}", e, err));
         }
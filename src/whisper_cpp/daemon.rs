@@ -1,78 +1,463 @@
 use anyhow::{anyhow, Context, Result};
+use base64::Engine;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::{Read, Write};
-use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::net::UnixListener;
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
-use std::sync::Arc;
-use tracing::{error, info, warn};
-use whisper_rs::{WhisperContext, WhisperContextParameters, FullParams, SamplingStrategy};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, TryLockError};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+use whisper_rs::{WhisperContext, WhisperContextParameters, FullParams};
 #[cfg(feature = "openvino")]
 use whisper_rs::WhisperState;
-use crate::helpers::{wav_to_samples, DaemonConfig, write_daemon_config, resolve_use_clipboard, resolve_socket_path};
-
-const SOCKET_PATH: &str = "/tmp/whisp-away-daemon.sock";
+use crate::activation;
+use crate::activity::{self, Activity};
+use crate::audio;
+use crate::recording::{self, Source};
+use crate::recording::capture::Capture;
+use crate::handoff;
+use crate::privacy;
+use crate::remote::{self, Connection};
+use crate::socket::{self, Job, Segment, TranscriptionResponse};
+use super::queue::JobQueue;
+use super::windows::{self, Decoding, Transcript};
+use crate::helpers::{DaemonConfig, write_daemon_config, read_daemon_config, resolve_use_clipboard, resolve_socket_path, resolve_model, resolve_language, resolve_translate, resolve_model_memory_mb, send_notification};
 
+/// `listener` is the socket systemd passed when it started the daemon on demand
 #[tokio::main]
-pub async fn run_daemon(model_path: &str) -> Result<()> {
+pub async fn run_daemon(model_path: &str, listener: Option<UnixListener>) -> Result<()> {
     // Write daemon config so CLI commands can read our settings
     let socket_path = resolve_socket_path();
     let config = DaemonConfig {
         backend: Some("whisper-cpp".to_string()),
         model: Some(model_path.to_string()),
-        socket_path: Some(socket_path),
+        socket_path: Some(socket_path.clone()),
         use_clipboard: Some(resolve_use_clipboard()),
+        compute_type: None,
+        language: Some(resolve_language()),
     };
     if let Err(e) = write_daemon_config(&config) {
         eprintln!("Warning: Failed to write daemon config: {}", e);
     }
     
     // Create and run daemon
-    let daemon = WhisperDaemon::new(model_path)?;
-    daemon.run().await
+    let daemon = WhisperDaemon::new(model_path, &socket_path)?;
+    daemon.run(listener).await
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct TranscriptionRequest {
+    #[serde(default)]
     audio_path: String,
+    /// "hello" asks what the daemon can do, "status" for the loaded models, "jobs" for the queue,
+    /// "cancel" cancels `job` or `request_id`, "unload"/"load" drop and restore the models and
+    /// "start_recording" records for the client, instead of a transcription;
+    /// "stop_and_transcribe" transcribes that recording
+    #[serde(default)]
+    command: Option<String>,
+    #[serde(default)]
+    job: Option<u64>,
+    /// The client's id for a transcription, so it can cancel it without knowing the job
+    #[serde(default)]
+    request_id: Option<String>,
+    /// Shown in the job list
+    #[serde(default)]
+    label: Option<String>,
+    /// Model to transcribe with; the daemon's default when unset
+    #[serde(default)]
+    model: Option<String>,
+    /// WAV bytes sent by clients that don't share our filesystem
+    #[serde(default)]
+    audio_base64: Option<String>,
+    /// Per-request overrides of the daemon's language and task
+    #[serde(default)]
+    language: Option<String>,
+    #[serde(default)]
+    task: Option<String>,
+    /// Send each segment as a line as soon as it is decoded, before the response
+    #[serde(default)]
+    stream: bool,
+    /// Beam search with this many beams instead of greedy decoding
+    #[serde(default)]
+    beam_size: Option<u32>,
+    /// Text whisper is primed with before decoding
+    #[serde(default)]
+    initial_prompt: Option<String>,
+    /// Report each segment's words with their probabilities
+    #[serde(default)]
+    words: bool,
+    /// The client passed the audio's file descriptor with the request
+    #[serde(default)]
+    audio_fd: bool,
+    /// That descriptor, closed with the request
+    #[serde(skip)]
+    received_fd: Option<OwnedFd>,
+    /// What "start_recording" records; the daemon's `source` setting when unset
+    #[serde(default)]
+    source: Option<Source>,
+    /// The pre-shared token, from clients on other machines
+    #[serde(default)]
+    token: Option<String>,
+    /// `audio_path` is a recording the daemon made, removed once transcribed
+    #[serde(skip)]
+    recorded: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct TranscriptionResponse {
-    success: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    text: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<String>,
+impl TranscriptionRequest {
+    fn language(&self) -> String {
+        self.language.clone().unwrap_or_else(resolve_language)
+    }
+    
+    fn translate(&self) -> bool {
+        match &self.task {
+            Some(task) => task == "translate",
+            None => resolve_translate(),
+        }
+    }
+    
+    /// How whisper decodes this request, given its resolved language
+    fn decoding<'a>(&'a self, language: &'a str) -> Decoding<'a> {
+        Decoding {
+            language,
+            translate: self.translate(),
+            beam_size: self.beam_size,
+            initial_prompt: self.initial_prompt.as_deref(),
+            words: self.words,
+        }
+    }
+    
+    /// Job list label: the client's, else the audio file name
+    fn label(&self) -> String {
+        if let Some(label) = &self.label {
+            return label.clone();
+        }
+        if self.audio_base64.is_some() {
+            return "inline audio".to_string();
+        }
+        Path::new(&self.audio_path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.audio_path.clone())
+    }
 }
 
-pub struct WhisperDaemon {
+/// Read one JSON request - inline audio can span many reads
+/// An audio file descriptor arrives with the first read over the Unix socket
+/// A remote client's request is read before its token is checked, so it must arrive whole
+/// by `deadline` and within `remote::MAX_REQUEST_BYTES`
+fn read_request(stream: &mut Connection, deadline: Option<Instant>) -> Result<TranscriptionRequest> {
+    let mut data = Vec::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let read = |stream: &mut Connection, buffer: &mut [u8]| -> Result<usize> {
+        if let Some(deadline) = deadline {
+            stream.set_read_timeout(Some(remote::time_left(deadline)?))?;
+        }
+        Ok(stream.read(buffer)?)
+    };
+    let (mut n, fd) = match stream.as_unix() {
+        Some(unix) => handoff::recv_with_fd(unix, &mut buffer)?,
+        None => (read(stream, &mut buffer)?, None),
+    };
+    while n > 0 {
+        data.extend_from_slice(&buffer[..n]);
+        if deadline.is_some() && data.len() > remote::MAX_REQUEST_BYTES {
+            return Err(anyhow!("Request larger than {} MB", remote::MAX_REQUEST_BYTES as u64 / MB));
+        }
+        
+        // Older clients keep the connection open, so stop as soon as the JSON is complete
+        if data.trim_ascii_end().ends_with(b"}") && serde_json::from_slice::<serde::de::IgnoredAny>(&data).is_ok() {
+            break;
+        }
+        n = read(stream, &mut buffer)?;
+    }
+    let mut request: TranscriptionRequest = serde_json::from_slice(&data).context("Failed to parse request")?;
+    // A descriptor the request didn't ask for is closed here
+    request.received_fd = fd.filter(|_| request.audio_fd);
+    Ok(request)
+}
+
+/// Audio file for a request; inline audio is written to a temp file removed on drop, as is
+/// a recording the daemon made
+struct RequestAudio {
+    path: String,
+    temporary: bool,
+}
+
+impl RequestAudio {
+    fn from_request(request: &TranscriptionRequest) -> Result<Self> {
+        if request.recorded {
+            return Ok(Self { path: request.audio_path.clone(), temporary: true });
+        }
+        if let Some(fd) = &request.received_fd {
+            // The descriptor stays open for as long as the request, which outlives this
+            return Ok(Self { path: format!("/proc/self/fd/{}", fd.as_raw_fd()), temporary: false });
+        }
+        let Some(encoded) = &request.audio_base64 else {
+            return Ok(Self { path: request.audio_path.clone(), temporary: false });
+        };
+        
+        let audio = base64::engine::general_purpose::STANDARD.decode(encoded)
+            .context("Failed to decode inline audio")?;
+        let path = format!("{}/whisp-away-inline-{}-{}.wav",
+            crate::paths::runtime_dir(),
+            std::process::id(),
+            INLINE_COUNTER.fetch_add(1, Ordering::Relaxed));
+        fs::write(&path, &audio).context("Failed to write inline audio")?;
+        info!("Received {} bytes of inline audio", audio.len());
+        Ok(Self { path, temporary: true })
+    }
+}
+
+impl Drop for RequestAudio {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = privacy::remove_file(&self.path);
+        }
+    }
+}
+
+/// Distinguishes temp files of concurrent inline requests
+static INLINE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+const MB: u64 = 1024 * 1024;
+
+/// A loaded whisper model
+#[derive(Clone)]
+struct LoadedModel {
+    #[cfg_attr(feature = "openvino", allow(dead_code))]
     ctx: Arc<WhisperContext>,
-    socket_path: String,
     // Single reusable state with OpenVINO initialized
     #[cfg(feature = "openvino")]
     state: Arc<tokio::sync::Mutex<WhisperState>>,
+    /// Size of the model file, used as its memory footprint
+    size: u64,
+}
+
+struct CachedModel {
+    name: String,
+    model: LoadedModel,
+    last_used: Instant,
+}
+
+/// Resident models, least recently used first
+/// Once their combined size exceeds the memory limit the oldest are unloaded,
+/// but the model a request just asked for always stays
+struct ModelCache {
+    /// Model used by requests that don't name one
+    default_model: String,
+    entries: Vec<CachedModel>,
+    memory_limit: u64,
+    /// Models were dropped on request; nothing is loaded until asked to again
+    unloaded: bool,
+}
+
+/// How often the state sent to `watch` subscribers is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Set while a model loads, which holds the model cache locked
+static LOADING: AtomicBool = AtomicBool::new(false);
+
+/// Commands and request fields this daemon handles, reported to `hello`; compute_type and
+/// batch_size are faster-whisper's
+const CAPABILITIES: &[&str] = &[
+    "status", "watch", "jobs", "cancel", "request_id", "unload", "load", "record",
+    "model", "language", "task", "beam_size", "initial_prompt", "words", "stream", "audio_base64", "audio_fd", "label",
+];
+
+/// Response to a "status" request
+#[derive(Debug, Clone, Serialize)]
+struct DaemonStatus {
+    success: bool,
+    backend: &'static str,
+    default_model: String,
+    memory_limit_mb: u64,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    unloaded: bool,
+    /// Recording for a client (`daemon_recording`)
+    recording: bool,
+    models: Vec<ModelStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ModelStatus {
+    name: String,
+    size_mb: u64,
+    idle_secs: u64,
+}
+
+impl ModelCache {
+    fn new(default_model: &str) -> Result<Self> {
+        let mut cache = Self {
+            default_model: default_model.to_string(),
+            entries: Vec::new(),
+            memory_limit: resolve_model_memory_mb() * MB,
+            unloaded: false,
+        };
+        cache.get(default_model)?;
+        Ok(cache)
+    }
+    
+    /// Get a model, loading it (and unloading others) if it isn't resident
+    fn get(&mut self, name: &str) -> Result<LoadedModel> {
+        if let Some(index) = self.entries.iter().position(|entry| entry.name == name) {
+            let mut entry = self.entries.remove(index);
+            entry.last_used = Instant::now();
+            let model = entry.model.clone();
+            self.entries.push(entry);
+            return Ok(model);
+        }
+        
+        let model = LoadedModel::load(name)?;
+        self.entries.push(CachedModel {
+            name: name.to_string(),
+            model: model.clone(),
+            last_used: Instant::now(),
+        });
+        self.evict();
+        Ok(model)
+    }
+    
+    /// Drop every model; requests still using one keep it until they finish
+    fn unload(&mut self) {
+        let freed = self.resident_bytes();
+        self.entries.clear();
+        self.unloaded = true;
+        info!("Unloaded all models ({} MB)", freed / MB);
+    }
+    
+    /// Load the default model again after `unload`
+    fn load(&mut self) -> Result<()> {
+        let name = self.default_model.clone();
+        self.get(&name)?;
+        self.unloaded = false;
+        info!("Loaded model {} again", name);
+        Ok(())
+    }
+    
+    fn resident_bytes(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.model.size).sum()
+    }
+    
+    fn evict(&mut self) {
+        while self.entries.len() > 1 && self.resident_bytes() > self.memory_limit {
+            let evicted = self.entries.remove(0);
+            // Requests still using it keep it alive until they finish
+            info!("Unloading model {} ({} MB) to stay under the {} MB limit",
+                  evicted.name, evicted.model.size / MB, self.memory_limit / MB);
+        }
+    }
+    
+    fn status(&self) -> DaemonStatus {
+        DaemonStatus {
+            success: true,
+            backend: "whisper-cpp",
+            default_model: self.default_model.clone(),
+            memory_limit_mb: self.memory_limit / MB,
+            unloaded: self.unloaded,
+            recording: false,
+            models: self.entries.iter().rev().map(|entry| ModelStatus {
+                name: entry.name.clone(),
+                size_mb: entry.model.size / MB,
+                idle_secs: entry.last_used.elapsed().as_secs(),
+            }).collect(),
+        }
+    }
+}
+
+pub struct WhisperDaemon {
+    models: Arc<Mutex<ModelCache>>,
+    queue: Arc<JobQueue>,
+    /// The recording made for a client, between "start_recording" and "stop_and_transcribe"
+    capture: Arc<Mutex<Option<Capture>>>,
+    socket_path: String,
 }
 
 impl WhisperDaemon {
-    pub fn new(model_path: &str) -> Result<Self> {
+    pub fn new(model_path: &str, socket_path: &str) -> Result<Self> {
+        Ok(Self {
+            models: Arc::new(Mutex::new(ModelCache::new(model_path)?)),
+            queue: Arc::new(JobQueue::new()),
+            capture: Arc::new(Mutex::new(None)),
+            socket_path: socket_path.to_string(),
+        })
+    }
+    
+    /// Watch the user config file and switch the default model when the resolved model changes
+    /// Language is resolved per request, so it needs no reload here
+    fn watch_config(&self) -> Result<notify::RecommendedWatcher> {
+        let models = Arc::clone(&self.models);
+        
+        crate::config::watch_config(move |_config| {
+            let new_model = resolve_model();
+            let mut models = models.lock().unwrap_or_else(|e| e.into_inner());
+            models.memory_limit = resolve_model_memory_mb() * MB;
+            let current_model = models.default_model.clone();
+            if new_model == current_model {
+                info!("Configuration reloaded");
+                send_notification("Voice Input", "🔄 Configuration reloaded", 2000);
+                return;
+            }
+            
+            info!("Configuration reloaded, switching model {} -> {}", current_model, new_model);
+            // Unloaded models stay that way; the new default is loaded by `load`
+            let loaded = if models.unloaded { Ok(()) } else { models.get(&new_model).map(|_| ()) };
+            match loaded {
+                Ok(_) => {
+                    models.default_model = new_model.clone();
+                    
+                    let mut daemon_config = read_daemon_config().unwrap_or_default();
+                    daemon_config.model = Some(new_model.clone());
+                    daemon_config.language = Some(resolve_language());
+                    if let Err(e) = write_daemon_config(&daemon_config) {
+                        warn!("Failed to update daemon config: {}", e);
+                    }
+                    
+                    send_notification(
+                        "Voice Input",
+                        &format!("🔄 Configuration reloaded\nModel: {}", new_model),
+                        2000
+                    );
+                }
+                Err(e) => {
+                    error!("Failed to load model {}, keeping {}: {}", new_model, current_model, e);
+                    send_notification(
+                        "Voice Input",
+                        &format!("❌ Failed to load model {}\nKeeping {}", new_model, current_model),
+                        3000
+                    );
+                }
+            }
+        })
+    }
+}
+
+impl LoadedModel {
+    fn load(model_path: &str) -> Result<Self> {
         // If model_path doesn't contain a path separator, treat it as a model name
         // and construct the full path
         let final_model_path = if !model_path.contains('/') {
-            let home = std::env::var("HOME").unwrap_or_else(|_| "/home/martin".to_string());
-            let model_extension = if model_path.ends_with(".bin") { "" } else { ".bin" };
-            format!("{}/.cache/whisper-cpp/models/ggml-{}{}", home, model_path, model_extension)
+            crate::paths::whisper_cpp_model_path(model_path)
         } else {
             model_path.to_string()
         };
         
         info!("Loading whisper.cpp model from: {}", final_model_path);
-        
-        // Check if model file exists
-        if !Path::new(&final_model_path).exists() {
-            return Err(anyhow::anyhow!("Model file not found: {}", final_model_path));
-        }
+        LOADING.store(true, Ordering::SeqCst);
+        let result = Self::load_from(model_path, &final_model_path);
+        LOADING.store(false, Ordering::SeqCst);
+        result
+    }
+
+    fn load_from(model_path: &str, final_model_path: &str) -> Result<Self> {
+        // A truncated or corrupted model is replaced or named here, not left to whisper
+        super::models::ensure_intact(model_path)?;
+        let metadata = fs::metadata(final_model_path)
+            .with_context(|| format!("Model file not found: {}", final_model_path))?;
         
         // Create whisper context with GPU configuration
         let mut ctx_params = WhisperContextParameters::default();
@@ -84,7 +469,7 @@ impl WhisperDaemon {
         
         info!("Initializing WhisperContext with configured acceleration");
         let t_ctx = std::time::Instant::now();
-        let ctx = WhisperContext::new_with_params(&final_model_path, ctx_params)
+        let ctx = WhisperContext::new_with_params(final_model_path, ctx_params)
             .context("Failed to create WhisperContext")?;
         eprintln!("DEBUG DAEMON: Context creation took {:?}", t_ctx.elapsed());
         
@@ -128,13 +513,113 @@ impl WhisperDaemon {
         
         Ok(Self {
             ctx: Arc::new(ctx),
-            socket_path: SOCKET_PATH.to_string(),
             #[cfg(feature = "openvino")]
             state,
+            size: metadata.len(),
         })
     }
+}
+
+impl WhisperDaemon {
+    pub async fn run(&self, activated: Option<UnixListener>) -> Result<()> {
+        // Started on demand, the socket is systemd's and clients are already waiting on it;
+        // once idle the daemon exits until the next dictation starts it again
+        let (listener, idle) = match activated {
+            Some(listener) => {
+                let idle_mins = activation::idle_exit_mins_setting().value;
+                (listener, activation::IdleExit::start(Duration::from_secs(idle_mins * 60)))
+            }
+            None => (self.bind()?, None),
+        };
+        activation::write_pid();
+        self.warm_up().await;
+        // Clears an unloaded state left by a previous daemon
+        activity::set(Activity::Idle);
+        
+        // Keep the watcher alive for the lifetime of the daemon
+        let _config_watcher = match self.watch_config() {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                warn!("Config hot-reload disabled: {}", e);
+                None
+            }
+        };
+        
+        if let Some(remote) = remote::Listener::bind()? {
+            self.serve_remote(remote, idle.clone());
+        }
+
+        // Accept connections in a loop
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let models = Arc::clone(&self.models);
+                    let queue = Arc::clone(&self.queue);
+                    let capture = Arc::clone(&self.capture);
+                    let idle = idle.clone();
+                    // Spawn a task to handle the connection
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(Connection::accepted(stream), models, queue, capture, idle.as_deref()).await {
+                            error!("Error handling connection: {}", e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    error!("Error accepting connection: {}", e);
+                }
+            }
+        }
+        
+        Ok(())
+    }
     
-    pub async fn run(&self) -> Result<()> {
+    /// Accept clients from other machines on their own thread, next to the socket's loop
+    fn serve_remote(&self, remote: remote::Listener, idle: Option<Arc<activation::IdleExit>>) {
+        let remote = Arc::new(remote);
+        let runtime = tokio::runtime::Handle::current();
+        let models = Arc::clone(&self.models);
+        let queue = Arc::clone(&self.queue);
+        let capture = Arc::clone(&self.capture);
+        std::thread::spawn(move || {
+            for stream in remote.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        error!("Error accepting remote connection: {}", e);
+                        continue;
+                    }
+                };
+                let peer = stream.peer_addr().map_or_else(|_| "unknown".to_string(), |addr| addr.ip().to_string());
+                let remote = Arc::clone(&remote);
+                let models = Arc::clone(&models);
+                let queue = Arc::clone(&queue);
+                let capture = Arc::clone(&capture);
+                let idle = idle.clone();
+                runtime.spawn(async move {
+                    // Everything up to the token check blocks for as long as a slow client
+                    // takes, so it runs on the blocking pool rather than an async worker
+                    let client = peer.clone();
+                    let accepted = tokio::task::spawn_blocking(move || accept_remote(&remote, stream, &client)).await
+                        .map_err(anyhow::Error::from)
+                        .and_then(|accepted| accepted);
+                    let (stream, request) = match accepted {
+                        Ok(Some(accepted)) => accepted,
+                        Ok(None) => return,
+                        Err(e) => {
+                            warn!("Remote client {}: {:#}", peer, e);
+                            return;
+                        }
+                    };
+                    debug!("Remote client {} connected", peer);
+                    if let Err(e) = handle_request(stream, request, models, queue, capture, idle.as_deref()).await {
+                        error!("Error handling connection from {}: {}", peer, e);
+                    }
+                });
+            }
+        });
+    }
+
+    fn bind(&self) -> Result<UnixListener> {
         // Remove existing socket if it exists
         if Path::new(&self.socket_path).exists() {
             fs::remove_file(&self.socket_path)?;
@@ -150,259 +635,468 @@ impl WhisperDaemon {
         fs::set_permissions(&self.socket_path, perms)?;
         
         info!("Daemon listening on {}", self.socket_path);
-        
-        // Accept connections in a loop
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    #[cfg(feature = "openvino")]
-                    {
-                        let state = Arc::clone(&self.state);
-                        // Spawn a task to handle the connection
-                        tokio::spawn(async move {
-                            let result = handle_connection_with_state(stream, state).await;
-                            
-                            if let Err(e) = result {
-                                error!("Error handling connection: {}", e);
-                            }
-                        });
-                    }
-                    #[cfg(not(feature = "openvino"))]
-                    {
-                        let ctx = Arc::clone(&self.ctx);
-                        // Spawn a task to handle the connection
-                        tokio::spawn(async move {
-                            let result = handle_connection(stream, ctx).await;
-                            
-                            if let Err(e) = result {
-                                error!("Error handling connection: {}", e);
-                            }
-                        });
-                    }
-                }
-                Err(e) => {
-                    error!("Error accepting connection: {}", e);
-                }
-            }
+        Ok(listener)
+    }
+    
+    /// Transcribe a second of silence with the default model before taking requests, so the
+    /// backend's one-time setup (GPU kernels, buffers) isn't paid for by the first dictation
+    async fn warm_up(&self) {
+        let started = Instant::now();
+        let path = format!("{}/whisp-away-warm-up-{}.wav", crate::paths::runtime_dir(), std::process::id());
+        let mut silence = audio::header(1, 16000, 32000);
+        silence.resize(silence.len() + 32000, 0);
+        if let Err(e) = fs::write(&path, &silence) {
+            warn!("Skipping warm-up: failed to write {}: {}", path, e);
+            return;
+        }
+        let model = {
+            let mut models = self.models.lock().unwrap_or_else(|e| e.into_inner());
+            let name = models.default_model.clone();
+            models.get(&name)
+        };
+        let decoding = Decoding { language: "en", translate: false, beam_size: None, initial_prompt: None, words: false };
+        let never = Arc::new(AtomicBool::new(false));
+        let result = match model {
+            #[cfg(feature = "openvino")]
+            Ok(model) => transcribe_with_state(&path, model.state, decoding, &|_, _| {}, None, &never).await,
+            #[cfg(not(feature = "openvino"))]
+            Ok(model) => transcribe_audio(&path, model.ctx, decoding, &|_, _| {}, None, &never),
+            Err(e) => Err(e),
+        };
+        let _ = fs::remove_file(&path);
+        match result {
+            Ok(_) => info!("Warmed up in {:?}", started.elapsed()),
+            Err(e) => warn!("Warm-up failed: {:#}", e),
         }
-        
-        Ok(())
     }
 }
 
+/// Response to a "jobs" request
+#[derive(Debug, Serialize)]
+struct JobsResponse {
+    success: bool,
+    jobs: Vec<Job>,
+}
+
 async fn handle_connection(
-    mut stream: UnixStream,
-    ctx: Arc<WhisperContext>,
+    mut stream: Connection,
+    models: Arc<Mutex<ModelCache>>,
+    queue: Arc<JobQueue>,
+    capture: Arc<Mutex<Option<Capture>>>,
+    idle: Option<&activation::IdleExit>,
 ) -> Result<()> {
-    // Read request
-    let mut buffer = vec![0; 4096];
-    let n = stream.read(&mut buffer)?;
-    let request_str = String::from_utf8_lossy(&buffer[..n]);
-    
-    // Parse request
-    let request: TranscriptionRequest = serde_json::from_str(&request_str)
-        .context("Failed to parse request")?;
-    
-    info!("Processing audio file: {}", request.audio_path);
-    
-    // Check if file exists
-    if !Path::new(&request.audio_path).exists() {
-        let response = TranscriptionResponse {
-            success: false,
-            text: None,
-            error: Some(format!("Audio file not found: {}", request.audio_path)),
-        };
-        let response_json = serde_json::to_string(&response)?;
-        stream.write_all(response_json.as_bytes())?;
-        return Ok(());
+    let request = read_request(&mut stream, None)?;
+    handle_request(stream, request, models, queue, capture, idle).await
+}
+
+/// Commands a client on another machine may send: they only read the daemon's state
+/// Loading models, cancelling jobs and recording are for clients on this machine
+const REMOTE_COMMANDS: &[&str] = &["hello", "status", "watch", "jobs"];
+
+/// Why a remote client's request is refused: it can transcribe audio it sends inline, but
+/// not reach this machine's files or microphone
+fn remote_refusal(request: &TranscriptionRequest) -> Option<String> {
+    if let Some(command) = &request.command {
+        return (!REMOTE_COMMANDS.contains(&command.as_str()))
+            .then(|| format!("'{}' is only available on the daemon's machine", command));
     }
-    
-    // Check file size (WAV header is 44 bytes)
-    let metadata = fs::metadata(&request.audio_path)?;
-    if metadata.len() <= 44 {
-        warn!("Audio file is empty (only header): {}", request.audio_path);
-        let response = TranscriptionResponse {
-            success: true,
-            text: Some(String::new()),
-            error: None,
-        };
-        let response_json = serde_json::to_string(&response)?;
-        stream.write_all(response_json.as_bytes())?;
-        return Ok(());
+    if request.audio_fd || request.audio_base64.is_none() {
+        return Some("Remote requests must send their audio inline".to_string());
+    }
+    None
+}
+
+/// A remote client's connection and request, once it has sent the right token
+/// Blocks through the TLS handshake and the read, which must be done by the deadline;
+/// None when the client was told its token is wrong, or that it asked for what only
+/// clients on this machine may
+fn accept_remote(remote: &remote::Listener, tcp: std::net::TcpStream, peer: &str) -> Result<Option<(Connection, TranscriptionRequest)>> {
+    let deadline = Instant::now() + remote::REQUEST_DEADLINE;
+    let mut stream = remote.open(tcp, deadline)?;
+    let request = match read_request(&mut stream, Some(deadline)) {
+        Ok(request) => request,
+        Err(e) => {
+            let _ = write_error(&mut stream, format!("{:#}", e));
+            return Err(e);
+        }
+    };
+    if !remote.token.matches(request.token.as_deref()) {
+        warn!("Refusing a request from {} with a wrong or missing token", peer);
+        write_error(&mut stream, remote::TOKEN_REFUSED.to_string())?;
+        return Ok(None);
+    }
+    if let Some(refusal) = remote_refusal(&request) {
+        warn!("Refusing a request from {}: {}", peer, refusal);
+        write_error(&mut stream, refusal)?;
+        return Ok(None);
+    }
+    // Nothing more is read; answering can take as long as the transcription
+    stream.set_read_timeout(None)?;
+    Ok(Some((stream, request)))
+}
+
+async fn handle_request(
+    mut stream: Connection,
+    mut request: TranscriptionRequest,
+    models: Arc<Mutex<ModelCache>>,
+    queue: Arc<JobQueue>,
+    capture: Arc<Mutex<Option<Capture>>>,
+    idle: Option<&activation::IdleExit>,
+) -> Result<()> {
+    match request.command.as_deref() {
+        Some("hello") => {
+            let hello = serde_json::json!({
+                "success": true,
+                "protocol": socket::PROTOCOL_VERSION,
+                "version": env!("CARGO_PKG_VERSION"),
+                "capabilities": CAPABILITIES,
+            });
+            stream.write_all(hello.to_string().as_bytes())?;
+            return Ok(());
+        }
+        Some("status") => {
+            let mut status = models.lock().unwrap_or_else(|e| e.into_inner()).status();
+            status.recording = capture.lock().unwrap_or_else(|e| e.into_inner()).is_some();
+            stream.write_all(serde_json::to_string(&status)?.as_bytes())?;
+            return Ok(());
+        }
+        Some("watch") => return watch(stream, models, queue, capture).await,
+        Some("jobs") => {
+            let response = JobsResponse { success: true, jobs: queue.list() };
+            stream.write_all(serde_json::to_string(&response)?.as_bytes())?;
+            return Ok(());
+        }
+        Some("unload") => {
+            let status = {
+                let mut models = models.lock().unwrap_or_else(|e| e.into_inner());
+                models.unload();
+                models.status()
+            };
+            if !queue.busy() {
+                activity::set(Activity::Unloaded);
+            }
+            stream.write_all(serde_json::to_string(&status)?.as_bytes())?;
+            return Ok(());
+        }
+        Some("load") => {
+            // Loading holds up other requests until the model is ready, as in handle_transcription
+            let result = {
+                let mut models = models.lock().unwrap_or_else(|e| e.into_inner());
+                models.load().map(|()| models.status())
+            };
+            return match result {
+                Ok(status) => {
+                    if !queue.busy() {
+                        activity::set(Activity::Idle);
+                    }
+                    stream.write_all(serde_json::to_string(&status)?.as_bytes())?;
+                    Ok(())
+                }
+                Err(e) => write_error(&mut stream, format!("{:#}", e)),
+            };
+        }
+        Some("start_recording") => {
+            let result = {
+                let mut capture = capture.lock().unwrap_or_else(|e| e.into_inner());
+                if capture.is_some() {
+                    Err(anyhow!("Already recording"))
+                } else {
+                    let source = request.source.unwrap_or_else(|| recording::source_setting(None).value);
+                    Capture::start(source).map(|started| *capture = Some(started))
+                }
+            };
+            let response = match result {
+                Ok(()) => serde_json::json!({ "success": true, "recording": true }),
+                Err(e) => serde_json::json!({ "success": false, "recording": false, "error": format!("{:#}", e) }),
+            };
+            stream.write_all(response.to_string().as_bytes())?;
+            return Ok(());
+        }
+        Some("stop_and_transcribe") => {
+            let Some(recorded) = capture.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+                return write_error(&mut stream, "Not recording".to_string());
+            };
+            match recorded.stop() {
+                Ok(audio_path) => {
+                    request.audio_path = audio_path;
+                    request.recorded = true;
+                }
+                Err(e) => return write_error(&mut stream, format!("{:#}", e)),
+            }
+        }
+        Some("cancel") => {
+            let result = match (request.job, &request.request_id) {
+                (Some(id), _) => queue.cancel(id),
+                (None, Some(request_id)) => queue.cancel_request(request_id),
+                (None, None) => Err(anyhow!("No job given")),
+            };
+            return match result {
+                Ok(()) => write_response(&mut stream, &TranscriptionResponse { success: true, ..Default::default() }),
+                Err(e) => write_error(&mut stream, e.to_string()),
+            };
+        }
+        _ => {}
     }
     
-    // Transcribe using a fresh state for each request
-    let text = transcribe_audio(&request.audio_path, ctx)?;
+    let _transcribing = idle.map(|idle| idle.transcribing());
+    let ticket = queue.submit(request.label(), request.model.clone(), request.request_id.clone());
+    let Some(_turn) = queue.wait_turn(&ticket).await else {
+        info!("Job {} cancelled before it started", ticket.id);
+        return write_cancelled(&mut stream);
+    };
     
-    // Send response
-    let response = TranscriptionResponse {
-        success: true,
-        text: Some(text),
-        error: None,
+    activity::set(Activity::Transcribing);
+    let progress = |done: usize, total: usize| {
+        if total > 1 {
+            info!("Job {}: transcribed window {}/{}", ticket.id, done, total);
+            queue.set_progress(ticket.id, done as f32 / total as f32);
+        }
+    };
+    // Segments go out from their own thread while whisper blocks this one
+    let (segments, streamer) = match request.stream.then(|| stream.try_clone()).transpose()? {
+        Some(mut writer) => {
+            let (sender, receiver) = std::sync::mpsc::channel::<Segment>();
+            let streamer = std::thread::spawn(move || {
+                for segment in receiver {
+                    let line = serde_json::json!({ "segment": segment }).to_string() + "\n";
+                    // The client may have gone away; the job finishes either way
+                    if writer.write_all(line.as_bytes()).is_err() {
+                        break;
+                    }
+                }
+            });
+            (Some(sender), Some(streamer))
+        }
+        None => (None, None),
     };
+    let result = handle_transcription(&request, &models, &progress, segments, &ticket.abort).await;
+    if let Some(streamer) = streamer {
+        let _ = streamer.join();
+    }
+    let success = result.as_ref().is_ok_and(|response| response.success);
+    let keep = queue.finish(&ticket, success);
+    activity::set(if queue.busy() {
+        Activity::Transcribing
+    } else if models.lock().unwrap_or_else(|e| e.into_inner()).unloaded {
+        Activity::Unloaded
+    } else if result.is_ok() || !keep {
+        // An aborted transcription fails, but the user asked for that
+        Activity::Idle
+    } else {
+        Activity::Error
+    });
     
-    let response_json = serde_json::to_string(&response)?;
+    if !keep {
+        info!("Job {} was cancelled while running, discarding its result", ticket.id);
+        return write_cancelled(&mut stream);
+    }
+    match result {
+        Ok(response) => write_response(&mut stream, &response),
+        Err(e) => {
+            write_error(&mut stream, e.to_string())?;
+            Err(e)
+        }
+    }
+}
+
+/// Sent to `watch` subscribers: the status, what the daemon is doing and its queue
+#[derive(Debug, Serialize)]
+struct WatchState {
+    #[serde(flatten)]
+    status: DaemonStatus,
+    state: Activity,
+    jobs: Vec<Job>,
+}
+
+/// Send the daemon's state to a `watch` subscriber (the tray) as a JSON line, then again
+/// whenever it changes, until the subscriber disconnects
+async fn watch(
+    mut stream: Connection,
+    models: Arc<Mutex<ModelCache>>,
+    queue: Arc<JobQueue>,
+    capture: Arc<Mutex<Option<Capture>>>,
+) -> Result<()> {
+    debug!("Client subscribed to the daemon's state");
+    let mut status = None;
+    let mut last = String::new();
+    loop {
+        // A model being loaded holds the cache, so its last status stands in
+        match models.try_lock() {
+            Ok(models) => status = Some(models.status()),
+            Err(TryLockError::Poisoned(e)) => status = Some(e.into_inner().status()),
+            Err(TryLockError::WouldBlock) => {}
+        }
+        if let Some(status) = &status {
+            let mut status = status.clone();
+            status.recording = capture.lock().unwrap_or_else(|e| e.into_inner()).is_some();
+            let mut state = WatchState {
+                status,
+                state: if LOADING.load(Ordering::SeqCst) { Activity::Loading } else { activity::current() },
+                jobs: queue.list(),
+            };
+            let line = serde_json::to_string(&state)?;
+            // Idle times tick on their own, so they alone don't send a line
+            for model in &mut state.status.models {
+                model.idle_secs = 0;
+            }
+            let compared = serde_json::to_string(&state)?;
+            if compared != last {
+                if let Err(e) = stream.write_all(format!("{}\n", line).as_bytes()) {
+                    debug!("State subscriber went away: {}", e);
+                    return Ok(());
+                }
+                last = compared;
+            }
+        }
+        tokio::time::sleep(WATCH_INTERVAL).await;
+    }
+}
+
+fn write_response(stream: &mut Connection, response: &TranscriptionResponse) -> Result<()> {
+    let response_json = serde_json::to_string(response)?;
     stream.write_all(response_json.as_bytes())?;
-    
     Ok(())
 }
 
-#[cfg(feature = "openvino")]
-async fn handle_connection_with_state(
-    mut stream: UnixStream,
-    state: Arc<tokio::sync::Mutex<WhisperState>>,
-) -> Result<()> {
-    // Read request
-    let mut buffer = vec![0; 4096];
-    let n = stream.read(&mut buffer)?;
-    let request_str = String::from_utf8_lossy(&buffer[..n]);
-    
-    // Parse request
-    let request: TranscriptionRequest = serde_json::from_str(&request_str)
-        .context("Failed to parse request")?;
+fn write_error(stream: &mut Connection, error: String) -> Result<()> {
+    write_response(stream, &TranscriptionResponse {
+        success: false,
+        error: Some(error),
+        ..Default::default()
+    })
+}
+
+fn write_cancelled(stream: &mut Connection) -> Result<()> {
+    write_response(stream, &TranscriptionResponse {
+        success: false,
+        error: Some("Cancelled".to_string()),
+        cancelled: true,
+        ..Default::default()
+    })
+}
+
+async fn handle_transcription(
+    request: &TranscriptionRequest,
+    models: &Mutex<ModelCache>,
+    progress: &(dyn Fn(usize, usize) + Sync),
+    segments: Option<Sender<Segment>>,
+    abort: &Arc<AtomicBool>,
+) -> Result<TranscriptionResponse> {
+    let audio = RequestAudio::from_request(request)?;
     
-    info!("Processing audio file: {}", request.audio_path);
+    info!("Processing audio file: {}", audio.path);
     
     // Check if file exists
-    if !Path::new(&request.audio_path).exists() {
-        let response = TranscriptionResponse {
+    if !Path::new(&audio.path).exists() {
+        return Ok(TranscriptionResponse {
             success: false,
-            text: None,
-            error: Some(format!("Audio file not found: {}", request.audio_path)),
-        };
-        let response_json = serde_json::to_string(&response)?;
-        stream.write_all(response_json.as_bytes())?;
-        return Ok(());
+            error: Some(format!("Audio file not found: {}", audio.path)),
+            ..Default::default()
+        });
     }
     
-    // Check file size (WAV header is 44 bytes)
-    let metadata = fs::metadata(&request.audio_path)?;
-    if metadata.len() <= 44 {
-        warn!("Audio file is empty (only header): {}", request.audio_path);
-        let response = TranscriptionResponse {
+    if audio::is_empty(&audio.path) {
+        warn!("Audio file is empty (only header): {}", audio.path);
+        return Ok(TranscriptionResponse {
             success: true,
             text: Some(String::new()),
-            error: None,
-        };
-        let response_json = serde_json::to_string(&response)?;
-        stream.write_all(response_json.as_bytes())?;
-        return Ok(());
+            ..Default::default()
+        });
     }
     
-    // Transcribe using the reusable state
-    let text = transcribe_with_state(&request.audio_path, state).await?;
-    
-    // Send response
-    let response = TranscriptionResponse {
-        success: true,
-        text: Some(text),
-        error: None,
+    // Loading a model that isn't resident holds up other requests until it's ready
+    let (model_name, model) = {
+        let mut models = models.lock().unwrap_or_else(|e| e.into_inner());
+        if models.unloaded {
+            info!("Models are unloaded, refusing the transcription");
+            return Ok(TranscriptionResponse {
+                success: false,
+                error: Some("Models are unloaded (whisp-away daemon load)".to_string()),
+                unloaded: true,
+                ..Default::default()
+            });
+        }
+        let name = request.model.clone().unwrap_or_else(|| models.default_model.clone());
+        let model = models.get(&name);
+        (name, model)
     };
+    let model = model?;
     
-    let response_json = serde_json::to_string(&response)?;
-    stream.write_all(response_json.as_bytes())?;
+    // OpenVINO reuses the model's pre-initialized state, otherwise each request gets a fresh one
+    let started = Instant::now();
+    let language = request.language();
+    #[cfg(feature = "openvino")]
+    let transcript = transcribe_with_state(&audio.path, Arc::clone(&model.state), request.decoding(&language), progress, segments, abort).await?;
+    #[cfg(not(feature = "openvino"))]
+    let transcript = transcribe_audio(&audio.path, Arc::clone(&model.ctx), request.decoding(&language), progress, segments, abort)?;
+    let inference_secs = started.elapsed().as_secs_f64();
     
-    Ok(())
+    Ok(TranscriptionResponse {
+        success: true,
+        text: Some(transcript.text),
+        segments: transcript.segments,
+        language: transcript.language,
+        model: Some(model_name),
+        duration: Some(transcript.duration),
+        inference_secs: Some(inference_secs),
+        rtf: (transcript.duration > 0.0).then(|| inference_secs / transcript.duration),
+        confidence: transcript.confidence.map(f64::from),
+        no_speech_prob: transcript.no_speech_prob.map(f64::from),
+        ..Default::default()
+    })
 }
 
 #[cfg(feature = "openvino")]
 async fn transcribe_with_state(
     audio_path: &str,
     state: Arc<tokio::sync::Mutex<WhisperState>>,
-) -> Result<String> {
-    use std::time::Instant;
+    decoding: Decoding<'_>,
+    progress: &(dyn Fn(usize, usize) + Sync),
+    segments: Option<Sender<Segment>>,
+    abort: &Arc<AtomicBool>,
+) -> Result<Transcript> {
     let start = Instant::now();
     
-    // Load and convert audio 
-    let t1 = Instant::now();
-    let audio_data = std::fs::read(audio_path)
-        .context("Failed to read audio file")?;
-    eprintln!("DEBUG DAEMON: File read took {:?}", t1.elapsed());
-    
-    let t2 = Instant::now();
-    let samples = wav_to_samples(&audio_data)?;
-    eprintln!("DEBUG DAEMON: WAV conversion took {:?}", t2.elapsed());
-    
     // Lock the state for exclusive use
     let mut state = state.lock().await;
     eprintln!("DEBUG DAEMON: Using pre-initialized state with OpenVINO");
     
-    // Set up parameters - optimized for speed
-    let t4 = Instant::now();
-    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
-    let num_threads = std::thread::available_parallelism()
-        .map(|n| n.get() as i32)
-        .unwrap_or(8);
-    params.set_n_threads(num_threads);
-    params.set_translate(false);
-    params.set_language(Some("en"));
-    params.set_print_special(false);
-    params.set_print_progress(false);
-    params.set_print_timestamps(false);
-    params.set_suppress_blank(true);
-    params.set_temperature(0.0);
-    params.set_single_segment(false);
-    params.set_no_context(true);
-    eprintln!("DEBUG DAEMON: Params setup took {:?}", t4.elapsed());
-    
-    // Run transcription
-    let t5 = Instant::now();
-    eprintln!("DEBUG DAEMON: Starting whisper transcription with {} samples...", samples.len());
-    state.full(params, &samples)
-        .context("Failed to transcribe audio")?;
-    eprintln!("DEBUG DAEMON: Whisper transcription completed in {:?}", t5.elapsed());
-    
-    // Get the transcribed text from segments
-    let t6 = Instant::now();
-    let mut text = String::new();
-    let num_segments = state.full_n_segments();
-    for i in 0..num_segments {
-        let segment = state.get_segment(i)
-            .ok_or_else(|| anyhow!("Failed to get segment {}", i))?;
-        let segment_text = segment.to_str()?;
-        text.push_str(segment_text);
-        text.push(' ');
-    }
-    eprintln!("DEBUG DAEMON: Segment extraction took {:?}", t6.elapsed());
-    
+    let transcript = windows::transcribe(&mut state, audio_path, decoding, |params| configure(params, abort), progress, segments)?;
     eprintln!("DEBUG DAEMON: Total transcription time: {:?}", start.elapsed());
     
-    Ok(text.trim().to_string())
+    Ok(transcript)
 }
 
 fn transcribe_audio(
     audio_path: &str,
     ctx: Arc<WhisperContext>,
-) -> Result<String> {
-    use std::time::Instant;
+    decoding: Decoding<'_>,
+    progress: &(dyn Fn(usize, usize) + Sync),
+    segments: Option<Sender<Segment>>,
+    abort: &Arc<AtomicBool>,
+) -> Result<Transcript> {
     let start = Instant::now();
     
-    // Load and convert audio 
-    let t1 = Instant::now();
-    let audio_data = std::fs::read(audio_path)
-        .context("Failed to read audio file")?;
-    eprintln!("DEBUG DAEMON: File read took {:?}", t1.elapsed());
-    
-    let t2 = Instant::now();
-    let samples = wav_to_samples(&audio_data)?;
-    eprintln!("DEBUG DAEMON: WAV conversion took {:?}", t2.elapsed());
-    
     // Create a fresh state for this transcription
-    let t3 = Instant::now();
+    let t1 = Instant::now();
     let mut state = ctx.create_state()
         .context("Failed to create whisper state")?;
-    eprintln!("DEBUG DAEMON: State creation took {:?}", t3.elapsed());
+    eprintln!("DEBUG DAEMON: State creation took {:?}", t1.elapsed());
     eprintln!("DEBUG DAEMON: OpenVINO (if configured) was initialized automatically at context creation");
     
-    // Set up parameters - optimized for speed
-    let t4 = Instant::now();
-    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+    let transcript = windows::transcribe(&mut state, audio_path, decoding, |params| configure(params, abort), progress, segments)?;
+    eprintln!("DEBUG DAEMON: Total transcription time: {:?}", start.elapsed());
+    
+    Ok(transcript)
+}
+
+/// Parameters optimized for speed, on top of language and task; whisper gives up once
+/// `abort` is set
+fn configure(params: &mut FullParams, abort: &Arc<AtomicBool>) {
     let num_threads = std::thread::available_parallelism()
         .map(|n| n.get() as i32)
         .unwrap_or(8);
     params.set_n_threads(num_threads);
-    params.set_translate(false);
-    params.set_language(Some("en"));
     params.set_print_special(false);
     params.set_print_progress(false);
     params.set_print_timestamps(false);
@@ -410,30 +1104,30 @@ fn transcribe_audio(
     params.set_temperature(0.0);
     params.set_single_segment(false);
     params.set_no_context(true);
-    eprintln!("DEBUG DAEMON: Params setup took {:?}", t4.elapsed());
-    
-    // Run transcription
-    let t5 = Instant::now();
-    eprintln!("DEBUG DAEMON: Starting whisper transcription with {} samples...", samples.len());
-    state.full(params, &samples)
-        .context("Failed to transcribe audio")?;
-    eprintln!("DEBUG DAEMON: Whisper transcription completed in {:?}", t5.elapsed());
-    
-    // Get the transcribed text from segments
-    let t6 = Instant::now();
-    let mut text = String::new();
-    let num_segments = state.full_n_segments();
-    for i in 0..num_segments {
-        let segment = state.get_segment(i)
-            .ok_or_else(|| anyhow!("Failed to get segment {}", i))?;
-        let segment_text = segment.to_str()?;
-        text.push_str(segment_text);
-        text.push(' ');
-    }
-    eprintln!("DEBUG DAEMON: Segment extraction took {:?}", t6.elapsed());
-    
-    eprintln!("DEBUG DAEMON: Total transcription time: {:?}", start.elapsed());
-    
-    Ok(text.trim().to_string())
+    let abort = Arc::clone(abort);
+    params.set_abort_callback_safe(move || abort.load(Ordering::Relaxed));
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: &str) -> TranscriptionRequest {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn remote_clients_only_send_inline_audio_and_read_state() {
+        assert_eq!(remote_refusal(&request(r#"{"audio_path": "/tmp/rec.wav", "audio_base64": "UklGRg=="}"#)), None);
+        assert_eq!(remote_refusal(&request(r#"{"command": "status"}"#)), None);
+        assert_eq!(remote_refusal(&request(r#"{"command": "watch"}"#)), None);
+
+        for json in [r#"{"audio_path": "/etc/shadow"}"#, r#"{"audio_path": "/tmp/rec.wav", "audio_fd": true, "audio_base64": "UklGRg=="}"#] {
+            assert_eq!(remote_refusal(&request(json)).as_deref(), Some("Remote requests must send their audio inline"), "{}", json);
+        }
+        for command in ["start_recording", "stop_and_transcribe", "load", "unload", "cancel"] {
+            let refusal = remote_refusal(&request(&format!(r#"{{"command": "{}"}}"#, command)));
+            assert_eq!(refusal, Some(format!("'{}' is only available on the daemon's machine", command)));
+        }
+    }
+}
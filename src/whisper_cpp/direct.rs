@@ -1,34 +1,36 @@
 use anyhow::{anyhow, Context, Result};
-use std::fs;
 use std::process::Command;
-use whisper_rs::{WhisperContext, WhisperContextParameters, FullParams, SamplingStrategy};
-use crate::helpers::wav_to_samples;
-use crate::typing;
+use tracing::debug;
+use whisper_rs::{WhisperContext, WhisperContextParameters};
+use std::time::Instant;
+use crate::failover::{self, Attempt, Step};
+use crate::helpers;
+use crate::settings::{Layers, Resolved};
+use crate::typing::Output;
+
+/// Resolves extra flags for the whisper.cpp binary with priority:
+/// 1. CLI flags (--whisper-arg, repeatable)
+/// 2. WA_WHISPER_ARGS env var, split on whitespace
+/// 3. User config file `whisper_args` (a list)
+/// 4. Default to none
+pub fn extra_args_setting() -> Resolved<Vec<String>> {
+    let cli = &helpers::overrides().whisper_args;
+    Layers::new("whisper.cpp args")
+        .cli((!cli.is_empty()).then(|| cli.clone()))
+        .env("WA_WHISPER_ARGS", |value| Some(value.split_whitespace().map(str::to_string).collect()))
+        .config(|c| c.whisper_args)
+        .or_default(Vec::new())
+}
 
 /// Core transcription function using whisper-rs library
 pub fn transcribe_audio(audio_file: &str, model: &str) -> Result<String> {
     let total_start = std::time::Instant::now();
     
-    let home = std::env::var("HOME").unwrap_or_else(|_| "/home/martin".to_string());
-    let model_extension = if model.ends_with(".bin") { "" } else { ".bin" };
-    let model_path = format!("{}/.cache/whisper-cpp/models/ggml-{}{}", home, model, model_extension);
-    
-    if !std::path::Path::new(&model_path).exists() {
-        return Err(anyhow::anyhow!("Model file not found: {}", model_path));
-    }
-    
-    let t1 = std::time::Instant::now();
-    let audio_data = fs::read(audio_file)
-        .context("Failed to read audio file")?;
-    eprintln!("DEBUG FALLBACK: Audio file read took {:?}", t1.elapsed());
-    
-    let t2 = std::time::Instant::now();
-    let samples = wav_to_samples(&audio_data)?;
-    eprintln!("DEBUG FALLBACK: WAV conversion took {:?}", t2.elapsed());
+    let model_path = crate::paths::whisper_cpp_model_path(model);
+    super::models::ensure_intact(model)?;
     
     eprintln!("DEBUG FALLBACK: Starting whisper-rs transcription for file: {}", audio_file);
     eprintln!("DEBUG FALLBACK: Model path: {}", model_path);
-    eprintln!("DEBUG FALLBACK: Audio samples: {} samples", samples.len());
     
     let mut ctx_params = WhisperContextParameters::default();
     ctx_params.use_gpu(true);
@@ -82,44 +84,40 @@ pub fn transcribe_audio(audio_file: &str, model: &str) -> Result<String> {
         }
     }
     
-    let t6 = std::time::Instant::now();
-    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
-    
-    // Match the native CLI's thread count more closely
-    let num_threads = 4;  // Try with 4 threads like CLI default
-    params.set_n_threads(num_threads);
-    eprintln!("DEBUG FALLBACK: Using {} threads (forced to 4 to match CLI)", num_threads);
-    
-    params.set_translate(false);
-    params.set_language(Some("en"));
-    params.set_print_special(false);
-    params.set_print_progress(false);
-    params.set_print_timestamps(false);
-    params.set_suppress_blank(true);
-    params.set_temperature(0.0);
-    eprintln!("DEBUG FALLBACK: Param setup took {:?}", t6.elapsed());
-    
     eprintln!("DEBUG FALLBACK: Starting transcription...");
-    let t7 = std::time::Instant::now();
-    state.full(params, &samples)
-        .context("Failed to transcribe audio")?;
-    eprintln!("DEBUG FALLBACK: Whisper transcription (state.full) took {:?}", t7.elapsed());
-    
-    let t8 = std::time::Instant::now();
-    let mut transcribed_text = String::new();
-    let num_segments = state.full_n_segments();
-    for i in 0..num_segments {
-        let segment = state.get_segment(i)
-            .ok_or_else(|| anyhow!("Failed to get segment {}", i))?;
-        let segment_text = segment.to_str()?;
-        eprintln!("DEBUG FALLBACK: Segment: {:?}", segment_text);
-        transcribed_text.push_str(segment_text);
-        transcribed_text.push(' ');
-    }
-    eprintln!("DEBUG FALLBACK: Segment extraction took {:?}", t8.elapsed());
+    let t6 = std::time::Instant::now();
+    let language = crate::helpers::resolve_language();
+    let initial_prompt = crate::helpers::resolve_initial_prompt();
+    let transcript = super::windows::transcribe(
+        &mut state,
+        audio_file,
+        super::windows::Decoding {
+            language: &language,
+            translate: crate::helpers::resolve_translate(),
+            beam_size: crate::helpers::overrides().beam_size,
+            initial_prompt: initial_prompt.as_deref(),
+            words: false,
+        },
+        |params| {
+            // Match the native CLI's thread count more closely
+            params.set_n_threads(4);
+            params.set_print_special(false);
+            params.set_print_progress(false);
+            params.set_print_timestamps(false);
+            params.set_suppress_blank(true);
+            params.set_temperature(0.0);
+        },
+        |done, total| {
+            if total > 1 {
+                debug!("Transcribed window {}/{}", done, total);
+            }
+        },
+        None,
+    )?;
+    eprintln!("DEBUG FALLBACK: Whisper transcription took {:?}", t6.elapsed());
     
-    let clean_text = transcribed_text.trim().to_string();
-    eprintln!("DEBUG FALLBACK: Final transcription: {:?}", clean_text);
+    let clean_text = transcript.text;
+    eprintln!("DEBUG FALLBACK: Final transcription: '{}'", crate::privacy::redact(&clean_text));
     eprintln!("DEBUG FALLBACK: TOTAL TIME: {:?}", total_start.elapsed());
     
     Ok(clean_text)
@@ -127,47 +125,61 @@ pub fn transcribe_audio(audio_file: &str, model: &str) -> Result<String> {
 
 
 /// Transcribe audio using whisper-cpp CLI binary
-pub fn transcribe_with_cli(audio_file: &str, model: &str, whisper_path: &str, use_clipboard: bool) -> Result<()> {
+/// `attempts` are the ways of transcribing that failed before, for the history
+pub fn transcribe_with_cli(audio_file: &str, model: &str, whisper_path: &str, output: &Output, attempts: Vec<Attempt>) -> Result<()> {
     let acceleration = crate::helpers::get_acceleration_type();
     let transcribe_msg = format!("⏳ Transcribing with CLI... ({})", acceleration);
     
-    Command::new("notify-send")
-        .args(&[
-            "Voice Input (whisper.cpp)",
-            &transcribe_msg,
-            "-t", "2000",
-            "-h", "string:x-canonical-private-synchronous:voice"
-        ])
-        .spawn()?;
+    crate::helpers::send_notification("Voice Input (whisper.cpp)", &transcribe_msg, 2000);
 
-    let home = std::env::var("HOME").unwrap_or_else(|_| "/home/martin".to_string());
-    let model_extension = if model.ends_with(".bin") { "" } else { ".bin" };
-    let model_path = format!("{}/.cache/whisper-cpp/models/ggml-{}{}", home, model, model_extension);
-    
-    let output = Command::new(whisper_path)
-        .args(&[
-            "-m", &model_path,
-            "-f", audio_file,
-            "-t", "8",
-            "-np",
-            "-nt"
-        ])
+    let started = Instant::now();
+    match transcribe_audio_cli(audio_file, model, whisper_path) {
+        Ok(text) => failover::deliver_direct(text, model, Step::WhisperCppCli, started, attempts, "whisper-cpp CLI", output),
+        Err(e) => {
+            crate::helpers::send_notification("Voice Input (whisper.cpp)", "❌ Transcription failed", 2000);
+            Err(e)
+        }
+    }
+}
+
+/// Core transcription function running the whisper-cpp CLI binary
+pub fn transcribe_audio_cli(audio_file: &str, model: &str, whisper_path: &str) -> Result<String> {
+    let model_path = crate::paths::whisper_cpp_model_path(model);
+    let language = crate::helpers::resolve_language();
+    
+    let mut command = Command::new(whisper_path);
+    command.args(&[
+        "-m", &model_path,
+        "-f", audio_file,
+        "-l", &language,
+        "-t", "8",
+        "-np",
+        "-nt"
+    ]);
+    if crate::helpers::resolve_translate() {
+        command.arg("-tr");
+    }
+    if let Some(beam_size) = crate::helpers::overrides().beam_size {
+        command.args(["-bs", &beam_size.to_string()]);
+    }
+    if let Some(prompt) = crate::helpers::resolve_initial_prompt() {
+        command.args(["--prompt", &prompt]);
+    }
+    // Last, so they can override the flags above
+    let extra_args = extra_args_setting().value;
+    if !extra_args.is_empty() {
+        debug!("Extra whisper.cpp args: {:?}", extra_args);
+        command.args(&extra_args);
+    }
+    let cli_output = command
         .output()
         .context("Failed to run whisper-cpp")?;
 
-    if !output.status.success() {
-        Command::new("notify-send")
-            .args(&[
-                "Voice Input (whisper.cpp)",
-                "❌ Transcription failed",
-                "-t", "2000",
-                "-h", "string:x-canonical-private-synchronous:voice"
-            ])
-            .spawn()?;
-        return Err(anyhow!("whisper-cpp failed: {}", String::from_utf8_lossy(&output.stderr)));
+    if !cli_output.status.success() {
+        return Err(anyhow!("whisper-cpp failed: {}", String::from_utf8_lossy(&cli_output.stderr)));
     }
 
-    let stdout_text = String::from_utf8_lossy(&output.stdout);
+    let stdout_text = String::from_utf8_lossy(&cli_output.stdout);
     let mut result = String::new();
     
     for line in stdout_text.lines() {
@@ -184,38 +196,22 @@ pub fn transcribe_with_cli(audio_file: &str, model: &str, whisper_path: &str, us
         }
     }
 
-    typing::output_text(result.trim(), use_clipboard, "whisper-cpp CLI")?;
-    Ok(())
+    Ok(result.trim().to_string())
 }
 
 /// Transcribe audio from file and type the result using wtype
-pub fn transcribe_with_whisper_rs(audio_file: &str, model: &str, _whisper_path: &str, use_clipboard: bool) -> Result<()> {
+/// `attempts` are the ways of transcribing that failed before, for the history
+pub fn transcribe_with_whisper_rs(audio_file: &str, model: &str, _whisper_path: &str, output: &Output, attempts: Vec<Attempt>) -> Result<()> {
     let acceleration = crate::helpers::get_acceleration_type();
     let transcribe_msg = format!("⏳ Transcribing with GPU... ({})", acceleration);
     
-    Command::new("notify-send")
-        .args(&[
-            "Voice Input (whisper.cpp)",
-            &transcribe_msg,
-            "-t", "2000",
-            "-h", "string:x-canonical-private-synchronous:voice"
-        ])
-        .spawn()?;
+    crate::helpers::send_notification("Voice Input (whisper.cpp)", &transcribe_msg, 2000);
 
+    let started = Instant::now();
     match transcribe_audio(audio_file, model) {
-        Ok(clean_text) => {
-        typing::output_text(&clean_text, use_clipboard, "whisper-cpp")?;
-            Ok(())
-        }
+        Ok(clean_text) => failover::deliver_direct(clean_text, model, Step::WhisperCpp, started, attempts, "whisper-cpp", output),
         Err(e) => {
-            Command::new("notify-send")
-                .args(&[
-                    "Voice Input (whisper.cpp)",
-                    "❌ Model file not found",
-                    "-t", "2000",
-                    "-h", "string:x-canonical-private-synchronous:voice"
-                ])
-                .spawn()?;
+            crate::helpers::send_notification("Voice Input (whisper.cpp)", "❌ Model file not found", 2000);
             Err(e)
         }
     }
@@ -0,0 +1,30 @@
+use anyhow::Result;
+use std::path::Path;
+use tracing::debug;
+use crate::paths;
+use crate::settings::{Layers, Resolved};
+use super::models;
+
+/// Resolves the model of the embedded engine with priority:
+/// 1. WA_EMBEDDED_MODEL env var
+/// 2. User config file `embedded_model`
+/// 3. Default to "base" (multilingual, 142 MB)
+pub fn model_setting() -> Resolved<String> {
+    Layers::new("embedded model")
+        .env("WA_EMBEDDED_MODEL", Some)
+        .config(|c| c.embedded_model)
+        .or_default("base".to_string())
+}
+
+/// Transcribe with the whisper.cpp built into the binary, downloading its model on first use
+/// Needs nothing but curl, so a portable build works without Python or a whisper.cpp install
+pub fn transcribe_audio(audio_file: &str) -> Result<String> {
+    let model = model_setting().value;
+    let path = paths::whisper_cpp_model_path(&model);
+    if Path::new(&path).exists() {
+        debug!("Embedded model {} found at {}", model, path);
+    } else {
+        models::download(&model)?;
+    }
+    super::direct::transcribe_audio(audio_file, &model)
+}
@@ -1,6 +1,11 @@
 pub mod client;
 pub mod daemon;
 pub mod direct;
+#[cfg(feature = "portable")]
+pub mod embedded;
+pub mod models;
+mod queue;
+mod windows;
 
 pub use client::stop_and_transcribe_daemon;
-pub use daemon::run_daemon;
\ No newline at end of file
+pub use daemon::run_daemon;
@@ -0,0 +1,375 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+use std::process::Command;
+use tracing::{debug, info, warn};
+use crate::helpers;
+use crate::models::GGML_MAGIC;
+use crate::paths;
+
+/// Where ggml models are downloaded from
+const MODEL_URL: &str = "https://huggingface.co/ggerganov/whisper.cpp/resolve/main";
+
+/// Hyperparameters after the magic: vocabulary, audio and text sizes, mels and ftype
+const HPARAM_COUNT: usize = 11;
+
+/// Check a model and, when it is damaged, download it again if it is one of the
+/// ggerganov/whisper.cpp models in the models directory
+/// `model` is a model name ("base.en") or the path of a model file
+pub fn ensure_intact(model: &str) -> Result<()> {
+    let path = resolve_path(model);
+    if !Path::new(&path).exists() {
+        if let Some(message) = crate::models::mismatch(model, "whisper-cpp", &crate::models::scan()) {
+            return Err(anyhow!("{}", message));
+        }
+        return Err(anyhow!("Model file not found: {}", path));
+    }
+    let Err(e) = verify(&path) else {
+        return Ok(());
+    };
+    let Some(name) = downloadable_name(model) else {
+        return Err(e);
+    };
+    warn!("{:#}; downloading it again", e);
+    helpers::send_notification("Voice Input", &format!("⚠️ The {} model is damaged\nDownloading it again...", name), 5000);
+    download(&name).map_err(|download_error| anyhow!("{:#}, and downloading it again failed: {:#}", e, download_error))
+}
+
+/// The model file a name or path refers to
+fn resolve_path(model: &str) -> String {
+    if model.contains('/') {
+        model.to_string()
+    } else {
+        paths::whisper_cpp_model_path(model)
+    }
+}
+
+/// A model name whose file is in the models directory, where a download can replace it
+fn downloadable_name(model: &str) -> Option<String> {
+    (!model.contains('/')).then(|| model.strip_suffix(".bin").unwrap_or(model).to_string())
+}
+
+/// Download a model to the models directory, replacing a damaged copy only once the new one
+/// is complete and checked
+pub fn download(model: &str) -> Result<()> {
+    let path = paths::whisper_cpp_model_path(model);
+    let file_name = Path::new(&path).file_name().context("Invalid model path")?.to_string_lossy().into_owned();
+    let url = format!("{}/{}", MODEL_URL, file_name);
+    info!("Downloading {} to {}", url, path);
+    helpers::send_notification("Voice Input", &format!("⬇️ Downloading the {} model...", model), 5000);
+
+    if let Some(dir) = Path::new(&path).parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    // Downloaded next to the model and renamed once complete, so an interrupted download
+    // is never mistaken for the model (and parallel downloads don't share a file)
+    let partial = format!("{}.{}.part", path, std::process::id());
+    let headers = format!("{}.headers", partial);
+    let result = fetch(&url, &partial, &headers).and_then(|()| {
+        verify_structure(&partial)?;
+        // Hugging Face names the SHA-256 of LFS files in the X-Linked-Etag header
+        let expected = fs::read_to_string(&headers).ok().and_then(|headers| linked_etag(&headers));
+        if let Some(expected) = &expected {
+            let actual = sha256(&partial)?;
+            if &actual != expected {
+                return Err(anyhow!("Downloaded {} doesn't match its checksum (expected {}, got {})", file_name, expected, actual));
+            }
+            fs::write(checksum_path(&path), format!("{}  {}\n", expected, file_name))
+                .context("Failed to save the model checksum")?;
+        }
+        Ok(expected)
+    });
+    let _ = fs::remove_file(&headers);
+    let expected = match result {
+        Ok(expected) => expected,
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            return Err(e.context(format!("Failed to download the {} model", model)));
+        }
+    };
+    fs::rename(&partial, &path).context("Failed to save the downloaded model")?;
+    if expected.is_some() {
+        write_stamp(&path);
+    }
+    info!("Downloaded model {}", model);
+    Ok(())
+}
+
+fn fetch(url: &str, output: &str, headers: &str) -> Result<()> {
+    let result = Command::new("curl")
+        .args(["--fail", "--location", "--silent", "--show-error", "--dump-header", headers, "--output", output, url])
+        .output()
+        .context("Failed to run curl, which downloads the model")?;
+    if !result.status.success() {
+        return Err(anyhow!("{}", String::from_utf8_lossy(&result.stderr).trim()));
+    }
+    Ok(())
+}
+
+/// The SHA-256 in an `X-Linked-Etag` header, of any response in a redirect chain
+fn linked_etag(headers: &str) -> Option<String> {
+    headers.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if !name.trim().eq_ignore_ascii_case("x-linked-etag") {
+            return None;
+        }
+        let value = value.trim().trim_matches('"').to_lowercase();
+        (value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())).then_some(value)
+    })
+}
+
+/// `ggml-base.en.bin.sha256` next to the model, in `sha256sum` format
+fn checksum_path(model_path: &str) -> String {
+    format!("{}.sha256", model_path)
+}
+
+/// Records the size and modification time of a model whose checksum matched, so the
+/// (slow, for large models) hash is only computed again when the file changes
+fn stamp_path(model_path: &str) -> String {
+    format!("{}.verified", model_path)
+}
+
+fn file_stamp(path: &str) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some(format!("{} {}", metadata.len(), modified.as_nanos()))
+}
+
+fn write_stamp(path: &str) {
+    if let Some(stamp) = file_stamp(path) {
+        let _ = fs::write(stamp_path(path), stamp);
+    }
+}
+
+/// Check that a model file is complete and, when a checksum is kept next to it, that it
+/// matches; the error names the file and what is wrong with it
+fn verify(path: &str) -> Result<()> {
+    verify_structure(path)?;
+
+    let Ok(checksum) = fs::read_to_string(checksum_path(path)) else {
+        return Ok(());
+    };
+    let Some(expected) = checksum.split_whitespace().next().map(str::to_lowercase) else {
+        return Ok(());
+    };
+    if file_stamp(path).is_some_and(|stamp| fs::read_to_string(stamp_path(path)).ok().as_deref() == Some(stamp.as_str())) {
+        debug!("{} already verified against {}", path, checksum_path(path));
+        return Ok(());
+    }
+    debug!("Checking {} against {}", path, checksum_path(path));
+    let actual = sha256(path)?;
+    if actual != expected {
+        return Err(anyhow!(
+            "Model file {} is corrupted: its SHA-256 is {}, but {} expects {}",
+            path, actual, checksum_path(path), expected
+        ));
+    }
+    write_stamp(path);
+    Ok(())
+}
+
+/// SHA-256 of a file with coreutils `sha256sum`, or `shasum` on macOS
+fn sha256(path: &str) -> Result<String> {
+    let output = Command::new("sha256sum").arg(path).output()
+        .or_else(|_| Command::new("shasum").args(["-a", "256", path]).output())
+        .context("Failed to checksum the model (needs sha256sum or shasum)")?;
+    if !output.status.success() {
+        return Err(anyhow!("Failed to checksum {}: {}", path, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    String::from_utf8_lossy(&output.stdout).split_whitespace().next()
+        .map(str::to_lowercase)
+        .ok_or_else(|| anyhow!("Failed to checksum {}", path))
+}
+
+/// Walk the ggml file the way whisper.cpp loads it (header, mel filters, vocabulary, then
+/// tensors up to the end of the file) without reading the weights, so a truncated or
+/// garbled model is caught before whisper fails on it with a cryptic load error
+fn verify_structure(path: &str) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("Failed to open model file {}", path))?;
+    let len = file.metadata().with_context(|| format!("Failed to read model file {}", path))?.len();
+    let mut reader = ModelReader { inner: BufReader::new(file), len };
+    let truncated = |section: &str| anyhow!(
+        "Model file {} is truncated: its {} bytes end partway through the {} (an interrupted download?)",
+        path, len, section
+    );
+    // Sizes too large to add up can only come from a garbled header
+    let corrupted = |section: &str| anyhow!("Model file {} is corrupted: the {} claim an impossible size", path, section);
+
+    let magic = reader.u32().ok_or_else(|| truncated("header"))?;
+    if magic != GGML_MAGIC {
+        return Err(anyhow!("{} is not a whisper.cpp (ggml) model: bad magic number {:#010x}", path, magic));
+    }
+    let mut hparams = [0u32; HPARAM_COUNT];
+    for value in &mut hparams {
+        *value = reader.u32().ok_or_else(|| truncated("header"))?;
+    }
+
+    let n_mel = reader.u32().ok_or_else(|| truncated("mel filters"))? as u64;
+    let n_fft = reader.u32().ok_or_else(|| truncated("mel filters"))? as u64;
+    let filters = n_mel.checked_mul(n_fft).and_then(|count| count.checked_mul(4))
+        .ok_or_else(|| corrupted("mel filters"))?;
+    reader.skip(filters).ok_or_else(|| truncated("mel filters"))?;
+
+    let n_vocab = reader.u32().ok_or_else(|| truncated("vocabulary"))?;
+    if n_vocab != hparams[0] {
+        debug!("{}: vocabulary has {} tokens, header says {}", path, n_vocab, hparams[0]);
+    }
+    for _ in 0..n_vocab {
+        let token_len = reader.u32().ok_or_else(|| truncated("vocabulary"))?;
+        reader.skip(token_len as u64).ok_or_else(|| truncated("vocabulary"))?;
+    }
+
+    let mut tensors = 0;
+    while !reader.at_end() {
+        let section = format!("weights (tensor {})", tensors);
+        let (n_dims, name_len, ttype) = (|| Some((reader.u32()?, reader.u32()?, reader.u32()?)))()
+            .ok_or_else(|| truncated(&section))?;
+        if !(1..=4).contains(&n_dims) || name_len == 0 || name_len > 1024 {
+            return Err(anyhow!("Model file {} is corrupted: tensor {} has an invalid header", path, tensors));
+        }
+        let mut elements = 1u64;
+        for _ in 0..n_dims {
+            let dimension = reader.u32().ok_or_else(|| truncated(&section))? as u64;
+            elements = elements.checked_mul(dimension).ok_or_else(|| corrupted(&section))?;
+        }
+        reader.skip(name_len as u64).ok_or_else(|| truncated(&section))?;
+        let Some(block) = block_size(ttype) else {
+            // A tensor type newer than this check: the rest can't be walked
+            debug!("{}: tensor type {} unknown, not checking the rest", path, ttype);
+            return Ok(());
+        };
+        let size = tensor_size(block, elements).ok_or_else(|| corrupted(&section))?;
+        reader.skip(size).ok_or_else(|| truncated(&section))?;
+        tensors += 1;
+    }
+    if tensors == 0 {
+        return Err(truncated("weights"));
+    }
+    debug!("{}: {} tensors, {} bytes, intact", path, tensors, len);
+    Ok(())
+}
+
+/// How ggml stores a tensor type: (elements per block, bytes per block)
+fn block_size(ttype: u32) -> Option<(u64, u64)> {
+    Some(match ttype {
+        0 => (1, 4),      // F32
+        1 => (1, 2),      // F16
+        2 => (32, 18),    // Q4_0
+        3 => (32, 20),    // Q4_1
+        6 => (32, 22),    // Q5_0
+        7 => (32, 24),    // Q5_1
+        8 => (32, 34),    // Q8_0
+        10 => (256, 84),  // Q2_K
+        11 => (256, 110), // Q3_K
+        12 => (256, 144), // Q4_K
+        13 => (256, 176), // Q5_K
+        14 => (256, 210), // Q6_K
+        _ => return None,
+    })
+}
+
+/// Bytes of tensor data; None when it doesn't fit in a u64
+fn tensor_size((block, bytes): (u64, u64), elements: u64) -> Option<u64> {
+    elements.div_ceil(block).checked_mul(bytes)
+}
+
+/// Reads a model file front to back; None once it ends too early
+struct ModelReader {
+    inner: BufReader<File>,
+    len: u64,
+}
+
+impl ModelReader {
+    fn u32(&mut self) -> Option<u32> {
+        let mut bytes = [0; 4];
+        self.inner.read_exact(&mut bytes).ok()?;
+        Some(u32::from_le_bytes(bytes))
+    }
+
+    fn skip(&mut self, count: u64) -> Option<()> {
+        if self.position()?.checked_add(count)? > self.len {
+            return None;
+        }
+        self.inner.seek_relative(count as i64).ok()
+    }
+
+    fn position(&mut self) -> Option<u64> {
+        self.inner.stream_position().ok()
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.position() == Some(self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push(file: &mut Vec<u8>, values: &[u32]) {
+        file.extend(values.iter().flat_map(|value| value.to_le_bytes()));
+    }
+
+    /// A ggml model with 2x3 mel filters, the vocabulary "a" and "bc", and one tensor
+    /// of `dims` F32 elements whose data has `data_len` bytes
+    fn model(n_mel: u32, n_fft: u32, dims: &[u32], data_len: usize) -> Vec<u8> {
+        let mut file = Vec::new();
+        push(&mut file, &[GGML_MAGIC, 2]);
+        push(&mut file, &[0; HPARAM_COUNT - 1]);
+        push(&mut file, &[n_mel, n_fft]);
+        file.resize(file.len() + 2 * 3 * 4, 0);
+        push(&mut file, &[2, 1]);
+        file.extend_from_slice(b"a");
+        push(&mut file, &[2]);
+        file.extend_from_slice(b"bc");
+        push(&mut file, &[dims.len() as u32, 6, 0]);
+        push(&mut file, dims);
+        file.extend_from_slice(b"tensor");
+        file.resize(file.len() + data_len, 0);
+        file
+    }
+
+    fn verify(name: &str, file: &[u8]) -> Result<()> {
+        let path = std::env::temp_dir().join(format!("whisp-away-model-{}-{}.bin", name, std::process::id()));
+        fs::write(&path, file).unwrap();
+        let result = verify_structure(path.to_str().unwrap());
+        fs::remove_file(path).unwrap();
+        result
+    }
+
+    #[test]
+    fn accepts_an_intact_model() {
+        verify("intact", &model(2, 3, &[2, 2], 16)).unwrap();
+    }
+
+    #[test]
+    fn finds_truncated_models() {
+        let intact = model(2, 3, &[2, 2], 16);
+        for (cut, section) in [(20, "header"), (60, "mel filters"), (90, "vocabulary"), (110, "weights (tensor 0)")] {
+            let error = verify("truncated", &intact[..cut]).unwrap_err().to_string();
+            assert!(error.contains("truncated") && error.contains(section), "{} bytes: {}", cut, error);
+        }
+        // Ends right after the vocabulary
+        let error = verify("no-tensors", &intact[..95]).unwrap_err().to_string();
+        assert!(error.contains("truncated") && error.ends_with("the weights (an interrupted download?)"), "{}", error);
+    }
+
+    #[test]
+    fn finds_corrupt_headers() {
+        let mut bad_magic = model(2, 3, &[2, 2], 16);
+        bad_magic[0] ^= 0xff;
+        assert!(verify("magic", &bad_magic).unwrap_err().to_string().contains("bad magic number"));
+
+        // Sizes whose products don't fit in 64 bits
+        let error = verify("mel", &model(u32::MAX, u32::MAX, &[2, 2], 16)).unwrap_err().to_string();
+        assert!(error.contains("corrupted") && error.contains("mel filters"), "{}", error);
+        let error = verify("elements", &model(2, 3, &[u32::MAX; 3], 16)).unwrap_err().to_string();
+        assert!(error.contains("corrupted") && error.contains("tensor 0"), "{}", error);
+        let error = verify("bytes", &model(2, 3, &[u32::MAX, u32::MAX], 16)).unwrap_err().to_string();
+        assert!(error.contains("corrupted") && error.contains("tensor 0"), "{}", error);
+
+        let error = verify("dims", &model(2, 3, &[1; 5], 16)).unwrap_err().to_string();
+        assert!(error.contains("invalid header"), "{}", error);
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Notify, Semaphore, SemaphorePermit};
+use tracing::info;
+use crate::helpers::now_secs;
+use crate::socket::{Job, JobState};
+
+/// Finished jobs kept in the job list
+const RECENT_JOBS: usize = 10;
+
+struct TrackedJob {
+    job: Job,
+    /// Wakes the request waiting for its turn when the job is cancelled
+    cancel: Arc<Notify>,
+    /// Set when the job is cancelled, for whisper's abort callback while it runs
+    abort: Arc<AtomicBool>,
+}
+
+/// A submitted job, held by the connection waiting for its result
+pub struct Ticket {
+    pub id: u64,
+    cancel: Arc<Notify>,
+    /// Stops the running transcription once set
+    pub abort: Arc<AtomicBool>,
+}
+
+/// Transcription jobs, run one at a time in order of arrival
+/// Concurrent requests used to compete for the CPU; now later ones wait as
+/// pending jobs, which the tray lists and can cancel
+pub struct JobQueue {
+    jobs: Mutex<Vec<TrackedJob>>,
+    next_id: AtomicU64,
+    /// Single permit held by the running job (tokio hands it out first come, first served)
+    turn: Semaphore,
+}
+
+impl JobQueue {
+    pub fn new() -> Self {
+        Self {
+            jobs: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+            turn: Semaphore::new(1),
+        }
+    }
+
+    fn jobs(&self) -> std::sync::MutexGuard<'_, Vec<TrackedJob>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_state(&self, id: u64, state: JobState) {
+        if let Some(tracked) = self.jobs().iter_mut().find(|tracked| tracked.job.id == id) {
+            tracked.job.state = state;
+        }
+    }
+
+    fn state(&self, id: u64) -> Option<JobState> {
+        self.jobs().iter().find(|tracked| tracked.job.id == id).map(|tracked| tracked.job.state)
+    }
+
+    /// Add a pending job; `request_id` is the client's name for it
+    pub fn submit(&self, label: String, model: Option<String>, request_id: Option<String>) -> Ticket {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancel = Arc::new(Notify::new());
+        let abort = Arc::new(AtomicBool::new(false));
+        self.jobs().push(TrackedJob {
+            job: Job {
+                id,
+                label,
+                model,
+                state: JobState::Pending,
+                submitted: now_secs(),
+                finished: None,
+                progress: None,
+                request_id,
+            },
+            cancel: Arc::clone(&cancel),
+            abort: Arc::clone(&abort),
+        });
+        Ticket { id, cancel, abort }
+    }
+
+    /// Wait until the job may run and mark it running
+    /// None when it was cancelled while pending
+    pub async fn wait_turn(&self, ticket: &Ticket) -> Option<SemaphorePermit<'_>> {
+        let permit = tokio::select! {
+            permit = self.turn.acquire() => permit.ok()?,
+            _ = ticket.cancel.notified() => return None,
+        };
+        // Cancelled just as the previous job finished
+        if self.state(ticket.id) != Some(JobState::Pending) {
+            return None;
+        }
+        self.set_state(ticket.id, JobState::Running);
+        Some(permit)
+    }
+
+    /// Share of a running job's audio transcribed so far (0.0-1.0)
+    pub fn set_progress(&self, id: u64, progress: f32) {
+        if let Some(tracked) = self.jobs().iter_mut().find(|tracked| tracked.job.id == id) {
+            tracked.job.progress = Some(progress);
+        }
+    }
+
+    /// Record the outcome of a job that ran
+    /// Returns false when it was cancelled while running, so its result should be discarded
+    pub fn finish(&self, ticket: &Ticket, success: bool) -> bool {
+        let mut jobs = self.jobs();
+        let Some(tracked) = jobs.iter_mut().find(|tracked| tracked.job.id == ticket.id) else {
+            return false;
+        };
+        let cancelled = tracked.job.state == JobState::Cancelled;
+        if !cancelled {
+            tracked.job.state = if success { JobState::Done } else { JobState::Failed };
+            tracked.job.finished = Some(now_secs());
+        }
+
+        // Keep only the most recent finished jobs
+        let finished = jobs.iter().filter(|tracked| !tracked.job.state.is_active()).count();
+        let mut excess = finished.saturating_sub(RECENT_JOBS);
+        jobs.retain(|tracked| {
+            if excess > 0 && !tracked.job.state.is_active() {
+                excess -= 1;
+                return false;
+            }
+            true
+        });
+        !cancelled
+    }
+
+    /// Cancel a pending job, or abort the running one; whisper stops at its next check and
+    /// the result, if any, is discarded
+    pub fn cancel(&self, id: u64) -> Result<()> {
+        self.cancel_where(|job| job.id == id, || anyhow!("No job {}", id))
+    }
+
+    /// Cancel the job a client submitted with `request_id`
+    pub fn cancel_request(&self, request_id: &str) -> Result<()> {
+        self.cancel_where(
+            |job| job.request_id.as_deref() == Some(request_id),
+            || anyhow!("No job for request {}", request_id),
+        )
+    }
+
+    fn cancel_where(&self, matches: impl Fn(&Job) -> bool, missing: impl FnOnce() -> anyhow::Error) -> Result<()> {
+        let mut jobs = self.jobs();
+        let tracked = jobs.iter_mut()
+            .rev()
+            .find(|tracked| matches(&tracked.job))
+            .ok_or_else(missing)?;
+        let id = tracked.job.id;
+        if !tracked.job.state.is_active() {
+            return Err(anyhow!("Job {} already finished", id));
+        }
+        info!("Cancelling {:?} job {} ({})", tracked.job.state, id, tracked.job.label);
+        tracked.job.state = JobState::Cancelled;
+        tracked.job.finished = Some(now_secs());
+        tracked.cancel.notify_one();
+        tracked.abort.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// All tracked jobs, oldest first
+    pub fn list(&self) -> Vec<Job> {
+        self.jobs().iter().map(|tracked| tracked.job.clone()).collect()
+    }
+
+    /// Whether any job is pending or running
+    pub fn busy(&self) -> bool {
+        self.jobs().iter().any(|tracked| tracked.job.state.is_active())
+    }
+}
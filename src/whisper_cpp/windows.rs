@@ -0,0 +1,415 @@
+use anyhow::{anyhow, Context, Result};
+use std::ffi::{c_int, c_void, CStr};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::mpsc::Sender;
+use tracing::debug;
+use whisper_rs::whisper_rs_sys::{self, whisper_context, whisper_state};
+use whisper_rs::{get_lang_str, FullParams, SamplingStrategy, WhisperState};
+use crate::audio;
+use crate::helpers;
+use crate::socket::{Segment, Word};
+
+const SAMPLE_RATE: usize = 16000;
+
+/// Audio longer than this is transcribed one window at a time, so an hour-long recording
+/// never has to be in memory at once (~19 MB of samples per window instead of ~230 MB)
+const WINDOW_SECS: usize = 300;
+
+/// Audio shared by neighbouring windows, so a sentence cut at one window's edge is heard
+/// whole in the other
+const OVERLAP_SECS: usize = 10;
+
+/// Transcribed text, its timed segments and the confidence signals whisper exposes
+pub struct Transcript {
+    pub text: String,
+    pub segments: Vec<Segment>,
+    /// Detected language (the requested one unless it was "auto")
+    pub language: Option<String>,
+    /// Audio length in seconds
+    pub duration: f64,
+    pub confidence: Option<f32>,
+    pub no_speech_prob: Option<f32>,
+}
+
+/// What whisper decodes: the language and task, and how widely it searches
+pub struct Decoding<'a> {
+    pub language: &'a str,
+    pub translate: bool,
+    /// Beam search with this many beams instead of greedy decoding
+    pub beam_size: Option<u32>,
+    /// Text whisper is primed with before each window
+    pub initial_prompt: Option<&'a str>,
+    /// Report each segment's words with their probabilities
+    pub words: bool,
+}
+
+/// A stretch of the recording, as samples for whisper
+struct Window {
+    index: usize,
+    /// Seconds from the start of the recording
+    start: f64,
+    /// Segments whose middle falls in [keep_from, keep_to) belong to this window; the rest of
+    /// the overlap belongs to the neighbour
+    keep_from: f64,
+    keep_to: f64,
+    samples: Vec<f32>,
+}
+
+/// Reads a 16kHz mono 16-bit WAV file one window at a time
+struct WavWindows {
+    file: File,
+    data_offset: u64,
+    total_samples: usize,
+    next_index: usize,
+}
+
+impl WavWindows {
+    fn open(path: &str) -> Result<Self> {
+        let wav = audio::probe(path)?;
+        if !wav.is_whisper_ready() {
+            return Err(anyhow!(
+                "{} is {}Hz, {} channel(s), {}-bit; whisper needs 16kHz mono 16-bit",
+                path, wav.sample_rate, wav.channels, wav.bits_per_sample
+            ));
+        }
+        let file = File::open(path).context("Failed to read audio file")?;
+        Ok(Self { file, data_offset: wav.data_offset, total_samples: wav.frames() as usize, next_index: 0 })
+    }
+
+    fn duration_secs(&self) -> f64 {
+        self.total_samples as f64 / SAMPLE_RATE as f64
+    }
+
+    fn window_count(&self) -> usize {
+        window_count(self.total_samples)
+    }
+
+    fn read_window(&mut self, index: usize) -> Result<Window> {
+        let (first, len) = window_span(index, self.total_samples);
+        self.file.seek(SeekFrom::Start(self.data_offset + first as u64 * 2))?;
+        let mut bytes = vec![0; len * 2];
+        self.file.read_exact(&mut bytes).context("Failed to read audio file")?;
+        let samples = bytes.chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]) as f32 / i16::MAX as f32)
+            .collect();
+
+        let keep = keep_range(index, self.window_count());
+        Ok(Window {
+            index,
+            start: first as f64 / SAMPLE_RATE as f64,
+            keep_from: keep.start,
+            keep_to: keep.end,
+            samples,
+        })
+    }
+}
+
+/// Samples from the start of one window to the start of the next
+const STEP_SAMPLES: usize = (WINDOW_SECS - OVERLAP_SECS) * SAMPLE_RATE;
+
+/// Windows needed to cover `total_samples` (at least one, even when there are none)
+fn window_count(total_samples: usize) -> usize {
+    let beyond_first = total_samples.saturating_sub(WINDOW_SECS * SAMPLE_RATE);
+    1 + beyond_first.div_ceil(STEP_SAMPLES)
+}
+
+/// The first sample of window `index` and how many it has
+fn window_span(index: usize, total_samples: usize) -> (usize, usize) {
+    let first = index * STEP_SAMPLES;
+    (first, (WINDOW_SECS * SAMPLE_RATE).min(total_samples - first))
+}
+
+/// Seconds in which a segment's middle has to fall to belong to window `index` of `count`:
+/// each window gives up half of an overlap to its neighbour
+fn keep_range(index: usize, count: usize) -> Range<f64> {
+    let start = (index * STEP_SAMPLES) as f64 / SAMPLE_RATE as f64;
+    let half_overlap = OVERLAP_SECS as f64 / 2.0;
+    let from = if index == 0 { f64::MIN } else { start + half_overlap };
+    let to = if index + 1 == count { f64::MAX } else { start + WINDOW_SECS as f64 - half_overlap };
+    from..to
+}
+
+impl Iterator for WavWindows {
+    type Item = Result<Window>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_index >= self.window_count() {
+            return None;
+        }
+        let index = self.next_index;
+        self.next_index += 1;
+        Some(self.read_window(index))
+    }
+}
+
+/// Words put together from whisper's tokens: a token starting with a space starts a word,
+/// the rest (pieces of a word, punctuation) continue it
+#[derive(Default)]
+struct Words {
+    words: Vec<Word>,
+    /// The word being put together: its bytes (a token can end inside a UTF-8 character)
+    /// and its tokens' probabilities
+    bytes: Vec<u8>,
+    probabilities: Vec<f32>,
+}
+
+impl Words {
+    fn add(&mut self, token: &[u8], probability: f32) {
+        // Timestamps and other special tokens, e.g. "[_BEG_]" or "<|endoftext|>"
+        if token.starts_with(b"[_") || token.starts_with(b"<|") {
+            return;
+        }
+        if token.starts_with(b" ") {
+            self.end_word();
+        }
+        self.bytes.extend_from_slice(token);
+        self.probabilities.push(probability);
+    }
+
+    fn end_word(&mut self) {
+        if !self.probabilities.is_empty() {
+            // The mean of its tokens, like faster-whisper's word probabilities
+            let probability = self.probabilities.iter().sum::<f32>() / self.probabilities.len() as f32;
+            self.words.push(Word { word: String::from_utf8_lossy(&self.bytes).into_owned(), probability: probability as f64 });
+        }
+        self.bytes.clear();
+        self.probabilities.clear();
+    }
+
+    fn finish(mut self) -> Vec<Word> {
+        self.end_word();
+        self.words
+    }
+}
+
+/// Where the segments of the window being decoded go as soon as whisper has them
+struct Streaming {
+    /// Seconds from the start of the recording to the window's start
+    offset: f64,
+    /// The segments kept from this window, as in `transcribe`
+    keep: Range<f64>,
+    sender: Sender<Segment>,
+}
+
+/// whisper.cpp's new-segment callback, which sends the segments it was handed on
+/// whisper-rs's safe callback leaves out the no-speech probability, which the hallucination
+/// filter needs before a streamed segment is typed
+unsafe extern "C" fn on_new_segments(_: *mut whisper_context, state: *mut whisper_state, n_new: c_int, user_data: *mut c_void) {
+    // SAFETY: user_data is the `Streaming` set with this callback, alive until `full` returns
+    let streaming = unsafe { &*(user_data as *const Streaming) };
+    // SAFETY: whisper.cpp hands us the state it is decoding, with its segments so far
+    let count = unsafe { whisper_rs_sys::whisper_full_n_segments_from_state(state) };
+    for i in (count - n_new).max(0)..count {
+        // SAFETY: i is below the state's segment count; the text stays valid until the next
+        // decode, and is copied right away
+        let (t0, t1, text, no_speech_prob) = unsafe {
+            let text = whisper_rs_sys::whisper_full_get_segment_text_from_state(state, i);
+            if text.is_null() {
+                continue;
+            }
+            (
+                whisper_rs_sys::whisper_full_get_segment_t0_from_state(state, i),
+                whisper_rs_sys::whisper_full_get_segment_t1_from_state(state, i),
+                CStr::from_ptr(text).to_string_lossy().into_owned(),
+                whisper_rs_sys::whisper_full_get_segment_no_speech_prob_from_state(state, i),
+            )
+        };
+        let start = streaming.offset + t0 as f64 / 100.0;
+        let end = streaming.offset + t1 as f64 / 100.0;
+        // The same segments as kept in `transcribe`, just earlier
+        if streaming.keep.contains(&((start + end) / 2.0)) {
+            let segment = Segment { start, end, text: text.trim().to_string(), no_speech_prob: Some(no_speech_prob as f64), ..Default::default() };
+            let _ = streaming.sender.send(segment);
+        }
+    }
+}
+
+/// Transcribe a WAV file window by window with one whisper state
+/// `configure` sets the caller's parameters (threads, context) on top of `decoding`;
+/// `progress` hears about every finished window as (done, total); `segments` gets each
+/// segment as soon as whisper decodes it, before its window is finished
+pub fn transcribe(
+    state: &mut WhisperState,
+    audio_path: &str,
+    decoding: Decoding,
+    configure: impl Fn(&mut FullParams),
+    progress: impl Fn(usize, usize),
+    segments: Option<Sender<Segment>>,
+) -> Result<Transcript> {
+    let windows = WavWindows::open(audio_path)?;
+    let count = windows.window_count();
+    let fast_path = windows.duration_secs() < helpers::fast_path_secs_setting().value;
+    if fast_path {
+        debug!("{:.1}s of audio, transcribing with the fast path", windows.duration_secs());
+    }
+    let mut transcript = Transcript {
+        text: String::new(),
+        segments: Vec::new(),
+        language: None,
+        duration: windows.duration_secs(),
+        confidence: None,
+        no_speech_prob: None,
+    };
+    let mut language = decoding.language.to_string();
+    let mut probability_sum = 0.0f32;
+    let mut token_count = 0;
+
+    for window in windows {
+        let window = window?;
+        let strategy = match decoding.beam_size {
+            Some(beam_size) => SamplingStrategy::BeamSearch { beam_size: beam_size as i32, patience: -1.0 },
+            None => SamplingStrategy::Greedy { best_of: 1 },
+        };
+        let mut params = FullParams::new(strategy);
+        params.set_translate(decoding.translate);
+        params.set_language(Some(&language));
+        if let Some(prompt) = decoding.initial_prompt {
+            params.set_initial_prompt(prompt);
+        }
+        configure(&mut params);
+        if fast_path && decoding.beam_size.is_none() {
+            // A retry at a higher temperature can double the time of a short command
+            params.set_temperature_inc(0.0);
+        }
+        // Read by `on_new_segments` while `full` runs, so it lives until the window is done
+        let mut streaming = segments.clone().map(|sender| Streaming {
+            offset: window.start,
+            keep: window.keep_from..window.keep_to,
+            sender,
+        });
+        if let Some(streaming) = streaming.as_mut() {
+            // SAFETY: the callback only reads `streaming`, which outlives `full` below
+            unsafe {
+                params.set_new_segment_callback(Some(on_new_segments));
+                params.set_new_segment_callback_user_data(streaming as *mut Streaming as *mut c_void);
+            }
+        }
+        debug!("Transcribing window {}/{} ({} samples from {:.0}s)", window.index + 1, count, window.samples.len(), window.start);
+        state.full(params, &window.samples)
+            .context("Failed to transcribe audio")?;
+
+        for i in 0..state.full_n_segments() {
+            let segment = state.get_segment(i)
+                .ok_or_else(|| anyhow!("Failed to get segment {}", i))?;
+            // Timestamps are in centiseconds from the start of the window
+            let start = window.start + segment.start_timestamp() as f64 / 100.0;
+            let end = window.start + segment.end_timestamp() as f64 / 100.0;
+            if !(window.keep_from..window.keep_to).contains(&((start + end) / 2.0)) {
+                continue;
+            }
+            let segment_text = segment.to_str()?;
+            transcript.text.push_str(segment_text);
+            transcript.text.push(' ');
+            let segment_no_speech = segment.no_speech_probability();
+            let mut words = Words::default();
+            for t in 0..segment.n_tokens() {
+                if let Some(token) = segment.get_token(t) {
+                    probability_sum += token.token_probability();
+                    token_count += 1;
+                    if decoding.words {
+                        words.add(token.to_bytes()?, token.token_probability());
+                    }
+                }
+            }
+            transcript.segments.push(Segment {
+                start,
+                end,
+                text: segment_text.trim().to_string(),
+                no_speech_prob: Some(segment_no_speech as f64),
+                words: words.finish(),
+                ..Default::default()
+            });
+
+            transcript.no_speech_prob = Some(transcript.no_speech_prob.map_or(segment_no_speech, |p| p.max(segment_no_speech)));
+        }
+
+        if transcript.language.is_none() {
+            transcript.language = state.full_lang_id_from_state().ok()
+                .and_then(get_lang_str)
+                .map(str::to_string);
+            // Later windows keep the language of the first, so the transcript doesn't switch mid-way
+            if let Some(detected) = transcript.language.as_ref().filter(|_| language == "auto") {
+                language = detected.clone();
+            }
+        }
+        progress(window.index + 1, count);
+    }
+
+    transcript.text = transcript.text.trim().to_string();
+    transcript.confidence = (token_count > 0).then(|| probability_sum / token_count as f32);
+    Ok(transcript)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: usize = SAMPLE_RATE;
+
+    #[test]
+    fn windows_cover_the_recording_with_overlap() {
+        assert_eq!(window_count(0), 1);
+        assert_eq!(window_count(WINDOW_SECS * SECOND), 1);
+        assert_eq!(window_count(WINDOW_SECS * SECOND + 1), 2);
+        assert_eq!(window_count(590 * SECOND), 2);
+        assert_eq!(window_count(590 * SECOND + 1), 3);
+
+        // 400s: 0-300s, then 290-400s
+        let total = 400 * SECOND;
+        assert_eq!(window_span(0, total), (0, 300 * SECOND));
+        assert_eq!(window_span(1, total), (290 * SECOND, 110 * SECOND));
+        // The last window ends with the recording
+        let total = 590 * SECOND + 1;
+        let (first, len) = window_span(window_count(total) - 1, total);
+        assert_eq!((first, first + len), (580 * SECOND, total));
+    }
+
+    #[test]
+    fn every_moment_belongs_to_one_window() {
+        assert_eq!(keep_range(0, 1), f64::MIN..f64::MAX);
+        let ranges: Vec<Range<f64>> = (0..3).map(|index| keep_range(index, 3)).collect();
+        assert_eq!(ranges[0], f64::MIN..295.0);
+        assert_eq!(ranges[1], 295.0..585.0);
+        assert_eq!(ranges[2], 585.0..f64::MAX);
+        // Window 1 covers 290-590s: each boundary is in the middle of an overlap
+        let (first, len) = window_span(1, 870 * SECOND);
+        assert_eq!(((first / SECOND) as f64 + 5.0, ((first + len) / SECOND) as f64 - 5.0), (295.0, 585.0));
+    }
+
+    /// A 16kHz mono WAV file with a LIST chunk between the format and the samples, as
+    /// ffmpeg writes them
+    fn wav_with_list(samples: &[i16]) -> Vec<u8> {
+        let list = b"INFOISFT\x06\x00\x00\x00ffmpeg";
+        let data_len = samples.len() as u32 * 2;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(4 + 24 + 8 + list.len() as u32 + 8 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&[1, 0, 1, 0]);
+        wav.extend_from_slice(&16000u32.to_le_bytes());
+        wav.extend_from_slice(&32000u32.to_le_bytes());
+        wav.extend_from_slice(&[2, 0, 16, 0]);
+        wav.extend_from_slice(b"LIST");
+        wav.extend_from_slice(&(list.len() as u32).to_le_bytes());
+        wav.extend_from_slice(list);
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        wav.extend(samples.iter().flat_map(|sample| sample.to_le_bytes()));
+        wav
+    }
+
+    #[test]
+    fn reads_samples_after_other_chunks() {
+        let path = std::env::temp_dir().join(format!("whisp-away-windows-{}.wav", std::process::id()));
+        std::fs::write(&path, wav_with_list(&[i16::MAX, 0, -i16::MAX])).unwrap();
+        let mut windows = WavWindows::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(windows.total_samples, 3);
+        let window = windows.next().unwrap().unwrap();
+        assert_eq!(window.samples, [1.0, 0.0, -1.0]);
+        assert!(windows.next().is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+}